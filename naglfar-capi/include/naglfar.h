@@ -0,0 +1,76 @@
+/*
+ * The C API of naglfar: lays out and renders HTML documents without a window or the network.
+ * Link with the library built from naglfar-capi (libnaglfar_capi.so or libnaglfar_capi.a).
+ *
+ *     NaglfarDocument *document =
+ *         naglfar_document_new("<p>Hello</p>", "p { color: red; }", "https://example.com/");
+ *     naglfar_document_layout(document, 320, 240);
+ *     uint8_t *pixels = malloc(320 * 4 * 240);
+ *     naglfar_document_render_rgba(document, pixels, 320, 240, 320 * 4);
+ *     naglfar_document_free(document);
+ *
+ * The functions are called from one thread at a time. Sizes and coordinates are in CSS px, and
+ * the coordinates are from the top left of the document.
+ */
+
+#ifndef NAGLFAR_H
+#define NAGLFAR_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct NaglfarDocument NaglfarDocument;
+
+typedef struct {
+    /* The tag name of the innermost element, e.g. "p" */
+    char *tag_name;
+    /* The URL of the link the element is in, or NULL */
+    char *link_url;
+} NaglfarHitTestResult;
+
+/*
+ * Creates a document from html, with relative URLs resolved against url, and styled with its
+ * <style> tag and then css (which may be NULL). Returns NULL if html or url is NULL or not
+ * UTF-8, or url is not an absolute URL.
+ */
+NaglfarDocument *naglfar_document_new(const char *html, const char *css, const char *url);
+
+/* Frees document. Nothing happens if it is NULL. */
+void naglfar_document_free(NaglfarDocument *document);
+
+/* Lays document out in a viewport of width x height. Returns 0, or -1 on errors. */
+int naglfar_document_layout(NaglfarDocument *document, double width, double height);
+
+/*
+ * The height of the laid out document (laid out in the last viewport first if it is not), or
+ * -1 on errors.
+ */
+double naglfar_document_height(NaglfarDocument *document);
+
+/*
+ * Renders the top left width x height of document on white into buffer: 4 bytes a pixel (R, G,
+ * B and A, which is always 255), and stride bytes a row. Returns 0, or -1 on errors (e.g.
+ * stride is less than 4 * width).
+ */
+int naglfar_document_render_rgba(NaglfarDocument *document, uint8_t *buffer, uint32_t width,
+                                 uint32_t height, size_t stride);
+
+/*
+ * Finds what is at (x, y) in document. Returns 1 and fills result if an element is there, 0
+ * (with result NULLs) if none is, or -1 on errors.
+ */
+int naglfar_document_hit_test(NaglfarDocument *document, double x, double y,
+                              NaglfarHitTestResult *result);
+
+/* Frees the strings of result and sets them to NULL. */
+void naglfar_hit_test_result_clear(NaglfarHitTestResult *result);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
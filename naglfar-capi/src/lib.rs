@@ -0,0 +1,303 @@
+//! The C API of naglfar (declared in ``include/naglfar.h``): a document created from HTML and
+//! CSS strings, laid out at a given size, rendered into a buffer of the caller and hit-tested.
+//!
+//! Panics are caught at the boundary (unwinding into C is undefined behavior): a function that
+//! panics returns its error value.
+
+extern crate cairo;
+extern crate naglfar;
+
+use naglfar::document::Document;
+use naglfar::dom::{Node, NodeType};
+use naglfar::network::Url;
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+/// ``NaglfarDocument`` of C, only used through pointers.
+pub struct NaglfarDocument(Document);
+
+/// What is at a point of a document. The strings are freed by
+/// ``naglfar_hit_test_result_clear``.
+#[repr(C)]
+pub struct NaglfarHitTestResult {
+    /// The tag name of the innermost element, e.g. ``p``
+    pub tag_name: *mut c_char,
+    /// The URL of the link the element is in, or NULL
+    pub link_url: *mut c_char,
+}
+
+/// Runs ``f``, or returns ``error`` if it panics.
+fn catch<T, F: FnOnce() -> T>(error: T, f: F) -> T {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(error)
+}
+
+/// ``s`` as a ``&str``. None if it is NULL or not UTF-8.
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        None
+    } else {
+        CStr::from_ptr(s).to_str().ok()
+    }
+}
+
+/// A copy of ``s`` to be freed by ``CString::from_raw``, or NULL if it contains a NUL.
+fn to_c_string(s: &str) -> *mut c_char {
+    CString::new(s)
+        .map(CString::into_raw)
+        .unwrap_or(ptr::null_mut())
+}
+
+/// Creates a document from ``html``, with relative URLs resolved against ``url``, and styled
+/// with its ``<style>`` tag and then ``css`` (which may be NULL). Nothing is loaded from the
+/// network. Returns NULL if ``html`` or ``url`` is NULL or not UTF-8, or ``url`` is not an
+/// absolute URL.
+///
+/// # Safety
+///
+/// The strings are NUL-terminated (or NULL).
+#[no_mangle]
+pub unsafe extern "C" fn naglfar_document_new(
+    html: *const c_char,
+    css: *const c_char,
+    url: *const c_char,
+) -> *mut NaglfarDocument {
+    let (html, url) = match (to_str(html), to_str(url).and_then(|url| Url::parse(url).ok())) {
+        (Some(html), Some(url)) => (html, url),
+        _ => return ptr::null_mut(),
+    };
+    let css = to_str(css);
+    catch(ptr::null_mut(), || {
+        let mut document = Document::from_html(html, url);
+        if let Some(css) = css {
+            document.append_stylesheet(css);
+        }
+        Box::into_raw(Box::new(NaglfarDocument(document)))
+    })
+}
+
+/// Frees ``document``. Nothing happens if it is NULL.
+///
+/// # Safety
+///
+/// ``document`` is from ``naglfar_document_new``, and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn naglfar_document_free(document: *mut NaglfarDocument) {
+    if !document.is_null() {
+        drop(Box::from_raw(document));
+    }
+}
+
+/// Lays ``document`` out in a viewport of ``width`` x ``height`` CSS px. Returns 0, or -1 if
+/// ``document`` is NULL or the layout failed.
+///
+/// # Safety
+///
+/// ``document`` is from ``naglfar_document_new`` (or NULL), and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn naglfar_document_layout(
+    document: *mut NaglfarDocument,
+    width: f64,
+    height: f64,
+) -> c_int {
+    let document = match document.as_mut() {
+        Some(document) => document,
+        None => return -1,
+    };
+    catch(-1, || {
+        document.0.layout(width, height);
+        0
+    })
+}
+
+/// The height of the laid out ``document`` in CSS px (laid out in the last viewport first if
+/// it is not), to size the buffer of ``naglfar_document_render_rgba`` for the whole document.
+/// Returns -1 if ``document`` is NULL or the layout failed.
+///
+/// # Safety
+///
+/// ``document`` is from ``naglfar_document_new`` (or NULL), and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn naglfar_document_height(document: *mut NaglfarDocument) -> f64 {
+    let document = match document.as_mut() {
+        Some(document) => document,
+        None => return -1.0,
+    };
+    catch(-1.0, || {
+        document
+            .0
+            .layout_root()
+            .dimensions
+            .margin_box()
+            .height
+            .to_f64_px()
+    })
+}
+
+/// Renders the top left ``width`` x ``height`` CSS px of ``document`` (laid out in the last
+/// viewport first if it is not) on white into ``buffer``: 4 bytes a pixel (R, G, B and A, which
+/// is always 255), and ``stride`` bytes a row. Returns 0, or -1 if ``document`` or ``buffer`` is
+/// NULL, ``stride`` is less than 4 x ``width``, or the rendering failed.
+///
+/// # Safety
+///
+/// ``document`` is from ``naglfar_document_new`` (or NULL), and not freed yet. ``buffer`` has
+/// ``stride`` x ``height`` bytes (or is NULL).
+#[no_mangle]
+pub unsafe extern "C" fn naglfar_document_render_rgba(
+    document: *mut NaglfarDocument,
+    buffer: *mut u8,
+    width: u32,
+    height: u32,
+    stride: usize,
+) -> c_int {
+    let document = match document.as_mut() {
+        Some(document) => document,
+        None => return -1,
+    };
+    if buffer.is_null() || stride < width as usize * 4 {
+        return -1;
+    }
+    let buffer = slice::from_raw_parts_mut(buffer, stride * height as usize);
+    catch(-1, || match render_rgba(&mut document.0, buffer, width, height, stride) {
+        Some(()) => 0,
+        None => -1,
+    })
+}
+
+fn render_rgba(
+    document: &mut Document,
+    buffer: &mut [u8],
+    width: u32,
+    height: u32,
+    stride: usize,
+) -> Option<()> {
+    let mut surface =
+        cairo::ImageSurface::create(cairo::Format::Rgb24, width as i32, height as i32).ok()?;
+    {
+        let ctx = cairo::Context::new(&surface);
+        ctx.set_source_rgb(1.0, 1.0, 1.0);
+        ctx.paint();
+        document.paint(&ctx).ok()?;
+    }
+    let surface_stride = surface.get_stride() as usize;
+    let data = surface.get_data().ok()?;
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            // A native-endian 0x00RRGGBB
+            let i = y * surface_stride + x * 4;
+            let pixel = u32::from_ne_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+            let j = y * stride + x * 4;
+            buffer[j] = (pixel >> 16) as u8;
+            buffer[j + 1] = (pixel >> 8) as u8;
+            buffer[j + 2] = pixel as u8;
+            buffer[j + 3] = 255;
+        }
+    }
+    Some(())
+}
+
+/// Finds what is at (``x``, ``y``) in ``document`` (in CSS px), laid out in the last viewport
+/// first if it is not. Returns 1 and fills ``result`` if an element is there, 0 (with ``result``
+/// NULLs) if none is, or -1 if ``document`` or ``result`` is NULL or the layout failed.
+///
+/// # Safety
+///
+/// ``document`` is from ``naglfar_document_new`` (or NULL), and not freed yet. ``result`` points
+/// to a ``NaglfarHitTestResult`` (or is NULL), whose strings are overwritten without being freed.
+#[no_mangle]
+pub unsafe extern "C" fn naglfar_document_hit_test(
+    document: *mut NaglfarDocument,
+    x: f64,
+    y: f64,
+    result: *mut NaglfarHitTestResult,
+) -> c_int {
+    let (document, result) = match (document.as_mut(), result.as_mut()) {
+        (Some(document), Some(result)) => (document, result),
+        _ => return -1,
+    };
+    result.tag_name = ptr::null_mut();
+    result.link_url = ptr::null_mut();
+    catch(-1, || {
+        let tag_name = match document.0.element_at(x, y) {
+            Some(&Node {
+                data: NodeType::Element(ref e),
+                ..
+            }) => e.tag_name.clone(),
+            _ => return 0,
+        };
+        result.tag_name = to_c_string(tag_name.as_str());
+        if let Some(url) = document.0.link_at(x, y) {
+            result.link_url = to_c_string(url);
+        }
+        1
+    })
+}
+
+/// Frees the strings of ``result`` and sets them to NULL. Nothing happens if it is NULL.
+///
+/// # Safety
+///
+/// ``result`` is filled by ``naglfar_document_hit_test`` (or has NULLs, or is NULL).
+#[no_mangle]
+pub unsafe extern "C" fn naglfar_hit_test_result_clear(result: *mut NaglfarHitTestResult) {
+    if let Some(result) = result.as_mut() {
+        for s in &mut [&mut result.tag_name, &mut result.link_url] {
+            if !s.is_null() {
+                drop(CString::from_raw(**s));
+                **s = ptr::null_mut();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_c_api() {
+    let html = CString::new(
+        "<!DOCTYPE html><style>body { margin: 0; } p { margin: 0; height: 20px; }</style>\
+         <p><a href=\"b.html\">b</a></p>",
+    ).unwrap();
+    let css = CString::new("p { background-color: #0000ff; }").unwrap();
+    let url = CString::new("https://example.com/a.html").unwrap();
+    let relative_url = CString::new("a.html").unwrap();
+
+    unsafe {
+        assert!(naglfar_document_new(html.as_ptr(), ptr::null(), relative_url.as_ptr()).is_null());
+        assert!(naglfar_document_new(ptr::null(), ptr::null(), url.as_ptr()).is_null());
+        assert_eq!(naglfar_document_layout(ptr::null_mut(), 10.0, 10.0), -1);
+
+        let document = naglfar_document_new(html.as_ptr(), css.as_ptr(), url.as_ptr());
+        assert!(!document.is_null());
+        assert_eq!(naglfar_document_layout(document, 40.0, 30.0), 0);
+        assert!(naglfar_document_height(document) >= 20.0);
+
+        let mut buffer = vec![0; 48 * 30];
+        assert_eq!(naglfar_document_render_rgba(document, buffer.as_mut_ptr(), 40, 30, 40), -1);
+        assert_eq!(naglfar_document_render_rgba(document, buffer.as_mut_ptr(), 10, 30, 48), 0);
+        // The first pixel: the paragraph, and the 11th is not rendered.
+        assert_eq!(&buffer[0..4], &[0, 0, 255, 255]);
+        assert_eq!(&buffer[40..44], &[0, 0, 0, 0]);
+        // Under the paragraph
+        assert_eq!(&buffer[48 * 25..48 * 25 + 4], &[255, 255, 255, 255]);
+
+        let mut result = NaglfarHitTestResult {
+            tag_name: ptr::null_mut(),
+            link_url: ptr::null_mut(),
+        };
+        assert_eq!(naglfar_document_hit_test(document, 1.0, 5.0, &mut result), 1);
+        assert_eq!(CStr::from_ptr(result.tag_name).to_str(), Ok("a"));
+        assert_eq!(
+            CStr::from_ptr(result.link_url).to_str(),
+            Ok("https://example.com/b.html")
+        );
+        naglfar_hit_test_result_clear(&mut result);
+        assert!(result.tag_name.is_null() && result.link_url.is_null());
+        assert_eq!(naglfar_document_hit_test(document, 1.0, 25.0, &mut result), 0);
+        assert!(result.tag_name.is_null());
+
+        naglfar_document_free(document);
+    }
+}
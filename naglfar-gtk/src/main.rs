@@ -0,0 +1,130 @@
+extern crate naglfar;
+use naglfar::gpu::{self, Renderer};
+use naglfar::interface;
+use naglfar::stats;
+use naglfar::trace;
+
+extern crate clap;
+use clap::{App, Arg};
+
+const VERSION_STR: &'static str = env!("CARGO_PKG_VERSION");
+
+/// Parses ``<width>x<height>`` (CSS px).
+fn parse_size(s: &str) -> Option<(f64, f64)> {
+    let mut parts = s.splitn(2, 'x');
+    let width: f64 = parts.next()?.trim().parse().ok()?;
+    let height: f64 = parts.next()?.trim().parse().ok()?;
+    if width > 0.0 && height > 0.0 {
+        Some((width, height))
+    } else {
+        None
+    }
+}
+
+fn main() {
+    let app = App::new("Naglfar")
+        .version(VERSION_STR)
+        .author("uint256_t")
+        .about("A toy web browser implementation in Rust from scratch")
+        .arg(
+            Arg::with_name("URL")
+                .help("Set URL (starts with http(s)://, file:// or data:) or local path")
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("headless")
+                .long("headless")
+                .help("Save a screenshot of the page without opening a window"),
+        )
+        .arg(
+            Arg::with_name("size")
+                .long("size")
+                .value_name("WIDTHxHEIGHT")
+                .help("Set the size of the viewport in headless mode")
+                .default_value("800x600")
+                .validator(|s| match parse_size(s.as_str()) {
+                    Some(_) => Ok(()),
+                    None => Err(format!("invalid size: {} (e.g. 1280x800)", s)),
+                }),
+        )
+        .arg(
+            Arg::with_name("out")
+                .long("out")
+                .value_name("FILE")
+                .help(
+                    "Set the PNG file the screenshot is saved to in headless mode \
+                     (or a web page drawing it on a canvas if it ends with .html, or the page \
+                     printed if it ends with .pdf)",
+                )
+                .default_value("screenshot.png"),
+        )
+        .arg(
+            Arg::with_name("full-page")
+                .long("full-page")
+                .help("Save the whole page, not only the viewport, in headless mode"),
+        )
+        .arg(
+            Arg::with_name("renderer")
+                .long("renderer")
+                .value_name("RENDERER")
+                .help("Set what the page is rasterized with (gpu needs the gpu feature)")
+                .possible_values(&["cairo", "gpu"])
+                .default_value("cairo"),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .long("stats")
+                .help("Print how long each phase of the pipeline took in headless mode"),
+        );
+    let app_matches = app.clone().get_matches();
+    trace::init();
+    gpu::set_renderer(Renderer::parse(app_matches.value_of("renderer").unwrap()).unwrap());
+
+    let url = if let Some(url) = app_matches.value_of("URL") {
+        url.to_string()
+    } else {
+        let mut cur_dir = std::env::current_dir().unwrap();
+        cur_dir.push("example");
+        cur_dir.push("top.html");
+        format!("file://{}", cur_dir.to_str().unwrap())
+    };
+
+    if app_matches.is_present("headless") {
+        let (width, height) = parse_size(app_matches.value_of("size").unwrap()).unwrap();
+        let out = app_matches.value_of("out").unwrap();
+        let saved = if out.ends_with(".html") {
+            let page = interface::canvas_page(url.as_str(), width, height);
+            std::fs::write(out, page).map_err(|e| format!("{}", e))
+        } else if out.ends_with(".pdf") {
+            // Printed on the pages of its ``@page`` rules, not the viewport
+            interface::print_to_pdf(url.as_str(), out)
+                .map(|_| ())
+                .map_err(|e| format!("{:?}", e))
+        } else if app_matches.is_present("full-page") {
+            interface::full_page_screenshot(url.as_str(), width, height, out)
+                .map_err(|e| format!("{:?}", e))
+        } else {
+            interface::screenshot(url.as_str(), width, height, out).map_err(|e| format!("{:?}", e))
+        };
+        if let Err(e) = saved {
+            eprintln!("cannot save the screenshot to {}: {}", out, e);
+            std::process::exit(1);
+        }
+        if app_matches.is_present("stats") {
+            for line in stats::stats().summary() {
+                eprintln!("{}", line);
+            }
+        }
+        return;
+    }
+
+    interface::run_with_url(url);
+}
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("1280x800"), Some((1280.0, 800.0)));
+    assert_eq!(parse_size("1280"), None);
+    assert_eq!(parse_size("0x800"), None);
+    assert_eq!(parse_size("ax800"), None);
+}
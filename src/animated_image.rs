@@ -0,0 +1,540 @@
+//! Animated images: a GIF or an APNG of several frames is decoded into all its frames (see
+//! ``decode``), each shown for its delay. The frame of the image shown (see ``inline::pixbuf``)
+//! is advanced by a task of the engine (``interface::post_task``) when it is due, which paints the
+//! page again, until the image has been played as many times as it is to be.
+//! ref. https://wiki.mozilla.org/APNG_Specification
+//!
+//! The frames of a GIF are composed by gdk-pixbuf. Those of an APNG are decoded as PNGs of their
+//! own (of the chunks shared with the default image) and composed here by their dispose and blend
+//! operations.
+
+use inline;
+use interface;
+use task::TaskId;
+
+use gdk_pixbuf::{Colorspace, InterpType, Pixbuf, PixbufAnimationExt, PixbufExt, PixbufLoader,
+                 PixbufLoaderExt};
+use glib::TimeVal;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// How long a frame of a (too short) delay of 10ms or less is shown, as by the other browsers
+pub const DEFAULT_FRAME_DELAY: u64 = 100;
+
+pub const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// The whole image, composed of the frames up to this one
+    pub pixbuf: Pixbuf,
+    pub delay: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimatedImage {
+    /// At least two
+    pub frames: Vec<Frame>,
+    /// How many times the frames are played, None for ever
+    pub plays: Option<u32>,
+}
+
+/// An animated image being played
+struct Playing {
+    image: AnimatedImage,
+    frame: usize,
+    /// How many times all the frames have been shown
+    played: u32,
+    /// Advances to the next frame
+    task: TaskId,
+}
+
+thread_local!(
+    /// The animated images being played, by their URL
+    static PLAYING: RefCell<HashMap<String, Playing>> = { RefCell::new(HashMap::new()) };
+);
+
+/// Decodes the frames of the GIF or the APNG ``data``. None if it is not animated (of one
+/// frame, or of another type), or broken.
+pub fn decode(data: &[u8]) -> Option<AnimatedImage> {
+    if data.starts_with(b"GIF8") {
+        decode_gif(data)
+    } else if data.starts_with(&PNG_SIGNATURE) {
+        decode_apng(data)
+    } else {
+        None
+    }
+}
+
+/// Shows the first frame of ``image`` at ``url``, and plays it from now. It is played again from
+/// the first frame if it was already.
+pub fn play(url: &str, image: AnimatedImage) {
+    stop(url);
+    inline::show_frame(url, image.frames[0].pixbuf.clone());
+    let task = schedule(url, image.frames[0].delay);
+    PLAYING.with(|p| {
+        p.borrow_mut().insert(
+            url.to_string(),
+            Playing {
+                image: image,
+                frame: 0,
+                played: 0,
+                task: task,
+            },
+        )
+    });
+}
+
+/// Stops playing the image at ``url``, which shows the frame shown now. Returns false if it was
+/// not being played.
+pub fn stop(url: &str) -> bool {
+    match PLAYING.with(|p| p.borrow_mut().remove(url)) {
+        Some(playing) => interface::cancel_task(playing.task),
+        None => false,
+    }
+}
+
+/// Stops playing all the images, e.g. when another page is loaded.
+pub fn stop_all() {
+    let playing: Vec<Playing> = PLAYING.with(|p| p.borrow_mut().drain().map(|(_, p)| p).collect());
+    for playing in playing {
+        interface::cancel_task(playing.task);
+    }
+}
+
+/// The index of the frame of the image at ``url`` shown now. None if it is not being played.
+pub fn current_frame(url: &str) -> Option<usize> {
+    PLAYING.with(|p| p.borrow().get(url).map(|playing| playing.frame))
+}
+
+/// Shows the next frame of the image at ``url``, and has the page painted again. Returns false
+/// if it is not being played, or has been played as many times as it is to be (then, its last
+/// frame is still shown).
+pub fn advance(url: &str) -> bool {
+    let next = PLAYING.with(|p| {
+        let mut playing = p.borrow_mut();
+        let (frame, done) = {
+            let image = playing.get_mut(url)?;
+            let frame = (image.frame + 1) % image.image.frames.len();
+            if frame == 0 {
+                image.played += 1;
+            }
+            let plays = image.image.plays;
+            (frame, frame == 0 && plays.is_some_and(|plays| image.played >= plays))
+        };
+        if done {
+            playing.remove(url);
+            return None;
+        }
+        let image = playing.get_mut(url)?;
+        image.frame = frame;
+        Some(image.image.frames[frame].clone())
+    });
+    let next = match next {
+        Some(next) => next,
+        None => return false,
+    };
+    inline::show_frame(url, next.pixbuf);
+    interface::invalidate_display_list();
+    let task = schedule(url, next.delay);
+    PLAYING.with(|p| {
+        if let Some(playing) = p.borrow_mut().get_mut(url) {
+            playing.task = task;
+        }
+    });
+    true
+}
+
+fn schedule(url: &str, delay: Duration) -> TaskId {
+    let url = url.to_string();
+    interface::post_task(delay, move || {
+        advance(&url);
+    })
+}
+
+/// The delay of a frame to be shown ``ms`` milliseconds
+fn frame_delay(ms: u64) -> Duration {
+    Duration::from_millis(if ms <= 10 { DEFAULT_FRAME_DELAY } else { ms })
+}
+
+fn decode_gif(data: &[u8]) -> Option<AnimatedImage> {
+    let (frame_count, plays) = gif_frames(data)?;
+    if frame_count < 2 {
+        return None;
+    }
+    let loader = PixbufLoader::new_with_type("gif").ok()?;
+    loader.write(data).ok()?;
+    loader.close().ok()?;
+    let animation = loader.get_animation()?;
+
+    // Advanced by the delay of each frame from the time 0
+    let mut time = 0;
+    let time_val = |ms: i64| TimeVal {
+        tv_sec: (ms / 1000) as _,
+        tv_usec: (ms % 1000 * 1000) as _,
+    };
+    let iter = animation.get_iter(time_val(time));
+    let mut frames = vec![];
+    for _ in 0..frame_count {
+        // -1 for the last frame of an image not played again
+        let delay = iter.get_delay_time();
+        frames.push(Frame {
+            pixbuf: iter.get_pixbuf().copy()?,
+            delay: frame_delay(delay.max(0) as u64),
+        });
+        if delay < 0 {
+            break;
+        }
+        time += delay as i64;
+        iter.advance(time_val(time));
+    }
+    if frames.len() < 2 {
+        return None;
+    }
+    Some(AnimatedImage {
+        frames: frames,
+        plays: plays,
+    })
+}
+
+/// The number of image descriptors of a GIF, and how many times it is played by its
+/// ``NETSCAPE2.0`` extension (once without one)
+fn gif_frames(data: &[u8]) -> Option<(usize, Option<u32>)> {
+    let color_table_size = |packed: u8| {
+        if packed & 0x80 != 0 {
+            3 << ((packed & 7) + 1)
+        } else {
+            0
+        }
+    };
+    // After the sub-blocks from ``i`` and their terminator
+    let skip_sub_blocks = |mut i: usize| loop {
+        let size = *data.get(i)? as usize;
+        i += 1 + size;
+        if size == 0 {
+            return Some(i);
+        }
+    };
+
+    let mut i = 13 + color_table_size(*data.get(10)?);
+    let (mut frames, mut plays) = (0, Some(1));
+    loop {
+        match *data.get(i)? {
+            // An extension
+            0x21 => {
+                if data.get(i + 1) == Some(&0xff)
+                    && data.get(i + 2..i + 14) == Some(&b"\x0bNETSCAPE2.0"[..])
+                {
+                    let count = u16::from_le_bytes([*data.get(i + 16)?, *data.get(i + 17)?]) as u32;
+                    // Repeated ``count`` times, or for ever if it is 0
+                    plays = if count == 0 { None } else { Some(count + 1) };
+                }
+                i = skip_sub_blocks(i + 2)?;
+            }
+            // An image descriptor, its color table and its LZW minimum code size
+            0x2c => {
+                frames += 1;
+                let packed = *data.get(i + 9)?;
+                i = skip_sub_blocks(i + 10 + color_table_size(packed) + 1)?;
+            }
+            // The trailer
+            0x3b => return Some((frames, plays)),
+            _ => return None,
+        }
+    }
+}
+
+/// A frame of an APNG (of its ``fcTL`` chunk), and its image data
+struct ApngFrame {
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    delay: Duration,
+    dispose_op: u8,
+    blend_op: u8,
+    data: Vec<u8>,
+}
+
+/// ``dispose_op``: the frame area is cleared to transparent black before the next frame
+const APNG_DISPOSE_OP_BACKGROUND: u8 = 1;
+/// ``dispose_op``: the frame area is reverted to how it was before the frame
+const APNG_DISPOSE_OP_PREVIOUS: u8 = 2;
+/// ``blend_op``: the frame replaces its area (instead of being composited over it)
+const APNG_BLEND_OP_SOURCE: u8 = 0;
+/// The most px of all the frames of an APNG decoded, each as large as the image (1 GiB)
+const APNG_MAX_DECODED_AREA: u64 = 1 << 28;
+
+fn decode_apng(data: &[u8]) -> Option<AnimatedImage> {
+    let mut ihdr = None;
+    let mut plays = None;
+    // The chunks before the image data (e.g. ``PLTE``), which all the frames are of
+    let mut shared_chunks = vec![];
+    let mut frames: Vec<ApngFrame> = vec![];
+    let mut i = PNG_SIGNATURE.len();
+    while let Some(header) = data.get(i..i + 8) {
+        let length = be32(&header[0..4]) as usize;
+        let kind = &header[4..8];
+        let chunk = data.get(i + 8..i + 8 + length)?;
+        i += 12 + length;
+        match kind {
+            b"IHDR" => ihdr = Some(chunk),
+            b"acTL" if chunk.len() >= 8 => {
+                let num_plays = be32(&chunk[4..8]);
+                plays = Some(if num_plays == 0 { None } else { Some(num_plays) });
+            }
+            b"fcTL" if chunk.len() >= 26 => {
+                let (delay_num, delay_den) = (be16(&chunk[20..22]), be16(&chunk[22..24]));
+                let delay_den = if delay_den == 0 { 100 } else { delay_den };
+                frames.push(ApngFrame {
+                    width: be32(&chunk[4..8]),
+                    height: be32(&chunk[8..12]),
+                    x: be32(&chunk[12..16]),
+                    y: be32(&chunk[16..20]),
+                    delay: frame_delay(delay_num as u64 * 1000 / delay_den as u64),
+                    dispose_op: chunk[24],
+                    blend_op: chunk[25],
+                    data: vec![],
+                });
+            }
+            // The default image is the first frame only if an ``fcTL`` is before it.
+            b"IDAT" => {
+                if let Some(frame) = frames.last_mut() {
+                    frame.data.extend_from_slice(chunk);
+                }
+            }
+            b"fdAT" if chunk.len() >= 4 => frames.last_mut()?.data.extend_from_slice(&chunk[4..]),
+            b"IEND" => break,
+            _ if frames.is_empty() => shared_chunks.push((kind, chunk)),
+            _ => {}
+        }
+    }
+    let (ihdr, plays) = (ihdr?, plays?);
+    if frames.len() < 2 || ihdr.len() < 13 {
+        return None;
+    }
+    let (width, height) = (be32(&ihdr[0..4]), be32(&ihdr[4..8]));
+    if width as u64 * height as u64 * frames.len() as u64 > APNG_MAX_DECODED_AREA {
+        return None;
+    }
+
+    let canvas = Pixbuf::new(Colorspace::Rgb, true, 8, width as i32, height as i32);
+    canvas.fill(0);
+    let mut composed = vec![];
+    for (n, frame) in frames.iter().enumerate() {
+        let inside = |offset: u32, size: u32, max: u32| {
+            offset.checked_add(size).is_some_and(|end| end <= max)
+        };
+        if !inside(frame.x, frame.width, width) || !inside(frame.y, frame.height, height) {
+            return None;
+        }
+        let pixbuf = decode_apng_frame(ihdr, &shared_chunks, frame)?;
+        let (x, y) = (frame.x as i32, frame.y as i32);
+        let (w, h) = (frame.width as i32, frame.height as i32);
+        let previous = if frame.dispose_op == APNG_DISPOSE_OP_PREVIOUS && n > 0 {
+            canvas.copy()
+        } else {
+            None
+        };
+        if frame.blend_op == APNG_BLEND_OP_SOURCE {
+            pixbuf.copy_area(0, 0, w, h, &canvas, x, y);
+        } else {
+            let (offset_x, offset_y) = (x as f64, y as f64);
+            let interp = InterpType::Nearest;
+            pixbuf.composite(&canvas, x, y, w, h, offset_x, offset_y, 1.0, 1.0, interp, 255);
+        }
+        composed.push(Frame {
+            pixbuf: canvas.copy()?,
+            delay: frame.delay,
+        });
+
+        match (frame.dispose_op, previous) {
+            (APNG_DISPOSE_OP_PREVIOUS, Some(previous)) => {
+                previous.copy_area(0, 0, width as i32, height as i32, &canvas, 0, 0)
+            }
+            // The first frame reverted to how it was before is cleared.
+            (APNG_DISPOSE_OP_BACKGROUND, _) | (APNG_DISPOSE_OP_PREVIOUS, None) => {
+                canvas.new_subpixbuf(x, y, w, h)?.fill(0)
+            }
+            _ => {}
+        }
+    }
+    Some(AnimatedImage {
+        frames: composed,
+        plays: plays,
+    })
+}
+
+/// Decodes ``frame`` as a PNG of its own of the header ``ihdr`` (of the frame size) and
+/// ``shared_chunks``.
+fn decode_apng_frame(
+    ihdr: &[u8],
+    shared_chunks: &[(&[u8], &[u8])],
+    frame: &ApngFrame,
+) -> Option<Pixbuf> {
+    let mut header = ihdr.to_vec();
+    header[0..4].copy_from_slice(&frame.width.to_be_bytes());
+    header[4..8].copy_from_slice(&frame.height.to_be_bytes());
+    let mut png = PNG_SIGNATURE.to_vec();
+    png.extend(png_chunk(b"IHDR", &header));
+    for &(kind, chunk) in shared_chunks {
+        png.extend(png_chunk(kind, chunk));
+    }
+    png.extend(png_chunk(b"IDAT", &frame.data));
+    png.extend(png_chunk(b"IEND", &[]));
+
+    let loader = PixbufLoader::new_with_type("png").ok()?;
+    loader.write(&png).ok()?;
+    loader.close().ok()?;
+    let pixbuf = loader.get_pixbuf()?;
+    if pixbuf.get_has_alpha() {
+        Some(pixbuf)
+    } else {
+        pixbuf.add_alpha(false, 0, 0, 0)
+    }
+}
+
+/// A PNG chunk of ``kind`` and ``data``, with its length and its CRC
+pub fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let crc = crc32(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+    chunk
+}
+
+/// The CRC-32 of PNG chunks
+/// ref. https://www.w3.org/TR/png/#D-CRCAppendix
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn be32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn be16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+/// The red, the green, the blue and the alpha of the pixel at (``x``, ``y``) of ``pixbuf``
+#[cfg(test)]
+fn pixel(pixbuf: &Pixbuf, x: i32, y: i32) -> [u8; 4] {
+    let i = (y * pixbuf.get_rowstride() + x * pixbuf.get_n_channels()) as usize;
+    let pixels = unsafe { pixbuf.get_pixels() };
+    [pixels[i], pixels[i + 1], pixels[i + 2], pixels[i + 3]]
+}
+
+#[test]
+fn test_decode_gif() {
+    // 1x1, red then blue, shown 200ms and 500ms, for ever
+    let mut gif = b"GIF89a\x01\x00\x01\x00\xf0\x00\x00\xff\x00\x00\x00\x00\xff".to_vec();
+    gif.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+    for &(delay, lzw) in &[(20u8, b"\x44\x01"), (50, b"\x4c\x01")] {
+        gif.extend_from_slice(&[0x21, 0xf9, 4, 0, delay, 0, 0, 0]);
+        gif.extend_from_slice(b"\x2c\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02");
+        gif.extend_from_slice(lzw);
+        gif.push(0);
+    }
+    gif.push(0x3b);
+    assert_eq!(gif_frames(&gif), Some((2, None)));
+
+    let image = decode(&gif).unwrap();
+    assert_eq!(image.plays, None);
+    let delays: Vec<Duration> = image.frames.iter().map(|frame| frame.delay).collect();
+    assert_eq!(delays, vec![Duration::from_millis(200), Duration::from_millis(500)]);
+    assert_eq!(pixel(&image.frames[0].pixbuf, 0, 0), [255, 0, 0, 255]);
+    assert_eq!(pixel(&image.frames[1].pixbuf, 0, 0), [0, 0, 255, 255]);
+
+    // Played from the first frame, and then for ever
+    let url = "file:///a.gif";
+    play(url, image);
+    assert_eq!(current_frame(url), Some(0));
+    assert!(advance(url) && current_frame(url) == Some(1));
+    assert!(advance(url) && current_frame(url) == Some(0));
+    assert_eq!(pixel(&inline::pixbuf(url).unwrap(), 0, 0), [255, 0, 0, 255]);
+    assert!(stop(url) && !advance(url));
+}
+
+#[test]
+fn test_decode_apng() {
+    // The image data of a PNG of ``pixbuf``
+    let idat = |pixbuf: &Pixbuf| {
+        let png = pixbuf.save_to_bufferv("png", &[]).unwrap();
+        let (mut data, mut i) = (vec![], PNG_SIGNATURE.len());
+        while i < png.len() {
+            let length = be32(&png[i..i + 4]) as usize;
+            if &png[i + 4..i + 8] == b"IDAT" {
+                data.extend_from_slice(&png[i + 8..i + 8 + length]);
+            }
+            i += 12 + length;
+        }
+        data
+    };
+    let frame_control = |n: u32, size: u32, offset: u32, dispose_op: u8| {
+        let mut chunk = vec![];
+        for &value in &[n, size, size, offset, offset] {
+            chunk.extend_from_slice(&value.to_be_bytes());
+        }
+        chunk.extend_from_slice(&[0, 1, 0, 4, dispose_op, 1]);
+        png_chunk(b"fcTL", &chunk)
+    };
+    let (red, blue) = (
+        Pixbuf::new(Colorspace::Rgb, true, 8, 2, 2),
+        Pixbuf::new(Colorspace::Rgb, true, 8, 1, 1),
+    );
+    red.fill(0xff00_00ff);
+    blue.fill(0x0000_ffff);
+
+    // Red, then blue at (``offset``, ``offset``), played twice
+    let apng = |ihdr: &[u8], offset: u32| {
+        let mut apng = PNG_SIGNATURE.to_vec();
+        apng.extend(png_chunk(b"IHDR", ihdr));
+        apng.extend(png_chunk(b"acTL", b"\x00\x00\x00\x02\x00\x00\x00\x02"));
+        apng.extend(frame_control(0, 2, 0, APNG_DISPOSE_OP_BACKGROUND));
+        apng.extend(png_chunk(b"IDAT", &idat(&red)));
+        apng.extend(frame_control(1, 1, offset, 0));
+        let mut fdat = 2u32.to_be_bytes().to_vec();
+        fdat.extend(idat(&blue));
+        apng.extend(png_chunk(b"fdAT", &fdat));
+        apng.extend(png_chunk(b"IEND", &[]));
+        apng
+    };
+    let ihdr = b"\x00\x00\x00\x02\x00\x00\x00\x02\x08\x06\x00\x00\x00";
+
+    // 2x2, with the blue over its bottom right
+    let image = decode(&apng(ihdr, 1)).unwrap();
+    assert_eq!(image.plays, Some(2));
+    assert_eq!(image.frames[0].delay, Duration::from_millis(250));
+    let (first, second) = (&image.frames[0].pixbuf, &image.frames[1].pixbuf);
+    assert_eq!(pixel(first, 1, 1), [255, 0, 0, 255]);
+    // Over the area of the first frame cleared
+    assert_eq!(pixel(second, 0, 0), [0, 0, 0, 0]);
+    assert_eq!(pixel(second, 1, 1), [0, 0, 255, 255]);
+
+    // Stopped on the last frame after two plays
+    let url = "file:///a.png";
+    play(url, image);
+    assert!(advance(url) && advance(url) && advance(url));
+    assert!(!advance(url) && current_frame(url).is_none());
+    assert_eq!(pixel(&inline::pixbuf(url).unwrap(), 1, 1), [0, 0, 255, 255]);
+
+    // Not animated
+    assert!(decode(&red.save_to_bufferv("png", &[]).unwrap()).is_none());
+
+    // Malformed: a short header, a frame out of the image (its offset and size overflowing), and
+    // an image too large to be decoded
+    assert!(decode(&apng(&ihdr[..2], 1)).is_none());
+    assert!(decode(&apng(ihdr, u32::MAX)).is_none());
+    let huge = b"\x00\x01\x00\x00\x00\x01\x00\x00\x08\x06\x00\x00\x00";
+    assert!(decode(&apng(huge, 1)).is_none());
+}
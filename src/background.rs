@@ -0,0 +1,716 @@
+//! Background images: their size, where they are placed in the box, and how they are repeated
+//! to fill it. ref. https://www.w3.org/TR/css-backgrounds-3/#backgrounds
+//!
+//! An image is placed in the padding box (the positioning area), and its copies fill the border
+//! box (the painting area), which they are clipped to. A fixed image is placed in the viewport
+//! instead, so it does not move as the content scrolls.
+//!
+//! The content of a replaced element is placed in its content box the same way, by
+//! ``object-fit`` and ``object-position`` (``object_rect``).
+//!
+//! A border image is sliced into nine regions (``BorderImage::regions``): its corners are painted
+//! in the corners of the border area, its edges are stretched or repeated along its sides, and its
+//! middle is only painted with ``fill``.
+//! ref. https://www.w3.org/TR/css-backgrounds-3/#border-images
+
+use layout::{EdgeSizes, Rect};
+use style::{BackgroundPosition, BackgroundRepeat, BackgroundSize, BorderImageRepeat, ObjectFit,
+            Style};
+use css::{Unit, Value};
+
+use app_units::Au;
+
+/// At most as many copies of an image are painted in a box, e.g. of a tiny image repeated over a
+/// long page.
+const MAX_TILES: usize = 10000;
+
+/// The background image of a box, as read from its style
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackgroundImage {
+    pub size: BackgroundSize,
+    pub position: (BackgroundPosition, BackgroundPosition),
+    pub repeat: (BackgroundRepeat, BackgroundRepeat),
+    /// ``background-attachment: fixed``
+    pub fixed: bool,
+}
+
+impl BackgroundImage {
+    pub fn from_style(style: &Style) -> BackgroundImage {
+        BackgroundImage {
+            size: style.background_size(),
+            position: style.background_position(),
+            repeat: style.background_repeat(),
+            fixed: style.background_attachment_fixed(),
+        }
+    }
+
+    /// The positioning area and the painting area of the image of a box with ``padding_box``
+    /// and ``border_box``, in the coordinates of the document. A fixed image is placed in
+    /// ``viewport`` (the part of the document shown), and only painted in the part of the box
+    /// in it. None if nothing is painted.
+    pub fn areas(
+        &self,
+        padding_box: Rect,
+        border_box: Rect,
+        viewport: Rect,
+    ) -> Option<(Rect, Rect)> {
+        if self.fixed {
+            Some((viewport, border_box.intersection(viewport)?))
+        } else {
+            Some((padding_box, border_box))
+        }
+    }
+
+    /// Where the copies of an image of ``image_size`` (px) are painted (each as large as the
+    /// image is scaled to), in a box whose padding box is ``positioning_area`` and border box is
+    /// ``painting_area``.
+    pub fn tiles(
+        &self,
+        image_size: (f64, f64),
+        positioning_area: Rect,
+        painting_area: Rect,
+    ) -> Vec<Rect> {
+        let area = (
+            positioning_area.width.to_f64_px(),
+            positioning_area.height.to_f64_px(),
+        );
+        let (width, height) = self.tile_size(image_size, area);
+        if !(width > 0.0 && height > 0.0) {
+            return vec![];
+        }
+
+        let xs = axis_tiles(
+            positioning_area.x.to_f64_px(),
+            area.0,
+            painting_area.x.to_f64_px(),
+            painting_area.width.to_f64_px(),
+            width,
+            &self.position.0,
+            self.repeat.0,
+        );
+        let ys = axis_tiles(
+            positioning_area.y.to_f64_px(),
+            area.1,
+            painting_area.y.to_f64_px(),
+            painting_area.height.to_f64_px(),
+            height,
+            &self.position.1,
+            self.repeat.1,
+        );
+        let mut tiles = vec![];
+        for &y in &ys {
+            for &x in &xs {
+                if tiles.len() == MAX_TILES {
+                    return tiles;
+                }
+                tiles.push(Rect {
+                    x: Au::from_f64_px(x),
+                    y: Au::from_f64_px(y),
+                    width: Au::from_f64_px(width),
+                    height: Au::from_f64_px(height),
+                });
+            }
+        }
+        tiles
+    }
+
+    /// The size an image of ``image_size`` is scaled to in a positioning area of ``area``.
+    pub fn tile_size(&self, image_size: (f64, f64), area: (f64, f64)) -> (f64, f64) {
+        let (image_width, image_height) = image_size;
+        if !(image_width > 0.0 && image_height > 0.0) {
+            return (0.0, 0.0);
+        }
+        let ratio = image_width / image_height;
+        let (mut width, mut height, width_auto, height_auto) = match self.size {
+            BackgroundSize::Cover | BackgroundSize::Contain => {
+                let scale_x = area.0 / image_width;
+                let scale_y = area.1 / image_height;
+                let scale = if self.size == BackgroundSize::Cover {
+                    scale_x.max(scale_y)
+                } else {
+                    scale_x.min(scale_y)
+                };
+                (image_width * scale, image_height * scale, false, false)
+            }
+            BackgroundSize::Explicit(ref width, ref height) => {
+                let width = width.as_ref().and_then(|w| w.maybe_percent_to_px(area.0));
+                let height = height.as_ref().and_then(|h| h.maybe_percent_to_px(area.1));
+                match (width, height) {
+                    (Some(w), Some(h)) => (w, h, false, false),
+                    (Some(w), None) => (w, w / ratio, false, true),
+                    (None, Some(h)) => (h * ratio, h, true, false),
+                    (None, None) => (image_width, image_height, true, true),
+                }
+            }
+        };
+
+        // ``round`` scales the image so that it fits a whole number of times, and the other
+        // direction too if its size is ``auto``, to keep the ratio.
+        let rounded = |size: f64, area: f64| area / (area / size).round().max(1.0);
+        match self.repeat {
+            (BackgroundRepeat::Round, BackgroundRepeat::Round) => {
+                width = rounded(width, area.0);
+                height = rounded(height, area.1);
+            }
+            (BackgroundRepeat::Round, _) => {
+                let rounded_width = rounded(width, area.0);
+                if height_auto {
+                    height *= rounded_width / width;
+                }
+                width = rounded_width;
+            }
+            (_, BackgroundRepeat::Round) => {
+                let rounded_height = rounded(height, area.1);
+                if width_auto {
+                    width *= rounded_height / height;
+                }
+                height = rounded_height;
+            }
+            _ => {}
+        }
+        (width, height)
+    }
+}
+
+/// The border image of a box, as read from its style. The sides are in the order top, right,
+/// bottom and left.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BorderImage {
+    /// The offsets of the slicing lines from the edges of the image (in px of the image, or
+    /// percentages of its size)
+    pub slice: [Value; 4],
+    /// ``fill``: the middle region is painted too.
+    pub fill: bool,
+    /// A number of times the border width, a length, a percentage of the border area or ``auto``
+    /// (the size of the slice)
+    pub width: [Value; 4],
+    /// How far the border area extends beyond the border box, a number of times the border width
+    /// or a length
+    pub outset: [Value; 4],
+    pub repeat: (BorderImageRepeat, BorderImageRepeat),
+}
+
+/// A region of a border image: the part ``source`` of the image (px) is scaled to the size of
+/// each of ``tiles`` and painted there, clipped to ``clip``.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BorderImageRegion {
+    pub source: Rect,
+    pub tiles: Vec<Rect>,
+    pub clip: Rect,
+}
+
+impl BorderImage {
+    pub fn from_style(style: &Style) -> BorderImage {
+        let lengths = |values: &[Value]| -> Vec<Value> {
+            values
+                .iter()
+                .filter(|value| match **value {
+                    Value::Length(_, _) | Value::Num(_) => true,
+                    Value::Keyword(ref k) => k == "auto",
+                    _ => false,
+                })
+                .cloned()
+                .collect()
+        };
+        let slice = style.border_image_part("border-image-slice", 0);
+        let width = style.border_image_part("border-image-width", 1);
+        let outset = style.border_image_part("border-image-outset", 2);
+        BorderImage {
+            slice: sides(&lengths(&slice)).unwrap_or_else(|| {
+                let all = Value::Length(100.0, Unit::Percent);
+                [all.clone(), all.clone(), all.clone(), all]
+            }),
+            fill: slice.contains(&Value::Keyword("fill".to_string())),
+            width: sides(&lengths(&width))
+                .unwrap_or([Value::Num(1.0), Value::Num(1.0), Value::Num(1.0), Value::Num(1.0)]),
+            outset: sides(&lengths(&outset))
+                .unwrap_or([Value::Num(0.0), Value::Num(0.0), Value::Num(0.0), Value::Num(0.0)]),
+            repeat: style.border_image_repeat(),
+        }
+    }
+
+    /// The regions of an image of ``image_size`` (px) painted in the border of a box with
+    /// ``border_box`` and ``border`` widths. Empty regions are left out.
+    pub fn regions(
+        &self,
+        image_size: (f64, f64),
+        border_box: Rect,
+        border: &EdgeSizes,
+    ) -> Vec<BorderImageRegion> {
+        let (image_width, image_height) = image_size;
+        if !(image_width > 0.0 && image_height > 0.0) {
+            return vec![];
+        }
+        let border = [
+            border.top.to_f64_px(),
+            border.right.to_f64_px(),
+            border.bottom.to_f64_px(),
+            border.left.to_f64_px(),
+        ];
+        // The size of the image or of the area across the slicing lines of each side
+        let across = |sizes: (f64, f64), side: usize| match side {
+            0 | 2 => sizes.1,
+            _ => sizes.0,
+        };
+
+        let slice: Vec<f64> = self.slice
+            .iter()
+            .enumerate()
+            .map(|(side, slice)| {
+                let size = across(image_size, side);
+                slice
+                    .maybe_percent_to_px(size)
+                    .unwrap_or(0.0)
+                    .round()
+                    .max(0.0)
+                    .min(size)
+            })
+            .collect();
+
+        let outset: Vec<f64> = self.outset
+            .iter()
+            .zip(&border)
+            .map(|(outset, &border)| match *outset {
+                Value::Num(n) => n * border,
+                ref outset => outset.to_px().unwrap_or(0.0),
+            }.max(0.0))
+            .collect();
+        let x = border_box.x.to_f64_px() - outset[3];
+        let y = border_box.y.to_f64_px() - outset[0];
+        let area = (
+            border_box.width.to_f64_px() + outset[1] + outset[3],
+            border_box.height.to_f64_px() + outset[0] + outset[2],
+        );
+
+        let mut width: Vec<f64> = self.width
+            .iter()
+            .enumerate()
+            .map(|(side, width)| match *width {
+                Value::Num(n) => n * border[side],
+                Value::Keyword(_) => slice[side],
+                ref width => width.maybe_percent_to_px(across(area, side)).unwrap_or(0.0),
+            }.max(0.0))
+            .collect();
+        // The widths of the opposite sides are scaled down if they overlap.
+        let scale = (area.0 / (width[1] + width[3]))
+            .min(area.1 / (width[0] + width[2]))
+            .min(1.0);
+        for width in &mut width {
+            *width *= scale;
+        }
+
+        // The starts and the sizes of the columns and the rows, in the image and in the area
+        let image_columns = [
+            (0.0, slice[3]),
+            (slice[3], image_width - slice[3] - slice[1]),
+            (image_width - slice[1], slice[1]),
+        ];
+        let image_rows = [
+            (0.0, slice[0]),
+            (slice[0], image_height - slice[0] - slice[2]),
+            (image_height - slice[2], slice[2]),
+        ];
+        let columns = [
+            (x, width[3]),
+            (x + width[3], area.0 - width[3] - width[1]),
+            (x + area.0 - width[1], width[1]),
+        ];
+        let rows = [
+            (y, width[0]),
+            (y + width[0], area.1 - width[0] - width[2]),
+            (y + area.1 - width[2], width[2]),
+        ];
+        // The scale of the edges and the middle across the sides, that of the top and the left
+        // edges for the middle
+        let edge_scale = |size: f64, image_size: f64| if image_size > 0.0 {
+            size / image_size
+        } else {
+            0.0
+        };
+        let horizontal_scale = |row: usize| edge_scale(rows[row].1, image_rows[row].1);
+        let vertical_scale = |column: usize| edge_scale(columns[column].1, image_columns[column].1);
+
+        let mut regions = vec![];
+        for row in 0..3 {
+            for column in 0..3 {
+                let (image_x, image_width) = image_columns[column];
+                let (image_y, image_height) = image_rows[row];
+                let (x, width) = columns[column];
+                let (y, height) = rows[row];
+                if ((row, column) == (1, 1) && !self.fill)
+                    || !(image_width > 0.0 && image_height > 0.0 && width > 0.0 && height > 0.0)
+                {
+                    continue;
+                }
+
+                let (xs, tile_width) = if column == 1 {
+                    let scale = horizontal_scale(if row == 1 { 0 } else { row });
+                    let tile_width = if scale > 0.0 { image_width * scale } else { width };
+                    border_tiles(x, width, tile_width, self.repeat.0)
+                } else {
+                    (vec![x], width)
+                };
+                let (ys, tile_height) = if row == 1 {
+                    let scale = vertical_scale(if column == 1 { 0 } else { column });
+                    let tile_height = if scale > 0.0 { image_height * scale } else { height };
+                    border_tiles(y, height, tile_height, self.repeat.1)
+                } else {
+                    (vec![y], height)
+                };
+                let mut tiles = vec![];
+                for &y in &ys {
+                    for &x in &xs {
+                        if tiles.len() < MAX_TILES {
+                            tiles.push(px_rect(x, y, tile_width, tile_height));
+                        }
+                    }
+                }
+                if tiles.is_empty() {
+                    continue;
+                }
+                regions.push(BorderImageRegion {
+                    source: px_rect(image_x, image_y, image_width, image_height),
+                    tiles: tiles,
+                    clip: px_rect(x, y, width, height),
+                });
+            }
+        }
+        regions
+    }
+}
+
+/// The values of the four sides given by one to four ``values``, as in ``margin``.
+fn sides(values: &[Value]) -> Option<[Value; 4]> {
+    match *values {
+        [ref all] => Some([all.clone(), all.clone(), all.clone(), all.clone()]),
+        [ref vertical, ref horizontal] => Some([
+            vertical.clone(),
+            horizontal.clone(),
+            vertical.clone(),
+            horizontal.clone(),
+        ]),
+        [ref top, ref horizontal, ref bottom] => Some([
+            top.clone(),
+            horizontal.clone(),
+            bottom.clone(),
+            horizontal.clone(),
+        ]),
+        [ref top, ref right, ref bottom, ref left, ..] => {
+            Some([top.clone(), right.clone(), bottom.clone(), left.clone()])
+        }
+        [] => None,
+    }
+}
+
+/// The starts and the size of the copies of a border image tile of ``size`` in one direction,
+/// filling a part of the border area from ``start`` of ``length``.
+fn border_tiles(start: f64, length: f64, size: f64, repeat: BorderImageRepeat) -> (Vec<f64>, f64) {
+    match repeat {
+        BorderImageRepeat::Stretch => (vec![start], length),
+        BorderImageRepeat::Repeat => {
+            // Centered, and clipped at both ends
+            let end = start + length;
+            let origin = start + (length - size) / 2.0;
+            let mut tile = origin - ((origin - start) / size).ceil() * size;
+            let mut starts = vec![];
+            while tile < end && starts.len() < MAX_TILES {
+                starts.push(tile);
+                tile += size;
+            }
+            (starts, size)
+        }
+        BorderImageRepeat::Round => {
+            let count = (length / size).round().max(1.0).min(MAX_TILES as f64);
+            let size = length / count;
+            ((0..count as usize).map(|i| start + i as f64 * size).collect(), size)
+        }
+        BorderImageRepeat::Space => {
+            let count = (length / size).floor().min(MAX_TILES as f64);
+            let gap = (length - count * size) / (count + 1.0);
+            let starts = (0..count as usize)
+                .map(|i| start + gap + i as f64 * (size + gap))
+                .collect();
+            (starts, size)
+        }
+    }
+}
+
+fn px_rect(x: f64, y: f64, width: f64, height: f64) -> Rect {
+    Rect {
+        x: Au::from_f64_px(x),
+        y: Au::from_f64_px(y),
+        width: Au::from_f64_px(width),
+        height: Au::from_f64_px(height),
+    }
+}
+
+/// Where the content of ``natural_size`` (px) of a replaced element with ``content_box`` is
+/// painted, scaled by ``fit`` and placed at ``position``. It may overflow the content box, which
+/// it is clipped to.
+pub fn object_rect(
+    fit: ObjectFit,
+    position: &(BackgroundPosition, BackgroundPosition),
+    natural_size: (f64, f64),
+    content_box: Rect,
+) -> Rect {
+    let (box_width, box_height) = (
+        content_box.width.to_f64_px(),
+        content_box.height.to_f64_px(),
+    );
+    let (natural_width, natural_height) = natural_size;
+    let contain = || {
+        let scale = (box_width / natural_width).min(box_height / natural_height);
+        (natural_width * scale, natural_height * scale)
+    };
+    let (width, height) = if !(natural_width > 0.0 && natural_height > 0.0) {
+        (box_width, box_height)
+    } else {
+        match fit {
+            ObjectFit::Fill => (box_width, box_height),
+            ObjectFit::Contain => contain(),
+            ObjectFit::Cover => {
+                let scale = (box_width / natural_width).max(box_height / natural_height);
+                (natural_width * scale, natural_height * scale)
+            }
+            ObjectFit::None => natural_size,
+            ObjectFit::ScaleDown if natural_width <= box_width && natural_height <= box_height => {
+                natural_size
+            }
+            ObjectFit::ScaleDown => contain(),
+        }
+    };
+    Rect {
+        x: content_box.x + Au::from_f64_px(offset(&position.0, box_width - width)),
+        y: content_box.y + Au::from_f64_px(offset(&position.1, box_height - height)),
+        width: Au::from_f64_px(width),
+        height: Au::from_f64_px(height),
+    }
+}
+
+/// The starts of the copies of a tile of ``size`` in one direction, in a positioning area from
+/// ``area_start`` of ``area_size``, which cover the painting area from ``paint_start`` of
+/// ``paint_size``.
+fn axis_tiles(
+    area_start: f64,
+    area_size: f64,
+    paint_start: f64,
+    paint_size: f64,
+    size: f64,
+    position: &BackgroundPosition,
+    repeat: BackgroundRepeat,
+) -> Vec<f64> {
+    let paint_end = paint_start + paint_size;
+    let (origin, step) = match repeat {
+        BackgroundRepeat::Space if (area_size / size).floor() >= 2.0 => {
+            // The first and the last touch the edges of the area, and the position is ignored.
+            let count = (area_size / size).floor();
+            (area_start, size + (area_size - count * size) / (count - 1.0))
+        }
+        BackgroundRepeat::Space | BackgroundRepeat::NoRepeat => {
+            return vec![area_start + offset(position, area_size - size)];
+        }
+        BackgroundRepeat::Repeat | BackgroundRepeat::Round => {
+            (area_start + offset(position, area_size - size), size)
+        }
+    };
+
+    // From the first copy that reaches into the painting area
+    let mut start = origin - ((origin - paint_start) / step).ceil() * step;
+    let mut starts = vec![];
+    while start < paint_end && starts.len() < MAX_TILES {
+        if start + size > paint_start {
+            starts.push(start);
+        }
+        start += step;
+    }
+    starts
+}
+
+/// The offset of ``position`` from the start of the positioning area, where ``free_space`` is
+/// the size of the area minus that of the image.
+fn offset(position: &BackgroundPosition, free_space: f64) -> f64 {
+    let offset = match position.offset {
+        Value::Length(percent, Unit::Percent) => free_space * percent / 100.0,
+        ref offset => offset.to_px().unwrap_or(0.0),
+    };
+    if position.from_end {
+        free_space - offset
+    } else {
+        offset
+    }
+}
+
+#[test]
+fn test_tiles() {
+    use style::style_from;
+
+    let background = |css: &str| BackgroundImage::from_style(&style_from(css));
+    let px = Au::from_f64_px;
+    let rect = |x, y, w, h| Rect {
+        x: px(x),
+        y: px(y),
+        width: px(w),
+        height: px(h),
+    };
+    let starts = |tiles: Vec<Rect>| -> Vec<(f64, f64)> {
+        tiles
+            .iter()
+            .map(|t| (t.x.to_f64_px(), t.y.to_f64_px()))
+            .collect()
+    };
+    // A 100x50 padding box in a 110x60 border box
+    let positioning_area = rect(5.0, 5.0, 100.0, 50.0);
+    let painting_area = rect(0.0, 0.0, 110.0, 60.0);
+    let tiles = |css: &str, image_size| {
+        background(css).tiles(image_size, positioning_area, painting_area)
+    };
+
+    let no_repeat = tiles(
+        "background-repeat: no-repeat; background-position: right 10px top",
+        (20.0, 10.0),
+    );
+    assert_eq!(starts(no_repeat), vec![(75.0, 5.0)]);
+    let centered = tiles("background: url(a.png) no-repeat center", (20.0, 10.0));
+    assert_eq!(starts(centered), vec![(45.0, 25.0)]);
+    let bottom = tiles("background-repeat: no-repeat; background-position: bottom", (20.0, 10.0));
+    assert_eq!(starts(bottom), vec![(45.0, 45.0)]);
+
+    // Repeated from the position in both directions over the border box
+    let repeat_x = tiles("background-repeat: repeat-x; background-position: 10px 0", (40.0, 10.0));
+    assert_eq!(starts(repeat_x), vec![(-25.0, 5.0), (15.0, 5.0), (55.0, 5.0), (95.0, 5.0)]);
+    // Starting at -35px, which reaches into the border
+    assert_eq!(tiles("", (40.0, 40.0)).len(), 4 * 3);
+
+    // Four fit in the 100px, with 4px between them, and the pattern goes on in the border box.
+    let space = tiles("background-repeat: space no-repeat", (22.0, 10.0));
+    let xs: Vec<f64> = starts(space).iter().map(|t| t.0).collect();
+    assert_eq!(xs, vec![-21.0, 5.0, 31.0, 57.0, 83.0, 109.0]);
+    let one = tiles("background-repeat: space; background-position: 50% 0", (60.0, 60.0));
+    assert_eq!(starts(one), vec![(25.0, 5.0)]);
+
+    let size = |css: &str, image_size| background(css).tile_size(image_size, (100.0, 50.0));
+    assert_eq!(size("background-size: cover", (10.0, 10.0)), (100.0, 100.0));
+    assert_eq!(size("background-size: contain", (10.0, 10.0)), (50.0, 50.0));
+    assert_eq!(size("background-size: 50% auto", (10.0, 20.0)), (50.0, 100.0));
+    assert_eq!(size("background-size: auto 10px", (10.0, 20.0)), (5.0, 10.0));
+    assert_eq!(size("background-size: 30px 40px", (10.0, 20.0)), (30.0, 40.0));
+    // 3.3 tiles are rounded to 3, and the height is scaled with the width.
+    assert_eq!(size("background-repeat: round repeat", (30.0, 30.0)).0, 100.0 / 3.0);
+    assert_eq!(size("background-repeat: round repeat", (30.0, 30.0)).1, 100.0 / 3.0);
+
+    // A fixed image placed in the viewport scrolled by 100px
+    let viewport = rect(0.0, 100.0, 200.0, 50.0);
+    let fixed = background("background: url(a.png) fixed no-repeat right bottom");
+    let box_scrolled_past = rect(0.0, 0.0, 110.0, 60.0);
+    assert_eq!(fixed.areas(positioning_area, box_scrolled_past, viewport), None);
+    let (positioning, painting) = fixed
+        .areas(positioning_area, rect(0.0, 80.0, 110.0, 60.0), viewport)
+        .unwrap();
+    assert_eq!((positioning, painting), (viewport, rect(0.0, 100.0, 110.0, 40.0)));
+    let tiles = fixed.tiles((20.0, 10.0), positioning, painting);
+    assert_eq!(starts(tiles), vec![(180.0, 140.0)]);
+    assert!(!background("background-attachment: scroll").fixed);
+}
+
+#[test]
+fn test_object_rect() {
+    use std::collections::HashMap;
+    use style::style_from;
+
+    let px = Au::from_f64_px;
+    let rect = |x, y, w, h| Rect {
+        x: px(x),
+        y: px(y),
+        width: px(w),
+        height: px(h),
+    };
+    // A 40x20 image in a 100x50 content box at (10, 10)
+    let content_box = rect(10.0, 10.0, 100.0, 50.0);
+    let object = |css: &str| {
+        let style = style_from(css);
+        object_rect(style.object_fit(), &style.object_position(), (40.0, 20.0), content_box)
+    };
+    assert_eq!(object(""), content_box);
+    assert_eq!(object("object-fit: contain"), rect(10.0, 10.0, 100.0, 50.0));
+    assert_eq!(object("object-fit: none"), rect(40.0, 25.0, 40.0, 20.0));
+    assert_eq!(object("object-fit: scale-down"), rect(40.0, 25.0, 40.0, 20.0));
+    assert_eq!(
+        object("object-fit: none; object-position: right 5px top"),
+        rect(65.0, 10.0, 40.0, 20.0)
+    );
+    let style = style_from("object-fit: cover; object-position: 0 0");
+    let tall = object_rect(style.object_fit(), &style.object_position(), (10.0, 20.0), content_box);
+    assert_eq!(tall, rect(10.0, 10.0, 100.0, 200.0));
+    let centered = Style::new_with(HashMap::new()).object_position();
+    let wide = object_rect(ObjectFit::Contain, &centered, (200.0, 20.0), content_box);
+    assert_eq!(wide, rect(10.0, 30.0, 100.0, 10.0));
+}
+
+#[test]
+fn test_border_image() {
+    use style::style_from;
+
+    let border_image = |css: &str| BorderImage::from_style(&style_from(css));
+    let px = Au::from_f64_px;
+    let rect = |x, y, w, h| Rect {
+        x: px(x),
+        y: px(y),
+        width: px(w),
+        height: px(h),
+    };
+    let starts = |tiles: &Vec<Rect>| -> Vec<(f64, f64)> {
+        tiles
+            .iter()
+            .map(|t| (t.x.to_f64_px(), t.y.to_f64_px()))
+            .collect()
+    };
+    // A 30x30 image in the 10px border of a 100x60 border box
+    let border = EdgeSizes {
+        left: px(10.0),
+        right: px(10.0),
+        top: px(10.0),
+        bottom: px(10.0),
+    };
+    let border_box = rect(0.0, 0.0, 100.0, 60.0);
+    let regions = |css: &str| border_image(css).regions((30.0, 30.0), border_box, &border);
+
+    // The corners and the stretched edges, without the middle
+    let stretched = regions("border-image: url(a.png) 10");
+    assert_eq!(stretched.len(), 8);
+    assert_eq!(stretched[0].source, rect(0.0, 0.0, 10.0, 10.0));
+    assert_eq!(stretched[0].tiles, vec![rect(0.0, 0.0, 10.0, 10.0)]);
+    assert_eq!(stretched[1].source, rect(10.0, 0.0, 10.0, 10.0));
+    assert_eq!(stretched[1].tiles, vec![rect(10.0, 0.0, 80.0, 10.0)]);
+    assert_eq!(stretched[7].source, rect(20.0, 20.0, 10.0, 10.0));
+    assert_eq!(stretched[7].clip, rect(90.0, 50.0, 10.0, 10.0));
+
+    // The middle too, with the edges scaled to the width and a whole number of times
+    let rounded = regions("border-image: url(a.png) 10 fill / 20px round");
+    assert_eq!(rounded.len(), 9);
+    assert_eq!(rounded[1].clip, rect(20.0, 0.0, 60.0, 20.0));
+    assert_eq!(starts(&rounded[1].tiles), vec![(20.0, 0.0), (40.0, 0.0), (60.0, 0.0)]);
+    assert_eq!(rounded[4].source, rect(10.0, 10.0, 10.0, 10.0));
+    assert_eq!(rounded[4].tiles.len(), 3);
+    assert_eq!(rounded[4].tiles[0], rect(20.0, 20.0, 20.0, 20.0));
+
+    // Repeated from the center of each edge
+    let repeated = regions(
+        "border-image-source: url(a.png); border-image-slice: 33.3333%; \
+         border-image-repeat: repeat",
+    );
+    assert_eq!(repeated[1].tiles.len(), 9);
+    assert_eq!(repeated[1].tiles[0], rect(5.0, 0.0, 10.0, 10.0));
+    assert_eq!(repeated[3].clip, rect(0.0, 10.0, 10.0, 40.0));
+    assert_eq!(
+        starts(&repeated[3].tiles),
+        vec![(0.0, 5.0), (0.0, 15.0), (0.0, 25.0), (0.0, 35.0), (0.0, 45.0)]
+    );
+
+    // The widths are scaled down when they overlap, and the border area extends by the outset.
+    let overlapping = regions("border-image: url(a.png) 10 / 40px / 5px");
+    assert_eq!(overlapping[0].clip, rect(-5.0, -5.0, 35.0, 35.0));
+    assert_eq!(overlapping[2].clip, rect(70.0, -5.0, 35.0, 35.0));
+
+    let (spaced, size) = border_tiles(0.0, 35.0, 10.0, BorderImageRepeat::Space);
+    assert_eq!((spaced, size), (vec![1.25, 12.5, 23.75], 10.0));
+}
@@ -0,0 +1,220 @@
+//! A simplified Unicode Bidirectional Algorithm for reordering the boxes in a line.
+//! ref. https://www.unicode.org/reports/tr9/
+//!
+//! Texts are split into boxes of a single direction (see ``first_run_len``), so the levels are
+//! resolved for boxes instead of characters. Pango orders the characters in each box.
+
+/// The bidirectional types of characters, merged into the ones the boxes need.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BidiClass {
+    /// Strong left-to-right (L)
+    LeftToRight,
+    /// Strong right-to-left (R, AL)
+    RightToLeft,
+    /// Weak (EN, AN)
+    Number,
+    /// Whitespace, punctuation, etc.
+    Neutral,
+}
+
+pub fn bidi_class(c: char) -> BidiClass {
+    match c as u32 {
+        0x30...0x39 | 0x0660...0x0669 | 0x06F0...0x06F9 => BidiClass::Number,
+        0x0590...0x05FF // Hebrew
+        | 0x0600...0x06FF // Arabic
+        | 0x0700...0x08FF // Syriac, Thaana, NKo, etc.
+        | 0xFB1D...0xFDFF | 0xFE70...0xFEFF // Presentation forms
+        | 0x10800...0x10FFF | 0x1E800...0x1EFFF => BidiClass::RightToLeft,
+        _ if c.is_alphabetic() => BidiClass::LeftToRight,
+        _ => BidiClass::Neutral,
+    }
+}
+
+fn is_strong(class: BidiClass) -> bool {
+    class == BidiClass::LeftToRight || class == BidiClass::RightToLeft
+}
+
+/// The class of a box of text: the first strong direction, or ``Number`` if it has only
+/// numbers and neutrals.
+pub fn text_class(text: &str) -> BidiClass {
+    let mut class = BidiClass::Neutral;
+    for c in text.chars() {
+        match bidi_class(c) {
+            BidiClass::Number => class = BidiClass::Number,
+            BidiClass::Neutral => {}
+            strong => return strong,
+        }
+    }
+    class
+}
+
+/// The length (in bytes) of the first part of ``text`` that has a single direction. Texts only
+/// in left-to-right scripts are not split. Otherwise, the neutrals between the strong
+/// characters of different directions become a part of their own, since their direction
+/// depends on the texts around them.
+pub fn first_run_len(text: &str) -> usize {
+    if !text.chars().any(|c| bidi_class(c) == BidiClass::RightToLeft) {
+        return text.len();
+    }
+
+    let mut chars = text.char_indices();
+    let first_class = chars
+        .clone()
+        .map(|(_, c)| bidi_class(c))
+        .find(|&class| class != BidiClass::Number);
+    let direction = match first_class {
+        Some(class) if is_strong(class) => class,
+        // Leading neutrals (and numbers)
+        _ => {
+            return chars
+                .find(|&(_, c)| is_strong(bidi_class(c)))
+                .map_or(text.len(), |(i, _)| i)
+        }
+    };
+
+    let mut end = 0;
+    for (i, c) in chars {
+        match bidi_class(c) {
+            BidiClass::Neutral => {}
+            BidiClass::Number => end = i + c.len_utf8(),
+            class if class == direction => end = i + c.len_utf8(),
+            _ => break,
+        }
+    }
+    end
+}
+
+/// The paragraph embedding level: 1 if the first strong box is right-to-left, otherwise 0.
+pub fn paragraph_level(classes: &[BidiClass]) -> u8 {
+    match classes.iter().find(|&&class| is_strong(class)) {
+        Some(&BidiClass::RightToLeft) => 1,
+        _ => 0,
+    }
+}
+
+/// The embedding levels of the boxes in a line.
+pub fn resolve_levels(classes: &[BidiClass], paragraph_level: u8) -> Vec<u8> {
+    let embedding = if paragraph_level % 2 == 1 {
+        BidiClass::RightToLeft
+    } else {
+        BidiClass::LeftToRight
+    };
+
+    // W7: Numbers after left-to-right text (or at the start of a left-to-right paragraph)
+    // are left-to-right.
+    let mut last_strong = embedding;
+    let mut resolved: Vec<BidiClass> = classes
+        .iter()
+        .map(|&class| match class {
+            BidiClass::Number if last_strong == BidiClass::LeftToRight => BidiClass::LeftToRight,
+            class => {
+                if is_strong(class) {
+                    last_strong = class;
+                }
+                class
+            }
+        })
+        .collect();
+
+    // N1, N2: Neutrals between texts of the same direction take it (numbers count as
+    // right-to-left). Otherwise the embedding direction.
+    let direction_of = |class: BidiClass| match class {
+        BidiClass::Number => BidiClass::RightToLeft,
+        class => class,
+    };
+    for i in 0..resolved.len() {
+        if resolved[i] != BidiClass::Neutral {
+            continue;
+        }
+        let before = resolved[..i]
+            .iter()
+            .rev()
+            .find(|&&class| class != BidiClass::Neutral)
+            .map_or(embedding, |&class| direction_of(class));
+        let after = resolved[i + 1..]
+            .iter()
+            .find(|&&class| class != BidiClass::Neutral)
+            .map_or(embedding, |&class| direction_of(class));
+        resolved[i] = if before == after { before } else { embedding };
+    }
+
+    // I1, I2
+    resolved
+        .iter()
+        .map(|&class| match (embedding, class) {
+            (BidiClass::LeftToRight, BidiClass::RightToLeft) => paragraph_level + 1,
+            (BidiClass::LeftToRight, BidiClass::Number) => paragraph_level + 2,
+            (BidiClass::RightToLeft, BidiClass::LeftToRight)
+            | (BidiClass::RightToLeft, BidiClass::Number) => paragraph_level + 1,
+            _ => paragraph_level,
+        })
+        .collect()
+}
+
+/// The indices of the boxes from left to right (L2): from the highest level to the lowest odd
+/// level, each sequence of boxes at that level or higher is reversed.
+pub fn visual_order(levels: &[u8]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..levels.len()).collect();
+    let max_level = match levels.iter().max() {
+        Some(&max_level) => max_level,
+        None => return order,
+    };
+    let lowest_odd = match levels.iter().filter(|&&level| level % 2 == 1).min() {
+        Some(&level) => level,
+        None if max_level > 0 => 1,
+        None => return order,
+    };
+
+    for level in (lowest_odd..max_level + 1).rev() {
+        let mut i = 0;
+        while i < order.len() {
+            if levels[order[i]] < level {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < order.len() && levels[order[i]] >= level {
+                i += 1;
+            }
+            order[start..i].reverse();
+        }
+    }
+    order
+}
+
+#[test]
+fn test_first_run_len() {
+    assert_eq!(first_run_len("hello world"), 11);
+    assert_eq!(first_run_len("hello שלום world"), 5);
+    assert_eq!(first_run_len(" שלום world"), 1);
+    assert_eq!(first_run_len("שלום 123 world"), "שלום 123".len());
+    assert_eq!(first_run_len("שלום "), "שלום".len());
+    assert_eq!(first_run_len(" "), 1);
+}
+
+#[test]
+fn test_visual_order() {
+    use self::BidiClass::*;
+
+    let order = |classes: &[BidiClass]| {
+        visual_order(&resolve_levels(classes, paragraph_level(classes)))
+    };
+
+    assert_eq!(order(&[LeftToRight, Neutral, LeftToRight]), vec![0, 1, 2]);
+    // "hello" "שלום" " " "עולם" "world": The neutral between the Hebrew words is right-to-left.
+    assert_eq!(
+        order(&[LeftToRight, Neutral, RightToLeft, Neutral, RightToLeft, Neutral, LeftToRight]),
+        vec![0, 1, 4, 3, 2, 5, 6]
+    );
+    // A right-to-left paragraph with an English word and a number after it.
+    assert_eq!(
+        order(&[RightToLeft, Neutral, LeftToRight, Neutral, Number]),
+        vec![2, 3, 4, 1, 0]
+    );
+    assert_eq!(paragraph_level(&[Neutral, Number, RightToLeft]), 1);
+    assert_eq!(
+        resolve_levels(&[RightToLeft, Neutral, Number], 0),
+        vec![1, 1, 2]
+    );
+    assert!(visual_order(&[]).is_empty());
+}
@@ -1,6 +1,10 @@
+use containment;
 use css::{Unit, Value};
 use float::Floats;
-use layout::{Dimensions, LayoutBox};
+use dom::NodeType;
+use layout::{BoxType, Dimensions, LayoutBox, INDEFINITE_HEIGHT};
+use quirks::{self, Mode};
+use style::Display;
 
 use std::cmp::max;
 
@@ -13,12 +17,17 @@ impl LayoutBox {
         floats: &mut Floats,
         last_margin_bottom: Au,
         containing_block: Dimensions,
-        _saved_block: Dimensions,
+        saved_block: Dimensions,
         viewport: Dimensions,
     ) {
         self.load_image(containing_block);
 
-        self.floats = floats.clone();
+        // The floats inside a box with layout containment are kept apart from those outside.
+        self.floats = if self.property.contain().layout {
+            Floats::new()
+        } else {
+            floats.clone()
+        };
 
         let margin = self.property.margin();
         let padding = self.property.padding();
@@ -40,14 +49,14 @@ impl LayoutBox {
             self.floats.translate(self.dimensions.offset());
         }
 
-        self.layout_block_children(viewport);
+        self.layout_block_children(saved_block, viewport);
 
         self.assign_replaced_width_if_necessary();
         self.assign_replaced_height_if_necessary();
 
         // Parent height can depend on child height, so `calculate_height` must be called after the
         // children are laid out.
-        self.calculate_block_height();
+        self.calculate_block_height(saved_block);
     }
 
     /// Calculate the width of a block-level non-replaced element in normal flow.
@@ -109,7 +118,8 @@ impl LayoutBox {
             // If the values are overconstrained, calculate margin_right.
             (false, false, false) => {
                 margin_right = Value::Length(
-                    margin_right.maybe_percent_to_px(cb_width).unwrap() + underflow.to_f64_px(),
+                    margin_right.maybe_percent_to_px(cb_width).unwrap_or(0f64)
+                        + underflow.to_f64_px(),
                     Unit::Px,
                 );
             }
@@ -138,7 +148,8 @@ impl LayoutBox {
                     // Width can't be negative. Adjust the right margin instead.
                     width = Value::Length(0.0, Unit::Px);
                     margin_right = Value::Length(
-                        margin_right.maybe_percent_to_px(cb_width).unwrap() + underflow.to_f64_px(),
+                        margin_right.maybe_percent_to_px(cb_width).unwrap_or(0f64)
+                            + underflow.to_f64_px(),
                         Unit::Px,
                     );
                 }
@@ -151,6 +162,15 @@ impl LayoutBox {
             }
         }
 
+        layout_trace!(
+            "width: {} (specified {:?}), margin: {} {} (underflow {}px)",
+            width,
+            self.property.value("width"),
+            margin_left,
+            margin_right,
+            underflow.to_f64_px()
+        );
+
         let d = &mut self.dimensions;
         if let Some(width) = width.maybe_percent_to_px(cb_width) {
             d.content.width = Au::from_f64_px(width)
@@ -206,11 +226,11 @@ impl LayoutBox {
             d.margin.top = d.margin.top - last_margin_bottom;
         }
 
-        d.border.top = Au::from_f64_px(border.0.maybe_percent_to_px(cb_width).unwrap());
-        d.border.bottom = Au::from_f64_px(border.2.maybe_percent_to_px(cb_width).unwrap());
+        d.border.top = Au::from_f64_px(border.0.maybe_percent_to_px(cb_width).unwrap_or(0f64));
+        d.border.bottom = Au::from_f64_px(border.2.maybe_percent_to_px(cb_width).unwrap_or(0f64));
 
-        d.padding.top = Au::from_f64_px(padding.0.maybe_percent_to_px(cb_width).unwrap());
-        d.padding.bottom = Au::from_f64_px(padding.2.maybe_percent_to_px(cb_width).unwrap());
+        d.padding.top = Au::from_f64_px(padding.0.maybe_percent_to_px(cb_width).unwrap_or(0f64));
+        d.padding.bottom = Au::from_f64_px(padding.2.maybe_percent_to_px(cb_width).unwrap_or(0f64));
 
         self.z_index = self.property.lookup("z-index", "z-index", &vec![zero])[0]
             .clone()
@@ -224,7 +244,34 @@ impl LayoutBox {
 
     /// Lay out the block's children within its content area.
     /// Sets `self.dimensions.height` to the total content height.
-    pub fn layout_block_children(&mut self, viewport: Dimensions) {
+    /// ``saved_block`` is the containing block of the box, for its percentage height (see
+    /// ``definite_height``).
+    pub fn layout_block_children(&mut self, saved_block: Dimensions, viewport: Dimensions) {
+        // Skipped by ``content-visibility``: nothing is laid out, nor painted.
+        if containment::skips_contents(self) {
+            self.children.clear();
+            self.dimensions.content.height = Au(0);
+            return;
+        }
+
+        // The percentage heights of the children are of the height of the box if it does not
+        // depend on them.
+        let mut percentage_block = self.dimensions;
+        percentage_block.content.height = match self.definite_height(saved_block) {
+            Some(height) => height,
+            // In quirks mode, of the height of the ancestor whose height does not depend on them
+            None if quirks::mode() == Mode::Quirks => saved_block.content.height,
+            None => INDEFINITE_HEIGHT,
+        };
+
+        match self.property.display() {
+            Display::Flex => {
+                return self.layout_flex_items(saved_block, percentage_block, viewport)
+            }
+            Display::Grid => return self.layout_grid_items(percentage_block, viewport),
+            _ => {}
+        }
+
         let d = &mut self.dimensions;
         let mut last_margin_bottom = Au(0);
         let mut floats = &mut self.floats;
@@ -242,7 +289,7 @@ impl LayoutBox {
                 floats.ceiling = max(floats.ceiling, d.content.height);
             }
 
-            child.layout(&mut floats, last_margin_bottom, *d, *d, viewport);
+            child.layout(&mut floats, last_margin_bottom, *d, percentage_block, viewport);
 
             if child.in_normal_flow() {
                 last_margin_bottom = child.dimensions.margin.bottom;
@@ -253,13 +300,48 @@ impl LayoutBox {
     }
 
     /// Height of a block-level non-replaced element in normal flow with overflow visible.
-    pub fn calculate_block_height(&mut self) {
+    pub fn calculate_block_height(&mut self, saved_block: Dimensions) {
+        // Size containment: the content overflows the box, which is as high as if it were empty.
+        if containment::size_contained(self) {
+            self.dimensions.content.height = containment::contained_size(self).1;
+        }
         // If the height is set to an explicit length, use that exact length.
         // Otherwise, just keep the value set by `layout_block_children`.
-        if let Some(val) = self.get_style_node().value("height") {
-            if let Value::Length(h, Unit::Px) = val[0] {
-                self.dimensions.content.height = Au::from_f64_px(h);
+        if let Some(height) = self.definite_height(saved_block) {
+            self.dimensions.content.height = height;
+        } else if self.fills_parent_in_quirks_mode() && saved_block.content.height >= Au(0) {
+            // As high as the viewport (or the ``<html>`` for ``<body>``), less its own margins
+            let d = &mut self.dimensions;
+            let edges = d.margin.top + d.margin.bottom + d.border.top + d.border.bottom
+                + d.padding.top + d.padding.bottom;
+            d.content.height = max(d.content.height, saved_block.content.height - edges);
+        }
+    }
+
+    /// Whether the box is ``<html>`` or ``<body>`` in quirks mode, which fill the viewport if
+    /// their heights are ``auto``.
+    /// ref. https://quirks.spec.whatwg.org/#the-html-element-fills-the-viewport-quirk
+    fn fills_parent_in_quirks_mode(&self) -> bool {
+        quirks::mode() == Mode::Quirks
+            && self.box_type == BoxType::BlockNode
+            && match self.node.data {
+                NodeType::Element(ref e) => e.tag_name == "html" || e.tag_name == "body",
+                NodeType::Text(_) => false,
+            }
+    }
+
+    /// ``height`` if it does not depend on the content: a length, or a percentage of the height
+    /// of ``saved_block``, the containing block, if it is definite (not ``INDEFINITE_HEIGHT``).
+    /// The initial containing block of the root is the viewport, so ``height: 100%`` on
+    /// ``<html>``, on ``<body>`` then on the boxes in it fills the window.
+    /// ref. https://www.w3.org/TR/CSS2/visudet.html#the-height-property
+    pub fn definite_height(&self, saved_block: Dimensions) -> Option<Au> {
+        match self.property.value("height")?[0] {
+            Value::Length(h, Unit::Px) => Some(Au::from_f64_px(h)),
+            Value::Length(p, Unit::Percent) if saved_block.content.height >= Au(0) => {
+                Some(Au::from_f64_px(saved_block.content.height.to_f64_px() * p / 100.0))
             }
+            _ => None,
         }
     }
 }
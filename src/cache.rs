@@ -0,0 +1,161 @@
+use network::Url;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Cached responses are also saved here so that they survive restarts.
+pub const DISK_CACHE_DIR: &'static str = "cache/http";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheEntry {
+    /// The URL after redirects.
+    pub url: Url,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+    /// Validators sent back on revalidation (``If-None-Match`` and ``If-Modified-Since``).
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// The entry may be used without revalidation until then.
+    pub fresh_until: Option<SystemTime>,
+}
+
+impl CacheEntry {
+    pub fn is_fresh(&self, now: SystemTime) -> bool {
+        match self.fresh_until {
+            Some(fresh_until) => now < fresh_until,
+            None => false,
+        }
+    }
+
+    pub fn has_validators(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+
+    /// Everything but the body, one ``name value`` pair per line.
+    fn to_meta(&self) -> String {
+        let mut meta = format!("url {}\n", self.url.as_str());
+        if let Some(ref content_type) = self.content_type {
+            meta += format!("content-type {}\n", content_type).as_str();
+        }
+        if let Some(ref etag) = self.etag {
+            meta += format!("etag {}\n", etag).as_str();
+        }
+        if let Some(ref last_modified) = self.last_modified {
+            meta += format!("last-modified {}\n", last_modified).as_str();
+        }
+        if let Some(fresh_until) = self.fresh_until {
+            if let Ok(since_epoch) = fresh_until.duration_since(UNIX_EPOCH) {
+                meta += format!("fresh-until {}\n", since_epoch.as_secs()).as_str();
+            }
+        }
+        meta
+    }
+
+    fn from_meta(meta: &str, body: Vec<u8>) -> Option<CacheEntry> {
+        let mut fields: HashMap<&str, &str> = HashMap::new();
+        for line in meta.lines() {
+            let mut name_value = line.splitn(2, ' ');
+            if let (Some(name), Some(value)) = (name_value.next(), name_value.next()) {
+                fields.insert(name, value);
+            }
+        }
+
+        Some(CacheEntry {
+            url: Url::parse(fields.get("url")?).ok()?,
+            content_type: fields.get("content-type").map(|s| s.to_string()),
+            body: body,
+            etag: fields.get("etag").map(|s| s.to_string()),
+            last_modified: fields.get("last-modified").map(|s| s.to_string()),
+            fresh_until: fields
+                .get("fresh-until")
+                .and_then(|secs| secs.parse().ok())
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+        })
+    }
+}
+
+thread_local!(
+    static MEMORY_CACHE: RefCell<HashMap<String, CacheEntry>> = { RefCell::new(HashMap::new()) };
+);
+
+/// Returns the cached response for ``url`` (requested URL, not the one after redirects).
+pub fn lookup(url: &Url) -> Option<CacheEntry> {
+    if let Some(entry) = MEMORY_CACHE.with(|c| c.borrow().get(url.as_str()).cloned()) {
+        return Some(entry);
+    }
+
+    let entry = load_from_disk(url)?;
+    MEMORY_CACHE.with(|c| {
+        c.borrow_mut()
+            .insert(url.as_str().to_string(), entry.clone())
+    });
+    Some(entry)
+}
+
+pub fn store(url: &Url, entry: CacheEntry) {
+    // The disk cache is best effort.
+    save_to_disk(url, &entry).ok();
+    MEMORY_CACHE.with(|c| c.borrow_mut().insert(url.as_str().to_string(), entry));
+}
+
+/// Returns (metadata file path, body file path).
+fn disk_paths(url: &Url) -> (PathBuf, PathBuf) {
+    let mut hasher = DefaultHasher::new();
+    url.as_str().hash(&mut hasher);
+    let name = format!("{:016x}", hasher.finish());
+    let dir = PathBuf::from(DISK_CACHE_DIR);
+    (
+        dir.join(format!("{}.meta", name)),
+        dir.join(format!("{}.body", name)),
+    )
+}
+
+fn load_from_disk(url: &Url) -> Option<CacheEntry> {
+    let (meta_path, body_path) = disk_paths(url);
+
+    let mut meta = "".to_string();
+    fs::File::open(meta_path)
+        .ok()?
+        .read_to_string(&mut meta)
+        .ok()?;
+    let mut body = vec![];
+    fs::File::open(body_path)
+        .ok()?
+        .read_to_end(&mut body)
+        .ok()?;
+
+    CacheEntry::from_meta(meta.as_str(), body)
+}
+
+fn save_to_disk(url: &Url, entry: &CacheEntry) -> ::std::io::Result<()> {
+    let (meta_path, body_path) = disk_paths(url);
+    fs::create_dir_all(DISK_CACHE_DIR)?;
+    fs::File::create(body_path)?.write_all(entry.body.as_slice())?;
+    fs::File::create(meta_path)?.write_all(entry.to_meta().as_bytes())
+}
+
+#[test]
+fn test_meta() {
+    let entry = CacheEntry {
+        url: Url::parse("http://example.com/a.css").unwrap(),
+        content_type: Some("text/css; charset=utf-8".to_string()),
+        body: b"div { color: red; }".to_vec(),
+        etag: Some("W/\"abc\"".to_string()),
+        last_modified: None,
+        fresh_until: Some(UNIX_EPOCH + Duration::from_secs(1500000000)),
+    };
+    assert_eq!(
+        CacheEntry::from_meta(entry.to_meta().as_str(), entry.body.clone()),
+        Some(entry.clone())
+    );
+
+    assert!(entry.is_fresh(UNIX_EPOCH + Duration::from_secs(1400000000)));
+    assert!(!entry.is_fresh(UNIX_EPOCH + Duration::from_secs(1600000000)));
+    assert!(entry.has_validators());
+}
@@ -0,0 +1,363 @@
+//! A paint backend for the canvas of a web page: the display list becomes a script of calls to
+//! a ``CanvasRenderingContext2D`` (named ``ctx``), so a page laid out by naglfar can be shown in
+//! a browser (see ``to_html``).
+//!
+//! Texts are drawn with the fonts of the browser, so they may be a bit wider or narrower than
+//! the lines they are laid out in. Images are drawn as placeholders. A box with a ``filter`` is
+//! drawn on an ``OffscreenCanvas`` (``ctx`` meanwhile), which is then drawn with the filter.
+
+use css::{Color, TextDecoration};
+use font::{Font, FontSlant, FontWeight};
+use layout::{ImageMetaData, Rect};
+use painter::{PaintBackend, IMAGE_PLACEHOLDER_COLOR};
+use shape::{FillRule, Shape};
+use svg::{self, PathSegment};
+use filter::Filter;
+use image::Image;
+
+use std::fmt::Write;
+
+#[derive(Clone, Debug, Default)]
+pub struct CanvasBackend {
+    /// One statement a line
+    pub script: String,
+}
+
+impl CanvasBackend {
+    pub fn new() -> CanvasBackend {
+        CanvasBackend {
+            script: String::new(),
+        }
+    }
+
+    fn fill_style(&mut self, color: &Color) {
+        writeln!(
+            self.script,
+            "ctx.fillStyle = \"rgba({}, {}, {}, {})\";",
+            color.r,
+            color.g,
+            color.b,
+            color.a as f64 / 255.0
+        ).unwrap();
+    }
+
+    fn fill(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        writeln!(
+            self.script,
+            "ctx.fillRect({}, {}, {}, {});",
+            x, y, width, height
+        ).unwrap();
+    }
+}
+
+/// ``s`` as a string literal of JavaScript.
+fn string_literal(s: &str) -> String {
+    let mut literal = String::with_capacity(s.len() + 2);
+    literal.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            // Not to end the <script> the script is in
+            '<' => literal.push_str("\\u003c"),
+            c if (c as u32) < 0x20 || c == '\u{2028}' || c == '\u{2029}' => {
+                write!(literal, "\\u{:04x}", c as u32).unwrap()
+            }
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// The CSS ``font`` shorthand for ``font``.
+fn css_font(font: &Font) -> String {
+    let mut css = String::new();
+    if font.slant == FontSlant::Italic {
+        css += "italic ";
+    }
+    if font.weight == FontWeight::Bold {
+        css += "bold ";
+    }
+    let generic = ["serif", "sans-serif", "monospace", "cursive", "fantasy"];
+    let families: Vec<String> = font.family
+        .names()
+        .split(',')
+        .map(|name| {
+            if generic.contains(&name) {
+                name.to_string()
+            } else {
+                format!("'{}'", name.replace(['\\', '\''], ""))
+            }
+        })
+        .collect();
+    css + format!("{}px {}", font.size.to_f64_px(), families.join(", ")).as_str()
+}
+
+impl PaintBackend for CanvasBackend {
+    fn fill_rect(&mut self, color: &Color, rect: Rect) {
+        self.fill_style(color);
+        self.fill(
+            rect.x.to_f64_px(),
+            rect.y.to_f64_px(),
+            rect.width.to_f64_px(),
+            rect.height.to_f64_px(),
+        );
+    }
+
+    fn draw_image(&mut self, _image: &Image, _metadata: &ImageMetaData, rect: Rect) {
+        self.fill_rect(&IMAGE_PLACEHOLDER_COLOR, rect);
+    }
+
+    fn draw_image_tiles(
+        &mut self,
+        _image: &Image,
+        _metadata: &ImageMetaData,
+        tiles: &[Rect],
+        clip: Rect,
+    ) {
+        for tile in tiles {
+            if let Some(rect) = tile.intersection(clip) {
+                self.fill_rect(&IMAGE_PLACEHOLDER_COLOR, rect);
+            }
+        }
+    }
+
+    fn draw_text(
+        &mut self,
+        text: &str,
+        rect: Rect,
+        color: &Color,
+        decorations: &[TextDecoration],
+        font: &Font,
+    ) {
+        let x = rect.x.to_f64_px();
+        let baseline = (rect.y + font.select().get_ascent_descent().0).to_f64_px();
+        let size = font.size.to_f64_px();
+
+        writeln!(self.script, "ctx.font = {};", string_literal(&css_font(font))).unwrap();
+        writeln!(
+            self.script,
+            "ctx.letterSpacing = \"{}px\";",
+            font.letter_spacing.to_f64_px()
+        ).unwrap();
+        self.fill_style(color);
+        writeln!(
+            self.script,
+            "ctx.fillText({}, {}, {});",
+            string_literal(text),
+            x,
+            baseline
+        ).unwrap();
+
+        // Like the lines drawn by Pango, roughly
+        let thickness = (size / 16.0).max(1.0);
+        let width = rect.width.to_f64_px();
+        for decoration in decorations {
+            match *decoration {
+                TextDecoration::Underline => self.fill(x, baseline + thickness, width, thickness),
+                TextDecoration::Overline => {
+                    self.fill(x, rect.y.to_f64_px(), width, thickness)
+                }
+                TextDecoration::LineThrough => {
+                    self.fill(x, baseline - size * 0.3, width, thickness)
+                }
+                TextDecoration::None => {}
+            }
+        }
+    }
+
+    fn draw_path(&mut self, path: &svg::Path) {
+        writeln!(self.script, "ctx.beginPath();").unwrap();
+        for segment in &path.segments {
+            match *segment {
+                PathSegment::MoveTo(x, y) => writeln!(self.script, "ctx.moveTo({}, {});", x, y),
+                PathSegment::LineTo(x, y) => writeln!(self.script, "ctx.lineTo({}, {});", x, y),
+                PathSegment::CurveTo(x1, y1, x2, y2, x, y) => writeln!(
+                    self.script,
+                    "ctx.bezierCurveTo({}, {}, {}, {}, {}, {});",
+                    x1, y1, x2, y2, x, y
+                ),
+                PathSegment::Close => writeln!(self.script, "ctx.closePath();"),
+            }.unwrap();
+        }
+        if let Some((ref color, fill_rule)) = path.fill {
+            self.fill_style(color);
+            match fill_rule {
+                FillRule::NonZero => writeln!(self.script, "ctx.fill();"),
+                FillRule::EvenOdd => writeln!(self.script, "ctx.fill(\"evenodd\");"),
+            }.unwrap();
+        }
+        if let Some((ref color, width)) = path.stroke {
+            writeln!(
+                self.script,
+                "ctx.strokeStyle = \"rgba({}, {}, {}, {})\"; ctx.lineWidth = {}; ctx.stroke();",
+                color.r,
+                color.g,
+                color.b,
+                color.a as f64 / 255.0,
+                width
+            ).unwrap();
+        }
+    }
+
+    fn push_clip(&mut self, rect: Rect) {
+        writeln!(
+            self.script,
+            "ctx.save(); ctx.beginPath(); ctx.rect({}, {}, {}, {}); ctx.clip();",
+            rect.x.to_f64_px(),
+            rect.y.to_f64_px(),
+            rect.width.to_f64_px(),
+            rect.height.to_f64_px()
+        ).unwrap();
+    }
+
+    fn push_clip_path(&mut self, shape: &Shape) {
+        writeln!(self.script, "ctx.save(); ctx.beginPath();").unwrap();
+        let fill_rule = match *shape {
+            Shape::Ellipse {
+                center: (cx, cy),
+                radius: (rx, ry),
+            } => {
+                writeln!(
+                    self.script,
+                    "ctx.ellipse({}, {}, {}, {}, 0, 0, 2 * Math.PI);",
+                    cx, cy, rx, ry
+                ).unwrap();
+                FillRule::NonZero
+            }
+            Shape::Inset {
+                rect: (x, y, width, height),
+                radius,
+            } => {
+                writeln!(
+                    self.script,
+                    "ctx.roundRect({}, {}, {}, {}, {});",
+                    x, y, width, height, radius
+                ).unwrap();
+                FillRule::NonZero
+            }
+            Shape::Polygon(fill_rule, ref points) => {
+                for &(x, y) in points {
+                    writeln!(self.script, "ctx.lineTo({}, {});", x, y).unwrap();
+                }
+                writeln!(self.script, "ctx.closePath();").unwrap();
+                fill_rule
+            }
+        };
+        match fill_rule {
+            FillRule::NonZero => writeln!(self.script, "ctx.clip();"),
+            FillRule::EvenOdd => writeln!(self.script, "ctx.clip(\"evenodd\");"),
+        }.unwrap();
+    }
+
+    fn pop_clip(&mut self) {
+        writeln!(self.script, "ctx.restore();").unwrap();
+    }
+
+    fn push_layer(&mut self, rect: Rect) {
+        let (x, y) = (rect.x.to_f64_px(), rect.y.to_f64_px());
+        writeln!(self.script, "layers.push([ctx, {}, {}]);", x, y).unwrap();
+        writeln!(
+            self.script,
+            "ctx = new OffscreenCanvas({}, {}).getContext(\"2d\");",
+            rect.width.to_f64_px().ceil().max(1.0),
+            rect.height.to_f64_px().ceil().max(1.0)
+        ).unwrap();
+        writeln!(
+            self.script,
+            "ctx.textBaseline = \"alphabetic\"; ctx.translate({}, {});",
+            -x,
+            -y
+        ).unwrap();
+    }
+
+    fn pop_layer(&mut self, filters: &[Filter]) {
+        let filter: Vec<String> = filters.iter().map(|f| f.to_string()).collect();
+        writeln!(
+            self.script,
+            "{{ const [parent, x, y] = layers.pop(); parent.save(); parent.filter = {}; \
+             parent.drawImage(ctx.canvas, x, y); parent.restore(); ctx = parent; }}",
+            string_literal(&filter.join(" "))
+        ).unwrap();
+    }
+}
+
+/// A web page drawing ``script`` (from ``CanvasBackend``) on a canvas of ``width`` x ``height``
+/// CSS px.
+pub fn to_html(script: &str, width: f64, height: f64) -> String {
+    format!(
+        "<!DOCTYPE html>\n<meta charset=\"utf-8\">\n\
+         <canvas id=\"naglfar\" width=\"{w}\" height=\"{h}\"></canvas>\n\
+         <script>\n\
+         let ctx = document.getElementById(\"naglfar\").getContext(\"2d\");\n\
+         ctx.textBaseline = \"alphabetic\";\n\
+         // The contexts under the layers of filtered boxes\n\
+         const layers = [];\n\
+         {script}</script>\n",
+        w = width.ceil(),
+        h = height.ceil(),
+        script = script
+    )
+}
+
+#[test]
+fn test_canvas_backend() {
+    use css::{RED, WHITE};
+    use font::{FontFamily, FontFeatures};
+    use painter::{self, DisplayCommand, DisplayCommandInfo};
+
+    use app_units::Au;
+
+    assert_eq!(string_literal("a\"\\\n</script>"), "\"a\\\"\\\\\\u000a\\u003c/script>\"");
+
+    let rect = Rect {
+        x: Au::from_f64_px(1.0),
+        y: Au::from_f64_px(2.0),
+        width: Au::from_f64_px(30.0),
+        height: Au::from_f64_px(16.0),
+    };
+    let font = Font::new(
+        Au::from_f64_px(16.0),
+        FontWeight::Bold,
+        FontSlant::Normal,
+        FontFamily::new(&["DejaVu Sans".to_string(), "serif".to_string()]),
+        FontFeatures::new(&[]),
+        Au(0),
+        Au(0),
+    );
+    let list = vec![
+        DisplayCommandInfo::new(DisplayCommand::SolidColor(WHITE, rect)),
+        DisplayCommandInfo::new(DisplayCommand::Text(
+            "a<b".to_string(),
+            rect,
+            RED,
+            vec![TextDecoration::Underline],
+            font,
+        )),
+    ];
+
+    let mut backend = CanvasBackend::new();
+    painter::paint(&list, &mut backend);
+    let lines: Vec<&str> = backend.script.lines().collect();
+    assert_eq!(lines[0], "ctx.fillStyle = \"rgba(255, 255, 255, 1)\";");
+    assert_eq!(lines[1], "ctx.fillRect(1, 2, 30, 16);");
+    assert_eq!(lines[2], "ctx.font = \"bold 16px 'DejaVu Sans', serif\";");
+    assert_eq!(lines[4], "ctx.fillStyle = \"rgba(255, 0, 0, 1)\";");
+    assert!(lines[5].starts_with("ctx.fillText(\"a\\u003cb\", 1, "));
+    // The underline
+    assert!(lines[6].starts_with("ctx.fillRect(1, "));
+    assert_eq!(lines.len(), 7);
+
+    let mut layer = CanvasBackend::new();
+    layer.push_layer(rect);
+    layer.pop_layer(&[Filter::Blur(2.0), Filter::Grayscale(1.0)]);
+    let lines: Vec<&str> = layer.script.lines().collect();
+    assert_eq!(lines[0], "layers.push([ctx, 1, 2]);");
+    assert_eq!(lines[1], "ctx = new OffscreenCanvas(30, 16).getContext(\"2d\");");
+    assert!(lines[3].contains("parent.filter = \"blur(2px) grayscale(1)\";"));
+
+    let html = to_html(&backend.script, 800.0, 600.5);
+    assert!(html.contains("<canvas id=\"naglfar\" width=\"800\" height=\"601\"></canvas>"));
+    assert!(html.contains(backend.script.as_str()));
+}
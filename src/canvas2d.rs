@@ -0,0 +1,684 @@
+//! ``<canvas>``: a replaced element showing a bitmap as large as its ``width`` and ``height``
+//! (300x150 by default), which scripts (``getContext('2d')``) and the embedder (``with_context``)
+//! draw on with the 2D context API: rectangles, paths, text and images.
+//! ref. https://html.spec.whatwg.org/multipage/canvas.html
+//!
+//! The bitmap of a canvas is an offscreen Cairo surface, kept by the id of its node until another
+//! page is loaded (see ``clear``). Cairo keeps the current path and the transform; ``save`` and
+//! ``restore`` keep the rest of the ``DrawingState`` with them. The bitmap is painted like an
+//! image into the content box of the canvas (see ``pixbuf``), and is cleared when ``width`` or
+//! ``height`` changes.
+//!
+//! The bitmaps need the ``raster`` feature. Without it, a canvas is not drawn on
+//! (``getContext('2d')`` is null), and is painted as an empty box.
+
+use css::{self, Color, BLACK};
+use dom::{Node, NodeType};
+#[cfg(feature = "raster")]
+use media;
+use image::Image;
+
+#[cfg(feature = "raster")]
+use cairo::{self, FillRule, Format, ImageSurface, Operator};
+#[cfg(feature = "raster")]
+use cairo::prelude::SurfaceExt;
+#[cfg(feature = "raster")]
+use gdk::{self, ContextExt};
+#[cfg(feature = "raster")]
+use pango::{self, LayoutExt};
+#[cfg(feature = "raster")]
+use pangocairo;
+
+#[cfg(feature = "raster")]
+use std::cell::{Cell, RefCell};
+#[cfg(feature = "raster")]
+use std::collections::HashMap;
+
+/// The size of a canvas without ``width`` or ``height``
+pub const DEFAULT_SIZE: (u32, u32) = (300, 150);
+
+/// The largest width and height of a bitmap, that of Cairo
+const MAX_SIZE: u32 = 32767;
+
+/// The most px of a bitmap (16384 x 16384, 1 GiB)
+const MAX_AREA: u64 = 16384 * 16384;
+
+/// The ``font`` of a canvas until it is set
+pub const DEFAULT_FONT: &str = "10px sans-serif";
+
+#[cfg(feature = "raster")]
+thread_local!(
+    /// The canvases drawn on, by the id of their node
+    static CANVASES: RefCell<HashMap<usize, Context2d>> = { RefCell::new(HashMap::new()) };
+    /// Set when a canvas is drawn on, which then has to be painted again
+    static DRAWN: Cell<bool> = { Cell::new(false) };
+);
+
+/// ``textAlign``: where the text drawn is from the point given
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextAlign {
+    Start,
+    End,
+    Left,
+    Right,
+    Center,
+}
+
+impl TextAlign {
+    pub fn from_keyword(keyword: &str) -> Option<TextAlign> {
+        match keyword {
+            "start" => Some(TextAlign::Start),
+            "end" => Some(TextAlign::End),
+            "left" => Some(TextAlign::Left),
+            "right" => Some(TextAlign::Right),
+            "center" => Some(TextAlign::Center),
+            _ => None,
+        }
+    }
+
+    pub fn keyword(self) -> &'static str {
+        match self {
+            TextAlign::Start => "start",
+            TextAlign::End => "end",
+            TextAlign::Left => "left",
+            TextAlign::Right => "right",
+            TextAlign::Center => "center",
+        }
+    }
+}
+
+/// ``textBaseline``: which line of the text drawn is at the point given
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextBaseline {
+    Top,
+    Hanging,
+    Middle,
+    Alphabetic,
+    Ideographic,
+    Bottom,
+}
+
+impl TextBaseline {
+    pub fn from_keyword(keyword: &str) -> Option<TextBaseline> {
+        match keyword {
+            "top" => Some(TextBaseline::Top),
+            "hanging" => Some(TextBaseline::Hanging),
+            "middle" => Some(TextBaseline::Middle),
+            "alphabetic" => Some(TextBaseline::Alphabetic),
+            "ideographic" => Some(TextBaseline::Ideographic),
+            "bottom" => Some(TextBaseline::Bottom),
+            _ => None,
+        }
+    }
+
+    pub fn keyword(self) -> &'static str {
+        match self {
+            TextBaseline::Top => "top",
+            TextBaseline::Hanging => "hanging",
+            TextBaseline::Middle => "middle",
+            TextBaseline::Alphabetic => "alphabetic",
+            TextBaseline::Ideographic => "ideographic",
+            TextBaseline::Bottom => "bottom",
+        }
+    }
+}
+
+/// What ``save`` and ``restore`` keep, with the transform and the clip of Cairo
+#[derive(Clone, Debug, PartialEq)]
+pub struct DrawingState {
+    pub fill_style: Color,
+    pub stroke_style: Color,
+    pub line_width: f64,
+    /// The ``font`` shorthand of CSS, e.g. ``bold 20px serif`` (see ``is_valid_font``)
+    pub font: String,
+    /// Multiplies the alpha of all that is drawn
+    pub global_alpha: f64,
+    pub text_align: TextAlign,
+    pub text_baseline: TextBaseline,
+}
+
+impl Default for DrawingState {
+    fn default() -> DrawingState {
+        DrawingState {
+            fill_style: BLACK,
+            stroke_style: BLACK,
+            line_width: 1.0,
+            font: DEFAULT_FONT.to_string(),
+            global_alpha: 1.0,
+            text_align: TextAlign::Start,
+            text_baseline: TextBaseline::Alphabetic,
+        }
+    }
+}
+
+/// The 2D context of a canvas, and its bitmap. The coordinates are in px of the bitmap.
+#[cfg(feature = "raster")]
+pub struct Context2d {
+    /// The size it was made of (see ``new``)
+    size: (u32, u32),
+    surface: ImageSurface,
+    ctx: cairo::Context,
+    state: DrawingState,
+    /// By ``save``
+    saved: Vec<DrawingState>,
+}
+
+#[cfg(feature = "raster")]
+impl Context2d {
+    /// A transparent bitmap of (``width``, ``height``) px, at most ``MAX_SIZE`` px wide and high.
+    /// It is empty (0 x 0 px) if it is larger than ``MAX_AREA``, or if it can't be allocated.
+    pub fn new(size: (u32, u32)) -> Context2d {
+        let (width, height) = (size.0.min(MAX_SIZE), size.1.min(MAX_SIZE));
+        let surface = if width as u64 * height as u64 <= MAX_AREA {
+            ImageSurface::create(Format::ARgb32, width as i32, height as i32).ok()
+        } else {
+            None
+        };
+        let surface = match surface {
+            Some(surface) => surface,
+            None => ImageSurface::create(Format::ARgb32, 0, 0).unwrap(),
+        };
+        let ctx = cairo::Context::new(&surface);
+        Context2d {
+            size: size,
+            surface: surface,
+            ctx: ctx,
+            state: DrawingState::default(),
+            saved: vec![],
+        }
+    }
+
+    /// The size it was made of, that of the bitmap unless it is too large (see ``new``)
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    fn bitmap_size(&self) -> (u32, u32) {
+        (self.surface.get_width() as u32, self.surface.get_height() as u32)
+    }
+
+    pub fn state(&self) -> &DrawingState {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut DrawingState {
+        &mut self.state
+    }
+
+    pub fn save(&mut self) {
+        self.ctx.save();
+        self.saved.push(self.state.clone());
+    }
+
+    /// Restores what the last ``save`` kept, if any.
+    pub fn restore(&mut self) {
+        if let Some(state) = self.saved.pop() {
+            self.ctx.restore();
+            self.state = state;
+        }
+    }
+
+    pub fn translate(&mut self, x: f64, y: f64) {
+        self.ctx.translate(x, y);
+    }
+
+    pub fn scale(&mut self, x: f64, y: f64) {
+        self.ctx.scale(x, y);
+    }
+
+    /// Rotates by ``angle`` radians, clockwise.
+    pub fn rotate(&mut self, angle: f64) {
+        self.ctx.rotate(angle);
+    }
+
+    pub fn fill_rect(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        self.set_source(self.state.fill_style);
+        self.without_path(|ctx| {
+            ctx.rectangle(x, y, width, height);
+            ctx.fill();
+        });
+    }
+
+    pub fn stroke_rect(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        self.set_source(self.state.stroke_style);
+        self.ctx.set_line_width(self.state.line_width);
+        self.without_path(|ctx| {
+            ctx.rectangle(x, y, width, height);
+            ctx.stroke();
+        });
+    }
+
+    /// Makes the rectangle transparent.
+    pub fn clear_rect(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        self.without_path(|ctx| {
+            ctx.save();
+            ctx.set_operator(Operator::Clear);
+            ctx.rectangle(x, y, width, height);
+            ctx.fill();
+            ctx.restore();
+        });
+    }
+
+    pub fn begin_path(&mut self) {
+        self.ctx.new_path();
+    }
+
+    pub fn close_path(&mut self) {
+        self.ctx.close_path();
+    }
+
+    pub fn move_to(&mut self, x: f64, y: f64) {
+        self.ctx.move_to(x, y);
+    }
+
+    pub fn line_to(&mut self, x: f64, y: f64) {
+        self.ctx.line_to(x, y);
+    }
+
+    pub fn bezier_curve_to(&mut self, x1: f64, y1: f64, x2: f64, y2: f64, x: f64, y: f64) {
+        self.ensure_point(x1, y1);
+        self.ctx.curve_to(x1, y1, x2, y2, x, y);
+    }
+
+    pub fn quadratic_curve_to(&mut self, cx: f64, cy: f64, x: f64, y: f64) {
+        self.ensure_point(cx, cy);
+        // The cubic curve of the same shape
+        let (x0, y0) = self.ctx.get_current_point();
+        self.ctx.curve_to(
+            x0 + (cx - x0) * 2.0 / 3.0,
+            y0 + (cy - y0) * 2.0 / 3.0,
+            x + (cx - x) * 2.0 / 3.0,
+            y + (cy - y) * 2.0 / 3.0,
+            x,
+            y,
+        );
+    }
+
+    /// Adds the arc of the circle at (``x``, ``y``) from the angle ``start`` to ``end`` (radians,
+    /// clockwise), and the line from the current point to it. Ignored if ``radius`` is negative.
+    pub fn arc(&mut self, x: f64, y: f64, radius: f64, start: f64, end: f64, ccw: bool) {
+        if radius < 0.0 {
+            return;
+        }
+        if ccw {
+            self.ctx.arc_negative(x, y, radius, start, end);
+        } else {
+            self.ctx.arc(x, y, radius, start, end);
+        }
+    }
+
+    pub fn rect(&mut self, x: f64, y: f64, width: f64, height: f64) {
+        self.ctx.rectangle(x, y, width, height);
+    }
+
+    /// Fills the current path, by the even-odd rule if ``even_odd`` (else the nonzero rule).
+    pub fn fill(&mut self, even_odd: bool) {
+        self.set_source(self.state.fill_style);
+        self.ctx.set_fill_rule(if even_odd {
+            FillRule::EvenOdd
+        } else {
+            FillRule::Winding
+        });
+        self.ctx.fill_preserve();
+    }
+
+    pub fn stroke(&mut self) {
+        self.set_source(self.state.stroke_style);
+        self.ctx.set_line_width(self.state.line_width);
+        self.ctx.stroke_preserve();
+    }
+
+    /// Draws ``text`` in ``font`` from (``x``, ``y``), by ``textAlign`` and ``textBaseline``.
+    pub fn fill_text(&mut self, text: &str, x: f64, y: f64) {
+        if let Some((layout, (dx, dy))) = self.text_layout(text) {
+            self.set_source(self.state.fill_style);
+            self.without_path(|ctx| {
+                ctx.move_to(x + dx, y + dy);
+                pangocairo::functions::show_layout(ctx, &layout);
+            });
+        }
+    }
+
+    /// Strokes the outlines of ``text``, placed like ``fill_text``.
+    pub fn stroke_text(&mut self, text: &str, x: f64, y: f64) {
+        if let Some((layout, (dx, dy))) = self.text_layout(text) {
+            self.set_source(self.state.stroke_style);
+            self.ctx.set_line_width(self.state.line_width);
+            self.without_path(|ctx| {
+                ctx.move_to(x + dx, y + dy);
+                pangocairo::functions::layout_path(ctx, &layout);
+                ctx.stroke();
+            });
+        }
+    }
+
+    /// The width (px) of ``text`` drawn in ``font``.
+    pub fn measure_text(&self, text: &str) -> f64 {
+        self.text_layout(text)
+            .map_or(0.0, |(layout, _)| layout.get_pixel_size().0 as f64)
+    }
+
+    /// Draws the rectangle ``source`` (x, y, width and height) of ``image`` into ``dest``.
+    pub fn draw_image(
+        &mut self,
+        image: &Image,
+        source: (f64, f64, f64, f64),
+        dest: (f64, f64, f64, f64),
+    ) {
+        let (sx, sy, sw, sh) = source;
+        let (dx, dy, dw, dh) = dest;
+        if sw == 0.0 || sh == 0.0 {
+            return;
+        }
+        let alpha = self.state.global_alpha;
+        self.without_path(|ctx| {
+            ctx.save();
+            ctx.rectangle(dx, dy, dw, dh);
+            ctx.clip();
+            ctx.translate(dx, dy);
+            ctx.scale(dw / sw, dh / sh);
+            ctx.set_source_pixbuf(image, -sx, -sy);
+            ctx.paint_with_alpha(alpha);
+            ctx.restore();
+        });
+    }
+
+    /// The color of the pixel at (``x``, ``y``), or None outside the bitmap.
+    pub fn pixel(&self, x: u32, y: u32) -> Option<Color> {
+        let (width, height) = self.bitmap_size();
+        if x >= width || y >= height {
+            return None;
+        }
+        let mut copy = ImageSurface::create(Format::ARgb32, 1, 1).ok()?;
+        {
+            let ctx = cairo::Context::new(&copy);
+            ctx.set_operator(Operator::Source);
+            ctx.set_source_surface(&self.surface, -(x as f64), -(y as f64));
+            ctx.paint();
+        }
+        let data = copy.get_data().ok()?;
+        // Premultiplied ARGB, in the native byte order
+        let argb = u32::from_ne_bytes([data[0], data[1], data[2], data[3]]);
+        let a = argb >> 24;
+        let channel = |shift: u32| ((argb >> shift & 0xff) * 255).checked_div(a).unwrap_or(0) as u8;
+        Some(Color {
+            r: channel(16),
+            g: channel(8),
+            b: channel(0),
+            a: a as u8,
+        })
+    }
+
+    /// The bitmap as a pixbuf to paint
+    pub fn pixbuf(&self) -> Option<Image> {
+        let (width, height) = self.bitmap_size();
+        if width == 0 || height == 0 {
+            return None;
+        }
+        self.surface.flush();
+        gdk::pixbuf_get_from_surface(&self.surface, 0, 0, width as i32, height as i32)
+    }
+
+    fn set_source(&self, color: Color) {
+        self.ctx.set_source_rgba(
+            color.r as f64 / 255.0,
+            color.g as f64 / 255.0,
+            color.b as f64 / 255.0,
+            color.a as f64 / 255.0 * self.state.global_alpha,
+        );
+    }
+
+    /// Moves to (``x``, ``y``) if there is no current point, as before a curve.
+    fn ensure_point(&self, x: f64, y: f64) {
+        if !self.ctx.has_current_point() {
+            self.ctx.move_to(x, y);
+        }
+    }
+
+    /// Runs ``f``, which draws a path of its own, keeping the current path.
+    fn without_path<F: FnOnce(&cairo::Context)>(&self, f: F) {
+        let path = self.ctx.copy_path();
+        self.ctx.new_path();
+        f(&self.ctx);
+        self.ctx.new_path();
+        self.ctx.append_path(&path);
+    }
+
+    /// The layout of ``text`` in ``font``, and where it is from the point it is drawn from
+    fn text_layout(&self, text: &str) -> Option<(pango::Layout, (f64, f64))> {
+        let layout = pangocairo::functions::create_layout(&self.ctx)?;
+        let font_desc = font_description(&self.state.font)
+            .or_else(|| font_description(DEFAULT_FONT))?;
+        layout.set_font_description(Some(&font_desc));
+        // On a single line
+        layout.set_text(text.replace(|c: char| c.is_whitespace(), " ").as_str());
+        let (width, height) = layout.get_pixel_size();
+        let (width, height) = (width as f64, height as f64);
+        let baseline = layout.get_baseline() as f64 / pango::SCALE as f64;
+        let dx = match self.state.text_align {
+            TextAlign::Start | TextAlign::Left => 0.0,
+            TextAlign::End | TextAlign::Right => -width,
+            TextAlign::Center => -width / 2.0,
+        };
+        let dy = match self.state.text_baseline {
+            TextBaseline::Top | TextBaseline::Hanging => 0.0,
+            TextBaseline::Middle => -height / 2.0,
+            TextBaseline::Alphabetic => -baseline,
+            TextBaseline::Ideographic | TextBaseline::Bottom => -height,
+        };
+        Some((layout, (dx, dy)))
+    }
+}
+
+/// The size (px) of the bitmap of the ``<canvas>`` ``node``
+pub fn bitmap_size(node: &Node) -> (u32, u32) {
+    let attr = |name: &str, default: u32| match node.data {
+        NodeType::Element(ref e) => e.attrs
+            .get(name)
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(default),
+        NodeType::Text(_) => default,
+    };
+    (attr("width", DEFAULT_SIZE.0), attr("height", DEFAULT_SIZE.1))
+}
+
+/// The natural size (px) of the ``<canvas>`` ``node``, that of its bitmap
+pub fn intrinsic_size(node: &Node) -> (f64, f64) {
+    let (width, height) = bitmap_size(node);
+    (width as f64, height as f64)
+}
+
+/// Draws with ``f`` on the canvas of ``node_id``, whose bitmap is of ``size`` (see
+/// ``bitmap_size``). Its bitmap is made, or cleared if it was of another size.
+#[cfg(feature = "raster")]
+pub fn with_context<T, F>(node_id: usize, size: (u32, u32), f: F) -> T
+where
+    F: FnOnce(&mut Context2d) -> T,
+{
+    DRAWN.with(|d| d.set(true));
+    CANVASES.with(|c| {
+        let mut canvases = c.borrow_mut();
+        let context = canvases
+            .entry(node_id)
+            .or_insert_with(|| Context2d::new(size));
+        if context.size() != size {
+            *context = Context2d::new(size);
+        }
+        f(context)
+    })
+}
+
+/// The drawing state of the canvas of ``node_id``, the default one until it is drawn on
+#[cfg(feature = "raster")]
+pub fn state(node_id: usize) -> DrawingState {
+    CANVASES.with(|c| {
+        c.borrow()
+            .get(&node_id)
+            .map_or_else(DrawingState::default, |context| context.state.clone())
+    })
+}
+
+/// The bitmap of the canvas of ``node_id`` to paint, None if it has not been drawn on since it
+/// was made of ``size``
+#[cfg(feature = "raster")]
+pub fn pixbuf(node_id: usize, size: (u32, u32)) -> Option<Image> {
+    CANVASES.with(|c| {
+        c.borrow()
+            .get(&node_id)
+            .filter(|context| context.size() == size)
+            .and_then(Context2d::pixbuf)
+    })
+}
+
+#[cfg(not(feature = "raster"))]
+pub fn pixbuf(_node_id: usize, _size: (u32, u32)) -> Option<Image> {
+    None
+}
+
+/// Whether a canvas has been drawn on since the last call
+#[cfg(feature = "raster")]
+pub fn take_drawn() -> bool {
+    DRAWN.with(|d| d.replace(false))
+}
+
+#[cfg(not(feature = "raster"))]
+pub fn take_drawn() -> bool {
+    false
+}
+
+/// Forgets the canvases, of the page before.
+#[cfg(feature = "raster")]
+pub fn clear() {
+    CANVASES.with(|c| c.borrow_mut().clear());
+    DRAWN.with(|d| d.set(false));
+}
+
+#[cfg(not(feature = "raster"))]
+pub fn clear() {}
+
+/// Whether ``font`` can be the ``font`` of a canvas
+#[cfg(feature = "raster")]
+pub fn is_valid_font(font: &str) -> bool {
+    font_description(font).is_some()
+}
+
+/// The Pango font of the ``font`` shorthand ``font``: the style, the variant and the weight, the
+/// size (and the line height, ignored) and the families. None if it is invalid.
+#[cfg(feature = "raster")]
+fn font_description(font: &str) -> Option<pango::FontDescription> {
+    let mut font_desc = pango::FontDescription::new();
+    let mut words = font.split_whitespace();
+    let mut size = None;
+    for word in &mut words {
+        match word.to_lowercase().as_str() {
+            "normal" => {}
+            "italic" | "oblique" => font_desc.set_style(pango::Style::Italic),
+            "small-caps" => font_desc.set_variant(pango::Variant::SmallCaps),
+            "bold" | "bolder" => font_desc.set_weight(pango::Weight::Bold),
+            "lighter" => font_desc.set_weight(pango::Weight::Light),
+            weight if weight.parse::<u32>().is_ok() => {
+                font_desc.set_weight(match weight.parse::<u32>().unwrap() {
+                    0..=349 => pango::Weight::Light,
+                    350..=549 => pango::Weight::Normal,
+                    _ => pango::Weight::Bold,
+                })
+            }
+            size_and_line_height => {
+                size = media::parse_length(size_and_line_height.split('/').next().unwrap());
+                break;
+            }
+        }
+    }
+    let size = size.filter(|&size| size > 0.0)?;
+    let families: Vec<String> = words
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split(',')
+        .map(|family| family.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+        .filter(|family| !family.is_empty())
+        .collect();
+    if families.is_empty() {
+        return None;
+    }
+    font_desc.set_family(families.join(",").as_str());
+    font_desc.set_absolute_size(size * pango::SCALE as f64);
+    Some(font_desc)
+}
+
+/// ``fillStyle`` and ``strokeStyle`` of ``color``: ``#rrggbb`` if it is opaque, else ``rgba()``
+pub fn serialize_color(color: Color) -> String {
+    if color.a == 255 {
+        format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+    } else {
+        let alpha = (color.a as f64 / 255.0 * 1000.0).round() / 1000.0;
+        format!("rgba({}, {}, {}, {})", color.r, color.g, color.b, alpha)
+    }
+}
+
+/// The color of ``fillStyle`` or ``strokeStyle`` set to ``style``, None if it is not one
+pub fn parse_color(style: &str) -> Option<Color> {
+    css::parse_value(style.to_string()).to_color()
+}
+
+#[cfg(feature = "raster")]
+#[test]
+fn test_context2d() {
+    use css::{RED, WHITE};
+
+    let mut context = Context2d::new((20, 10));
+    assert_eq!(context.pixel(0, 0), Some(Color { r: 0, g: 0, b: 0, a: 0 }));
+    assert_eq!(context.pixel(20, 0), None);
+
+    context.state_mut().fill_style = RED;
+    context.fill_rect(0.0, 0.0, 10.0, 10.0);
+    assert_eq!(context.pixel(5, 5), Some(RED));
+    assert_eq!(context.pixel(15, 5).map(|color| color.a), Some(0));
+
+    // A path is kept by ``fill_rect``, and filled in the transform when it was added.
+    context.save();
+    context.translate(10.0, 0.0);
+    context.state_mut().fill_style = WHITE;
+    context.begin_path();
+    context.rect(0.0, 0.0, 5.0, 5.0);
+    context.fill_rect(5.0, 5.0, 5.0, 5.0);
+    context.restore();
+    assert_eq!(context.state().fill_style, RED);
+    context.fill(false);
+    assert_eq!(context.pixel(12, 2), Some(RED));
+    assert_eq!(context.pixel(17, 7), Some(WHITE));
+
+    context.clear_rect(0.0, 0.0, 5.0, 5.0);
+    assert_eq!(context.pixel(2, 2).map(|color| color.a), Some(0));
+
+    context.state_mut().global_alpha = 0.5;
+    context.fill_rect(15.0, 0.0, 5.0, 5.0);
+    assert_eq!(context.pixel(17, 2).map(|color| color.a), Some(128));
+
+    assert!(is_valid_font("italic bold 12px/30px Georgia, \"DejaVu Serif\""));
+    assert!(!is_valid_font("bold serif"));
+    assert!(!is_valid_font("12px"));
+    assert_eq!(serialize_color(RED), "#ff0000");
+    assert_eq!(
+        parse_color("rgba(0, 0, 255, 0.5)").map(serialize_color),
+        Some("rgba(0, 0, 255, 0.498)".to_string())
+    );
+}
+
+#[cfg(feature = "raster")]
+#[test]
+fn test_too_large_bitmap() {
+    use css::RED;
+
+    // Empty, and kept as it is while the canvas is of the same size
+    let size = (MAX_SIZE, MAX_SIZE);
+    with_context(0, size, |context| {
+        assert_eq!(context.size(), size);
+        assert_eq!(context.pixel(0, 0), None);
+        assert!(context.pixbuf().is_none());
+        context.state_mut().fill_style = RED;
+        context.fill_rect(0.0, 0.0, 10.0, 10.0);
+    });
+    with_context(0, size, |context| assert_eq!(context.state().fill_style, RED));
+    assert!(pixbuf(0, size).is_none());
+    clear();
+}
@@ -0,0 +1,238 @@
+//! Size containment and ``content-visibility``: boxes laid out as if they were empty (as large as
+//! ``contain-intrinsic-size``), and boxes whose contents are skipped, neither laid out nor
+//! painted. ref. https://www.w3.org/TR/css-contain-2/#content-visibility
+//!
+//! ``content-visibility: auto`` skips the contents of a box while it is far from the part of the
+//! document shown (see ``set_visible_area``). Where the box is comes from the layout before, kept
+//! by the id of its node, so the page is laid out again (see ``layout::layout_tree``) until the
+//! boxes whose contents are laid out are those near the part shown.
+
+use layout::{self, LayoutBox, Rect};
+use overflow;
+use style::ContentVisibility;
+
+use app_units::Au;
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+/// How many times at most a page is laid out for its ``content-visibility: auto`` boxes
+pub const MAX_LAYOUT_PASSES: usize = 4;
+
+thread_local!(
+    /// The part of the document shown (CSS px). None is the whole document, so that nothing is
+    /// skipped for ``auto`` (e.g. when it is rendered without a window).
+    static VISIBLE_AREA: Cell<Option<Rect>> = { Cell::new(None) };
+    /// The ``content-visibility: auto`` boxes laid out, by the id of their node: their border box
+    /// in the document (None until it is known), and whether their contents were skipped
+    static AUTO_BOXES: RefCell<HashMap<usize, (Option<Rect>, bool)>> = {
+        RefCell::new(HashMap::new())
+    };
+    /// The content sizes of the ``auto`` boxes when their contents were last laid out, for
+    /// ``contain-intrinsic-size: auto``
+    static LAST_SIZES: RefCell<HashMap<usize, (Au, Au)>> = { RefCell::new(HashMap::new()) };
+);
+
+/// Sets the part of the document shown, e.g. when it is scrolled. Returns whether the page has to
+/// be laid out again: a ``content-visibility: auto`` box has come near it, or gone away from it.
+pub fn set_visible_area(area: Option<Rect>) -> bool {
+    if VISIBLE_AREA.with(|a| a.replace(area)) == area {
+        return false;
+    }
+    invalidate_if_near_changed()
+}
+
+pub fn visible_area() -> Option<Rect> {
+    VISIBLE_AREA.with(|a| a.get())
+}
+
+/// The ``content-visibility: auto`` boxes laid out on a thread, to lay out on another one (see
+/// ``layout_worker``)
+#[derive(Clone, Debug, Default)]
+pub struct Boxes {
+    auto_boxes: HashMap<usize, (Option<Rect>, bool)>,
+    last_sizes: HashMap<usize, (Au, Au)>,
+}
+
+/// The ``content-visibility: auto`` boxes laid out on this thread
+pub fn boxes() -> Boxes {
+    Boxes {
+        auto_boxes: AUTO_BOXES.with(|b| b.borrow().clone()),
+        last_sizes: LAST_SIZES.with(|s| s.borrow().clone()),
+    }
+}
+
+/// Keeps ``boxes`` (laid out on another thread) instead of those laid out on this thread. Returns
+/// whether the page has to be laid out again, like ``set_visible_area``, if the part of the
+/// document shown has changed meanwhile.
+pub fn set_boxes(boxes: Boxes) -> bool {
+    AUTO_BOXES.with(|b| *b.borrow_mut() = boxes.auto_boxes);
+    LAST_SIZES.with(|s| *s.borrow_mut() = boxes.last_sizes);
+    invalidate_if_near_changed()
+}
+
+/// Invalidates the layout if the contents of an ``auto`` box near the part of the document shown
+/// were skipped, or those of a box far from it were not. Returns whether they were.
+fn invalidate_if_near_changed() -> bool {
+    let changed = AUTO_BOXES.with(|b| {
+        b.borrow()
+            .values()
+            .any(|&(border_box, skipped)| skipped == is_near(border_box))
+    });
+    if changed {
+        layout::invalidate_layout();
+    }
+    changed
+}
+
+/// Whether the contents of ``layout_box`` are skipped (by ``content-visibility``) in the layout
+/// going on.
+pub fn skips_contents(layout_box: &LayoutBox) -> bool {
+    match layout_box.property.content_visibility() {
+        ContentVisibility::Visible => false,
+        ContentVisibility::Hidden => true,
+        ContentVisibility::Auto => {
+            let id = match layout_box.node_id {
+                Some(id) => id,
+                None => return false,
+            };
+            AUTO_BOXES.with(|b| {
+                let mut boxes = b.borrow_mut();
+                let auto_box = boxes.entry(id).or_insert((None, true));
+                auto_box.1 = !is_near(auto_box.0);
+                auto_box.1
+            })
+        }
+    }
+}
+
+/// Whether ``layout_box`` is laid out as if it were empty: it has size containment, or its
+/// contents are skipped.
+pub fn size_contained(layout_box: &LayoutBox) -> bool {
+    layout_box.property.contain().size || skips_contents(layout_box)
+}
+
+/// The content width and height of ``layout_box`` laid out as if it were empty
+pub fn contained_size(layout_box: &LayoutBox) -> (Au, Au) {
+    let last_size = layout_box
+        .node_id
+        .and_then(|id| LAST_SIZES.with(|s| s.borrow().get(&id).cloned()));
+    let ((auto_width, width), (auto_height, height)) =
+        layout_box.property.contain_intrinsic_size();
+    match last_size {
+        Some((last_width, last_height)) => (
+            if auto_width { last_width } else { Au::from_f64_px(width) },
+            if auto_height { last_height } else { Au::from_f64_px(height) },
+        ),
+        None => (Au::from_f64_px(width), Au::from_f64_px(height)),
+    }
+}
+
+/// Keeps where the ``content-visibility: auto`` boxes of the laid out tree ``root`` are, and the
+/// sizes of those whose contents were laid out. Returns whether it has to be laid out again: the
+/// contents of a box near the part of the document shown were skipped, or those of a box far
+/// from it were not.
+pub fn update(root: &LayoutBox) -> bool {
+    fn update_box(layout_box: &LayoutBox, x: Au, y: Au, changed: &mut bool) {
+        if let (ContentVisibility::Auto, Some(id)) =
+            (layout_box.property.content_visibility(), layout_box.node_id)
+        {
+            let border_box = layout_box.dimensions.border_box().add_parent_coordinate(x, y);
+            let skipped = AUTO_BOXES.with(|b| {
+                let mut boxes = b.borrow_mut();
+                let auto_box = boxes.entry(id).or_insert((None, false));
+                auto_box.0 = Some(border_box);
+                auto_box.1
+            });
+            *changed |= skipped == is_near(Some(border_box));
+            if !skipped {
+                let content = layout_box.dimensions.content;
+                LAST_SIZES.with(|s| s.borrow_mut().insert(id, (content.width, content.height)));
+            }
+        }
+        let (child_x, child_y) = overflow::child_origin(layout_box, x, y);
+        for child in &layout_box.children {
+            update_box(child, child_x, child_y, changed);
+        }
+    }
+
+    if AUTO_BOXES.with(|b| b.borrow().is_empty()) {
+        return false;
+    }
+    let mut changed = false;
+    update_box(root, Au(0), Au(0), &mut changed);
+    changed
+}
+
+/// Whether a box with ``border_box`` (None if it is not known) is near the part of the document
+/// shown: less than the width and the height of the part shown away from it.
+pub fn is_near(border_box: Option<Rect>) -> bool {
+    let area = match VISIBLE_AREA.with(|a| a.get()) {
+        Some(area) => area,
+        None => return true,
+    };
+    let border_box = match border_box {
+        Some(border_box) => border_box,
+        None => return false,
+    };
+    // Touching is near, for empty boxes.
+    border_box.x <= area.x + area.width * 2
+        && border_box.x + border_box.width >= area.x - area.width
+        && border_box.y <= area.y + area.height * 2
+        && border_box.y + border_box.height >= area.y - area.height
+}
+
+#[test]
+fn test_content_visibility() {
+    use layout;
+
+    let px = Au::from_f64_px;
+    let lay_out = || {
+        layout::lay_out_html(
+            "<div><section><p></p></section><section><p></p></section><section><p></p></section>\
+             <section><p></p></section><section><p></p></section><footer><p></p></footer></div>",
+            "section { display: block; content-visibility: auto; \
+                       contain-intrinsic-size: auto 10px; }
+             p { height: 50px; }
+             footer { display: block; content-visibility: hidden; contain-intrinsic-size: 20px; }",
+            100.0,
+            50.0,
+        )
+    };
+    let heights = |root: &LayoutBox| -> Vec<f64> {
+        root.children
+            .iter()
+            .map(|child| child.dimensions.content.height.to_f64_px())
+            .collect()
+    };
+
+    // Without a window, nothing is skipped for ``auto``.
+    let root = lay_out();
+    assert_eq!(heights(&root), vec![50.0, 50.0, 50.0, 50.0, 50.0, 20.0]);
+    assert!(root.children[5].children.is_empty());
+
+    // Those near the top of the document are laid out, and the others are not. Those laid out
+    // first (before where they are was known) keep the size they had.
+    let top = Rect {
+        x: Au(0),
+        y: Au(0),
+        width: px(100.0),
+        height: px(50.0),
+    };
+    AUTO_BOXES.with(|b| b.borrow_mut().clear());
+    LAST_SIZES.with(|s| s.borrow_mut().clear());
+    set_visible_area(Some(top));
+    let root = lay_out();
+    assert_eq!(heights(&root), vec![50.0, 50.0, 50.0, 50.0, 50.0, 20.0]);
+    assert!(!root.children[2].children.is_empty());
+    assert!(root.children[3].children.is_empty());
+    assert!(!set_visible_area(Some(top)));
+
+    // Scrolled down, the ones left are skipped.
+    let bottom = Rect { y: px(200.0), ..top };
+    assert!(set_visible_area(Some(bottom)));
+    let root = lay_out();
+    assert_eq!(heights(&root), vec![50.0, 50.0, 50.0, 50.0, 50.0, 20.0]);
+    assert!(root.children[0].children.is_empty());
+    assert!(!root.children[4].children.is_empty());
+}
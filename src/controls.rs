@@ -0,0 +1,94 @@
+//! Where the links and the form controls of the page are painted. They are registered while the
+//! display list is built (see ``painter``), for the window to hit-test and to draw them.
+
+use layout::Rect;
+use font::Font;
+use selection;
+
+use std::{cell::{Cell, RefCell}, collections::HashMap, cmp::min};
+
+#[cfg(feature = "gtk")]
+use gtk;
+
+use app_units::Au;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnkerKind {
+    URL(String),
+    URLFragment(String),
+}
+
+/// Where <input type=text> or <textarea> is painted, and what it shows.
+#[derive(Clone, Debug)]
+pub struct TextInputArea {
+    pub rect: Rect, // content area
+    pub font: Font,
+    pub line_height: Au,
+    pub value: String,
+    pub multiline: bool,
+    /// False for ``pointer-events: none``: clicks go through it.
+    pub pointer_events: bool,
+}
+
+/// Where a <select> is painted, and the labels of its options.
+#[derive(Clone, Debug)]
+pub struct SelectArea {
+    pub rect: Rect, // border box
+    pub options: Vec<String>,
+    pub selected: Option<usize>,
+    /// False for ``pointer-events: none``: clicks go through it.
+    pub pointer_events: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Caret {
+    pub id: usize,  // node id of the focused text input
+    pub pos: usize, // byte offset in the value
+}
+
+thread_local!(
+    pub static ANKERS: RefCell<HashMap<Rect, AnkerKind>> = { RefCell::new(HashMap::with_capacity(8)) };
+    // HashMap<URL Fragment(id), y coordinate of the content>
+    pub static URL_FRAGMENTS: RefCell<HashMap<String, f64>> = { RefCell::new(HashMap::with_capacity(8)) };
+    // HashMap<node id, the area of the text input>
+    pub static TEXT_INPUTS: RefCell<HashMap<usize, TextInputArea>> = { RefCell::new(HashMap::with_capacity(8)) };
+    pub static FOCUSED_TEXT_INPUT: RefCell<Option<Caret>> = { RefCell::new(None) };
+    // HashMap<node id, the area of the <select>>
+    pub static SELECTS: RefCell<HashMap<usize, SelectArea>> = { RefCell::new(HashMap::with_capacity(8)) };
+    // The node id of the focused <select>
+    pub static FOCUSED_SELECT: Cell<Option<usize>> = { Cell::new(None) };
+);
+
+#[cfg(feature = "gtk")]
+thread_local!(
+    pub static BUTTONS: RefCell<HashMap<usize, gtk::Button>> = { RefCell::new(HashMap::with_capacity(8)) };
+);
+
+impl TextInputArea {
+    /// The rectangle of the caret placed before `value[pos]`.
+    pub fn caret_rect(&self, pos: usize) -> Rect {
+        let before = &self.value[..min(pos, self.value.len())];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line = before.matches('\n').count() as i32;
+        let font = self.font.select();
+        Rect {
+            x: self.rect.x + Au::from_f64_px(font.text_width(&before[line_start..])),
+            y: self.rect.y + self.line_height * line,
+            width: Au::from_f64_px(1.0),
+            height: self.line_height,
+        }
+    }
+
+    /// The byte offset in `value` nearest to the point (x, y).
+    pub fn position_at(&self, x: f64, y: f64) -> usize {
+        let font = self.font.select();
+        let lines: Vec<&str> = self.value.split('\n').collect();
+        let line = ((y - self.rect.y.to_f64_px()) / self.line_height.to_f64_px()).max(0.0) as usize;
+        let line = min(line, lines.len() - 1);
+        let line_start = lines[..line].iter().fold(0, |acc, l| acc + l.len() + 1);
+        let text = lines[line];
+
+        let x = x - self.rect.x.to_f64_px();
+        line_start + selection::offset_at(text, x, |s| font.text_width(s))
+    }
+}
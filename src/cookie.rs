@@ -0,0 +1,348 @@
+#[cfg(feature = "network")]
+use network::HttpDate;
+use network::Url;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Persistent cookies are saved here. Session cookies are not.
+pub const COOKIE_FILE: &'static str = "cache/cookies/cookies.txt";
+
+/// ref. https://tools.ietf.org/html/rfc6265
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    /// Sent only to ``domain`` itself (no ``Domain`` attribute), not to its subdomains.
+    pub host_only: bool,
+    pub path: String,
+    /// None for session cookies.
+    pub expires: Option<SystemTime>,
+    pub secure: bool,
+}
+
+impl Cookie {
+    /// Parses the value of ``Set-Cookie`` received from ``url``.
+    /// Returns None if the cookie must be ignored.
+    pub fn parse(set_cookie: &str, url: &Url, now: SystemTime) -> Option<Cookie> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        let mut attrs = set_cookie.split(';');
+
+        let name_value = attrs.next()?;
+        let eq = name_value.find('=')?;
+        let name = name_value[..eq].trim();
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: name_value[eq + 1..].trim().to_string(),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(url),
+            expires: None,
+            secure: false,
+        };
+        let mut max_age = None;
+
+        for attr in attrs {
+            let (attr_name, attr_value) = match attr.find('=') {
+                Some(eq) => (attr[..eq].trim(), attr[eq + 1..].trim()),
+                None => (attr.trim(), ""),
+            };
+            match attr_name.to_ascii_lowercase().as_str() {
+                // Dates are parsed by reqwest.
+                #[cfg(feature = "network")]
+                "expires" => {
+                    if let Ok(date) = attr_value.parse::<HttpDate>() {
+                        cookie.expires = Some(SystemTime::from(date));
+                    }
+                }
+                "max-age" => {
+                    if let Ok(secs) = attr_value.parse::<i64>() {
+                        max_age = Some(secs);
+                    }
+                }
+                "domain" if !attr_value.is_empty() => {
+                    let domain = attr_value.trim_start_matches('.').to_ascii_lowercase();
+                    if !domain_matches(host.as_str(), domain.as_str()) {
+                        return None;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if attr_value.starts_with('/') => cookie.path = attr_value.to_string(),
+                "secure" => cookie.secure = true,
+                _ => {}
+            }
+        }
+
+        // Max-Age takes precedence over Expires.
+        if let Some(secs) = max_age {
+            cookie.expires = Some(if secs > 0 {
+                now + Duration::from_secs(secs as u64)
+            } else {
+                UNIX_EPOCH
+            });
+        }
+
+        Some(cookie)
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        match self.expires {
+            Some(expires) => expires <= now,
+            None => false,
+        }
+    }
+
+    /// Returns true if this cookie has to be sent to ``url``.
+    pub fn matches(&self, url: &Url, now: SystemTime) -> bool {
+        let host = match url.host_str() {
+            Some(host) => host.to_ascii_lowercase(),
+            None => return false,
+        };
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(host.as_str(), self.domain.as_str())
+        };
+        domain_ok && path_matches(url.path(), self.path.as_str())
+            && (!self.secure || url.scheme() == "https") && !self.is_expired(now)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CookieJar {
+    pub cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    pub fn new() -> CookieJar {
+        CookieJar { cookies: vec![] }
+    }
+
+    /// Stores ``cookie``, replacing the one with the same name, domain and path.
+    /// An expired cookie just removes the old one.
+    pub fn set(&mut self, cookie: Cookie, now: SystemTime) {
+        self.cookies.retain(|c| {
+            !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path)
+        });
+        if !cookie.is_expired(now) {
+            self.cookies.push(cookie);
+        }
+    }
+
+    /// The value of ``Cookie`` header sent to ``url``.
+    pub fn cookie_header(&self, url: &Url, now: SystemTime) -> Option<String> {
+        let mut cookies: Vec<&Cookie> = self.cookies
+            .iter()
+            .filter(|c| c.matches(url, now))
+            .collect();
+        if cookies.is_empty() {
+            return None;
+        }
+        // Cookies with longer paths are listed first.
+        cookies.sort_by(|a, b| b.path.len().cmp(&a.path.len()));
+        Some(
+            cookies
+                .iter()
+                .map(|c| format!("{}={}", c.name, c.value))
+                .collect::<Vec<String>>()
+                .join("; "),
+        )
+    }
+
+    /// One persistent cookie per line:
+    /// ``domain host_only path secure expires(seconds since the epoch) name value`` separated
+    /// by tabs.
+    pub fn to_file_content(&self, now: SystemTime) -> String {
+        self.cookies
+            .iter()
+            .filter(|c| !c.is_expired(now))
+            .filter_map(|c| {
+                let expires = c.expires?.duration_since(UNIX_EPOCH).ok()?.as_secs();
+                Some(format!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                    c.domain, c.host_only, c.path, c.secure, expires, c.name, c.value
+                ))
+            })
+            .collect()
+    }
+
+    pub fn from_file_content(content: &str) -> CookieJar {
+        let cookies = content
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.splitn(7, '\t').collect();
+                if fields.len() != 7 {
+                    return None;
+                }
+                Some(Cookie {
+                    domain: fields[0].to_string(),
+                    host_only: fields[1] == "true",
+                    path: fields[2].to_string(),
+                    secure: fields[3] == "true",
+                    expires: Some(UNIX_EPOCH + Duration::from_secs(fields[4].parse().ok()?)),
+                    name: fields[5].to_string(),
+                    value: fields[6].to_string(),
+                })
+            })
+            .collect();
+        CookieJar { cookies: cookies }
+    }
+}
+
+/// ref. https://tools.ietf.org/html/rfc6265#section-5.1.3
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain || (host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.'))
+}
+
+/// ref. https://tools.ietf.org/html/rfc6265#section-5.1.4
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
+/// The directory of the request path. ref. https://tools.ietf.org/html/rfc6265#section-5.1.4
+fn default_path(url: &Url) -> String {
+    let path = url.path();
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(i) => path[..i].to_string(),
+    }
+}
+
+// Shared by the worker threads loading subresources. None until loaded from COOKIE_FILE.
+static COOKIE_JAR: Mutex<Option<CookieJar>> = Mutex::new(None);
+
+fn with_jar<F, T>(f: F) -> T
+where
+    F: FnOnce(&mut CookieJar) -> T,
+{
+    let mut jar = COOKIE_JAR.lock().unwrap();
+    f(jar.get_or_insert_with(|| {
+        let mut content = "".to_string();
+        match fs::File::open(COOKIE_FILE).and_then(|mut file| file.read_to_string(&mut content)) {
+            Ok(_) => CookieJar::from_file_content(content.as_str()),
+            Err(_) => CookieJar::new(),
+        }
+    }))
+}
+
+/// The value of ``Cookie`` header sent to ``url``.
+pub fn cookie_header(url: &Url) -> Option<String> {
+    with_jar(|jar| jar.cookie_header(url, SystemTime::now()))
+}
+
+/// Stores the cookies given by ``Set-Cookie`` headers in the response from ``url``.
+pub fn store_set_cookies(url: &Url, set_cookies: &[String]) {
+    if set_cookies.is_empty() {
+        return;
+    }
+
+    let now = SystemTime::now();
+    with_jar(|jar| {
+        for set_cookie in set_cookies {
+            if let Some(cookie) = Cookie::parse(set_cookie.as_str(), url, now) {
+                jar.set(cookie, now);
+            }
+        }
+
+        // Saving cookies is best effort.
+        fs::create_dir_all("cache/cookies")
+            .and_then(|_| fs::File::create(COOKIE_FILE))
+            .and_then(|mut file| file.write_all(jar.to_file_content(now).as_bytes()))
+            .ok();
+    });
+}
+
+#[test]
+fn test_parse() {
+    let now = UNIX_EPOCH + Duration::from_secs(1500000000);
+    let url = Url::parse("https://www.example.com/a/b.html").unwrap();
+
+    assert_eq!(
+        Cookie::parse("id=a3fWa; Max-Age=60; Secure; HttpOnly", &url, now),
+        Some(Cookie {
+            name: "id".to_string(),
+            value: "a3fWa".to_string(),
+            domain: "www.example.com".to_string(),
+            host_only: true,
+            path: "/a".to_string(),
+            expires: Some(now + Duration::from_secs(60)),
+            secure: true,
+        })
+    );
+
+    let cookie = Cookie::parse(
+        "lang=ja; Domain=.example.com; Path=/; Expires=Wed, 21 Oct 2015 07:28:00 GMT",
+        &url,
+        now,
+    ).unwrap();
+    assert_eq!(cookie.domain, "example.com");
+    assert!(!cookie.host_only);
+    assert_eq!(cookie.path, "/");
+    #[cfg(feature = "network")]
+    assert_eq!(
+        cookie.expires,
+        Some(UNIX_EPOCH + Duration::from_secs(1445412480))
+    );
+
+    // Cookies for other domains are rejected.
+    assert_eq!(Cookie::parse("a=b; Domain=example.org", &url, now), None);
+    assert_eq!(Cookie::parse("a=b; Domain=ample.com", &url, now), None);
+    assert_eq!(Cookie::parse("=b", &url, now), None);
+}
+
+#[test]
+fn test_cookie_jar() {
+    let now = UNIX_EPOCH + Duration::from_secs(1500000000);
+    let url = Url::parse("https://www.example.com/a/b.html").unwrap();
+    let mut jar = CookieJar::new();
+    for set_cookie in &[
+        "session=1",
+        "id=2; Path=/a; Max-Age=60",
+        "lang=ja; Domain=example.com; Path=/; Max-Age=60",
+        "secret=3; Secure; Max-Age=60",
+        "other=4; Path=/ab",
+    ] {
+        jar.set(Cookie::parse(set_cookie, &url, now).unwrap(), now);
+    }
+
+    assert_eq!(
+        jar.cookie_header(&url, now),
+        Some("session=1; id=2; secret=3; lang=ja".to_string())
+    );
+    assert_eq!(
+        jar.cookie_header(&Url::parse("http://sub.example.com/a/b").unwrap(), now),
+        Some("lang=ja".to_string())
+    );
+    assert_eq!(
+        jar.cookie_header(&Url::parse("http://example.org/").unwrap(), now),
+        None
+    );
+
+    // Overwritten, then deleted by an expired cookie.
+    jar.set(Cookie::parse("id=5; Path=/a", &url, now).unwrap(), now);
+    assert_eq!(jar.cookies.iter().filter(|c| c.name == "id").count(), 1);
+    jar.set(Cookie::parse("id=; Path=/a; Max-Age=0", &url, now).unwrap(), now);
+    assert!(jar.cookies.iter().all(|c| c.name != "id"));
+
+    // Session cookies are not saved.
+    let saved = CookieJar::from_file_content(jar.to_file_content(now).as_str());
+    assert_eq!(
+        saved
+            .cookies
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<&str>>(),
+        vec!["lang", "secret"]
+    );
+    assert_eq!(saved.cookie_header(&url, now), Some("secret=3; lang=ja".to_string()));
+}
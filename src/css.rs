@@ -1,16 +1,48 @@
 use std::{fmt, collections::HashSet};
 
 use html::remove_comments;
+use media::{self, MediaQuery};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Stylesheet {
     pub rules: Vec<Rule>,
+    /// URLs given by ``@import``, in order of appearance (not resolved).
+    pub imports: Vec<String>,
+    /// The ``@page`` rules, in order of appearance
+    pub pages: Vec<PageRule>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Rule {
     pub selectors: Vec<Selector>,
     pub declarations: Vec<Declaration>,
+    /// The queries of the ``@media`` rules it is in, from the outermost. It applies while one of
+    /// the queries of each of them matches (see ``Rule::applies``).
+    pub media: Vec<Vec<MediaQuery>>,
+}
+
+impl Rule {
+    /// Whether the queries of its ``@media`` rules match now
+    pub fn applies(&self) -> bool {
+        self.media.iter().all(|queries| media::matches(queries))
+    }
+}
+
+/// An ``@page`` rule, e.g. ``@page :first { margin-top: 2in }`` (see ``paged``)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageRule {
+    /// The page selector in lowercase, e.g. ``:first``, or empty for all pages
+    pub selector: String,
+    pub declarations: Vec<Declaration>,
+    /// The queries of the ``@media`` rules it is in, like those of ``Rule``
+    pub media: Vec<Vec<MediaQuery>>,
+}
+
+impl PageRule {
+    /// Whether the queries of its ``@media`` rules match now
+    pub fn applies(&self) -> bool {
+        self.media.iter().all(|queries| media::matches(queries))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -25,6 +57,9 @@ pub struct SimpleSelector {
     pub tag_name: Option<String>,
     pub id: Option<String>,
     pub class: HashSet<String>,
+    /// e.g. ``before`` of ``p::before``, which matches the pseudo-element instead of the
+    /// element. Only the last simple selector of a selector has one.
+    pub pseudo_element: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -39,14 +74,62 @@ pub enum Value {
     Length(f64, Unit),
     Num(f64),
     Color(Color),
+    Str(String),
+    /// ``url(...)``, e.g. of ``background-image``
+    Url(String),
+    /// A function of ``FUNCTIONS`` and its arguments (without the commas), e.g.
+    /// ``circle(50% at 0 0)`` of ``shape-outside``, ``blur(2px)`` of ``filter`` or
+    /// ``counter(item)`` of ``content``
+    Function(String, Vec<Value>),
+    /// ``[a b]``, the names of a line between the tracks of ``grid-template-columns`` or
+    /// ``grid-template-rows``
+    LineNames(Vec<String>),
+    /// A ``,`` between the items of a list of ``SEPARATED``
+    Comma,
+    /// A ``/`` between the parts of a shorthand of ``SEPARATED``, e.g. the slice and the width
+    /// of ``border-image``
+    Slash,
 }
 
+/// The properties whose values are split by commas or by slashes (with spaces around them),
+/// which are kept as ``Value::Comma`` and ``Value::Slash``. The separators of the other
+/// properties are skipped.
+pub const SEPARATED: [&'static str; 7] = [
+    "text-shadow",
+    "border-image",
+    "aspect-ratio",
+    "font-family",
+    "grid-row",
+    "grid-column",
+    "grid-area",
+];
+
+/// The functions whose arguments are parsed. Other functions (but ``rgb``, ``rgba`` and ``url``)
+/// are parsed as a keyword of their name.
+pub const FUNCTIONS: [&'static str; 13] = [
+    "circle",
+    "ellipse",
+    "inset",
+    "polygon",
+    "blur",
+    "brightness",
+    "drop-shadow",
+    "grayscale",
+    "counter",
+    "counters",
+    "attr",
+    "repeat",
+    "minmax",
+];
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Unit {
     Px,
     Pt,
     Percent,
     Em,
+    /// A share of the space left in a grid container
+    Fr,
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -189,12 +272,20 @@ pub fn pt2px(f: f64) -> f64 {
 pub type Specificity = (usize, usize, usize);
 
 impl Selector {
+    /// The pseudo-element the selector matches, if any (of its last simple selector)
+    pub fn pseudo_element(&self) -> Option<&str> {
+        match *self {
+            Selector::Simple(ref simple) => simple.pseudo_element.as_deref(),
+            Selector::Descendant(_, ref b) | Selector::Child(_, ref b) => b.pseudo_element(),
+        }
+    }
+
     // ref: http://www.w3.org/TR/selectors/#specificity
     pub fn specificity(&self) -> Specificity {
         fn specificity_simple(simple: &SimpleSelector) -> Specificity {
             let a = simple.id.iter().count();
             let b = simple.class.len();
-            let c = simple.tag_name.iter().count();
+            let c = simple.tag_name.iter().count() + simple.pseudo_element.iter().count();
             (a, b, c)
         }
 
@@ -215,8 +306,12 @@ impl Selector {
 }
 
 pub fn parse(source: String) -> Stylesheet {
+    let mut parser = Parser::new(source);
+    let rules = parser.parse_rules();
     Stylesheet {
-        rules: Parser::new(source).parse_rules(),
+        rules: rules,
+        imports: parser.imports,
+        pages: parser.pages,
     }
 }
 
@@ -224,13 +319,17 @@ pub fn parse_attr_style(source: String) -> Vec<Declaration> {
     let mut decls = Vec::new();
     let mut parser = Parser::new(source);
     loop {
-        parser.consume_whitespace().unwrap();
+        parser.consume_whitespace().ok();
         if parser.eof() {
             break;
         }
         match parser.parse_declaration() {
             Ok(ok) => decls.push(ok),
-            Err(_) => {}
+            Err(_) => {
+                parser.skip_declaration();
+                // Not in a block
+                parser.skip_char_if_any('}').ok();
+            }
         }
     }
     decls
@@ -243,6 +342,50 @@ pub fn parse_value(source: String) -> Value {
     }
 }
 
+/// Parses the list of selectors ``source``.
+fn parse_selector_list(source: &str) -> Vec<Selector> {
+    Parser::new(format!("{}{{", source))
+        .parse_selectors()
+        .unwrap_or_default()
+}
+
+/// Splits the list of selectors ``source`` at its commas (not those in parentheses).
+fn split_selector_list(source: &str) -> Vec<&str> {
+    let mut selectors = vec![];
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in source.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                selectors.push(source[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    selectors.push(source[start..].trim());
+    selectors.retain(|selector| !selector.is_empty());
+    selectors
+}
+
+/// The list of selectors of a rule written ``nested`` in the rule of ``parent``: ``&`` is each
+/// selector of the parent, and a selector without it is relative to them (in them, or after
+/// them by the combinator it starts with, e.g. ``> p``).
+fn nest_selectors(parent: &str, nested: &str) -> String {
+    let mut selectors = vec![];
+    for nested in split_selector_list(nested) {
+        for parent in split_selector_list(parent) {
+            selectors.push(if nested.contains('&') {
+                nested.replace('&', parent)
+            } else {
+                format!("{} {}", parent, nested)
+            });
+        }
+    }
+    selectors.join(", ")
+}
+
 fn valid_ident_char(c: char) -> bool {
     // TODO: other char codes?
     c.is_alphanumeric() || c == '-' || c == '_'
@@ -260,24 +403,24 @@ fn valid_alpha_percent_char(c: char) -> bool {
 
 fn valid_hex_char(c: char) -> bool {
     // TODO: other char codes?
-    match c.to_ascii_lowercase() {
-        'a' | 'b' | 'c' | 'd' | 'e' | 'f' => true,
-        c if c.is_numeric() => true,
-        _ => false,
-    }
+    c.is_ascii_hexdigit()
 }
 
 #[derive(Clone, Debug)]
 struct Parser {
     pos: usize,
     input: String,
+    imports: Vec<String>,
+    pages: Vec<PageRule>,
 }
 
 impl Parser {
     fn new(input: String) -> Parser {
         Parser {
             pos: 0,
-            input: remove_comments(input.as_bytes(), "/*", "*/"),
+            input: remove_comments(input.as_str(), "/*", "*/"),
+            imports: vec![],
+            pages: vec![],
         }
     }
 
@@ -290,51 +433,112 @@ impl Parser {
                 break;
             }
 
-            if self.next_char().unwrap() == '@' {
-                // TODO: Ignore all at-mark rules. Implement correctly ASAP!
-                assert_eq!(self.consume_char().unwrap(), '@');
-                let ident = self.parse_identifier().unwrap();
-                if ident == "charset" || ident == "import" {
-                    self.consume_while(|c| c != ';').unwrap();
-                    assert_eq!(self.consume_char().unwrap(), ';');
-                } else if ident == "font-face" || ident == "-ms-viewport" {
-                    self.consume_while(|c| c != '{').unwrap();
-                    assert_eq!(self.consume_char().unwrap(), '{');
-                    loop {
-                        self.consume_whitespace().unwrap();
-                        if self.next_char().unwrap() == '}' {
-                            self.consume_char().unwrap();
-                            break;
-                        }
-                        self.parse_rule().unwrap();
-                    }
-                } else {
-                    // @support, @media...
-                    self.consume_while(|c| c != '{').unwrap();
-                    assert_eq!(self.consume_char().unwrap(), '{');
-                    loop {
-                        self.consume_whitespace().unwrap();
-                        if self.next_char().unwrap() == '}' {
-                            self.consume_char().unwrap();
-                            break;
-                        }
-                        self.parse_rule().unwrap();
-                    }
+            if self.starts_with("@") {
+                // An at-rule not closed lasts until the end.
+                match self.parse_at_rule() {
+                    Ok(ok) => rules.extend(ok),
+                    Err(()) => break,
                 }
             } else {
                 if let Ok(ok) = self.parse_rule() {
-                    rules.push(ok)
+                    rules.extend(ok)
                 }
             }
         }
         rules
     }
 
-    fn parse_rule(&mut self) -> Result<Rule, ()> {
-        Ok(Rule {
-            selectors: self.parse_selectors()?,
-            declarations: self.parse_declarations()?,
-        })
+    /// Parses an at-rule, and returns the rules in it that apply (those of ``@media``). The other
+    /// at-rules but ``@import`` and ``@page`` are ignored.
+    fn parse_at_rule(&mut self) -> Result<Vec<Rule>, ()> {
+        self.expect_char('@')?;
+        let ident = self.parse_identifier()?;
+        if ident == "import" {
+            self.consume_whitespace()?;
+            if let Ok(url) = self.parse_import_url() {
+                self.imports.push(url);
+            }
+            // Media queries are ignored.
+            self.consume_while(|c| c != ';')?;
+            self.expect_char(';')?;
+            Ok(vec![])
+        } else if ident == "charset" {
+            self.consume_while(|c| c != ';')?;
+            self.expect_char(';')?;
+            Ok(vec![])
+        } else if ident == "page" {
+            let selector = self.consume_while(|c| c != '{')?.trim().to_lowercase();
+            let declarations = self.parse_page_block()?;
+            self.pages.push(PageRule {
+                selector: selector,
+                declarations: declarations,
+                media: vec![],
+            });
+            Ok(vec![])
+        } else {
+            // @font-face, @support...
+            let prelude = self.consume_while(|c| c != '{')?;
+            self.expect_char('{')?;
+            let first_page = self.pages.len();
+            let mut rules = vec![];
+            loop {
+                self.consume_whitespace()?;
+                match self.next_char()? {
+                    '}' => {
+                        self.consume_char()?;
+                        break;
+                    }
+                    '@' => rules.extend(self.parse_at_rule()?),
+                    _ => rules.extend(self.parse_rule().unwrap_or_default()),
+                }
+            }
+            if ident != "media" {
+                self.pages.truncate(first_page);
+                return Ok(vec![]);
+            }
+            let queries = media::parse_query_list(prelude.as_str());
+            for rule in &mut rules {
+                rule.media.insert(0, queries.clone());
+            }
+            for page in &mut self.pages[first_page..] {
+                page.media.insert(0, queries.clone());
+            }
+            Ok(rules)
+        }
+    }
+
+    /// Parses the declarations of an ``@page`` rule. Its margin rules (e.g. ``@top-center``) are
+    /// ignored.
+    fn parse_page_block(&mut self) -> Result<Vec<Declaration>, ()> {
+        self.expect_char('{')?;
+        let mut declarations = vec![];
+        loop {
+            self.consume_whitespace()?;
+            match self.next_char()? {
+                '}' => {
+                    self.consume_char()?;
+                    break;
+                }
+                '@' => {
+                    self.consume_while(|c| c != '{')?;
+                    self.consume_while(|c| c != '}')?;
+                    self.expect_char('}')?;
+                }
+                _ => match self.parse_declaration() {
+                    Ok(declaration) => declarations.push(declaration),
+                    Err(()) => self.skip_declaration(),
+                },
+            }
+        }
+        Ok(declarations)
+    }
+
+    /// Parses a rule, and the rules nested in it after it.
+    fn parse_rule(&mut self) -> Result<Vec<Rule>, ()> {
+        let start = self.pos;
+        let selectors = self.parse_selectors()?;
+        let source = self.input[start..self.pos].trim().to_string();
+        self.parse_block(selectors, source.as_str())
     }
 
     fn parse_selectors(&mut self) -> Result<Vec<Selector>, ()> {
@@ -372,13 +576,13 @@ impl Parser {
                 return Ok(Selector::Descendant(s1, Box::new(s2)));
             }
             '>' => {
-                assert_eq!(self.consume_char()?, '>');
+                self.expect_char('>')?;
                 self.consume_whitespace()?;
                 let s2 = self.parse_selector()?;
                 return Ok(Selector::Child(s1, Box::new(s2)));
             }
             '+' => {
-                assert_eq!(self.consume_char()?, '+');
+                self.expect_char('+')?;
                 self.consume_whitespace()?;
                 let s2 = self.parse_selector()?;
                 // TODO: Adjacent sibling selector is treated as Descendant selector for now.
@@ -395,6 +599,7 @@ impl Parser {
             tag_name: None,
             id: None,
             class: HashSet::new(),
+            pseudo_element: None,
         };
         while !self.eof() {
             match self.next_char()? {
@@ -411,7 +616,9 @@ impl Parser {
                     self.consume_char()?;
                 }
                 ':' => {
-                    self.parse_pseudo_class_or_element()?;
+                    if let Some(pseudo_element) = self.parse_pseudo_class_or_element()? {
+                        selector.pseudo_element = Some(pseudo_element);
+                    }
                 }
                 '[' => {
                     unsupported_feature = self.parse_attribute().is_err();
@@ -429,61 +636,124 @@ impl Parser {
         }
     }
 
-    // TODO: Implement correctly
-    fn parse_pseudo_class_or_element(&mut self) -> Result<(), ()> {
-        assert_eq!(self.skip_char_if_any(':')?, true); // pseudo-class
-        self.skip_char_if_any(':')?; //pseudo-element
+    /// Returns the name of a pseudo-element (``::before``, or ``:before`` written like a
+    /// pseudo-class as in CSS 2). Pseudo-classes are ignored for now.
+    // TODO: Implement pseudo-classes
+    fn parse_pseudo_class_or_element(&mut self) -> Result<Option<String>, ()> {
+        self.expect_char(':')?; // pseudo-class
+        let element = self.skip_char_if_any(':')?; //pseudo-element
         self.consume_whitespace()?;
-        self.parse_identifier()?;
+        let name = self.parse_identifier()?;
         self.consume_whitespace()?;
         if self.skip_char_if_any('(')? {
             self.consume_while(|c| c != ')')?;
-            assert_eq!(self.consume_char()?, ')');
+            self.expect_char(')')?;
         }
-        Ok(())
+        let legacy = ["before", "after", "first-line", "first-letter"];
+        Ok(if element || legacy.contains(&name.as_str()) {
+            Some(name)
+        } else {
+            None
+        })
     }
 
     // TODO: Implement correctly
     fn parse_attribute(&mut self) -> Result<(), ()> {
         if self.skip_char_if_any('[')? {
             self.consume_while(|c| c != ']')?;
-            assert_eq!(self.consume_char()?, ']');
+            self.expect_char(']')?;
         }
         // TODO: Just returns Err(()) to ignore this selector for now
         Err(())
     }
 
-    fn parse_declarations(&mut self) -> Result<Vec<Declaration>, ()> {
-        assert_eq!(self.consume_char()?, '{');
+    /// Parses the block of the rule of ``selectors`` (written ``source``): the rule, and the rules
+    /// nested in it (see ``nest_selectors``) flattened after it.
+    /// ref. https://drafts.csswg.org/css-nesting/
+    fn parse_block(&mut self, selectors: Vec<Selector>, source: &str) -> Result<Vec<Rule>, ()> {
+        self.expect_char('{')?;
         let mut declarations = Vec::new();
+        let mut nested_rules = vec![];
         loop {
             self.consume_whitespace()?;
-            if self.next_char()? == '}' {
-                self.consume_char()?;
-                break;
+            match self.next_char()? {
+                '}' => {
+                    self.consume_char()?;
+                    break;
+                }
+                '@' => {
+                    self.consume_char()?;
+                    let ident = self.parse_identifier()?;
+                    let prelude = self.consume_while(|c| c != '{' && c != ';')?;
+                    if !self.skip_char_if_any(';')? {
+                        // Like at the top level, only the rules in ``@media`` apply.
+                        let mut rules = self.parse_block(selectors.clone(), source)?;
+                        if ident == "media" {
+                            let queries = media::parse_query_list(prelude.as_str());
+                            for rule in &mut rules {
+                                rule.media.insert(0, queries.clone());
+                            }
+                            nested_rules.extend(rules);
+                        }
+                    }
+                }
+                _ if self.starts_nested_rule() => {
+                    let start = self.pos;
+                    self.consume_while(|c| c != '{')?;
+                    let nested = nest_selectors(source, &self.input[start..self.pos]);
+                    let nested_selectors = parse_selector_list(nested.as_str());
+                    nested_rules.extend(self.parse_block(nested_selectors, nested.as_str())?);
+                }
+                _ => match self.parse_declaration() {
+                    Ok(declaration) => declarations.push(declaration),
+                    Err(()) => self.skip_declaration(),
+                },
             }
-            declarations.push(self.parse_declaration()?);
         }
-        Ok(declarations)
+        let mut rules = vec![Rule {
+            selectors: selectors,
+            declarations: declarations,
+            media: vec![],
+        }];
+        rules.extend(nested_rules);
+        Ok(rules)
+    }
+
+    /// Whether a nested rule starts here rather than a declaration: a ``{`` comes before the end
+    /// of a declaration.
+    fn starts_nested_rule(&self) -> bool {
+        self.input[self.pos..]
+            .find(['{', ';', '}'])
+            .is_some_and(|end| self.input[self.pos + end..].starts_with('{'))
     }
 
+    /// Fails if the declaration is invalid, e.g. without a value.
     fn parse_declaration(&mut self) -> Result<Declaration, ()> {
         let property_name = self.parse_identifier()?;
         self.consume_whitespace()?;
-        assert_eq!(self.consume_char()?, ':');
+        self.expect_char(':')?;
         self.consume_whitespace()?;
-        let values = self.parse_values()?;
+        let values = self.parse_values(SEPARATED.contains(&property_name.as_str()))?;
         self.consume_whitespace()?;
 
+        if property_name.is_empty() || values.is_empty() {
+            return Err(());
+        }
         Ok(Declaration {
             name: property_name,
             values: values,
         })
     }
 
+    /// Skips the rest of an invalid declaration, until the ``;`` (or the ``}``) after it.
+    fn skip_declaration(&mut self) {
+        self.consume_while(|c| c != ';' && c != '}').ok();
+        self.skip_char_if_any(';').ok();
+    }
+
     // Methods for parsing values:
 
-    fn parse_values(&mut self) -> Result<Vec<Value>, ()> {
+    fn parse_values(&mut self, keep_separators: bool) -> Result<Vec<Value>, ()> {
         let mut values = vec![];
 
         if let Ok(()) = (|| -> Result<(), ()> {
@@ -496,16 +766,36 @@ impl Parser {
                     break;
                 }
 
-                if let Ok(ok) = self.parse_value() {
-                    values.push(ok)
+                if keep_separators && self.skip_char_if_any('/')? {
+                    values.push(Value::Slash);
+                    continue;
+                }
+
+                let start = self.pos;
+                match self.parse_value() {
+                    Ok(ref value) if *value == Value::Keyword("".to_string()) => {}
+                    Ok(value) => values.push(value),
+                    Err(()) => {}
+                }
+                if self.pos == start && self.next_char()? != '}' {
+                    // e.g. ")", which is not a value
+                    self.consume_char()?;
                 }
 
                 self.consume_while(|c| c == ' ' || c == '\t')?;
-                if self.skip_char_if_any('\n')? || self.next_char()? == '}' {
+                if self.skip_char_if_any('\n')? {
+                    // The strings of ``grid-template-areas`` go on in the lines after it.
+                    self.consume_whitespace()?;
+                    if self.eof() || !['"', '\''].contains(&self.next_char()?) {
+                        break;
+                    }
+                } else if self.next_char()? == '}' {
                     break;
                 }
 
-                self.skip_char_if_any(',')?;
+                if self.skip_char_if_any(',')? && keep_separators {
+                    values.push(Value::Comma);
+                }
             }
             Ok(())
         })()
@@ -520,6 +810,7 @@ impl Parser {
             '.' | '0'...'9' => self.parse_length(),
             '#' => self.parse_color(),
             '\"' | '\'' => self.parse_string(),
+            '[' => self.parse_line_names(),
             _ => {
                 self.skip_char_if_any('!')?; // TODO: Is this correct?
                 self.skip_char_if_any('\\')?; // TODO: Is this correct?
@@ -529,6 +820,9 @@ impl Parser {
                     "rgb" => self.parse_rgb_color(),
                     "rgba" => self.parse_rgba_color(),
                     "url" => self.parse_url(),
+                    _ if FUNCTIONS.contains(&ident.as_str()) && self.starts_with("(") => {
+                        self.parse_function(ident)
+                    }
                     _ if !self.eof() && self.next_char()? == '(' => {
                         // TODO: Unsupported functions are ignored.
                         let mut nest = 0;
@@ -540,7 +834,7 @@ impl Parser {
                             }
                             nest > 0
                         })?;
-                        assert_eq!(self.consume_char()?, ')');
+                        self.expect_char(')')?;
                         Ok(Value::Keyword(ident))
                     }
                     _ => Ok(Value::Keyword(ident)),
@@ -552,7 +846,8 @@ impl Parser {
     fn parse_length(&mut self) -> Result<Value, ()> {
         let num = self.parse_float()?;
         if !self.eof() && valid_alpha_percent_char(self.next_char()?) {
-            Ok(Value::Length(num, self.parse_unit()?))
+            let (unit, per_unit) = self.parse_unit()?;
+            Ok(Value::Length(num * per_unit, unit))
         } else {
             Ok(Value::Num(num))
         }
@@ -574,36 +869,71 @@ impl Parser {
         n
     }
 
+    /// Parses ``"a.css"``, ``'a.css'``, ``url(a.css)`` or ``url("a.css")``.
+    fn parse_import_url(&mut self) -> Result<String, ()> {
+        let url_function = self.starts_with("url(");
+        if url_function {
+            self.consume_while(|c| c != '(')?;
+            self.expect_char('(')?;
+            self.consume_whitespace()?;
+        }
+
+        let url = match self.next_char()? {
+            quote @ '"' | quote @ '\'' => {
+                self.consume_char()?;
+                let url = self.consume_while(|c| c != quote)?;
+                self.expect_char(quote)?;
+                url
+            }
+            _ if url_function => self.consume_while(|c| c != ')' && !c.is_whitespace())?,
+            _ => return Err(()),
+        };
+
+        if url_function {
+            self.expect_char_ignore_whitespace(')')?;
+        }
+
+        Ok(url)
+    }
+
     fn parse_string(&mut self) -> Result<Value, ()> {
         let quote = self.consume_char()?;
-        self.consume_while(|c| c != quote)?;
-        assert_eq!(self.consume_char()?, quote);
-        // TODO: Implement correctly
-        Ok(Value::Num(0.0))
+        // TODO: Escapes are not supported.
+        let s = self.consume_while(|c| c != quote)?;
+        self.expect_char(quote)?;
+        Ok(Value::Str(s))
     }
 
-    fn parse_unit(&mut self) -> Result<Unit, ()> {
+    /// Parses a unit, and how many of it one of that unit is. The absolute units (e.g. ``cm``)
+    /// are in px.
+    fn parse_unit(&mut self) -> Result<(Unit, f64), ()> {
         match &*self.parse_identifier_percent()? {
-            "px" => Ok(Unit::Px),
-            "pt" => Ok(Unit::Pt),
-            "%" => Ok(Unit::Percent),
-            "em" => Ok(Unit::Em),
+            "px" => Ok((Unit::Px, 1.0)),
+            "pt" => Ok((Unit::Pt, 1.0)),
+            "%" => Ok((Unit::Percent, 1.0)),
+            "em" => Ok((Unit::Em, 1.0)),
+            "fr" => Ok((Unit::Fr, 1.0)),
+            "in" => Ok((Unit::Px, 96.0)),
+            "cm" => Ok((Unit::Px, 96.0 / 2.54)),
+            "mm" => Ok((Unit::Px, 96.0 / 25.4)),
+            "q" => Ok((Unit::Px, 96.0 / 101.6)),
+            "pc" => Ok((Unit::Px, 16.0)),
             u => {
                 println!("unrecognized unit: {}", u);
                 // Unrecognized units are treated as Px
-                Ok(Unit::Pt)
+                Ok((Unit::Pt, 1.0))
             }
         }
     }
 
     fn parse_rgb_color(&mut self) -> Result<Value, ()> {
-        assert_eq!(self.consume_char_ignore_whitescape()?, '(');
+        self.expect_char_ignore_whitespace('(')?;
         let r = self.parse_float()?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ',');
+        self.expect_char_ignore_whitespace(',')?;
         let g = self.parse_float()?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ',');
+        self.expect_char_ignore_whitespace(',')?;
         let b = self.parse_float()?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ')');
+        self.expect_char_ignore_whitespace(')')?;
         Ok(Value::Color(Color {
             r: r as u8,
             g: g as u8,
@@ -613,15 +943,15 @@ impl Parser {
     }
 
     fn parse_rgba_color(&mut self) -> Result<Value, ()> {
-        assert_eq!(self.consume_char_ignore_whitescape()?, '(');
+        self.expect_char_ignore_whitespace('(')?;
         let r = self.parse_float()?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ',');
+        self.expect_char_ignore_whitespace(',')?;
         let g = self.parse_float()?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ',');
+        self.expect_char_ignore_whitespace(',')?;
         let b = self.parse_float()?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ',');
+        self.expect_char_ignore_whitespace(',')?;
         let a = self.parse_float()?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ')');
+        self.expect_char_ignore_whitespace(')')?;
         Ok(Value::Color(Color {
             r: r as u8,
             g: g as u8,
@@ -630,30 +960,75 @@ impl Parser {
         }))
     }
 
+    /// Parses ``(a.png)`` or ``("a.png")`` after ``url``.
     fn parse_url(&mut self) -> Result<Value, ()> {
-        // TODO: Implement correctly
-        assert_eq!(self.consume_char_ignore_whitescape()?, '(');
-        self.consume_while(|c| c != ')')?;
-        assert_eq!(self.consume_char_ignore_whitescape()?, ')');
-        Ok(Value::Num(0.0))
+        self.expect_char_ignore_whitespace('(')?;
+        let url = match self.next_char()? {
+            quote @ '"' | quote @ '\'' => {
+                self.consume_char()?;
+                let url = self.consume_while(|c| c != quote)?;
+                self.consume_char()?;
+                url
+            }
+            _ => self.consume_while(|c| c != ')' && !c.is_whitespace())?,
+        };
+        self.expect_char_ignore_whitespace(')')?;
+        Ok(Value::Url(url))
+    }
+
+    /// Parses the arguments of the function ``name``, from the ``(``.
+    fn parse_function(&mut self, name: String) -> Result<Value, ()> {
+        self.expect_char('(')?;
+        let mut args = vec![];
+        loop {
+            self.consume_whitespace()?;
+            if self.skip_char_if_any(')')? {
+                return Ok(Value::Function(name, args));
+            }
+            if self.skip_char_if_any(',')? {
+                continue;
+            }
+            let start = self.pos;
+            if let Ok(arg) = self.parse_value() {
+                args.push(arg);
+            }
+            if self.pos == start {
+                // Not a value (e.g. ``;``): the function is not closed.
+                return Err(());
+            }
+        }
+    }
+
+    /// Parses ``[a b]``.
+    fn parse_line_names(&mut self) -> Result<Value, ()> {
+        self.expect_char('[')?;
+        let mut names = vec![];
+        loop {
+            self.consume_whitespace()?;
+            if self.skip_char_if_any(']')? {
+                return Ok(Value::LineNames(names));
+            }
+            let name = self.parse_identifier()?;
+            if name.is_empty() {
+                return Err(());
+            }
+            names.push(name);
+        }
     }
 
     fn parse_color(&mut self) -> Result<Value, ()> {
-        assert_eq!(self.consume_char()?, '#');
+        self.expect_char('#')?;
         let hex_str = self.parse_hex_num()?;
+        let hex = |range: ::std::ops::Range<usize>| {
+            u8::from_str_radix(&hex_str[range], 16).map_err(|_| ())
+        };
         let (r, g, b) = match hex_str.len() {
             3 => {
-                let r = u8::from_str_radix(&hex_str[0..1], 16).unwrap();
-                let g = u8::from_str_radix(&hex_str[1..2], 16).unwrap();
-                let b = u8::from_str_radix(&hex_str[2..3], 16).unwrap();
+                let (r, g, b) = (hex(0..1)?, hex(1..2)?, hex(2..3)?);
                 (r * 16 + r, g * 16 + g, b * 16 + b)
             }
-            6 => (
-                u8::from_str_radix(&hex_str[0..2], 16).unwrap(),
-                u8::from_str_radix(&hex_str[2..4], 16).unwrap(),
-                u8::from_str_radix(&hex_str[4..6], 16).unwrap(),
-            ),
-            _ => panic!(),
+            6 => (hex(0..2)?, hex(2..4)?, hex(4..6)?),
+            _ => return Err(()),
         };
         Ok(Value::Color(Color {
             r: r,
@@ -681,11 +1056,10 @@ impl Parser {
         Ok(self.consume_while(valid_ident_percent_char)?.to_lowercase())
     }
 
-    fn consume_char_ignore_whitescape(&mut self) -> Result<char, ()> {
-        self.consume_whitespace()?;
-        let c = self.consume_char()?;
+    fn expect_char_ignore_whitespace(&mut self, c: char) -> Result<(), ()> {
         self.consume_whitespace()?;
-        Ok(c)
+        self.expect_char(c)?;
+        self.consume_whitespace()
     }
 
     fn consume_whitespace(&mut self) -> Result<(), ()> {
@@ -703,16 +1077,23 @@ impl Parser {
         Ok(s)
     }
     fn consume_char(&mut self) -> Result<char, ()> {
-        let mut iter = self.input[self.pos..].char_indices();
-        let (_, cur_char) = iter.next().ok_or(())?;
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
-        self.pos += next_pos;
+        let cur_char = self.next_char()?;
+        self.pos += cur_char.len_utf8();
         Ok(cur_char)
     }
 
+    /// Consumes ``c`` if it comes next. Otherwise, returns an error without consuming anything.
+    fn expect_char(&mut self, c: char) -> Result<(), ()> {
+        if self.skip_char_if_any(c)? {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
     fn skip_char_if_any(&mut self, c: char) -> Result<bool, ()> {
         if !self.eof() && self.next_char()? == c {
-            assert_eq!(self.consume_char()?, c);
+            self.consume_char()?;
             return Ok(true);
         }
         Ok(false)
@@ -723,10 +1104,11 @@ impl Parser {
     }
 
     fn next2_char(&self) -> Result<char, ()> {
-        let mut iter = self.input[self.pos..].char_indices();
-        iter.next().ok_or(())?;
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
-        self.input[self.pos + next_pos..].chars().next().ok_or(())
+        self.input[self.pos..].chars().nth(1).ok_or(())
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.input[self.pos..].starts_with(s)
     }
 
     fn eof(&self) -> bool {
@@ -734,79 +1116,120 @@ impl Parser {
     }
 }
 
+impl fmt::Display for SimpleSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut universal = true;
+        if let Some(ref tag_name) = self.tag_name {
+            universal = false;
+            try!(write!(f, "{}", tag_name));
+        }
+        if !self.class.is_empty() {
+            universal = false;
+            for class in &self.class {
+                try!(write!(f, ".{}", class))
+            }
+        }
+        if let Some(ref id) = self.id {
+            universal = false;
+            try!(write!(f, "#{}", id));
+        }
+        if universal {
+            try!(write!(f, "*"))
+        }
+        if let Some(ref pseudo_element) = self.pseudo_element {
+            try!(write!(f, "::{}", pseudo_element));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Selector::Simple(ref selector) => write!(f, "{}", selector),
+            &Selector::Descendant(ref a, ref b) => write!(f, "{} {}", a, b),
+            &Selector::Child(ref a, ref b) => write!(f, "{} > {}", a, b),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Value::Keyword(ref kw) => write!(f, "{}", kw),
+            &Value::Length(ref l, Unit::Px) => write!(f, "{}px", l),
+            &Value::Length(ref l, Unit::Pt) => write!(f, "{}pt", l),
+            &Value::Length(ref l, Unit::Percent) => write!(f, "{}%", l),
+            &Value::Length(ref l, Unit::Em) => write!(f, "{}em", l),
+            &Value::Length(ref l, Unit::Fr) => write!(f, "{}fr", l),
+            &Value::Num(ref n) => write!(f, "{}", n),
+            &Value::Str(ref s) => write!(f, "\"{}\"", s),
+            &Value::Url(ref url) => write!(f, "url(\"{}\")", url),
+            &Value::Function(ref name, ref args) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}({})", name, args.join(" "))
+            }
+            &Value::Color(ref color) => {
+                write!(f, "rgba({}, {}, {}, {})", color.r, color.g, color.b, color.a)
+            }
+            &Value::LineNames(ref names) => write!(f, "[{}]", names.join(" ")),
+            &Value::Comma => write!(f, ","),
+            &Value::Slash => write!(f, "/"),
+        }
+    }
+}
+
+/// ``name: value ...;``
+impl fmt::Display for Declaration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}:", self.name));
+        for value in &self.values {
+            match *value {
+                Value::Comma => try!(write!(f, "{}", value)),
+                _ => try!(write!(f, " {}", value)),
+            }
+        }
+        write!(f, ";")
+    }
+}
+
 impl fmt::Display for Stylesheet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for rule in &self.rules {
+            for queries in &rule.media {
+                let queries: Vec<String> = queries.iter().map(|q| q.to_string()).collect();
+                try!(write!(f, "@media {} {{ ", queries.join(", ")));
+            }
             for (i, selector) in rule.selectors.iter().enumerate() {
-                fn show(f: &mut fmt::Formatter, selector: &Selector) -> fmt::Result {
-                    fn show_simple(
-                        f: &mut fmt::Formatter,
-                        selector: &SimpleSelector,
-                    ) -> fmt::Result {
-                        let mut universal = true;
-                        if let Some(ref tag_name) = selector.tag_name {
-                            universal = false;
-                            try!(write!(f, "{}", tag_name));
-                        }
-                        if !selector.class.is_empty() {
-                            universal = false;
-                            for class in &selector.class {
-                                try!(write!(f, ".{}", class))
-                            }
-                        }
-                        if let Some(ref id) = selector.id {
-                            universal = false;
-                            try!(write!(f, "#{}", id));
-                        }
-                        if universal {
-                            try!(write!(f, "*"))
-                        }
-                        Ok(())
-                    }
-
-                    match selector {
-                        &Selector::Simple(ref selector) => show_simple(f, selector),
-                        &Selector::Descendant(ref a, ref b) => {
-                            try!(show_simple(f, &*a));
-                            try!(write!(f, " "));
-                            show(f, &*b)
-                        }
-                        &Selector::Child(ref a, ref b) => {
-                            try!(show_simple(f, &*a));
-                            try!(write!(f, " > "));
-                            show(f, &*b)
-                        }
-                    }
-                }
-                try!(show(f, &selector));
-
+                try!(write!(f, "{}", selector));
                 if i != rule.selectors.len() - 1 {
                     try!(write!(f, ", "));
                 }
             }
             try!(writeln!(f, " {{"));
             for decl in &rule.declarations {
-                try!(write!(f, "  {}:", decl.name,));
-                for value in &decl.values {
-                    try!(write!(
-                        f,
-                        " {}",
-                        match value {
-                            &Value::Keyword(ref kw) => kw.clone(),
-                            &Value::Length(ref f, Unit::Px) => format!("{}px", f),
-                            &Value::Length(ref f, Unit::Pt) => format!("{}pt", f),
-                            &Value::Length(ref f, Unit::Percent) => format!("{}%", f),
-                            &Value::Length(ref f, Unit::Em) => format!("{}em", f),
-                            &Value::Num(ref f) => format!("{}", f),
-                            &Value::Color(ref color) => {
-                                format!("rgba({}, {}, {}, {})", color.r, color.g, color.b, color.a)
-                            }
-                        }
-                    ))
-                }
-                try!(writeln!(f));
+                try!(writeln!(f, "  {}", decl));
+            }
+            try!(write!(f, "}}"));
+            for _ in &rule.media {
+                try!(write!(f, " }}"));
             }
-            try!(writeln!(f, "}}"));
+            try!(writeln!(f));
+        }
+        for page in &self.pages {
+            for queries in &page.media {
+                let queries: Vec<String> = queries.iter().map(|q| q.to_string()).collect();
+                try!(write!(f, "@media {} {{ ", queries.join(", ")));
+            }
+            try!(writeln!(f, "@page {} {{", page.selector));
+            for decl in &page.declarations {
+                try!(writeln!(f, "  {}", decl));
+            }
+            try!(write!(f, "}}"));
+            for _ in &page.media {
+                try!(write!(f, " }}"));
+            }
+            try!(writeln!(f));
         }
         Ok(())
     }
@@ -836,6 +1259,7 @@ fn test1() {
                     tag_name: None,
                     id: Some("id".to_string()),
                     class: HashSet::new(),
+                    pseudo_element: None,
                 }),
                 Selector::Simple(SimpleSelector {
                     tag_name: None,
@@ -845,17 +1269,20 @@ fn test1() {
                         h.insert("class".to_string());
                         h
                     },
+                pseudo_element: None,
                 }),
                 Selector::Child(
                     SimpleSelector {
                         tag_name: Some("p".to_string()),
                         id: None,
                         class: HashSet::new(),
+                        pseudo_element: None,
                     },
                     Box::new(Selector::Simple(SimpleSelector {
                         tag_name: Some("a".to_string()),
                         id: None,
                         class: HashSet::new(),
+                        pseudo_element: None,
                     })),
                 ),
                 Selector::Descendant(
@@ -863,27 +1290,32 @@ fn test1() {
                         tag_name: Some("div".to_string()),
                         id: None,
                         class: HashSet::new(),
+                        pseudo_element: None,
                     },
                     Box::new(Selector::Simple(SimpleSelector {
                         tag_name: Some("p".to_string()),
                         id: None,
                         class: HashSet::new(),
+                        pseudo_element: None,
                     })),
                 ),
                 Selector::Simple(SimpleSelector {
                     tag_name: Some("div".to_string()),
                     id: None,
                     class: HashSet::new(),
+                    pseudo_element: None,
                 }),
                 Selector::Simple(SimpleSelector {
                     tag_name: Some("h1".to_string()),
                     id: None,
                     class: HashSet::new(),
+                    pseudo_element: None,
                 }),
                 Selector::Simple(SimpleSelector {
                     tag_name: None,
                     id: None,
                     class: HashSet::new(),
+                    pseudo_element: None,
                 }),
             ],
             declarations: vec![
@@ -931,20 +1363,28 @@ fn test1() {
                 },
                 Declaration {
                     name: "content".to_string(),
-                    values: vec![Value::Num(0.0)],
+                    values: vec![Value::Str("aa".to_string())],
                 },
                 Declaration {
                     name: "background-image".to_string(),
-                    values: vec![Value::Num(0.0)],
+                    values: vec![Value::Url("aaa".to_string())],
                 },
                 Declaration {
                     name: "unknown".to_string(),
                     values: vec![Value::Keyword("unknown".to_string())],
                 },
             ],
+            media: vec![],
         },
     ];
-    assert_eq!(stylesheet, Stylesheet { rules: rules });
+    assert_eq!(
+        stylesheet,
+        Stylesheet {
+            rules: rules,
+            imports: vec![],
+            pages: vec![],
+        }
+    );
 }
 
 #[test]
@@ -967,6 +1407,25 @@ fn test2() {
     );
 }
 
+#[test]
+fn test_functions() {
+    let src = "shape-outside: polygon(0 0, 50% 10px) margin-box; clip-path: unknown(1px)";
+    let decls = parse_attr_style(src.to_string());
+    let percent = Value::Length(50.0, Unit::Percent);
+    assert_eq!(
+        decls[0].values,
+        vec![
+            Value::Function(
+                "polygon".to_string(),
+                vec![Value::Num(0.0), Value::Num(0.0), percent, Value::Length(10.0, Unit::Px)],
+            ),
+            Value::Keyword("margin-box".to_string()),
+        ]
+    );
+    assert_eq!(decls[0].values[0].to_string(), "polygon(0 0 50% 10px)");
+    assert_eq!(decls[1].values, vec![Value::Keyword("unknown".to_string())]);
+}
+
 #[test]
 fn test_rgb_rgba() {
     let src = "color: rgb(1, 2, 3); background: rgba(250, 1, 250, 0.3); ";
@@ -1003,10 +1462,12 @@ fn test_rgb_rgba() {
 
 #[test]
 fn test_at_mark_rules() {
-    // At-mark rules are all ignored.
-    parse(
+    // At-mark rules except @import and @media are all ignored.
+    let stylesheet = parse(
         "
           @import 'a.css';
+          @import url(b.css) screen;
+          @import url( '../c.css' );
 
           @font-face { 
             div { 
@@ -1019,9 +1480,32 @@ fn test_at_mark_rules() {
               a: b 
             } 
           }
+
+          @media (prefers-color-scheme: dark) {
+            p { a: b }
+            @media screen, print { q { a: b } }
+          }
         "
             .to_string(),
     );
+    assert_eq!(
+        stylesheet.imports,
+        vec!["a.css".to_string(), "b.css".to_string(), "../c.css".to_string()]
+    );
+    let applied = || -> Vec<String> {
+        stylesheet
+            .rules
+            .iter()
+            .filter(|rule| rule.applies())
+            .map(|rule| rule.selectors[0].to_string())
+            .collect()
+    };
+    assert_eq!(stylesheet.rules.len(), 3);
+    assert_eq!(stylesheet.rules[2].media.len(), 2);
+    assert_eq!(applied(), vec!["div"]);
+    media::set_preferred_color_scheme(media::ColorScheme::Dark);
+    assert_eq!(applied(), vec!["div", "p", "q"]);
+    media::set_preferred_color_scheme(media::ColorScheme::Light);
 }
 
 #[test]
@@ -1047,3 +1531,46 @@ fn test_attribute() {
             .to_string(),
     );
 }
+
+#[test]
+fn test_nesting() {
+    let stylesheet = parse(
+        "
+        .a, .b {
+            color: red;
+            > p { color: blue; }
+            &#c { margin: 0; em, & + span { padding: 0; } }
+            .d & { margin: 1px; }
+            @media print { color: black; }
+            div:hover { padding: 1px; }
+            width: 10px;
+        }
+        "
+        .to_string(),
+    );
+    let rules: Vec<(Vec<String>, Vec<&str>)> = stylesheet
+        .rules
+        .iter()
+        .map(|rule| {
+            let mut selectors: Vec<String> = rule.selectors.iter().map(|s| s.to_string()).collect();
+            selectors.sort();
+            let names = rule.declarations.iter().map(|d| d.name.as_str()).collect();
+            (selectors, names)
+        })
+        .collect();
+    let strings = |strs: &[&str]| strs.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        rules,
+        vec![
+            (strings(&[".a", ".b"]), vec!["color", "width"]),
+            (strings(&[".a > p", ".b > p"]), vec!["color"]),
+            (strings(&[".a#c", ".b#c"]), vec!["margin"]),
+            // ``+`` is parsed like a descendant combinator.
+            (strings(&[".a#c em", ".a#c span", ".b#c em", ".b#c span"]), vec!["padding"]),
+            (strings(&[".d .a", ".d .b"]), vec!["margin"]),
+            // In ``@media print``, so it does not apply
+            (strings(&[".a", ".b"]), vec!["color"]),
+            (strings(&[".a div", ".b div"]), vec!["padding"]),
+        ]
+    );
+}
@@ -6,6 +6,8 @@ use std::cell::RefCell;
 pub fn default_style() -> Stylesheet {
     Stylesheet {
         rules: DEFAULT_RULES.with(|default_rules| default_rules.borrow().clone()),
+        imports: vec![],
+        pages: vec![],
     }
 }
 
@@ -21,9 +23,16 @@ thread_local!(
         rule_h3(&mut rules);
         rule_a(&mut rules);
         rule_img(&mut rules);
+        rule_picture(&mut rules);
+        rule_svg(&mut rules);
+        rule_canvas(&mut rules);
+        rule_math(&mut rules);
         rule_b(&mut rules);
         rule_i(&mut rules);
+        rule_q(&mut rules);
         rule_button(&mut rules);
+        rule_text_input(&mut rules);
+        rule_select(&mut rules);
         rule_style(&mut rules);
         rule_title(&mut rules);
         rule_script(&mut rules);
@@ -33,7 +42,13 @@ thread_local!(
 
 macro_rules! tag_name { ($name:expr) => {
     Selector::Simple(SimpleSelector {
-        tag_name: Some($name.to_string()), id: None, class: HashSet::new() })
+        tag_name: Some($name.to_string()), id: None, class: HashSet::new(), pseudo_element: None })
+}}
+
+macro_rules! pseudo_element { ($name:expr, $pseudo_element:expr) => {
+    Selector::Simple(SimpleSelector {
+        tag_name: Some($name.to_string()), id: None, class: HashSet::new(),
+        pseudo_element: Some($pseudo_element.to_string()) })
 }}
 
 macro_rules! decl { ($name:expr, $( $val:expr ),*) => {
@@ -46,7 +61,7 @@ macro_rules! decl { ($name:expr, $( $val:expr ),*) => {
 macro_rules! keyword { ($str:expr) => { Value::Keyword($str.to_string()) }}
 macro_rules! len_px  { ($val:expr) => { Value::Length($val, Unit::Px) }}
 // macro_rules! num     { ($val:expr) => { Value::Num($val) }}
-macro_rules! color   { ($clr:expr) => { Value::Color($clr) }}
+// macro_rules! color   { ($clr:expr) => { Value::Color($clr) }}
 
 fn rule_universal(rules: &mut Vec<Rule>) {
     rules.push(Rule {
@@ -55,9 +70,11 @@ fn rule_universal(rules: &mut Vec<Rule>) {
                 tag_name: None,
                 id: None,
                 class: HashSet::new(),
+                pseudo_element: None,
             }),
         ],
         declarations: vec![decl!("display", keyword!("block"))],
+        media: vec![],
     });
 }
 
@@ -68,8 +85,10 @@ fn rule_html(rules: &mut Vec<Rule>) {
             // decl!("width", keyword!("auto")),
             // decl!("padding", len_px!(0f64)),
             // decl!("margin", len_px!(0f64)),
-            decl!("background", color!(WHITE)),
+            // The canvas is white unless the root or the body has a background (see
+            // ``painter::build_display_list``).
         ],
+        media: vec![],
     });
 }
 
@@ -87,6 +106,7 @@ fn rule_span(rules: &mut Vec<Rule>) {
     rules.push(Rule {
         selectors: vec![tag_name!("span")],
         declarations: vec![decl!("display", keyword!("inline"))],
+        media: vec![],
     });
 }
 
@@ -98,6 +118,7 @@ fn rule_h1(rules: &mut Vec<Rule>) {
             decl!("font-weight", keyword!("bold")),
             decl!("padding", len_px!(10f64)),
         ],
+        media: vec![],
     });
 }
 
@@ -109,6 +130,7 @@ fn rule_h2(rules: &mut Vec<Rule>) {
             decl!("font-weight", keyword!("bold")),
             decl!("padding", len_px!(10f64)),
         ],
+        media: vec![],
     });
 }
 
@@ -120,6 +142,7 @@ fn rule_h3(rules: &mut Vec<Rule>) {
             decl!("font-weight", keyword!("bold")),
             decl!("padding", len_px!(10f64)),
         ],
+        media: vec![],
     });
 }
 
@@ -130,15 +153,12 @@ fn rule_a(rules: &mut Vec<Rule>) {
             decl!("display", keyword!("inline")),
             decl!(
                 "color",
-                color!(Color {
-                    r: 0,
-                    g: 0,
-                    b: 0xee,
-                    a: 0xff,
-                })
+keyword!("linktext")
             ),
             decl!("text-decoration", keyword!("underline")),
+            decl!("cursor", keyword!("pointer")),
         ],
+        media: vec![],
     });
 }
 
@@ -146,6 +166,44 @@ fn rule_img(rules: &mut Vec<Rule>) {
     rules.push(Rule {
         selectors: vec![tag_name!("img")],
         declarations: vec![decl!("display", keyword!("inline"))],
+        media: vec![],
+    });
+}
+
+fn rule_picture(rules: &mut Vec<Rule>) {
+    rules.push(Rule {
+        selectors: vec![tag_name!("picture")],
+        declarations: vec![decl!("display", keyword!("inline"))],
+        media: vec![],
+    });
+    rules.push(Rule {
+        selectors: vec![tag_name!("source")],
+        declarations: vec![decl!("display", keyword!("none"))],
+        media: vec![],
+    });
+}
+
+fn rule_svg(rules: &mut Vec<Rule>) {
+    rules.push(Rule {
+        selectors: vec![tag_name!("svg")],
+        declarations: vec![decl!("display", keyword!("inline"))],
+        media: vec![],
+    });
+}
+
+fn rule_canvas(rules: &mut Vec<Rule>) {
+    rules.push(Rule {
+        selectors: vec![tag_name!("canvas")],
+        declarations: vec![decl!("display", keyword!("inline"))],
+        media: vec![],
+    });
+}
+
+fn rule_math(rules: &mut Vec<Rule>) {
+    rules.push(Rule {
+        selectors: vec![tag_name!("math")],
+        declarations: vec![decl!("display", keyword!("inline"))],
+        media: vec![],
     });
 }
 
@@ -156,6 +214,7 @@ fn rule_b(rules: &mut Vec<Rule>) {
             decl!("display", keyword!("inline")),
             decl!("font-weight", keyword!("bold")),
         ],
+        media: vec![],
     });
 }
 
@@ -166,6 +225,25 @@ fn rule_i(rules: &mut Vec<Rule>) {
             decl!("display", keyword!("inline")),
             decl!("font-style", keyword!("italic")),
         ],
+        media: vec![],
+    });
+}
+
+fn rule_q(rules: &mut Vec<Rule>) {
+    rules.push(Rule {
+        selectors: vec![tag_name!("q")],
+        declarations: vec![decl!("display", keyword!("inline"))],
+        media: vec![],
+    });
+    rules.push(Rule {
+        selectors: vec![pseudo_element!("q", "before")],
+        declarations: vec![decl!("content", keyword!("open-quote"))],
+        media: vec![],
+    });
+    rules.push(Rule {
+        selectors: vec![pseudo_element!("q", "after")],
+        declarations: vec![decl!("content", keyword!("close-quote"))],
+        media: vec![],
     });
 }
 
@@ -173,6 +251,37 @@ fn rule_button(rules: &mut Vec<Rule>) {
     rules.push(Rule {
         selectors: vec![tag_name!("button")],
         declarations: vec![decl!("display", keyword!("inline"))],
+        media: vec![],
+    });
+}
+
+fn rule_text_input(rules: &mut Vec<Rule>) {
+    rules.push(Rule {
+        selectors: vec![tag_name!("input"), tag_name!("textarea")],
+        declarations: vec![
+            decl!("display", keyword!("inline")),
+            decl!("padding", len_px!(2f64)),
+            decl!("border-width", len_px!(1f64)),
+            decl!("border-color", keyword!("buttonborder")),
+            decl!("background-color", keyword!("field")),
+            decl!("color", keyword!("fieldtext")),
+        ],
+        media: vec![],
+    });
+}
+
+fn rule_select(rules: &mut Vec<Rule>) {
+    rules.push(Rule {
+        selectors: vec![tag_name!("select")],
+        declarations: vec![
+            decl!("display", keyword!("inline")),
+            decl!("padding", len_px!(2f64)),
+            decl!("border-width", len_px!(1f64)),
+            decl!("border-color", keyword!("buttonborder")),
+            decl!("background-color", keyword!("buttonface")),
+            decl!("color", keyword!("buttontext")),
+        ],
+        media: vec![],
     });
 }
 
@@ -180,6 +289,7 @@ fn rule_style(rules: &mut Vec<Rule>) {
     rules.push(Rule {
         selectors: vec![tag_name!("style")],
         declarations: vec![decl!("display", keyword!("none"))],
+        media: vec![],
     });
 }
 
@@ -187,6 +297,7 @@ fn rule_title(rules: &mut Vec<Rule>) {
     rules.push(Rule {
         selectors: vec![tag_name!("title")],
         declarations: vec![decl!("display", keyword!("none"))],
+        media: vec![],
     });
 }
 
@@ -194,5 +305,6 @@ fn rule_script(rules: &mut Vec<Rule>) {
     rules.push(Rule {
         selectors: vec![tag_name!("script")],
         declarations: vec![decl!("display", keyword!("none"))],
+        media: vec![],
     });
 }
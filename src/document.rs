@@ -0,0 +1,535 @@
+//! The embedding API: a document parsed from HTML, styled with its stylesheets, laid out in a
+//! viewport and painted to any cairo context, without a window or the network. Without the
+//! ``raster`` feature, it is painted as a script for the canvas of a web page instead (see
+//! ``Document::canvas_script``).
+//!
+//! ```
+//! extern crate cairo;
+//! extern crate naglfar;
+//!
+//! use naglfar::document::Document;
+//! use naglfar::network::Url;
+//!
+//! fn main() {
+//!     let url = Url::parse("https://example.com/").unwrap();
+//!     let mut document = Document::from_html("<p>Hello</p>", url);
+//!     document.append_stylesheet("p { color: red; }");
+//!     document.layout(320.0, 240.0);
+//!
+//!     let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 320, 240).unwrap();
+//!     document.paint(&cairo::Context::new(&surface)).unwrap();
+//! }
+//! ```
+
+use canvas::CanvasBackend;
+use css::{self, Stylesheet};
+use dom::{Node, NodeType};
+use event::{self, Event};
+use html;
+use inspector;
+use layout::{self, BoxType, Dimensions, LayoutBox, LayoutInfo};
+use media::{self, ColorScheme};
+use network::Url;
+use overflow;
+use painter;
+use quirks;
+#[cfg(feature = "raster")]
+use raster;
+use script;
+use scroll::{ScrollAnimation, FRAME_INTERVAL};
+
+#[cfg(feature = "raster")]
+use std::error::Error;
+#[cfg(feature = "raster")]
+use std::fmt;
+use std::time::Instant;
+
+use app_units::Au;
+#[cfg(feature = "raster")]
+use cairo;
+#[cfg(feature = "raster")]
+use pangocairo;
+
+/// The viewport a document is laid out in until ``Document::layout`` is called (CSS px).
+pub const DEFAULT_VIEWPORT_WIDTH: f64 = 800.0;
+pub const DEFAULT_VIEWPORT_HEIGHT: f64 = 600.0;
+
+/// Why ``Document::paint`` failed.
+#[cfg(feature = "raster")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaintError {
+    /// The target was in an error state (e.g. its surface was finished), so nothing was painted.
+    Target(cairo::Status),
+    /// No Pango layout could be created for the target, so the text cannot be measured.
+    Text,
+}
+
+#[cfg(feature = "raster")]
+impl fmt::Display for PaintError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PaintError::Target(status) => write!(f, "cannot paint on the target: {:?}", status),
+            PaintError::Text => write!(f, "cannot lay out text on the target"),
+        }
+    }
+}
+
+#[cfg(feature = "raster")]
+impl Error for PaintError {}
+
+/// Where ``Document::scroll_to`` scrolls the viewport to
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScrollTarget {
+    /// The scroll position (CSS px)
+    Offset(f64, f64),
+    /// The top left of the element with this id (e.g. the fragment of a URL)
+    Element(String),
+}
+
+pub struct Document {
+    root: Node,
+    stylesheet: Stylesheet,
+    viewport: Dimensions,
+    /// None until laid out, and again after the stylesheet or the DOM changes
+    layout_root: Option<LayoutBox>,
+    scripts: script::Runtime,
+    /// How far the viewport is scrolled right and down (CSS px)
+    scroll: (f64, f64),
+    scroll_animation: Option<ScrollAnimation>,
+    /// Selected from the doctype, for the layout
+    quirks_mode: quirks::Mode,
+}
+
+impl Document {
+    /// Parses ``html`` with relative URLs resolved against ``url``, runs its ``<script>``s, and
+    /// parses the stylesheet in its ``<style>`` tag. Nothing is loaded: neither ``<link>``ed nor
+    /// ``@import``ed stylesheets, nor images.
+    pub fn from_html(html: &str, url: Url) -> Document {
+        let mut root = html::parse(html.to_string(), url.clone());
+        let quirks_mode = quirks::mode();
+        let mut scripts = script::Runtime::new(root.find_base_url().unwrap_or(url));
+        scripts.run_scripts(&mut root);
+        let stylesheet = css::parse(root.find_stylesheet_in_style_tag().unwrap_or_default());
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = Au::from_f64_px(DEFAULT_VIEWPORT_WIDTH);
+        viewport.content.height = Au::from_f64_px(DEFAULT_VIEWPORT_HEIGHT);
+        Document {
+            root: root,
+            stylesheet: stylesheet,
+            viewport: viewport,
+            layout_root: None,
+            scripts: scripts,
+            scroll: (0.0, 0.0),
+            scroll_animation: None,
+            quirks_mode: quirks_mode,
+        }
+    }
+
+    /// The root of the DOM tree.
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    /// The author stylesheet: the ``<style>`` tag and the appended ones, in order.
+    pub fn stylesheet(&self) -> &Stylesheet {
+        &self.stylesheet
+    }
+
+    /// Appends the rules of ``css`` to the author stylesheet, which win over the earlier ones
+    /// of the same specificity.
+    pub fn append_stylesheet(&mut self, css: &str) {
+        let appended = css::parse(css.to_string());
+        self.stylesheet.rules.extend(appended.rules);
+        self.stylesheet.imports.extend(appended.imports);
+        self.layout_root = None;
+    }
+
+    /// Sets the color scheme preferred by the user (see ``media::set_preferred_color_scheme``),
+    /// of all the documents of the thread. The document is styled again if it changed.
+    pub fn set_preferred_color_scheme(&mut self, scheme: ColorScheme) {
+        if media::set_preferred_color_scheme(scheme) {
+            self.layout_root = None;
+        }
+    }
+
+    /// Runs ``source`` like a script of the document (with its global variables). The document
+    /// is laid out again if it changes the DOM. Returns the value of its last expression
+    /// statement as a string, or the error it throws.
+    pub fn eval_script(&mut self, source: &str) -> Result<String, String> {
+        let result = self.scripts.eval(&mut self.root, source);
+        if self.scripts.take_mutated() {
+            self.layout_root = None;
+        }
+        result
+    }
+
+    /// Lays the document out in a viewport of ``width`` x ``height`` CSS px.
+    pub fn layout(&mut self, width: f64, height: f64) -> &LayoutBox {
+        self.viewport.content.width = Au::from_f64_px(width);
+        self.viewport.content.height = Au::from_f64_px(height);
+        self.layout_root = None;
+        self.layout_root()
+    }
+
+    /// The laid out tree, laid out in the last viewport first if it is not.
+    pub fn layout_root(&mut self) -> &LayoutBox {
+        if self.layout_root.is_none() {
+            // The boxes cached are of the last document laid out, which may be another one.
+            layout::LAYOUTBOX.with(|lb| *lb.borrow_mut() = None);
+            quirks::set_mode(self.quirks_mode);
+            self.layout_root = Some(layout::layout_tree(
+                &self.root,
+                &self.stylesheet,
+                self.viewport,
+            ));
+        }
+        self.layout_root.as_ref().unwrap()
+    }
+
+    /// The innermost element at (``x``, ``y``) in the document (in CSS px), laid out first if it
+    /// is not.
+    pub fn element_at(&mut self, x: f64, y: f64) -> Option<&Node> {
+        self.element_box_at(x, y).map(|layout_box| &layout_box.node)
+    }
+
+    fn element_box_at(&mut self, x: f64, y: f64) -> Option<&LayoutBox> {
+        inspector::path_at(self.layout_root(), x, y)
+            .into_iter()
+            .rev()
+            .find(|layout_box| match (&layout_box.box_type, &layout_box.node.data) {
+                (&BoxType::AnonymousBlock, _) | (_, &NodeType::Text(_)) => false,
+                _ => true,
+            })
+    }
+
+    /// Dispatches the mouse event ``kind`` (e.g. ``click``) to the innermost element at (``x``,
+    /// ``y``) in the document (in CSS px), laid out first if it is not. Returns false if a
+    /// listener canceled it: the embedder then does not do its default action (e.g. following
+    /// ``link_at``).
+    pub fn dispatch_mouse_event(&mut self, kind: &str, x: f64, y: f64) -> bool {
+        let id = self.element_box_at(x, y).and_then(|layout_box| layout_box.node_id);
+        match id.and_then(|id| self.root.find_path_by_id(id)) {
+            Some(target) => self.dispatch_event(Event::mouse(kind, target, x, y)),
+            None => true,
+        }
+    }
+
+    /// Dispatches the key event ``kind`` (e.g. ``keydown``) of ``key`` (e.g. ``a`` or ``Enter``)
+    /// to the ``<body>``. Returns false if a listener canceled it.
+    pub fn dispatch_key_event(&mut self, kind: &str, key: &str) -> bool {
+        let target = event::body_path(&self.root);
+        self.dispatch_event(Event::key(kind, target, key))
+    }
+
+    /// How far the viewport is scrolled right and down (CSS px).
+    pub fn scroll_position(&self) -> (f64, f64) {
+        self.scroll
+    }
+
+    /// Scrolls the viewport to ``target``, as far as the document (laid out first if it is not)
+    /// goes. With ``scroll-behavior: smooth`` on the root, it gets there in the calls of
+    /// ``run_timers``. Returns false if there is no element of ``target``.
+    pub fn scroll_to(&mut self, target: ScrollTarget) -> bool {
+        let (x, y) = match target {
+            ScrollTarget::Offset(x, y) => (x, y),
+            ScrollTarget::Element(id) => match element_origin(self.layout_root(), &id) {
+                Some((x, y)) => (x.to_f64_px(), y.to_f64_px()),
+                None => return false,
+            },
+        };
+        let document = self.layout_root().dimensions.margin_box();
+        let max_x = (document.width - self.viewport.content.width).to_f64_px().max(0.0);
+        let max_y = (document.height - self.viewport.content.height).to_f64_px().max(0.0);
+        let to = (x.min(max_x).max(0.0), y.min(max_y).max(0.0));
+        if self.layout_root().property.smooth_scrolling() {
+            self.scroll_animation = Some(ScrollAnimation::new(self.scroll, to, Instant::now()));
+        } else {
+            self.scroll_animation = None;
+            self.scroll = to;
+        }
+        true
+    }
+
+    /// Runs the functions given to ``setTimeout`` and ``setInterval`` that are due at ``now``
+    /// (each once at most), and moves a smooth scroll on. Returns when the next one is due, or
+    /// the next frame of the scroll, to call this again then.
+    pub fn run_timers(&mut self, now: Instant) -> Option<Instant> {
+        self.scripts.run_timers(&mut self.root, now);
+        if self.scripts.take_mutated() {
+            self.layout_root = None;
+        }
+        let next_timer = self.scripts.next_timer();
+        let animation = match self.scroll_animation {
+            Some(animation) => animation,
+            None => return next_timer,
+        };
+        let (position, done) = animation.position(now);
+        self.scroll = position;
+        if done {
+            self.scroll_animation = None;
+            return next_timer;
+        }
+        let next_frame = now + FRAME_INTERVAL;
+        Some(next_timer.map_or(next_frame, |next_timer| next_timer.min(next_frame)))
+    }
+
+    fn dispatch_event(&mut self, event: Event) -> bool {
+        let not_canceled = self.scripts.dispatch_event(&mut self.root, event);
+        if self.scripts.take_mutated() {
+            self.layout_root = None;
+        }
+        not_canceled
+    }
+
+    /// The URL of the link at (``x``, ``y``) in the document (in CSS px): resolved against the
+    /// URL of the document, except for ``#fragment``s.
+    pub fn link_at(&mut self, x: f64, y: f64) -> Option<&str> {
+        inspector::path_at(self.layout_root(), x, y)
+            .into_iter()
+            .rev()
+            .find_map(|layout_box| match layout_box.info {
+                LayoutInfo::Anker => layout_box.node.anker_url().map(|url| url.as_str()),
+                _ => None,
+            })
+    }
+
+    /// Paints the whole document (laid out first if it is not) on ``target``, in its user
+    /// space: its origin is the top left of the document, and a unit is a CSS px.
+    #[cfg(feature = "raster")]
+    pub fn paint(&mut self, target: &cairo::Context) -> Result<(), PaintError> {
+        let status = target.status();
+        if status != cairo::Status::Success {
+            return Err(PaintError::Target(status));
+        }
+        let mut pango_layout = pangocairo::functions::create_layout(target)
+            .ok_or(PaintError::Text)?;
+
+        let items = self.display_list();
+        raster::paint_to(target, &items, &mut pango_layout);
+        Ok(())
+    }
+
+    /// Paints the whole document like ``paint``, as a script of calls to a 2D context named
+    /// ``ctx`` (see ``canvas::CanvasBackend``).
+    pub fn canvas_script(&mut self) -> String {
+        let items = self.display_list();
+        let mut backend = CanvasBackend::new();
+        painter::paint(&items, &mut backend);
+        backend.script
+    }
+
+    /// The display list of the whole document, laid out first if it is not
+    fn display_list(&mut self) -> painter::DisplayList {
+        self.layout_root();
+        // Fixed backgrounds are in the viewport where it is scrolled to.
+        let mut viewport = self.viewport.content;
+        viewport.x = Au::from_f64_px(self.scroll.0);
+        viewport.y = Au::from_f64_px(self.scroll.1);
+        painter::set_viewport(viewport);
+        painter::build_display_list(self.layout_root.as_mut().unwrap())
+    }
+}
+
+/// The top left of the border box of the element with ``id`` in the document laid out in ``root``
+fn element_origin(root: &LayoutBox, id: &str) -> Option<(Au, Au)> {
+    fn find(layout_box: &LayoutBox, id: &str, x: Au, y: Au) -> Option<(Au, Au)> {
+        if let NodeType::Element(ref e) = layout_box.node.data {
+            if e.id().is_some_and(|e_id| e_id == id) {
+                let border_box = layout_box.dimensions.border_box();
+                return Some((x + border_box.x, y + border_box.y));
+            }
+        }
+        let (child_x, child_y) = overflow::child_origin(layout_box, x, y);
+        layout_box
+            .children
+            .iter()
+            .find_map(|child| find(child, id, child_x, child_y))
+    }
+    find(root, id, Au(0), Au(0))
+}
+
+#[cfg(feature = "raster")]
+#[test]
+fn test_document() {
+    let url = Url::parse("file:///a.html").unwrap();
+    let mut document = Document::from_html(
+        "<style>div { height: 10px; background-color: red; }</style><div></div>",
+        url,
+    );
+    document.append_stylesheet("body { margin: 0; } div { background-color: #00ff00; }");
+    assert_eq!(document.stylesheet().rules.len(), 3);
+
+    // html > body > div
+    let width = document.layout(40.0, 30.0).children[0].dimensions.content.width;
+    assert_eq!(width, Au::from_f64_px(40.0));
+
+    let mut surface = cairo::ImageSurface::create(cairo::Format::Rgb24, 40, 30).unwrap();
+    document.paint(&cairo::Context::new(&surface)).unwrap();
+    {
+        let data = surface.get_data().unwrap();
+        // The first pixel (BGRx): the div, green as appended
+        assert_eq!(&data[0..3], &[0, 255, 0]);
+    }
+
+    use cairo::prelude::SurfaceExt;
+    surface.finish();
+    assert_eq!(
+        document.paint(&cairo::Context::new(&surface)),
+        Err(PaintError::Target(cairo::Status::SurfaceFinished))
+    );
+}
+
+#[test]
+fn test_canvas_script() {
+    let url = Url::parse("file:///a.html").unwrap();
+    let mut document = Document::from_html("<div></div>", url);
+    document.append_stylesheet("body { margin: 0; } div { height: 10px; background-color: lime; }");
+    document.layout(40.0, 30.0);
+    // The div, after the background of the page
+    let script = document.canvas_script();
+    assert!(script.contains("\"rgba(0, 255, 0, 1)\";\nctx.fillRect(0, 0, 40, 10);\n"));
+}
+
+#[test]
+fn test_hit_test() {
+    let mut document = Document::from_html(
+        "<!DOCTYPE html><style>body { margin: 0; } p { margin: 0; height: 20px; }</style>\
+         <p>a</p><p><a href=\"b.html\">b</a></p>",
+        Url::parse("file:///a.html").unwrap(),
+    );
+    document.layout(100.0, 100.0);
+    let tag_name = |node: Option<&Node>| match node.map(|node| &node.data) {
+        Some(&NodeType::Element(ref e)) => Some(e.tag_name.clone()),
+        _ => None,
+    };
+    assert_eq!(tag_name(document.element_at(1.0, 10.0)), Some("p".to_string()));
+    assert_eq!(tag_name(document.element_at(1.0, 21.0)), Some("a".to_string()));
+    assert_eq!(tag_name(document.element_at(1.0, 90.0)), None);
+    assert_eq!(document.link_at(1.0, 10.0), None);
+    assert_eq!(document.link_at(1.0, 21.0), Some("file:///b.html"));
+
+    // Hits go through a box with ``pointer-events: none`` to the one under it, but not through
+    // its descendants with ``auto``.
+    let mut document = Document::from_html(
+        "<style>body { margin: 0; } div { height: 20px; pointer-events: none; }</style>\
+         <div><a href=\"b.html\">b</a></div>\
+         <div><a href=\"c.html\" style=\"pointer-events: auto\">c</a></div>",
+        Url::parse("file:///a.html").unwrap(),
+    );
+    document.layout(100.0, 100.0);
+    assert_eq!(tag_name(document.element_at(1.0, 10.0)), Some("html".to_string()));
+    assert_eq!(document.link_at(1.0, 10.0), None);
+    assert_eq!(tag_name(document.element_at(1.0, 30.0)), Some("a".to_string()));
+    assert_eq!(document.link_at(1.0, 30.0), Some("file:///c.html"));
+
+    // Nor out of the clip-path of a box (here its left half), even where its children are
+    let mut document = Document::from_html(
+        "<style>body { margin: 0; } div { width: 100px; height: 20px; }</style>\
+         <div style=\"clip-path: inset(0 50% 0 0)\"><p style=\"margin: 0\">a</p></div>",
+        Url::parse("file:///a.html").unwrap(),
+    );
+    document.layout(200.0, 100.0);
+    assert_eq!(tag_name(document.element_at(10.0, 10.0)), Some("p".to_string()));
+    assert_eq!(tag_name(document.element_at(60.0, 10.0)), Some("html".to_string()));
+}
+
+#[test]
+fn test_scripts() {
+    let mut document = Document::from_html(
+        "<!DOCTYPE html><style>body { margin: 0; }</style>\
+         <div id=\"a\" style=\"height: 10px\"></div>\
+         <script>document.getElementById('a').style.height = '20px';</script>",
+        Url::parse("file:///a.html").unwrap(),
+    );
+    let height = |document: &mut Document| document.layout_root().dimensions.content.height;
+    assert_eq!(height(&mut document), Au::from_f64_px(20.0));
+
+    let source = "var div = document.getElementById('a'); div.style.height = '30px'";
+    assert_eq!(document.eval_script(source), Ok("30px".to_string()));
+    assert_eq!(height(&mut document), Au::from_f64_px(30.0));
+    assert_eq!(document.eval_script("div.id"), Ok("a".to_string()));
+    assert!(document.eval_script("div.x(").unwrap_err().starts_with("SyntaxError"));
+}
+
+#[test]
+fn test_events() {
+    let mut document = Document::from_html(
+        "<!DOCTYPE html><style>body { margin: 0; } div { height: 10px; }</style>\
+         <body><div id=\"a\"><a href=\"b.html\">b</a></div>\
+         <script>var a = document.getElementById('a');\
+         a.addEventListener('click', e => { a.style.height = '20px'; e.preventDefault() });\
+         document.body.addEventListener('keydown', e => a.style.height = e.key.length + 'px');\
+         </script></body>",
+        Url::parse("file:///a.html").unwrap(),
+    );
+    let height = |document: &mut Document| document.layout_root().dimensions.content.height;
+    assert_eq!(height(&mut document), Au::from_f64_px(10.0));
+
+    assert!(document.dispatch_mouse_event("click", 50.0, 90.0));
+    assert_eq!(height(&mut document), Au::from_f64_px(10.0));
+    // On the link in the div
+    assert!(!document.dispatch_mouse_event("click", 1.0, 5.0));
+    assert_eq!(height(&mut document), Au::from_f64_px(20.0));
+
+    assert!(document.dispatch_key_event("keydown", "Enter"));
+    assert_eq!(height(&mut document), Au::from_f64_px(5.0));
+}
+
+#[test]
+fn test_timers() {
+    use std::time::Duration;
+
+    let mut document = Document::from_html(
+        "<body><div id=\"a\"></div><script>var a = document.getElementById('a'), n = 0;\
+         var id = setInterval(() => { a.textContent = ++n; if (n == 3) clearInterval(id) }, 10);\
+         var cleared = setTimeout(() => a.textContent = 'cleared', 5);\
+         setTimeout((x, y) => a.id = x + y, 20, 'b', 'c'); clearTimeout(cleared);</script></body>",
+        Url::parse("file:///a.html").unwrap(),
+    );
+    let later = |ms: u64| Instant::now() + Duration::from_millis(ms);
+    assert!(document.run_timers(Instant::now()).is_some());
+    let text = |document: &mut Document| document.eval_script("a.id + a.textContent").unwrap();
+    assert_eq!(text(&mut document), "a");
+
+    // The interval runs once a call.
+    document.run_timers(later(15));
+    assert_eq!(text(&mut document), "a1");
+    document.run_timers(later(100));
+    assert_eq!(text(&mut document), "bc2");
+    assert!(document.run_timers(later(100)).is_none());
+    assert_eq!(text(&mut document), "bc3");
+
+    document.eval_script("setTimeout('a.id = \"d\"'); setTimeout(function() { throw 1 })").unwrap();
+    assert_eq!(document.run_timers(later(100)), None);
+    assert_eq!(text(&mut document), "d3");
+}
+
+#[test]
+fn test_scroll_to() {
+    use scroll::SMOOTH_SCROLL_DURATION;
+
+    let mut document = Document::from_html(
+        "<style>body { margin: 0; } div { height: 100px; }</style>\
+         <div></div><div id=\"b\"></div><div></div>",
+        Url::parse("file:///a.html").unwrap(),
+    );
+    document.layout(100.0, 50.0);
+    assert!(document.scroll_to(ScrollTarget::Element("b".to_string())));
+    assert_eq!(document.scroll_position(), (0.0, 100.0));
+    assert!(!document.scroll_to(ScrollTarget::Element("c".to_string())));
+    // As far as the document goes
+    assert!(document.scroll_to(ScrollTarget::Offset(10.0, 1000.0)));
+    assert_eq!(document.scroll_position(), (0.0, 250.0));
+    assert_eq!(document.run_timers(Instant::now()), None);
+
+    // Smoothly, a frame a call
+    document.append_stylesheet("html { scroll-behavior: smooth; }");
+    assert!(document.scroll_to(ScrollTarget::Element("b".to_string())));
+    assert_eq!(document.scroll_position(), (0.0, 250.0));
+    let now = Instant::now();
+    assert_eq!(document.run_timers(now), Some(now + FRAME_INTERVAL));
+    let (_, y) = document.scroll_position();
+    assert!(100.0 < y && y <= 250.0);
+    assert_eq!(document.run_timers(now + SMOOTH_SCROLL_DURATION), None);
+    assert_eq!(document.scroll_position(), (0.0, 100.0));
+}
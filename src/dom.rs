@@ -1,7 +1,8 @@
 use std::collections::{HashMap, HashSet};
-use std::path::{Path, PathBuf};
 use std::{fmt, iter};
 use css;
+use media;
+use network::Url;
 
 pub type AttrMap = HashMap<String, String>;
 
@@ -24,8 +25,17 @@ pub enum LayoutType {
     Generic,
     Text,
     Image,
+    /// An inline ``<svg>``, painted from its subtree
+    Svg,
+    /// A ``<math>``, laid out from its subtree
+    Math,
+    /// A ``<canvas>``, showing the bitmap drawn on it
+    Canvas,
     Anker,
     Button,
+    TextInput,
+    /// A ``<select>``, shown as a closed dropdown of its selected option
+    Select,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -49,8 +59,18 @@ impl Node {
             data: NodeType::Element(ElementData {
                 layout_type: match name.to_lowercase().as_str() {
                     "img" => LayoutType::Image,
+                    "svg" => LayoutType::Svg,
+                    "math" => LayoutType::Math,
+                    "canvas" => LayoutType::Canvas,
                     "a" => LayoutType::Anker,
                     // "button" => LayoutType::Button,
+                    "input" => match attrs.get("type").map(|t| t.to_lowercase()) {
+                        None => LayoutType::TextInput,
+                        Some(ref t) if t == "text" => LayoutType::TextInput,
+                        _ => LayoutType::Generic,
+                    },
+                    "textarea" => LayoutType::TextInput,
+                    "select" => LayoutType::Select,
                     _ => LayoutType::Generic,
                 },
                 tag_name: name,
@@ -77,10 +97,11 @@ impl Node {
     pub fn is_inline(&self) -> bool {
         match self.data {
             NodeType::Element(ElementData { ref tag_name, .. }) => match tag_name.as_str() {
-                "a" | "abbr" | "acronym" | "b" | "bdo" | "big" | "br" | "button" | "cite"
-                | "code" | "dfn" | "em" | "i" | "img" | "input" | "kbd" | "label" | "map"
-                | "object" | "q" | "samp" | "script" | "select" | "small" | "span" | "strong"
-                | "sub" | "sup" | "textarea" | "time" | "tt" | "var" => true,
+                "a" | "abbr" | "acronym" | "b" | "bdo" | "big" | "br" | "button" | "canvas"
+                | "cite" | "code" | "dfn" | "em" | "i" | "img" | "input" | "kbd" | "label" | "map"
+                | "math" | "object" | "picture" | "q" | "samp" | "script" | "select" | "small"
+                | "span" | "strong" | "sub" | "sup" | "svg" | "textarea" | "time" | "tt"
+                | "var" => true,
                 _ => false,
             },
             NodeType::Text(_) => false,
@@ -114,7 +135,8 @@ impl Node {
         }
     }
 
-    pub fn find_stylesheet_path(&self) -> Option<PathBuf> {
+    /// The URL of the first stylesheet ``<link>`` whose ``media`` matches (see ``media_applies``)
+    pub fn find_stylesheet_url(&self) -> Option<String> {
         let mut buf = vec![];
         self.find_nodes_by_tag_name("link", &mut buf);
         for &Node { ref data, .. } in buf {
@@ -122,10 +144,8 @@ impl Node {
                 &NodeType::Element(ElementData { ref attrs, .. }) => {
                     if let Some(name) = attrs.get("rel") {
                         println!(">>{}", name);
-                        if name == "stylesheet" {
-                            return attrs
-                                .get("href")
-                                .and_then(|filename| Some(Path::new(filename).to_path_buf()));
+                        if name == "stylesheet" && media_applies(attrs) {
+                            return attrs.get("href").cloned();
                         }
                     }
                 }
@@ -135,18 +155,64 @@ impl Node {
         None
     }
 
+    /// The URL of the first ``<base>`` with an ``href`` (resolved when it is parsed), which the
+    /// relative URLs of the document are resolved against
+    pub fn find_base_url(&self) -> Option<Url> {
+        let mut buf = vec![];
+        self.find_nodes_by_tag_name("base", &mut buf);
+        buf.into_iter().find_map(|base| match base.data {
+            NodeType::Element(ElementData { ref attrs, .. }) => {
+                attrs.get("href").and_then(|href| Url::parse(href).ok())
+            }
+            NodeType::Text(_) => None,
+        })
+    }
+
+    /// The ``href`` of the first ``<link>`` whose ``rel`` has ``icon`` (e.g. ``shortcut icon``)
+    pub fn find_icon_url(&self) -> Option<String> {
+        let mut buf = vec![];
+        self.find_nodes_by_tag_name("link", &mut buf);
+        buf.into_iter().find_map(|link| match link.data {
+            NodeType::Element(ElementData { ref attrs, .. }) => attrs
+                .get("rel")
+                .filter(|rel| rel.split_whitespace().any(|rel| rel.eq_ignore_ascii_case("icon")))
+                .and(attrs.get("href"))
+                .cloned(),
+            NodeType::Text(_) => None,
+        })
+    }
+
+    /// The text of the first ``<style>`` whose ``media`` matches (see ``media_applies``)
     pub fn find_stylesheet_in_style_tag(&self) -> Option<String> {
-        self.find_first_node_by_tag_name("style")
+        let mut buf = vec![];
+        self.find_nodes_by_tag_name("style", &mut buf);
+        buf.into_iter()
+            .find(|style| match style.data {
+                NodeType::Element(ElementData { ref attrs, .. }) => media_applies(attrs),
+                NodeType::Text(_) => false,
+            })
             .and_then(|&Node { ref children, .. }| match children[0].data {
                 NodeType::Element(_) => None,
                 NodeType::Text(ref s) => Some(s.clone()),
             })
     }
 
-    pub fn image_url(&self) -> Option<&String> {
-        match self.data {
-            NodeType::Element(ElementData { ref attrs, .. }) => attrs.get("src"),
-            NodeType::Text(_) => None,
+    /// The text of the first ``<title>``, with the white space collapsed. None if it is empty.
+    pub fn find_title(&self) -> Option<String> {
+        let title = self.find_first_node_by_tag_name("title")?;
+        let text: Vec<&str> = title
+            .children
+            .iter()
+            .filter_map(|child| match child.data {
+                NodeType::Text(ref s) => Some(s.as_str()),
+                NodeType::Element(_) => None,
+            })
+            .collect();
+        let text = text.concat().split_whitespace().collect::<Vec<_>>().join(" ");
+        if text.is_empty() {
+            None
+        } else {
+            Some(text)
         }
     }
 
@@ -157,6 +223,180 @@ impl Node {
         }
     }
 
+    /// The texts of the node and its descendants, joined (``textContent``)
+    pub fn text_content(&self) -> String {
+        match self.data {
+            NodeType::Text(ref text) => text.clone(),
+            NodeType::Element(_) => self.children.iter().map(Node::text_content).collect(),
+        }
+    }
+
+    /// Returns the number of nodes in the tree rooted at this node (including itself).
+    pub fn count_nodes(&self) -> usize {
+        1 + self.children
+            .iter()
+            .fold(0, |acc, child| acc + child.count_nodes())
+    }
+
+    /// Finds the node whose id is `id`. The id of a node is its index in document order
+    /// (pre-order), the same numbering `layout::build_layout_tree` gives to nodes.
+    pub fn find_node_by_id_mut(&mut self, id: usize) -> Option<&mut Node> {
+        fn find<'a>(node: &'a mut Node, id: usize, cur: &mut usize) -> Option<&'a mut Node> {
+            if *cur == id {
+                return Some(node);
+            }
+            for child in &mut node.children {
+                *cur += 1;
+                if let Some(node) = find(child, id, cur) {
+                    return Some(node);
+                }
+            }
+            None
+        }
+        find(self, id, &mut 0)
+    }
+
+    /// The child indices from this node to the node whose id is `id`.
+    pub fn find_path_by_id(&self, id: usize) -> Option<Vec<usize>> {
+        let mut path = vec![];
+        let mut node = self;
+        let mut cur = 0;
+        'descend: while cur != id {
+            cur += 1;
+            for (i, child) in node.children.iter().enumerate() {
+                let count = child.count_nodes();
+                if id < cur + count {
+                    path.push(i);
+                    node = child;
+                    continue 'descend;
+                }
+                cur += count;
+            }
+            return None;
+        }
+        Some(path)
+    }
+
+    /// The id of the node at the child indices `path` from this node (see `find_node_by_id_mut`).
+    pub fn find_id_by_path(&self, path: &[usize]) -> Option<usize> {
+        let mut id = 0;
+        let mut node = self;
+        for &i in path {
+            let child = node.children.get(i)?;
+            id += 1 + node.children[..i].iter().map(Node::count_nodes).sum::<usize>();
+            node = child;
+        }
+        Some(id)
+    }
+
+    pub fn is_multiline_text_input(&self) -> bool {
+        match self.data {
+            NodeType::Element(ElementData { ref tag_name, .. }) => tag_name == "textarea",
+            NodeType::Text(_) => false,
+        }
+    }
+
+    /// The current value of <input type=text> or <textarea>.
+    pub fn text_input_value(&self) -> String {
+        match self.data {
+            NodeType::Element(ElementData { ref attrs, .. }) => {
+                if self.is_multiline_text_input() {
+                    self.children
+                        .iter()
+                        .fold("".to_string(), |mut value, child| {
+                            if let NodeType::Text(ref text) = child.data {
+                                value += text.as_str();
+                            }
+                            value
+                        })
+                } else {
+                    attrs.get("value").cloned().unwrap_or_else(|| "".to_string())
+                }
+            }
+            NodeType::Text(_) => "".to_string(),
+        }
+    }
+
+    pub fn set_text_input_value(&mut self, value: String) {
+        if self.is_multiline_text_input() {
+            self.children = vec![Node::text(value)];
+        } else if let NodeType::Element(ElementData { ref mut attrs, .. }) = self.data {
+            attrs.insert("value".to_string(), value);
+        }
+    }
+
+    /// The ``<option>``s of a ``<select>``, also those in its ``<optgroup>``s.
+    pub fn select_options(&self) -> Vec<&Node> {
+        fn collect<'a>(node: &'a Node, options: &mut Vec<&'a Node>) {
+            for child in &node.children {
+                match child.data {
+                    NodeType::Element(ref e) if e.tag_name == "option" => options.push(child),
+                    NodeType::Element(ref e) if e.tag_name == "optgroup" => collect(child, options),
+                    _ => {}
+                }
+            }
+        }
+        let mut options = vec![];
+        collect(self, &mut options);
+        options
+    }
+
+    /// What an ``<option>`` shows: its ``label``, or its text with the spaces collapsed.
+    pub fn option_label(&self) -> String {
+        match self.data {
+            NodeType::Element(ElementData { ref attrs, .. }) if attrs.contains_key("label") => {
+                attrs["label"].clone()
+            }
+            _ => self.text_content().split_whitespace().collect::<Vec<_>>().join(" "),
+        }
+    }
+
+    /// The ``value`` of an ``<option>``, or its label.
+    pub fn option_value(&self) -> String {
+        match self.data {
+            NodeType::Element(ElementData { ref attrs, .. }) if attrs.contains_key("value") => {
+                attrs["value"].clone()
+            }
+            _ => self.option_label(),
+        }
+    }
+
+    /// The index of the selected option of a ``<select>``: the last one with ``selected``, or
+    /// the first one. None if it has no option.
+    pub fn selected_option(&self) -> Option<usize> {
+        let options = self.select_options();
+        let selected = options.iter().rposition(|option| match option.data {
+            NodeType::Element(ElementData { ref attrs, .. }) => attrs.contains_key("selected"),
+            NodeType::Text(_) => false,
+        });
+        selected.or(if options.is_empty() { None } else { Some(0) })
+    }
+
+    /// Selects the option at ``index`` of a ``<select>`` (only it has ``selected``).
+    pub fn set_selected_option(&mut self, index: usize) {
+        fn set(node: &mut Node, index: usize, i: &mut usize) {
+            for child in &mut node.children {
+                let is_optgroup = match child.data {
+                    NodeType::Element(ref mut e) if e.tag_name == "option" => {
+                        if *i == index {
+                            e.attrs.insert("selected".to_string(), "".to_string());
+                        } else {
+                            e.attrs.remove("selected");
+                        }
+                        *i += 1;
+                        false
+                    }
+                    NodeType::Element(ref e) => e.tag_name == "optgroup",
+                    NodeType::Text(_) => false,
+                };
+                if is_optgroup {
+                    set(child, index, i);
+                }
+            }
+        }
+        set(self, index, &mut 0);
+    }
+
     pub fn attr(&self, name: &str) -> Option<css::Value> {
         match self.data {
             NodeType::Element(ElementData { ref attrs, .. }) => attrs
@@ -182,6 +422,14 @@ impl ElementData {
     }
 }
 
+/// Whether the ``media`` of a ``<link>`` or a ``<style>`` with ``attrs`` matches now, e.g. not
+/// ``print`` on the screen. It does without one.
+fn media_applies(attrs: &AttrMap) -> bool {
+    attrs
+        .get("media")
+        .is_none_or(|queries| media::matches(&media::parse_query_list(queries)))
+}
+
 // Functions for displaying
 
 fn walk(node: &Node, indent: usize, f: &mut fmt::Formatter) -> fmt::Result {
@@ -247,3 +495,115 @@ fn test_id() {
         None
     )
 }
+
+#[test]
+fn test_find_title() {
+    let head = |children| {
+        Node::elem(
+            "head".to_string(),
+            HashMap::new(),
+            vec![Node::elem("title".to_string(), HashMap::new(), children)],
+        )
+    };
+    assert_eq!(
+        head(vec![Node::text("\n  A\n  page ".to_string())]).find_title(),
+        Some("A page".to_string())
+    );
+    assert_eq!(head(vec![Node::text(" ".to_string())]).find_title(), None);
+    assert_eq!(head(vec![]).find_title(), None);
+}
+
+#[test]
+fn test_find_icon_url() {
+    let link = |rel: &str, href: &str| {
+        let mut attrs = HashMap::new();
+        attrs.insert("rel".to_string(), rel.to_string());
+        attrs.insert("href".to_string(), href.to_string());
+        Node::elem("link".to_string(), attrs, vec![])
+    };
+    let head = |children| Node::elem("head".to_string(), HashMap::new(), children);
+    assert_eq!(
+        head(vec![link("stylesheet", "a.css"), link("Shortcut Icon", "a.ico"), link("icon", "b")])
+            .find_icon_url(),
+        Some("a.ico".to_string())
+    );
+    assert_eq!(head(vec![link("apple-touch-icon", "a.png")]).find_icon_url(), None);
+
+    let mut print = link("stylesheet", "print.css");
+    if let NodeType::Element(ref mut e) = print.data {
+        e.attrs.insert("media".to_string(), "print".to_string());
+    }
+    let links = head(vec![print, link("stylesheet", "screen.css")]);
+    assert_eq!(links.find_stylesheet_url(), Some("screen.css".to_string()));
+    media::set_media_type(media::MediaType::Print);
+    assert_eq!(links.find_stylesheet_url(), Some("print.css".to_string()));
+    media::set_media_type(media::MediaType::Screen);
+}
+
+#[test]
+fn test_select_options() {
+    use html;
+
+    let mut select = html::parse(
+        "<select><option value=\"a\">  A\n one</option><optgroup label=\"g\"><option \
+         selected>B</option><option label=\"C\">c</option></optgroup></select>"
+            .to_string(),
+        Url::parse("file:///").unwrap(),
+    );
+    assert_eq!(select.layout_type(), LayoutType::Select);
+    let options = |select: &Node| -> Vec<(String, String)> {
+        select
+            .select_options()
+            .iter()
+            .map(|option| (option.option_label(), option.option_value()))
+            .collect()
+    };
+    assert_eq!(
+        options(&select),
+        vec![
+            ("A one".to_string(), "a".to_string()),
+            ("B".to_string(), "B".to_string()),
+            ("C".to_string(), "C".to_string()),
+        ]
+    );
+    assert_eq!(select.selected_option(), Some(1));
+    select.set_selected_option(2);
+    assert_eq!(select.selected_option(), Some(2));
+    select.set_selected_option(0);
+    assert_eq!(select.selected_option(), Some(0));
+    select.children.clear();
+    assert_eq!(select.selected_option(), None);
+}
+
+#[test]
+fn test_text_input_value() {
+    let mut attrs = HashMap::new();
+    attrs.insert("value".to_string(), "abc".to_string());
+    let mut root = Node::elem(
+        "body".to_string(),
+        HashMap::new(),
+        vec![
+            Node::elem("input".to_string(), attrs, vec![]),
+            Node::elem(
+                "textarea".to_string(),
+                HashMap::new(),
+                vec![Node::text("line".to_string())],
+            ),
+        ],
+    );
+    assert_eq!(root.children[0].layout_type(), LayoutType::TextInput);
+    assert_eq!(root.children[0].text_input_value(), "abc");
+    assert_eq!(root.children[1].text_input_value(), "line");
+
+    // body(0), input(1), textarea(2), #text(3)
+    root.find_node_by_id_mut(1)
+        .unwrap()
+        .set_text_input_value("abcd".to_string());
+    root.find_node_by_id_mut(2)
+        .unwrap()
+        .set_text_input_value("two\nlines".to_string());
+    assert_eq!(root.children[0].text_input_value(), "abcd");
+    assert_eq!(root.children[1].text_input_value(), "two\nlines");
+    assert_eq!(root.count_nodes(), 4);
+    assert!(root.find_node_by_id_mut(4).is_none());
+}
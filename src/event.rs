@@ -0,0 +1,96 @@
+//! DOM events: the input of the window (a click, the pointer moving onto an element, a key
+//! press) dispatched to the listeners scripts add with ``addEventListener``.
+//!
+//! An event goes from ``window`` and ``document`` down the ancestors of its target (capturing),
+//! to the target, and back up (bubbling). See ``script::Runtime::dispatch_event``.
+
+use dom::{Node, NodeType};
+
+/// The ``eventPhase`` of an event whose listeners are being called
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    None = 0,
+    Capturing = 1,
+    AtTarget = 2,
+    Bubbling = 3,
+}
+
+/// What happened to an event dispatched to the page
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Dispatched {
+    /// A listener canceled it, so its default action (e.g. following a link) is not done
+    pub canceled: bool,
+    /// The listeners changed the DOM, which has to be laid out and painted again, or drew on a
+    /// canvas
+    pub dom_changed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// The type, e.g. ``click``
+    pub kind: String,
+    /// The path of child indices from the root to the element the event is for
+    pub target: Vec<usize>,
+    /// The point in the document (CSS px) for mouse events
+    pub client_x: f64,
+    pub client_y: f64,
+    /// The ``key`` of key events, e.g. ``a`` or ``Enter``
+    pub key: String,
+}
+
+impl Event {
+    /// A mouse event (``click`` or ``mouseover``) at (``x``, ``y``) in the document.
+    pub fn mouse(kind: &str, target: Vec<usize>, x: f64, y: f64) -> Event {
+        Event {
+            kind: kind.to_string(),
+            target: target,
+            client_x: x,
+            client_y: y,
+            key: String::new(),
+        }
+    }
+
+    /// A key event (``keydown``) of ``key``.
+    pub fn key(kind: &str, target: Vec<usize>, key: &str) -> Event {
+        Event {
+            kind: kind.to_string(),
+            target: target,
+            client_x: 0.0,
+            client_y: 0.0,
+            key: key.to_string(),
+        }
+    }
+
+    /// An event of nothing but its target, e.g. ``change``.
+    pub fn new(kind: &str, target: Vec<usize>) -> Event {
+        Event::key(kind, target, "")
+    }
+
+    /// The elements the event goes through, from the root to the target.
+    pub fn propagation_path(&self) -> Vec<Vec<usize>> {
+        (0..self.target.len() + 1)
+            .map(|len| self.target[..len].to_vec())
+            .collect()
+    }
+}
+
+/// The path of the ``<body>`` of ``document``, which key events go to when nothing is focused,
+/// or of the root if there is none.
+pub fn body_path(document: &Node) -> Vec<usize> {
+    document
+        .children
+        .iter()
+        .position(|child| match child.data {
+            NodeType::Element(ref e) => e.tag_name == "body",
+            NodeType::Text(_) => false,
+        })
+        .map_or(vec![], |i| vec![i])
+}
+
+#[test]
+fn test_propagation_path() {
+    let event = Event::mouse("click", vec![1, 0], 0.0, 0.0);
+    assert_eq!(event.propagation_path(), vec![vec![], vec![1], vec![1, 0]]);
+    let event = Event::key("keydown", vec![], "a");
+    assert_eq!(event.propagation_path(), vec![Vec::<usize>::new()]);
+}
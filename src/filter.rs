@@ -0,0 +1,374 @@
+//! Filter effects: ``filter: blur() grayscale() brightness() drop-shadow()``.
+//! ref. https://www.w3.org/TR/filter-effects-1/#FilterProperty
+//!
+//! A box with a filter is painted (with its descendants) into a layer of its own (between
+//! ``DisplayCommand::PushLayer`` and ``PopLayer``), and the filters are applied to the pixels of
+//! the layer, one after the other, before it is painted over what is under it.
+
+use css::{Color, Unit, Value};
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    /// A gaussian blur of the standard deviation (px)
+    Blur(f64),
+    /// 0 (unchanged) to 1 (completely gray)
+    Grayscale(f64),
+    /// A factor of the colors: 0 is black, and 1 is unchanged.
+    Brightness(f64),
+    /// A copy of the layer in ``color``, blurred by ``blur`` (px, twice the standard deviation)
+    /// and moved by ``offset`` (px), under it
+    DropShadow {
+        offset: (f64, f64),
+        blur: f64,
+        color: Color,
+    },
+}
+
+impl Filter {
+    /// A filter function, e.g. ``blur(2px)``. ``current_color`` is the color of a shadow that
+    /// does not have one.
+    pub fn parse(value: &Value, current_color: Color) -> Option<Filter> {
+        let (name, args) = match *value {
+            Value::Function(ref name, ref args) => (name.as_str(), args),
+            _ => return None,
+        };
+        // A number or a percentage, and 1 if omitted
+        let amount = || match args.first() {
+            Some(&Value::Length(percent, Unit::Percent)) => Some(percent / 100.0),
+            Some(&Value::Num(amount)) => Some(amount),
+            Some(_) => None,
+            None => Some(1.0),
+        };
+        match name {
+            "blur" => {
+                let blur = args.first().map_or(Some(0.0), |arg| arg.to_px())?;
+                Some(Filter::Blur(blur.max(0.0)))
+            }
+            "grayscale" => Some(Filter::Grayscale(amount()?.clamp(0.0, 1.0))),
+            "brightness" => Some(Filter::Brightness(amount()?.max(0.0))),
+            "drop-shadow" => {
+                let lengths: Vec<f64> = args.iter().filter_map(|arg| arg.to_px()).collect();
+                if lengths.len() < 2 {
+                    return None;
+                }
+                Some(Filter::DropShadow {
+                    offset: (lengths[0], lengths[1]),
+                    blur: lengths.get(2).cloned().unwrap_or(0.0).max(0.0),
+                    color: args.iter()
+                        .find_map(|arg| arg.to_color())
+                        .unwrap_or(current_color),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// How far (px) out of what is painted in the layer the filter paints
+    pub fn outset(&self) -> f64 {
+        match *self {
+            // Beyond 3 standard deviations, a blur is not visible.
+            Filter::Blur(deviation) => 3.0 * deviation,
+            Filter::DropShadow {
+                offset: (x, y),
+                blur,
+                ..
+            } => x.abs().max(y.abs()) + 1.5 * blur,
+            Filter::Grayscale(_) | Filter::Brightness(_) => 0.0,
+        }
+    }
+}
+
+/// As a CSS filter function
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Filter::Blur(deviation) => write!(f, "blur({}px)", deviation),
+            Filter::Grayscale(amount) => write!(f, "grayscale({})", amount),
+            Filter::Brightness(amount) => write!(f, "brightness({})", amount),
+            Filter::DropShadow {
+                offset: (x, y),
+                blur,
+                color,
+            } => write!(
+                f,
+                "drop-shadow({}px {}px {}px rgba({}, {}, {}, {}))",
+                x,
+                y,
+                blur,
+                color.r,
+                color.g,
+                color.b,
+                color.a as f64 / 255.0
+            ),
+        }
+    }
+}
+
+/// Applies ``filters`` to an image of ``width`` x ``height`` pixels of premultiplied ARGB in
+/// ``data`` (a ``u32`` in the byte order of the machine a pixel, and ``stride`` bytes a row, like
+/// a cairo image surface), where a CSS px is ``scale`` pixels.
+pub fn apply(
+    filters: &[Filter],
+    data: &mut [u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    scale: f64,
+) {
+    if filters.is_empty() || width == 0 || height == 0 {
+        return;
+    }
+    let mut image = Image::read(data, width, height, stride);
+    for filter in filters {
+        match *filter {
+            Filter::Blur(deviation) => image.blur(deviation * scale),
+            Filter::Grayscale(amount) => image.grayscale(amount),
+            Filter::Brightness(amount) => image.brightness(amount),
+            Filter::DropShadow {
+                offset: (x, y),
+                blur,
+                color,
+            } => {
+                let offset = ((x * scale).round() as isize, (y * scale).round() as isize);
+                image.drop_shadow(offset, blur / 2.0 * scale, color)
+            }
+        }
+    }
+    image.write(data, stride);
+}
+
+/// Premultiplied RGBA pixels (0 to 255), a row after another
+struct Image {
+    pixels: Vec<[f32; 4]>,
+    width: usize,
+    height: usize,
+}
+
+impl Image {
+    fn read(data: &[u8], width: usize, height: usize, stride: usize) -> Image {
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in data.chunks(stride).take(height) {
+            for pixel in row.chunks(4).take(width) {
+                let argb = u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                let channel = |shift: u32| ((argb >> shift) & 0xff) as f32;
+                pixels.push([channel(16), channel(8), channel(0), channel(24)]);
+            }
+        }
+        Image {
+            pixels: pixels,
+            width: width,
+            height: height,
+        }
+    }
+
+    fn write(&self, data: &mut [u8], stride: usize) {
+        for (row, pixels) in data.chunks_mut(stride)
+            .zip(self.pixels.chunks(self.width))
+        {
+            for (pixel, &[r, g, b, a]) in row.chunks_mut(4).zip(pixels) {
+                let a = a.round().clamp(0.0, 255.0);
+                // A premultiplied color is not more than the alpha.
+                let channel = |c: f32| c.round().clamp(0.0, a) as u32;
+                let argb = (a as u32) << 24 | channel(r) << 16 | channel(g) << 8 | channel(b);
+                pixel.copy_from_slice(&argb.to_ne_bytes());
+            }
+        }
+    }
+
+    /// ref. https://www.w3.org/TR/filter-effects-1/#grayscaleEquivalent
+    fn grayscale(&mut self, amount: f64) {
+        let rest = (1.0 - amount) as f32;
+        let (r, g, b) = (0.2126, 0.7152, 0.0722);
+        let matrix = [
+            [r + (1.0 - r) * rest, g - g * rest, b - b * rest],
+            [r - r * rest, g + (1.0 - g) * rest, b - b * rest],
+            [r - r * rest, g - g * rest, b + (1.0 - b) * rest],
+        ];
+        // The matrix is linear, so it applies to premultiplied colors as well.
+        for pixel in &mut self.pixels {
+            let color = [pixel[0], pixel[1], pixel[2]];
+            for (channel, row) in pixel.iter_mut().zip(&matrix) {
+                *channel = row[0] * color[0] + row[1] * color[1] + row[2] * color[2];
+            }
+        }
+    }
+
+    fn brightness(&mut self, amount: f64) {
+        for pixel in &mut self.pixels {
+            let alpha = pixel[3];
+            for channel in pixel.iter_mut().take(3) {
+                *channel = (*channel * amount as f32).min(alpha);
+            }
+        }
+    }
+
+    /// A gaussian blur of ``deviation`` (pixels), approximated by three box blurs in each
+    /// direction. ref. https://www.w3.org/TR/filter-effects-1/#feGaussianBlurElement
+    fn blur(&mut self, deviation: f64) {
+        let size = (deviation * 3.0 * (2.0 * ::std::f64::consts::PI).sqrt() / 4.0 + 0.5).floor();
+        let radius = (size / 2.0) as usize;
+        if radius == 0 {
+            return;
+        }
+        for _ in 0..3 {
+            self.box_blur(radius, true);
+            self.box_blur(radius, false);
+        }
+    }
+
+    /// Replaces each pixel by the mean of the ``2 * radius + 1`` pixels around it in a row (or a
+    /// column if not ``horizontal``). Out of the image is transparent.
+    fn box_blur(&mut self, radius: usize, horizontal: bool) {
+        let (lines, length) = if horizontal {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        };
+        let (width, size) = (self.width, (2 * radius + 1) as f32);
+        let index = |line: usize, i: usize| {
+            if horizontal {
+                line * width + i
+            } else {
+                i * width + line
+            }
+        };
+        let mut line_pixels = vec![[0.0; 4]; length];
+        for line in 0..lines {
+            for (i, pixel) in line_pixels.iter_mut().enumerate() {
+                *pixel = self.pixels[index(line, i)];
+            }
+            let mut sum = [0.0f32; 4];
+            for pixel in line_pixels.iter().take(radius) {
+                add(&mut sum, pixel, 1.0);
+            }
+            for i in 0..length {
+                if let Some(entering) = line_pixels.get(i + radius) {
+                    add(&mut sum, entering, 1.0);
+                }
+                if i > radius {
+                    add(&mut sum, &line_pixels[i - radius - 1], -1.0);
+                }
+                let pixel = &mut self.pixels[index(line, i)];
+                for (channel, sum) in pixel.iter_mut().zip(&sum) {
+                    *channel = sum / size;
+                }
+            }
+        }
+    }
+
+    /// ref. https://www.w3.org/TR/filter-effects-1/#dropshadowEquivalent
+    fn drop_shadow(&mut self, offset: (isize, isize), deviation: f64, color: Color) {
+        let alpha = color.a as f32 / 255.0;
+        let color = [
+            color.r as f32 * alpha,
+            color.g as f32 * alpha,
+            color.b as f32 * alpha,
+            color.a as f32,
+        ];
+        let mut shadow = Image {
+            pixels: vec![[0.0; 4]; self.pixels.len()],
+            width: self.width,
+            height: self.height,
+        };
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (from_x, from_y) = (x as isize - offset.0, y as isize - offset.1);
+                if from_x < 0 || from_y < 0 {
+                    continue;
+                }
+                let (from_x, from_y) = (from_x as usize, from_y as usize);
+                if from_x >= self.width || from_y >= self.height {
+                    continue;
+                }
+                let coverage = self.pixels[from_y * self.width + from_x][3] / 255.0;
+                for (channel, color) in shadow.pixels[y * self.width + x].iter_mut().zip(&color) {
+                    *channel = color * coverage;
+                }
+            }
+        }
+        shadow.blur(deviation);
+        // The layer over its shadow
+        for (pixel, shadow) in self.pixels.iter_mut().zip(&shadow.pixels) {
+            let rest = 1.0 - pixel[3] / 255.0;
+            add(pixel, shadow, rest);
+        }
+    }
+}
+
+fn add(sum: &mut [f32; 4], pixel: &[f32; 4], factor: f32) {
+    for (sum, channel) in sum.iter_mut().zip(pixel) {
+        *sum += channel * factor;
+    }
+}
+
+#[test]
+fn test_filters() {
+    use css::{self, BLACK, RED};
+
+    let filters = |src: &str| -> Vec<Filter> {
+        let values = css::parse(format!("a {{ filter: {} }}", src)).rules[0].declarations[0]
+            .values
+            .clone();
+        values.iter().filter_map(|v| Filter::parse(v, BLACK)).collect()
+    };
+    assert_eq!(
+        filters("blur(2px) grayscale(50%) brightness(1.5)"),
+        vec![Filter::Blur(2.0), Filter::Grayscale(0.5), Filter::Brightness(1.5)]
+    );
+    assert_eq!(filters("grayscale() brightness(200%)"), vec![
+        Filter::Grayscale(1.0),
+        Filter::Brightness(2.0),
+    ]);
+    let shadow = Filter::DropShadow {
+        offset: (2.0, 3.0),
+        blur: 4.0,
+        color: RED,
+    };
+    assert_eq!(filters("drop-shadow(2px 3px 4px red)"), vec![shadow.clone()]);
+    assert_eq!(filters("drop-shadow(red 2px 3px 4px)"), vec![shadow.clone()]);
+    assert_eq!(shadow.to_string(), "drop-shadow(2px 3px 4px rgba(255, 0, 0, 1))");
+    assert_eq!(shadow.outset(), 9.0);
+    assert_eq!(filters("none"), vec![]);
+
+    // 3x1 pixels: opaque red, half transparent white, transparent
+    let pixel = |argb: u32| argb.to_ne_bytes().to_vec();
+    let image = || [pixel(0xffff0000), pixel(0x80808080), pixel(0)].concat();
+    let pixels = |data: &[u8]| -> Vec<u32> {
+        data.chunks(4)
+            .map(|p| u32::from_ne_bytes([p[0], p[1], p[2], p[3]]))
+            .collect()
+    };
+    let filtered = |filters: &[Filter]| {
+        let mut data = image();
+        apply(filters, &mut data, 3, 1, 12, 1.0);
+        pixels(&data)
+    };
+    assert_eq!(filtered(&[]), pixels(&image()));
+    assert_eq!(filtered(&[Filter::Brightness(0.0)]), vec![0xff000000, 0x80000000, 0]);
+    // Not brighter than white
+    assert_eq!(filtered(&[Filter::Brightness(2.0)])[1], 0x80808080);
+    assert_eq!(filtered(&[Filter::Grayscale(1.0)])[0], 0xff363636);
+    assert_eq!(filtered(&[Filter::Grayscale(0.0)]), pixels(&image()));
+
+    // A blur spreads the alpha, and keeps the total.
+    let mut data = vec![0; 9 * 9 * 4];
+    let center = (4 * 9 + 4) * 4;
+    data[center..center + 4].copy_from_slice(&pixel(0xffffffff));
+    apply(&[Filter::Blur(1.0)], &mut data, 9, 9, 9 * 4, 1.0);
+    let alphas: Vec<u32> = pixels(&data).iter().map(|p| p >> 24).collect();
+    assert!(alphas[4 * 9 + 4] < 0xff && alphas[4 * 9 + 3] > 0);
+    assert_eq!(alphas[4 * 9 + 5], alphas[4 * 9 + 3]);
+    assert_eq!(alphas[5 * 9 + 4], alphas[4 * 9 + 3]);
+    let total: u32 = alphas.iter().sum();
+    assert!((0xff - 40..=0xff + 40).contains(&total));
+
+    // The shadow of the red pixel, one pixel to the right, under the white one
+    let shadow = Filter::DropShadow {
+        offset: (1.0, 0.0),
+        blur: 0.0,
+        color: BLACK,
+    };
+    assert_eq!(filtered(&[shadow]), vec![0xffff0000, 0xff808080, 0x80000000]);
+}
@@ -0,0 +1,892 @@
+//! Flex layout: the items of a flex container (``display: flex``) are laid out one after the
+//! other in a line along its main axis (by ``flex-direction``, the width of rows, or the height of
+//! columns), each as large as its ``flex-basis`` (or its ``width`` or ``height``, or its content),
+//! grown or shrunk by ``flex-grow`` and ``flex-shrink`` so that they fill the container with a gap
+//! between them, and along the cross axis as large as the line, the largest of them.
+//! ref. https://www.w3.org/TR/css-flexbox-1/#layout-algorithm
+//!
+//! With ``flex-wrap``, the items are broken into as many lines as they need, with a gap between
+//! them, which share the cross size of the container by ``align-content``.
+//!
+//! The items that do not fill their line are placed in the space left by ``justify-content``, and
+//! across the line by ``align-items`` (or their ``align-self``).
+//!
+//! The items are placed in the lines by their ``order``, but are kept in the order of the document
+//! among the children of the container, which is painted (and focused) in that order.
+//!
+//! The items are blocks (see ``layout::blockify``), or the anonymous blocks of the texts between
+//! them, and are laid out apart from the floats outside the container.
+
+use css::{Unit, Value};
+use float::Floats;
+use layout::{BoxType, Dimensions, LayoutBox, Rect, Text};
+use style::{ContentDistribution, FlexWrap, ItemAlignment};
+
+use std::cmp::{max, min};
+
+use app_units::Au;
+
+/// An item of a flex container being sized
+#[derive(Clone, Debug)]
+struct FlexItem {
+    /// Which child of the container it is
+    index: usize,
+    /// The size of its margins, borders and paddings along the main axis
+    edges: Au,
+    /// The size of its content box along the main axis before it is grown or shrunk
+    base_size: Au,
+    /// The size of its content box along the main axis
+    main_size: Au,
+    grow: f64,
+    shrink: f64,
+    /// How it is placed across its line
+    alignment: ItemAlignment,
+    /// In a column, the width of its margin box before it is stretched
+    column_width: Option<Au>,
+    /// Where its margin box is along the main axis of its line
+    main_position: Au,
+}
+
+impl FlexItem {
+    /// The size of its margin box along the main axis
+    fn outer_size(&self) -> Au {
+        self.main_size + self.edges
+    }
+}
+
+/// A line of the items of a flex container
+#[derive(Clone, Debug, Default)]
+struct FlexLine {
+    items: Vec<FlexItem>,
+    /// Its size along the cross axis
+    cross_size: Au,
+    /// Where it is along the cross axis of the content box of the container
+    cross_offset: Au,
+    /// Where the baselines of its items aligned by them are from its top
+    baseline: Au,
+}
+
+impl LayoutBox {
+    /// Lays out the children of a flex container (see the module doc) in its content box, and
+    /// makes it as high as them. ``saved_block`` is the containing block of the container, and
+    /// ``percentage_block`` that of the percentage heights of the items.
+    pub fn layout_flex_items(
+        &mut self,
+        saved_block: Dimensions,
+        percentage_block: Dimensions,
+        viewport: Dimensions,
+    ) {
+        let container = self.dimensions;
+        let width = container.content.width;
+        let definite_height = self.definite_height(saved_block);
+        let direction = self.property.flex_direction();
+        let column = direction.is_column();
+        let (row_gap, column_gap) = self.property.gap(width, percentage_block.content.height);
+        let (main_gap, cross_gap) = if column {
+            (row_gap, column_gap)
+        } else {
+            (column_gap, row_gap)
+        };
+        // The sizes of the content box along the axes, negative or None if they are indefinite
+        let main_available = if column {
+            definite_height.unwrap_or(Au(-1))
+        } else {
+            width
+        };
+        let cross_available = if column {
+            Some(width).filter(|width| *width >= Au(0))
+        } else {
+            definite_height
+        };
+        let wrap = self.property.flex_wrap();
+        let justify_content = self.property.justify_content();
+        let align_items = self.property.align_items();
+
+        let mut items = vec![];
+        for (index, child) in self.children.iter_mut().enumerate() {
+            let alignment = match child.property.align_self().unwrap_or(align_items) {
+                // The lines of a column are not across it.
+                ItemAlignment::Baseline if column => ItemAlignment::Start,
+                alignment => alignment,
+            };
+            // Stretched as wide as a single column in the first place
+            let stretched = wrap == FlexWrap::NoWrap && alignment == ItemAlignment::Stretch;
+            let column_width = if column {
+                Some(child.column_width(stretched, container, percentage_block, viewport))
+            } else {
+                None
+            };
+            let mut item = child.flex_item(
+                index,
+                container,
+                column_width,
+                percentage_block,
+                viewport,
+            );
+            item.alignment = alignment;
+            items.push(item);
+        }
+        // Stable, the items of the same ``order`` in the order of the document
+        items.sort_by_key(|item| self.children[item.index].property.order());
+        let mut lines = match wrap {
+            FlexWrap::NoWrap => vec![FlexLine {
+                items: items,
+                ..FlexLine::default()
+            }],
+            FlexWrap::Wrap | FlexWrap::WrapReverse => {
+                break_lines(items, main_available, main_gap)
+            }
+        };
+
+        let mut main_size = Au(0);
+        for line in &mut lines {
+            resolve_flexible_lengths(&mut line.items, main_available, main_gap);
+            let used = line.items
+                .iter()
+                .fold(main_gap * (line.items.len() as i32 - 1), |used, item| {
+                    used + item.outer_size()
+                });
+            main_size = max(main_size, used);
+            let free = if main_available >= Au(0) {
+                main_available - used
+            } else {
+                Au(0)
+            };
+            let (mut position, between) = distribute(justify_content, free, line.items.len());
+            let (mut above_baseline, mut under_baseline) = (Au(0), Au(0));
+            for item in &mut line.items {
+                item.main_position = position;
+                position += item.outer_size() + main_gap + between;
+                let child = &mut self.children[item.index];
+                child.lay_out_flex_item(item, container, None, percentage_block, viewport);
+                let (_, cross_size) = main_cross(child.dimensions.margin_box(), column);
+                if item.alignment == ItemAlignment::Baseline {
+                    let baseline = child.item_baseline();
+                    above_baseline = max(above_baseline, baseline);
+                    under_baseline = max(under_baseline, cross_size - baseline);
+                }
+                line.cross_size = max(line.cross_size, cross_size);
+            }
+            line.cross_size = max(line.cross_size, above_baseline + under_baseline);
+            line.baseline = above_baseline;
+        }
+        if main_available >= Au(0) {
+            main_size = main_available;
+        }
+        // A single line is as large as the container if its size does not depend on the items.
+        if let (FlexWrap::NoWrap, Some(size)) = (wrap, cross_available) {
+            lines[0].cross_size = size;
+        }
+
+        let cross_size = align_lines(
+            &mut lines,
+            self.property.align_content(),
+            cross_available,
+            cross_gap,
+            wrap == FlexWrap::WrapReverse,
+        );
+        for line in &lines {
+            for item in &line.items {
+                let child = &mut self.children[item.index];
+                let (_, size) = main_cross(child.dimensions.margin_box(), column);
+                let free = line.cross_size - size;
+                let cross_offset = match item.alignment {
+                    ItemAlignment::Stretch => {
+                        child.stretch_item(
+                            item,
+                            line.cross_size,
+                            container,
+                            percentage_block,
+                            viewport,
+                        );
+                        Au(0)
+                    }
+                    ItemAlignment::Start => Au(0),
+                    ItemAlignment::End => free,
+                    ItemAlignment::Center => free / 2,
+                    ItemAlignment::Baseline => line.baseline - child.item_baseline(),
+                };
+                let main_offset = if direction.is_reverse() {
+                    main_size - item.main_position - item.outer_size()
+                } else {
+                    item.main_position
+                };
+                let (x, y) = if column {
+                    (line.cross_offset + cross_offset, main_offset)
+                } else {
+                    (main_offset, line.cross_offset + cross_offset)
+                };
+                child.dimensions.content.x += x;
+                child.dimensions.content.y += y;
+            }
+        }
+        self.dimensions.content.height = if column { main_size } else { cross_size };
+    }
+
+    /// The item of the box, the ``index``th child of ``container``, of its flex factors and its
+    /// base size. ``column_width`` is the width it is laid out in if the container is a column.
+    fn flex_item(
+        &mut self,
+        index: usize,
+        container: Dimensions,
+        column_width: Option<Au>,
+        percentage_block: Dimensions,
+        viewport: Dimensions,
+    ) -> FlexItem {
+        let (grow, shrink, basis) = self.property.flex();
+        let container_width = container.content.width;
+        // The percentages of a column are of the height of the container.
+        let containing_size = match column_width {
+            Some(_) => percentage_block.content.height,
+            None => container_width,
+        };
+        let size = |value: &Value| match *value {
+            Value::Length(_, Unit::Percent) if containing_size < Au(0) => None,
+            ref value => value
+                .maybe_percent_to_px(containing_size.to_f64_px())
+                .map(|px| max(Au::from_f64_px(px), Au(0))),
+        };
+        // ``auto`` is the ``width`` (or the ``height``), and ``content`` (or ``auto``) the size of
+        // the content.
+        let specified = match basis {
+            Value::Keyword(ref k) if k == "auto" => match column_width {
+                Some(_) => self.definite_height(percentage_block),
+                None => self.specified_width(container_width),
+            },
+            ref basis => size(basis),
+        };
+        let base_size = match (specified, column_width) {
+            (Some(size), _) => size,
+            (None, None) => self.max_content_width(container, percentage_block, viewport),
+            (None, Some(width)) => {
+                let mut layout_box = self.clone();
+                let mut area = container;
+                area.content.width = width;
+                layout_box.property.property.remove("height");
+                layout_box.layout_item(area, percentage_block, viewport);
+                layout_box.dimensions.content.height
+            }
+        };
+        let edges = match column_width {
+            Some(_) => self.vertical_edges(container_width),
+            None => self.horizontal_edges(container_width),
+        };
+        FlexItem {
+            index: index,
+            edges: edges,
+            base_size: base_size,
+            main_size: base_size,
+            grow: grow,
+            shrink: shrink,
+            alignment: ItemAlignment::Stretch,
+            column_width: column_width,
+            main_position: Au(0),
+        }
+    }
+
+    /// The width of the margin box of the item (the box) in a column of ``container``: that of its
+    /// ``width``, or the width of the container if it is ``stretched``, or of its content.
+    fn column_width(
+        &mut self,
+        stretched: bool,
+        container: Dimensions,
+        percentage_block: Dimensions,
+        viewport: Dimensions,
+    ) -> Au {
+        let container_width = container.content.width;
+        let edges = self.horizontal_edges(container_width);
+        if let Some(width) = self.specified_width(container_width) {
+            return width + edges;
+        }
+        if stretched && container_width >= Au(0) {
+            return container_width;
+        }
+        let width = self.max_content_width(container, percentage_block, viewport) + edges;
+        if container_width >= Au(0) {
+            min(width, container_width)
+        } else {
+            width
+        }
+    }
+
+    /// The content width of the box by its ``width``, None if it is ``auto`` (or a percentage of
+    /// an indefinite ``containing_width``)
+    fn specified_width(&self, containing_width: Au) -> Option<Au> {
+        match *self.property.value("width")?.first()? {
+            Value::Length(_, Unit::Percent) if containing_width < Au(0) => None,
+            ref value => value
+                .maybe_percent_to_px(containing_width.to_f64_px())
+                .map(|px| max(Au::from_f64_px(px), Au(0))),
+        }
+    }
+
+    /// Lays out the item (the box) at the top left of the content box of ``container``, as large
+    /// as ``item`` along the main axis, and as wide as ``stretched_width`` (its margin box) in a
+    /// column if it is stretched.
+    fn lay_out_flex_item(
+        &mut self,
+        item: &FlexItem,
+        container: Dimensions,
+        stretched_width: Option<Au>,
+        percentage_block: Dimensions,
+        viewport: Dimensions,
+    ) {
+        let mut area = container;
+        match item.column_width {
+            None => {
+                // As wide as its margin box is in the line, whatever its ``width``
+                area.content.width = item.outer_size();
+                let width = self.property.property.remove("width");
+                self.layout_item(area, percentage_block, viewport);
+                if let Some(width) = width {
+                    self.property.property.insert("width".to_string(), width);
+                }
+            }
+            Some(width) => {
+                area.content.width = stretched_width.unwrap_or(width);
+                self.layout_item(area, percentage_block, viewport);
+                // As high as its content box is in the line, whatever its ``height``
+                self.dimensions.content.height = item.main_size;
+            }
+        }
+    }
+
+    /// Makes the item (laid out) as large as ``cross_size`` (its margin box) along the cross axis
+    /// of ``container`` if its size is ``auto``, as ``align-self: stretch`` does.
+    fn stretch_item(
+        &mut self,
+        item: &FlexItem,
+        cross_size: Au,
+        container: Dimensions,
+        percentage_block: Dimensions,
+        viewport: Dimensions,
+    ) {
+        match item.column_width {
+            None => self.stretch_height(cross_size, percentage_block),
+            Some(width) => {
+                let auto = self.specified_width(container.content.width).is_none();
+                if auto && width != cross_size && !self.is_replaced() {
+                    self.lay_out_flex_item(
+                        item,
+                        container,
+                        Some(cross_size),
+                        percentage_block,
+                        viewport,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Where the baseline of the first line of the item (laid out) is from the top of its margin
+    /// box, or the bottom of its border box if it has no line
+    fn item_baseline(&self) -> Au {
+        let d = self.dimensions;
+        match self.first_baseline() {
+            Some(baseline) => d.margin.top + d.border.top + d.padding.top + baseline,
+            None => d.margin_box().height - d.margin.bottom,
+        }
+    }
+
+    /// Where the baseline of the first text in the box (laid out) is from the top of its content
+    /// box, None if there is no text
+    fn first_baseline(&self) -> Option<Au> {
+        self.children.iter().find_map(|child| {
+            let baseline = match child.box_type {
+                BoxType::TextNode(Text { ref font, .. }) => Some(font.get_ascent_descent().0),
+                _ => child.first_baseline(),
+            };
+            baseline.map(|baseline| child.dimensions.content.y + baseline)
+        })
+    }
+
+    /// Lays out the box as an item of a flex or grid container at the top left of ``area`` (the
+    /// width of its margin box, or of the containing block of an item whose ``width`` is not
+    /// ``auto``), apart from the floats outside the container.
+    pub fn layout_item(
+        &mut self,
+        area: Dimensions,
+        percentage_block: Dimensions,
+        viewport: Dimensions,
+    ) {
+        let mut containing_block = area;
+        containing_block.content.height = Au(0);
+        self.layout(
+            &mut Floats::new(),
+            Au(0),
+            containing_block,
+            percentage_block,
+            viewport,
+        );
+    }
+
+    /// The content width of the box laid out as wide as its content, as a float whose width is
+    /// ``auto`` is (see ``shrink_to_fit_width``), in ``containing_block``.
+    pub fn max_content_width(
+        &self,
+        containing_block: Dimensions,
+        percentage_block: Dimensions,
+        viewport: Dimensions,
+    ) -> Au {
+        let mut layout_box = self.clone();
+        layout_box.floats = Floats::new();
+        if layout_box.box_type == BoxType::AnonymousBlock {
+            // The lines are as long as their texts (see ``LineMaker::intrinsic_width``).
+            let mut containing_block = containing_block;
+            containing_block.content.width = Au(-1);
+            layout_box.layout_item(containing_block, percentage_block, viewport);
+            return layout_box.dimensions.content.width;
+        }
+        if layout_box.is_replaced() {
+            layout_box.layout_item(containing_block, percentage_block, viewport);
+            return layout_box.dimensions.content.width;
+        }
+        layout_box.assign_padding();
+        layout_box.assign_border_width();
+        layout_box.assign_margin();
+        layout_box.dimensions.content.width = Au(-1);
+        layout_box.layout_block_children(percentage_block, viewport);
+        layout_box.shrink_to_fit_width()
+    }
+
+    /// The width of the margins, the borders and the paddings on the left and the right of the
+    /// box, of its percentages of ``containing_width`` (0 if it is indefinite). ``auto`` margins
+    /// are 0.
+    pub fn horizontal_edges(&mut self, containing_width: Au) -> Au {
+        let (_, margin_right, _, margin_left) = self.property.margin();
+        let (_, padding_right, _, padding_left) = self.property.padding();
+        let (_, border_right, _, border_left) = self.property.border_width();
+        let px = |value: &Value| {
+            value
+                .maybe_percent_to_px(max(containing_width, Au(0)).to_f64_px())
+                .unwrap_or(0.0)
+        };
+        Au::from_f64_px(
+            px(&margin_left) + px(&margin_right) + px(&padding_left) + px(&padding_right)
+                + px(&border_left) + px(&border_right),
+        )
+    }
+
+    /// The height of the margins, the borders and the paddings on the top and the bottom of the
+    /// box, of its percentages of ``containing_width`` (0 if it is indefinite). ``auto`` margins
+    /// are 0.
+    pub fn vertical_edges(&mut self, containing_width: Au) -> Au {
+        let (margin_top, _, margin_bottom, _) = self.property.margin();
+        let (padding_top, _, padding_bottom, _) = self.property.padding();
+        let (border_top, _, border_bottom, _) = self.property.border_width();
+        let px = |value: &Value| {
+            value
+                .maybe_percent_to_px(max(containing_width, Au(0)).to_f64_px())
+                .unwrap_or(0.0)
+        };
+        Au::from_f64_px(
+            px(&margin_top) + px(&margin_bottom) + px(&padding_top) + px(&padding_bottom)
+                + px(&border_top) + px(&border_bottom),
+        )
+    }
+
+    /// Makes the item as high as ``height`` (its margin box), if its height is ``auto``, as
+    /// ``align-self: stretch`` does. ``percentage_block`` is the containing block of its
+    /// percentage height.
+    pub fn stretch_height(&mut self, height: Au, percentage_block: Dimensions) {
+        if self.is_replaced() || self.definite_height(percentage_block).is_some() {
+            return;
+        }
+        let d = &mut self.dimensions;
+        let edges = d.margin_box().height - d.content.height;
+        d.content.height = max(d.content.height, height - edges);
+    }
+}
+
+/// The sizes of ``rect`` along the main axis and the cross axis of a row, or a ``column``
+fn main_cross(rect: Rect, column: bool) -> (Au, Au) {
+    if column {
+        (rect.height, rect.width)
+    } else {
+        (rect.width, rect.height)
+    }
+}
+
+/// Breaks the ``items`` into lines in which their margin boxes (as large as their base sizes)
+/// and the ``gap``s between them fit in ``available`` px, with one item at least in each.
+fn break_lines(items: Vec<FlexItem>, available: Au, gap: Au) -> Vec<FlexLine> {
+    let mut lines = vec![FlexLine::default()];
+    let mut width = Au(0);
+    for item in items {
+        let size = item.base_size + item.edges;
+        let first = lines.last().map_or(true, |line| line.items.is_empty());
+        let overflows = available >= Au(0) && width + gap + size > available;
+        if !first && overflows {
+            lines.push(FlexLine::default());
+        }
+        width = if first || overflows { size } else { width + gap + size };
+        lines.last_mut().unwrap().items.push(item);
+    }
+    lines
+}
+
+/// Places the ``lines`` one after the other with ``gap`` between them, in the space they leave in
+/// ``size`` px (if it is definite) by ``distribution``, from the bottom up if ``reverse``. Returns
+/// the height of them all, or ``size``.
+fn align_lines(
+    lines: &mut [FlexLine],
+    distribution: ContentDistribution,
+    size: Option<Au>,
+    gap: Au,
+    reverse: bool,
+) -> Au {
+    let count = lines.len() as i32;
+    let used = lines
+        .iter()
+        .fold(gap * (count - 1), |used, line| used + line.cross_size);
+    let free = size.map_or(Au(0), |size| size - used);
+    if distribution == ContentDistribution::Stretch && free > Au(0) {
+        for line in lines.iter_mut() {
+            line.cross_size += free / count;
+        }
+    }
+    let (mut offset, between) = distribute(distribution, free, lines.len());
+    let height = size.unwrap_or(used);
+    for line in lines.iter_mut() {
+        line.cross_offset = if reverse {
+            height - offset - line.cross_size
+        } else {
+            offset
+        };
+        offset += line.cross_size + gap + between;
+    }
+    height
+}
+
+/// Where the first of ``count`` boxes distributed by ``distribution`` in the ``free`` px they
+/// leave is, and the space added between each two. The space they overflow by (if ``free`` is
+/// negative) is not distributed: they are at the start, or at the center for ``space-around``
+/// and ``space-evenly``.
+fn distribute(distribution: ContentDistribution, free: Au, count: usize) -> (Au, Au) {
+    let count = count as i32;
+    if count == 0 {
+        return (Au(0), Au(0));
+    }
+    match distribution {
+        ContentDistribution::Start | ContentDistribution::Stretch => (Au(0), Au(0)),
+        ContentDistribution::End => (free, Au(0)),
+        ContentDistribution::Center => (free / 2, Au(0)),
+        ContentDistribution::SpaceBetween if free > Au(0) && count > 1 => {
+            (Au(0), free / (count - 1))
+        }
+        ContentDistribution::SpaceBetween => (Au(0), Au(0)),
+        ContentDistribution::SpaceAround if free > Au(0) => (free / (count * 2), free / count),
+        ContentDistribution::SpaceEvenly if free > Au(0) => {
+            (free / (count + 1), free / (count + 1))
+        }
+        ContentDistribution::SpaceAround | ContentDistribution::SpaceEvenly => (free / 2, Au(0)),
+    }
+}
+
+/// Grows the ``items`` by their ``flex-grow`` into the space of ``available`` px (their margin
+/// boxes and the ``gap``s between them) they leave, or shrinks them by their ``flex-shrink``
+/// (and their base sizes) if they overflow it. The items keep their base sizes in an indefinite
+/// (negative) width.
+/// ref. https://www.w3.org/TR/css-flexbox-1/#resolve-flexible-lengths
+fn resolve_flexible_lengths(items: &mut [FlexItem], available: Au, gap: Au) {
+    if available < Au(0) || items.is_empty() {
+        return;
+    }
+    let used = items
+        .iter()
+        .fold(gap * (items.len() as i32 - 1), |used, item| {
+            used + item.base_size + item.edges
+        });
+    let free_space = (available - used).to_f64_px();
+
+    if free_space > 0.0 {
+        // Only a part of the space is taken if the factors are less than 1 in all.
+        let total: f64 = items.iter().map(|item| item.grow).sum();
+        if total > 0.0 {
+            for item in items.iter_mut() {
+                let grown = free_space * item.grow / total.max(1.0);
+                item.main_size = item.base_size + Au::from_f64_px(grown);
+            }
+        }
+    } else if free_space < 0.0 {
+        // The larger items shrink more.
+        let total: f64 = items
+            .iter()
+            .map(|item| item.shrink * item.base_size.to_f64_px())
+            .sum();
+        if total > 0.0 {
+            for item in items.iter_mut() {
+                let ratio = item.shrink * item.base_size.to_f64_px() / total;
+                let shrunk = item.base_size - Au::from_f64_px(-free_space * ratio);
+                item.main_size = max(shrunk, Au(0));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_flex() {
+    use layout;
+
+    let lay_out = |css_src: &str| {
+        let css_src = format!(
+            ".flex {{ display: flex; width: 300px; }} .item {{ height: 10px; }} {}",
+            css_src
+        );
+        layout::lay_out_html(
+            "<div class=\"flex\"><div class=\"item\" id=\"a\"></div> \
+             <span class=\"item\" id=\"b\"></span> <div class=\"item\" id=\"c\"></div></div>",
+            css_src.as_str(),
+            400.0,
+            0.0,
+        )
+    };
+    let positions = |root: &LayoutBox| -> Vec<(f64, f64)> {
+        root.children
+            .iter()
+            .map(|item| {
+                let border_box = item.dimensions.border_box();
+                (border_box.x.to_f64_px(), border_box.width.to_f64_px())
+            })
+            .collect()
+    };
+
+    // Blocks side by side, the white space between them not laid out
+    let root = lay_out("#a { width: 50px; } #b { width: 20px; } #c { width: 30px; }");
+    assert_eq!(root.children.len(), 3);
+    assert_eq!(
+        positions(&root),
+        vec![(0.0, 50.0), (50.0, 20.0), (70.0, 30.0)]
+    );
+    assert_eq!(root.dimensions.content.height, Au::from_f64_px(10.0));
+
+    // The space left is shared by ``flex-grow``, after the gaps.
+    let root = lay_out(
+        ".flex { column-gap: 10px; } .item { flex: 1; } #b { flex-grow: 2; } #c { flex: none; \
+         width: 10px; }",
+    );
+    assert_eq!(
+        positions(&root),
+        vec![(0.0, 90.0), (100.0, 180.0), (290.0, 10.0)]
+    );
+
+    // Those too wide shrink as much as they are wide.
+    let root = lay_out(
+        "#a { width: 200px; } #b { width: 200px; padding: 0 10px; } #c { flex-shrink: 0; \
+         width: 100px; }",
+    );
+    assert_eq!(
+        positions(&root),
+        vec![(0.0, 90.0), (90.0, 110.0), (200.0, 100.0)]
+    );
+
+    // As high as the line, but those of a height
+    let root = lay_out("#a { height: auto; } #b { height: 30px; }");
+    let heights: Vec<Au> = root.children
+        .iter()
+        .map(|item| item.dimensions.content.height)
+        .collect();
+    let px = Au::from_f64_px;
+    assert_eq!(heights, vec![px(30.0), px(30.0), px(10.0)]);
+}
+
+#[test]
+fn test_flex_wrap() {
+    use layout;
+
+    let lay_out = |css_src: &str| {
+        let css_src = format!(
+            ".flex {{ display: flex; flex-wrap: wrap; width: 300px; gap: 10px; }}
+             #a {{ width: 100px; height: 10px; }} #b {{ width: 150px; }}
+             #c {{ width: 100px; height: 20px; }} {}",
+            css_src
+        );
+        layout::lay_out_html(
+            "<div class=\"flex\"><div id=\"a\"></div><div id=\"b\"></div><div id=\"c\"></div>\
+             </div>",
+            css_src.as_str(),
+            400.0,
+            0.0,
+        )
+    };
+    let positions = |root: &LayoutBox| -> Vec<(f64, f64, f64)> {
+        root.children
+            .iter()
+            .map(|item| {
+                let d = item.dimensions;
+                (
+                    d.content.x.to_f64_px(),
+                    d.content.y.to_f64_px(),
+                    d.content.height.to_f64_px(),
+                )
+            })
+            .collect()
+    };
+
+    // Broken where the next one does not fit, each line as high as its highest item
+    let root = lay_out("");
+    assert_eq!(
+        positions(&root),
+        vec![(0.0, 0.0, 10.0), (110.0, 0.0, 10.0), (0.0, 20.0, 20.0)]
+    );
+    assert_eq!(root.dimensions.content.height, Au::from_f64_px(40.0));
+
+    // The lines stretched to share the height of the container
+    let root = lay_out(".flex { height: 100px; }");
+    assert_eq!(
+        positions(&root),
+        vec![(0.0, 0.0, 10.0), (110.0, 0.0, 40.0), (0.0, 50.0, 20.0)]
+    );
+    let root = lay_out(".flex { height: 100px; align-content: space-between; }");
+    assert_eq!(
+        positions(&root),
+        vec![(0.0, 0.0, 10.0), (110.0, 0.0, 10.0), (0.0, 80.0, 20.0)]
+    );
+
+    // From the bottom up
+    let root = lay_out(".flex { flex-wrap: wrap-reverse; }");
+    assert_eq!(
+        positions(&root),
+        vec![(0.0, 30.0, 10.0), (110.0, 30.0, 10.0), (0.0, 0.0, 20.0)]
+    );
+
+    // A single line is as high as the container.
+    let root = lay_out(".flex { flex-wrap: nowrap; height: 50px; } #b { width: auto; }");
+    assert_eq!(
+        positions(&root),
+        vec![(0.0, 0.0, 10.0), (110.0, 0.0, 50.0), (120.0, 0.0, 20.0)]
+    );
+}
+
+#[test]
+fn test_flex_alignment() {
+    use layout;
+
+    let lay_out = |css_src: &str| {
+        let css_src = format!(
+            ".flex {{ display: flex; width: 300px; }} div {{ width: 60px; }}
+             #a {{ height: 10px; }} #b {{ height: 30px; }} {}",
+            css_src
+        );
+        layout::lay_out_html(
+            "<div class=\"flex\"><div id=\"a\"></div><div id=\"b\"></div><div id=\"c\"></div>\
+             </div>",
+            css_src.as_str(),
+            400.0,
+            0.0,
+        )
+    };
+    let x = |css_src: &str| -> Vec<f64> {
+        lay_out(css_src)
+            .children
+            .iter()
+            .map(|item| item.dimensions.content.x.to_f64_px())
+            .collect()
+    };
+    let y = |css_src: &str| -> Vec<(f64, f64)> {
+        lay_out(css_src)
+            .children
+            .iter()
+            .map(|item| {
+                let content = item.dimensions.content;
+                (content.y.to_f64_px(), content.height.to_f64_px())
+            })
+            .collect()
+    };
+
+    // The 120px left in the line
+    assert_eq!(x(""), vec![0.0, 60.0, 120.0]);
+    assert_eq!(x(".flex { justify-content: flex-end; }"), vec![120.0, 180.0, 240.0]);
+    assert_eq!(x(".flex { justify-content: center; }"), vec![60.0, 120.0, 180.0]);
+    assert_eq!(x(".flex { justify-content: space-between; }"), vec![0.0, 120.0, 240.0]);
+    assert_eq!(x(".flex { justify-content: space-around; }"), vec![20.0, 120.0, 220.0]);
+    assert_eq!(x(".flex { justify-content: space-evenly; }"), vec![30.0, 120.0, 210.0]);
+
+    // In the 30px of the line
+    assert_eq!(y(""), vec![(0.0, 10.0), (0.0, 30.0), (0.0, 30.0)]);
+    assert_eq!(
+        y(".flex { align-items: center; }"),
+        vec![(10.0, 10.0), (0.0, 30.0), (15.0, 0.0)]
+    );
+    assert_eq!(
+        y(".flex { align-items: flex-end; } #c { align-self: stretch; }"),
+        vec![(20.0, 10.0), (0.0, 30.0), (0.0, 30.0)]
+    );
+
+    // The first lines on the same baseline
+    let root = layout::lay_out_html(
+        "<div class=\"flex\"><div id=\"a\">a</div><div id=\"b\">b</div></div>",
+        ".flex { display: flex; align-items: baseline; } #a { font-size: 10px; }
+         #b { font-size: 30px; padding-top: 5px; }",
+        400.0,
+        0.0,
+    );
+    let (a, b) = (&root.children[0], &root.children[1]);
+    assert!(a.dimensions.content.y > b.dimensions.content.y);
+    assert_eq!(
+        a.dimensions.content.y + a.first_baseline().unwrap(),
+        b.dimensions.content.y + b.first_baseline().unwrap()
+    );
+}
+
+#[test]
+fn test_flex_direction() {
+    use layout;
+
+    let lay_out = |css_src: &str| {
+        let css_src = format!(
+            ".flex {{ display: flex; width: 300px; }} #a {{ width: 50px; height: 10px; }}
+             #b {{ width: 20px; height: 20px; }} #c {{ width: 30px; height: 30px; }} {}",
+            css_src
+        );
+        layout::lay_out_html(
+            "<div class=\"flex\"><div id=\"a\"></div><div id=\"b\"></div><div id=\"c\"></div>\
+             </div>",
+            css_src.as_str(),
+            400.0,
+            0.0,
+        )
+    };
+    let positions = |root: &LayoutBox| -> Vec<(f64, f64)> {
+        root.children
+            .iter()
+            .map(|item| {
+                let content = item.dimensions.content;
+                (content.x.to_f64_px(), content.y.to_f64_px())
+            })
+            .collect()
+    };
+
+    // From the right, then in the order of ``order``, kept in the order of the document
+    let root = lay_out(".flex { flex-direction: row-reverse; }");
+    assert_eq!(positions(&root), vec![(250.0, 0.0), (230.0, 0.0), (200.0, 0.0)]);
+    let root = lay_out("#a { order: 1; } #c { order: -1; }");
+    assert_eq!(positions(&root), vec![(50.0, 0.0), (30.0, 0.0), (0.0, 0.0)]);
+
+    // From the top, or the bottom, as high as them
+    let root = lay_out(".flex { flex-direction: column; }");
+    assert_eq!(positions(&root), vec![(0.0, 0.0), (0.0, 10.0), (0.0, 30.0)]);
+    assert_eq!(root.dimensions.content.height, Au::from_f64_px(60.0));
+    let root = lay_out(".flex { flex-flow: column-reverse; }");
+    assert_eq!(positions(&root), vec![(0.0, 50.0), (0.0, 30.0), (0.0, 0.0)]);
+
+    // Grown along the height, placed across the width
+    let root = lay_out(
+        ".flex { flex-direction: column; height: 100px; align-items: center; } \
+         #b { flex-grow: 1; } #c { width: auto; align-self: stretch; }",
+    );
+    assert_eq!(positions(&root), vec![(125.0, 0.0), (140.0, 10.0), (0.0, 70.0)]);
+    let heights: Vec<f64> = root.children
+        .iter()
+        .map(|item| item.dimensions.content.height.to_f64_px())
+        .collect();
+    assert_eq!(heights, vec![10.0, 60.0, 30.0]);
+    assert_eq!(root.children[2].dimensions.content.width, Au::from_f64_px(300.0));
+
+    // Columns side by side
+    let root = lay_out(
+        ".flex { flex-flow: column wrap; height: 35px; column-gap: 5px; align-items: start; \
+         align-content: start; }",
+    );
+    assert_eq!(positions(&root), vec![(0.0, 0.0), (0.0, 10.0), (55.0, 0.0)]);
+}
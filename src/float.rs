@@ -1,4 +1,6 @@
+use containment;
 use layout::{BoxType, Dimensions, EdgeSizes, LayoutBox, LayoutInfo, Rect};
+use shape::Shape;
 use style;
 
 use std::cmp::{max, min};
@@ -16,8 +18,12 @@ pub type FloatList = Vec<Float>;
 
 #[derive(Clone, Debug)]
 pub struct Float {
+    /// The margin box
     pub rect: Rect,
     pub float_type: style::FloatType,
+    /// The area the inline content wraps around (``shape-outside``) in the coordinates of
+    /// ``rect``, and clipped to it. None is the whole margin box.
+    pub shape: Option<Shape>,
 }
 
 impl Float {
@@ -25,8 +31,33 @@ impl Float {
         Float {
             rect: rect,
             float_type: float_type,
+            shape: None,
         }
     }
+
+    pub fn with_shape(rect: Rect, float_type: style::FloatType, shape: Shape) -> Float {
+        Float {
+            rect: rect,
+            float_type: float_type,
+            shape: Some(shape),
+        }
+    }
+
+    /// The left and the right of the area the float excludes from the lines between ``top`` and
+    /// ``top + height``. None if its shape is not there, so the lines go over it.
+    pub fn exclusion(&self, top: Au, height: Au) -> Option<(Au, Au)> {
+        let (left, right) = (self.rect.x, self.rect.x + self.rect.width);
+        let shape = match self.shape {
+            Some(ref shape) => shape,
+            None => return Some((left, right)),
+        };
+        let (start, end) =
+            shape.horizontal_extent(top.to_f64_px(), (top + height).to_f64_px())?;
+        Some((
+            max(left, Au::from_f64_px(start)),
+            min(right, Au::from_f64_px(end)),
+        ))
+    }
 }
 
 impl Floats {
@@ -47,6 +78,11 @@ impl Floats {
         !self.float_list.is_empty()
     }
 
+    /// Whether a float has a ``shape-outside``, so the lines next to it are not all as wide.
+    pub fn has_shapes(&self) -> bool {
+        self.float_list.iter().any(|float| float.shape.is_some())
+    }
+
     pub fn translate(&mut self, delta: EdgeSizes) {
         self.offset.left += delta.left;
         self.offset.right += delta.right;
@@ -68,22 +104,21 @@ impl Floats {
         let mut r_height = None;
 
         for float in self.float_list.iter() {
+            if !(float.rect.y + float.rect.height > ceiling && float.rect.y < ceiling + height) {
+                continue;
+            }
+            let (start, end) = match float.exclusion(ceiling, height) {
+                Some(exclusion) => exclusion,
+                None => continue,
+            };
             match float.float_type {
-                style::FloatType::Left
-                    if float.rect.x + float.rect.width > left
-                        && float.rect.y + float.rect.height > ceiling
-                        && float.rect.y < ceiling + height =>
-                {
-                    left = float.rect.x + float.rect.width;
+                style::FloatType::Left if end > left => {
+                    left = end;
                     l_ceiling = Some(float.rect.y);
                     l_height = Some(float.rect.height);
                 }
-                style::FloatType::Right
-                    if (max_width - float.rect.x) > right
-                        && float.rect.y + float.rect.height > ceiling
-                        && float.rect.y < ceiling + height =>
-                {
-                    right += float.rect.width;
+                style::FloatType::Right if (max_width - start) > right => {
+                    right += float.rect.x + float.rect.width - start;
                     r_ceiling = Some(float.rect.y);
                     r_height = Some(float.rect.height);
                 }
@@ -147,7 +182,7 @@ impl LayoutBox {
         floats: &mut Floats,
         _last_margin_bottom: Au,
         containing_block: Dimensions,
-        _saved_block: Dimensions,
+        saved_block: Dimensions,
         viewport: Dimensions,
     ) {
         self.assign_padding();
@@ -156,42 +191,40 @@ impl LayoutBox {
 
         // TODO: Implement correctly
         match self.info {
-            LayoutInfo::Image(_) => self.load_image(containing_block),
-            LayoutInfo::Generic | LayoutInfo::Anker => {
+            LayoutInfo::Image(_)
+            | LayoutInfo::Svg(_)
+            | LayoutInfo::Canvas(_)
+            | LayoutInfo::Math(_)
+            | LayoutInfo::TextInput(_)
+            | LayoutInfo::Select(_) => {
+                self.load_image(containing_block)
+            }
+            LayoutInfo::Generic
+            | LayoutInfo::Anker
+            | LayoutInfo::Button(_)
+            | LayoutInfo::Text => {
                 let width_not_specified = self.calculate_float_width(containing_block);
+                // With size containment, the width is that of an empty float.
+                let size_contained = containment::size_contained(self);
 
                 if width_not_specified {
                     // When dimensions.content.width < Au(0), LineBreaker inside layout_float_children()
                     // calculates shrink-to-fit width.
-                    self.dimensions.content.width = Au(-1);
+                    self.dimensions.content.width = if size_contained {
+                        containment::contained_size(self).0
+                    } else {
+                        Au(-1)
+                    };
                 }
 
-                self.layout_float_children(viewport);
+                self.layout_float_children(saved_block, viewport);
 
-                if width_not_specified {
-                    self.dimensions.content.width = Au(0);
-
-                    for child in &self.children {
-                        match child.box_type {
-                            BoxType::BlockNode | BoxType::AnonymousBlock => {
-                                self.dimensions.content.width = max(
-                                    self.dimensions.content.width,
-                                    child.dimensions.border_box().width,
-                                );
-                            }
-                            BoxType::Float => {
-                                // Ignore whether the float is on left or right
-                                self.dimensions.content.width +=
-                                    child.dimensions.border_box().width;
-                            }
-                            _ => {}
-                        }
-                    }
+                if width_not_specified && !size_contained {
+                    self.dimensions.content.width = self.shrink_to_fit_width();
                 }
 
-                self.calculate_block_height();
+                self.calculate_block_height(saved_block);
             }
-            _ => unimplemented!("{:?}", self.info),
         };
 
         self.assign_replaced_width_if_necessary();
@@ -199,14 +232,38 @@ impl LayoutBox {
 
         self.calculate_float_position(floats, containing_block);
 
-        floats.add_float(Float::new(
-            self.dimensions.margin_box(),
-            self.get_style_node().float(),
-        ));
+        let float_type = self.get_style_node().float();
+        floats.add_float(match self.property.shape_outside() {
+            Some((shape, reference_box)) => Float::with_shape(
+                self.dimensions.margin_box(),
+                float_type,
+                shape.resolve(reference_box.rect(self.dimensions)),
+            ),
+            None => Float::new(self.dimensions.margin_box(), float_type),
+        });
     }
 
-    pub fn layout_float_children(&mut self, viewport: Dimensions) {
-        self.layout_block_children(viewport);
+    /// The content width of a box whose children are laid out shrink-to-fit (in a negative
+    /// width): that of its widest block, or of its floats side by side.
+    pub fn shrink_to_fit_width(&self) -> Au {
+        let mut width = Au(0);
+        for child in &self.children {
+            match child.box_type {
+                BoxType::BlockNode | BoxType::AnonymousBlock => {
+                    width = max(width, child.dimensions.border_box().width);
+                }
+                BoxType::Float => {
+                    // Ignore whether the float is on left or right
+                    width += child.dimensions.border_box().width;
+                }
+                _ => {}
+            }
+        }
+        width
+    }
+
+    pub fn layout_float_children(&mut self, saved_block: Dimensions, viewport: Dimensions) {
+        self.layout_block_children(saved_block, viewport);
         // The height of float children in a float element is noticed.
         self.dimensions.content.height = max(
             self.dimensions.content.height,
@@ -225,12 +282,12 @@ impl LayoutBox {
             );
             if margin_box.width <= available_area.width {
                 self.dimensions.content.x = match self.property.float() {
-                    style::FloatType::Left => self.dimensions.left_offset() + available_area.x,
                     style::FloatType::Right => {
                         available_area.width + available_area.x - self.dimensions.content.width
                             - self.dimensions.right_offset()
                     }
-                    _ => unreachable!(),
+                    // A float box whose float is not right floats left.
+                    _ => self.dimensions.left_offset() + available_area.x,
                 };
                 self.dimensions.content.y = containing_block.content.height + float_height;
                 break;
@@ -269,3 +326,35 @@ impl LayoutBox {
         }
     }
 }
+
+#[test]
+fn test_shape_outside() {
+    use shape::Shape;
+
+    let px = Au::from_f64_px;
+    let mut floats = Floats::new();
+    // A 100px circle floating left
+    floats.add_float(Float::with_shape(
+        Rect {
+            x: px(0.0),
+            y: px(0.0),
+            width: px(100.0),
+            height: px(100.0),
+        },
+        style::FloatType::Left,
+        Shape::Ellipse {
+            center: (50.0, 50.0),
+            radius: (50.0, 50.0),
+        },
+    ));
+    assert!(floats.has_shapes());
+    // The lines start where the circle ends, and are widest next to its top and bottom.
+    let area = floats.available_area(px(300.0), px(40.0), px(20.0));
+    assert_eq!((area.x, area.width), (px(100.0), px(200.0)));
+    let area = floats.available_area(px(300.0), px(0.0), px(10.0));
+    assert_eq!(area.x, px(80.0));
+    assert_eq!(area.width, px(220.0));
+    // Under the circle
+    let area = floats.available_area(px(300.0), px(100.0), px(10.0));
+    assert_eq!((area.x, area.width), (px(0.0), px(300.0)));
+}
@@ -1,14 +1,33 @@
+//! The fonts, and the metrics of texts set in them. They are measured with Pango, or estimated
+//! from the font size without the ``raster`` feature (see ``Font::text_width``).
+
+#[cfg(feature = "raster")]
 use cairo;
+#[cfg(feature = "raster")]
 use pango;
+#[cfg(feature = "raster")]
 use pangocairo;
 
+#[cfg(feature = "raster")]
 use css::px2pt;
+use hyphenation::{Hyphenator, HYPHEN};
+use linebreak;
 
+#[cfg(feature = "raster")]
 use std::cell::RefCell;
+#[cfg(feature = "raster")]
+use std::ffi::CString;
+use std::sync::Mutex;
+#[cfg(feature = "raster")]
 use pango::{ContextExt, LayoutExt};
+#[cfg(feature = "raster")]
+use pango_sys;
+#[cfg(feature = "raster")]
+use glib::translate::{from_glib_full, ToGlibPtr};
 
 use app_units::Au;
 
+#[cfg(feature = "raster")]
 thread_local!(
     pub static PANGO_LAYOUT: RefCell<pango::Layout> = {
         let surface = cairo::ImageSurface::create(cairo::Format::Rgb24, 0, 0).unwrap();
@@ -26,6 +45,72 @@ pub struct Font {
     pub size: Au,
     pub weight: FontWeight,
     pub slant: FontSlant,
+    pub family: FontFamily,
+    pub features: FontFeatures,
+    /// ``letter-spacing``, added after every character
+    pub letter_spacing: Au,
+    /// ``word-spacing``, added after every space (with ``letter_spacing``)
+    pub word_spacing: Au,
+    pub tab_size: TabSize,
+    /// The language of the text (``lang``), which Pango selects the fonts and shapes by
+    pub lang: FontLanguage,
+}
+
+/// ``tab-size``: the distance between the tab stops.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TabSize {
+    /// In the advances of a space (with ``letter-spacing`` and ``word-spacing``)
+    Spaces(f64),
+    Length(Au),
+}
+
+pub const DEFAULT_TAB_SIZE: TabSize = TabSize::Spaces(8.0);
+
+/// A ``font-family`` list. The names are interned so that ``Font`` stays ``Copy``.
+/// Pango picks the first font in the list that has the glyph for each run of the text, then
+/// a system fallback font, so the text is split at font boundaries the same way when it is
+/// measured and painted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontFamily(usize);
+
+/// The default of ``font-family``.
+pub const DEFAULT_FONT_FAMILY: FontFamily = FontFamily(0);
+
+// The lists other than the default, indexed by FontFamily(i + 1). Fonts are used by the workers
+// laying out pages too.
+static FONT_FAMILIES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// The OpenType features given to the shaper, e.g. ``smcp=1,kern=0`` (from ``font-variant``,
+/// ``font-kerning`` and ``font-feature-settings``). Interned like ``FontFamily``.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontFeatures(usize);
+
+/// No features other than the defaults of the font.
+pub const DEFAULT_FONT_FEATURES: FontFeatures = FontFeatures(0);
+
+// Indexed by FontFeatures(i + 1)
+static FONT_FEATURE_LISTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// A language tag (e.g. ``ja``), interned like ``FontFamily``.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontLanguage(usize);
+
+/// The language is not known (the default of Pango is used).
+pub const DEFAULT_FONT_LANGUAGE: FontLanguage = FontLanguage(0);
+
+// Indexed by FontLanguage(i + 1)
+static FONT_LANGUAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// The index of ``s`` in ``table`` plus 1, adding it if not found.
+fn intern(table: &Mutex<Vec<String>>, s: String) -> usize {
+    let mut table = table.lock().unwrap();
+    match table.iter().position(|t| *t == s) {
+        Some(i) => i + 1,
+        None => {
+            table.push(s);
+            table.len()
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -40,23 +125,135 @@ pub enum FontSlant {
     Italic,
 }
 
+impl FontFamily {
+    /// The list of the names in ``font-family``. A generic family is appended to fall back on
+    /// if the list has none.
+    pub fn new(names: &[String]) -> FontFamily {
+        let mut names: Vec<&str> = names
+            .iter()
+            .map(|name| match name.trim() {
+                "system-ui" => "sans-serif",
+                name => name,
+            })
+            .filter(|name| !name.is_empty())
+            .collect();
+        let generic = ["serif", "sans-serif", "monospace", "cursive", "fantasy"];
+        if !names.iter().any(|name| generic.contains(name)) {
+            names.push("sans-serif");
+        }
+        let names = names.join(",");
+        if names == "sans-serif" {
+            return DEFAULT_FONT_FAMILY;
+        }
+        FontFamily(intern(&FONT_FAMILIES, names))
+    }
+
+    /// The names separated by commas, as Pango takes them.
+    pub fn names(&self) -> String {
+        match self.0 {
+            0 => "sans-serif".to_string(),
+            i => FONT_FAMILIES.lock().unwrap()[i - 1].clone(),
+        }
+    }
+}
+
+impl FontFeatures {
+    /// ``features`` are (tag, value) like ("liga", 0). A later one overrides the earlier ones.
+    pub fn new(features: &[(String, u32)]) -> FontFeatures {
+        if features.is_empty() {
+            return DEFAULT_FONT_FEATURES;
+        }
+        let features = features
+            .iter()
+            .map(|&(ref tag, value)| format!("{}={}", tag, value))
+            .collect::<Vec<String>>()
+            .join(",");
+        FontFeatures(intern(&FONT_FEATURE_LISTS, features))
+    }
+
+    /// Separated by commas, as Pango takes them.
+    pub fn to_pango_string(&self) -> String {
+        match self.0 {
+            0 => "".to_string(),
+            i => FONT_FEATURE_LISTS.lock().unwrap()[i - 1].clone(),
+        }
+    }
+
+    /// The attributes for a Pango layout to shape text with the features.
+    #[cfg(feature = "raster")]
+    pub fn to_pango_attr_list(&self) -> pango::AttrList {
+        let attr_list = pango::AttrList::new();
+        if *self != DEFAULT_FONT_FEATURES {
+            // Features with a NUL in them are ignored.
+            if let Ok(features) = CString::new(self.to_pango_string()) {
+                let attr: Option<pango::Attribute> = unsafe {
+                    from_glib_full(pango_sys::pango_attr_font_features_new(features.as_ptr()))
+                };
+                if let Some(attr) = attr {
+                    attr_list.insert(attr);
+                }
+            }
+        }
+        attr_list
+    }
+}
+
+impl FontLanguage {
+    pub fn new(lang: Option<&str>) -> FontLanguage {
+        match lang.map(str::trim) {
+            None | Some("") => DEFAULT_FONT_LANGUAGE,
+            Some(lang) => FontLanguage(intern(&FONT_LANGUAGES, lang.to_lowercase())),
+        }
+    }
+
+    pub fn tag(&self) -> Option<String> {
+        match self.0 {
+            0 => None,
+            i => Some(FONT_LANGUAGES.lock().unwrap()[i - 1].clone()),
+        }
+    }
+}
+
 impl Font {
-    pub fn new(size: Au, weight: FontWeight, slant: FontSlant) -> Font {
+    pub fn new(
+        size: Au,
+        weight: FontWeight,
+        slant: FontSlant,
+        family: FontFamily,
+        features: FontFeatures,
+        letter_spacing: Au,
+        word_spacing: Au,
+    ) -> Font {
+        let font = Font {
+            size: size,
+            weight: weight,
+            slant: slant,
+            family: family,
+            features: features,
+            letter_spacing: letter_spacing,
+            word_spacing: word_spacing,
+            tab_size: DEFAULT_TAB_SIZE,
+            lang: DEFAULT_FONT_LANGUAGE,
+        };
+
+        #[cfg(feature = "raster")]
         FONT_DESC.with(|font_desc| {
             let mut font_desc = font_desc.borrow_mut();
-            font_desc.set_size(pango::units_from_double(px2pt(size.to_f64_px())));
-            font_desc.set_style(slant.to_pango_font_slant());
-            font_desc.set_weight(weight.to_pango_font_weight());
+            font.set_to_pango_font_desc(&mut font_desc);
             PANGO_LAYOUT.with(|layout| {
                 layout.borrow_mut().set_font_description(Some(&*font_desc));
             })
         });
 
-        Font {
-            size: size,
-            weight: weight,
-            slant: slant,
-        }
+        font
+    }
+
+    #[cfg(feature = "raster")]
+    pub fn set_to_pango_font_desc(&self, font_desc: &mut pango::FontDescription) {
+        font_desc.set_family(self.family.names().as_str());
+        font_desc.set_size(pango::units_from_double(px2pt(self.size.to_f64_px())));
+        font_desc.set_style(self.slant.to_pango_font_slant());
+        font_desc.set_weight(self.weight.to_pango_font_weight());
     }
 
     pub fn new_empty() -> Font {
@@ -64,25 +261,141 @@ impl Font {
             size: Au(0),
             weight: FontWeight::Normal,
             slant: FontSlant::Normal,
+            family: DEFAULT_FONT_FAMILY,
+            features: DEFAULT_FONT_FEATURES,
+            letter_spacing: Au(0),
+            word_spacing: Au(0),
+            tab_size: DEFAULT_TAB_SIZE,
+            lang: DEFAULT_FONT_LANGUAGE,
         }
     }
 
+    /// Makes this font the one measured by ``text_width`` etc.
+    pub fn select(&self) -> Font {
+        Font {
+            tab_size: self.tab_size,
+            lang: self.lang,
+            ..Font::new(
+                self.size,
+                self.weight,
+                self.slant,
+                self.family,
+                self.features,
+                self.letter_spacing,
+                self.word_spacing,
+            )
+        }
+    }
+
+    #[cfg(feature = "raster")]
     pub fn text_width(&self, text: &str) -> f64 {
+        let tabs = self.to_pango_tab_array(text);
         PANGO_LAYOUT.with(|layout| {
             let layout = layout.borrow_mut();
+            layout.set_tabs(tabs.as_ref());
             layout.set_text(text);
+            // The widths change with the features (e.g. ligatures and kerning) and spacing.
+            layout.set_attributes(Some(&self.to_pango_attr_list(text)));
             pango::units_to_double(layout.get_size().0)
         })
     }
 
+    /// Without Pango, the advances are estimated from the size: half an em, 0.6em in a monospace
+    /// font and an em for the wide (CJK) characters. The spacing is added as Pango adds it.
+    #[cfg(not(feature = "raster"))]
+    pub fn text_width(&self, text: &str) -> f64 {
+        let em = self.size.to_f64_px();
+        let monospace = self.family.names().split(',').any(|name| name == "monospace");
+        let mut width = 0.0;
+        for c in text.chars() {
+            width += match c {
+                '\t' => {
+                    let tab = self.tab_width().max(1.0);
+                    tab - width % tab
+                }
+                c if is_combining_mark(c) => continue,
+                c if is_wide(c) => em,
+                _ if monospace => 0.6 * em,
+                _ => 0.5 * em,
+            };
+            width += self.letter_spacing.to_f64_px();
+            if is_word_separator(c) {
+                width += self.word_spacing.to_f64_px();
+            }
+        }
+        width
+    }
+
+    /// The distance (in px) between the tab stops. Needs the font selected.
+    pub fn tab_width(&self) -> f64 {
+        match self.tab_size {
+            TabSize::Spaces(spaces) => spaces * self.text_width(" "),
+            TabSize::Length(length) => length.to_f64_px(),
+        }
+    }
+
+    /// The tab stops for a Pango layout to lay out ``text`` with, if it has tabs. They are from
+    /// the start of the text box (not the line).
+    #[cfg(feature = "raster")]
+    pub fn to_pango_tab_array(&self, text: &str) -> Option<pango::TabArray> {
+        if !text.contains('\t') {
+            return None;
+        }
+        let mut tabs = pango::TabArray::new(1, false);
+        // Pango repeats the distance to the last stop. Zero would be the default of Pango.
+        let width = pango::units_from_double(self.tab_width()).max(1);
+        tabs.set_tab(0, pango::TabAlign::Left, width);
+        Some(tabs)
+    }
+
+    /// The attributes for a Pango layout to shape ``text`` with: the features, the language and
+    /// the spacing. Measured and painted text must be given the same attributes.
+    #[cfg(feature = "raster")]
+    pub fn to_pango_attr_list(&self, text: &str) -> pango::AttrList {
+        let attr_list = self.features.to_pango_attr_list();
+        if let Some(lang) = self.lang.tag() {
+            let language = pango::Language::from_string(&lang);
+            let attr: Option<pango::Attribute> = unsafe {
+                from_glib_full(pango_sys::pango_attr_language_new((&language).to_glib_none().0))
+            };
+            if let Some(attr) = attr {
+                attr_list.insert(attr);
+            }
+        }
+        let spacing = |spacing: Au, range: Option<(usize, usize)>| {
+            let spacing = pango::units_from_double(spacing.to_f64_px());
+            if let Some(mut attr) = pango::Attribute::new_letter_spacing(spacing) {
+                if let Some((start, end)) = range {
+                    attr.set_start_index(start as u32);
+                    attr.set_end_index(end as u32);
+                }
+                attr_list.insert(attr);
+            }
+        };
+
+        if self.letter_spacing != Au(0) {
+            spacing(self.letter_spacing, None);
+        }
+        if self.word_spacing != Au(0) {
+            // Pango has no word spacing. The spaces are given more letter spacing instead.
+            for (i, c) in text.char_indices().filter(|&(_, c)| is_word_separator(c)) {
+                spacing(
+                    self.letter_spacing + self.word_spacing,
+                    Some((i, i + c.len_utf8())),
+                );
+            }
+        }
+        attr_list
+    }
+
+    #[cfg(feature = "raster")]
     pub fn get_ascent_descent(&self) -> (Au, Au) {
         FONT_DESC.with(|font_desc| {
             let font_desc = font_desc.borrow();
             PANGO_LAYOUT.with(|layout| {
                 let ctx = layout.borrow_mut().get_context().unwrap();
-                let metrics =
-                    ctx.get_metrics(Some(&*font_desc), Some(&pango::Language::from_string("")))
-                        .unwrap();
+                let language = pango::Language::from_string(&self.lang.tag().unwrap_or_default());
+                let metrics = ctx.get_metrics(Some(&*font_desc), Some(&language)).unwrap();
                 (
                     Au::from_f64_px(pango::units_to_double(metrics.get_ascent()) as f64),
                     Au::from_f64_px(pango::units_to_double(metrics.get_descent()) as f64),
@@ -91,44 +404,199 @@ impl Font {
         })
     }
 
-    pub fn compute_max_chars_and_width(&self, s: &str, max_width: f64) -> (usize, f64) {
-        if max_width < 0f64 {
-            return (0, 0.0);
+    /// Without Pango, 0.8em above the baseline and 0.2em below it.
+    #[cfg(not(feature = "raster"))]
+    pub fn get_ascent_descent(&self) -> (Au, Au) {
+        (self.size.scale_by(0.8), self.size.scale_by(0.2))
+    }
+
+    /// The length (in bytes) of the part of ``s`` put on the line with ``max_width`` left,
+    /// its width, and whether a hyphen is put after it.
+    pub fn compute_max_chars_and_width(
+        &self,
+        s: &str,
+        max_width: f64,
+        hyphenator: &Hyphenator,
+    ) -> (usize, f64, bool) {
+        max_chars_and_width(
+            s,
+            max_width,
+            |text| self.text_width(text),
+            |word| hyphenator.hyphenation_points(word),
+        )
+    }
+}
+
+/// Splits ``s`` at the last break opportunity (see ``linebreak``) that fits in ``max_width``, or
+/// at the last cluster that fits if there is none. At least one cluster is taken.
+/// ``text_width`` measures shaped text, so prefixes are measured as a whole (not per character)
+/// for ligatures, combining marks and joined scripts like Arabic to measure as they are painted.
+/// The word over the end of the line is hyphenated at the last of ``hyphenation_points`` (in
+/// the word) that fits with a hyphen, if any. Returns true as well then.
+pub fn max_chars_and_width<F, H>(
+    s: &str,
+    max_width: f64,
+    text_width: F,
+    hyphenation_points: H,
+) -> (usize, f64, bool)
+where
+    F: Fn(&str) -> f64,
+    H: Fn(&str) -> Vec<usize>,
+{
+    if max_width < 0f64 || s.is_empty() {
+        return (0, 0.0, false);
+    }
+
+    // Where a line can be broken without splitting a cluster (a base and its combining marks).
+    let boundaries: Vec<usize> = s.char_indices()
+        .filter(|&(i, c)| i > 0 && !is_combining_mark(c))
+        .map(|(i, _)| i)
+        .chain(Some(s.len()))
+        .collect();
+
+    // The longest prefix that fits. Shaped widths grow with the text, so binary search works.
+    let fitting = match boundaries.binary_search_by(|&end| {
+        text_width(&s[..end]).partial_cmp(&max_width).unwrap()
+    }) {
+        Ok(i) => Some(boundaries[i]),
+        Err(0) => None,
+        Err(i) => Some(boundaries[i - 1]),
+    };
+    let fitting_end = fitting.unwrap_or(0);
+
+    // Whitespace before a break may overflow the line (it becomes invisible at the end of the
+    // line). (the end of the text before the whitespace, the break)
+    let last_splittable = linebreak::break_opportunities(s)
+        .into_iter()
+        .rev()
+        .map(|end| (s[..end].trim_end().len(), end))
+        .find(|&(start, _)| start <= fitting_end);
+
+    let word_start = last_splittable.map_or(0, |(_, end)| end);
+    if fitting.is_some() && word_start < fitting_end && fitting_end < s.len() {
+        let word_end = s[fitting_end..]
+            .find(char::is_whitespace)
+            .map_or(s.len(), |i| fitting_end + i);
+        let hyphenated = hyphenation_points(&s[word_start..word_end])
+            .into_iter()
+            .rev()
+            .map(|point| word_start + point)
+            .filter(|&point| word_start < point && point <= fitting_end)
+            .map(|point| (point, text_width(format!("{}{}", &s[..point], HYPHEN).as_str())))
+            .find(|&(_, width)| width <= max_width);
+        if let Some((point, width)) = hyphenated {
+            return (point, width, true);
         }
+    }
 
-        PANGO_LAYOUT.with(|layout| {
-            let layout = layout.borrow_mut();
-            // TODO: Inefficient implementation!
-            let mut text_width = 0.0;
-            let mut last_splittable_pos = None;
-            let mut last_pos = 0;
-            for (pos, c) in s.char_indices() {
-                if c.is_whitespace() || c.is_ascii_punctuation() {
-                    last_splittable_pos = Some(pos);
-                }
+    match (last_splittable, fitting) {
+        (Some((start, end)), _) => {
+            let width = text_width(&s[..end]);
+            // A hanging whitespace isn't counted.
+            (end, if width > max_width { text_width(&s[..start]) } else { width }, false)
+        }
+        (None, Some(end)) => (end, text_width(&s[..end]), false),
+        (None, None) => (boundaries[0], text_width(&s[..boundaries[0]]), false),
+    }
+}
 
-                layout.set_text(c.to_string().as_str());
-                let c_width = pango::units_to_double(layout.get_size().0);
-                text_width += c_width;
-
-                if text_width > max_width {
-                    if let Some(pos) = last_splittable_pos {
-                        return (pos + 1, text_width - c_width); // '1' means whitespace or punctuation.
-                    } else {
-                        if pos == 0 {
-                            break;
-                        }
-                        if pos - last_pos > 1 {
-                            // if c is multi-byte character
-                            return (pos, text_width - c_width);
-                        }
-                    }
-                }
+/// The characters ``word-spacing`` is added to.
+fn is_word_separator(c: char) -> bool {
+    c == ' ' || c == '\u{a0}'
+}
 
-                last_pos = pos;
-            }
+/// The characters set in an em without Pango: CJK, Hangul and the fullwidth forms.
+#[cfg(not(feature = "raster"))]
+fn is_wide(c: char) -> bool {
+    match c as u32 {
+        0x1100...0x115F // Hangul Jamo
+        | 0x2E80...0x303E | 0x3041...0x33FF // CJK radicals and symbols, kana
+        | 0x3400...0x4DBF | 0x4E00...0x9FFF | 0xF900...0xFAFF // CJK ideographs
+        | 0xAC00...0xD7A3 // Hangul syllables
+        | 0xFF00...0xFF60 | 0xFFE0...0xFFE6 // Fullwidth forms
+        | 0x20000...0x3FFFD => true,
+        _ => false,
+    }
+}
 
-            (if s.is_empty() { 0 } else { 1 }, text_width)
-        })
+/// Combining marks and joiners, which are drawn with the character before them.
+pub fn is_combining_mark(c: char) -> bool {
+    match c as u32 {
+        0x0300...0x036F // Combining Diacritical Marks
+        | 0x0483...0x0489 // Cyrillic
+        | 0x0591...0x05C7 // Hebrew
+        | 0x0610...0x061A | 0x064B...0x065F | 0x0670 | 0x06D6...0x06ED // Arabic
+        | 0x0900...0x0903 | 0x093A...0x094F | 0x0951...0x0957 | 0x0962...0x0963 // Devanagari
+        | 0x0E31 | 0x0E34...0x0E3A | 0x0E47...0x0E4E // Thai
+        | 0x1AB0...0x1AFF | 0x1DC0...0x1DFF | 0x20D0...0x20FF // Combining marks (supplements)
+        | 0x200C | 0x200D // ZWNJ, ZWJ
+        | 0x3099 | 0x309A // Japanese (semi-)voiced sound marks
+        | 0xFE00...0xFE0F | 0xFE20...0xFE2F // Variation selectors, half marks
+        | 0x1F3FB...0x1F3FF // Emoji modifiers
+        | 0xE0100...0xE01EF => true,
+        _ => false,
     }
 }
+
+#[test]
+fn test_max_chars_and_width() {
+    let monospace = |s: &str| s.chars().filter(|&c| !is_combining_mark(c)).count() as f64 * 10.0;
+    let split = |s, max_width| max_chars_and_width(s, max_width, monospace, |_| vec![]);
+
+    assert_eq!(split("hello world", 80.0), (6, 60.0, false));
+    // The whitespace hangs.
+    assert_eq!(split("hello world", 55.0), (6, 50.0, false));
+    assert_eq!(split("a-b", 25.0), (2, 20.0, false));
+    // No break opportunity
+    assert_eq!(split("abcdef", 35.0), (3, 30.0, false));
+    // Between ideographs
+    assert_eq!(split("日本語です", 35.0), (9, 30.0, false));
+    assert_eq!(split("日本語。です", 35.0), (6, 20.0, false));
+    assert_eq!(split("abcdef", 5.0), (1, 10.0, false));
+    // Not between "e" and the combining acute accent
+    assert_eq!(split("cafe\u{301}s", 45.0), (6, 40.0, false));
+    assert_eq!(split("", 10.0), (0, 0.0, false));
+
+    // "hy-phen-ation"
+    let hyphenate = |s, max_width| {
+        max_chars_and_width(s, max_width, monospace, |word: &str| {
+            if word == "hyphenation" { vec![2, 6] } else { vec![] }
+        })
+    };
+    assert_eq!(hyphenate("a hyphenation", 100.0), (8, 90.0, true));
+    assert_eq!(hyphenate("a hyphenation", 50.0), (4, 50.0, true));
+    // The hyphen doesn't fit.
+    assert_eq!(hyphenate("a hyphenation", 40.0), (2, 20.0, false));
+}
+
+#[test]
+fn test_font_family() {
+    let family = |names: &[&str]| {
+        FontFamily::new(&names.iter().map(|s| s.to_string()).collect::<Vec<String>>())
+    };
+
+    assert_eq!(family(&[]), DEFAULT_FONT_FAMILY);
+    assert_eq!(family(&["system-ui"]), DEFAULT_FONT_FAMILY);
+    assert_eq!(family(&["Noto Sans CJK JP", "serif"]).names(), "Noto Sans CJK JP,serif");
+    assert_eq!(family(&["Noto Color Emoji"]).names(), "Noto Color Emoji,sans-serif");
+    assert_eq!(family(&["a", "monospace"]), family(&["a", "monospace"]));
+    assert!(family(&["a", "monospace"]) != family(&["b", "monospace"]));
+}
+
+#[test]
+fn test_font_features() {
+    let features = |features: &[(&str, u32)]| {
+        FontFeatures::new(&features
+            .iter()
+            .map(|&(tag, value)| (tag.to_string(), value))
+            .collect::<Vec<(String, u32)>>())
+    };
+
+    assert_eq!(features(&[]), DEFAULT_FONT_FEATURES);
+    assert_eq!(features(&[]).to_pango_string(), "");
+    assert_eq!(
+        features(&[("smcp", 1), ("liga", 0)]).to_pango_string(),
+        "smcp=1,liga=0"
+    );
+    assert_eq!(features(&[("kern", 0)]), features(&[("kern", 0)]));
+}
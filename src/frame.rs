@@ -0,0 +1,96 @@
+//! The frame scheduler: what has to be done again since the page was painted (an
+//! ``Invalidation``, e.g. when it is scrolled, restyled or laid out again, or an animation moves)
+//! is collected by the ``FrameScheduler`` of the view, and done once on the next frame of the
+//! frame clock of its window (see ``window::schedule_frame``), which paints the page once for all
+//! of them.
+//!
+//! The animations (e.g. smooth scrolling) move on by the time of each frame while they request
+//! the next one (``request_animation_frame``).
+
+use std::mem;
+
+/// What has to be done again before the page is painted, each including the ones before it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Invalidation {
+    /// Painted again from the page rasterized, e.g. when it is scrolled or the caret blinks
+    Paint,
+    /// Rasterized again from its display list, e.g. when a box is scrolled or a resource arrives
+    Raster,
+    /// Laid out again (if its source or style changed) to build its display list again, e.g. for
+    /// the focus ring or another frame of an animated image
+    Layout,
+}
+
+/// The frames of a view (see ``window::NaglfarView``), each view producing its own
+#[derive(Clone, Debug, Default)]
+pub struct FrameScheduler {
+    /// The invalidations since the last frame, None if a frame is not requested
+    pending: Option<Invalidation>,
+    /// Whether an animation moves on the next frame
+    animation_frame: bool,
+    /// How many frames have been produced
+    frame_count: u64,
+}
+
+impl FrameScheduler {
+    pub fn new() -> FrameScheduler {
+        FrameScheduler::default()
+    }
+
+    /// Records ``invalidation`` for the next frame.
+    pub fn invalidate(&mut self, invalidation: Invalidation) {
+        self.pending = self.pending.max(Some(invalidation));
+    }
+
+    /// Makes the animations move on the next frame, which is painted again.
+    pub fn request_animation_frame(&mut self) {
+        self.animation_frame = true;
+        self.invalidate(Invalidation::Paint)
+    }
+
+    /// Whether a frame is requested and not produced yet
+    pub fn frame_requested(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Starts a frame: returns what has to be done again for it, and whether the animations move
+    /// on it. The invalidations from then on are for the next frame.
+    pub fn begin_frame(&mut self) -> Option<(Invalidation, bool)> {
+        let invalidation = self.pending.take()?;
+        self.frame_count += 1;
+        Some((invalidation, mem::replace(&mut self.animation_frame, false)))
+    }
+
+    /// How many frames have been produced
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+}
+
+#[test]
+fn test_frame_scheduler() {
+    let mut frames = FrameScheduler::new();
+    assert_eq!(frames.begin_frame(), None);
+    assert_eq!(frames.frame_count(), 0);
+
+    // Coalesced into a frame of the most to be done again
+    frames.invalidate(Invalidation::Paint);
+    frames.invalidate(Invalidation::Layout);
+    frames.invalidate(Invalidation::Raster);
+    assert!(frames.frame_requested());
+    assert_eq!(frames.begin_frame(), Some((Invalidation::Layout, false)));
+    assert!(!frames.frame_requested() && frames.begin_frame().is_none());
+    assert_eq!(frames.frame_count(), 1);
+
+    // An animation requests each frame it moves on.
+    frames.request_animation_frame();
+    frames.invalidate(Invalidation::Raster);
+    assert_eq!(frames.begin_frame(), Some((Invalidation::Raster, true)));
+    frames.invalidate(Invalidation::Paint);
+    assert_eq!(frames.begin_frame(), Some((Invalidation::Paint, false)));
+
+    // Each view produces its own frames.
+    let other = FrameScheduler::new();
+    frames.invalidate(Invalidation::Paint);
+    assert!(!other.frame_requested());
+}
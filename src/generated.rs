@@ -0,0 +1,364 @@
+//! Generated content: the ``::before`` and ``::after`` boxes of an element, whose text is their
+//! ``content``, the counters it may show (``counter()`` and ``counters()``) and its quotes.
+//! ref. https://www.w3.org/TR/css-content-3/#content-property
+//! ref. https://www.w3.org/TR/css-lists-3/#auto-numbering
+//!
+//! The counters are updated in the order the boxes are generated (see
+//! ``layout::build_layout_tree``). A counter reset by an element is in scope for the element, its
+//! descendants and its following siblings, until a following sibling resets it again.
+
+use css::Value;
+use dom::ElementData;
+use style::Style;
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// The counters in scope while the boxes are generated
+#[derive(Clone, Debug, Default)]
+pub struct Counters {
+    /// The values of the instances of each counter, the outermost first, with how many scopes
+    /// were open when it was instantiated
+    instances: HashMap<String, Vec<(i32, usize)>>,
+    /// The counters instantiated in each scope open (of the elements whose children are being
+    /// generated), the outermost first. Those of the root stay in scope.
+    scopes: Vec<Vec<String>>,
+    /// How many quotes are open (by ``open-quote``, not closed yet by ``close-quote``)
+    quote_depth: usize,
+}
+
+impl Counters {
+    pub fn new() -> Counters {
+        Counters::default()
+    }
+
+    /// Opens the scope of the children of an element: the counters they instantiate are only in
+    /// scope until ``leave``.
+    pub fn enter(&mut self) {
+        self.scopes.push(vec![]);
+    }
+
+    pub fn leave(&mut self) {
+        for name in self.scopes.pop().unwrap_or_default() {
+            if let Some(instances) = self.instances.get_mut(&name) {
+                instances.pop();
+            }
+        }
+    }
+
+    /// Applies ``counter-reset``, ``counter-increment`` and ``counter-set`` of the element (or
+    /// the pseudo-element) with ``style``, in this order.
+    pub fn update(&mut self, style: &Style) {
+        for (name, value) in style.counter_changes("counter-reset", 0) {
+            self.reset(&name, value);
+        }
+        for (name, value) in style.counter_changes("counter-increment", 1) {
+            if self.instances.get(&name).is_none_or(|i| i.is_empty()) {
+                self.reset(&name, 0);
+            }
+            let count = &mut self.innermost(&name).0;
+            *count = count.saturating_add(value);
+        }
+        for (name, value) in style.counter_changes("counter-set", 0) {
+            if self.instances.get(&name).is_none_or(|i| i.is_empty()) {
+                self.reset(&name, value);
+            }
+            self.innermost(&name).0 = value;
+        }
+    }
+
+    /// The value of the innermost instance of ``name``, 0 if there is none
+    pub fn value(&self, name: &str) -> i32 {
+        self.values(name).last().cloned().unwrap_or(0)
+    }
+
+    /// The values of all the instances of ``name`` in scope, the outermost first
+    pub fn values(&self, name: &str) -> Vec<i32> {
+        self.instances
+            .get(name)
+            .map(|instances| instances.iter().map(|&(value, _)| value).collect())
+            .unwrap_or_default()
+    }
+
+    fn reset(&mut self, name: &str, value: i32) {
+        let depth = self.scopes.len();
+        let instances = self.instances.entry(name.to_string()).or_default();
+        match instances.last_mut() {
+            // Reset by a preceding sibling (or this element), whose scope ends here
+            Some(&mut (ref mut last, last_depth)) if last_depth == depth => *last = value,
+            _ => {
+                instances.push((value, depth));
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.push(name.to_string());
+                }
+            }
+        }
+    }
+
+    fn innermost(&mut self, name: &str) -> &mut (i32, usize) {
+        self.instances
+            .get_mut(name)
+            .and_then(|instances| instances.last_mut())
+            .expect("instantiated")
+    }
+}
+
+/// The text of the ``::before`` or ``::after`` of ``elem`` with ``style``, with the values of
+/// ``counters``. None if no box is generated (``content: normal`` or ``none``, the default).
+/// The quotes it opens or closes are counted in ``counters``.
+pub fn content(style: &Style, elem: &ElementData, counters: &mut Counters) -> Option<String> {
+    let values = style.value("content")?;
+    let mut text = String::new();
+    for value in &values {
+        match *value {
+            Value::Str(ref s) => text += s,
+            Value::Keyword(ref k) if k == "none" || k == "normal" => return None,
+            Value::Keyword(ref k) if k == "open-quote" || k == "no-open-quote" => {
+                if k == "open-quote" {
+                    text += &quote(style, counters.quote_depth).0;
+                }
+                counters.quote_depth += 1;
+            }
+            // Closes nothing if no quote is open.
+            Value::Keyword(ref k) if k == "close-quote" || k == "no-close-quote" => {
+                if counters.quote_depth > 0 {
+                    counters.quote_depth -= 1;
+                    if k == "close-quote" {
+                        text += &quote(style, counters.quote_depth).1;
+                    }
+                }
+            }
+            Value::Function(ref name, ref args) => {
+                let keyword = |i: usize| match args.get(i) {
+                    Some(&Value::Keyword(ref k)) => Some(k.as_str()),
+                    _ => None,
+                };
+                match name.as_str() {
+                    // counter(name, style)
+                    "counter" => if let Some(counter) = keyword(0) {
+                        text += &format(counters.value(counter), keyword(1).unwrap_or("decimal"))
+                    },
+                    // counters(name, separator, style)
+                    "counters" => if let Some(counter) = keyword(0) {
+                        let separator = match args.get(1) {
+                            Some(&Value::Str(ref s)) => s.as_str(),
+                            _ => "",
+                        };
+                        let style = keyword(2).unwrap_or("decimal");
+                        let mut values = counters.values(counter);
+                        if values.is_empty() {
+                            values.push(0);
+                        }
+                        let formatted: Vec<String> =
+                            values.iter().map(|&value| format(value, style)).collect();
+                        text += &formatted.join(separator);
+                    },
+                    "attr" => if let Some(attr) = keyword(0).and_then(|a| elem.attrs.get(a)) {
+                        text += attr
+                    },
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(text)
+}
+
+/// The open and close quotes of the nesting level ``depth`` (from 0) in ``style``: those of the
+/// deepest level given if it is deeper, empty if ``quotes: none``.
+fn quote(style: &Style, depth: usize) -> (String, String) {
+    let mut quotes = style.quotes();
+    let depth = depth.min(quotes.len().saturating_sub(1));
+    if depth < quotes.len() {
+        quotes.swap_remove(depth)
+    } else {
+        Default::default()
+    }
+}
+
+/// Whether ``property`` applies to ``pseudo_element`` (``first-line`` and ``first-letter`` only
+/// take a few of them)
+pub fn applies_to(pseudo_element: &str, property: &str) -> bool {
+    let text = property.starts_with("font")
+        || [
+            "color",
+            "line-height",
+            "letter-spacing",
+            "word-spacing",
+            "text-decoration",
+            "text-transform",
+            "text-shadow",
+        ].contains(&property);
+    match pseudo_element {
+        "first-line" => text,
+        "first-letter" => {
+            text || property == "float"
+                || ["background", "margin", "padding", "border"]
+                    .iter()
+                    .any(|prefix| property.starts_with(prefix))
+        }
+        _ => true,
+    }
+}
+
+/// The range of the first letter of ``text``, with the punctuation before and after it (e.g.
+/// ``"A."`` in ``' "A." b'``, after the white spaces).
+pub fn first_letter(text: &str) -> Range<usize> {
+    let is_punctuation = |c: char| {
+        c.is_ascii_punctuation() || "\u{ab}\u{bb}\u{2018}\u{2019}\u{201c}\u{201d}".contains(c)
+    };
+    let start = text.len() - text.trim_start().len();
+    let mut end = start;
+    let mut letter = false;
+    for c in text[start..].chars() {
+        match (letter, is_punctuation(c)) {
+            (_, true) => {}
+            (false, false) if !c.is_whitespace() => letter = true,
+            _ => break,
+        }
+        end += c.len_utf8();
+    }
+    start..end
+}
+
+/// ``value`` in the counter style ``style`` (a ``list-style-type``), e.g. ``iv`` in
+/// ``lower-roman``. Styles that do not represent a value fall back to ``decimal``.
+pub fn format(value: i32, style: &str) -> String {
+    match style {
+        "none" => String::new(),
+        "disc" => "\u{2022}".to_string(),
+        "circle" => "\u{25e6}".to_string(),
+        "square" => "\u{25aa}".to_string(),
+        "decimal-leading-zero" if (0..10).contains(&value) => format!("0{}", value),
+        "lower-roman" if (1..4000).contains(&value) => roman(value),
+        "upper-roman" if (1..4000).contains(&value) => roman(value).to_uppercase(),
+        "lower-alpha" | "lower-latin" if value > 0 => alphabetic(value, LATIN),
+        "upper-alpha" | "upper-latin" if value > 0 => alphabetic(value, LATIN).to_uppercase(),
+        "lower-greek" if value > 0 => alphabetic(value, GREEK),
+        _ => value.to_string(),
+    }
+}
+
+fn roman(mut value: i32) -> String {
+    let numerals = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut roman = String::new();
+    for &(n, numeral) in numerals.iter() {
+        while value >= n {
+            roman += numeral;
+            value -= n;
+        }
+    }
+    roman
+}
+
+const LATIN: &str = "abcdefghijklmnopqrstuvwxyz";
+const GREEK: &str = "αβγδεζηθικλμνξοπρστυφχψω";
+
+/// 1 is the first of ``letters``, the number of letters plus 1 is the first twice, ...
+fn alphabetic(mut value: i32, letters: &str) -> String {
+    let letters: Vec<char> = letters.chars().collect();
+    let count = letters.len() as i32;
+    let mut digits = vec![];
+    while value > 0 {
+        value -= 1;
+        digits.push(letters[(value % count) as usize]);
+        value /= count;
+    }
+    digits.iter().rev().collect()
+}
+
+#[test]
+fn test_counters() {
+    use style::style_from;
+
+    let mut counters = Counters::new();
+    counters.update(&style_from("counter-reset: chapter section 3"));
+    assert_eq!((counters.value("chapter"), counters.value("section")), (0, 3));
+    counters.update(&style_from("counter-increment: chapter section -1"));
+    assert_eq!((counters.value("chapter"), counters.value("section")), (1, 2));
+
+    // A nested list: the counter of the inner one is a new instance, out of scope after it.
+    counters.enter();
+    counters.update(&style_from("counter-reset: section"));
+    counters.update(&style_from("counter-increment: section 5"));
+    assert_eq!(counters.values("section"), vec![2, 5]);
+    // A following sibling resets the same instance again.
+    counters.update(&style_from("counter-reset: section 7"));
+    assert_eq!(counters.values("section"), vec![2, 7]);
+    counters.leave();
+    assert_eq!(counters.values("section"), vec![2]);
+
+    // Incremented without being reset: instantiated at 0 first
+    counters.update(&style_from("counter-increment: page; counter-set: chapter 9"));
+    assert_eq!((counters.value("page"), counters.value("chapter")), (1, 9));
+    assert_eq!(counters.value("unknown"), 0);
+
+    // Clamped instead of overflowing
+    counters.update(&style_from("counter-reset: big 2147483647"));
+    counters.update(&style_from("counter-increment: big 10"));
+    assert_eq!(counters.value("big"), i32::MAX);
+    counters.update(&style_from("counter-set: big -99999999999"));
+    counters.update(&style_from("counter-increment: big -1"));
+    assert_eq!(counters.value("big"), i32::MIN);
+
+    let elem = ElementData {
+        tag_name: "h2".to_string(),
+        layout_type: ::dom::LayoutType::Generic,
+        attrs: vec![("title".to_string(), "Intro".to_string())]
+            .into_iter()
+            .collect(),
+    };
+    let mut content = |css: &str| content(&style_from(css), &elem, &mut counters);
+    assert_eq!(
+        content("content: 'Chapter ' counter(chapter, upper-roman) '. ' attr(title)"),
+        Some("Chapter IX. Intro".to_string())
+    );
+    assert_eq!(
+        content("content: counters(section, '.') ' ' counter(page)"),
+        Some("2 1".to_string())
+    );
+    assert_eq!(content("content: none"), None);
+    assert_eq!(content("color: red"), None);
+
+    // Nested quotes, the second level repeated, and a close quote without an open one
+    assert_eq!(
+        content("content: open-quote open-quote no-open-quote open-quote"),
+        Some("\u{201c}\u{2018}\u{2018}".to_string())
+    );
+    let quotes = "quotes: '<' '>'; content: close-quote no-close-quote close-quote";
+    assert_eq!(content(quotes), Some(">>".to_string()));
+    assert_eq!(content("quotes: none; content: open-quote"), Some("".to_string()));
+    assert_eq!(
+        content("content: close-quote close-quote close-quote"),
+        Some("\u{2019}\u{201d}".to_string())
+    );
+
+    assert_eq!(first_letter("  \u{201c}A.\u{201d} b"), 2..10);
+    assert_eq!(first_letter("\u{e9}t\u{e9}"), 0..2);
+    assert_eq!(first_letter("(a)"), 0..3);
+    assert_eq!(first_letter(" "), 1..1);
+    assert!(applies_to("first-letter", "float") && !applies_to("first-line", "float"));
+
+    assert_eq!(format(4, "lower-roman"), "iv");
+    assert_eq!(format(1994, "upper-roman"), "MCMXCIV");
+    assert_eq!(format(28, "lower-alpha"), "ab");
+    assert_eq!(format(0, "lower-alpha"), "0");
+    assert_eq!(format(3, "decimal-leading-zero"), "03");
+    assert_eq!(format(18, "lower-greek"), "\u{3c3}");
+    assert_eq!(format(2, "disc"), "\u{2022}");
+}
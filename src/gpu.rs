@@ -0,0 +1,1047 @@
+//! A paint backend that renders the display list with wgpu, selected instead of cairo by
+//! ``set_renderer`` (``--renderer gpu``, or Ctrl+Shift+G in the window). It needs the ``gpu``
+//! feature, and a GPU to render with; otherwise the page is painted with cairo as usual.
+//!
+//! The display list becomes quads (see ``QuadBackend``): a solid rect is a quad of its color,
+//! and a text, an image or a path is rasterized once with cairo into a texture atlas, and is a
+//! quad textured with it. Only the part of a raster in the tile and its clip is rasterized, so
+//! that no page of the atlas is larger than a texture can be. Clips are scissor rects (to the
+//! bounds of a ``clip-path``). A display list with filters is painted with cairo, which applies
+//! them. Each tile of the page (see ``tile``) is rendered on its own.
+
+use css::{Color, TextDecoration};
+use font::Font;
+use layout::{ImageMetaData, Rect};
+use painter::{DisplayCommand, DisplayList, PaintBackend};
+use shape::Shape;
+use svg;
+use filter::Filter;
+use raster::CairoBackend;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use cairo::{self, Context};
+use gdk_pixbuf;
+use pango;
+
+/// The size of a page of the texture atlas (twice a tile). Larger rasters get a page of their
+/// own, up to the largest texture of the GPU.
+pub const ATLAS_PAGE_SIZE: i32 = 1024;
+/// Between the rasters in the atlas, so that they don't bleed into each other
+const ATLAS_PADDING: i32 = 1;
+
+/// What the page is rasterized with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Renderer {
+    Cairo,
+    Gpu,
+}
+
+/// Set by the browser before the window (on its own thread) is opened
+static GPU_RENDERER: AtomicBool = AtomicBool::new(false);
+
+impl Renderer {
+    /// ``cairo`` or ``gpu``
+    pub fn parse(s: &str) -> Option<Renderer> {
+        match s {
+            "cairo" => Some(Renderer::Cairo),
+            "gpu" => Some(Renderer::Gpu),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Renderer::Cairo => "cairo",
+            Renderer::Gpu => "gpu",
+        }
+    }
+}
+
+pub fn set_renderer(renderer: Renderer) {
+    GPU_RENDERER.store(renderer == Renderer::Gpu, Ordering::SeqCst);
+}
+
+pub fn renderer() -> Renderer {
+    if GPU_RENDERER.load(Ordering::SeqCst) {
+        Renderer::Gpu
+    } else {
+        Renderer::Cairo
+    }
+}
+
+/// Whether the display list can be rendered as quads: filters are only applied by cairo.
+pub fn supports(items: &DisplayList) -> bool {
+    items.iter().all(|item| match item.command {
+        DisplayCommand::PopLayer(ref filters) => filters.is_empty(),
+        _ => true,
+    })
+}
+
+/// Renders the display list on the GPU into a new surface of ``area`` (device px: x, y, width,
+/// height) of the document at ``scale``, like ``raster::paint_tile``. None if it is not
+/// supported, there is no GPU, or ``area`` or a raster in it is larger than its textures can be.
+#[cfg(feature = "gpu")]
+pub fn paint(
+    items: &DisplayList,
+    scale: f64,
+    area: ScissorRect,
+    pango_layout: &mut pango::Layout,
+) -> Option<cairo::ImageSurface> {
+    if !supports(items) {
+        return None;
+    }
+    let max_size = device::max_texture_size()?;
+    if area.2 > max_size || area.3 > max_size {
+        return None;
+    }
+    let mut backend = QuadBackend::new(scale, area, max_size, pango_layout);
+    ::painter::paint(items, &mut backend);
+    let QuadBackend {
+        quads,
+        atlas,
+        out_of_atlas,
+        ..
+    } = backend;
+    if out_of_atlas {
+        return None;
+    }
+    device::render(&quads, atlas, area)
+}
+
+/// Without the ``gpu`` feature, always painted with cairo
+#[cfg(not(feature = "gpu"))]
+pub fn paint(
+    _items: &DisplayList,
+    _scale: f64,
+    _area: ScissorRect,
+    _pango_layout: &mut pango::Layout,
+) -> Option<cairo::ImageSurface> {
+    None
+}
+
+/// A scissor rect in device px: (x, y, width, height)
+pub type ScissorRect = (i32, i32, i32, i32);
+
+/// A rect of the target in device px, filled with a rect of a page of the atlas multiplied by
+/// a color (premultiplied). Solid rects are the white texel of the first page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quad {
+    /// (x, y, width, height) in the target
+    pub rect: (f32, f32, f32, f32),
+    pub page: usize,
+    /// (x, y, width, height) in the page
+    pub texels: (f32, f32, f32, f32),
+    pub color: [f32; 4],
+    /// None if not clipped
+    pub scissor: Option<ScissorRect>,
+}
+
+/// Pages of rasters, each filled shelf by shelf from the top.
+pub struct Atlas {
+    pub pages: Vec<AtlasPage>,
+    /// The largest width and height of a page (of a texture of the GPU)
+    max_size: i32,
+}
+
+pub struct AtlasPage {
+    pub surface: cairo::ImageSurface,
+    shelves: Shelves,
+}
+
+/// Where the rasters are put in a page: rows of the height of the tallest raster in them.
+#[derive(Clone, Debug)]
+pub struct Shelves {
+    width: i32,
+    height: i32,
+    /// (top, height, the x the next raster is put at) of each row
+    rows: Vec<(i32, i32, i32)>,
+}
+
+impl Shelves {
+    pub fn new(width: i32, height: i32) -> Shelves {
+        Shelves {
+            width: width,
+            height: height,
+            rows: vec![],
+        }
+    }
+
+    /// Where a raster of ``width`` x ``height`` is put, None if the page is full.
+    pub fn allocate(&mut self, width: i32, height: i32) -> Option<(i32, i32)> {
+        let (padded_width, padded_height) = (width + ATLAS_PADDING, height + ATLAS_PADDING);
+        if padded_width > self.width + ATLAS_PADDING {
+            return None;
+        }
+        // The lowest row it fits in, not much taller than it
+        for row in &mut self.rows {
+            let (top, row_height, ref mut x) = *row;
+            if padded_height <= row_height && row_height <= padded_height * 2
+                && *x + width <= self.width
+            {
+                let position = (*x, top);
+                *x += padded_width;
+                return Some(position);
+            }
+        }
+        let top = self.rows
+            .last()
+            .map_or(0, |&(top, row_height, _)| top + row_height);
+        if top + height > self.height {
+            return None;
+        }
+        self.rows.push((top, padded_height, padded_width));
+        Some((0, top))
+    }
+}
+
+impl Atlas {
+    /// The first page has the white texel at (0, 0). No page is larger than ``max_size`` x
+    /// ``max_size``.
+    pub fn new(max_size: i32) -> Atlas {
+        let mut atlas = Atlas {
+            pages: vec![],
+            max_size: max_size,
+        };
+        if let Some((page, x, y)) = atlas.allocate(1, 1) {
+            let ctx = Context::new(&atlas.pages[page].surface);
+            ctx.set_source_rgb(1.0, 1.0, 1.0);
+            ctx.rectangle(x as f64, y as f64, 1.0, 1.0);
+            ctx.fill();
+        }
+        atlas
+    }
+
+    /// Where a raster of ``width`` x ``height`` is put: (page, x, y). None if it is larger than
+    /// a page can be, or the page cannot be allocated.
+    pub fn allocate(&mut self, width: i32, height: i32) -> Option<(usize, i32, i32)> {
+        if width > self.max_size || height > self.max_size {
+            return None;
+        }
+        for (i, page) in self.pages.iter_mut().enumerate() {
+            if let Some((x, y)) = page.shelves.allocate(width, height) {
+                return Some((i, x, y));
+            }
+        }
+        let page_size = ATLAS_PAGE_SIZE.min(self.max_size);
+        let (page_width, page_height) = (width.max(page_size), height.max(page_size));
+        let mut shelves = Shelves::new(page_width, page_height);
+        let (x, y) = shelves.allocate(width, height)?;
+        self.pages.push(AtlasPage {
+            surface: cairo::ImageSurface::create(cairo::Format::ARgb32, page_width, page_height)
+                .ok()?,
+            shelves: shelves,
+        });
+        Some((self.pages.len() - 1, x, y))
+    }
+}
+
+/// Turns the display list into quads, rasterizing what is not a solid rect into the atlas.
+pub struct QuadBackend<'a> {
+    pub quads: Vec<Quad>,
+    pub atlas: Atlas,
+    /// Set when a raster could not be put in the atlas, so the quads miss it
+    pub out_of_atlas: bool,
+    /// Device px per CSS px
+    scale: f64,
+    /// The part of the document rendered (device px), out of which nothing is rasterized
+    area: ScissorRect,
+    pango_layout: &'a mut pango::Layout,
+    /// The clips pushed, intersected. None if not clipped.
+    scissors: Vec<Option<ScissorRect>>,
+    /// Where each image scaled to a size is in the atlas, by the pixbuf and the size, so that
+    /// the tiles of a background share it
+    images: HashMap<(usize, i32, i32), (usize, i32, i32)>,
+}
+
+impl<'a> QuadBackend<'a> {
+    /// Quads of ``area`` (device px) of the document, with an atlas of pages of ``max_size``
+    /// px at most
+    pub fn new(
+        scale: f64,
+        area: ScissorRect,
+        max_size: i32,
+        pango_layout: &'a mut pango::Layout,
+    ) -> QuadBackend<'a> {
+        let atlas = Atlas::new(max_size);
+        QuadBackend {
+            quads: vec![],
+            // Without the white texel for the solid rects
+            out_of_atlas: atlas.pages.is_empty(),
+            atlas: atlas,
+            scale: scale,
+            area: area,
+            pango_layout: pango_layout,
+            scissors: vec![],
+            images: HashMap::new(),
+        }
+    }
+
+    fn scissor(&self) -> Option<ScissorRect> {
+        self.scissors.last().cloned().unwrap_or(None)
+    }
+
+    /// ``rect`` (CSS px) in device px, rounded out
+    fn device_rect(&self, x: f64, y: f64, width: f64, height: f64) -> ScissorRect {
+        let (left, top) = (
+            (x * self.scale).floor() as i32,
+            (y * self.scale).floor() as i32,
+        );
+        let right = ((x + width) * self.scale).ceil() as i32;
+        let bottom = ((y + height) * self.scale).ceil() as i32;
+        (left, top, (right - left).max(0), (bottom - top).max(0))
+    }
+
+    fn push_scissor(&mut self, rect: ScissorRect) {
+        let scissor = match self.scissor() {
+            Some(clip) => intersect(clip, rect),
+            None => rect,
+        };
+        self.scissors.push(Some(scissor));
+    }
+
+    /// Where a raster of ``width`` x ``height`` is put in the atlas: (page, x, y). None if it
+    /// cannot be, and then the display list is not rendered with quads.
+    fn allocate(&mut self, width: i32, height: i32) -> Option<(usize, i32, i32)> {
+        let texels = self.atlas.allocate(width, height);
+        if texels.is_none() {
+            self.out_of_atlas = true;
+        }
+        texels
+    }
+
+    /// Rasterizes what ``draw`` paints with cairo (in CSS px, in the coordinates of the
+    /// document) within ``bounds`` (CSS px) into the atlas, and adds a quad of it. Only the part
+    /// in the area rendered and the clip is rasterized.
+    fn add_raster<F: FnOnce(&mut CairoBackend)>(&mut self, bounds: (f64, f64, f64, f64), draw: F) {
+        let rect = self.device_rect(bounds.0, bounds.1, bounds.2, bounds.3);
+        let rect = intersect(rect, self.area);
+        let (x, y, width, height) = match self.scissor() {
+            Some(scissor) => intersect(rect, scissor),
+            None => rect,
+        };
+        if width == 0 || height == 0 {
+            return;
+        }
+        let (page, texel_x, texel_y) = match self.allocate(width, height) {
+            Some(texels) => texels,
+            None => return,
+        };
+        let ctx = Context::new(&self.atlas.pages[page].surface);
+        ctx.rectangle(
+            texel_x as f64,
+            texel_y as f64,
+            width as f64,
+            height as f64,
+        );
+        ctx.clip();
+        // The device px of the document at (x, y) is at the texel
+        ctx.translate((texel_x - x) as f64, (texel_y - y) as f64);
+        ctx.scale(self.scale, self.scale);
+        draw(&mut CairoBackend::new(ctx, &mut *self.pango_layout));
+        self.add_quad((x, y, width, height), page, (texel_x, texel_y));
+    }
+
+    fn add_quad(&mut self, rect: ScissorRect, page: usize, texel: (i32, i32)) {
+        let scissor = self.scissor();
+        if scissor.map_or(false, |(_, _, width, height)| width == 0 || height == 0) {
+            return;
+        }
+        self.quads.push(Quad {
+            rect: (rect.0 as f32, rect.1 as f32, rect.2 as f32, rect.3 as f32),
+            page: page,
+            texels: (texel.0 as f32, texel.1 as f32, rect.2 as f32, rect.3 as f32),
+            color: [1.0, 1.0, 1.0, 1.0],
+            scissor: scissor,
+        });
+    }
+
+    /// Where ``image`` scaled to ``metadata`` is in the atlas, rasterized if not yet
+    fn image_texels(
+        &mut self,
+        image: &gdk_pixbuf::Pixbuf,
+        metadata: &ImageMetaData,
+    ) -> Option<(usize, i32, i32, i32, i32)> {
+        let (_, _, width, height) = self.device_rect(
+            0.0,
+            0.0,
+            metadata.width.to_f64_px(),
+            metadata.height.to_f64_px(),
+        );
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let key = (image as *const gdk_pixbuf::Pixbuf as usize, width, height);
+        if let Some(&(page, x, y)) = self.images.get(&key) {
+            return Some((page, x, y, width, height));
+        }
+        let (page, x, y) = self.allocate(width, height)?;
+        let ctx = Context::new(&self.atlas.pages[page].surface);
+        ctx.translate(x as f64, y as f64);
+        ctx.scale(self.scale, self.scale);
+        CairoBackend::new(ctx, &mut *self.pango_layout).draw_image(
+            image,
+            metadata,
+            Rect::default(),
+        );
+        self.images.insert(key, (page, x, y));
+        Some((page, x, y, width, height))
+    }
+}
+
+fn intersect(a: ScissorRect, b: ScissorRect) -> ScissorRect {
+    let (left, top) = (a.0.max(b.0), a.1.max(b.1));
+    let right = (a.0 + a.2).min(b.0 + b.2);
+    let bottom = (a.1 + a.3).min(b.1 + b.3);
+    (left, top, (right - left).max(0), (bottom - top).max(0))
+}
+
+impl<'a> PaintBackend for QuadBackend<'a> {
+    fn fill_rect(&mut self, color: &Color, rect: Rect) {
+        let scissor = self.scissor();
+        if scissor.map_or(false, |(_, _, width, height)| width == 0 || height == 0) {
+            return;
+        }
+        let alpha = color.a as f32 / 255.0;
+        self.quads.push(Quad {
+            rect: (
+                (rect.x.to_f64_px() * self.scale) as f32,
+                (rect.y.to_f64_px() * self.scale) as f32,
+                (rect.width.to_f64_px() * self.scale) as f32,
+                (rect.height.to_f64_px() * self.scale) as f32,
+            ),
+            page: 0,
+            texels: (0.0, 0.0, 1.0, 1.0),
+            color: [
+                color.r as f32 / 255.0 * alpha,
+                color.g as f32 / 255.0 * alpha,
+                color.b as f32 / 255.0 * alpha,
+                alpha,
+            ],
+            scissor: scissor,
+        });
+    }
+
+    fn draw_image(&mut self, image: &gdk_pixbuf::Pixbuf, metadata: &ImageMetaData, rect: Rect) {
+        let tiles = [rect];
+        let clip = Rect {
+            width: metadata.width,
+            height: metadata.height,
+            ..rect
+        };
+        self.draw_image_tiles(image, metadata, &tiles, clip)
+    }
+
+    fn draw_image_tiles(
+        &mut self,
+        image: &gdk_pixbuf::Pixbuf,
+        metadata: &ImageMetaData,
+        tiles: &[Rect],
+        clip: Rect,
+    ) {
+        let (_, _, width, height) = self.device_rect(
+            0.0,
+            0.0,
+            metadata.width.to_f64_px(),
+            metadata.height.to_f64_px(),
+        );
+        if width > ATLAS_PAGE_SIZE || height > ATLAS_PAGE_SIZE {
+            // Too large to be shared by the tiles: the part of each shown is rasterized.
+            self.push_clip(clip);
+            for tile in tiles {
+                let bounds = (
+                    tile.x.to_f64_px(),
+                    tile.y.to_f64_px(),
+                    metadata.width.to_f64_px(),
+                    metadata.height.to_f64_px(),
+                );
+                self.add_raster(bounds, |cairo| cairo.draw_image(image, metadata, *tile));
+            }
+            self.pop_clip();
+            return;
+        }
+        let (page, texel_x, texel_y, width, height) = match self.image_texels(image, metadata) {
+            Some(texels) => texels,
+            None => return,
+        };
+        self.push_clip(clip);
+        for tile in tiles {
+            let (x, y, _, _) = self.device_rect(tile.x.to_f64_px(), tile.y.to_f64_px(), 0.0, 0.0);
+            self.add_quad((x, y, width, height), page, (texel_x, texel_y));
+        }
+        self.pop_clip();
+    }
+
+    fn draw_text(
+        &mut self,
+        text: &str,
+        rect: Rect,
+        color: &Color,
+        decorations: &[TextDecoration],
+        font: &Font,
+    ) {
+        // Glyphs may overhang the rect of the text (e.g. italics).
+        let overhang = rect.height.to_f64_px() / 2.0 + 2.0;
+        let bounds = (
+            rect.x.to_f64_px() - overhang,
+            rect.y.to_f64_px() - overhang,
+            rect.width.to_f64_px() + overhang * 2.0,
+            rect.height.to_f64_px() + overhang * 2.0,
+        );
+        self.add_raster(bounds, |cairo| {
+            cairo.draw_text(text, rect, color, decorations, font)
+        });
+    }
+
+    fn draw_path(&mut self, path: &svg::Path) {
+        if let Some(bounds) = path.bounds() {
+            // Antialiased beyond its bounds
+            let bounds = (
+                bounds.x.to_f64_px() - 1.0,
+                bounds.y.to_f64_px() - 1.0,
+                bounds.width.to_f64_px() + 2.0,
+                bounds.height.to_f64_px() + 2.0,
+            );
+            self.add_raster(bounds, |cairo| cairo.draw_path(path));
+        }
+    }
+
+    fn push_clip(&mut self, rect: Rect) {
+        let rect = self.device_rect(
+            rect.x.to_f64_px(),
+            rect.y.to_f64_px(),
+            rect.width.to_f64_px(),
+            rect.height.to_f64_px(),
+        );
+        self.push_scissor(rect);
+    }
+
+    /// Clipped to the bounds of ``shape``
+    fn push_clip_path(&mut self, shape: &Shape) {
+        let (x, y, width, height) = match *shape {
+            Shape::Ellipse {
+                center: (cx, cy),
+                radius: (rx, ry),
+            } => (cx - rx, cy - ry, rx * 2.0, ry * 2.0),
+            Shape::Inset {
+                rect: (x, y, width, height),
+                ..
+            } => (x, y, width, height),
+            Shape::Polygon(_, ref points) => {
+                let xs = points.iter().map(|&(x, _)| x);
+                let ys = points.iter().map(|&(_, y)| y);
+                let left = xs.clone().fold(::std::f64::INFINITY, f64::min);
+                let right = xs.fold(::std::f64::NEG_INFINITY, f64::max);
+                let top = ys.clone().fold(::std::f64::INFINITY, f64::min);
+                let bottom = ys.fold(::std::f64::NEG_INFINITY, f64::max);
+                if points.is_empty() {
+                    (0.0, 0.0, 0.0, 0.0)
+                } else {
+                    (left, top, right - left, bottom - top)
+                }
+            }
+        };
+        let rect = self.device_rect(x, y, width, height);
+        self.push_scissor(rect);
+    }
+
+    fn pop_clip(&mut self) {
+        self.scissors.pop();
+    }
+
+    /// Without filters (see ``supports``), a layer is painted as if it were not one.
+    fn push_layer(&mut self, _rect: Rect) {}
+
+    fn pop_layer(&mut self, _filters: &[Filter]) {}
+}
+
+/// The wgpu device, and the rendering of the quads with it
+#[cfg(feature = "gpu")]
+mod device {
+    use super::{Atlas, Quad, ScissorRect};
+
+    use std::cell::RefCell;
+    use std::ops::Range;
+
+    use cairo;
+    use pollster;
+    use wgpu::{self, util::DeviceExt};
+
+    const SHADER: &'static str = "
+        struct Viewport { size: vec2<f32> };
+        @group(0) @binding(0) var<uniform> viewport: Viewport;
+        @group(0) @binding(1) var atlas: texture_2d<f32>;
+        @group(0) @binding(2) var atlas_sampler: sampler;
+
+        struct Varyings {
+            @builtin(position) position: vec4<f32>,
+            @location(0) texel: vec2<f32>,
+            @location(1) color: vec4<f32>,
+        };
+
+        @vertex
+        fn vs_main(@location(0) position: vec2<f32>, @location(1) texel: vec2<f32>,
+                   @location(2) color: vec4<f32>) -> Varyings {
+            let ndc = position / viewport.size * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0);
+            return Varyings(vec4<f32>(ndc, 0.0, 1.0), texel, color);
+        }
+
+        @fragment
+        fn fs_main(in: Varyings) -> @location(0) vec4<f32> {
+            let size = vec2<f32>(textureDimensions(atlas));
+            return textureSample(atlas, atlas_sampler, in.texel / size) * in.color;
+        }
+    ";
+
+    /// The format of the target and the pages, that of ``cairo::Format::ARgb32`` (premultiplied)
+    /// on little endian machines
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8Unorm;
+
+    /// Position (2), texel (2) and color (4)
+    const VERTEX_FLOATS: usize = 8;
+
+    struct Gpu {
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        pipeline: wgpu::RenderPipeline,
+        bind_group_layout: wgpu::BindGroupLayout,
+        sampler: wgpu::Sampler,
+    }
+
+    thread_local!(
+        /// Made when first rendered with. None if there is no GPU to render with.
+        static GPU: RefCell<Option<Option<Gpu>>> = { RefCell::new(None) };
+    );
+
+    fn new_gpu() -> Option<Gpu> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        }))?;
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("naglfar"),
+                required_features: wgpu::Features::empty(),
+                required_limits: wgpu::Limits::downlevel_defaults()
+                    .using_resolution(adapter.limits()),
+            },
+            None,
+        )).ok()?;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                        count: None,
+                    },
+                ],
+            });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: (VERTEX_FLOATS * 4) as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float32x2, 1 => Float32x2, 2 => Float32x4
+                    ],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: FORMAT,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        // Rasters are placed on whole device px: sampled texel by texel.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        Some(Gpu {
+            device: device,
+            queue: queue,
+            pipeline: pipeline,
+            bind_group_layout: bind_group_layout,
+            sampler: sampler,
+        })
+    }
+
+    /// The quads drawn in a row with the same page and scissor
+    struct Batch {
+        page: usize,
+        scissor: (u32, u32, u32, u32),
+        vertices: Range<u32>,
+    }
+
+    /// Calls ``f`` with the GPU, made if it is not yet. None if there is no GPU.
+    fn with_gpu<T, F: FnOnce(&Gpu) -> T>(f: F) -> Option<T> {
+        GPU.with(|gpu| {
+            let mut gpu = gpu.borrow_mut();
+            if gpu.is_none() {
+                *gpu = Some(new_gpu());
+            }
+            gpu.as_ref().unwrap().as_ref().map(f)
+        })
+    }
+
+    /// The largest width and height of a texture (and of the target). None if there is no GPU.
+    pub fn max_texture_size() -> Option<i32> {
+        with_gpu(|gpu| gpu.device.limits().max_texture_dimension_2d.min(i32::MAX as u32) as i32)
+    }
+
+    /// Renders ``quads`` onto a new surface of ``area`` (device px) of the document. ``area``
+    /// and the pages of ``atlas`` are at most ``max_texture_size``.
+    pub fn render(quads: &[Quad], atlas: Atlas, area: ScissorRect) -> Option<cairo::ImageSurface> {
+        with_gpu(|gpu| gpu.render(quads, atlas, area))
+    }
+
+    impl Gpu {
+        fn render(&self, quads: &[Quad], atlas: Atlas, area: ScissorRect) -> cairo::ImageSurface {
+            let (left, top) = (area.0 as f32, area.1 as f32);
+            let (width, height) = (area.2.max(1) as u32, area.3.max(1) as u32);
+            let size = |width: u32, height: u32| wgpu::Extent3d {
+                width: width,
+                height: height,
+                depth_or_array_layers: 1,
+            };
+
+            // The pages, uploaded as they are
+            let pages: Vec<wgpu::BindGroup> = atlas
+                .pages
+                .into_iter()
+                .map(|page| {
+                    let mut surface = page.surface;
+                    let (page_width, page_height, stride) = (
+                        surface.get_width() as u32,
+                        surface.get_height() as u32,
+                        surface.get_stride() as u32,
+                    );
+                    let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+                        label: None,
+                        size: size(page_width, page_height),
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format: FORMAT,
+                        usage: wgpu::TextureUsages::TEXTURE_BINDING
+                            | wgpu::TextureUsages::COPY_DST,
+                        view_formats: &[],
+                    });
+                    if let Ok(data) = surface.get_data() {
+                        self.queue.write_texture(
+                            texture.as_image_copy(),
+                            &data[..],
+                            wgpu::ImageDataLayout {
+                                offset: 0,
+                                bytes_per_row: Some(stride),
+                                rows_per_image: None,
+                            },
+                            size(page_width, page_height),
+                        );
+                    }
+                    let viewport = self.device
+                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                            label: None,
+                            contents: &floats_to_bytes(&[width as f32, height as f32]),
+                            usage: wgpu::BufferUsages::UNIFORM,
+                        });
+                    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                    self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: None,
+                        layout: &self.bind_group_layout,
+                        entries: &[
+                            wgpu::BindGroupEntry {
+                                binding: 0,
+                                resource: viewport.as_entire_binding(),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 1,
+                                resource: wgpu::BindingResource::TextureView(&view),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 2,
+                                resource: wgpu::BindingResource::Sampler(&self.sampler),
+                            },
+                        ],
+                    })
+                })
+                .collect();
+
+            // Two triangles a quad, in batches
+            let mut vertices: Vec<f32> = Vec::with_capacity(quads.len() * 6 * VERTEX_FLOATS);
+            let mut batches: Vec<Batch> = vec![];
+            for quad in quads {
+                // In the target, whose top left is that of the area
+                let scissor = match quad.scissor {
+                    Some((x, y, w, h)) => {
+                        let (x, y) = (x - area.0, y - area.1);
+                        let (left, top) = (x.max(0) as u32, y.max(0) as u32);
+                        let right = ((x + w).max(0) as u32).min(width);
+                        let bottom = ((y + h).max(0) as u32).min(height);
+                        if right <= left || bottom <= top {
+                            continue;
+                        }
+                        (left, top, right - left, bottom - top)
+                    }
+                    None => (0, 0, width, height),
+                };
+                let (x, y, w, h) = quad.rect;
+                let (x, y) = (x - left, y - top);
+                let (u, v, uw, vh) = quad.texels;
+                let start = (vertices.len() / VERTEX_FLOATS) as u32;
+                for &(px, py, tu, tv) in &[
+                    (x, y, u, v),
+                    (x + w, y, u + uw, v),
+                    (x, y + h, u, v + vh),
+                    (x + w, y, u + uw, v),
+                    (x + w, y + h, u + uw, v + vh),
+                    (x, y + h, u, v + vh),
+                ] {
+                    vertices.extend_from_slice(&[px, py, tu, tv]);
+                    vertices.extend_from_slice(&quad.color);
+                }
+                let end = start + 6;
+                if let Some(batch) = batches.last_mut() {
+                    if batch.page == quad.page && batch.scissor == scissor {
+                        batch.vertices.end = end;
+                        continue;
+                    }
+                }
+                batches.push(Batch {
+                    page: quad.page,
+                    scissor: scissor,
+                    vertices: start..end,
+                });
+            }
+
+            let target = self.device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: size(width, height),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            });
+            let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+            let vertex_buffer = self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: None,
+                    contents: &floats_to_bytes(&vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                });
+
+            let mut encoder = self.device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            {
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &target_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                pass.set_pipeline(&self.pipeline);
+                if !vertices.is_empty() {
+                    pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                }
+                for batch in &batches {
+                    let (x, y, w, h) = batch.scissor;
+                    pass.set_bind_group(0, &pages[batch.page], &[]);
+                    pass.set_scissor_rect(x, y, w, h);
+                    pass.draw(batch.vertices.clone(), 0..1);
+                }
+            }
+
+            // Read back row by row, the rows of the buffer being aligned
+            let unpadded = width * 4;
+            let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+            let padded = (unpadded + align - 1) / align * align;
+            let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: (padded * height) as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            });
+            encoder.copy_texture_to_buffer(
+                target.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &readback,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(padded),
+                        rows_per_image: None,
+                    },
+                },
+                size(width, height),
+            );
+            self.queue.submit(Some(encoder.finish()));
+
+            let slice = readback.slice(..);
+            slice.map_async(wgpu::MapMode::Read, |_| ());
+            self.device.poll(wgpu::Maintain::Wait);
+
+            let mut surface =
+                cairo::ImageSurface::create(cairo::Format::ARgb32, width as i32, height as i32)
+                    .unwrap();
+            let stride = surface.get_stride() as usize;
+            {
+                let pixels = slice.get_mapped_range();
+                let mut data = surface.get_data().unwrap();
+                for row in 0..height as usize {
+                    let from = row * padded as usize;
+                    data[row * stride..row * stride + unpadded as usize]
+                        .copy_from_slice(&pixels[from..from + unpadded as usize]);
+                }
+            }
+            readback.unmap();
+            surface
+        }
+    }
+
+    fn floats_to_bytes(floats: &[f32]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(floats.len() * 4);
+        for f in floats {
+            bytes.extend_from_slice(&f.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+#[test]
+fn test_shelves() {
+    let mut shelves = Shelves::new(100, 100);
+    assert_eq!(shelves.allocate(40, 20), Some((0, 0)));
+    assert_eq!(shelves.allocate(40, 18), Some((41, 0)));
+    // Not wide enough left in the row
+    assert_eq!(shelves.allocate(30, 20), Some((0, 21)));
+    // Much shorter than the rows: a row of its own
+    assert_eq!(shelves.allocate(10, 5), Some((0, 42)));
+    assert_eq!(shelves.allocate(101, 5), None);
+    assert_eq!(shelves.allocate(10, 60), None);
+}
+
+#[test]
+fn test_atlas() {
+    let mut atlas = Atlas::new(2048);
+    assert_eq!(atlas.pages.len(), 1);
+    // The white texel is at (0, 0) of the first page.
+    assert_eq!(atlas.allocate(10, 10), Some((0, 0, 2)));
+    // Larger than a page: a page of its own
+    assert_eq!(atlas.allocate(ATLAS_PAGE_SIZE + 1, 10), Some((1, 0, 0)));
+    assert_eq!(atlas.pages[1].surface.get_width(), ATLAS_PAGE_SIZE + 1);
+    // Larger than a texture
+    assert_eq!(atlas.allocate(2049, 10), None);
+    assert_eq!(atlas.pages.len(), 2);
+    // Pages no larger than a texture
+    let mut atlas = Atlas::new(512);
+    assert_eq!(atlas.pages[0].surface.get_width(), 512);
+}
+
+#[test]
+fn test_quads() {
+    use app_units::Au;
+
+    let rect = |x: i32, y: i32, width: i32, height: i32| Rect {
+        x: Au::from_px(x),
+        y: Au::from_px(y),
+        width: Au::from_px(width),
+        height: Au::from_px(height),
+    };
+    let red = Color {
+        r: 255,
+        g: 0,
+        b: 0,
+        a: 255,
+    };
+    let surface = cairo::ImageSurface::create(cairo::Format::Rgb24, 0, 0).unwrap();
+    let pango_ctx = ::pangocairo::functions::create_context(&Context::new(&surface)).unwrap();
+    let mut pango_layout = pango::Layout::new(&pango_ctx);
+    let mut backend = QuadBackend::new(2.0, (0, 0, 512, 512), 2048, &mut pango_layout);
+    backend.fill_rect(&red, rect(1, 2, 3, 4));
+    backend.push_clip(rect(0, 0, 10, 10));
+    backend.push_clip(rect(5, 5, 10, 10));
+    backend.fill_rect(&red, rect(0, 0, 20, 20));
+    backend.pop_clip();
+    // Clipped to nothing
+    backend.push_clip(rect(50, 50, 10, 10));
+    backend.fill_rect(&red, rect(0, 0, 20, 20));
+    backend.pop_clip();
+    backend.pop_clip();
+
+    assert_eq!(backend.quads.len(), 2);
+    assert_eq!(backend.quads[0].rect, (2.0, 4.0, 6.0, 8.0));
+    assert_eq!(backend.quads[0].color, [1.0, 0.0, 0.0, 1.0]);
+    assert_eq!(backend.quads[0].scissor, None);
+    assert_eq!(backend.quads[1].scissor, Some((10, 10, 10, 10)));
+
+    // Only the part in the area is rasterized, of a path far larger than a texture.
+    let path = svg::Path {
+        segments: vec![
+            svg::PathSegment::MoveTo(0.0, 0.0),
+            svg::PathSegment::LineTo(100_000.0, 0.0),
+            svg::PathSegment::LineTo(100_000.0, 100_000.0),
+            svg::PathSegment::Close,
+        ],
+        fill: Some((red, ::shape::FillRule::NonZero)),
+        stroke: None,
+    };
+    backend.draw_path(&path);
+    assert!(!backend.out_of_atlas);
+    assert_eq!(backend.quads.len(), 3);
+    assert_eq!(backend.quads[2].rect, (0.0, 0.0, 512.0, 512.0));
+}
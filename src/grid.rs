@@ -0,0 +1,747 @@
+//! Grid layout: the items of a grid container (``display: grid``) are placed in the areas of its
+//! columns and rows (by ``grid-column`` and ``grid-row``, or in the first free cells in order),
+//! which are as large as ``grid-template-columns`` and ``grid-template-rows`` (or as the items
+//! in them), with ``column-gap`` and ``row-gap`` between them. The items fill their areas.
+//! ref. https://www.w3.org/TR/css-grid-1/
+//!
+//! The items are placed by the names of the lines too (``[name]`` in the templates), and by the
+//! areas of ``grid-template-areas`` (``grid-area: name``), whose edges are the lines
+//! ``name-start`` and ``name-end``.
+//!
+//! The columns and the rows not in the templates (the implicit ones) are ``auto``, and the lines
+//! before the first one, or the lines of a name not in the templates, are not supported.
+
+use css::{Unit, Value};
+use layout::{Dimensions, LayoutBox, INDEFINITE_HEIGHT};
+
+use std::cmp::{max, min};
+
+use app_units::Au;
+
+/// The most columns or rows of a grid, so that a large line number or ``repeat()`` count does not
+/// make it exhaust the memory
+const MAX_TRACKS: usize = 1000;
+
+/// The size of a column or a row. ref. https://www.w3.org/TR/css-grid-1/#track-sizing
+#[derive(Clone, Debug, PartialEq)]
+pub enum TrackSize {
+    /// A length, or a percentage of the content box of the container
+    Length(Value),
+    /// A share of the space left by the other tracks (``fr``)
+    Flex(f64),
+    /// As large as its items (``auto``, ``min-content`` or ``max-content``)
+    Auto,
+    /// ``minmax()``: at least the first, at most the second
+    MinMax(Box<TrackSize>, Box<TrackSize>),
+}
+
+/// The line an item starts or ends at in one direction (e.g. ``grid-column-start``)
+#[derive(Clone, Debug, PartialEq)]
+pub enum GridLine {
+    /// Placed automatically
+    Auto,
+    /// The line of a number from 1, or from the last line of the template if it is negative
+    Line(i32),
+    /// As many tracks from the other line
+    Span(u32),
+    /// The line of a name of the number from 1 (from the last one if it is negative), or the
+    /// edge of the area of the name
+    Named(String, i32),
+}
+
+impl TrackSize {
+    fn parse(value: &Value) -> Option<TrackSize> {
+        match *value {
+            Value::Length(fr, Unit::Fr) => Some(TrackSize::Flex(fr.max(0.0))),
+            Value::Length(..) => Some(TrackSize::Length(value.clone())),
+            Value::Keyword(ref k) if ["auto", "min-content", "max-content"].contains(&&**k) => {
+                Some(TrackSize::Auto)
+            }
+            Value::Function(ref name, ref args) if name == "minmax" && args.len() == 2 => {
+                Some(TrackSize::MinMax(
+                    Box::new(TrackSize::parse(&args[0])?),
+                    Box::new(TrackSize::parse(&args[1])?),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// The size of the track in ``available`` px, None if it depends on its items or on the
+    /// space left (or is a percentage of an indefinite size)
+    fn fixed(&self, available: Au) -> Option<Au> {
+        match *self {
+            TrackSize::Length(Value::Length(_, Unit::Percent)) if available < Au(0) => None,
+            TrackSize::Length(ref length) => length
+                .maybe_percent_to_px(available.to_f64_px())
+                .map(|px| max(Au::from_f64_px(px), Au(0))),
+            TrackSize::MinMax(_, ref max) => max.fixed(available),
+            TrackSize::Flex(_) | TrackSize::Auto => None,
+        }
+    }
+
+    /// The share of the space left the track takes
+    fn flex(&self) -> Option<f64> {
+        match *self {
+            TrackSize::Flex(fr) => Some(fr),
+            TrackSize::MinMax(_, ref max) => max.flex(),
+            TrackSize::Length(_) | TrackSize::Auto => None,
+        }
+    }
+
+    /// The size the track is at least in ``available`` px
+    fn min(&self, available: Au) -> Au {
+        match *self {
+            TrackSize::MinMax(ref min, _) => min.fixed(available).unwrap_or(Au(0)),
+            _ => Au(0),
+        }
+    }
+}
+
+impl GridLine {
+    /// The line of ``grid-column-start`` (or of a part of ``grid-column``), e.g. ``2``, ``-1``,
+    /// ``span 2`` or ``main 2``. The span of the lines of a name is a span of one track.
+    pub fn parse(values: &[Value]) -> GridLine {
+        let span = values.contains(&Value::Keyword("span".to_string()));
+        let number = values.iter().find_map(|value| match *value {
+            Value::Num(n) => Some(n.max(-(MAX_TRACKS as f64)).min(MAX_TRACKS as f64) as i32),
+            _ => None,
+        });
+        let name = values.iter().find_map(|value| match *value {
+            Value::Keyword(ref k) if k != "span" && k != "auto" => Some(k.clone()),
+            _ => None,
+        });
+        match (span, number, name) {
+            (true, number, _) => GridLine::Span(max(number.unwrap_or(1), 1) as u32),
+            (false, number, Some(name)) => match number {
+                Some(0) | None => GridLine::Named(name, 1),
+                Some(number) => GridLine::Named(name, number),
+            },
+            (false, Some(number), None) if number != 0 => GridLine::Line(number),
+            (false, _, None) => GridLine::Auto,
+        }
+    }
+
+    /// The line (a ``Line``) of the ``Named`` line of the start (or the end if not ``start``) of
+    /// an item in the lines of ``line_names``, or ``Auto`` if there is no such line
+    fn resolve_name(self, start: bool, line_names: &[(String, usize)]) -> GridLine {
+        let (name, number) = match self {
+            GridLine::Named(name, number) => (name, number),
+            line => return line,
+        };
+        // The edge of the area of the name first
+        let edge = format!("{}-{}", name, if start { "start" } else { "end" });
+        let named = |name: &str| -> Vec<usize> {
+            line_names
+                .iter()
+                .filter(|&&(ref line_name, _)| line_name == name)
+                .map(|&(_, line)| line)
+                .collect()
+        };
+        let mut lines = named(&edge);
+        if lines.is_empty() {
+            lines = named(&name);
+        }
+        let line = if number > 0 {
+            lines.get(number as usize - 1)
+        } else {
+            lines.len().checked_sub(-number as usize).and_then(|i| lines.get(i))
+        };
+        match line {
+            Some(&line) => GridLine::Line(line as i32 + 1),
+            None => GridLine::Auto,
+        }
+    }
+}
+
+/// The tracks of ``grid-template-columns`` or ``grid-template-rows``, and the names of the lines
+/// between them
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrackList {
+    pub tracks: Vec<TrackSize>,
+    /// Each name of a line and the line (from 0)
+    pub line_names: Vec<(String, usize)>,
+}
+
+impl TrackList {
+    fn push(&mut self, value: &Value) {
+        match *value {
+            Value::LineNames(ref names) => for name in names {
+                self.line_names.push((name.clone(), self.tracks.len()));
+            },
+            ref value => self.tracks.extend(TrackSize::parse(value)),
+        }
+    }
+}
+
+/// The tracks of ``grid-template-columns`` or ``grid-template-rows``, with their ``repeat()``s
+/// expanded: ``auto-fill`` and ``auto-fit`` repeat the tracks as many times as they fit in
+/// ``available`` px with ``gap`` between them (once if it is indefinite, or if their sizes are
+/// not fixed). Empty for ``none``.
+pub fn track_list(values: &[Value], available: Au, gap: Au) -> TrackList {
+    let mut list = TrackList::default();
+    for value in values {
+        match *value {
+            Value::Function(ref name, ref args) if name == "repeat" && !args.is_empty() => {
+                let repeated: Vec<TrackSize> =
+                    args[1..].iter().filter_map(TrackSize::parse).collect();
+                let count = match args[0] {
+                    Value::Num(n) if n >= 1.0 => n.min(MAX_TRACKS as f64) as usize,
+                    Value::Keyword(ref k) if k == "auto-fill" || k == "auto-fit" => {
+                        let size = repeated.iter().try_fold(Au(0), |size, track| {
+                            Some(size + track.fixed(available)? + gap)
+                        });
+                        match size {
+                            Some(size) if available >= Au(0) && size > Au(0) => {
+                                max((available + gap).0 / size.0, 1) as usize
+                            }
+                            _ => 1,
+                        }
+                    }
+                    _ => 0,
+                };
+                for _ in 0..min(count, MAX_TRACKS) {
+                    if list.tracks.len() >= MAX_TRACKS {
+                        break;
+                    }
+                    for arg in &args[1..] {
+                        list.push(arg);
+                    }
+                }
+            }
+            ref value => list.push(value),
+        }
+    }
+    list.tracks.truncate(MAX_TRACKS);
+    list
+}
+
+/// The areas of the names of ``grid-template-areas`` (a string of the names of the cells of each
+/// row, ``.`` for those of no name), each around all the cells of its name
+pub fn template_areas(values: &[Value]) -> Vec<(String, Area)> {
+    let mut areas: Vec<(String, Area)> = vec![];
+    let rows = values.iter().filter_map(|value| match *value {
+        Value::Str(ref row) => Some(row),
+        _ => None,
+    });
+    for (row, cells) in rows.take(MAX_TRACKS).enumerate() {
+        for (column, name) in cells.split_whitespace().take(MAX_TRACKS).enumerate() {
+            if name.chars().all(|c| c == '.') {
+                continue;
+            }
+            let name = name.to_lowercase();
+            match areas.iter_mut().find(|area| area.0 == name) {
+                Some(&mut (_, ref mut area)) => {
+                    let (end_column, end_row) = (
+                        max(area.column + area.columns, column + 1),
+                        max(area.row + area.rows, row + 1),
+                    );
+                    area.column = min(area.column, column);
+                    area.columns = end_column - area.column;
+                    area.rows = end_row - area.row;
+                }
+                None => areas.push((
+                    name,
+                    Area {
+                        column: column,
+                        row: row,
+                        columns: 1,
+                        rows: 1,
+                    },
+                )),
+            }
+        }
+    }
+    areas
+}
+
+/// The first track (from 0) of an item placed from ``start`` to ``end`` in a direction of
+/// ``explicit`` tracks in the template, None if it is placed automatically, and how many tracks
+/// it spans
+fn resolve_lines(start: &GridLine, end: &GridLine, explicit: usize) -> (Option<usize>, usize) {
+    // The lines from 0
+    let line = |line: i32| {
+        let line = if line > 0 {
+            line - 1
+        } else {
+            explicit as i32 + 1 + line
+        };
+        min(max(line, 0), MAX_TRACKS as i32 - 1)
+    };
+    let span = |span: u32| min(span as usize, MAX_TRACKS);
+    match (start, end) {
+        (&GridLine::Line(start), &GridLine::Line(end)) => {
+            let (start, end) = (line(start), line(end));
+            let first = min(start, end);
+            (Some(first as usize), max((end - start).abs(), 1) as usize)
+        }
+        (&GridLine::Line(start), &GridLine::Span(n)) => (Some(line(start) as usize), span(n)),
+        (&GridLine::Line(start), _) => (Some(line(start) as usize), 1),
+        (&GridLine::Span(n), &GridLine::Line(end)) => {
+            (Some(max(line(end) - span(n) as i32, 0) as usize), span(n))
+        }
+        (&GridLine::Auto, &GridLine::Line(end)) => (Some(max(line(end) - 1, 0) as usize), 1),
+        (&GridLine::Span(n), _) | (&GridLine::Auto, &GridLine::Span(n)) => (None, span(n)),
+        // The ``Named`` lines are resolved before (see ``GridLine::resolve_name``).
+        _ => (None, 1),
+    }
+}
+
+/// The first column of an item (from 0, None if it is placed automatically) and how many it
+/// spans, and the same of its rows (see ``resolve_lines``)
+type Placement = ((Option<usize>, usize), (Option<usize>, usize));
+
+/// Where an item is in the grid: its first column and row (from 0), and how many it spans
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Area {
+    pub column: usize,
+    pub row: usize,
+    pub columns: usize,
+    pub rows: usize,
+}
+
+/// Which cells of the grid are taken
+struct Cells {
+    columns: usize,
+    /// The rows, each of ``columns`` cells
+    rows: Vec<Vec<bool>>,
+}
+
+impl Cells {
+    fn fits(&self, area: &Area) -> bool {
+        area.column + area.columns <= self.columns
+            && (area.row..area.row + area.rows).all(|row| match self.rows.get(row) {
+                Some(cells) => !cells[area.column..area.column + area.columns].contains(&true),
+                None => true,
+            })
+    }
+
+    fn take(&mut self, area: &Area) {
+        while self.rows.len() < area.row + area.rows {
+            self.rows.push(vec![false; self.columns]);
+        }
+        for row in area.row..area.row + area.rows {
+            for cell in &mut self.rows[row][area.column..area.column + area.columns] {
+                *cell = true;
+            }
+        }
+    }
+}
+
+/// The areas of the items placed by ``placements`` in a grid of ``columns`` columns at least.
+/// Those in a given row are placed first, then the others in order in the first cells free from
+/// where the one before was placed.
+/// ref. https://www.w3.org/TR/css-grid-1/#auto-placement-algo
+fn place_items(placements: &[Placement], columns: usize) -> Vec<Area> {
+    let columns = placements
+        .iter()
+        .map(|&((column, columns), _)| column.unwrap_or(0) + columns)
+        .fold(max(columns, 1), max);
+    let mut cells = Cells {
+        columns: columns,
+        rows: vec![],
+    };
+    let mut areas: Vec<Option<Area>> = vec![None; placements.len()];
+
+    // Those in a given row, in the first columns free in it
+    for (i, &((column, columns), (row, rows))) in placements.iter().enumerate() {
+        let row = match row {
+            Some(row) => row,
+            None => continue,
+        };
+        let mut area = Area {
+            column: column.unwrap_or(0),
+            row: row,
+            columns: columns,
+            rows: rows,
+        };
+        while column.is_none() && !cells.fits(&area) && area.column + columns < cells.columns {
+            area.column += 1;
+        }
+        cells.take(&area);
+        areas[i] = Some(area);
+    }
+
+    // The others from the cursor
+    let (mut cursor_row, mut cursor_column) = (0, 0);
+    for (i, &((column, columns), (_, rows))) in placements.iter().enumerate() {
+        if areas[i].is_some() {
+            continue;
+        }
+        let mut area = Area {
+            column: cursor_column,
+            row: cursor_row,
+            columns: columns,
+            rows: rows,
+        };
+        match column {
+            Some(column) => {
+                if column < cursor_column {
+                    area.row += 1;
+                }
+                area.column = column;
+                while !cells.fits(&area) {
+                    area.row += 1;
+                }
+            }
+            None => while !cells.fits(&area) {
+                area.column += 1;
+                if area.column + columns > cells.columns {
+                    area.column = 0;
+                    area.row += 1;
+                }
+            },
+        }
+        cells.take(&area);
+        cursor_row = area.row;
+        cursor_column = area.column + area.columns;
+        areas[i] = Some(area);
+    }
+    areas.into_iter().map(Option::unwrap).collect()
+}
+
+/// The sizes of the ``tracks`` in ``available`` px (negative if it is indefinite) with ``gap``
+/// between them. ``items`` are the first tracks, the numbers of tracks and the sizes of the items
+/// in them, which those sized by their items are at least as large as. The flexible tracks share
+/// the space left, or are sized by their items if it is indefinite, and the ``auto`` tracks are
+/// stretched to the space left if there are none.
+/// ref. https://www.w3.org/TR/css-grid-1/#algo-track-sizing
+fn size_tracks(
+    tracks: &[TrackSize],
+    available: Au,
+    gap: Au,
+    items: &[(usize, usize, Au)],
+) -> Vec<Au> {
+    let definite = available >= Au(0);
+    let flexible = |track: &TrackSize| if definite { track.flex() } else { None };
+    let sized_by_items =
+        |track: &TrackSize| track.fixed(available).is_none() && flexible(track).is_none();
+
+    let mut sizes: Vec<Au> = tracks
+        .iter()
+        .map(|track| max(track.min(available), track.fixed(available).unwrap_or(Au(0))))
+        .collect();
+    // The items in one track first, then those spanning more grow the tracks they span.
+    let mut items = items.to_vec();
+    items.sort_by_key(|&(_, count, _)| count);
+    for &(first, count, size) in &items {
+        let spanned = first..first + count;
+        let grown: Vec<usize> = spanned
+            .clone()
+            .filter(|&i| sized_by_items(&tracks[i]))
+            .collect();
+        let current = spanned.fold(gap * (count as i32 - 1), |current, i| current + sizes[i]);
+        if grown.is_empty() || current >= size {
+            continue;
+        }
+        let growth = (size - current) / grown.len() as i32;
+        for i in grown {
+            sizes[i] += growth;
+        }
+    }
+    if !definite {
+        return sizes;
+    }
+
+    let used = sizes
+        .iter()
+        .fold(gap * (max(tracks.len(), 1) as i32 - 1), |used, &size| used + size);
+    let total: f64 = tracks.iter().filter_map(|track| flexible(track)).sum();
+    if total > 0.0 {
+        // The space left by the others, of which a ``fr`` is a part, or all if they are less
+        let left = tracks
+            .iter()
+            .zip(&sizes)
+            .filter(|&(track, _)| flexible(track).is_some())
+            .fold(available - used, |left, (_, &size)| left + size);
+        let fr = left.to_f64_px().max(0.0) / total.max(1.0);
+        for (track, size) in tracks.iter().zip(sizes.iter_mut()) {
+            if let Some(flex) = flexible(track) {
+                *size = max(*size, Au::from_f64_px(fr * flex));
+            }
+        }
+    } else if available > used {
+        let stretched: Vec<usize> = (0..tracks.len())
+            .filter(|&i| tracks[i] == TrackSize::Auto)
+            .collect();
+        if !stretched.is_empty() {
+            let growth = (available - used) / stretched.len() as i32;
+            for i in stretched {
+                sizes[i] += growth;
+            }
+        }
+    }
+    sizes
+}
+
+/// Where each track starts from the start of the first one, with ``gap`` between them, and where
+/// the last one ends
+fn track_offsets(sizes: &[Au], gap: Au) -> (Vec<Au>, Au) {
+    let mut offsets = Vec::with_capacity(sizes.len());
+    let mut offset = Au(0);
+    for &size in sizes {
+        offsets.push(offset);
+        offset += size + gap;
+    }
+    let end = if sizes.is_empty() { Au(0) } else { offset - gap };
+    (offsets, end)
+}
+
+/// The size of ``count`` tracks from ``first`` with ``gap`` between them
+fn span_size(sizes: &[Au], first: usize, count: usize, gap: Au) -> Au {
+    sizes[first..first + count]
+        .iter()
+        .fold(gap * (count as i32 - 1), |size, &track| size + track)
+}
+
+impl LayoutBox {
+    /// Lays out the children of a grid container (see the module doc) in its content box, and
+    /// makes it as high as its rows. ``percentage_block`` is the containing block of its
+    /// percentage heights.
+    pub fn layout_grid_items(&mut self, percentage_block: Dimensions, viewport: Dimensions) {
+        let container = self.dimensions;
+        let (width, height) = (container.content.width, percentage_block.content.height);
+        let (row_gap, column_gap) = self.property.gap(width, height);
+        let template = |name: &str, available: Au, gap: Au| {
+            track_list(&self.property.value(name).unwrap_or_default(), available, gap)
+        };
+        let column_template = template("grid-template-columns", width, column_gap);
+        let row_template = template("grid-template-rows", height, row_gap);
+        // The named areas are in the explicit grid, between the lines of their names.
+        let named_areas =
+            template_areas(&self.property.value("grid-template-areas").unwrap_or_default());
+        let (mut column_names, mut row_names) = (
+            column_template.line_names.clone(),
+            row_template.line_names.clone(),
+        );
+        let (mut explicit_columns, mut explicit_rows) =
+            (column_template.tracks.len(), row_template.tracks.len());
+        for &(ref name, area) in &named_areas {
+            column_names.push((format!("{}-start", name), area.column));
+            column_names.push((format!("{}-end", name), area.column + area.columns));
+            row_names.push((format!("{}-start", name), area.row));
+            row_names.push((format!("{}-end", name), area.row + area.rows));
+            explicit_columns = max(explicit_columns, area.column + area.columns);
+            explicit_rows = max(explicit_rows, area.row + area.rows);
+        }
+
+        let placements: Vec<_> = self.children
+            .iter()
+            .map(|child| {
+                let (start, end) = child.property.grid_placement("column");
+                let column = resolve_lines(
+                    &start.resolve_name(true, &column_names),
+                    &end.resolve_name(false, &column_names),
+                    explicit_columns,
+                );
+                let (start, end) = child.property.grid_placement("row");
+                let row = resolve_lines(
+                    &start.resolve_name(true, &row_names),
+                    &end.resolve_name(false, &row_names),
+                    explicit_rows,
+                );
+                (column, row)
+            })
+            .collect();
+        let areas = place_items(&placements, explicit_columns);
+        // The implicit tracks are ``auto``.
+        let tracks = |template: &[TrackSize], count: usize| -> Vec<TrackSize> {
+            (0..max(template.len(), count))
+                .map(|i| template.get(i).cloned().unwrap_or(TrackSize::Auto))
+                .collect()
+        };
+        let columns = tracks(
+            &column_template.tracks,
+            areas.iter().map(|a| a.column + a.columns).fold(explicit_columns, max),
+        );
+        let rows = tracks(
+            &row_template.tracks,
+            areas.iter().map(|a| a.row + a.rows).fold(explicit_rows, max),
+        );
+
+        // The columns, by the widths of the contents of the items in those sized by them
+        let mut widths = vec![];
+        for (child, area) in self.children.iter_mut().zip(&areas) {
+            let spanned = &columns[area.column..area.column + area.columns];
+            if spanned.iter().all(|track| track.fixed(width).is_some()) {
+                continue;
+            }
+            let specified = child.property.value("width").and_then(|values| {
+                values[0].maybe_percent_to_px(max(width, Au(0)).to_f64_px())
+            });
+            let content_width = match specified {
+                Some(px) => Au::from_f64_px(px),
+                None => child.max_content_width(container, percentage_block, viewport),
+            };
+            let edges = child.horizontal_edges(width);
+            widths.push((area.column, area.columns, content_width + edges));
+        }
+        let column_sizes = size_tracks(&columns, width, column_gap, &widths);
+        let (column_offsets, _) = track_offsets(&column_sizes, column_gap);
+
+        // The items in their columns, then the rows by their heights
+        let fixed_rows: Vec<Option<Au>> = rows.iter().map(|track| track.fixed(height)).collect();
+        let mut heights = vec![];
+        for (child, area) in self.children.iter_mut().zip(&areas) {
+            let mut area_block = container;
+            area_block.content.width =
+                span_size(&column_sizes, area.column, area.columns, column_gap);
+            // The percentages of the height of its rows if it does not depend on the items
+            let mut percentage_block = area_block;
+            percentage_block.content.height = fixed_rows[area.row..area.row + area.rows]
+                .iter()
+                .try_fold(row_gap * (area.rows as i32 - 1), |size, &row| Some(size + row?))
+                .unwrap_or(INDEFINITE_HEIGHT);
+            child.layout_item(area_block, percentage_block, viewport);
+            heights.push((area.row, area.rows, child.dimensions.margin_box().height));
+        }
+        let row_sizes = size_tracks(&rows, height, row_gap, &heights);
+        let (row_offsets, rows_end) = track_offsets(&row_sizes, row_gap);
+
+        for (child, area) in self.children.iter_mut().zip(&areas) {
+            child.dimensions.content.x += column_offsets[area.column];
+            child.dimensions.content.y += row_offsets[area.row];
+            let area_height = span_size(&row_sizes, area.row, area.rows, row_gap);
+            child.stretch_height(area_height, percentage_block);
+        }
+        self.dimensions.content.height = rows_end;
+    }
+}
+
+#[test]
+fn test_grid() {
+    use layout;
+
+    let lay_out = |css_src: &str| {
+        let css_src = format!(
+            ".grid {{ display: grid; width: 300px; }} .item {{ height: 10px; }} {}",
+            css_src
+        );
+        layout::lay_out_html(
+            "<div class=\"grid\"><div class=\"item\" id=\"a\"></div> \
+             <div class=\"item\" id=\"b\"></div> <div class=\"item\" id=\"c\"></div></div>",
+            css_src.as_str(),
+            400.0,
+            0.0,
+        )
+    };
+    let areas = |root: &LayoutBox| -> Vec<(f64, f64, f64)> {
+        root.children
+            .iter()
+            .map(|item| {
+                let border_box = item.dimensions.border_box();
+                (
+                    border_box.x.to_f64_px(),
+                    border_box.y.to_f64_px(),
+                    border_box.width.to_f64_px(),
+                )
+            })
+            .collect()
+    };
+
+    // In the next cells, the ``fr`` sharing the space left after the gaps
+    let root = lay_out(
+        ".grid { grid-template-columns: 100px 1fr 2fr; gap: 20px 10px; } #c { height: 30px; }",
+    );
+    assert_eq!(root.children.len(), 3);
+    assert_eq!(
+        areas(&root),
+        vec![(0.0, 0.0, 100.0), (110.0, 0.0, 60.0), (180.0, 0.0, 120.0)]
+    );
+    // A single row as high as the highest of them
+    assert_eq!(root.dimensions.content.height, Au::from_f64_px(30.0));
+
+    // Placed by lines and spans, the others in the cells left after them, in rows added for them
+    let root = lay_out(
+        ".grid { grid-template-columns: repeat(3, 100px); row-gap: 5px; }
+         #a { grid-column: 2 / span 2; } #b { grid-row: 2; grid-column: -2; }",
+    );
+    assert_eq!(
+        areas(&root),
+        vec![(100.0, 0.0, 200.0), (200.0, 15.0, 100.0), (0.0, 15.0, 100.0)]
+    );
+    assert_eq!(root.dimensions.content.height, Au::from_f64_px(25.0));
+
+    // The ``auto`` columns are stretched to fill the container, and the items to fill the rows.
+    let root = lay_out(
+        ".grid { grid-template-columns: 50px auto; grid-template-rows: 40px; }
+         #a { height: auto; }",
+    );
+    assert_eq!(
+        areas(&root),
+        vec![(0.0, 0.0, 50.0), (50.0, 0.0, 250.0), (0.0, 40.0, 50.0)]
+    );
+    assert_eq!(
+        root.children[0].dimensions.content.height,
+        Au::from_f64_px(40.0)
+    );
+}
+
+#[test]
+fn test_grid_areas() {
+    use layout;
+
+    let lay_out = |css_src: &str| {
+        let css_src = format!(
+            ".grid {{ display: grid; width: 300px; grid-template-columns: [side] 100px [main] 1fr \
+             [end];
+               grid-template-areas:
+                 \"header header\"
+                 \"side   main\"
+                 \".      footer\"; }}
+             .grid div {{ height: 10px; }} {}",
+            css_src
+        );
+        layout::lay_out_html(
+            "<div class=\"grid\"><div id=\"a\"></div><div id=\"b\"></div><div id=\"c\"></div>\
+             <div id=\"d\"></div></div>",
+            css_src.as_str(),
+            400.0,
+            0.0,
+        )
+    };
+    let areas = |root: &LayoutBox| -> Vec<(f64, f64, f64)> {
+        root.children
+            .iter()
+            .map(|item| {
+                let content = item.dimensions.content;
+                (
+                    content.x.to_f64_px(),
+                    content.y.to_f64_px(),
+                    content.width.to_f64_px(),
+                )
+            })
+            .collect()
+    };
+
+    // In the areas of their names
+    let root = lay_out(
+        "#a { grid-area: header; } #b { grid-area: side; } #c { grid-area: main; }
+         #d { grid-area: footer; }",
+    );
+    assert_eq!(
+        areas(&root),
+        vec![
+            (0.0, 0.0, 300.0),
+            (0.0, 10.0, 100.0),
+            (100.0, 10.0, 200.0),
+            (100.0, 20.0, 200.0),
+        ]
+    );
+    assert_eq!(root.dimensions.content.height, Au::from_f64_px(30.0));
+
+    // Between the lines of their names, or of the edges of the areas
+    let root = lay_out(
+        "#a { grid-column: main / end; grid-row: footer; } #b { grid-column: side; }
+         #c { grid-area: 1 / header-start / 2 / main-start; } #d { grid-area: side / 2; }",
+    );
+    assert_eq!(
+        areas(&root),
+        vec![
+            (100.0, 20.0, 200.0),
+            (0.0, 10.0, 100.0),
+            (0.0, 0.0, 100.0),
+            (100.0, 10.0, 200.0),
+        ]
+    );
+}
@@ -0,0 +1,99 @@
+/// A visited page.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub url: String,
+    /// The scroll position (in CSS px) when the page was left.
+    pub scroll_y: f64,
+}
+
+/// The session history, the pages visited in the window.
+#[derive(Clone, Debug, PartialEq)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    current: usize,
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            entries: vec![],
+            current: 0,
+        }
+    }
+
+    pub fn current(&self) -> Option<&HistoryEntry> {
+        self.entries.get(self.current)
+    }
+
+    /// Adds a newly visited page after the current one. The pages ahead of the current one are
+    /// forgotten. Loading the current page again doesn't add a new entry.
+    pub fn push(&mut self, url: String) {
+        if self.current().map(|entry| &entry.url) == Some(&url) {
+            return;
+        }
+        if !self.entries.is_empty() {
+            self.entries.truncate(self.current + 1);
+            self.current += 1;
+        }
+        self.entries.push(HistoryEntry {
+            url: url,
+            scroll_y: 0.0,
+        });
+    }
+
+    /// Remembers the scroll position of the current page.
+    pub fn set_scroll_y(&mut self, scroll_y: f64) {
+        if let Some(entry) = self.entries.get_mut(self.current) {
+            entry.scroll_y = scroll_y;
+        }
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.current > 0
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        self.current + 1 < self.entries.len()
+    }
+
+    pub fn back(&mut self) -> Option<&HistoryEntry> {
+        if !self.can_go_back() {
+            return None;
+        }
+        self.current -= 1;
+        self.current()
+    }
+
+    pub fn forward(&mut self) -> Option<&HistoryEntry> {
+        if !self.can_go_forward() {
+            return None;
+        }
+        self.current += 1;
+        self.current()
+    }
+}
+
+#[test]
+fn test_history() {
+    let mut history = History::new();
+    assert!(history.back().is_none());
+
+    history.push("a".to_string());
+    history.push("b".to_string());
+    history.set_scroll_y(100.0);
+    history.push("c".to_string());
+    history.push("c".to_string());
+    assert!(!history.can_go_forward());
+
+    assert_eq!(history.back().map(|e| e.url.as_str()), Some("b"));
+    assert_eq!(history.current().map(|e| e.scroll_y), Some(100.0));
+    assert_eq!(history.back().map(|e| e.url.as_str()), Some("a"));
+    assert!(!history.can_go_back());
+    assert_eq!(history.forward().map(|e| e.url.as_str()), Some("b"));
+
+    // Visiting another page drops "c".
+    history.push("d".to_string());
+    assert!(!history.can_go_forward());
+    assert_eq!(history.back().map(|e| e.url.as_str()), Some("b"));
+    assert_eq!(history.forward().map(|e| e.url.as_str()), Some("d"));
+}
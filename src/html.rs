@@ -1,33 +1,252 @@
 use dom;
+use network::{resolve_url, Url};
+use quirks;
+use srcset;
 
 use std::collections::HashMap;
 use std::cell::RefCell;
-use std::path::PathBuf;
 use std::cmp::max;
-use std::str::from_utf8;
+use std::mem;
 
-thread_local!(pub static CUR_DIR: RefCell<PathBuf> = { RefCell::new(PathBuf::new()) });
+thread_local!(pub static BASE_URL: RefCell<Option<Url>> = { RefCell::new(None) });
 
-pub fn parse(source: String, file_path: PathBuf) -> dom::Node {
-    CUR_DIR.with(|cur_dir| {
-        *cur_dir.borrow_mut() = if let Some(parent) = file_path.parent() {
-            parent.to_path_buf()
+/// Parses ``source``. Relative URLs in ``src`` and ``href`` are resolved against ``base_url``.
+/// Never fails: malformed markup is parsed as far as possible, and the rest is skipped. The
+/// quirks mode of the page is selected from its doctype (see ``quirks::mode_for_doctype``).
+pub fn parse(source: String, base_url: Url) -> dom::Node {
+    BASE_URL.with(|base| *base.borrow_mut() = Some(base_url));
+    let mut parser = Parser::new(source);
+    let mut nodes = parser.parse_to_end();
+    quirks::set_mode(quirks::mode_for_doctype(parser.doctype.as_deref()));
+
+    // If the document contains a root element, just return it. Otherwise, create one.
+    match nodes.first() {
+        Some(&dom::Node {
+            data: dom::NodeType::Element(_),
+            ..
+        }) if nodes.len() == 1 => nodes.swap_remove(0),
+        _ => dom::Node::elem("html".to_string(), HashMap::new(), nodes),
+    }
+}
+
+/// Parses ``source`` as the contents of a ``context`` element (e.g. set to its ``innerHTML``),
+/// and returns the nodes. Relative URLs are resolved against ``base_url``. The contents of a
+/// ``script`` or ``style`` are text.
+pub fn parse_fragment(source: String, context: &str, base_url: Url) -> Vec<dom::Node> {
+    if RAW_TEXT_ELEMENTS.contains(&context) {
+        return if source.is_empty() {
+            vec![]
         } else {
-            // file_path terminates in '/' (like www.example.com/ex1/)
-            file_path.to_path_buf()
+            vec![dom::Node::text(source)]
+        };
+    }
+    BASE_URL.with(|base| *base.borrow_mut() = Some(base_url));
+    Parser::new(source).parse_to_end()
+}
+
+/// The HTML of ``node`` (its ``outerHTML``), which is parsed back to it. Attributes are in the
+/// order of their names.
+pub fn serialize(node: &dom::Node) -> String {
+    let mut html = String::new();
+    serialize_to(node, &mut html);
+    html
+}
+
+/// The HTML of the children of ``node`` (its ``innerHTML``).
+pub fn serialize_children(node: &dom::Node) -> String {
+    let mut html = String::new();
+    for child in &node.children {
+        serialize_to(child, &mut html);
+    }
+    html
+}
+
+fn serialize_to(node: &dom::Node, html: &mut String) {
+    let e = match node.data {
+        dom::NodeType::Text(ref text) => {
+            html.push_str(&escape(text, false));
+            return;
         }
-    });
-    let mut nodes = match Parser::new(source).parse_nodes() {
-        Ok(nodes) => nodes,
-        Err(_) => panic!("unknown error"),
+        dom::NodeType::Element(ref e) => e,
     };
 
-    // If the document contains a root element, just return it. Otherwise, create one.
-    if nodes.len() == 1 {
-        nodes.swap_remove(0)
-    } else {
-        dom::Node::elem("html".to_string(), HashMap::new(), nodes)
+    html.push('<');
+    html.push_str(&e.tag_name);
+    let mut attrs: Vec<(&String, &String)> = e.attrs.iter().collect();
+    attrs.sort();
+    for (name, value) in attrs {
+        html.push_str(&format!(" {}=\"{}\"", name, escape(value, true)));
+    }
+    html.push('>');
+    if is_not_to_close_tag(e.tag_name.as_str()) {
+        return;
+    }
+    for child in &node.children {
+        match child.data {
+            dom::NodeType::Text(ref text) if RAW_TEXT_ELEMENTS.contains(&e.tag_name.as_str()) => {
+                html.push_str(text)
+            }
+            _ => serialize_to(child, html),
+        }
+    }
+    html.push_str(&format!("</{}>", e.tag_name));
+}
+
+/// ``s`` with ``&``, no-break spaces, ``<`` and ``>`` (or ``"`` in an attribute value) replaced
+/// by character references.
+fn escape(s: &str, in_attribute: bool) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '\u{a0}' => escaped.push_str("&nbsp;"),
+            '"' if in_attribute => escaped.push_str("&quot;"),
+            '<' if !in_attribute => escaped.push_str("&lt;"),
+            '>' if !in_attribute => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// ``s`` with the character references (e.g. ``&amp;``, ``&#38;`` and ``&#x26;``) replaced by
+/// the characters. Unknown ones are left as they are.
+fn decode_character_references(s: &str) -> String {
+    let mut decoded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let end = rest.find(';').filter(|&end| end <= MAX_CHARACTER_REFERENCE);
+        let c = end.and_then(|end| match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            name if name.starts_with("#x") || name.starts_with("#X") => {
+                u32::from_str_radix(&name[2..], 16).ok().and_then(::std::char::from_u32)
+            }
+            name if name.starts_with('#') => {
+                name[1..].parse().ok().and_then(::std::char::from_u32)
+            }
+            _ => None,
+        });
+        match (c, end) {
+            (Some(c), Some(end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// The longest character reference decoded (``&#x10ffff;``), without the ``;``
+const MAX_CHARACTER_REFERENCE: usize = 9;
+
+/// The elements whose contents are text (see ``Parser::consume_special_element``)
+const RAW_TEXT_ELEMENTS: [&str; 2] = ["script", "style"];
+
+/// The document for a plain text resource. Each line becomes a block.
+pub fn plain_text_document(text: &str) -> dom::Node {
+    let lines = text.lines()
+        .map(|line| {
+            dom::Node::elem(
+                "div".to_string(),
+                HashMap::new(),
+                // A no-break space keeps the height of empty lines.
+                vec![dom::Node::text(if line.is_empty() { "\u{a0}" } else { line }.to_string())],
+            )
+        })
+        .collect();
+    dom::Node::elem(
+        "html".to_string(),
+        HashMap::new(),
+        vec![dom::Node::elem("body".to_string(), HashMap::new(), lines)],
+    )
+}
+
+/// The index page of the local directory ``dir_url``. ``entries`` are (name, is directory).
+pub fn directory_document(dir_url: &Url, entries: Vec<(String, bool)>) -> dom::Node {
+    fn link(dir_url: &Url, name: &str, is_dir: bool) -> Option<dom::Node> {
+        let mut reference = name.replace('%', "%25")
+            .replace('#', "%23")
+            .replace('?', "%3F");
+        let mut text = name.to_string();
+        if is_dir {
+            reference.push('/');
+            text.push('/');
+        }
+
+        let mut attrs = HashMap::new();
+        attrs.insert(
+            "href".to_string(),
+            resolve_url(dir_url, reference.as_str())?.into_string(),
+        );
+        Some(dom::Node::elem(
+            "div".to_string(),
+            HashMap::new(),
+            vec![dom::Node::elem("a".to_string(), attrs, vec![dom::Node::text(text)])],
+        ))
     }
+
+    let title = format!("Index of {}", dir_url.path());
+    let mut body = vec![
+        dom::Node::elem(
+            "h1".to_string(),
+            HashMap::new(),
+            vec![dom::Node::text(title.clone())],
+        ),
+    ];
+    body.extend(link(dir_url, "..", true));
+    body.extend(
+        entries
+            .iter()
+            .filter_map(|&(ref name, is_dir)| link(dir_url, name.as_str(), is_dir)),
+    );
+
+    dom::Node::elem(
+        "html".to_string(),
+        HashMap::new(),
+        vec![
+            dom::Node::elem(
+                "head".to_string(),
+                HashMap::new(),
+                vec![
+                    dom::Node::elem(
+                        "title".to_string(),
+                        HashMap::new(),
+                        vec![dom::Node::text(title)],
+                    ),
+                ],
+            ),
+            dom::Node::elem("body".to_string(), HashMap::new(), body),
+        ],
+    )
+}
+
+/// The document for an image resource, which just shows ``url``.
+pub fn image_document(url: &str) -> dom::Node {
+    let mut attrs = HashMap::new();
+    attrs.insert("src".to_string(), url.to_string());
+    dom::Node::elem(
+        "html".to_string(),
+        HashMap::new(),
+        vec![
+            dom::Node::elem(
+                "body".to_string(),
+                HashMap::new(),
+                vec![dom::Node::elem("img".to_string(), attrs, vec![])],
+            ),
+        ],
+    )
 }
 
 fn is_not_to_close_tag(tag_name: &str) -> bool {
@@ -42,48 +261,41 @@ fn is_not_to_close_tag(tag_name: &str) -> bool {
     }
 }
 
-pub fn remove_comments(s: &[u8], opening: &str, closing: &str) -> String {
+/// Removes the comments between ``opening`` and ``closing``, which may nest. A comment not
+/// closed lasts until the end, and a ``closing`` outside comments is removed.
+pub fn remove_comments(s: &str, opening: &str, closing: &str) -> String {
     let mut level = 0;
-    let mut pos = 0;
-    let mut ret = "".to_string();
-    let len = s.len();
-    let opening_len = opening.len();
-    let closing_len = closing.len();
+    let mut ret = String::with_capacity(s.len());
+    let mut rest = s;
 
-    if len as isize - max(opening_len, closing_len) as isize - 1 < 0 {
-        return from_utf8(s).unwrap().to_string();
-    }
-
-    while pos < len {
-        if pos < len - opening_len - 1 && s[pos..(pos + opening_len)] == *opening.as_bytes() {
-            pos += opening_len;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with(opening) {
+            rest = &rest[opening.len()..];
             level += 1;
             continue;
         }
-        if pos < len - closing_len && s[pos..(pos + closing_len)] == *closing.as_bytes() {
-            pos += closing_len;
-            if level <= 0 {
-                panic!("not found corresponding \"/*\"")
-            }
-            level -= 1;
+        if rest.starts_with(closing) {
+            rest = &rest[closing.len()..];
+            level = max(level, 1) - 1;
             continue;
         }
         if level == 0 {
-            ret.push(s[pos] as char);
+            ret.push(c);
         }
-        pos += 1;
+        rest = &rest[c.len_utf8()..];
     }
-
-    if level != 0 {
-        panic!("comments are not balanced")
-    }
-
     ret
 }
 
 struct Parser {
     pos: usize,
     input: String,
+    /// What is between ``<!`` and ``>`` of the first doctype, e.g. ``DOCTYPE html``
+    doctype: Option<String>,
+    /// In an ``<svg>`` or a ``<math>``, where ``/>`` closes the elements
+    in_foreign_content: bool,
+    /// Whether a ``<base href>`` has set ``BASE_URL``
+    has_base: bool,
 }
 
 impl Parser {
@@ -91,14 +303,34 @@ impl Parser {
         Parser {
             pos: 0,
             input: input,
+            doctype: None,
+            in_foreign_content: false,
+            has_base: false,
+        }
+    }
+
+    /// The nodes until the end of the input
+    fn parse_to_end(&mut self) -> Vec<dom::Node> {
+        let mut nodes = vec![];
+        while !self.eof() {
+            nodes.append(&mut self.parse_nodes().unwrap_or_else(|_| vec![]));
+            // A closing tag without an opening tag
+            self.consume_while(|c| c != '>').ok();
+            self.consume_char().ok();
         }
+        nodes
     }
 
     fn parse_nodes(&mut self) -> Result<Vec<dom::Node>, ()> {
         let mut nodes: Vec<dom::Node> = vec![];
         loop {
             // TODO: Is this correct?
+            // (The text of a script is not rendered, so the whitespace after it is not kept.)
             match nodes.last() {
+                Some(&dom::Node {
+                    data: dom::NodeType::Element(ref e),
+                    ..
+                }) if e.tag_name == "script" => self.consume_whitespace()?,
                 Some(last) if last.is_inline() && last.contains_text() => {}
                 _ => self.consume_whitespace()?,
             };
@@ -111,8 +343,10 @@ impl Parser {
 
             // DOCTYPE
             if self.starts_with("<!") {
-                self.consume_while(|c| c != '>')?;
-                assert_eq!(self.consume_char()?, '>');
+                self.pos += 2;
+                let doctype = self.consume_while(|c| c != '>')?;
+                self.doctype.get_or_insert(doctype);
+                self.skip_char_if_any('>')?;
                 continue;
             }
 
@@ -137,16 +371,27 @@ impl Parser {
 
     fn parse_element(&mut self) -> Result<dom::Node, ()> {
         // Opening tag.
-        assert_eq!(self.consume_char()?, '<');
+        self.expect_char('<')?;
         let tag_name = self.parse_tag_name()?;
         let attrs = self.parse_attributes()?;
-        assert_eq!(self.consume_char()?, '>');
+        let self_closing = self.input[..self.pos].ends_with('/');
+        self.expect_char('>')?;
+
+        // The URLs after the first ``<base href>`` (resolved against the document URL) are
+        // relative to it.
+        if tag_name == "base" && !self.has_base {
+            if let Some(base_url) = attrs.get("href").and_then(|href| Url::parse(href).ok()) {
+                BASE_URL.with(|base| *base.borrow_mut() = Some(base_url));
+                self.has_base = true;
+            }
+        }
 
-        if is_not_to_close_tag(tag_name.as_str()) {
+        let foreign = self.in_foreign_content || tag_name == "svg" || tag_name == "math";
+        if is_not_to_close_tag(tag_name.as_str()) || (foreign && self_closing) {
             return Ok(dom::Node::elem(tag_name, attrs, vec![]));
         }
 
-        if tag_name == "script" || tag_name == "style" {
+        if RAW_TEXT_ELEMENTS.contains(&tag_name.as_str()) {
             return Ok(dom::Node::elem(
                 tag_name,
                 attrs,
@@ -155,15 +400,16 @@ impl Parser {
         }
 
         // Contents.
-        let children = self.parse_nodes()?;
+        let outer_foreign = mem::replace(&mut self.in_foreign_content, foreign);
+        let children = self.parse_nodes();
+        self.in_foreign_content = outer_foreign;
+        let children = children?;
 
-        // Closing tag.
-        if !self.eof() {
-            assert_eq!(self.consume_char()?, '<');
-            assert_eq!(self.consume_char()?, '/');
+        // Closing tag. (Missing at the end of the input)
+        if self.skip_str_if_any("</") {
             // assert_eq!(, tag_name);
-            self.parse_tag_name()?;
-            assert_eq!(self.consume_char()?, '>');
+            self.consume_while(|c| c != '>')?;
+            self.skip_char_if_any('>')?;
         }
 
         Ok(dom::Node::elem(tag_name, attrs, children))
@@ -192,10 +438,18 @@ impl Parser {
     }
 
     fn parse_attr(&mut self) -> Result<(String, String), ()> {
-        let name = self.parse_tag_name()?;
-        if self.consume_char()? != '=' {
+        // e.g. ``data-id`` and ``stroke-width``
+        let name =
+            self.consume_while(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == ':')?;
+        if name.is_empty() {
+            self.consume_char()?;
             return Err(());
         }
+        // A boolean attribute (e.g. ``selected``) is empty.
+        if self.next_char()? != '=' {
+            return Ok((name, "".to_string()));
+        }
+        self.consume_char()?;
         let value = self.parse_attr_value()?;
         Ok((name, value))
     }
@@ -211,30 +465,26 @@ impl Parser {
         if open_quote_appeared {
             self.consume_char()?; // Maybe " or '
         }
-        Ok(value)
+        Ok(decode_character_references(&value))
     }
 
     fn parse_text(&mut self) -> Result<dom::Node, ()> {
         let mut last = '*'; // any char except space
-        Ok(dom::Node::text(
-            self.consume_while(|c| c != '<')?
-                .chars()
-                .fold("".to_string(), |mut s, c| {
-                    if !(last.is_whitespace() && c.is_whitespace()) {
-                        s.push(if c.is_whitespace() { ' ' } else { c });
-                    }
-                    last = c;
-                    s
-                }),
-        ))
+        let text = self.consume_while(|c| c != '<')?
+            .chars()
+            .fold("".to_string(), |mut s, c| {
+                if !(last.is_whitespace() && c.is_whitespace()) {
+                    s.push(if c.is_whitespace() { ' ' } else { c });
+                }
+                last = c;
+                s
+            });
+        Ok(dom::Node::text(decode_character_references(&text)))
     }
 
     fn consume_comment(&mut self) -> Result<(), ()> {
         while !self.eof() {
-            if self.starts_with("-->") {
-                assert_eq!('-', self.consume_char()?);
-                assert_eq!('-', self.consume_char()?);
-                assert_eq!('>', self.consume_char()?);
+            if self.skip_str_if_any("-->") {
                 break;
             }
             self.consume_char()?;
@@ -247,7 +497,7 @@ impl Parser {
         while !self.eof() {
             if self.starts_with("</") {
                 self.consume_while(|c| c != '>')?;
-                assert_eq!(self.consume_char()?, '>');
+                self.skip_char_if_any('>')?;
                 break;
             }
             body.push(self.consume_char()?);
@@ -271,13 +521,36 @@ impl Parser {
     }
 
     fn consume_char(&mut self) -> Result<char, ()> {
-        let mut iter = self.input[self.pos..].char_indices();
-        let (_, cur_char) = iter.next().ok_or(())?;
-        let (next_pos, _) = iter.next().unwrap_or((1, ' '));
-        self.pos += next_pos;
+        let cur_char = self.next_char()?;
+        self.pos += cur_char.len_utf8();
         Ok(cur_char)
     }
 
+    /// Consumes ``c`` if it comes next. Otherwise, returns an error without consuming anything.
+    fn expect_char(&mut self, c: char) -> Result<(), ()> {
+        if self.skip_char_if_any(c)? {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    fn skip_char_if_any(&mut self, c: char) -> Result<bool, ()> {
+        if !self.eof() && self.next_char()? == c {
+            self.consume_char()?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn skip_str_if_any(&mut self, s: &str) -> bool {
+        let found = self.starts_with(s);
+        if found {
+            self.pos += s.len();
+        }
+        found
+    }
+
     fn next_char(&self) -> Result<char, ()> {
         self.input[self.pos..].chars().next().ok_or(())
     }
@@ -292,31 +565,42 @@ impl Parser {
 }
 
 fn url_conv(attr: (String, String)) -> (String, String) {
+    let resolve = |url: &str| {
+        BASE_URL.with(|base| {
+            base.borrow()
+                .as_ref()
+                .and_then(|base| resolve_url(base, url))
+                .map(|url| url.into_string())
+        })
+    };
     match attr.0.to_lowercase().as_str() {
         "src" | "href" => {
-            if attr.1.len() > 0 && attr.1.chars().next().unwrap() == '#' {
+            if attr.1.starts_with('#') {
                 // URL Fragment
-                (attr.0.clone(), attr.1.clone())
-            } else if attr.1.starts_with("http") || attr.1.starts_with("file") {
-                // Absolute URL
-                (attr.0.clone(), attr.1.clone())
+                (attr.0, attr.1)
             } else {
-                // Normal URL
-                (
-                    attr.0.clone(),
-                    CUR_DIR.with(|dir| dir.borrow().join(attr.1).to_str().unwrap().to_string()),
-                )
+                let resolved = resolve(attr.1.as_str());
+                // Leave invalid URLs untouched.
+                (attr.0, resolved.unwrap_or(attr.1))
             }
         }
+        "srcset" => {
+            let mut candidates = srcset::parse_srcset(attr.1.as_str());
+            for candidate in &mut candidates {
+                if let Some(resolved) = resolve(candidate.url.as_str()) {
+                    candidate.url = resolved;
+                }
+            }
+            (attr.0, srcset::serialize_srcset(&candidates))
+        }
         _ => (attr.0, attr.1),
     }
 }
 
 #[test]
 fn test1() {
-    use std::path::Path;
     let src = "<html><head></head><body><div id=\"x\">test</div><p>paragrapgh</p><span>aa</span>\n  space<img src='a.png'></body></html>";
-    let dom_node = parse(src.to_string(), Path::new("./a/a.html").to_path_buf());
+    let dom_node = parse(src.to_string(), Url::parse("file:///a/a.html").unwrap());
     assert_eq!(
         dom_node,
         dom::Node::elem(
@@ -352,7 +636,7 @@ fn test1() {
                             "img".to_string(),
                             {
                                 let mut h = HashMap::new();
-                                h.insert("src".to_string(), "./a/a.png".to_string());
+                                h.insert("src".to_string(), "file:///a/a.png".to_string());
                                 h
                             },
                             vec![],
@@ -366,11 +650,153 @@ fn test1() {
 
 #[test]
 fn test_empty_source() {
-    use std::path::Path;
     let src = "";
-    let dom_node = parse(src.to_string(), Path::new("a.html").to_path_buf());
+    let dom_node = parse(src.to_string(), Url::parse("file:///a.html").unwrap());
     assert_eq!(
         dom_node,
         dom::Node::elem("html".to_string(), HashMap::new(), vec![])
     );
 }
+
+#[test]
+fn test_remove_comments() {
+    assert_eq!(remove_comments("a<!--b-->c", "<!--", "-->"), "ac");
+    assert_eq!(remove_comments("é<!--<!--b-->-->日", "<!--", "-->"), "é日");
+    assert_eq!(remove_comments("a<!--b", "<!--", "-->"), "a");
+    assert_eq!(remove_comments("a-->b", "<!--", "-->"), "ab");
+}
+
+#[test]
+fn test_malformed_source() {
+    let src = "</p><div>a</span><è =\"";
+    let dom_node = parse(src.to_string(), Url::parse("file:///a.html").unwrap());
+    assert_eq!(
+        dom_node,
+        dom::Node::elem(
+            "div".to_string(),
+            HashMap::new(),
+            vec![dom::Node::text("a".to_string())]
+        )
+    );
+}
+
+#[test]
+fn test_plain_text_document() {
+    let dom_node = plain_text_document("a\n\n<b>");
+    assert_eq!(
+        dom_node,
+        dom::Node::elem(
+            "html".to_string(),
+            HashMap::new(),
+            vec![
+                dom::Node::elem(
+                    "body".to_string(),
+                    HashMap::new(),
+                    vec![
+                        dom::Node::elem(
+                            "div".to_string(),
+                            HashMap::new(),
+                            vec![dom::Node::text("a".to_string())],
+                        ),
+                        dom::Node::elem(
+                            "div".to_string(),
+                            HashMap::new(),
+                            vec![dom::Node::text("\u{a0}".to_string())],
+                        ),
+                        dom::Node::elem(
+                            "div".to_string(),
+                            HashMap::new(),
+                            vec![dom::Node::text("<b>".to_string())],
+                        ),
+                    ],
+                ),
+            ]
+        )
+    );
+}
+
+#[test]
+fn test_directory_document() {
+    let dom_node = directory_document(
+        &Url::parse("file:///home/user/").unwrap(),
+        vec![("src".to_string(), true), ("a #1.html".to_string(), false)],
+    );
+    let mut links = vec![];
+    dom_node.find_nodes_by_tag_name("a", &mut links);
+    assert_eq!(
+        links
+            .iter()
+            .map(|a| a.anker_url().unwrap().as_str())
+            .collect::<Vec<&str>>(),
+        vec![
+            "file:///home/",
+            "file:///home/user/src/",
+            "file:///home/user/a%20%231.html",
+        ]
+    );
+}
+
+#[test]
+fn test_fragment() {
+    let url = Url::parse("file:///a/a.html").unwrap();
+    let source = "<b class=\"x &quot;y&quot;\">a &amp; b&nbsp;&#60;&#x3e;</b> <a href=\"b.html\">\
+                  &unknown; &amp &amp;</a><br>";
+    let nodes = parse_fragment(source.to_string(), "div", url.clone());
+    assert_eq!(nodes.len(), 4);
+    assert_eq!(nodes[0].children, vec![dom::Node::text("a & b\u{a0}<>".to_string())]);
+    assert_eq!(nodes[2].anker_url(), Some(&"file:///a/b.html".to_string()));
+
+    let root = dom::Node::elem("div".to_string(), HashMap::new(), nodes.clone());
+    let html = serialize_children(&root);
+    assert_eq!(
+        html,
+        "<b class=\"x &quot;y&quot;\">a &amp; b&nbsp;&lt;&gt;</b> \
+         <a href=\"file:///a/b.html\">&amp;unknown; &amp;amp &amp;</a><br>"
+    );
+    assert_eq!(parse_fragment(html, "div", url.clone()), nodes);
+    assert_eq!(serialize(&root), format!("<div>{}</div>", serialize_children(&root)));
+
+    let script = parse_fragment("a < b".to_string(), "script", url.clone());
+    assert_eq!(script, vec![dom::Node::text("a < b".to_string())]);
+    let script = dom::Node::elem("script".to_string(), HashMap::new(), script);
+    assert_eq!(serialize(&script), "<script>a < b</script>");
+}
+
+#[test]
+fn test_base_url() {
+    let src = "<html><head><link rel=\"stylesheet\" href=\"a.css\"><base href=\"../b/\">\
+               <base href=\"/c/\"></head><body><a href=\"d.html\"></a><img src=\"e.png\" \
+               srcset=\"e-2x.png 2x\"><a href=\"#f\"></a></body></html>";
+    let dom_node = parse(src.to_string(), Url::parse("http://example.com/a/a.html").unwrap());
+    let (head, body) = (&dom_node.children[0], &dom_node.children[1]);
+    let attr = |node: &dom::Node, name: &str| match node.data {
+        dom::NodeType::Element(ref e) => e.attrs[name].clone(),
+        dom::NodeType::Text(_) => unreachable!(),
+    };
+    assert_eq!(attr(&head.children[0], "href"), "http://example.com/a/a.css");
+    assert_eq!(attr(&head.children[1], "href"), "http://example.com/b/");
+    assert_eq!(
+        dom_node.find_base_url().map(|url| url.into_string()),
+        Some("http://example.com/b/".to_string())
+    );
+    // Only the first one
+    assert_eq!(attr(&body.children[0], "href"), "http://example.com/b/d.html");
+    assert_eq!(attr(&body.children[1], "src"), "http://example.com/b/e.png");
+    assert_eq!(attr(&body.children[1], "srcset"), "http://example.com/b/e-2x.png 2x");
+    assert_eq!(attr(&body.children[2], "href"), "#f");
+}
+
+#[test]
+fn test_foreign_content() {
+    let src = "<div/><p><svg viewBox=\"0 0 10 10\"><rect stroke-width=\"2\"/>\
+               <circle r=\"1\"/></svg></p></div>";
+    let dom_node = parse(src.to_string(), Url::parse("file:///a.html").unwrap());
+    // ``/>`` closes only the elements in the SVG.
+    let svg = &dom_node.children[0].children[0];
+    assert_eq!(svg.children.len(), 2);
+    assert_eq!(svg.children[0].attr("stroke-width"), Some(::css::Value::Num(2.0)));
+    match svg.data {
+        dom::NodeType::Element(ref e) => assert_eq!(e.attrs["viewBox"], "0 0 10 10"),
+        dom::NodeType::Text(_) => unreachable!(),
+    }
+}
@@ -0,0 +1,255 @@
+//! Hyphenation with the patterns of Liang's algorithm (the ones TeX and LibreOffice use).
+//! ref. https://www.tug.org/docs/liang/
+//!
+//! The dictionaries are the ``hyph_<lang>.dic`` files of libhyphen in ``HYPHENATION_DIR``.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+
+pub const HYPHENATION_DIR: &'static str = "/usr/share/hyphen";
+
+/// The ``hyphens`` property.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hyphens {
+    None,
+    /// Only at soft hyphens (U+00AD).
+    Manual,
+    /// Also where the dictionary of the language allows.
+    Auto,
+}
+
+const SOFT_HYPHEN: char = '\u{ad}';
+
+/// Shown at the end of a line broken in a word. The hyphen-minus is in (almost) every font.
+pub const HYPHEN: &'static str = "-";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Patterns {
+    /// e.g. "ach" => [0, 0, 0, 4] for the pattern ``ach4``
+    patterns: HashMap<String, Vec<u8>>,
+    /// The minimum numbers of the characters before and after a hyphen.
+    left_min: usize,
+    right_min: usize,
+}
+
+impl Patterns {
+    /// Parses a libhyphen dictionary: the encoding on the first line, then one pattern a line.
+    /// Only UTF-8 dictionaries are supported.
+    pub fn parse(src: &str) -> Patterns {
+        let mut patterns = Patterns {
+            patterns: HashMap::new(),
+            left_min: 2,
+            right_min: 2,
+        };
+
+        for line in src.lines().skip(1) {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next().and_then(|n| n.parse().ok())) {
+                (Some("LEFTHYPHENMIN"), Some(n)) => patterns.left_min = n,
+                (Some("RIGHTHYPHENMIN"), Some(n)) => patterns.right_min = n,
+                // Non-standard hyphenation (e.g. ``c1k/k=k``) is not supported.
+                (Some(pattern), None) if !pattern.starts_with('%') && !pattern.contains('/') => {
+                    let mut letters = String::new();
+                    let mut values = vec![0];
+                    for c in pattern.chars() {
+                        match c.to_digit(10) {
+                            Some(n) => *values.last_mut().unwrap() = n as u8,
+                            None => {
+                                letters.push(c);
+                                values.push(0);
+                            }
+                        }
+                    }
+                    patterns.patterns.insert(letters, values);
+                }
+                _ => {}
+            }
+        }
+        patterns
+    }
+
+    /// The byte offsets in ``word`` where a hyphen may be inserted.
+    pub fn hyphenation_points(&self, word: &str) -> Vec<usize> {
+        let offsets: Vec<usize> = word.char_indices().map(|(i, _)| i).collect();
+        let len = offsets.len();
+        if len < self.left_min + self.right_min {
+            return vec![];
+        }
+
+        let chars: Vec<char> = Some('.')
+            .into_iter()
+            .chain(word.chars().flat_map(|c| c.to_lowercase()))
+            .chain(Some('.'))
+            .collect();
+        // A lowercased character may have more than one character. Such words are skipped.
+        if chars.len() != len + 2 {
+            return vec![];
+        }
+
+        // values[i] is for the point before chars[i].
+        let mut values = vec![0u8; chars.len() + 1];
+        for start in 0..chars.len() {
+            let mut letters = String::new();
+            for (end, &c) in chars.iter().enumerate().skip(start) {
+                letters.push(c);
+                if let Some(pattern) = self.patterns.get(&letters) {
+                    for (i, &value) in pattern.iter().enumerate() {
+                        values[start + i] = values[start + i].max(value);
+                    }
+                }
+                if end - start > 8 {
+                    break; // Patterns are short.
+                }
+            }
+        }
+
+        // The point before the i-th character of the word is values[i + 1] (after '.').
+        (self.left_min..len + 1 - self.right_min)
+            .filter(|&i| values[i + 1] % 2 == 1)
+            .map(|i| offsets[i])
+            .collect()
+    }
+}
+
+// The dictionaries loaded (or not found) for each language
+static DICTIONARIES: Mutex<Vec<(String, Option<Arc<Patterns>>)>> = Mutex::new(Vec::new());
+
+/// The dictionary for ``lang`` (e.g. ``en-US``). Falls back on another dictionary of the
+/// same language (e.g. ``hyph_en_GB.dic`` for ``en``).
+pub fn dictionary(lang: &str) -> Option<Arc<Patterns>> {
+    let lang = lang.replace('-', "_");
+    let mut dictionaries = DICTIONARIES.lock().unwrap();
+    if let Some(&(_, ref patterns)) = dictionaries.iter().find(|&&(ref l, _)| *l == lang) {
+        return patterns.clone();
+    }
+
+    let primary = lang.split('_').next().unwrap_or("").to_string();
+    let exact = format!("{}/hyph_{}.dic", HYPHENATION_DIR, lang);
+    let file_name = if fs::metadata(&exact).is_ok() {
+        Some(exact)
+    } else {
+        fs::read_dir(HYPHENATION_DIR).ok().and_then(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .find(|path| match path.file_name().and_then(|name| name.to_str()) {
+                    Some(name) => {
+                        name.starts_with(format!("hyph_{}", primary).as_str())
+                            && name.ends_with(".dic")
+                    }
+                    None => false,
+                })
+                .map(|path| path.to_string_lossy().into_owned())
+        })
+    };
+
+    let patterns = file_name.and_then(|file_name| {
+        let mut src = String::new();
+        fs::File::open(file_name)
+            .and_then(|mut file| file.read_to_string(&mut src))
+            .ok()
+            .map(|_| Arc::new(Patterns::parse(src.as_str())))
+    });
+    dictionaries.push((lang, patterns.clone()));
+    patterns
+}
+
+/// Finds where words may be broken with a hyphen.
+#[derive(Clone, Debug)]
+pub struct Hyphenator {
+    hyphens: Hyphens,
+    patterns: Option<Arc<Patterns>>,
+}
+
+impl Hyphenator {
+    /// ``lang`` is needed for ``hyphens: auto``.
+    pub fn new(hyphens: Hyphens, lang: Option<&str>) -> Hyphenator {
+        Hyphenator {
+            hyphens: hyphens,
+            patterns: match (hyphens, lang) {
+                (Hyphens::Auto, Some(lang)) => dictionary(lang),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn with_patterns(hyphens: Hyphens, patterns: Option<Arc<Patterns>>) -> Hyphenator {
+        Hyphenator {
+            hyphens: hyphens,
+            patterns: patterns,
+        }
+    }
+
+    /// The byte offsets in ``word`` where it may be broken with a hyphen (after soft hyphens).
+    pub fn hyphenation_points(&self, word: &str) -> Vec<usize> {
+        if self.hyphens == Hyphens::None {
+            return vec![];
+        }
+        if word.contains(SOFT_HYPHEN) {
+            // Soft hyphens turn off the automatic hyphenation of the word.
+            return word.match_indices(SOFT_HYPHEN)
+                .map(|(i, _)| i + SOFT_HYPHEN.len_utf8())
+                .collect();
+        }
+        match self.patterns {
+            Some(ref patterns) if self.hyphens == Hyphens::Auto => {
+                patterns.hyphenation_points(word)
+            }
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_patterns() -> Patterns {
+    // Some of the patterns for "hyphenation" in hyph-en-us.tex
+    Patterns::parse(
+        "UTF-8
+LEFTHYPHENMIN 2
+RIGHTHYPHENMIN 3
+hy3ph
+he2n
+hena4
+hen5at
+1na
+n2at
+1tio
+2io
+o2n",
+    )
+}
+
+#[test]
+fn test_hyphenation_points() {
+    let patterns = test_patterns();
+    let split = |word: &str| {
+        let mut parts = vec![];
+        let mut start = 0;
+        for point in patterns.hyphenation_points(word) {
+            parts.push(word[start..point].to_string());
+            start = point;
+        }
+        parts.push(word[start..].to_string());
+        parts
+    };
+
+    assert_eq!(split("hyphenation"), vec!["hy", "phen", "ation"]);
+    assert_eq!(split("Hyphenation"), vec!["Hy", "phen", "ation"]);
+    assert_eq!(split("hyp"), vec!["hyp"]);
+}
+
+#[test]
+fn test_hyphenator() {
+    let patterns = Some(Arc::new(test_patterns()));
+    let auto = Hyphenator::with_patterns(Hyphens::Auto, patterns.clone());
+    let manual = Hyphenator::with_patterns(Hyphens::Manual, patterns.clone());
+    let none = Hyphenator::with_patterns(Hyphens::None, patterns);
+
+    assert_eq!(auto.hyphenation_points("hyphenation"), vec![2, 6]);
+    assert!(manual.hyphenation_points("hyphenation").is_empty());
+    assert_eq!(manual.hyphenation_points("hyphen\u{ad}ation"), vec![8]);
+    assert_eq!(auto.hyphenation_points("hyphen\u{ad}ation"), vec![8]);
+    assert!(none.hyphenation_points("hyphen\u{ad}ation").is_empty());
+}
@@ -0,0 +1,47 @@
+//! The decoded images painted (see ``painter::DisplayCommand::Image``): the pixbufs of
+//! gdk-pixbuf, with the ``raster`` feature. Without it, no image is decoded, and the images are
+//! painted as placeholders until they are loaded, that is always.
+
+#[cfg(feature = "raster")]
+pub use gdk_pixbuf::Pixbuf as Image;
+#[cfg(feature = "raster")]
+pub use gdk_pixbuf::PixbufExt;
+
+/// The image decoded from the file ``file_name``, None if it is broken.
+#[cfg(feature = "raster")]
+pub fn decode(file_name: &str) -> Option<Image> {
+    Image::new_from_file(file_name).ok()
+}
+
+#[cfg(not(feature = "raster"))]
+pub fn decode(_file_name: &str) -> Option<Image> {
+    None
+}
+
+/// No image (there is none of this type)
+#[cfg(not(feature = "raster"))]
+#[derive(Clone, Debug)]
+pub enum Image {}
+
+/// The methods of the pixbufs used on the images
+#[cfg(not(feature = "raster"))]
+pub trait PixbufExt {
+    fn get_width(&self) -> i32;
+    fn get_height(&self) -> i32;
+    fn new_subpixbuf(&self, x: i32, y: i32, width: i32, height: i32) -> Option<Image>;
+}
+
+#[cfg(not(feature = "raster"))]
+impl PixbufExt for Image {
+    fn get_width(&self) -> i32 {
+        match *self {}
+    }
+
+    fn get_height(&self) -> i32 {
+        match *self {}
+    }
+
+    fn new_subpixbuf(&self, _x: i32, _y: i32, _width: i32, _height: i32) -> Option<Image> {
+        match *self {}
+    }
+}
@@ -1,15 +1,18 @@
 use css::Value;
 use dom::NodeType;
 use font::Font;
-use layout::{BoxType, Dimensions, ImageData, LayoutBox, LayoutInfo, Rect, Text};
+use bidi;
+use layout::{BoxType, Dimensions, ImageData, LayoutBox, LayoutInfo, Rect, Text, INDEFINITE_HEIGHT};
 use float::Floats;
+use style::{Direction, Style, DEFAULT_FONT_SIZE, DEFAULT_LINE_HEIGHT_SCALE};
 
 use std::ops::Range;
 use std::collections::{HashMap, VecDeque};
 use std::cmp::max;
 
-use gdk_pixbuf::PixbufExt;
-use gdk_pixbuf;
+use image::Image;
+#[cfg(feature = "raster")]
+use image::PixbufExt;
 
 use app_units::Au;
 
@@ -41,6 +44,14 @@ impl LineMetrics {
     pub fn calculate_line_height(&self) -> Au {
         self.above_baseline + self.under_baseline
     }
+
+    /// Of a text with the font ``style`` (e.g. the strut of a block): half the leading of its
+    /// ``line-height`` is above it, and the other half under it.
+    pub fn of_text(style: &mut Style) -> LineMetrics {
+        let (ascent, descent) = style.font().get_ascent_descent();
+        let half_leading = (style.line_height() - (ascent + descent)) / 2;
+        LineMetrics::new(ascent + half_leading, half_leading + descent)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -55,6 +66,19 @@ pub struct LineMaker {
     pub cur_width: Au,
     pub cur_height: Au,
     pub cur_metrics: LineMetrics,
+    /// The lines with boxes are at least as high as this, that of a text of the block. None in
+    /// quirks modes, where lines of images alone are as high as them (see ``quirks``).
+    pub strut: Option<LineMetrics>,
+}
+
+/// Boxes other than texts (e.g. images) are neutral.
+fn bidi_class_of_box(layout_box: &LayoutBox) -> bidi::BidiClass {
+    match (&layout_box.box_type, &layout_box.node.data) {
+        (&BoxType::TextNode(ref text_info), &NodeType::Text(ref text)) => {
+            bidi::text_class(&text[text_info.range.clone()])
+        }
+        _ => bidi::BidiClass::Neutral,
+    }
 }
 
 impl LineMaker {
@@ -75,6 +99,7 @@ impl LineMaker {
             cur_width: Au(0),
             cur_height: Au(0),
             cur_metrics: LineMetrics::new(Au(0), Au(0)),
+            strut: None,
         }
     }
 
@@ -93,6 +118,9 @@ impl LineMaker {
                 .available_area(max_width, self.cur_height, Au(1))
         };
         let mut max_width_considered_float = self.pending.zone.width;
+        // Where the zone is for, as the lines next to a shaped float are not all as wide. None
+        // until it is for a line.
+        let mut zone_line = None;
 
         while let Some(layoutbox) = self.work_list.back_mut().unwrap().pop_front() {
             if let BoxType::TextNode(ref text_info) = layoutbox.box_type {
@@ -100,7 +128,21 @@ impl LineMaker {
             }
 
             macro_rules! update_available_zone { () => {
-                if !shrink_to_fit &&
+                if !shrink_to_fit && self.floats.has_shapes()
+                    && zone_line != Some(self.cur_height)
+                {
+                    // As wide as the shapes leave next to a line as high as the last one (or of
+                    // the default height)
+                    let line_height = self.lines.last().map_or(
+                        Au::from_f64_px(DEFAULT_FONT_SIZE * DEFAULT_LINE_HEIGHT_SCALE),
+                        |line| max(Au(1), line.metrics.calculate_line_height()),
+                    );
+                    self.pending.zone =
+                        self.floats
+                            .available_area(max_width, self.cur_height, line_height);
+                    max_width_considered_float = self.pending.zone.width;
+                    zone_line = Some(self.cur_height);
+                } else if !shrink_to_fit &&
                     (self.pending.zone.height > Au(0)
                         && (self.cur_height + self.floats.ceiling > self.pending.zone.height))
                 {
@@ -119,7 +161,8 @@ impl LineMaker {
                     update_available_zone!()
                 },
                 BoxType::InlineBlockNode => {
-                    self.run_on_inline_block_node(layoutbox, max_width_considered_float)
+                    self.run_on_inline_block_node(layoutbox, max_width_considered_float);
+                    update_available_zone!()
                 }
                 BoxType::InlineNode => self.run_on_inline_node(
                     &layoutbox,
@@ -133,6 +176,53 @@ impl LineMaker {
         }
     }
 
+    /// Like ``run``, with the texts of the first line with the properties of ``first_line`` (of
+    /// ``::first-line``). The next lines are laid out again, without them.
+    pub fn run_with_first_line(
+        &mut self,
+        first_line: &Style,
+        max_width: Au,
+        containing_block: Dimensions,
+    ) {
+        let boxes: Vec<LayoutBox> = self.work_list.pop_back().unwrap_or_default().into();
+        let floats = self.floats.clone();
+        self.work_list.push_back(
+            boxes
+                .iter()
+                .map(|layout_box| with_first_line(layout_box, first_line))
+                .collect(),
+        );
+        self.run(max_width, containing_block);
+
+        // All in the first line
+        let first = match self.lines.first() {
+            Some(line) => line.clone(),
+            None => return,
+        };
+        let mut in_first = self.new_boxes[first.range.clone()]
+            .iter()
+            .map(content_len)
+            .sum();
+        let mut rest = LineMaker::new(skip_content(boxes, &mut in_first), floats);
+        rest.cur_height = first.metrics.calculate_line_height();
+        rest.run(max_width, containing_block);
+
+        let offset = first.range.end;
+        let mut new_boxes = self.new_boxes[..offset].to_vec();
+        new_boxes.append(&mut rest.new_boxes);
+        rest.new_boxes = new_boxes;
+        rest.lines = Some(first)
+            .into_iter()
+            .chain(rest.lines.into_iter().map(|mut line| {
+                line.range = line.range.start + offset..line.range.end + offset;
+                line
+            }))
+            .collect();
+        rest.start += offset;
+        rest.end += offset;
+        *self = rest;
+    }
+
     pub fn intrinsic_width(&self) -> Au {
         let mut max_width = Au(0);
         for line in &self.lines {
@@ -142,6 +232,12 @@ impl LineMaker {
     }
 
     pub fn flush_cur_line(&mut self) {
+        if let Some(strut) = self.strut.filter(|_| self.end > self.start) {
+            self.cur_metrics.above_baseline =
+                max(self.cur_metrics.above_baseline, strut.above_baseline);
+            self.cur_metrics.under_baseline =
+                max(self.cur_metrics.under_baseline, strut.under_baseline);
+        }
         // Push remainings to `lines`.
         self.lines.push(Line {
             range: self.start..self.end,
@@ -162,10 +258,21 @@ impl LineMaker {
     pub fn assign_position(&mut self) {
         self.cur_height = Au(0);
 
+        let classes: Vec<bidi::BidiClass> = self.new_boxes.iter().map(bidi_class_of_box).collect();
+        // The direction of the block (which its boxes inherit), or of the first strong text
+        let paragraph_level = match self.new_boxes.first().and_then(|b| b.property.direction()) {
+            Some(Direction::Rtl) => 1,
+            Some(Direction::Ltr) => 0,
+            None => bidi::paragraph_level(&classes),
+        };
+
         for line in &self.lines {
             self.cur_width = Au(0);
 
-            for new_box in &mut self.new_boxes[line.range.clone()] {
+            // The boxes of right-to-left texts are placed from right to left.
+            let levels = bidi::resolve_levels(&classes[line.range.clone()], paragraph_level);
+            for i in bidi::visual_order(&levels) {
+                let new_box = &mut self.new_boxes[line.range.start + i];
                 let (left_floats_width, mut max_width_considered_float) =
                     (line.zone.x, line.zone.width);
 
@@ -288,30 +395,49 @@ impl LineMaker {
             LayoutInfo::Generic | LayoutInfo::Anker => {
                 layout_text(layoutbox.clone(), self, max_width, containing_block);
             }
-            LayoutInfo::Image(_) => {
-                // Replaced Inline Element (<img>)
+            LayoutInfo::Image(_)
+            | LayoutInfo::Svg(_)
+            | LayoutInfo::Canvas(_)
+            | LayoutInfo::Math(_)
+            | LayoutInfo::TextInput(_)
+            | LayoutInfo::Select(_) => {
+                // Replaced Inline Element (<img>, <svg>, <canvas>, <math>, <input type=text>,
+                // <textarea>, <select>)
                 let width;
                 let height;
                 let mut layoutbox = layoutbox.clone();
                 layoutbox.layout_inline(&mut self.floats, containing_block);
                 width = layoutbox.dimensions.border_box().width;
                 height = layoutbox.dimensions.border_box().height;
+                // A formula is under the baseline by its descent, the others are on it.
+                let under_baseline = match layoutbox.info {
+                    LayoutInfo::Math(ref math) => {
+                        let d = &layoutbox.dimensions;
+                        Au::from_f64_px(math.descent) + d.padding.bottom + d.border.bottom
+                    }
+                    _ => Au(0),
+                };
 
                 if !shrink_to_fit && self.cur_width + width > max_width {
                     self.flush_cur_line();
                     self.end += 1;
 
                     self.cur_width = width;
-                    self.cur_metrics.above_baseline = height;
+                    self.cur_metrics.above_baseline = height - under_baseline;
+                    self.cur_metrics.under_baseline =
+                        max(self.cur_metrics.under_baseline, under_baseline);
                 } else {
                     self.end += 1;
                     self.cur_width += width;
-                    self.cur_metrics.above_baseline = max(self.cur_metrics.above_baseline, height);
+                    self.cur_metrics.above_baseline =
+                        max(self.cur_metrics.above_baseline, height - under_baseline);
+                    self.cur_metrics.under_baseline =
+                        max(self.cur_metrics.under_baseline, under_baseline);
                 }
 
                 self.new_boxes.push(layoutbox);
             }
-            LayoutInfo::Button(_, _) => {
+            LayoutInfo::Button(_) => {
                 // let btn_text = text(&layoutbox);
                 // use gtk::Button;
                 // use gtk::BinExt;
@@ -426,11 +552,14 @@ impl LineMaker {
     fn run_on_inline_block_node(&mut self, mut layoutbox: LayoutBox, max_width: Au) {
         let mut containing_block: Dimensions = ::std::default::Default::default();
         containing_block.content.width = max_width - self.cur_width;
+        // The height of the line is not known yet, so percentage heights are ``auto``.
+        let mut saved_block = containing_block;
+        saved_block.content.height = INDEFINITE_HEIGHT;
         layoutbox.layout(
             &mut self.floats,
             Au(0),
             containing_block,
-            containing_block,
+            saved_block,
             containing_block,
         );
 
@@ -463,19 +592,18 @@ impl LineMaker {
         let shrink_to_fit = max_width < Au(0);
 
         let text = if let NodeType::Text(ref text) = layoutbox.node.data {
-            &text[self.pending.range.clone()]
+            let text = &text[self.pending.range.clone()];
+            // A box has text in one direction to be reordered in the line.
+            &text[..bidi::first_run_len(text)]
         } else {
             return;
         };
 
         let mut new_layoutbox = layoutbox.clone();
 
-        let font_size = new_layoutbox.property.font_size();
         let line_height = new_layoutbox.property.line_height();
-        let font_weight = new_layoutbox.property.font_weight();
-        let font_slant = new_layoutbox.property.font_style();
 
-        let my_font = Font::new(font_size, font_weight, font_slant);
+        let my_font = new_layoutbox.property.font();
         let text_width = Au::from_f64_px(my_font.text_width(text));
         let (ascent, descent) = my_font.get_ascent_descent();
 
@@ -492,15 +620,19 @@ impl LineMaker {
 
         if !shrink_to_fit && self.cur_width + text_width > max_width {
             let remaining_width = max_width - self.cur_width; // Is this correc?
-            let (max_chars, text_width) =
-                my_font.compute_max_chars_and_width(text, remaining_width.to_f64_px());
+            let (max_chars, text_width, hyphenated) = my_font.compute_max_chars_and_width(
+                text,
+                remaining_width.to_f64_px(),
+                &new_layoutbox.property.hyphenator(),
+            );
 
             new_layoutbox.dimensions.content.width = Au::from_f64_px(text_width);
             new_layoutbox.dimensions.content.height = ascent + descent;
 
             new_layoutbox.set_text_info(
-                Font::new(font_size, font_weight, font_slant),
+                my_font,
                 self.pending.range.start..self.pending.range.start + max_chars,
+                hyphenated,
             );
             self.new_boxes.push(new_layoutbox);
 
@@ -515,18 +647,81 @@ impl LineMaker {
             new_layoutbox.dimensions.content.height = ascent + descent;
 
             new_layoutbox.set_text_info(
-                Font::new(font_size, font_weight, font_slant),
+                my_font,
                 self.pending.range.start..text.len() + self.pending.range.start,
+                false,
             );
             self.new_boxes.push(new_layoutbox);
 
-            self.pending.range = 0..0;
+            self.pending.range = self.pending.range.start + text.len()..self.pending.range.end;
 
             self.cur_width += text_width;
         }
     }
 }
 
+/// ``layout_box`` with the properties of ``first_line`` for its texts (but those in
+/// ``::first-letter``, which overrides them)
+fn with_first_line(layout_box: &LayoutBox, first_line: &Style) -> LayoutBox {
+    let mut layout_box = layout_box.clone();
+    match (&layout_box.box_type, &layout_box.node.data) {
+        (&BoxType::TextNode(_), &NodeType::Text(ref text)) => {
+            layout_box.property.property.extend(first_line.property.clone());
+            // Only if the ranges stay in the same characters
+            let transformed = layout_box.property.transform_text(text);
+            if transformed.len() == text.len() {
+                layout_box.node.data = NodeType::Text(transformed);
+            }
+        }
+        (&BoxType::InlineNode, &NodeType::Element(ref elem))
+            if elem.tag_name != "::first-letter" =>
+        {
+            layout_box.children = layout_box
+                .children
+                .iter()
+                .map(|child| with_first_line(child, first_line))
+                .collect();
+        }
+        _ => {}
+    }
+    layout_box
+}
+
+/// How much content (text bytes, and atomic inline boxes) ``layout_box`` has, to be laid out in
+/// the lines
+fn content_len(layout_box: &LayoutBox) -> usize {
+    match (&layout_box.box_type, &layout_box.info) {
+        (&BoxType::TextNode(ref text), _) => text.range.len(),
+        (&BoxType::InlineNode, &LayoutInfo::Generic)
+        | (&BoxType::InlineNode, &LayoutInfo::Anker) => {
+            layout_box.children.iter().map(content_len).sum()
+        }
+        _ => 1,
+    }
+}
+
+/// ``boxes`` without their first ``len`` of content (see ``content_len``)
+fn skip_content(boxes: Vec<LayoutBox>, len: &mut usize) -> Vec<LayoutBox> {
+    let mut rest = vec![];
+    for mut layout_box in boxes {
+        if *len > 0 {
+            let content_len = content_len(&layout_box);
+            if content_len <= *len {
+                *len -= content_len;
+                continue;
+            }
+            if let BoxType::TextNode(ref mut text) = layout_box.box_type {
+                text.range.start += *len;
+                *len = 0;
+            }
+            let children = ::std::mem::take(&mut layout_box.children);
+            layout_box.children = skip_content(children, len);
+        }
+        rest.push(layout_box);
+    }
+    rest
+}
+
 impl LayoutBox {
     /// Lay out a inline-level element and its descendants.
     pub fn layout_inline(&mut self, _floats: &mut Floats, containing_block: Dimensions) {
@@ -557,6 +752,9 @@ impl LayoutBox {
     }
 
     pub fn content_inline_ascent(&mut self) -> Au {
+        if let LayoutInfo::Math(ref math) = self.info {
+            return Au::from_f64_px(math.ascent);
+        }
         let height = self.dimensions.content.height;
         match self.get_first_text_node() {
             Some(node) => match node.box_type {
@@ -576,48 +774,66 @@ impl LayoutBox {
         _floats: &mut Floats,
         _last_margin_bottom: Au,
         containing_block: Dimensions,
-        _saved_block: Dimensions,
+        saved_block: Dimensions,
         viewport: Dimensions,
     ) {
         // Child width can depend on parent width, so we need to calculate this box's width before
         // laying out its children.
-        self.calculate_inline_block_width(containing_block);
-
         self.assign_padding();
         self.assign_border_width();
         self.assign_margin();
+
+        self.calculate_inline_block_width(containing_block);
         // self.calculate_block_position(last_margin_bottom, containing_block);
 
-        self.layout_block_children(viewport);
+        self.layout_block_children(saved_block, viewport);
 
         // Parent height can depend on child height, so `calculate_height` must be called after the
         // children are laid out.
-        self.calculate_block_height();
+        self.calculate_block_height(saved_block);
     }
 
     /// Calculate the width of a block-level non-replaced element in normal flow.
     /// Sets the horizontal margin/padding/border dimensions, and the `width`.
     /// ref. https://www.w3.org/TR/CSS2/visudet.html#inlineblock-width
-    pub fn calculate_inline_block_width(&mut self, _containing_block: Dimensions) {
+    pub fn calculate_inline_block_width(&mut self, containing_block: Dimensions) {
+        let cb_width = containing_block.content.width.to_f64_px();
         // `width` has initial value `auto`.
-        // TODO: Implement calculating shrink-to-fit width
-        if let Some(x) = self.property.value("width") {
-            self.dimensions.content.width = Au::from_f64_px(x[0].to_px().unwrap());
-        } else {
-            // TODO
-            // width == auto
-            panic!("calculating shrink-to-fit width is unsupported.");
+        let width = self.property
+            .value("width")
+            .and_then(|x| x[0].maybe_percent_to_px(cb_width));
+        layout_trace!("width: {:?} (specified {:?})", width, self.property.value("width"));
+        self.dimensions.content.width = match width {
+            Some(width) => Au::from_f64_px(width.max(0.0)),
+            // TODO: Implement calculating shrink-to-fit width. For now, use the available width.
+            None => {
+                let d = self.dimensions;
+                let edges = d.margin.left + d.margin.right + d.border.left + d.border.right
+                    + d.padding.left + d.padding.right;
+                max(containing_block.content.width - edges, Au(0))
+            }
         };
     }
 }
 
 use dom::Node;
 
-pub fn get_image(node: &Node, imgdata: &mut ImageData, containing_block: Dimensions) {
+/// Sizes the image of ``node``, of the width over the height ``aspect_ratio`` (of the style) if
+/// it is not loaded yet.
+pub fn get_image(
+    node: &Node,
+    imgdata: &mut ImageData,
+    aspect_ratio: Option<f64>,
+    containing_block: Dimensions,
+) {
     let cb_width = containing_block.content.width.to_f64_px();
     let cb_height = containing_block.content.height.to_f64_px();
 
-    let pixbuf = imgdata.pixbuf.get_or_insert_with(|| get_pixbuf(node));
+    if imgdata.intrinsic_size.is_none() {
+        imgdata.intrinsic_size = imgdata.source.as_ref().and_then(|&(ref url, density)| {
+            image_size(url).map(|(width, height)| (width / density, height / density))
+        });
+    }
 
     let specified_width_px = node.attr("width")
         .and_then(|w| w.maybe_percent_to_px(cb_width));
@@ -625,19 +841,34 @@ pub fn get_image(node: &Node, imgdata: &mut ImageData, containing_block: Dimensi
     let specified_height_px = node.attr("height")
         .and_then(|h| h.maybe_percent_to_px(cb_height));
 
+    let (intrinsic_width, intrinsic_height) = match imgdata.intrinsic_size {
+        Some(size) => size,
+        None => {
+            // Not loaded yet. Reserve the specified size for the placeholder.
+            let (width, height) = match (specified_width_px, specified_height_px, aspect_ratio) {
+                (Some(width), None, Some(ratio)) => (width, width / ratio),
+                (None, Some(height), Some(ratio)) => (height * ratio, height),
+                (width, height, _) => (width.unwrap_or(0.0), height.unwrap_or(0.0)),
+            };
+            imgdata.metadata.width = Au::from_f64_px(width);
+            imgdata.metadata.height = Au::from_f64_px(height);
+            return;
+        }
+    };
+
     let (width, height) = match (specified_width_px, specified_height_px) {
         (Some(width), Some(height)) => (Au::from_f64_px(width), Au::from_f64_px(height)),
         (Some(width), None) => (
             Au::from_f64_px(width),
-            Au::from_f64_px(width * (pixbuf.get_height() as f64 / pixbuf.get_width() as f64)),
+            Au::from_f64_px(width * (intrinsic_height / intrinsic_width)),
         ),
         (None, Some(height)) => (
-            Au::from_f64_px(height * (pixbuf.get_width() as f64 / pixbuf.get_height() as f64)),
+            Au::from_f64_px(height * (intrinsic_width / intrinsic_height)),
             Au::from_f64_px(height),
         ),
         (None, None) => (
-            Au::from_f64_px(pixbuf.get_width() as f64),
-            Au::from_f64_px(pixbuf.get_height() as f64),
+            Au::from_f64_px(intrinsic_width),
+            Au::from_f64_px(intrinsic_height),
         ),
     };
 
@@ -646,26 +877,146 @@ pub fn get_image(node: &Node, imgdata: &mut ImageData, containing_block: Dimensi
 }
 
 use std::cell::RefCell;
+#[cfg(feature = "raster")]
+use std::fs;
+use std::sync::Mutex;
 
 type ImageKey = String; // URL
 
 thread_local!(
-    static IMG_CACHE: RefCell<HashMap<ImageKey, gdk_pixbuf::Pixbuf>> = {
+    static IMG_CACHE: RefCell<HashMap<ImageKey, Image>> = {
         RefCell::new(HashMap::new())
     };
+    /// The images to load, if their loads are deferred (see ``defer_image_loads``)
+    static DEFERRED_IMAGES: RefCell<Option<Vec<ImageKey>>> = { RefCell::new(None) };
 );
 
-use interface::download;
-
-pub fn get_pixbuf(node: &Node) -> gdk_pixbuf::Pixbuf {
-    IMG_CACHE.with(|c| {
-        let image_url = node.image_url().unwrap();
-        c.borrow_mut()
-            .entry(image_url.clone())
-            .or_insert_with(|| {
-                let (cache_name, _) = download(image_url.as_str());
-                gdk_pixbuf::Pixbuf::new_from_file(cache_name.as_str()).unwrap()
-            })
-            .clone()
-    })
+/// The sizes of the images registered on any thread (px), to lay them out on another one
+static IMAGE_SIZES: Mutex<Vec<(ImageKey, (f64, f64))>> = Mutex::new(Vec::new());
+
+#[cfg(feature = "raster")]
+use animated_image;
+use interface::{download, page_cache_mode};
+use network::Destination;
+use loader;
+use loader::Loaded;
+
+/// The size of the image at ``image_url`` (px). Returns None if it is not loaded yet. Then, it is
+/// loaded in the background and ``register_image`` is called when it arrives.
+pub fn image_size(image_url: &str) -> Option<(f64, f64)> {
+    let images = IMAGE_SIZES.lock().unwrap();
+    if let Some(&(_, size)) = images.iter().find(|&&(ref url, _)| url == image_url) {
+        return Some(size);
+    }
+    drop(images);
+
+    let deferred = DEFERRED_IMAGES.with(|d| match *d.borrow_mut() {
+        Some(ref mut images) => {
+            images.push(image_url.to_string());
+            true
+        }
+        None => false,
+    });
+    if !deferred {
+        load_images(&[image_url.to_string()]);
+    }
+
+    None
+}
+
+/// The decoded image at ``image_url`` (e.g. of an ``<img>``, see ``ImageData::source``), if it is
+/// registered on this thread.
+pub fn pixbuf(image_url: &str) -> Option<Image> {
+    IMG_CACHE.with(|c| c.borrow().get(image_url).cloned())
+}
+
+/// The decoded image at ``image_url`` (e.g. of ``background-image``). Returns None if it is not
+/// loaded yet. Then, it is loaded in the background like the images of ``<img>``s.
+pub fn background_pixbuf(image_url: &str) -> Option<Image> {
+    if let Some(pixbuf) = IMG_CACHE.with(|c| c.borrow().get(image_url).cloned()) {
+        return Some(pixbuf);
+    }
+    let deferred = DEFERRED_IMAGES.with(|d| match *d.borrow_mut() {
+        Some(ref mut images) => {
+            images.push(image_url.to_string());
+            true
+        }
+        None => false,
+    });
+    if !deferred {
+        load_images(&[image_url.to_string()]);
+    }
+    None
+}
+
+/// Loads the images at ``image_urls`` in the background, which ``register_image`` is called for
+/// when they arrive.
+pub fn load_images(image_urls: &[ImageKey]) {
+    for image_url in image_urls {
+        loader::spawn(image_url.as_str(), {
+            let image_url = image_url.clone();
+            let cache_mode = page_cache_mode();
+            // Shown as a broken image
+            move || match download(image_url.as_str(), Destination::Image, cache_mode) {
+                Ok(resource) => Loaded::Image(image_url, resource.file_name),
+                Err(_) => Loaded::Failed,
+            }
+        });
+    }
+}
+
+/// Makes the images not loaded yet be collected (and not loaded) on this thread until
+/// ``deferred_image_loads``, e.g. on a thread that cannot receive them.
+pub fn defer_image_loads() {
+    DEFERRED_IMAGES.with(|d| *d.borrow_mut() = Some(vec![]));
+}
+
+/// The images collected since ``defer_image_loads``, which are loaded again from then on.
+pub fn deferred_image_loads() -> Vec<ImageKey> {
+    DEFERRED_IMAGES.with(|d| d.borrow_mut().take().unwrap_or_default())
+}
+
+/// Forgets the decoded images so that they are loaded again.
+pub fn clear_image_cache() {
+    #[cfg(feature = "raster")]
+    animated_image::stop_all();
+    IMG_CACHE.with(|c| c.borrow_mut().clear());
+    IMAGE_SIZES.lock().unwrap().clear();
+}
+
+/// Shows ``pixbuf`` as the image at ``image_url`` from now, e.g. a frame of an animated image.
+pub fn show_frame(image_url: &str, pixbuf: Image) {
+    IMG_CACHE.with(|c| c.borrow_mut().insert(image_url.to_string(), pixbuf));
+}
+
+/// Decodes the downloaded image, and plays it if it is animated (see ``animated_image``).
+/// Returns false if it is broken.
+#[cfg(feature = "raster")]
+pub fn register_image(image_url: ImageKey, cache_name: &str) -> bool {
+    let animation = fs::read(cache_name).ok().and_then(|data| animated_image::decode(&data));
+    let pixbuf = match animation {
+        Some(ref animation) => Ok(animation.frames[0].pixbuf.clone()),
+        None => Image::new_from_file(cache_name),
+    };
+    match pixbuf {
+        Ok(pixbuf) => {
+            let size = (pixbuf.get_width() as f64, pixbuf.get_height() as f64);
+            let mut images = IMAGE_SIZES.lock().unwrap();
+            images.retain(|&(ref url, _)| *url != image_url);
+            images.push((image_url.clone(), size));
+            drop(images);
+            IMG_CACHE.with(|c| c.borrow_mut().insert(image_url.clone(), pixbuf));
+            if let Some(animation) = animation {
+                animated_image::play(&image_url, animation);
+            }
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Without the ``raster`` feature, no image is decoded, and all of them are broken.
+#[cfg(not(feature = "raster"))]
+pub fn register_image(_image_url: ImageKey, _cache_name: &str) -> bool {
+    false
 }
@@ -0,0 +1,212 @@
+//! The inspector: where the element at a point is in the document, the rules it matches, its
+//! computed style and its box.
+
+use css::{parse_attr_style, Stylesheet};
+use default_style;
+use dom::{ElementData, NodeType};
+use layout::{ancestor_selector, cascaded_rules, presentational_hints, BoxType, EdgeSizes, LayoutBox,
+             Origin};
+use overflow;
+use shape;
+use style::LANG_PROPERTY;
+
+use std::fmt::Write;
+
+use app_units::Au;
+
+/// The boxes from ``layout_root`` down to the innermost (and topmost) one whose border box
+/// contains (``x``, ``y``) in the document (in CSS px), skipping those with
+/// ``pointer-events: none``. Empty if none does.
+pub fn path_at(layout_root: &LayoutBox, x: f64, y: f64) -> Vec<&LayoutBox> {
+    fn find<'a>(
+        layout_box: &'a LayoutBox,
+        x: Au,
+        y: Au,
+        px: f64,
+        py: f64,
+        path: &mut Vec<&'a LayoutBox>,
+    ) -> bool {
+        // Neither the box nor its descendants are hit out of its clip-path.
+        if !shape::hits_clip_path(layout_box, x, y, px, py) {
+            return false;
+        }
+        let (child_x, child_y) = overflow::child_origin(layout_box, x, y);
+        // The one painted over the others first. None is hit where they are clipped.
+        let children = if overflow::hits_descendants(layout_box, x, y, px, py) {
+            &layout_box.children[..]
+        } else {
+            &[]
+        };
+        for child in children.iter().rev() {
+            if find(child, child_x, child_y, px, py, path) {
+                path.push(layout_box);
+                return true;
+            }
+        }
+
+        let border_box = layout_box.dimensions.border_box().add_parent_coordinate(x, y);
+        if border_box.contains_point(px, py) && layout_box.property.pointer_events() {
+            path.push(layout_box);
+            true
+        } else {
+            false
+        }
+    }
+
+    let mut path = vec![];
+    find(layout_root, Au(0), Au(0), x, y, &mut path);
+    path.reverse();
+    path
+}
+
+fn element(layout_box: &LayoutBox) -> Option<&ElementData> {
+    match (&layout_box.box_type, &layout_box.node.data) {
+        (&BoxType::AnonymousBlock, _) => None,
+        (_, &NodeType::Element(ref elem)) => Some(elem),
+        (_, &NodeType::Text(_)) => None,
+    }
+}
+
+/// e.g. ``div#main.a.b``
+fn describe(elem: &ElementData) -> String {
+    let mut classes: Vec<_> = elem.classes().into_iter().filter(|c| !c.is_empty()).collect();
+    classes.sort();
+    let mut s = elem.tag_name.clone();
+    if let Some(id) = elem.id() {
+        s += format!("#{}", id).as_str();
+    }
+    for class in classes {
+        s += format!(".{}", class).as_str();
+    }
+    s
+}
+
+fn edges(edges: EdgeSizes) -> String {
+    format!(
+        "{} {} {} {}",
+        edges.top.to_f64_px(),
+        edges.right.to_f64_px(),
+        edges.bottom.to_f64_px(),
+        edges.left.to_f64_px()
+    )
+}
+
+/// A report on the innermost element in ``path`` (see ``path_at``), or None if there is no
+/// element. ``stylesheet`` is the author stylesheet the page was styled with.
+pub fn inspect(path: &[&LayoutBox], stylesheet: &Stylesheet) -> Option<String> {
+    let target = path.iter().rposition(|layout_box| element(layout_box).is_some())?;
+    let elem = element(path[target])?;
+    let ancestors: Vec<&ElementData> = path[..target].iter().filter_map(|b| element(b)).collect();
+
+    let mut report = String::new();
+
+    // The DOM path
+    let mut names: Vec<String> = ancestors.iter().map(|elem| describe(elem)).collect();
+    names.push(describe(elem));
+    writeln!(report, "{}", names.join(" > ")).unwrap();
+
+    writeln!(report, "\nMatched rules (later ones win):").unwrap();
+    let default_style = default_style::default_style();
+    let appeared_elements = ancestors.iter().map(|elem| ancestor_selector(elem)).collect();
+    let hints: Vec<_> = presentational_hints(elem)
+        .into_iter()
+        .filter(|declaration| declaration.name != LANG_PROPERTY)
+        .collect();
+    if !hints.is_empty() {
+        writeln!(report, "  element {{ /* the attributes, over the user agent rules */").unwrap();
+        for declaration in &hints {
+            writeln!(report, "    {}", declaration).unwrap();
+        }
+        writeln!(report, "  }}").unwrap();
+    }
+    for (origin, (specificity, rule)) in
+        cascaded_rules(elem, None, &default_style, stylesheet, &appeared_elements)
+    {
+        let selectors: Vec<String> = rule.selectors.iter().map(|s| s.to_string()).collect();
+        writeln!(
+            report,
+            "  {} {{ /* {}, specificity {:?} */",
+            selectors.join(", "),
+            match origin {
+                Origin::UserAgent => "user agent",
+                Origin::Author => "author",
+            },
+            specificity
+        ).unwrap();
+        for declaration in &rule.declarations {
+            writeln!(report, "    {}", declaration).unwrap();
+        }
+        writeln!(report, "  }}").unwrap();
+    }
+    if let Some(attr_style) = elem.attrs.get("style") {
+        writeln!(report, "  element.style {{ /* the style attribute */").unwrap();
+        for declaration in parse_attr_style(attr_style.clone()) {
+            writeln!(report, "    {}", declaration).unwrap();
+        }
+        writeln!(report, "  }}").unwrap();
+    }
+
+    writeln!(report, "\nComputed style:").unwrap();
+    let mut properties: Vec<_> = path[target]
+        .property
+        .property
+        .iter()
+        .filter(|&(name, _)| name != LANG_PROPERTY)
+        .collect();
+    properties.sort_by(|&(a, _), &(b, _)| a.cmp(b));
+    for (name, values) in properties {
+        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        writeln!(report, "  {}: {};", name, values.join(" ")).unwrap();
+    }
+
+    // The content area in the coordinates of the document, like the display list
+    let (x, y) = path[..target].iter().fold((Au(0), Au(0)), |(x, y), b| {
+        (x + b.dimensions.content.x, y + b.dimensions.content.y)
+    });
+    let d = path[target].dimensions;
+    let content = d.content.add_parent_coordinate(x, y);
+    writeln!(report, "\nBox (px, top right bottom left):").unwrap();
+    writeln!(
+        report,
+        "  content  {} x {} at ({}, {})",
+        content.width.to_f64_px(),
+        content.height.to_f64_px(),
+        content.x.to_f64_px(),
+        content.y.to_f64_px()
+    ).unwrap();
+    writeln!(report, "  padding  {}", edges(d.padding)).unwrap();
+    writeln!(report, "  border   {}", edges(d.border)).unwrap();
+    writeln!(report, "  margin   {}", edges(d.margin)).unwrap();
+
+    Some(report)
+}
+
+#[test]
+fn test_inspect() {
+    use css;
+    use layout;
+
+    let css_src = "#main p { padding: 4px; } p { margin: 0; }";
+    let layout_root = layout::lay_out_html(
+        "<div id=\"main\" class=\"b a\"><p style=\"color: red\">text</p></div>",
+        css_src,
+        200.0,
+        0.0,
+    );
+    let stylesheet = css::parse(css_src.to_string());
+
+    let path = path_at(&layout_root, 10.0, 10.0);
+    assert_eq!(path.len(), 4); // div, p, the anonymous block and the text
+    let report = inspect(&path, &stylesheet).unwrap();
+    assert!(report.starts_with("div#main.a.b > p\n"));
+    assert!(report.contains("  p { /* author, specificity (0, 0, 1) */\n    margin: 0;\n  }\n"));
+    // Applied after the less specific one
+    assert!(report.contains("  }\n  #main p { /* author, specificity (1, 0, 1) */\n"));
+    assert!(report.contains("  element.style { /* the style attribute */\n    color: red;\n"));
+    assert!(report.contains("\n  padding: 4px;\n"));
+    assert!(report.contains("  content  192 x "));
+    assert!(report.contains("  padding  4 4 4 4\n"));
+
+    assert!(path_at(&layout_root, 10.0, 1000.0).is_empty());
+    assert_eq!(inspect(&[], &stylesheet), None);
+}
@@ -2,22 +2,52 @@ use html;
 use dom;
 use css;
 use layout;
-use painter;
+use layout_worker::{self, LaidOut};
+use media::{self, ColorScheme};
+use paged;
+use style;
+use painter::{self, DisplayCommand, DisplayCommandInfo};
+use canvas;
+use canvas2d;
+use inspector;
+use overflow;
+use shape;
+use script;
+use event::{self, Dispatched};
+use task::{TaskId, TaskQueue};
+use controls;
+#[cfg(feature = "raster")]
+use raster;
+#[cfg(feature = "gtk")]
 use window;
+use network;
+use loader;
+use loader::Loaded;
+use stats::{self, Phase};
+use inline;
+use network::{CacheMode, Destination, Url};
+use image::{self, Image};
 
+use std::fmt;
 use std::fs::OpenOptions;
-use std::io::prelude::*;
-use std::path::{Path, PathBuf};
+use std::io::{self, prelude::*};
+use std::path::Path;
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "raster")]
+extern crate cairo;
+#[cfg(feature = "gtk")]
 extern crate gtk;
+#[cfg(feature = "raster")]
+extern crate pango;
+#[cfg(feature = "raster")]
+extern crate pangocairo;
+#[cfg(feature = "gtk")]
 use gtk::WidgetExt;
 
 extern crate app_units;
 use app_units::Au;
 
-extern crate reqwest;
-use interface::reqwest::Url;
-
 use std::fs;
 use std::io::{BufWriter, Write};
 
@@ -29,156 +59,1205 @@ macro_rules! debug_println {
    ($($arg:tt)*) => { if cfg!(debug_assertions) { println!($($arg)*); } }
 }
 
-/// If ``url_str`` starts with ``http(s)://``, downloads the specified file:
-///  Returns (downloaded file name, file path(URL without ``http(s)://domain/``)).
-/// If ``url_str`` starts with ``file://``, does nothing especially.
-///  Just returns (local file name, local file path).
-pub fn download(url_str: &str) -> (String, PathBuf) {
-    let url = HTML_SRC_URL.with(|html_src_url| {
-        let mut html_src_url = html_src_url.borrow_mut();
-        if let Ok(parsed) = Url::parse(url_str) {
-            // If url_str is absolute URL(starts with scheme://)
-            *html_src_url = Some(url_str.to_string());
-            return parsed;
-        } else if let Some(ref mut html_src_url) = *html_src_url {
-            let mut url = Url::parse(html_src_url.as_str()).unwrap();
-            url.set_path(url_str);
+/// A downloaded (or local) file.
+#[derive(Clone, Debug)]
+pub struct Resource {
+    pub file_name: String,
+    /// The URL after redirects. Relative URLs in the resource are resolved against it.
+    pub url: Url,
+    /// e.g. ``text/html`` (without parameters)
+    pub mime_type: String,
+}
+
+/// Why a resource could not be downloaded
+#[derive(Debug)]
+pub enum DownloadError {
+    /// The request failed, or the server answered with an error status.
+    Fetch(network::FetchError),
+    /// A local file (or the cache) that cannot be read or written
+    Io(io::Error),
+    /// A ``data:`` URL that cannot be decoded, e.g. of invalid base64
+    InvalidDataUrl,
+    /// e.g. ``mailto:``
+    UnsupportedScheme(String),
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DownloadError::Fetch(ref e) => write!(f, "{}", e),
+            DownloadError::Io(ref e) => write!(f, "the file cannot be read: {}", e),
+            DownloadError::InvalidDataUrl => write!(f, "the data: URL is malformed"),
+            DownloadError::UnsupportedScheme(ref scheme) => {
+                write!(f, "{}: URLs are not supported", scheme)
+            }
+        }
+    }
+}
+
+/// Converts ``url_str`` given by the user or a document into an absolute URL.
+/// Local paths (e.g. ``/home/a.html``, or ``a.html`` when no document is open) become
+/// ``file://`` URLs.
+pub fn parse_url(url_str: &str) -> Url {
+    if let Ok(url) = Url::parse(url_str) {
+        return url;
+    }
+
+    let path = Path::new(url_str);
+    if !path.is_absolute() {
+        let resolved = HTML_SRC_URL.with(|html_src_url| {
+            html_src_url
+                .borrow()
+                .as_ref()
+                .and_then(|base| network::resolve_url(base, url_str))
+        });
+        if let Some(url) = resolved {
             return url;
         }
-        *html_src_url = Some(url_str.to_string());
-        Url::parse(url_str).unwrap()
-    });
+    }
+
+    let path = ::std::env::current_dir()
+        .expect("cannot get the current directory")
+        .join(path);
+    Url::from_file_path(path).expect("invalid URL")
+}
+
+/// Downloads ``url_str`` (an absolute URL, or a URL relative to the current document).
+/// If the URL starts with ``http(s)://``, downloads the specified file (following redirects).
+/// If the URL starts with ``file://``, does nothing especially. Just returns the local file.
+/// If the URL starts with ``data:``, saves the embedded data.
+/// If the URL starts with ``view-source:``, downloads the URL after it as plain text.
+pub fn download(
+    url_str: &str,
+    destination: Destination,
+    cache_mode: CacheMode,
+) -> Result<Resource, DownloadError> {
+    let url = parse_url(url_str);
 
     match url.scheme().to_ascii_lowercase().as_str() {
-        "file" => (url.path().to_string(), Path::new(url.path()).to_path_buf()),
-        "http" | "https" => {
-            let mut content: Vec<u8> = vec![];
-            reqwest::get(url.clone())
-                .unwrap()
-                .copy_to(&mut content)
-                .unwrap();
-
-            let path = Path::new(url.path());
-            let tmpfile_name = format!(
-                "cache/{}.{}",
-                rand::thread_rng()
-                    .sample_iter(&rand::distributions::Alphanumeric)
-                    .take(8)
-                    .collect::<String>(),
-                if let Some(ext) = path.extension() {
-                    ext.to_str().unwrap()
-                } else {
-                    "html"
+        "file" => {
+            let path = url.to_file_path().map_err(|_| {
+                DownloadError::Io(io::Error::new(io::ErrorKind::InvalidInput, "invalid file URL"))
+            })?;
+            if !path.exists() {
+                return Err(DownloadError::Io(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no such file or directory",
+                )));
+            }
+            let file_name = path.to_string_lossy().into_owned();
+
+            if path.is_dir() {
+                let mut url = url;
+                if !url.path().ends_with('/') {
+                    // So that the entries are resolved inside the directory.
+                    let dir_path = format!("{}/", url.path());
+                    url.set_path(dir_path.as_str());
                 }
-            );
+                return Ok(Resource {
+                    file_name: file_name,
+                    url: url,
+                    mime_type: "inode/directory".to_string(),
+                });
+            }
+
+            Ok(Resource {
+                mime_type: network::guess_mime_type(file_name.as_str())
+                    .unwrap_or("text/plain")
+                    .to_string(),
+                file_name: file_name,
+                url: url,
+            })
+        }
+        "http" | "https" => {
+            let response =
+                network::fetch(&url, destination, cache_mode).map_err(DownloadError::Fetch)?;
+
+            let file_name = save_to_cache_dir(
+                response.body.as_slice(),
+                Path::new(response.url.path())
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .unwrap_or("html"),
+            ).map_err(DownloadError::Io)?;
+
+            debug_println!("downloaded {}", response.url.as_str());
+
+            Ok(Resource {
+                file_name: file_name,
+                mime_type: match response.content_type {
+                    Some(ref content_type) => network::mime_essence(content_type.as_str()),
+                    None => network::guess_mime_type(response.url.path())
+                        .unwrap_or("text/html")
+                        .to_string(),
+                },
+                url: response.url,
+            })
+        }
+        "data" => {
+            let data_url =
+                network::decode_data_url(url.as_str()).ok_or(DownloadError::InvalidDataUrl)?;
+            let mime_type = network::mime_essence(data_url.mime_type.as_str());
+            // e.g. image/png -> png
+            let ext = mime_type
+                .split(&['/', '+'][..])
+                .nth(1)
+                .unwrap_or("txt")
+                .to_string();
+            Ok(Resource {
+                file_name: save_to_cache_dir(data_url.body.as_slice(), ext.as_str())
+                    .map_err(DownloadError::Io)?,
+                url: url,
+                mime_type: mime_type,
+            })
+        }
+        "view-source" => {
+            let source =
+                download(&url.as_str()["view-source:".len()..], destination, cache_mode)?;
+            Ok(Resource {
+                file_name: source.file_name,
+                url: url,
+                mime_type: "text/plain".to_string(),
+            })
+        }
+        scheme => Err(DownloadError::UnsupportedScheme(scheme.to_string())),
+    }
+}
 
-            debug_println!("downloaded {}", url.as_str());
+/// Reads the whole file. Invalid UTF-8 sequences are replaced.
+fn read_file(file_name: &str) -> io::Result<String> {
+    let mut content = vec![];
+    OpenOptions::new()
+        .read(true)
+        .open(file_name)?
+        .read_to_end(&mut content)?;
+    Ok(String::from_utf8_lossy(content.as_slice()).into_owned())
+}
+
+/// Returns (name, is directory) of the entries in ``dir``. Directories come first.
+fn read_dir(dir: &str) -> Vec<(String, bool)> {
+    let mut entries: Vec<(String, bool)> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| {
+                    (
+                        entry.file_name().to_string_lossy().into_owned(),
+                        entry.path().is_dir(),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_else(|_| vec![]);
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    entries
+}
 
-            let mut f = BufWriter::new(fs::File::create(tmpfile_name.as_str()).unwrap());
-            f.write_all(content.as_slice()).unwrap();
+/// Downloads the document at ``url_str`` and builds it. A document that cannot be downloaded is
+/// a page telling why.
+fn load_document(url_str: &str, cache_mode: CacheMode) -> (Url, dom::Node) {
+    match download(url_str, Destination::Document, cache_mode) {
+        Ok(resource) => (resource.url.clone(), build_document(&resource)),
+        Err(e) => (parse_url(url_str), error_document(url_str, &e)),
+    }
+}
+
+/// The page shown instead of a document that cannot be loaded.
+fn error_document(url: &str, error: &DownloadError) -> dom::Node {
+    html::plain_text_document(format!("Naglfar cannot load {}: {}.", url, error).as_str())
+}
 
-            (tmpfile_name, path.to_path_buf())
+/// Builds the document for ``resource`` according to its MIME type.
+fn build_document(resource: &Resource) -> dom::Node {
+    // The page telling why if the file cannot be read
+    let read = || {
+        read_file(resource.file_name.as_str())
+            .map_err(|e| error_document(resource.url.as_str(), &DownloadError::Io(e)))
+    };
+    match resource.mime_type.as_str() {
+        "text/html" | "application/xhtml+xml" => match read() {
+            Ok(src) => stats::time(Phase::Parse, || html::parse(src, resource.url.clone())),
+            Err(error_document) => error_document,
+        },
+        mime_type if mime_type.starts_with("image/") => html::image_document(resource.url.as_str()),
+        "inode/directory" => {
+            html::directory_document(&resource.url, read_dir(resource.file_name.as_str()))
         }
-        _ => unimplemented!(),
+        mime_type if network::is_text_mime_type(mime_type) => {
+            match read() {
+                Ok(text) => html::plain_text_document(text.as_str()),
+                Err(error_document) => error_document,
+            }
+        }
+        mime_type => html::plain_text_document(
+            format!("Naglfar cannot display {} ({}).", resource.url, mime_type).as_str(),
+        ),
     }
 }
 
-use std::cell::RefCell;
+/// Saves ``content`` under ``./cache`` with a random name. Returns the file name.
+fn save_to_cache_dir(content: &[u8], ext: &str) -> io::Result<String> {
+    let tmpfile_name = format!(
+        "cache/{}.{}",
+        rand::thread_rng()
+            .sample_iter(&rand::distributions::Alphanumeric)
+            .take(8)
+            .collect::<String>(),
+        ext
+    );
+
+    let mut f = BufWriter::new(fs::File::create(tmpfile_name.as_str())?);
+    f.write_all(content)?;
+
+    Ok(tmpfile_name)
+}
+
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use std::sync::mpsc::{Receiver, TryRecvError};
 
 thread_local!(
     static LAYOUT_SAVER: RefCell<(Au, Au, painter::DisplayList)> = { RefCell::new((Au(0), Au(0), vec![])) };
-    static HTML_SRC_URL: RefCell<Option<String>> = { RefCell::new(None) };
+    static HTML_SRC_URL: RefCell<Option<Url>> = { RefCell::new(None) };
+    // Set when a page is loaded, which is first laid out on a worker thread (see
+    // ``display_list``)
+    static LAYOUT_IN_BACKGROUND: Cell<bool> = { Cell::new(false) };
+    // The layout being done on the worker thread, until it is received (see ``receive_layout``)
+    static PENDING_LAYOUT: RefCell<Option<Receiver<LaidOut>>> = { RefCell::new(None) };
+    // Used for the document and all its subresources
+    static PAGE_CACHE_MODE: RefCell<CacheMode> = { RefCell::new(CacheMode::Default) };
     static HTML_TREE:    Rc<RefCell<Option<dom::Node>>> = { Rc::new(RefCell::new(None)) };
     static STYLESHEET:   Rc<RefCell<Option<css::Stylesheet>>> = { Rc::new(RefCell::new(None)) };
+    // The global variables of the scripts of the page
+    static SCRIPTS: RefCell<Option<script::Runtime>> = { RefCell::new(None) };
+    // The element the pointer is over (its path in the DOM), for ``mouseover``
+    static HOVERED: RefCell<Option<Vec<usize>>> = { RefCell::new(None) };
+    // The tasks of the engine (``post_task``)
+    static TASKS: RefCell<TaskQueue<EngineTask>> = { RefCell::new(TaskQueue::new()) };
+    // Called with when the next task is due, to run the tasks then (see ``set_task_waker``)
+    static TASK_WAKER: RefCell<Option<TaskWaker>> = { RefCell::new(None) };
+    // The decoded icon of the page, once it is loaded
+    static FAVICON: RefCell<Option<Image>> = { RefCell::new(None) };
 );
 
+type EngineTask = Box<dyn FnOnce()>;
+type TaskWaker = Rc<dyn Fn(Instant)>;
+
 static mut SRC_UPDATED: bool = false;
 
 pub fn update_html_source(html_src: String) {
-    let (html_src_cache_name, html_src_path) = download(html_src.as_str());
+    load_html_source(html_src, CacheMode::Default);
+}
+
+/// Loads the current document again. With ``CacheMode::Reload``, the document and its
+/// subresources are fetched again from the network.
+pub fn reload_html_source(cache_mode: CacheMode) {
+    if let Some(url) = current_url() {
+        load_html_source(url.as_str().to_string(), cache_mode);
+    }
+}
+
+fn load_html_source(html_src: String, cache_mode: CacheMode) {
+    loader::start_new_page();
+    stats::reset();
+    overflow::reset_scroll_offsets();
+    PAGE_CACHE_MODE.with(|m| *m.borrow_mut() = cache_mode);
+    if cache_mode == CacheMode::Reload {
+        inline::clear_image_cache();
+    }
+
+    let (html_src_url, mut html_tree) = load_document(html_src.as_str(), cache_mode);
+    // Relative URLs given afterwards (e.g. typed in the URL bar) are relative to this document.
+    HTML_SRC_URL.with(|u| *u.borrow_mut() = Some(html_src_url.clone()));
 
     debug_println!("HTML:");
-    let mut html_source = "".to_string();
-    OpenOptions::new()
-        .read(true)
-        .open(html_src_cache_name)
-        .unwrap()
-        .read_to_string(&mut html_source)
-        .ok()
-        .expect("cannot read file");
-    let html_tree = html::parse(html_source, html_src_path);
+    // The relative URLs of the document are relative to its ``<base href>``, if any.
+    let base_url = html_tree.find_base_url().unwrap_or_else(|| html_src_url.clone());
+    canvas2d::clear();
+    let mut scripts = script::Runtime::new(base_url.clone());
+    scripts.run_scripts(&mut html_tree);
     debug_println!("{}", html_tree);
 
     debug_println!("CSS:");
-    let mut css_source = "".to_string();
-    if let Some(stylesheet_path) = html_tree.find_stylesheet_path() {
-        let (css_cache_name, _) = download(stylesheet_path.to_str().unwrap());
-        OpenOptions::new()
-            .read(true)
-            .open(css_cache_name)
-            .unwrap()
-            .read_to_string(&mut css_source)
-            .ok()
-            .expect("cannot read file");
+    // External stylesheets are loaded in the background. Until then, only the default style is
+    // applied.
+    let stylesheet = if let Some(stylesheet_url) = html_tree.find_stylesheet_url() {
+        loader::spawn(stylesheet_url.as_str(), {
+            let stylesheet_url = stylesheet_url.clone();
+            move || Loaded::Stylesheet(load_stylesheet(stylesheet_url.as_str(), 0, cache_mode))
+        });
+        css::parse("".to_string())
     } else if let Some(stylesheet_str) = html_tree.find_stylesheet_in_style_tag() {
-        css_source = stylesheet_str;
+        let stylesheet = stats::time(Phase::Parse, || css::parse(stylesheet_str));
+        if !stylesheet.imports.is_empty() {
+            loader::spawn(base_url.as_str(), {
+                let stylesheet = stylesheet.clone();
+                let base_url = base_url.clone();
+                move || Loaded::Stylesheet(resolve_imports(stylesheet, &base_url, 0, cache_mode))
+            });
+        }
+        stylesheet
     } else {
         debug_println!("*** Not found any stylesheet but continue ***");
-    }
-    let stylesheet = css::parse(css_source);
+        css::parse("".to_string())
+    };
     debug_println!("{}", stylesheet);
 
+    // The icon is loaded in the background too.
+    FAVICON.with(|f| *f.borrow_mut() = None);
+    if let Some(icon_url) = favicon_url(&html_tree, &html_src_url) {
+        loader::spawn(icon_url.as_str(), {
+            let icon_url = icon_url.clone();
+            move || match download(icon_url.as_str(), Destination::Image, cache_mode) {
+                Ok(resource) => Loaded::Icon(icon_url, resource.file_name),
+                Err(_) => Loaded::Failed,
+            }
+        });
+    }
+
     HTML_TREE.with(|h| {
         *h.borrow_mut() = Some(html_tree);
     });
     STYLESHEET.with(|s| *s.borrow_mut() = Some(stylesheet));
+    SCRIPTS.with(|s| *s.borrow_mut() = Some(scripts));
+    HOVERED.with(|h| *h.borrow_mut() = None);
+    // The scripts may have set timers.
+    wake_task_loop();
 
-    layout::LAYOUTBOX.with(|lb| *lb.borrow_mut() = None);
+    layout::discard_layout();
+    LAYOUT_IN_BACKGROUND.with(|b| b.set(true));
+    PENDING_LAYOUT.with(|p| *p.borrow_mut() = None);
 
     unsafe {
         SRC_UPDATED = true;
     }
 }
 
-pub fn run_with_url(html_src: String) {
-    let main_browser_process = ::std::thread::spawn(|| {
-        update_html_source(html_src);
+/// The URL of the current document (after redirects).
+pub fn current_url() -> Option<Url> {
+    HTML_SRC_URL.with(|u| u.borrow().clone())
+}
+
+/// The URL of the icon of ``html_tree`` at ``url``: of its ``<link rel=icon>``, or
+/// ``/favicon.ico`` of a page on the web.
+pub fn favicon_url(html_tree: &dom::Node, url: &Url) -> Option<String> {
+    match html_tree.find_icon_url() {
+        Some(icon_url) => Some(icon_url),
+        None if url.scheme() == "http" || url.scheme() == "https" => {
+            url.join("/favicon.ico").ok().map(|url| url.into_string())
+        }
+        None => None,
+    }
+}
+
+/// The icon of the current document, once it is loaded (see ``favicon_url``).
+pub fn favicon() -> Option<Image> {
+    FAVICON.with(|f| f.borrow().clone())
+}
+
+/// The ``<title>`` of the current document.
+pub fn page_title() -> Option<String> {
+    HTML_TREE.with(|h| h.borrow().as_ref().and_then(|html_tree| html_tree.find_title()))
+}
+
+/// How much of the current page is loaded, from 0 to 1: the document, and the fraction of its
+/// subresources received. It may go down when the layout finds more images to load.
+pub fn load_progress() -> f64 {
+    match loader::progress() {
+        (_, 0) => 1.0,
+        (received, requested) => received as f64 / requested as f64,
+    }
+}
+
+/// The innermost element painted at (``x``, ``y``) in the document (in CSS px).
+pub fn node_at(x: f64, y: f64) -> Option<dom::Node> {
+    hit_test(x, y, &|layout_box| match (&layout_box.box_type, &layout_box.node.data) {
+        (&layout::BoxType::AnonymousBlock, _) | (_, &dom::NodeType::Text(_)) => None,
+        _ => Some(layout_box.node.clone()),
+    })
+}
+
+/// The dimensions of every box of the page as last laid out (see ``painter::box_model``).
+pub fn box_model() -> Vec<layout::Dimensions> {
+    layout::laid_out(|layout_tree| painter::box_model(layout_tree)).unwrap_or_default()
+}
+
+/// Whether the page as last laid out scrolls smoothly (``scroll-behavior: smooth`` on the root)
+pub fn smooth_scrolling() -> bool {
+    layout::laid_out(|layout_tree| layout_tree.property.smooth_scrolling()).unwrap_or(false)
+}
+
+/// What the inspector reports on the innermost element at (``x``, ``y``) in the document (in CSS
+/// px). See ``inspector::inspect``.
+pub fn inspect_at(x: f64, y: f64) -> Option<String> {
+    let stylesheet = STYLESHEET.with(|s| s.borrow().clone())?;
+    layout::laid_out(|layout_tree| {
+        inspector::inspect(&inspector::path_at(layout_tree, x, y), &stylesheet)
+    }).flatten()
+}
+
+/// The pointer to show at (``x``, ``y``) in the document (in CSS px). ``cursor: auto`` is the
+/// I-beam over text and text inputs.
+pub fn cursor_at(x: f64, y: f64) -> style::Cursor {
+    let cursor = hit_test(x, y, &|layout_box| match layout_box.box_type {
+        layout::BoxType::AnonymousBlock => None,
+        layout::BoxType::TextNode(_) => Some((layout_box.property.cursor(), true)),
+        _ => Some((layout_box.property.cursor(), layout_box.is_text_input())),
+    });
+    match cursor {
+        Some((style::Cursor::Auto, true)) => style::Cursor::Text,
+        Some((style::Cursor::Auto, false)) | None => style::Cursor::Default,
+        Some((cursor, _)) => cursor,
+    }
+}
+
+/// Calls ``f`` with the boxes whose border box contains (``x``, ``y``), innermost (and topmost)
+/// first, until it returns Some. Those with ``pointer-events: none`` are skipped.
+fn hit_test<T, F>(x: f64, y: f64, f: &F) -> Option<T>
+where
+    F: Fn(&layout::LayoutBox) -> Option<T>,
+{
+    fn find<T, F>(
+        layout_box: &layout::LayoutBox,
+        x: Au,
+        y: Au,
+        px: f64,
+        py: f64,
+        f: &F,
+    ) -> Option<T>
+    where
+        F: Fn(&layout::LayoutBox) -> Option<T>,
+    {
+        // Neither the box nor its descendants are hit out of its clip-path.
+        if !shape::hits_clip_path(layout_box, x, y, px, py) {
+            return None;
+        }
+        let (child_x, child_y) = overflow::child_origin(layout_box, x, y);
+        // The one painted over the others first. None is hit where they are clipped.
+        let children = if overflow::hits_descendants(layout_box, x, y, px, py) {
+            &layout_box.children[..]
+        } else {
+            &[]
+        };
+        for child in children.iter().rev() {
+            if let Some(found) = find(child, child_x, child_y, px, py, f) {
+                return Some(found);
+            }
+        }
+
+        let border_box = layout_box.dimensions.border_box().add_parent_coordinate(x, y);
+        if border_box.contains_point(px, py) && layout_box.property.pointer_events() {
+            f(layout_box)
+        } else {
+            None
+        }
+    }
+
+    layout::laid_out(|layout_tree| find(layout_tree, Au(0), Au(0), x, y, f)).flatten()
+}
+
+/// Scrolls the innermost box at (``x``, ``y``) in the document (CSS px) that the user scrolls,
+/// and that can be scrolled further by (``dx``, ``dy``) px, and paints the page again. Returns
+/// false if there is none (and the page is scrolled instead).
+pub fn scroll_box_at(x: f64, y: f64, dx: f64, dy: f64) -> bool {
+    layout::laid_out(|layout_tree| {
+        let scrolled = inspector::path_at(layout_tree, x, y)
+            .into_iter()
+            .rev()
+            .any(|layout_box| overflow::scroll_by(layout_box, dx, dy));
+        if scrolled {
+            // The links are where they are painted.
+            controls::ANKERS.with(|ankers| ankers.borrow_mut().clear());
+            let list = painter::build_display_list(layout_tree);
+            LAYOUT_SAVER.with(|x| x.borrow_mut().2 = list);
+        }
+        scrolled
+    }).unwrap_or(false)
+}
+
+/// Sets the scroll container whose scrollbars are at (``x``, ``y``) in the document (CSS px) as
+/// hovered, and paints the page again if it changed. Returns whether it changed.
+pub fn hover_scrollbar(x: f64, y: f64) -> bool {
+    layout::laid_out(|layout_tree| {
+        let changed = overflow::set_hovered_scrollbar(overflow::scrollbar_at(layout_tree, x, y));
+        if changed {
+            controls::ANKERS.with(|ankers| ankers.borrow_mut().clear());
+            let list = painter::build_display_list(layout_tree);
+            LAYOUT_SAVER.with(|x| x.borrow_mut().2 = list);
+        }
+        changed
+    }).unwrap_or(false)
+}
+
+/// The width of the scrollbar of the viewport (``auto_width`` for ``scrollbar-width: auto``), and
+/// the colors of its track and its thumb (see ``overflow::scrollbar_colors``), from the style of
+/// the root
+pub fn viewport_scrollbar(auto_width: f64, hovered: bool) -> (f64, css::Color, css::Color) {
+    let colors = |style: &style::Style| {
+        let width = overflow::scrollbar_width(style.scrollbar_width(), auto_width);
+        let (track, thumb) = overflow::scrollbar_colors(style, hovered);
+        (width, track, thumb)
+    };
+    layout::laid_out(|layout_tree| colors(&layout_tree.property))
+        .unwrap_or_else(|| colors(&style::Style::new()))
+}
+
+/// How the subresources of the current document are fetched.
+pub fn page_cache_mode() -> CacheMode {
+    PAGE_CACHE_MODE.with(|m| *m.borrow())
+}
+
+/// ``@import`` nested deeper than this is ignored (guards against import cycles).
+const MAX_IMPORT_DEPTH: usize = 8;
 
-        window::render(move |widget| {
-            let mut viewport: layout::Dimensions = ::std::default::Default::default();
-            viewport.content.width = Au::from_f64_px(widget.get_allocated_width() as f64);
-            viewport.content.height = Au::from_f64_px(widget.get_allocated_height() as f64);
-
-            LAYOUT_SAVER.with(|x| {
-                let (ref mut last_width, ref mut last_height, ref mut last_displays) =
-                    *x.borrow_mut();
-                if *last_width == viewport.content.width && *last_height == viewport.content.height
-                    && unsafe { !SRC_UPDATED }
-                {
-                    last_displays.clone()
-                } else {
-                    unsafe {
-                        SRC_UPDATED = false;
+/// A stylesheet that cannot be loaded is empty.
+fn load_stylesheet(url_str: &str, depth: usize, cache_mode: CacheMode) -> css::Stylesheet {
+    let resource = match download(url_str, Destination::Style, cache_mode) {
+        Ok(resource) => resource,
+        Err(_) => return css::parse("".to_string()),
+    };
+    let src = read_file(resource.file_name.as_str()).unwrap_or_default();
+    let mut stylesheet = stats::time(Phase::Parse, || css::parse(src));
+    resolve_urls(&mut stylesheet, &resource.url);
+    resolve_imports(stylesheet, &resource.url, depth, cache_mode)
+}
+
+/// Resolves the ``url(...)``s in ``stylesheet`` (e.g. of ``background-image``) against
+/// ``base_url``, the URL of the stylesheet. Those in ``<style>``s are resolved against the
+/// document when they are painted.
+fn resolve_urls(stylesheet: &mut css::Stylesheet, base_url: &Url) {
+    for rule in &mut stylesheet.rules {
+        for declaration in &mut rule.declarations {
+            for value in &mut declaration.values {
+                if let css::Value::Url(ref mut url) = *value {
+                    if let Some(resolved) = network::resolve_url(base_url, url) {
+                        *url = resolved.to_string();
                     }
+                }
+            }
+        }
+    }
+}
 
-                    *last_width = viewport.content.width;
-                    *last_height = viewport.content.height;
+/// Puts the rules of the stylesheets imported by ``stylesheet`` before its own rules.
+/// ``@import`` URLs are resolved against ``base_url``, the URL of ``stylesheet``.
+fn resolve_imports(
+    stylesheet: css::Stylesheet,
+    base_url: &Url,
+    depth: usize,
+    cache_mode: CacheMode,
+) -> css::Stylesheet {
+    if depth >= MAX_IMPORT_DEPTH {
+        return stylesheet;
+    }
 
-                    let html_tree = HTML_TREE.with(|h| (*h.borrow()).clone().unwrap());
-                    let stylesheet = STYLESHEET.with(|s| (*s.borrow()).clone().unwrap());
-                    let mut layout_tree = layout::layout_tree(&html_tree, &stylesheet, viewport);
-                    // debug_println!("LAYOUT:\n{}", layout_tree);
+    let (mut rules, mut pages) = (vec![], vec![]);
+    for import in &stylesheet.imports {
+        if let Some(url) = network::resolve_url(base_url, import) {
+            let imported = load_stylesheet(url.as_str(), depth + 1, cache_mode);
+            rules.extend(imported.rules);
+            pages.extend(imported.pages);
+        }
+    }
+    rules.extend(stylesheet.rules);
+    pages.extend(stylesheet.pages);
 
-                    let display_command = painter::build_display_list(&mut layout_tree);
-                    // debug_println!("DISPLAY:\n{:?}", display_command);
+    css::Stylesheet {
+        rules: rules,
+        imports: stylesheet.imports,
+        pages: pages,
+    }
+}
 
-                    *last_displays = display_command.clone();
+/// Applies the subresources loaded in the background since the last call.
+/// Returns true if the page has to be laid out and painted again.
+pub fn apply_loaded_resources() -> bool {
+    let mut updated = false;
+    let mut images_updated = false;
 
-                    display_command
+    for loaded in loader::finished() {
+        match loaded {
+            Loaded::Stylesheet(stylesheet) => {
+                debug_println!("{}", stylesheet);
+                // The boxes are kept if they are the same with the new styles.
+                layout::restyle(&stylesheet);
+                STYLESHEET.with(|s| *s.borrow_mut() = Some(stylesheet));
+                updated = true;
+            }
+            Loaded::Image(url, cache_name) => {
+                images_updated |= inline::register_image(url, cache_name.as_str());
+            }
+            Loaded::Icon(_, cache_name) => {
+                // A broken icon is not shown.
+                if let Some(pixbuf) = image::decode(cache_name.as_str()) {
+                    FAVICON.with(|f| *f.borrow_mut() = Some(pixbuf));
                 }
-            })
-        });
+            }
+            Loaded::Failed => {}
+        }
+    }
+
+    if images_updated {
+        layout::LAYOUTBOX.with(|lb| *lb.borrow_mut() = None);
+    }
+    updated |= images_updated;
+    if updated {
+        unsafe {
+            SRC_UPDATED = true;
+        }
+    }
+
+    updated
+}
+
+/// Takes the layout of the page loaded once it is received from the worker thread (see
+/// ``display_list``), and loads its images. Returns true if the page has to be painted again.
+pub fn receive_layout() -> bool {
+    let received = PENDING_LAYOUT.with(|p| p.borrow().as_ref().map(|job| job.try_recv()));
+    let laid_out = match received {
+        Some(Ok(laid_out)) => Some(laid_out),
+        Some(Err(TryRecvError::Empty)) | None => return false,
+        // The layout panicked: the page is laid out on this thread instead.
+        Some(Err(TryRecvError::Disconnected)) => None,
+    };
+    PENDING_LAYOUT.with(|p| *p.borrow_mut() = None);
+    LAYOUT_IN_BACKGROUND.with(|b| b.set(false));
+
+    match laid_out {
+        Some(mut laid_out) => {
+            laid_out.load_images();
+            let moved = laid_out.keep_state();
+            let items = painter::build_display_list(&mut laid_out.layout_root);
+            layout::keep_laid_out(laid_out.layout_root);
+            LAYOUT_SAVER.with(|x| x.borrow_mut().2 = items);
+            // Scrolled meanwhile: the boxes and the images now near are laid out.
+            if moved {
+                invalidate_display_list();
+            }
+        }
+        None => invalidate_display_list(),
+    }
+    true
+}
+
+/// Makes the next draw rebuild the display list (e.g. after focus changes).
+pub fn invalidate_display_list() {
+    unsafe {
+        SRC_UPDATED = true;
+    }
+}
+
+/// Sets the color scheme preferred by the user (see ``media::set_preferred_color_scheme``). The
+/// page is styled again if it changed, for ``prefers-color-scheme`` and the system colors.
+pub fn set_preferred_color_scheme(scheme: ColorScheme) {
+    if media::set_preferred_color_scheme(scheme) {
+        update_layout_tree();
+        invalidate_display_list();
+    }
+}
+
+/// Runs ``source`` like a script of the current page (with its global variables). The page is
+/// laid out again if it changes the DOM. Returns the value of its last expression statement as a
+/// string, or the error it throws.
+pub fn eval_script(source: &str) -> Result<String, String> {
+    with_scripts(|scripts, html_tree| scripts.eval(html_tree, source))
+        .map_or(Err("no page is loaded".to_string()), |(result, _)| result)
+}
+
+/// Draws with ``f`` on the ``<canvas>`` of ``node_id`` in the current page (see
+/// ``canvas2d::with_context``), which is then painted again. None if there is no such canvas.
+#[cfg(feature = "raster")]
+pub fn with_canvas_context<T, F>(node_id: usize, f: F) -> Option<T>
+where
+    F: FnOnce(&mut canvas2d::Context2d) -> T,
+{
+    let size = HTML_TREE.with(|h| {
+        let mut html_tree = h.borrow_mut();
+        let node = html_tree.as_mut()?.find_node_by_id_mut(node_id)?;
+        match node.data {
+            dom::NodeType::Element(ref e) if e.tag_name == "canvas" => {
+                Some(canvas2d::bitmap_size(node))
+            }
+            _ => None,
+        }
+    })?;
+    let result = canvas2d::with_context(node_id, size, f);
+    canvas2d::take_drawn();
+    invalidate_display_list();
+    Some(result)
+}
+
+/// Calls ``f`` with the scripts and the DOM of the current page, which is laid out again if
+/// they change it. Returns what ``f`` returns and whether the DOM changed or a canvas was drawn on,
+/// or None if no page is loaded.
+fn with_scripts<T, F>(f: F) -> Option<(T, bool)>
+where
+    F: FnOnce(&mut script::Runtime, &mut dom::Node) -> T,
+{
+    let result = SCRIPTS.with(|s| {
+        HTML_TREE.with(|h| match (s.borrow_mut().as_mut(), h.borrow_mut().as_mut()) {
+            (Some(scripts), Some(html_tree)) => {
+                let result = f(scripts, html_tree);
+                Some((result, scripts.take_mutated()))
+            }
+            _ => None,
+        })
+    });
+
+    if result.as_ref().is_some_and(|&(_, mutated)| mutated) {
+        update_layout_tree();
+        unsafe {
+            SRC_UPDATED = true;
+        }
+    }
+    // Painted again, though it is laid out the same
+    let drawn = canvas2d::take_drawn();
+    if drawn {
+        invalidate_display_list();
+    }
+    // They may have set timers.
+    wake_task_loop();
+    result.map(|(result, mutated)| (result, mutated || drawn))
+}
+
+/// Runs ``f`` on the main loop after ``delay`` (e.g. the next frame of an animation), unless
+/// it is canceled with ``cancel_task``.
+pub fn post_task<F: FnOnce() + 'static>(delay: Duration, f: F) -> TaskId {
+    let task: EngineTask = Box::new(f);
+    let id = TASKS.with(|t| t.borrow_mut().schedule(Instant::now() + delay, task));
+    wake_task_loop();
+    id
+}
+
+/// Cancels the task ``id`` of ``post_task``. Returns false if it has run or is canceled
+/// already.
+pub fn cancel_task(id: TaskId) -> bool {
+    TASKS.with(|t| t.borrow_mut().cancel(id).is_some())
+}
+
+/// When the next task of the engine or timer of the page is due.
+pub fn next_task_due() -> Option<Instant> {
+    let task = TASKS.with(|t| t.borrow().next_due());
+    let timer = SCRIPTS.with(|s| s.borrow().as_ref().and_then(|scripts| scripts.next_timer()));
+    match (task, timer) {
+        (Some(task), Some(timer)) => Some(task.min(timer)),
+        (task, timer) => task.or(timer),
+    }
+}
+
+/// Runs the tasks of the engine and the timers of the page that are due. Returns true if the
+/// page may have to be painted again: a task ran, or the timers changed the DOM or drew on a
+/// canvas.
+pub fn run_due_tasks() -> bool {
+    let now = Instant::now();
+    let mut ran = false;
+    // Each once at most, like the timers: the tasks they post are run by the next call.
+    let count = TASKS.with(|t| t.borrow().count_due(now));
+    for _ in 0..count {
+        // Not borrowed while running, to post or cancel tasks
+        match TASKS.with(|t| t.borrow_mut().pop_due(now)) {
+            Some((_, _, task)) => task(),
+            None => break,
+        }
+        ran = true;
+    }
+    let timers = with_scripts(|scripts, html_tree| scripts.run_timers(html_tree, now));
+    ran || timers.is_some_and(|(_, mutated)| mutated)
+}
+
+/// Makes ``waker`` be called with when the next task is due whenever one is posted or a timer is
+/// set: the window then calls ``run_due_tasks`` from its main loop.
+pub fn set_task_waker<F: Fn(Instant) + 'static>(waker: F) {
+    TASK_WAKER.with(|w| *w.borrow_mut() = Some(Rc::new(waker)));
+    wake_task_loop();
+}
+
+fn wake_task_loop() {
+    let waker = TASK_WAKER.with(|w| w.borrow().clone());
+    if let (Some(waker), Some(due)) = (waker, next_task_due()) {
+        waker(due);
+    }
+}
+
+fn dispatch_event(event: event::Event) -> Dispatched {
+    with_scripts(|scripts, html_tree| scripts.dispatch_event(html_tree, event))
+        .map_or(Dispatched::default(), |(not_canceled, mutated)| Dispatched {
+            canceled: !not_canceled,
+            dom_changed: mutated,
+        })
+}
+
+/// The path in the DOM of the innermost element painted at (``x``, ``y``) (in CSS px).
+fn element_path_at(x: f64, y: f64) -> Option<Vec<usize>> {
+    let id = hit_test(x, y, &|layout_box| match (&layout_box.box_type, &layout_box.node.data) {
+        (&layout::BoxType::AnonymousBlock, _) | (_, &dom::NodeType::Text(_)) => None,
+        _ => layout_box.node_id,
+    })?;
+    HTML_TREE.with(|h| h.borrow().as_ref().and_then(|html_tree| html_tree.find_path_by_id(id)))
+}
+
+/// Dispatches the mouse event ``kind`` (e.g. ``click``) to the innermost element at (``x``,
+/// ``y``) in the document (in CSS px).
+pub fn dispatch_mouse_event(kind: &str, x: f64, y: f64) -> Dispatched {
+    match element_path_at(x, y) {
+        Some(target) => dispatch_event(event::Event::mouse(kind, target, x, y)),
+        None => Dispatched::default(),
+    }
+}
+
+/// Dispatches ``mouseover`` to the element at (``x``, ``y``) in the document (in CSS px) if the
+/// pointer was not over it.
+pub fn move_pointer(x: f64, y: f64) -> Dispatched {
+    let target = element_path_at(x, y);
+    let moved_onto = HOVERED.with(|h| {
+        let mut hovered = h.borrow_mut();
+        let moved = *hovered != target;
+        *hovered = target.clone();
+        if moved {
+            target
+        } else {
+            None
+        }
+    });
+    match moved_onto {
+        Some(target) => dispatch_event(event::Event::mouse("mouseover", target, x, y)),
+        None => Dispatched::default(),
+    }
+}
+
+/// Dispatches the key event ``kind`` (e.g. ``keydown``) of ``key`` (e.g. ``a`` or ``Enter``) to
+/// the node whose id is ``focused`` (e.g. the focused text input), or to the ``<body>``.
+pub fn dispatch_key_event(kind: &str, key: &str, focused: Option<usize>) -> Dispatched {
+    let target = HTML_TREE.with(|h| {
+        h.borrow().as_ref().map(|html_tree| {
+            focused
+                .and_then(|id| html_tree.find_path_by_id(id))
+                .unwrap_or_else(|| event::body_path(html_tree))
+        })
+    });
+    match target {
+        Some(target) => dispatch_event(event::Event::key(kind, target, key)),
+        None => Dispatched::default(),
+    }
+}
+
+/// Replaces the value of the text input whose node id is `id`, and lays out the page again.
+pub fn set_text_input_value(id: usize, value: String) {
+    HTML_TREE.with(|h| {
+        if let Some(ref mut html_tree) = *h.borrow_mut() {
+            if let Some(node) = html_tree.find_node_by_id_mut(id) {
+                node.set_text_input_value(value);
+            }
+        }
+    });
+
+    update_layout_tree();
+
+    unsafe {
+        SRC_UPDATED = true;
+    }
+}
+
+/// Selects the option at ``index`` of the ``<select>`` whose node id is ``id``. If it was not
+/// selected, the page is laid out again and ``change`` is dispatched to the ``<select>``.
+pub fn set_selected_option(id: usize, index: usize) -> Dispatched {
+    let target = HTML_TREE.with(|h| {
+        let mut h = h.borrow_mut();
+        let html_tree = h.as_mut()?;
+        let select = html_tree.find_node_by_id_mut(id)?;
+        if select.selected_option() == Some(index) {
+            return None;
+        }
+        select.set_selected_option(index);
+        html_tree.find_path_by_id(id)
+    });
+    let target = match target {
+        Some(target) => target,
+        None => return Dispatched::default(),
+    };
+
+    update_layout_tree();
+
+    unsafe {
+        SRC_UPDATED = true;
+    }
+    dispatch_event(event::Event::new("change", target))
+}
+
+/// Updates the boxes of the page after its DOM changed (see ``layout::update``): only those
+/// changed are built again, and it is only laid out again if they are laid out otherwise.
+fn update_layout_tree() {
+    HTML_TREE.with(|h| {
+        STYLESHEET.with(|s| {
+            if let (Some(html_tree), Some(stylesheet)) = (&*h.borrow(), &*s.borrow()) {
+                layout::update(html_tree, stylesheet);
+            }
+        })
+    });
+}
+
+/// Loads and lays out the page at ``url_str`` in a viewport of ``width`` x ``height`` CSS px
+/// without a window (e.g. for layout tests). Stylesheets are loaded before the layout, but
+/// images are not waited for.
+pub fn layout_headless(url_str: &str, width: f64, height: f64) -> layout::LayoutBox {
+    let (html_tree, stylesheet) = load_headless(url_str);
+    layout_document(&html_tree, &stylesheet, width, height)
+}
+
+/// Loads the document at ``url_str`` and its stylesheet, and runs its scripts.
+fn load_headless(url_str: &str) -> (dom::Node, css::Stylesheet) {
+    let cache_mode = CacheMode::Default;
+    let (url, mut html_tree) = load_document(url_str, cache_mode);
+    HTML_SRC_URL.with(|u| *u.borrow_mut() = Some(url.clone()));
+
+    let base_url = html_tree.find_base_url().unwrap_or_else(|| url.clone());
+    script::Runtime::new(base_url.clone()).run_scripts(&mut html_tree);
+    let stylesheet = if let Some(stylesheet_url) = html_tree.find_stylesheet_url() {
+        load_stylesheet(stylesheet_url.as_str(), 0, cache_mode)
+    } else if let Some(stylesheet_str) = html_tree.find_stylesheet_in_style_tag() {
+        let stylesheet = stats::time(Phase::Parse, || css::parse(stylesheet_str));
+        resolve_imports(stylesheet, &base_url, 0, cache_mode)
+    } else {
+        css::parse("".to_string())
+    };
+    (html_tree, stylesheet)
+}
+
+fn layout_document(
+    html_tree: &dom::Node,
+    stylesheet: &css::Stylesheet,
+    width: f64,
+    height: f64,
+) -> layout::LayoutBox {
+    let mut viewport: layout::Dimensions = ::std::default::Default::default();
+    viewport.content.width = Au::from_f64_px(width);
+    viewport.content.height = Au::from_f64_px(height);
+    layout::LAYOUTBOX.with(|lb| *lb.borrow_mut() = None);
+    layout::layout_tree(html_tree, stylesheet, viewport)
+}
+
+/// Paints the page laid out by ``layout_headless`` (e.g. for reftests).
+#[cfg(feature = "raster")]
+pub fn render_headless(
+    url_str: &str,
+    width: f64,
+    height: f64,
+) -> Result<cairo::ImageSurface, cairo::Status> {
+    painter::set_viewport(viewport_rect(width, height));
+    let items = painter::build_display_list(&mut layout_headless(url_str, width, height));
+    raster::paint(&items, 1.0, &mut headless_pango_layout()?)
+}
+
+/// A Pango layout to paint texts with, not the one measuring them (``font::PANGO_LAYOUT``),
+/// which painting text uses too
+#[cfg(feature = "raster")]
+fn headless_pango_layout() -> Result<pango::Layout, cairo::Status> {
+    let surface = cairo::ImageSurface::create(cairo::Format::Rgb24, 0, 0)?;
+    let pango_ctx = pangocairo::functions::create_context(&cairo::Context::new(&surface)).unwrap();
+    Ok(pango::Layout::new(&pango_ctx))
+}
+
+/// The viewport of ``width`` x ``height`` CSS px at the top of the document
+fn viewport_rect(width: f64, height: f64) -> layout::Rect {
+    layout::Rect {
+        x: Au(0),
+        y: Au(0),
+        width: Au::from_f64_px(width),
+        height: Au::from_f64_px(height),
+    }
+}
+
+/// Renders the viewport (``width`` x ``height`` CSS px) of ``url_str`` without a window, like
+/// ``render_headless`` but cropped to the viewport, or filled with white below a shorter page.
+#[cfg(feature = "raster")]
+pub fn render_viewport_headless(
+    url_str: &str,
+    width: f64,
+    height: f64,
+) -> Result<cairo::ImageSurface, cairo::Status> {
+    let page = render_headless(url_str, width, height)?;
+
+    let surface = cairo::ImageSurface::create(
+        cairo::Format::ARgb32,
+        width.ceil() as i32,
+        height.ceil() as i32,
+    )?;
+    {
+        let ctx = cairo::Context::new(&surface);
+        ctx.set_source_rgb(1.0, 1.0, 1.0);
+        ctx.paint();
+        ctx.set_source_surface(&page, 0.0, 0.0);
+        ctx.paint();
+    }
+    Ok(surface)
+}
+
+/// Saves a screenshot of the viewport (``width`` x ``height`` CSS px) of ``url_str`` as a PNG
+/// file, without opening a window.
+#[cfg(feature = "raster")]
+pub fn screenshot(url_str: &str, width: f64, height: f64, out_path: &str) -> Result<(), cairo::IoError> {
+    let surface = render_viewport_headless(url_str, width, height).map_err(cairo::IoError::Cairo)?;
+    let mut file = fs::File::create(out_path).map_err(cairo::IoError::Io)?;
+    surface.write_to_png(&mut file)
+}
+
+/// Saves a screenshot of the whole document of ``url_str`` laid out in a viewport of ``width`` x
+/// ``height`` CSS px as a PNG file, not only the viewport, on white: as tall as the document (or
+/// the viewport below a shorter one). It is painted in strips (see ``raster::paint_png``), however
+/// tall it is. E.g. to archive it or to compare it with one taken before.
+#[cfg(feature = "raster")]
+pub fn full_page_screenshot(
+    url_str: &str,
+    width: f64,
+    height: f64,
+    out_path: &str,
+) -> Result<(), cairo::IoError> {
+    painter::set_viewport(viewport_rect(width, height));
+    let items = painter::build_display_list(&mut layout_headless(url_str, width, height));
+    let document_height = match items[0].command {
+        DisplayCommand::SolidColor(_, content_rect) => content_rect.height.to_f64_px(),
+        _ => height,
+    };
+
+    let size = (width.ceil() as i32, document_height.max(height).ceil() as i32);
+    let mut pango_layout = headless_pango_layout().map_err(cairo::IoError::Cairo)?;
+    let mut file = fs::File::create(out_path).map_err(cairo::IoError::Io)?;
+    raster::paint_png(&items, size, &mut pango_layout, &mut file)
+}
+
+/// Prints the page at ``url_str`` to a PDF file, split into the pages of its ``@page`` rules (see
+/// ``paged``), with the ``print`` media queries matching. Returns how many pages it has.
+#[cfg(feature = "raster")]
+pub fn print_to_pdf(url_str: &str, out_path: &str) -> Result<usize, cairo::Status> {
+    use cairo::prelude::SurfaceExt;
+
+    media::set_media_type(media::MediaType::Print);
+    let (html_tree, stylesheet) = load_headless(url_str);
+    let (_, _, width, height) = paged::page_box(&stylesheet, 0).area();
+    painter::set_viewport(viewport_rect(width, height));
+    let items =
+        painter::build_display_list(&mut layout_document(&html_tree, &stylesheet, width, height));
+    media::set_media_type(media::MediaType::Screen);
+
+    let document_height = match items[0].command {
+        DisplayCommand::SolidColor(_, content_rect) => content_rect.height.to_f64_px(),
+        _ => height,
+    };
+    let pages = paged::paginate(&stylesheet, document_height);
+    // In pt, 3/4 of a CSS px
+    let first = pages[0].0;
+    let surface = cairo::PDFSurface::create(out_path, first.width * 0.75, first.height * 0.75);
+    let ctx = cairo::Context::new(&surface);
+    ctx.scale(0.75, 0.75);
+    let mut pango_layout = pangocairo::functions::create_layout(&ctx).unwrap();
+    for &(page, offset) in &pages {
+        let (x, y, width, height) = page.area();
+        ctx.save();
+        ctx.rectangle(x, y, width, height);
+        ctx.clip();
+        ctx.translate(x, y - offset);
+        raster::paint_to(&ctx, &items, &mut pango_layout);
+        ctx.restore();
+        ctx.show_page();
+    }
+    surface.finish();
+    match surface.status() {
+        cairo::Status::Success => Ok(pages.len()),
+        status => Err(status),
+    }
+}
+
+/// A web page drawing the viewport (``width`` x ``height`` CSS px) of ``url_str`` on a canvas
+/// (see ``canvas``).
+pub fn canvas_page(url_str: &str, width: f64, height: f64) -> String {
+    painter::set_viewport(viewport_rect(width, height));
+    let items = painter::build_display_list(&mut layout_headless(url_str, width, height));
+    let mut backend = canvas::CanvasBackend::new();
+    painter::paint(&items, &mut backend);
+    canvas::to_html(&backend.script, width, height)
+}
+
+/// The display list of the current page laid out in ``widget`` at ``zoom`` (again if its size or
+/// the page changed). Only the background is painted until a page is loaded.
+#[cfg(feature = "gtk")]
+pub fn display_list(widget: &gtk::DrawingArea, zoom: f64) -> painter::DisplayList {
+    // Zooming changes how many CSS px fit in the window.
+    let mut viewport: layout::Dimensions = ::std::default::Default::default();
+    viewport.content.width = Au::from_f64_px(widget.get_allocated_width() as f64 / zoom);
+    viewport.content.height = Au::from_f64_px(widget.get_allocated_height() as f64 / zoom);
+
+    LAYOUT_SAVER.with(|x| {
+        let (ref mut last_width, ref mut last_height, ref mut last_displays) = *x.borrow_mut();
+        if *last_width == viewport.content.width && *last_height == viewport.content.height
+            && unsafe { !SRC_UPDATED }
+        {
+            // Painted again (not laid out) where the fixed backgrounds are now
+            if painter::fixed_backgrounds_moved() {
+                if let Some(items) = layout::laid_out(|tree| painter::build_display_list(tree)) {
+                    *last_displays = items;
+                }
+            }
+            last_displays.clone()
+        } else if LAYOUT_IN_BACKGROUND.with(|b| b.get()) {
+            // A page loaded is laid out on a worker thread, while the window goes on handling
+            // events, and only the background is painted until the layout is received (see
+            // ``receive_layout``). What changes meanwhile is laid out after it.
+            let background = DisplayCommand::SolidColor(css::WHITE, viewport.content);
+            if PENDING_LAYOUT.with(|p| p.borrow().is_none()) {
+                unsafe {
+                    SRC_UPDATED = false;
+                }
+                *last_width = viewport.content.width;
+                *last_height = viewport.content.height;
+                *last_displays = vec![DisplayCommandInfo::new(background.clone())];
+                layout::discard_layout();
+
+                let html_tree = HTML_TREE.with(|h| (*h.borrow()).clone());
+                let stylesheet = STYLESHEET.with(|s| (*s.borrow()).clone());
+                if let (Some(html_tree), Some(stylesheet)) = (html_tree, stylesheet) {
+                    let job = layout_worker::spawn(html_tree, stylesheet, viewport);
+                    PENDING_LAYOUT.with(|p| *p.borrow_mut() = Some(job));
+                }
+            }
+            vec![DisplayCommandInfo::new(background)]
+        } else {
+            unsafe {
+                SRC_UPDATED = false;
+            }
+
+            *last_width = viewport.content.width;
+            *last_height = viewport.content.height;
+
+            let html_tree = HTML_TREE.with(|h| (*h.borrow()).clone());
+            let stylesheet = STYLESHEET.with(|s| (*s.borrow()).clone());
+            let (html_tree, stylesheet) = match (html_tree, stylesheet) {
+                (Some(html_tree), Some(stylesheet)) => (html_tree, stylesheet),
+                _ => {
+                    let background = DisplayCommand::SolidColor(css::WHITE, viewport.content);
+                    *last_displays = vec![DisplayCommandInfo::new(background)];
+                    return last_displays.clone();
+                }
+            };
+            layout::lay_out(&html_tree, &stylesheet, viewport);
+            // debug_println!("LAYOUT:\n{}", layout_tree);
+
+            let display_command =
+                layout::laid_out(|layout_tree| painter::build_display_list(layout_tree)).unwrap();
+            // debug_println!("DISPLAY:\n{:?}", display_command);
+
+            *last_displays = display_command.clone();
+
+            display_command
+        }
+    })
+}
+
+#[cfg(feature = "gtk")]
+pub fn run_with_url(html_src: String) {
+    let main_browser_process = ::std::thread::spawn(|| {
+        update_html_source(html_src);
+
+        window::render(display_list);
     }).join();
 
     if let Err(_) = main_browser_process {
@@ -190,6 +1269,10 @@ pub fn run_with_url(html_src: String) {
         for entry in dir {
             if let Ok(entry) = entry {
                 let path = entry.path();
+                if path.is_dir() {
+                    // e.g. the HTTP cache, which is kept across runs
+                    continue;
+                }
                 if let Some(filename) = path.file_name() {
                     if filename.to_str().unwrap() == "README.md" {
                         continue;
@@ -200,3 +1283,23 @@ pub fn run_with_url(html_src: String) {
         }
     }
 }
+
+#[test]
+fn test_download_errors() {
+    let cache_mode = CacheMode::Default;
+    match download("mailto:a@example.com", Destination::Document, cache_mode) {
+        Err(DownloadError::UnsupportedScheme(ref scheme)) => assert_eq!(scheme, "mailto"),
+        other => panic!("{:?}", other),
+    }
+    let error = download("mailto:a@example.com", Destination::Document, cache_mode).unwrap_err();
+    assert_eq!(error.to_string(), "mailto: URLs are not supported");
+    // A broken image or an empty stylesheet
+    match download("data:image/png;base64,@@", Destination::Image, cache_mode) {
+        Err(DownloadError::InvalidDataUrl) => {}
+        other => panic!("{:?}", other),
+    }
+    match download("file:///no/such/file.html", Destination::Document, cache_mode) {
+        Err(DownloadError::Io(ref e)) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+        other => panic!("{:?}", other),
+    }
+}
@@ -0,0 +1,2191 @@
+//! A tree-walking interpreter of the JavaScript parsed by ``js_parser``, with the builtins that
+//! scripts of pages use most (``console``, ``Math``, ``JSON.stringify``, and the methods of
+//! strings, numbers and arrays). The objects of the embedder (e.g. DOM elements) are
+//! ``HostObject``s, whose properties and methods are those of its ``Host``.
+//!
+//! There are no prototypes: objects only have their own properties, and the methods of the
+//! builtin types. Strings are indexed by ``char``s. A script that runs too long (or recurses too
+//! deep) is stopped with an error.
+
+extern crate rand;
+
+use js_parser::{self, BinaryOp, Expr, ForEach, FunctionDef, LogicalOp, Stmt, UnaryOp, VarKind};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::f64;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The statements run by a script until it is stopped
+const MAX_STEPS: usize = 1_000_000;
+/// The depth of calls where a script is stopped
+const MAX_DEPTH: usize = 64;
+/// The most elements of an array and the most bytes of a string. Longer ones are a RangeError,
+/// so that a script cannot make the browser run out of memory (which cannot be caught).
+const MAX_LENGTH: usize = 1 << 24;
+
+pub type ObjectRef = Rc<RefCell<Object>>;
+pub type ScopeRef = Rc<RefCell<Scope>>;
+
+#[derive(Clone)]
+pub enum Value {
+    Undefined,
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Object(ObjectRef),
+}
+
+pub struct Object {
+    pub kind: ObjectKind,
+    /// In the order they were added
+    pub properties: Vec<(String, Value)>,
+    /// The function that constructed it with ``new``
+    constructor: Option<Value>,
+}
+
+pub enum ObjectKind {
+    Ordinary,
+    Array(Vec<Value>),
+    Function(Function),
+    /// With a ``name`` and a ``message``
+    Error,
+    Host(HostObject),
+}
+
+#[derive(Clone)]
+pub enum Function {
+    Closure(Rc<FunctionDef>, ScopeRef),
+    /// A builtin: the object it is of (e.g. ``Math``, or ``String.prototype`` for the methods of
+    /// strings) and its name
+    Builtin(&'static str, &'static str),
+    /// A method of a host object
+    Host(HostObject, &'static str),
+}
+
+/// An object of the embedder: what it is (e.g. ``element``), and which one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HostObject {
+    pub class: &'static str,
+    pub key: Vec<usize>,
+}
+
+/// The embedder, which gives its objects their properties. ``Interpreter::host`` is the
+/// embedder itself.
+pub trait Host: Sized {
+    /// The property ``name`` of ``object`` (``host_method`` for its methods), or undefined.
+    fn get(js: &mut Interpreter<Self>, object: &HostObject, name: &str) -> Result<Value, Value>;
+    /// Sets the property ``name`` of ``object``.
+    fn set(
+        js: &mut Interpreter<Self>,
+        object: &HostObject,
+        name: &str,
+        value: Value,
+    ) -> Result<(), Value>;
+    /// Calls the method ``name`` of ``object``.
+    fn call(
+        js: &mut Interpreter<Self>,
+        object: &HostObject,
+        name: &'static str,
+        args: Vec<Value>,
+    ) -> Result<Value, Value>;
+}
+
+/// Variables, of a function or a block
+pub struct Scope {
+    /// (value, whether it is not ``const``)
+    vars: HashMap<String, (Value, bool)>,
+    parent: Option<ScopeRef>,
+    /// Where ``var``s are declared
+    is_function: bool,
+}
+
+impl Scope {
+    fn new(parent: Option<&ScopeRef>, is_function: bool) -> ScopeRef {
+        Rc::new(RefCell::new(Scope {
+            vars: HashMap::new(),
+            parent: parent.cloned(),
+            is_function: is_function,
+        }))
+    }
+
+    fn declare(&mut self, name: &str, value: Value, mutable: bool) {
+        self.vars.insert(name.to_string(), (value, mutable));
+    }
+}
+
+fn lookup(scope: &ScopeRef, name: &str) -> Option<Value> {
+    let scope = scope.borrow();
+    match scope.vars.get(name) {
+        Some(&(ref value, _)) => Some(value.clone()),
+        None => scope.parent.as_ref().and_then(|parent| lookup(parent, name)),
+    }
+}
+
+/// Assigns to the variable ``name``. Returns false if none is declared.
+fn assign(scope: &ScopeRef, name: &str, value: Value) -> Result<bool, Value> {
+    let mut scope = scope.borrow_mut();
+    if let Some(var) = scope.vars.get_mut(name) {
+        if !var.1 {
+            return Err(error("TypeError", "Assignment to constant variable."));
+        }
+        var.0 = value;
+        return Ok(true);
+    }
+    match scope.parent {
+        Some(ref parent) => assign(parent, name, value),
+        None => Ok(false),
+    }
+}
+
+fn function_scope(scope: &ScopeRef) -> ScopeRef {
+    if scope.borrow().is_function {
+        return scope.clone();
+    }
+    let parent = scope.borrow().parent.clone();
+    match parent {
+        Some(parent) => function_scope(&parent),
+        None => scope.clone(),
+    }
+}
+
+/// Declares the ``var``s of ``stmts`` (outside of the functions in them) in ``scope``.
+fn declare_vars(stmts: &[Stmt], scope: &ScopeRef) {
+    for stmt in stmts {
+        declare_vars_of(stmt, scope);
+    }
+}
+
+fn declare_vars_of(stmt: &Stmt, scope: &ScopeRef) {
+    let declare = |name: &String| {
+        if !scope.borrow().vars.contains_key(name) {
+            scope.borrow_mut().declare(name, Value::Undefined, true);
+        }
+    };
+    match *stmt {
+        Stmt::Var(VarKind::Var, ref declarations) => {
+            for &(ref name, _) in declarations {
+                declare(name);
+            }
+        }
+        Stmt::ForEach(ForEach {
+            kind: Some(VarKind::Var),
+            ref name,
+            ref body,
+            ..
+        }) => {
+            declare(name);
+            declare_vars_of(body, scope);
+        }
+        Stmt::If(_, ref consequent, ref alternate) => {
+            declare_vars_of(consequent, scope);
+            if let Some(ref alternate) = *alternate {
+                declare_vars_of(alternate, scope);
+            }
+        }
+        Stmt::While(_, ref body) | Stmt::DoWhile(ref body, _) => declare_vars_of(body, scope),
+        Stmt::ForEach(ref for_each) => declare_vars_of(&for_each.body, scope),
+        Stmt::For(ref init, _, _, ref body) => {
+            if let Some(ref init) = *init {
+                declare_vars_of(init, scope);
+            }
+            declare_vars_of(body, scope);
+        }
+        Stmt::Switch(_, ref cases) => {
+            for &(_, ref body) in cases {
+                declare_vars(body, scope);
+            }
+        }
+        Stmt::Try(ref block, ref catch, ref finally) => {
+            declare_vars(block, scope);
+            if let Some((_, ref body)) = *catch {
+                declare_vars(body, scope);
+            }
+            if let Some(ref finally) = *finally {
+                declare_vars(finally, scope);
+            }
+        }
+        Stmt::Block(ref stmts) => declare_vars(stmts, scope),
+        _ => {}
+    }
+}
+
+/// How a statement ended
+enum Completion {
+    Normal,
+    Return(Value),
+    Break,
+    Continue,
+}
+
+/// What is assigned to
+enum Reference {
+    Var(String),
+    Property(Value, String),
+}
+
+const MATH_FUNCTIONS: [&str; 20] = [
+    "abs", "floor", "ceil", "round", "trunc", "sign", "sqrt", "cbrt", "pow", "min", "max",
+    "random", "sin", "cos", "tan", "atan", "atan2", "log", "exp", "hypot",
+];
+
+const STRING_METHODS: [&str; 24] = [
+    "charAt", "charCodeAt", "indexOf", "lastIndexOf", "includes", "startsWith", "endsWith",
+    "slice", "substring", "substr", "toUpperCase", "toLowerCase", "trim", "trimStart", "trimEnd",
+    "split", "replace", "replaceAll", "repeat", "padStart", "padEnd", "concat", "toString",
+    "valueOf",
+];
+
+const ARRAY_METHODS: [&str; 23] = [
+    "push", "pop", "shift", "unshift", "slice", "splice", "concat", "join", "reverse", "indexOf",
+    "includes", "map", "forEach", "filter", "reduce", "some", "every", "find", "findIndex",
+    "sort", "fill", "toString", "lastIndexOf",
+];
+
+const ERRORS: [&str; 5] = ["Error", "TypeError", "RangeError", "ReferenceError", "SyntaxError"];
+
+pub struct Interpreter<H: Host> {
+    pub host: H,
+    global: ScopeRef,
+    /// The lines logged with ``console``, to be taken by the embedder
+    pub console: Vec<String>,
+    depth: usize,
+    steps: usize,
+    /// Set when the script is stopped, so that it can not catch the error
+    stopped: bool,
+    /// The value of the last expression statement run outside of functions
+    completion: Value,
+}
+
+impl<H: Host> Interpreter<H> {
+    pub fn new(host: H) -> Interpreter<H> {
+        let mut js = Interpreter {
+            host: host,
+            global: Scope::new(None, true),
+            console: vec![],
+            depth: 0,
+            steps: 0,
+            stopped: false,
+            completion: Value::Undefined,
+        };
+        js.define_builtins();
+        js
+    }
+
+    /// Declares the global variable ``name``.
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.global.borrow_mut().declare(name, value, true);
+    }
+
+    pub fn global(&self, name: &str) -> Option<Value> {
+        lookup(&self.global, name)
+    }
+
+    fn define_builtins(&mut self) {
+        self.define_global("undefined", Value::Undefined);
+        self.define_global("NaN", Value::Number(f64::NAN));
+        self.define_global("Infinity", Value::Number(f64::INFINITY));
+        let console = ["log", "info", "warn", "error", "debug"]
+            .iter()
+            .map(|&name| (name.to_string(), builtin("console", name)))
+            .collect();
+        self.define_global("console", object(console));
+        let mut math: Vec<(String, Value)> = MATH_FUNCTIONS
+            .iter()
+            .map(|&name| (name.to_string(), builtin("Math", name)))
+            .collect();
+        math.push(("PI".to_string(), Value::Number(f64::consts::PI)));
+        math.push(("E".to_string(), Value::Number(f64::consts::E)));
+        self.define_global("Math", object(math));
+        let json = vec![("stringify".to_string(), builtin("JSON", "stringify"))];
+        self.define_global("JSON", object(json));
+        let date = vec![("now".to_string(), builtin("Date", "now"))];
+        self.define_global("Date", object(date));
+
+        for &name in &["parseInt", "parseFloat", "isNaN", "isFinite", "String", "Number"] {
+            self.define_global(name, builtin("global", name));
+        }
+        for &name in &["Boolean", "Object", "Array"] {
+            self.define_global(name, builtin("global", name));
+        }
+        for &name in ERRORS.iter() {
+            self.define_global(name, builtin("global", name));
+        }
+        for &name in &["keys", "values", "entries", "assign"] {
+            let object = self.global("Object").unwrap();
+            self.set_property(&object, name, builtin("Object", name)).ok();
+        }
+        let array = self.global("Array").unwrap();
+        self.set_property(&array, "isArray", builtin("Array", "isArray")).ok();
+    }
+
+    /// Runs the script ``source``. Returns the value of the last expression statement it runs
+    /// (outside of functions), or the error it throws (e.g. ``TypeError: a is not a function``).
+    pub fn run(&mut self, source: &str) -> Result<Value, String> {
+        let stmts = js_parser::parse(source).map_err(|e| e.to_string())?;
+        self.start();
+        let global = self.global.clone();
+        declare_vars(&stmts, &global);
+        self.declare_functions(&stmts, &global);
+        self.completion = Value::Undefined;
+        for stmt in &stmts {
+            self.exec(stmt, &global).map_err(|e| e.to_string())?;
+        }
+        Ok(mem::replace(&mut self.completion, Value::Undefined))
+    }
+
+    /// Calls ``function`` from the embedder (e.g. an event listener). Returns what it returns,
+    /// or the error it throws.
+    pub fn call_function(
+        &mut self,
+        function: &Value,
+        this: Value,
+        args: Vec<Value>,
+    ) -> Result<Value, String> {
+        self.start();
+        self.call(function, this, args).map_err(|e| e.to_string())
+    }
+
+    /// Resets the steps of a script run by the embedder.
+    fn start(&mut self) {
+        if self.depth == 0 {
+            self.steps = 0;
+            self.stopped = false;
+        }
+    }
+
+    fn step(&mut self) -> Result<(), Value> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            self.stopped = true;
+            return Err(error("Error", "the script ran too long"));
+        }
+        Ok(())
+    }
+
+    fn declare_functions(&mut self, stmts: &[Stmt], scope: &ScopeRef) {
+        for stmt in stmts {
+            if let Stmt::Function(ref def) = *stmt {
+                let function = closure(def, scope);
+                scope
+                    .borrow_mut()
+                    .declare(def.name.as_ref().unwrap(), function, true);
+            }
+        }
+    }
+
+    fn exec_block(&mut self, stmts: &[Stmt], scope: &ScopeRef) -> Result<Completion, Value> {
+        self.declare_functions(stmts, scope);
+        for stmt in stmts {
+            match self.exec(stmt, scope)? {
+                Completion::Normal => {}
+                completion => return Ok(completion),
+            }
+        }
+        Ok(Completion::Normal)
+    }
+
+    /// Runs ``stmts`` in a new block scope.
+    fn exec_scoped(&mut self, stmts: &[Stmt], scope: &ScopeRef) -> Result<Completion, Value> {
+        let block_scope = Scope::new(Some(scope), false);
+        self.exec_block(stmts, &block_scope)
+    }
+
+    fn exec(&mut self, stmt: &Stmt, scope: &ScopeRef) -> Result<Completion, Value> {
+        self.step()?;
+        match *stmt {
+            Stmt::Expr(ref expr) => {
+                let value = self.eval(expr, scope)?;
+                if self.depth == 0 {
+                    self.completion = value;
+                }
+            }
+            Stmt::Var(kind, ref declarations) => for &(ref name, ref init) in declarations {
+                let value = match *init {
+                    Some(ref init) => self.eval(init, scope)?,
+                    None if kind == VarKind::Var => continue,
+                    None => Value::Undefined,
+                };
+                if kind == VarKind::Var {
+                    function_scope(scope)
+                        .borrow_mut()
+                        .declare(name, value, true);
+                } else {
+                    scope
+                        .borrow_mut()
+                        .declare(name, value, kind == VarKind::Let);
+                }
+            },
+            Stmt::Function(_) | Stmt::Empty => {}
+            Stmt::Return(ref value) => {
+                let value = match *value {
+                    Some(ref value) => self.eval(value, scope)?,
+                    None => Value::Undefined,
+                };
+                return Ok(Completion::Return(value));
+            }
+            Stmt::If(ref test, ref consequent, ref alternate) => {
+                if self.eval(test, scope)?.truthy() {
+                    return self.exec(consequent, scope);
+                } else if let Some(ref alternate) = *alternate {
+                    return self.exec(alternate, scope);
+                }
+            }
+            Stmt::While(ref test, ref body) => while self.eval(test, scope)?.truthy() {
+                match self.exec(body, scope)? {
+                    Completion::Break => break,
+                    Completion::Return(value) => return Ok(Completion::Return(value)),
+                    Completion::Normal | Completion::Continue => {}
+                }
+            },
+            Stmt::DoWhile(ref body, ref test) => loop {
+                match self.exec(body, scope)? {
+                    Completion::Break => break,
+                    Completion::Return(value) => return Ok(Completion::Return(value)),
+                    Completion::Normal | Completion::Continue => {}
+                }
+                if !self.eval(test, scope)?.truthy() {
+                    break;
+                }
+            },
+            Stmt::For(ref init, ref test, ref update, ref body) => {
+                return self.exec_for(init, test, update, body, scope);
+            }
+            Stmt::ForEach(ref for_each) => return self.exec_for_each(for_each, scope),
+            Stmt::Switch(ref discriminant, ref cases) => {
+                let value = self.eval(discriminant, scope)?;
+                let mut start = None;
+                for (i, &(ref test, _)) in cases.iter().enumerate() {
+                    if let Some(ref test) = *test {
+                        if strict_equals(&value, &self.eval(test, scope)?) {
+                            start = Some(i);
+                            break;
+                        }
+                    }
+                }
+                let start = start.or_else(|| cases.iter().position(|case| case.0.is_none()));
+                let block_scope = Scope::new(Some(scope), false);
+                for &(_, ref body) in cases.iter().skip(start.unwrap_or(cases.len())) {
+                    match self.exec_block(body, &block_scope)? {
+                        Completion::Normal => {}
+                        Completion::Break => break,
+                        completion => return Ok(completion),
+                    }
+                }
+            }
+            Stmt::Break => return Ok(Completion::Break),
+            Stmt::Continue => return Ok(Completion::Continue),
+            Stmt::Throw(ref value) => return Err(self.eval(value, scope)?),
+            Stmt::Try(ref block, ref catch, ref finally) => {
+                let result = match (self.exec_scoped(block, scope), catch) {
+                    (Err(exception), &Some((ref name, ref body))) if !self.stopped => {
+                        let catch_scope = Scope::new(Some(scope), false);
+                        if let Some(ref name) = *name {
+                            catch_scope.borrow_mut().declare(name, exception, true);
+                        }
+                        self.exec_block(body, &catch_scope)
+                    }
+                    (result, _) => result,
+                };
+                if let Some(ref finally) = *finally {
+                    if self.stopped {
+                        return result;
+                    }
+                    match self.exec_scoped(finally, scope)? {
+                        Completion::Normal => {}
+                        completion => return Ok(completion),
+                    }
+                }
+                return result;
+            }
+            Stmt::Block(ref stmts) => return self.exec_scoped(stmts, scope),
+        }
+        Ok(Completion::Normal)
+    }
+
+    fn exec_for(
+        &mut self,
+        init: &Option<Box<Stmt>>,
+        test: &Option<Expr>,
+        update: &Option<Expr>,
+        body: &Stmt,
+        scope: &ScopeRef,
+    ) -> Result<Completion, Value> {
+        let loop_scope = Scope::new(Some(scope), false);
+        let mut names = vec![];
+        if let Some(ref init) = *init {
+            if let Stmt::Var(VarKind::Let, ref declarations) = **init {
+                names = declarations.iter().map(|d| d.0.clone()).collect();
+            }
+            self.exec(init, &loop_scope)?;
+        }
+        // Each iteration has its own copy of the ``let`` variables, for the closures in it.
+        let copy = |from: &ScopeRef| {
+            let iteration = Scope::new(Some(&loop_scope), false);
+            for name in &names {
+                let value = lookup(from, name).unwrap_or(Value::Undefined);
+                iteration.borrow_mut().declare(name, value, true);
+            }
+            iteration
+        };
+        let mut iteration = copy(&loop_scope);
+        loop {
+            if let Some(ref test) = *test {
+                if !self.eval(test, &iteration)?.truthy() {
+                    break;
+                }
+            }
+            match self.exec(body, &iteration)? {
+                Completion::Break => break,
+                Completion::Return(value) => return Ok(Completion::Return(value)),
+                Completion::Normal | Completion::Continue => {}
+            }
+            iteration = copy(&iteration);
+            if let Some(ref update) = *update {
+                self.eval(update, &iteration)?;
+            }
+        }
+        Ok(Completion::Normal)
+    }
+
+    fn exec_for_each(&mut self, for_each: &ForEach, scope: &ScopeRef) -> Result<Completion, Value> {
+        let iterable = self.eval(&for_each.iterable, scope)?;
+        let values = if for_each.of {
+            match iterable {
+                Value::Str(ref s) => s.chars().map(|c| Value::Str(c.to_string())).collect(),
+                _ => match array_elements(&iterable) {
+                    Some(elements) => elements,
+                    None => {
+                        return Err(error("TypeError", format!("{} is not iterable", iterable)));
+                    }
+                },
+            }
+        } else {
+            self.keys(&iterable)
+                .into_iter()
+                .map(Value::Str)
+                .collect()
+        };
+        for value in values {
+            let iteration = Scope::new(Some(scope), false);
+            match for_each.kind {
+                Some(VarKind::Var) => {
+                    function_scope(scope)
+                        .borrow_mut()
+                        .declare(&for_each.name, value, true);
+                }
+                Some(kind) => {
+                    iteration
+                        .borrow_mut()
+                        .declare(&for_each.name, value, kind == VarKind::Let);
+                }
+                None => self.put(&Reference::Var(for_each.name.clone()), value, scope)?,
+            }
+            match self.exec(&for_each.body, &iteration)? {
+                Completion::Break => break,
+                Completion::Return(value) => return Ok(Completion::Return(value)),
+                Completion::Normal | Completion::Continue => {}
+            }
+        }
+        Ok(Completion::Normal)
+    }
+
+    /// The keys of ``value`` for ``for in`` and ``Object.keys``
+    fn keys(&self, value: &Value) -> Vec<String> {
+        match *value {
+            Value::Str(ref s) => (0..s.chars().count()).map(|i| i.to_string()).collect(),
+            Value::Object(ref object) => {
+                let object = object.borrow();
+                let mut keys = match object.kind {
+                    ObjectKind::Array(ref elements) => {
+                        (0..elements.len()).map(|i| i.to_string()).collect()
+                    }
+                    _ => vec![],
+                };
+                keys.extend(object.properties.iter().map(|p| p.0.clone()));
+                keys
+            }
+            _ => vec![],
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr, scope: &ScopeRef) -> Result<Value, Value> {
+        Ok(match *expr {
+            Expr::Number(n) => Value::Number(n),
+            Expr::Str(ref s) => Value::Str(s.clone()),
+            Expr::Bool(b) => Value::Bool(b),
+            Expr::Null => Value::Null,
+            Expr::Template(ref strings, ref exprs) => {
+                let mut s = strings[0].clone();
+                for (expr, string) in exprs.iter().zip(&strings[1..]) {
+                    s += self.eval(expr, scope)?.to_string().as_str();
+                    s += string;
+                    if s.len() > MAX_LENGTH {
+                        return Err(invalid_string_length());
+                    }
+                }
+                Value::Str(s)
+            }
+            Expr::Ident(ref name) => match lookup(scope, name) {
+                Some(value) => value,
+                None => {
+                    return Err(error("ReferenceError", format!("{} is not defined", name)));
+                }
+            },
+            Expr::This => lookup(scope, "this").unwrap_or(Value::Undefined),
+            Expr::Array(ref exprs) => {
+                let mut elements = vec![];
+                for expr in exprs {
+                    elements.push(self.eval(expr, scope)?);
+                }
+                array(elements)
+            }
+            Expr::Object(ref properties) => {
+                let object = object(vec![]);
+                for &(ref key, ref value) in properties {
+                    let key = self.eval(key, scope)?.to_string();
+                    let value = self.eval(value, scope)?;
+                    self.set_property(&object, &key, value)?;
+                }
+                object
+            }
+            Expr::Function(ref def) => closure(def, scope),
+            Expr::Unary(UnaryOp::TypeOf, ref operand) => match **operand {
+                Expr::Ident(ref name) if lookup(scope, name).is_none() => {
+                    Value::Str("undefined".to_string())
+                }
+                _ => Value::Str(self.eval(operand, scope)?.type_of().to_string()),
+            },
+            Expr::Unary(UnaryOp::Delete, ref operand) => {
+                if let Reference::Property(Value::Object(object), key) =
+                    self.reference(operand, scope)?
+                {
+                    object.borrow_mut().properties.retain(|p| p.0 != key);
+                }
+                Value::Bool(true)
+            }
+            Expr::Unary(op, ref operand) => {
+                let value = self.eval(operand, scope)?;
+                match op {
+                    UnaryOp::Not => Value::Bool(!value.truthy()),
+                    UnaryOp::Neg => Value::Number(-value.to_number()),
+                    UnaryOp::Plus => Value::Number(value.to_number()),
+                    UnaryOp::BitNot => Value::Number(f64::from(!to_int32(value.to_number()))),
+                    _ => Value::Undefined,
+                }
+            }
+            Expr::Update(increment, prefix, ref target) => {
+                let reference = self.reference(target, scope)?;
+                let old = self.get(&reference, scope)?.to_number();
+                let new = if increment { old + 1.0 } else { old - 1.0 };
+                self.put(&reference, Value::Number(new), scope)?;
+                Value::Number(if prefix { new } else { old })
+            }
+            Expr::Binary(op, ref left, ref right) => {
+                let left = self.eval(left, scope)?;
+                let right = self.eval(right, scope)?;
+                self.binary(op, &left, &right)?
+            }
+            Expr::Logical(op, ref left, ref right) => {
+                let left = self.eval(left, scope)?;
+                let short_circuits = match op {
+                    LogicalOp::And => !left.truthy(),
+                    LogicalOp::Or => left.truthy(),
+                    LogicalOp::Nullish => !left.is_nullish(),
+                };
+                if short_circuits {
+                    left
+                } else {
+                    self.eval(right, scope)?
+                }
+            }
+            Expr::Conditional(ref test, ref consequent, ref alternate) => {
+                if self.eval(test, scope)?.truthy() {
+                    self.eval(consequent, scope)?
+                } else {
+                    self.eval(alternate, scope)?
+                }
+            }
+            Expr::Assign(op, ref target, ref value) => {
+                let reference = self.reference(target, scope)?;
+                let value = match op {
+                    Some(op) => {
+                        let old = self.get(&reference, scope)?;
+                        let value = self.eval(value, scope)?;
+                        self.binary(op, &old, &value)?
+                    }
+                    None => self.eval(value, scope)?,
+                };
+                self.put(&reference, value.clone(), scope)?;
+                value
+            }
+            Expr::Member(ref object, ref key) => {
+                let object = self.eval(object, scope)?;
+                let key = self.eval(key, scope)?.to_string();
+                self.get_property(&object, &key)?
+            }
+            Expr::Call(ref callee, ref args) => {
+                let (this, function) = match **callee {
+                    Expr::Member(ref object, ref key) => {
+                        let object = self.eval(object, scope)?;
+                        let key = self.eval(key, scope)?.to_string();
+                        let function = self.get_property(&object, &key)?;
+                        (object, function)
+                    }
+                    _ => (Value::Undefined, self.eval(callee, scope)?),
+                };
+                if !function.is_function() {
+                    return Err(error(
+                        "TypeError",
+                        format!("{} is not a function", describe(callee)),
+                    ));
+                }
+                let args = self.eval_args(args, scope)?;
+                self.call(&function, this, args)?
+            }
+            Expr::New(ref callee, ref args) => {
+                let function = self.eval(callee, scope)?;
+                let args = self.eval_args(args, scope)?;
+                self.construct(&function, args, callee)?
+            }
+            Expr::Sequence(ref exprs) => {
+                let mut value = Value::Undefined;
+                for expr in exprs {
+                    value = self.eval(expr, scope)?;
+                }
+                value
+            }
+        })
+    }
+
+    fn eval_args(&mut self, args: &[Expr], scope: &ScopeRef) -> Result<Vec<Value>, Value> {
+        let mut values = vec![];
+        for arg in args {
+            values.push(self.eval(arg, scope)?);
+        }
+        Ok(values)
+    }
+
+    fn reference(&mut self, expr: &Expr, scope: &ScopeRef) -> Result<Reference, Value> {
+        match *expr {
+            Expr::Ident(ref name) => Ok(Reference::Var(name.clone())),
+            Expr::Member(ref object, ref key) => {
+                let object = self.eval(object, scope)?;
+                let key = self.eval(key, scope)?.to_string();
+                Ok(Reference::Property(object, key))
+            }
+            _ => Err(error("SyntaxError", "invalid assignment target")),
+        }
+    }
+
+    fn get(&mut self, reference: &Reference, scope: &ScopeRef) -> Result<Value, Value> {
+        match *reference {
+            Reference::Var(ref name) => lookup(scope, name)
+                .ok_or_else(|| error("ReferenceError", format!("{} is not defined", name))),
+            Reference::Property(ref object, ref key) => self.get_property(object, key),
+        }
+    }
+
+    /// Assigns ``value`` to ``reference``: to a global variable if no variable of its name is
+    /// declared.
+    fn put(&mut self, reference: &Reference, value: Value, scope: &ScopeRef) -> Result<(), Value> {
+        match *reference {
+            Reference::Var(ref name) => {
+                if !assign(scope, name, value.clone())? {
+                    self.define_global(name, value);
+                }
+                Ok(())
+            }
+            Reference::Property(ref object, ref key) => self.set_property(object, key, value),
+        }
+    }
+
+    /// The property ``key`` of ``value``.
+    pub fn get_property(&mut self, value: &Value, key: &str) -> Result<Value, Value> {
+        let object = match *value {
+            Value::Undefined | Value::Null => {
+                return Err(error(
+                    "TypeError",
+                    format!("Cannot read properties of {} (reading '{}')", value, key),
+                ));
+            }
+            Value::Bool(_) => return Ok(Value::Undefined),
+            Value::Number(_) => {
+                return Ok(match key {
+                    "toFixed" => builtin("Number.prototype", "toFixed"),
+                    "toString" => builtin("Number.prototype", "toString"),
+                    _ => Value::Undefined,
+                })
+            }
+            Value::Str(ref s) => {
+                if key == "length" {
+                    return Ok(Value::Number(s.chars().count() as f64));
+                }
+                if let Some(i) = array_index(key) {
+                    return Ok(s.chars()
+                        .nth(i)
+                        .map(|c| Value::Str(c.to_string()))
+                        .unwrap_or(Value::Undefined));
+                }
+                return Ok(match STRING_METHODS.iter().find(|&&name| name == key) {
+                    Some(&name) => builtin("String.prototype", name),
+                    None => Value::Undefined,
+                });
+            }
+            Value::Object(ref object) => object,
+        };
+
+        let host_object = match object.borrow().kind {
+            ObjectKind::Host(ref host_object) => Some(host_object.clone()),
+            _ => None,
+        };
+        if let Some(host_object) = host_object {
+            return H::get(self, &host_object, key);
+        }
+
+        let object = object.borrow();
+        if let Some(&(_, ref value)) = object.properties.iter().find(|p| p.0 == key) {
+            return Ok(value.clone());
+        }
+        Ok(match object.kind {
+            ObjectKind::Array(ref elements) => match key {
+                "length" => Value::Number(elements.len() as f64),
+                _ => match array_index(key) {
+                    Some(i) => elements.get(i).cloned().unwrap_or(Value::Undefined),
+                    None => match ARRAY_METHODS.iter().find(|&&name| name == key) {
+                        Some(&name) => builtin("Array.prototype", name),
+                        None => Value::Undefined,
+                    },
+                },
+            },
+            ObjectKind::Function(_) => match key {
+                "call" => builtin("Function.prototype", "call"),
+                "apply" => builtin("Function.prototype", "apply"),
+                _ => Value::Undefined,
+            },
+            ObjectKind::Error if key == "toString" => builtin("Error.prototype", "toString"),
+            _ => Value::Undefined,
+        })
+    }
+
+    /// Sets the property ``key`` of ``value``.
+    pub fn set_property(&mut self, value: &Value, key: &str, new: Value) -> Result<(), Value> {
+        let object = match *value {
+            Value::Undefined | Value::Null => {
+                return Err(error(
+                    "TypeError",
+                    format!("Cannot set properties of {} (setting '{}')", value, key),
+                ));
+            }
+            Value::Object(ref object) => object,
+            _ => return Ok(()),
+        };
+
+        let host_object = match object.borrow().kind {
+            ObjectKind::Host(ref host_object) => Some(host_object.clone()),
+            _ => None,
+        };
+        if let Some(host_object) = host_object {
+            return H::set(self, &host_object, key, new);
+        }
+
+        let mut object = object.borrow_mut();
+        if let ObjectKind::Array(ref mut elements) = object.kind {
+            if key == "length" {
+                let len = new.to_number();
+                if len < 0.0 || len.fract() != 0.0 || len > MAX_LENGTH as f64 {
+                    return Err(invalid_array_length());
+                }
+                elements.resize(len as usize, Value::Undefined);
+                return Ok(());
+            }
+            if let Some(i) = array_index(key) {
+                if i >= MAX_LENGTH {
+                    return Err(invalid_array_length());
+                }
+                if i >= elements.len() {
+                    elements.resize(i + 1, Value::Undefined);
+                }
+                elements[i] = new;
+                return Ok(());
+            }
+        }
+        match object.properties.iter().position(|p| p.0 == key) {
+            Some(i) => object.properties[i].1 = new,
+            None => object.properties.push((key.to_string(), new)),
+        }
+        Ok(())
+    }
+
+    /// Calls ``function`` with ``this`` and ``args``.
+    pub fn call(&mut self, function: &Value, this: Value, args: Vec<Value>) -> Result<Value, Value> {
+        let function = match *function {
+            Value::Object(ref object) => match object.borrow().kind {
+                ObjectKind::Function(ref function) => function.clone(),
+                _ => return Err(error("TypeError", "not a function")),
+            },
+            _ => return Err(error("TypeError", format!("{} is not a function", function))),
+        };
+        if self.depth >= MAX_DEPTH {
+            self.stopped = true;
+            return Err(error("RangeError", "Maximum call stack size exceeded"));
+        }
+        self.step()?;
+        self.depth += 1;
+        let result = match function {
+            Function::Closure(ref def, ref scope) => self.call_closure(def, scope, this, args),
+            Function::Builtin(owner, name) => self.call_builtin(owner, name, this, args),
+            Function::Host(ref object, name) => H::call(self, object, name, args),
+        };
+        self.depth -= 1;
+        result
+    }
+
+    fn call_closure(
+        &mut self,
+        def: &FunctionDef,
+        scope: &ScopeRef,
+        this: Value,
+        args: Vec<Value>,
+    ) -> Result<Value, Value> {
+        let function_scope = Scope::new(Some(scope), true);
+        if !def.is_arrow {
+            function_scope.borrow_mut().declare("this", this, false);
+        }
+        let mut args = args.into_iter();
+        for &(ref name, ref default) in &def.params {
+            let value = match (args.next(), default) {
+                (Some(Value::Undefined), &Some(ref default)) | (None, &Some(ref default)) => {
+                    self.eval(default, &function_scope)?
+                }
+                (Some(value), _) => value,
+                (None, &None) => Value::Undefined,
+            };
+            function_scope.borrow_mut().declare(name, value, true);
+        }
+        declare_vars(&def.body, &function_scope);
+        match self.exec_block(&def.body, &function_scope)? {
+            Completion::Return(value) => Ok(value),
+            _ => Ok(Value::Undefined),
+        }
+    }
+
+    /// ``new function(args)``
+    fn construct(&mut self, function: &Value, args: Vec<Value>, callee: &Expr) -> Result<Value, Value> {
+        let constructor = match *function {
+            Value::Object(ref object) => match object.borrow().kind {
+                ObjectKind::Function(ref function) => Some(function.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+        match constructor {
+            Some(Function::Builtin("global", _)) => self.call(function, Value::Undefined, args),
+            Some(Function::Closure(ref def, _)) if !def.is_arrow => {
+                let this = object(vec![]);
+                if let Value::Object(ref object) = this {
+                    object.borrow_mut().constructor = Some(function.clone());
+                }
+                match self.call(function, this.clone(), args)? {
+                    result @ Value::Object(_) => Ok(result),
+                    _ => Ok(this),
+                }
+            }
+            _ => Err(error(
+                "TypeError",
+                format!("{} is not a constructor", describe(callee)),
+            )),
+        }
+    }
+
+    fn binary(&mut self, op: BinaryOp, left: &Value, right: &Value) -> Result<Value, Value> {
+        let number = |f: fn(f64, f64) -> f64| Value::Number(f(left.to_number(), right.to_number()));
+        let int32 = |f: fn(i32, i32) -> i32| {
+            Value::Number(f64::from(f(
+                to_int32(left.to_number()),
+                to_int32(right.to_number()),
+            )))
+        };
+        Ok(match op {
+            BinaryOp::Add => match (left.to_primitive(), right.to_primitive()) {
+                (left @ Value::Str(_), right) | (left, right @ Value::Str(_)) => {
+                    let (left, right) = (left.to_string(), right.to_string());
+                    if left.len() + right.len() > MAX_LENGTH {
+                        return Err(invalid_string_length());
+                    }
+                    Value::Str(left + right.as_str())
+                }
+                (left, right) => Value::Number(left.to_number() + right.to_number()),
+            },
+            BinaryOp::Sub => number(|a, b| a - b),
+            BinaryOp::Mul => number(|a, b| a * b),
+            BinaryOp::Div => number(|a, b| a / b),
+            BinaryOp::Rem => number(|a, b| a % b),
+            BinaryOp::Exp => number(f64::powf),
+            BinaryOp::Eq => Value::Bool(loose_equals(left, right)),
+            BinaryOp::NotEq => Value::Bool(!loose_equals(left, right)),
+            BinaryOp::StrictEq => Value::Bool(strict_equals(left, right)),
+            BinaryOp::StrictNotEq => Value::Bool(!strict_equals(left, right)),
+            BinaryOp::Lt => Value::Bool(less_than(left, right) == Some(true)),
+            BinaryOp::Gt => Value::Bool(less_than(right, left) == Some(true)),
+            BinaryOp::LtEq => Value::Bool(less_than(right, left) == Some(false)),
+            BinaryOp::GtEq => Value::Bool(less_than(left, right) == Some(false)),
+            BinaryOp::BitAnd => int32(|a, b| a & b),
+            BinaryOp::BitOr => int32(|a, b| a | b),
+            BinaryOp::BitXor => int32(|a, b| a ^ b),
+            BinaryOp::Shl => int32(|a, b| a.wrapping_shl(b as u32 & 31)),
+            BinaryOp::Shr => int32(|a, b| a >> (b as u32 & 31)),
+            BinaryOp::UShr => {
+                let shift = to_int32(right.to_number()) as u32 & 31;
+                Value::Number(f64::from(to_int32(left.to_number()) as u32 >> shift))
+            }
+            BinaryOp::InstanceOf => Value::Bool(instance_of(left, right)),
+            BinaryOp::In => {
+                if let Value::Object(_) = *right {
+                    let key = left.to_string();
+                    let has_own = self.keys(right).contains(&key);
+                    Value::Bool(has_own || !self.get_property(right, &key)?.is_undefined())
+                } else {
+                    return Err(error("TypeError", "Cannot use 'in' operator on a primitive"));
+                }
+            }
+        })
+    }
+
+    fn call_builtin(
+        &mut self,
+        owner: &'static str,
+        name: &'static str,
+        this: Value,
+        args: Vec<Value>,
+    ) -> Result<Value, Value> {
+        let arg = |i: usize| args.get(i).cloned().unwrap_or(Value::Undefined);
+        Ok(match (owner, name) {
+            ("console", _) => {
+                let line: Vec<String> = args.iter().map(inspect).collect();
+                self.console.push(line.join(" "));
+                Value::Undefined
+            }
+            ("Math", _) => Value::Number(math(name, &args)),
+            ("JSON", "stringify") => match stringify(&arg(0), 0)? {
+                Some(json) => Value::Str(json),
+                None => Value::Undefined,
+            },
+            ("Date", "now") => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+                Value::Number((now.as_secs() * 1000 + u64::from(now.subsec_millis())) as f64)
+            }
+            ("global", "parseInt") => Value::Number(parse_int(&arg(0).to_string(), &arg(1))),
+            ("global", "parseFloat") => Value::Number(parse_float(&arg(0).to_string())),
+            ("global", "isNaN") => Value::Bool(arg(0).to_number().is_nan()),
+            ("global", "isFinite") => Value::Bool(arg(0).to_number().is_finite()),
+            ("global", "String") => match args.first() {
+                Some(value) => Value::Str(value.to_string()),
+                None => Value::Str(String::new()),
+            },
+            ("global", "Number") => Value::Number(args.first().map_or(0.0, Value::to_number)),
+            ("global", "Boolean") => Value::Bool(arg(0).truthy()),
+            ("global", "Object") => match arg(0) {
+                value @ Value::Object(_) => value,
+                _ => object(vec![]),
+            },
+            ("global", "Array") => match *args.as_slice() {
+                [Value::Number(len)] => {
+                    if len < 0.0 || len.fract() != 0.0 || len > MAX_LENGTH as f64 {
+                        return Err(invalid_array_length());
+                    }
+                    array(vec![Value::Undefined; len as usize])
+                }
+                _ => array(args.clone()),
+            },
+            ("global", _) => {
+                let message = match arg(0) {
+                    Value::Undefined => String::new(),
+                    message => message.to_string(),
+                };
+                error(name, message)
+            }
+            ("Object", "keys") => array(self.keys(&arg(0)).into_iter().map(Value::Str).collect()),
+            ("Object", "values") | ("Object", "entries") => {
+                let object = arg(0);
+                let mut values = vec![];
+                for key in self.keys(&object) {
+                    let value = self.get_property(&object, &key)?;
+                    values.push(if name == "values" {
+                        value
+                    } else {
+                        array(vec![Value::Str(key), value])
+                    });
+                }
+                array(values)
+            }
+            ("Object", "assign") => {
+                let target = arg(0);
+                for source in args.iter().skip(1) {
+                    for key in self.keys(source) {
+                        let value = self.get_property(source, &key)?;
+                        self.set_property(&target, &key, value)?;
+                    }
+                }
+                target
+            }
+            ("Array", "isArray") => Value::Bool(array_elements(&arg(0)).is_some()),
+            ("Number.prototype", "toFixed") => {
+                let digits = arg(0).to_number();
+                if !(0.0..=100.0).contains(&digits) {
+                    return Err(error("RangeError", "toFixed() digits out of range"));
+                }
+                let n = this.to_number();
+                if n.is_finite() {
+                    Value::Str(format!("{:.*}", digits as usize, n))
+                } else {
+                    Value::Str(this.to_string())
+                }
+            }
+            ("Number.prototype", _) => Value::Str(this.to_string()),
+            ("String.prototype", _) => self.call_string_method(name, &this.to_string(), &args)?,
+            ("Array.prototype", _) => self.call_array_method(name, &this, &args)?,
+            ("Error.prototype", _) => Value::Str(this.to_string()),
+            ("Function.prototype", "call") => {
+                self.call(&this, arg(0), args.iter().skip(1).cloned().collect())?
+            }
+            ("Function.prototype", _) => {
+                let args = array_elements(&arg(1)).unwrap_or_default();
+                self.call(&this, arg(0), args)?
+            }
+            _ => Value::Undefined,
+        })
+    }
+
+    fn call_string_method(
+        &mut self,
+        name: &str,
+        s: &str,
+        args: &[Value],
+    ) -> Result<Value, Value> {
+        let arg = |i: usize| args.get(i).cloned().unwrap_or(Value::Undefined);
+        let chars: Vec<char> = s.chars().collect();
+        let len = chars.len();
+        let substring = |start: usize, end: usize| -> Value {
+            Value::Str(chars[start.min(end)..end].iter().collect())
+        };
+        // An index of substring(): clamped to the string
+        let clamp = |value: &Value, default: usize| match *value {
+            Value::Undefined => default,
+            ref value => {
+                let i = value.to_number();
+                if i.is_nan() || i < 0.0 {
+                    0
+                } else {
+                    (i as usize).min(len)
+                }
+            }
+        };
+        Ok(match name {
+            "charAt" => match chars.get(clamp(&arg(0), 0)) {
+                Some(c) => Value::Str(c.to_string()),
+                None => Value::Str(String::new()),
+            },
+            "charCodeAt" => match chars.get(clamp(&arg(0), 0)) {
+                Some(&c) => Value::Number(f64::from(c as u32)),
+                None => Value::Number(f64::NAN),
+            },
+            "indexOf" | "lastIndexOf" | "includes" => {
+                let needle: Vec<char> = arg(0).to_string().chars().collect();
+                let mut starts = (0..len + 1).filter(|&i| chars[i..].starts_with(&needle));
+                let i = if name == "lastIndexOf" {
+                    starts.next_back()
+                } else {
+                    let from = clamp(&arg(1), 0);
+                    starts.find(|&i| i >= from)
+                };
+                if name == "includes" {
+                    Value::Bool(i.is_some())
+                } else {
+                    Value::Number(i.map_or(-1.0, |i| i as f64))
+                }
+            }
+            "startsWith" => Value::Bool(s.starts_with(arg(0).to_string().as_str())),
+            "endsWith" => Value::Bool(s.ends_with(arg(0).to_string().as_str())),
+            "slice" => {
+                let start = relative_index(&arg(0), 0, len);
+                let end = relative_index(&arg(1), len, len);
+                substring(start, end.max(start))
+            }
+            "substring" => {
+                let (a, b) = (clamp(&arg(0), 0), clamp(&arg(1), len));
+                substring(a.min(b), a.max(b))
+            }
+            "substr" => {
+                let start = relative_index(&arg(0), 0, len);
+                let count = clamp(&arg(1), len - start);
+                substring(start, (start + count).min(len))
+            }
+            "toUpperCase" => Value::Str(s.to_uppercase()),
+            "toLowerCase" => Value::Str(s.to_lowercase()),
+            "trim" => Value::Str(s.trim().to_string()),
+            "trimStart" => Value::Str(s.trim_start().to_string()),
+            "trimEnd" => Value::Str(s.trim_end().to_string()),
+            "split" => {
+                let parts: Vec<Value> = match arg(0) {
+                    Value::Undefined => vec![Value::Str(s.to_string())],
+                    separator => match separator.to_string().as_str() {
+                        "" => chars.iter().map(|c| Value::Str(c.to_string())).collect(),
+                        separator => s.split(separator).map(|p| Value::Str(p.to_string())).collect(),
+                    },
+                };
+                let limit = match arg(1) {
+                    Value::Undefined => parts.len(),
+                    limit => limit.to_number() as usize,
+                };
+                array(parts.into_iter().take(limit).collect())
+            }
+            "replace" | "replaceAll" => {
+                let pattern = arg(0).to_string();
+                let replacement = arg(1);
+                let mut result = String::new();
+                let mut rest = s;
+                while let Some(i) = rest.find(pattern.as_str()) {
+                    result += &rest[..i];
+                    if replacement.is_function() {
+                        let args = vec![Value::Str(pattern.clone())];
+                        result += self.call(&replacement, Value::Undefined, args)?
+                            .to_string()
+                            .as_str();
+                    } else {
+                        result += replacement.to_string().as_str();
+                    }
+                    if result.len() > MAX_LENGTH {
+                        return Err(invalid_string_length());
+                    }
+                    rest = &rest[i + pattern.len()..];
+                    if name == "replace" || pattern.is_empty() {
+                        break;
+                    }
+                }
+                Value::Str(result + rest)
+            }
+            "repeat" => {
+                let count = arg(0).to_number();
+                if count < 0.0 || count.is_infinite() {
+                    return Err(error("RangeError", "Invalid count value"));
+                }
+                if count.trunc() * s.len() as f64 > MAX_LENGTH as f64 {
+                    return Err(invalid_string_length());
+                }
+                Value::Str(s.repeat(count as usize))
+            }
+            "padStart" | "padEnd" => {
+                let target = arg(0).to_number();
+                if target > MAX_LENGTH as f64 {
+                    return Err(invalid_string_length());
+                }
+                let target = target as usize;
+                let pad: Vec<char> = match arg(1) {
+                    Value::Undefined => vec![' '],
+                    pad => pad.to_string().chars().collect(),
+                };
+                if target <= len || pad.is_empty() {
+                    Value::Str(s.to_string())
+                } else {
+                    let padding: String = pad.iter().cycle().take(target - len).collect();
+                    Value::Str(if name == "padStart" {
+                        padding + s
+                    } else {
+                        s.to_string() + padding.as_str()
+                    })
+                }
+            }
+            "concat" => {
+                let mut result = s.to_string();
+                for arg in args {
+                    result += arg.to_string().as_str();
+                    if result.len() > MAX_LENGTH {
+                        return Err(invalid_string_length());
+                    }
+                }
+                Value::Str(result)
+            }
+            _ => Value::Str(s.to_string()),
+        })
+    }
+
+    fn call_array_method(
+        &mut self,
+        name: &str,
+        this: &Value,
+        args: &[Value],
+    ) -> Result<Value, Value> {
+        let arg = |i: usize| args.get(i).cloned().unwrap_or(Value::Undefined);
+        let object = match *this {
+            Value::Object(ref object) => object.clone(),
+            _ => return Err(error("TypeError", "not an array")),
+        };
+        {
+            let mut object = object.borrow_mut();
+            let elements = match object.kind {
+                ObjectKind::Array(ref mut elements) => elements,
+                _ => return Err(error("TypeError", "not an array")),
+            };
+            let len = elements.len();
+            match name {
+                "push" | "unshift" if len + args.len() > MAX_LENGTH => {
+                    return Err(invalid_array_length());
+                }
+                "push" => {
+                    elements.extend(args.iter().cloned());
+                    return Ok(Value::Number(elements.len() as f64));
+                }
+                "pop" => return Ok(elements.pop().unwrap_or(Value::Undefined)),
+                "shift" if elements.is_empty() => return Ok(Value::Undefined),
+                "shift" => return Ok(elements.remove(0)),
+                "unshift" => {
+                    elements.splice(0..0, args.iter().cloned());
+                    return Ok(Value::Number(elements.len() as f64));
+                }
+                "reverse" => {
+                    elements.reverse();
+                    return Ok(this.clone());
+                }
+                "fill" => {
+                    let start = relative_index(&arg(1), 0, len);
+                    let end = relative_index(&arg(2), len, len);
+                    for element in elements.iter_mut().take(end).skip(start) {
+                        *element = arg(0);
+                    }
+                    return Ok(this.clone());
+                }
+                "splice" => {
+                    let start = relative_index(&arg(0), 0, len);
+                    let count = match arg(1) {
+                        Value::Undefined if args.len() < 2 => len - start,
+                        count => (count.to_number().max(0.0) as usize).min(len - start),
+                    };
+                    let removed = elements
+                        .splice(start..start + count, args.iter().skip(2).cloned())
+                        .collect();
+                    return Ok(array(removed));
+                }
+                _ => {}
+            }
+        }
+
+        let elements = array_elements(this).unwrap();
+        let len = elements.len();
+        let callback = arg(0);
+        let needs_callback = ["map", "forEach", "filter", "reduce", "some", "every", "find"];
+        if (needs_callback.contains(&name) || name == "findIndex") && !callback.is_function() {
+            return Err(error("TypeError", format!("{} is not a function", callback)));
+        }
+        // Calls the callback with an element, its index and the array.
+        let call = |js: &mut Interpreter<H>, i: usize| {
+            let args = vec![elements[i].clone(), Value::Number(i as f64), this.clone()];
+            js.call(&callback, Value::Undefined, args)
+        };
+        Ok(match name {
+            "slice" => {
+                let start = relative_index(&arg(0), 0, len);
+                let end = relative_index(&arg(1), len, len);
+                array(elements[start.min(end)..end].to_vec())
+            }
+            "concat" => {
+                let mut result = elements.clone();
+                for arg in args {
+                    match array_elements(arg) {
+                        Some(elements) => result.extend(elements),
+                        None => result.push(arg.clone()),
+                    }
+                    if result.len() > MAX_LENGTH {
+                        return Err(invalid_array_length());
+                    }
+                }
+                array(result)
+            }
+            "join" | "toString" => {
+                let separator = match arg(0) {
+                    Value::Undefined => ",".to_string(),
+                    ref separator if name == "join" => separator.to_string(),
+                    _ => ",".to_string(),
+                };
+                Value::Str(join(&elements, &separator, 0).ok_or_else(invalid_string_length)?)
+            }
+            "indexOf" | "lastIndexOf" | "includes" => {
+                let mut found = elements.iter().enumerate().filter(|e| strict_equals(e.1, &arg(0)));
+                let i = if name == "lastIndexOf" {
+                    found.next_back().map(|e| e.0)
+                } else {
+                    found.next().map(|e| e.0)
+                };
+                if name == "includes" {
+                    Value::Bool(i.is_some())
+                } else {
+                    Value::Number(i.map_or(-1.0, |i| i as f64))
+                }
+            }
+            "map" => {
+                let mut result = vec![];
+                for i in 0..len {
+                    result.push(call(self, i)?);
+                }
+                array(result)
+            }
+            "forEach" => {
+                for i in 0..len {
+                    call(self, i)?;
+                }
+                Value::Undefined
+            }
+            "filter" => {
+                let mut result = vec![];
+                for (i, element) in elements.iter().enumerate() {
+                    if call(self, i)?.truthy() {
+                        result.push(element.clone());
+                    }
+                }
+                array(result)
+            }
+            "some" | "every" | "find" | "findIndex" => {
+                let mut found = None;
+                for i in 0..len {
+                    if call(self, i)?.truthy() == (name != "every") {
+                        found = Some(i);
+                        break;
+                    }
+                }
+                match (name, found) {
+                    ("some", found) => Value::Bool(found.is_some()),
+                    ("every", found) => Value::Bool(found.is_none()),
+                    ("find", Some(i)) => elements[i].clone(),
+                    ("find", None) => Value::Undefined,
+                    (_, found) => Value::Number(found.map_or(-1.0, |i| i as f64)),
+                }
+            }
+            "reduce" => {
+                let (mut accumulator, start) = match args.get(1) {
+                    Some(initial) => (initial.clone(), 0),
+                    None if len == 0 => {
+                        return Err(error("TypeError", "Reduce of empty array with no initial value"));
+                    }
+                    None => (elements[0].clone(), 1),
+                };
+                for (i, element) in elements.iter().enumerate().skip(start) {
+                    let args = vec![accumulator, element.clone(), Value::Number(i as f64), this.clone()];
+                    accumulator = self.call(&callback, Value::Undefined, args)?;
+                }
+                accumulator
+            }
+            "sort" => {
+                let sorted = self.sort(elements, &callback)?;
+                if let ObjectKind::Array(ref mut elements) = object.borrow_mut().kind {
+                    *elements = sorted;
+                }
+                this.clone()
+            }
+            _ => Value::Undefined,
+        })
+    }
+
+    /// A stable merge sort of ``values`` by ``compare`` (or by their strings if it is undefined),
+    /// which may throw or be inconsistent.
+    fn sort(&mut self, mut values: Vec<Value>, compare: &Value) -> Result<Vec<Value>, Value> {
+        if values.len() <= 1 {
+            return Ok(values);
+        }
+        let right = values.split_off(values.len() / 2);
+        let left = self.sort(values, compare)?;
+        let right = self.sort(right, compare)?;
+        let mut sorted = Vec::with_capacity(left.len() + right.len());
+        let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+        while let (Some(a), Some(b)) = (left.peek().cloned(), right.peek().cloned()) {
+            let order = match *compare {
+                Value::Undefined => match (a.to_string(), b.to_string()) {
+                    (_, _) if b.is_undefined() => -1.0,
+                    (_, _) if a.is_undefined() => 1.0,
+                    (a, b) if b < a => 1.0,
+                    _ => -1.0,
+                },
+                _ => self.call(compare, Value::Undefined, vec![a, b])?.to_number(),
+            };
+            if order > 0.0 {
+                sorted.push(right.next().unwrap());
+            } else {
+                sorted.push(left.next().unwrap());
+            }
+        }
+        sorted.extend(left);
+        sorted.extend(right);
+        Ok(sorted)
+    }
+}
+
+fn closure(def: &Rc<FunctionDef>, scope: &ScopeRef) -> Value {
+    new_object(ObjectKind::Function(Function::Closure(def.clone(), scope.clone())))
+}
+
+fn new_object(kind: ObjectKind) -> Value {
+    Value::Object(Rc::new(RefCell::new(Object {
+        kind: kind,
+        properties: vec![],
+        constructor: None,
+    })))
+}
+
+/// An ordinary object with ``properties``
+pub fn object(properties: Vec<(String, Value)>) -> Value {
+    let object = new_object(ObjectKind::Ordinary);
+    if let Value::Object(ref o) = object {
+        o.borrow_mut().properties = properties;
+    }
+    object
+}
+
+pub fn array(elements: Vec<Value>) -> Value {
+    new_object(ObjectKind::Array(elements))
+}
+
+pub fn builtin(object: &'static str, name: &'static str) -> Value {
+    new_object(ObjectKind::Function(Function::Builtin(object, name)))
+}
+
+pub fn host_object(class: &'static str, key: Vec<usize>) -> Value {
+    new_object(ObjectKind::Host(HostObject {
+        class: class,
+        key: key,
+    }))
+}
+
+/// The method ``name`` of ``object``
+pub fn host_method(object: &HostObject, name: &'static str) -> Value {
+    new_object(ObjectKind::Function(Function::Host(object.clone(), name)))
+}
+
+/// The error thrown for an array longer than ``MAX_LENGTH``
+fn invalid_array_length() -> Value {
+    error("RangeError", "Invalid array length")
+}
+
+/// The error thrown for a string longer than ``MAX_LENGTH``
+fn invalid_string_length() -> Value {
+    error("RangeError", "Invalid string length")
+}
+
+/// An error to throw, e.g. ``error("TypeError", "a is not a function")``
+pub fn error<S: Into<String>>(name: &str, message: S) -> Value {
+    let error = new_object(ObjectKind::Error);
+    if let Value::Object(ref object) = error {
+        object.borrow_mut().properties = vec![
+            ("name".to_string(), Value::Str(name.to_string())),
+            ("message".to_string(), Value::Str(message.into())),
+        ];
+    }
+    error
+}
+
+/// A copy of the elements of ``value`` if it is an array
+pub fn array_elements(value: &Value) -> Option<Vec<Value>> {
+    match *value {
+        Value::Object(ref object) => match object.borrow().kind {
+            ObjectKind::Array(ref elements) => Some(elements.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn array_index(key: &str) -> Option<usize> {
+    if key.is_empty() || (key.len() > 1 && key.starts_with('0')) {
+        return None;
+    }
+    key.parse().ok()
+}
+
+/// An index that counts from the end if it is negative, clamped to ``0..=len``
+fn relative_index(value: &Value, default: usize, len: usize) -> usize {
+    if value.is_undefined() {
+        return default;
+    }
+    let i = value.to_number();
+    if i.is_nan() {
+        0
+    } else if i < 0.0 {
+        (len as f64 + i.ceil()).max(0.0) as usize
+    } else {
+        (i as usize).min(len)
+    }
+}
+
+fn to_int32(n: f64) -> i32 {
+    if n.is_finite() {
+        n.trunc().rem_euclid(4_294_967_296.0) as u32 as i32
+    } else {
+        0
+    }
+}
+
+/// The source of a callee, for errors
+fn describe(expr: &Expr) -> String {
+    match *expr {
+        Expr::Ident(ref name) => name.clone(),
+        Expr::This => "this".to_string(),
+        Expr::Member(ref object, ref key) => match **key {
+            Expr::Str(ref key) => format!("{}.{}", describe(object), key),
+            _ => format!("{}[...]", describe(object)),
+        },
+        Expr::Call(ref callee, _) => format!("{}(...)", describe(callee)),
+        _ => "expression".to_string(),
+    }
+}
+
+fn math(name: &str, args: &[Value]) -> f64 {
+    let numbers: Vec<f64> = args.iter().map(Value::to_number).collect();
+    let x = numbers.first().cloned().unwrap_or(f64::NAN);
+    let y = numbers.get(1).cloned().unwrap_or(f64::NAN);
+    match name {
+        "abs" => x.abs(),
+        "floor" => x.floor(),
+        "ceil" => x.ceil(),
+        // Halves are rounded up, to +infinity.
+        "round" => (x + 0.5).floor(),
+        "trunc" => x.trunc(),
+        "sign" if x == 0.0 || x.is_nan() => x,
+        "sign" => x.signum(),
+        "sqrt" => x.sqrt(),
+        "cbrt" => x.cbrt(),
+        "pow" => x.powf(y),
+        "min" => numbers.iter().fold(f64::INFINITY, |a, &b| {
+            if a.is_nan() || b.is_nan() {
+                f64::NAN
+            } else {
+                a.min(b)
+            }
+        }),
+        "max" => numbers.iter().fold(f64::NEG_INFINITY, |a, &b| {
+            if a.is_nan() || b.is_nan() {
+                f64::NAN
+            } else {
+                a.max(b)
+            }
+        }),
+        "random" => self::rand::random(),
+        "sin" => x.sin(),
+        "cos" => x.cos(),
+        "tan" => x.tan(),
+        "atan" => x.atan(),
+        "atan2" => x.atan2(y),
+        "log" => x.ln(),
+        "exp" => x.exp(),
+        _ => numbers.iter().map(|n| n * n).sum::<f64>().sqrt(),
+    }
+}
+
+fn parse_int(s: &str, radix: &Value) -> f64 {
+    let s = s.trim_start();
+    let (sign, s) = match s.chars().next() {
+        Some('-') => (-1.0, &s[1..]),
+        Some('+') => (1.0, &s[1..]),
+        _ => (1.0, s),
+    };
+    let (mut radix, may_be_hex) = match radix.to_number() {
+        r if r.is_nan() || r == 0.0 => (10, true),
+        r if (2.0..=36.0).contains(&r) => (r as u32, r as u32 == 16),
+        _ => return f64::NAN,
+    };
+    let mut s = s;
+    if may_be_hex {
+        if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            s = hex;
+            radix = 16;
+        }
+    }
+    let digits: Vec<u32> = s.chars().map_while(|c| c.to_digit(radix)).collect();
+    if digits.is_empty() {
+        return f64::NAN;
+    }
+    sign * digits.iter().fold(0.0, |n, &d| n * f64::from(radix) + f64::from(d))
+}
+
+fn parse_float(s: &str) -> f64 {
+    let s = s.trim_start();
+    for infinity in &["Infinity", "+Infinity"] {
+        if s.starts_with(infinity) {
+            return f64::INFINITY;
+        }
+    }
+    if s.starts_with("-Infinity") {
+        return f64::NEG_INFINITY;
+    }
+    let candidate: String = s.chars()
+        .take_while(|c| c.is_ascii_digit() || "+-.eE".contains(*c))
+        .collect();
+    (1..candidate.len() + 1)
+        .rev()
+        .filter_map(|len| candidate[..len].parse().ok())
+        .next()
+        .unwrap_or(f64::NAN)
+}
+
+/// The JSON of ``value``, or None for undefined and functions
+fn stringify(value: &Value, depth: usize) -> Result<Option<String>, Value> {
+    if depth > 32 {
+        return Ok(Some("null".to_string()));
+    }
+    let json = match *value {
+        Value::Undefined => return Ok(None),
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) if n.is_finite() => value.to_string(),
+        Value::Number(_) => "null".to_string(),
+        Value::Str(ref s) => quote(s),
+        Value::Object(ref object) => {
+            let object = object.borrow();
+            match object.kind {
+                ObjectKind::Function(_) => return Ok(None),
+                ObjectKind::Array(ref elements) => {
+                    let mut json = String::from("[");
+                    for (i, e) in elements.iter().enumerate() {
+                        if i > 0 {
+                            json.push(',');
+                        }
+                        match stringify(e, depth + 1)? {
+                            Some(element) => json += element.as_str(),
+                            None => json += "null",
+                        }
+                        if json.len() > MAX_LENGTH {
+                            return Err(invalid_string_length());
+                        }
+                    }
+                    json + "]"
+                }
+                _ => {
+                    let mut json = String::from("{");
+                    for &(ref key, ref value) in &object.properties {
+                        if let Some(value) = stringify(value, depth + 1)? {
+                            if json.len() > 1 {
+                                json.push(',');
+                            }
+                            json += format!("{}:{}", quote(key), value).as_str();
+                            if json.len() > MAX_LENGTH {
+                                return Err(invalid_string_length());
+                            }
+                        }
+                    }
+                    json + "}"
+                }
+            }
+        }
+    };
+    Ok(Some(json))
+}
+
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            '\n' => quoted += "\\n",
+            '\r' => quoted += "\\r",
+            '\t' => quoted += "\\t",
+            c if (c as u32) < 0x20 => quoted += format!("\\u{:04x}", c as u32).as_str(),
+            c => quoted.push(c),
+        }
+    }
+    quoted + "\""
+}
+
+/// How ``console.log`` shows ``value``
+fn inspect(value: &Value) -> String {
+    match *value {
+        Value::Str(ref s) => s.clone(),
+        Value::Object(ref object) => {
+            let is_data = match object.borrow().kind {
+                ObjectKind::Ordinary | ObjectKind::Array(_) => true,
+                _ => false,
+            };
+            match stringify(value, 0) {
+                Ok(Some(json)) if is_data => json,
+                _ => value.to_string(),
+            }
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// ``elements`` as strings joined with ``separator``, None if longer than ``MAX_LENGTH``
+fn join(elements: &[Value], separator: &str, depth: usize) -> Option<String> {
+    let mut joined = String::new();
+    for (i, e) in elements.iter().enumerate() {
+        if i > 0 {
+            joined += separator;
+        }
+        match *e {
+            Value::Undefined | Value::Null => {}
+            ref e => joined += checked_to_string(e, depth + 1)?.as_str(),
+        }
+        if joined.len() > MAX_LENGTH {
+            return None;
+        }
+    }
+    Some(joined)
+}
+
+fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if n == 0.0 {
+        "0".to_string()
+    } else if n.abs() >= 1e21 || n.abs() < 1e-6 {
+        let s = format!("{:e}", n);
+        if s.contains("e-") {
+            s
+        } else {
+            s.replace('e', "e+")
+        }
+    } else {
+        n.to_string()
+    }
+}
+
+/// ``value`` as a string, with the arrays in arrays up to ``depth``. An array longer than
+/// ``MAX_LENGTH`` as a string is empty.
+fn to_string(value: &Value, depth: usize) -> String {
+    checked_to_string(value, depth).unwrap_or_default()
+}
+
+/// ``value`` as a string, None if it is an array longer than ``MAX_LENGTH`` as a string
+fn checked_to_string(value: &Value, depth: usize) -> Option<String> {
+    Some(match *value {
+        Value::Undefined => "undefined".to_string(),
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => number_to_string(n),
+        Value::Str(ref s) => s.clone(),
+        Value::Object(ref object) => {
+            let object = object.borrow();
+            let property = |name: &str| {
+                object
+                    .properties
+                    .iter()
+                    .find(|p| p.0 == name)
+                    .map(|p| p.1.to_string())
+                    .unwrap_or_default()
+            };
+            match object.kind {
+                ObjectKind::Array(_) if depth > 32 => String::new(),
+                ObjectKind::Array(ref elements) => return join(elements, ",", depth),
+                ObjectKind::Function(Function::Closure(ref def, _)) => format!(
+                    "function {}() {{ ... }}",
+                    def.name.as_ref().map_or("", |name| name.as_str())
+                ),
+                ObjectKind::Function(Function::Builtin(_, name))
+                | ObjectKind::Function(Function::Host(_, name)) => {
+                    format!("function {}() {{ [native code] }}", name)
+                }
+                ObjectKind::Error => match property("message").as_str() {
+                    "" => property("name"),
+                    message => format!("{}: {}", property("name"), message),
+                },
+                ObjectKind::Host(ref host) => format!("[object {}]", host.class),
+                ObjectKind::Ordinary => "[object Object]".to_string(),
+            }
+        }
+    })
+}
+
+fn strict_equals(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (&Value::Undefined, &Value::Undefined) | (&Value::Null, &Value::Null) => true,
+        (&Value::Bool(a), &Value::Bool(b)) => a == b,
+        (&Value::Number(a), &Value::Number(b)) => a == b,
+        (&Value::Str(ref a), &Value::Str(ref b)) => a == b,
+        (&Value::Object(ref a), &Value::Object(ref b)) => {
+            if Rc::ptr_eq(a, b) {
+                return true;
+            }
+            match (&a.borrow().kind, &b.borrow().kind) {
+                (&ObjectKind::Host(ref a), &ObjectKind::Host(ref b)) => a == b,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn loose_equals(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (&Value::Undefined, &Value::Null) | (&Value::Null, &Value::Undefined) => true,
+        (&Value::Undefined, _) | (&Value::Null, _) | (_, &Value::Undefined) | (_, &Value::Null) => {
+            strict_equals(a, b)
+        }
+        (&Value::Object(_), &Value::Object(_)) => strict_equals(a, b),
+        (&Value::Object(_), _) => loose_equals(&a.to_primitive(), b),
+        (_, &Value::Object(_)) => loose_equals(a, &b.to_primitive()),
+        (&Value::Str(ref a), &Value::Str(ref b)) => a == b,
+        _ => a.to_number() == b.to_number(),
+    }
+}
+
+/// ``a < b``, or None if one is NaN
+fn less_than(a: &Value, b: &Value) -> Option<bool> {
+    match (a.to_primitive(), b.to_primitive()) {
+        (Value::Str(ref a), Value::Str(ref b)) => Some(a < b),
+        (a, b) => {
+            let (a, b) = (a.to_number(), b.to_number());
+            if a.is_nan() || b.is_nan() {
+                None
+            } else {
+                Some(a < b)
+            }
+        }
+    }
+}
+
+fn instance_of(value: &Value, constructor: &Value) -> bool {
+    let function = match *constructor {
+        Value::Object(ref object) => match object.borrow().kind {
+            ObjectKind::Function(ref function) => function.clone(),
+            _ => return false,
+        },
+        _ => return false,
+    };
+    let object = match *value {
+        Value::Object(ref object) => object.borrow(),
+        _ => return false,
+    };
+    match (function, &object.kind) {
+        (Function::Builtin("global", "Object"), _) => true,
+        (Function::Builtin("global", "Array"), &ObjectKind::Array(_)) => true,
+        (Function::Builtin("global", "Error"), &ObjectKind::Error) => true,
+        (Function::Builtin("global", name), &ObjectKind::Error) => {
+            object.properties.iter().any(|p| p.0 == "name" && p.1.to_string() == name)
+        }
+        (Function::Closure(..), _) => match object.constructor {
+            Some(ref c) => strict_equals(c, constructor),
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+impl Value {
+    pub fn is_undefined(&self) -> bool {
+        match *self {
+            Value::Undefined => true,
+            _ => false,
+        }
+    }
+
+    fn is_nullish(&self) -> bool {
+        match *self {
+            Value::Undefined | Value::Null => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_function(&self) -> bool {
+        match *self {
+            Value::Object(ref object) => match object.borrow().kind {
+                ObjectKind::Function(_) => true,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// The host object ``self`` is, if it is one
+    pub fn as_host_object(&self) -> Option<HostObject> {
+        match *self {
+            Value::Object(ref object) => match object.borrow().kind {
+                ObjectKind::Host(ref host) => Some(host.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn truthy(&self) -> bool {
+        match *self {
+            Value::Undefined | Value::Null => false,
+            Value::Bool(b) => b,
+            Value::Number(n) => n != 0.0 && !n.is_nan(),
+            Value::Str(ref s) => !s.is_empty(),
+            Value::Object(_) => true,
+        }
+    }
+
+    pub fn to_number(&self) -> f64 {
+        match *self {
+            Value::Undefined => f64::NAN,
+            Value::Null => 0.0,
+            Value::Bool(b) => if b { 1.0 } else { 0.0 },
+            Value::Number(n) => n,
+            Value::Str(ref s) => {
+                let s = s.trim();
+                if s.is_empty() {
+                    return 0.0;
+                }
+                if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+                    return u64::from_str_radix(hex, 16).map_or(f64::NAN, |n| n as f64);
+                }
+                match s.trim_start_matches(['+', '-']) {
+                    "Infinity" => return parse_float(s),
+                    t if t.chars().all(|c| c.is_ascii_digit() || "eE.+-".contains(c)) => {}
+                    _ => return f64::NAN,
+                }
+                s.parse().unwrap_or(f64::NAN)
+            }
+            Value::Object(_) => self.to_primitive().to_number(),
+        }
+    }
+
+    /// Objects as their strings
+    fn to_primitive(&self) -> Value {
+        match *self {
+            Value::Object(_) => Value::Str(self.to_string()),
+            ref value => value.clone(),
+        }
+    }
+
+    fn type_of(&self) -> &'static str {
+        match *self {
+            Value::Undefined => "undefined",
+            Value::Null => "object",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::Str(_) => "string",
+            Value::Object(_) if self.is_function() => "function",
+            Value::Object(_) => "object",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", to_string(self, 0))
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Str(ref s) => write!(f, "{:?}", s),
+            ref value => write!(f, "{}", inspect(value)),
+        }
+    }
+}
+
+/// Strict equality (``===``)
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        strict_equals(self, other)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(s: &'a str) -> Value {
+        Value::Str(s.to_string())
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Value {
+        Value::Number(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
+    }
+}
+
+#[test]
+fn test_interpreter() {
+    struct NoHost;
+    impl Host for NoHost {
+        fn get(_: &mut Interpreter<Self>, _: &HostObject, _: &str) -> Result<Value, Value> {
+            Ok(Value::Undefined)
+        }
+        fn set(_: &mut Interpreter<Self>, _: &HostObject, _: &str, _: Value) -> Result<(), Value> {
+            Ok(())
+        }
+        fn call(
+            _: &mut Interpreter<Self>,
+            _: &HostObject,
+            _: &'static str,
+            _: Vec<Value>,
+        ) -> Result<Value, Value> {
+            Ok(Value::Undefined)
+        }
+    }
+
+    let mut js = Interpreter::new(NoHost);
+    let mut run = |source: &str| match js.run(source) {
+        Ok(value) => value.to_string(),
+        Err(e) => e,
+    };
+    let cases = [
+        ("1 + 2 * 3 - 2 ** 2", "3"),
+        ("'a' + 1 + 2", "a12"),
+        ("0.1 + 0.2", "0.30000000000000004"),
+        ("[1 / 0, -1 / 0, 0 / 0, 1e21, 5e-7, -0]", "Infinity,-Infinity,NaN,1e+21,5e-7,0"),
+        ("`a${1 + 1}b${'c'}`", "a2bc"),
+        ("7 % 3 + (5 >> 1) + (-1 >>> 28) + (6 & 3 | 8)", "28"),
+        ("'1' == 1 && null == undefined && '1' !== 1 && [2] == 2", "true"),
+        ("typeof nope + typeof null + typeof (() => 1)", "undefinedobjectfunction"),
+        ("function fact(n) { return n <= 1 ? 1 : n * fact(n - 1) } fact(5)", "120"),
+        ("var fs = []; for (let i = 0; i < 3; i++) fs.push(() => i); fs.map(f => f())", "0,1,2"),
+        ("var o = { n: 2, twice() { return this.n * 2 } }; o.twice()", "4"),
+        ("function P(x) { this.x = x } var p = new P(3); [p.x, p instanceof P]", "3,true"),
+        ("var s = 0; for (var k in { a: 1, b: 2 }) s += k; s", "0ab"),
+        ("var t = 0; for (const v of [1, 2, 3]) { if (v == 2) continue; t += v } t", "4"),
+        ("var n = 0; while (true) { if (++n > 4) break } n", "5"),
+        ("switch (2) { case 1: 'a'; case 2: var r = 'b'; case 3: r += 'c'; break; default: r = 'd' } r", "bc"),
+        ("try { null.x } catch (e) { e instanceof TypeError && e.message }", "Cannot read properties of null (reading 'x')"),
+        ("var f = ''; try { throw new Error('e') } catch (e) { f += e } finally { f += '!' } f", "Error: e!"),
+        ("'Hello'.slice(1, -1).toUpperCase() + 'a,b'.split(',').length", "ELL2"),
+        ("'abcabc'.indexOf('c', 3) + 'abc'.replace('b', x => x + x) + ' x '.trim()", "5abbcx"),
+        ("[3, 1, 10, 2].sort((a, b) => a - b) + ' ' + ['b', 'a', 10].sort()", "1,2,3,10 10,a,b"),
+        ("[1, 2, 3, 4].filter(x => x % 2).reduce((a, b) => a + b, 10)", "14"),
+        ("var a = [1, 2, 3]; a.splice(1, 1, 'x', 'y'); a.length = 3; a", "1,x,y"),
+        ("JSON.stringify({ a: [1, 'x\"'], b: null, c: undefined })", "{\"a\":[1,\"x\\\"\"],\"b\":null}"),
+        ("[parseInt('0x1f'), parseInt('12px'), parseFloat('3.5em'), Number(''), Number('1x')]", "31,12,3.5,0,NaN"),
+        ("(1.5).toFixed(2) + Math.max(1, 3, 2) + Math.round(-2.5) + Math.floor(Math.random())", "1.503-20"),
+        ("Object.keys({ x: 1, y: 2 }).join('') + Array.isArray([]) + ('x' in { x: 1 })", "xytruetrue"),
+        ("let c = 1; { let c = 2 } c", "1"),
+        ("((a, b = 2) => a + b)(1)", "3"),
+        ("fs.length", "3"),
+    ];
+    for &(source, expected) in cases.iter() {
+        assert_eq!(run(source), expected, "{}", source);
+    }
+
+    assert_eq!(run("nope()"), "ReferenceError: nope is not defined");
+    assert_eq!(run("var a = 1; a.b.c()"), "TypeError: Cannot read properties of undefined (reading 'c')");
+    assert_eq!(run("o.nope()"), "TypeError: o.nope is not a function");
+    assert_eq!(run("const k = 1; k = 2"), "TypeError: Assignment to constant variable.");
+    assert_eq!(run("throw 5"), "5");
+    assert_eq!(run("1 +"), "SyntaxError: unexpected end of script (line 1)");
+    // Scripts that do not end are stopped, even if they catch errors.
+    assert_eq!(run("try { while (true) {} } catch (e) {}"), "Error: the script ran too long");
+    assert_eq!(run("function f() { return f() } f()"), "RangeError: Maximum call stack size exceeded");
+    // Too large to allocate
+    assert_eq!(run("var a = []; a.length = 4e9"), "RangeError: Invalid array length");
+    assert_eq!(run("var a = []; a[1e9] = 0"), "RangeError: Invalid array length");
+    assert_eq!(run("Array(2 ** 31)"), "RangeError: Invalid array length");
+    assert_eq!(run("'x'.repeat(2 ** 31)"), "RangeError: Invalid string length");
+    assert_eq!(run("'x'.padStart(2 ** 31)"), "RangeError: Invalid string length");
+    assert_eq!(run("var s = 'x'; while (true) s += s"), "RangeError: Invalid string length");
+    let joined = "var s = 'x'.repeat(1 << 23); [s, s, s].join()";
+    assert_eq!(run(joined), "RangeError: Invalid string length");
+    assert_eq!(run("1"), "1");
+
+    run("console.log('a', 1, [1, 'b'], { c: true })");
+    assert_eq!(js.console, vec!["a 1 [1,\"b\"] {\"c\":true}".to_string()]);
+}
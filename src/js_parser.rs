@@ -0,0 +1,1314 @@
+//! A parser of the subset of JavaScript run by ``js_interpreter``: ES5 with ``let``, ``const``,
+//! arrow functions, template literals and default parameters. There are no regular expression
+//! literals, classes, generators, destructuring or spread.
+
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stmt {
+    Expr(Expr),
+    Var(VarKind, Vec<(String, Option<Expr>)>),
+    Function(Rc<FunctionDef>),
+    Return(Option<Expr>),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    While(Expr, Box<Stmt>),
+    DoWhile(Box<Stmt>, Expr),
+    /// (init, test, update, body)
+    For(Option<Box<Stmt>>, Option<Expr>, Option<Expr>, Box<Stmt>),
+    /// ``for (kind name of/in iterable) body``
+    ForEach(ForEach),
+    Switch(Expr, Vec<(Option<Expr>, Vec<Stmt>)>),
+    Break,
+    Continue,
+    Throw(Expr),
+    /// (block, catch clause with the name of the exception, finally block)
+    Try(Vec<Stmt>, Option<(Option<String>, Vec<Stmt>)>, Option<Vec<Stmt>>),
+    Block(Vec<Stmt>),
+    Empty,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VarKind {
+    Var,
+    Let,
+    Const,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForEach {
+    /// None if assigned to an existing variable
+    pub kind: Option<VarKind>,
+    pub name: String,
+    /// ``of`` iterates over the values, and ``in`` over the keys.
+    pub of: bool,
+    pub iterable: Expr,
+    pub body: Box<Stmt>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionDef {
+    pub name: Option<String>,
+    /// (name, default value)
+    pub params: Vec<(String, Option<Expr>)>,
+    pub body: Vec<Stmt>,
+    /// Arrow functions have the ``this`` of where they are defined.
+    pub is_arrow: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Str(String),
+    /// The strings between the expressions, one more than them
+    Template(Vec<String>, Vec<Expr>),
+    Bool(bool),
+    Null,
+    Ident(String),
+    This,
+    Array(Vec<Expr>),
+    /// (key, value)
+    Object(Vec<(Expr, Expr)>),
+    Function(Rc<FunctionDef>),
+    Unary(UnaryOp, Box<Expr>),
+    /// (increment or decrement, prefix or postfix, target)
+    Update(bool, bool, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Logical(LogicalOp, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// ``target op= value``, or ``target = value`` without an operator
+    Assign(Option<BinaryOp>, Box<Expr>, Box<Expr>),
+    /// ``object[key]``, and ``object.key`` with a ``Str`` key
+    Member(Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    New(Box<Expr>, Vec<Expr>),
+    Sequence(Vec<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+    Plus,
+    BitNot,
+    TypeOf,
+    Void,
+    Delete,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Exp,
+    Eq,
+    NotEq,
+    StrictEq,
+    StrictNotEq,
+    Lt,
+    Gt,
+    LtEq,
+    GtEq,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+    UShr,
+    InstanceOf,
+    In,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogicalOp {
+    And,
+    Or,
+    Nullish,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntaxError {
+    pub message: String,
+    pub line: usize,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SyntaxError: {} (line {})", self.message, self.line)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Num(f64),
+    Str(String),
+    /// The strings, and the sources of the expressions between them
+    Template(Vec<String>, Vec<String>),
+    Ident(String),
+    Punct(&'static str),
+    Eof,
+}
+
+#[derive(Clone, Debug)]
+struct Lexed {
+    token: Token,
+    /// For automatic semicolon insertion
+    newline_before: bool,
+    line: usize,
+}
+
+/// Longest first
+const PUNCTUATORS: [&str; 54] = [
+    ">>>=", "===", "!==", "**=", "...", "<<=", ">>=", ">>>", "=>", "==", "!=", "<=", ">=", "&&",
+    "||", "??", "++", "--", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "**", "<<", ">>",
+    "{", "}", "(", ")", "[", "]", ";", ",", "<", ">", "+", "-", "*", "/", "%", "&", "|", "^",
+    "!", "~", "?", ":", "=", ".", "@",
+];
+
+const RESERVED_WORDS: [&str; 35] = [
+    "break", "case", "catch", "class", "const", "continue", "debugger", "default", "delete",
+    "do", "else", "export", "extends", "false", "finally", "for", "function", "if", "import",
+    "in", "instanceof", "let", "new", "null", "return", "super", "switch", "this", "throw",
+    "true", "try", "typeof", "var", "void", "while",
+];
+
+/// How deep statements and expressions can be nested (counted by ``Parser::nested``), so that
+/// parsing a script doesn't overflow the stack
+const MAX_NESTING: usize = 128;
+
+/// Parses the script ``source``.
+pub fn parse(source: &str) -> Result<Vec<Stmt>, SyntaxError> {
+    let tokens = Lexer::new(source).tokens()?;
+    let mut parser = Parser {
+        tokens: tokens,
+        pos: 0,
+        depth: 0,
+    };
+    let mut stmts = vec![];
+    while parser.peek() != &Token::Eof {
+        stmts.push(parser.parse_statement()?);
+    }
+    Ok(stmts)
+}
+
+struct Lexer<'a> {
+    input: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Lexer<'a> {
+        Lexer {
+            input: input,
+            pos: 0,
+            line: 1,
+        }
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, SyntaxError> {
+        Err(SyntaxError {
+            message: message.to_string(),
+            line: self.line,
+        })
+    }
+
+    fn next_char(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn char_at(&self, n: usize) -> Option<char> {
+        self.input[self.pos..].chars().nth(n)
+    }
+
+    fn consume_char(&mut self) -> Option<char> {
+        let c = self.next_char()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.input[self.pos..].starts_with(s)
+    }
+
+    fn tokens(mut self) -> Result<Vec<Lexed>, SyntaxError> {
+        let mut tokens = vec![];
+        loop {
+            let newline_before = self.skip_whitespace_and_comments()?;
+            let line = self.line;
+            let token = self.token()?;
+            let eof = token == Token::Eof;
+            tokens.push(Lexed {
+                token: token,
+                newline_before: newline_before,
+                line: line,
+            });
+            if eof {
+                return Ok(tokens);
+            }
+        }
+    }
+
+    /// Returns true if a line ends in what is skipped.
+    fn skip_whitespace_and_comments(&mut self) -> Result<bool, SyntaxError> {
+        let line = self.line;
+        loop {
+            match self.next_char() {
+                Some(c) if c.is_whitespace() => {
+                    self.consume_char();
+                }
+                Some('/') if self.char_at(1) == Some('/') => {
+                    while self.next_char().is_some() && self.next_char() != Some('\n') {
+                        self.consume_char();
+                    }
+                }
+                Some('/') if self.char_at(1) == Some('*') => {
+                    self.pos += 2;
+                    while !self.starts_with("*/") {
+                        if self.consume_char().is_none() {
+                            return self.error("unterminated comment");
+                        }
+                    }
+                    self.pos += 2;
+                }
+                _ => return Ok(self.line != line),
+            }
+        }
+    }
+
+    fn token(&mut self) -> Result<Token, SyntaxError> {
+        let c = match self.next_char() {
+            Some(c) => c,
+            None => return Ok(Token::Eof),
+        };
+        if c.is_alphabetic() || c == '_' || c == '$' {
+            let start = self.pos;
+            while let Some(c) = self.next_char() {
+                if !(c.is_alphanumeric() || c == '_' || c == '$') {
+                    break;
+                }
+                self.consume_char();
+            }
+            return Ok(Token::Ident(self.input[start..self.pos].to_string()));
+        }
+        if c.is_ascii_digit()
+            || (c == '.' && self.char_at(1).map(|c| c.is_ascii_digit()) == Some(true))
+        {
+            return self.number();
+        }
+        if c == '"' || c == '\'' {
+            self.consume_char();
+            return Ok(Token::Str(self.string(c)?));
+        }
+        if c == '`' {
+            self.consume_char();
+            return self.template();
+        }
+        for punctuator in PUNCTUATORS.iter() {
+            if self.starts_with(punctuator) {
+                self.pos += punctuator.len();
+                return Ok(Token::Punct(punctuator));
+            }
+        }
+        self.error(format!("unexpected character {:?}", c).as_str())
+    }
+
+    fn number(&mut self) -> Result<Token, SyntaxError> {
+        let start = self.pos;
+        if self.starts_with("0x") || self.starts_with("0X") {
+            self.pos += 2;
+            let digits_start = self.pos;
+            while self.next_char().map(|c| c.is_ascii_hexdigit()) == Some(true) {
+                self.pos += 1;
+            }
+            return match u64::from_str_radix(&self.input[digits_start..self.pos], 16) {
+                Ok(n) => Ok(Token::Num(n as f64)),
+                Err(_) => self.error("invalid hexadecimal number"),
+            };
+        }
+        while self.next_char().map(|c| c.is_ascii_digit() || c == '.') == Some(true) {
+            self.pos += 1;
+        }
+        if self.next_char() == Some('e') || self.next_char() == Some('E') {
+            self.pos += 1;
+            if self.next_char() == Some('+') || self.next_char() == Some('-') {
+                self.pos += 1;
+            }
+            while self.next_char().map(|c| c.is_ascii_digit()) == Some(true) {
+                self.pos += 1;
+            }
+        }
+        match self.input[start..self.pos].parse() {
+            Ok(n) => Ok(Token::Num(n)),
+            Err(_) => self.error("invalid number"),
+        }
+    }
+
+    /// The rest of a string literal after the opening ``quote``.
+    fn string(&mut self, quote: char) -> Result<String, SyntaxError> {
+        let mut s = String::new();
+        loop {
+            match self.consume_char() {
+                Some(c) if c == quote => return Ok(s),
+                Some('\\') => {
+                    if let Some(c) = self.escape()? {
+                        s.push(c);
+                    }
+                }
+                Some('\n') | None => return self.error("unterminated string"),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// The character escaped after a backslash, or None for a line continuation.
+    fn escape(&mut self) -> Result<Option<char>, SyntaxError> {
+        let c = match self.consume_char() {
+            Some(c) => c,
+            None => return self.error("unterminated string"),
+        };
+        let hex = |lexer: &mut Lexer, len: usize| -> Result<char, SyntaxError> {
+            let digits: String = (0..len).filter_map(|_| lexer.consume_char()).collect();
+            match u32::from_str_radix(digits.as_str(), 16).ok().and_then(::std::char::from_u32) {
+                Some(c) => Ok(c),
+                None => lexer.error("invalid escape sequence"),
+            }
+        };
+        Ok(Some(match c {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'v' => '\u{b}',
+            '0' => '\0',
+            'x' => hex(self, 2)?,
+            'u' if self.next_char() == Some('{') => {
+                self.consume_char();
+                let len = self.input[self.pos..].find('}').unwrap_or(0);
+                let c = hex(self, len)?;
+                self.consume_char();
+                c
+            }
+            'u' => hex(self, 4)?,
+            '\n' => return Ok(None),
+            c => c,
+        }))
+    }
+
+    /// The rest of a template literal after the opening backquote.
+    fn template(&mut self) -> Result<Token, SyntaxError> {
+        let mut strings = vec![String::new()];
+        let mut sources = vec![];
+        loop {
+            match self.consume_char() {
+                Some('`') => return Ok(Token::Template(strings, sources)),
+                Some('\\') => {
+                    if let Some(c) = self.escape()? {
+                        strings.last_mut().unwrap().push(c);
+                    }
+                }
+                Some('$') if self.next_char() == Some('{') => {
+                    self.consume_char();
+                    let start = self.pos;
+                    let mut depth = 0;
+                    loop {
+                        match self.next_char() {
+                            Some('}') if depth == 0 => break,
+                            Some('}') => depth -= 1,
+                            Some('{') => depth += 1,
+                            Some(quote) if quote == '"' || quote == '\'' || quote == '`' => {
+                                self.consume_char();
+                                while self.next_char().is_some() && self.next_char() != Some(quote)
+                                {
+                                    if self.consume_char() == Some('\\') {
+                                        self.consume_char();
+                                    }
+                                }
+                            }
+                            Some(_) => {}
+                            None => return self.error("unterminated template literal"),
+                        }
+                        self.consume_char();
+                    }
+                    sources.push(self.input[start..self.pos].to_string());
+                    self.consume_char();
+                    strings.push(String::new());
+                }
+                Some(c) => strings.last_mut().unwrap().push(c),
+                None => return self.error("unterminated template literal"),
+            }
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Lexed>,
+    pos: usize,
+    /// How deep the statement or the expression being parsed is nested
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.peek_at(0).token
+    }
+
+    /// The ``n``th token from the next one (the last is always ``Eof``).
+    fn peek_at(&self, n: usize) -> &Lexed {
+        let last = self.tokens.len() - 1;
+        &self.tokens[(self.pos + n).min(last)]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.peek().clone();
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, SyntaxError> {
+        Err(SyntaxError {
+            message: message.to_string(),
+            line: self.peek_at(0).line,
+        })
+    }
+
+    /// Parses with ``parse`` one level deeper. A SyntaxError past ``MAX_NESTING``.
+    fn nested<T, F>(&mut self, parse: F) -> Result<T, SyntaxError>
+    where
+        F: FnOnce(&mut Parser) -> Result<T, SyntaxError>,
+    {
+        if self.depth >= MAX_NESTING {
+            return self.error("too deeply nested");
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn unexpected<T>(&self) -> Result<T, SyntaxError> {
+        match *self.peek() {
+            Token::Eof => self.error("unexpected end of script"),
+            Token::Num(n) => self.error(format!("unexpected number {}", n).as_str()),
+            Token::Str(ref s) => self.error(format!("unexpected string {:?}", s).as_str()),
+            Token::Template(..) => self.error("unexpected template literal"),
+            Token::Ident(ref name) => {
+                self.error(format!("unexpected {}", name).as_str())
+            }
+            Token::Punct(p) => self.error(format!("unexpected {}", p).as_str()),
+        }
+    }
+
+    fn is_punct(&self, punctuator: &str) -> bool {
+        match *self.peek() {
+            Token::Punct(p) => p == punctuator,
+            _ => false,
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match *self.peek() {
+            Token::Ident(ref name) => name == keyword,
+            _ => false,
+        }
+    }
+
+    fn eat_punct(&mut self, punctuator: &str) -> bool {
+        let is_punct = self.is_punct(punctuator);
+        if is_punct {
+            self.next();
+        }
+        is_punct
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let is_keyword = self.is_keyword(keyword);
+        if is_keyword {
+            self.next();
+        }
+        is_keyword
+    }
+
+    fn expect_punct(&mut self, punctuator: &str) -> Result<(), SyntaxError> {
+        if self.eat_punct(punctuator) {
+            Ok(())
+        } else {
+            self.error(format!("expected {}", punctuator).as_str())
+        }
+    }
+
+    /// An identifier that is not a reserved word.
+    fn parse_identifier(&mut self) -> Result<String, SyntaxError> {
+        match self.peek().clone() {
+            Token::Ident(ref name) if !RESERVED_WORDS.contains(&name.as_str()) => {
+                self.next();
+                Ok(name.clone())
+            }
+            _ => self.error("expected an identifier"),
+        }
+    }
+
+    /// The end of a statement: a semicolon, or where one is inserted.
+    fn consume_semicolon(&mut self) -> Result<(), SyntaxError> {
+        if self.eat_punct(";") || self.is_punct("}") || self.peek() == &Token::Eof
+            || self.peek_at(0).newline_before
+        {
+            Ok(())
+        } else {
+            self.unexpected()
+        }
+    }
+
+    fn parse_block(&mut self) -> Result<Vec<Stmt>, SyntaxError> {
+        self.expect_punct("{")?;
+        let mut stmts = vec![];
+        while !self.eat_punct("}") {
+            if self.peek() == &Token::Eof {
+                return self.error("expected }");
+            }
+            stmts.push(self.parse_statement()?);
+        }
+        Ok(stmts)
+    }
+
+    fn parse_var_kind(&mut self) -> Option<VarKind> {
+        if self.eat_keyword("var") {
+            Some(VarKind::Var)
+        } else if self.eat_keyword("let") {
+            Some(VarKind::Let)
+        } else if self.eat_keyword("const") {
+            Some(VarKind::Const)
+        } else {
+            None
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Stmt, SyntaxError> {
+        self.nested(Parser::parse_statement_inner)
+    }
+
+    fn parse_statement_inner(&mut self) -> Result<Stmt, SyntaxError> {
+        if self.is_punct("{") {
+            return Ok(Stmt::Block(self.parse_block()?));
+        }
+        if self.eat_punct(";") {
+            return Ok(Stmt::Empty);
+        }
+        if let Some(kind) = self.parse_var_kind() {
+            let declarations = self.parse_declarations()?;
+            self.consume_semicolon()?;
+            return Ok(Stmt::Var(kind, declarations));
+        }
+        let keyword = match *self.peek() {
+            Token::Ident(ref name) => name.clone(),
+            _ => String::new(),
+        };
+        match keyword.as_str() {
+            "function" => {
+                self.next();
+                let function = self.parse_function(false)?;
+                if function.name.is_none() {
+                    return self.error("expected the name of the function");
+                }
+                Ok(Stmt::Function(Rc::new(function)))
+            }
+            "return" => {
+                self.next();
+                let value = if self.is_punct(";") || self.is_punct("}")
+                    || self.peek() == &Token::Eof || self.peek_at(0).newline_before
+                {
+                    None
+                } else {
+                    Some(self.parse_expression()?)
+                };
+                self.consume_semicolon()?;
+                Ok(Stmt::Return(value))
+            }
+            "if" => {
+                self.next();
+                self.expect_punct("(")?;
+                let test = self.parse_expression()?;
+                self.expect_punct(")")?;
+                let consequent = Box::new(self.parse_statement()?);
+                let alternate = if self.eat_keyword("else") {
+                    Some(Box::new(self.parse_statement()?))
+                } else {
+                    None
+                };
+                Ok(Stmt::If(test, consequent, alternate))
+            }
+            "while" => {
+                self.next();
+                self.expect_punct("(")?;
+                let test = self.parse_expression()?;
+                self.expect_punct(")")?;
+                Ok(Stmt::While(test, Box::new(self.parse_statement()?)))
+            }
+            "do" => {
+                self.next();
+                let body = Box::new(self.parse_statement()?);
+                if !self.eat_keyword("while") {
+                    return self.error("expected while");
+                }
+                self.expect_punct("(")?;
+                let test = self.parse_expression()?;
+                self.expect_punct(")")?;
+                self.eat_punct(";");
+                Ok(Stmt::DoWhile(body, test))
+            }
+            "for" => {
+                self.next();
+                self.parse_for()
+            }
+            "switch" => {
+                self.next();
+                self.parse_switch()
+            }
+            "break" | "continue" => {
+                self.next();
+                self.consume_semicolon()?;
+                Ok(if keyword == "break" {
+                    Stmt::Break
+                } else {
+                    Stmt::Continue
+                })
+            }
+            "throw" => {
+                self.next();
+                let value = self.parse_expression()?;
+                self.consume_semicolon()?;
+                Ok(Stmt::Throw(value))
+            }
+            "try" => {
+                self.next();
+                let block = self.parse_block()?;
+                let catch = if self.eat_keyword("catch") {
+                    let name = if self.eat_punct("(") {
+                        let name = self.parse_identifier()?;
+                        self.expect_punct(")")?;
+                        Some(name)
+                    } else {
+                        None
+                    };
+                    Some((name, self.parse_block()?))
+                } else {
+                    None
+                };
+                let finally = if self.eat_keyword("finally") {
+                    Some(self.parse_block()?)
+                } else {
+                    None
+                };
+                if catch.is_none() && finally.is_none() {
+                    return self.error("expected catch or finally");
+                }
+                Ok(Stmt::Try(block, catch, finally))
+            }
+            "class" | "import" | "export" => self.error(format!("{} is not supported", keyword).as_str()),
+            _ => {
+                let expr = self.parse_expression()?;
+                self.consume_semicolon()?;
+                Ok(Stmt::Expr(expr))
+            }
+        }
+    }
+
+    fn parse_declarations(&mut self) -> Result<Vec<(String, Option<Expr>)>, SyntaxError> {
+        let mut declarations = vec![];
+        loop {
+            let name = self.parse_identifier()?;
+            let init = if self.eat_punct("=") {
+                Some(self.parse_assignment()?)
+            } else {
+                None
+            };
+            declarations.push((name, init));
+            if !self.eat_punct(",") {
+                return Ok(declarations);
+            }
+        }
+    }
+
+    /// After ``for``.
+    fn parse_for(&mut self) -> Result<Stmt, SyntaxError> {
+        self.expect_punct("(")?;
+        let kind = self.parse_var_kind();
+        let is_for_each = match (self.peek(), &self.peek_at(1).token) {
+            (&Token::Ident(_), &Token::Ident(ref next)) => next == "of" || next == "in",
+            _ => false,
+        };
+        if is_for_each {
+            let name = self.parse_identifier()?;
+            let of = self.next() == Token::Ident("of".to_string());
+            let iterable = self.parse_expression()?;
+            self.expect_punct(")")?;
+            return Ok(Stmt::ForEach(ForEach {
+                kind: kind,
+                name: name,
+                of: of,
+                iterable: iterable,
+                body: Box::new(self.parse_statement()?),
+            }));
+        }
+
+        let init = match kind {
+            Some(kind) => Some(Box::new(Stmt::Var(kind, self.parse_declarations()?))),
+            None if self.is_punct(";") => None,
+            None => Some(Box::new(Stmt::Expr(self.parse_expression()?))),
+        };
+        self.expect_punct(";")?;
+        let test = if self.is_punct(";") {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        self.expect_punct(";")?;
+        let update = if self.is_punct(")") {
+            None
+        } else {
+            Some(self.parse_expression()?)
+        };
+        self.expect_punct(")")?;
+        Ok(Stmt::For(init, test, update, Box::new(self.parse_statement()?)))
+    }
+
+    /// After ``switch``.
+    fn parse_switch(&mut self) -> Result<Stmt, SyntaxError> {
+        self.expect_punct("(")?;
+        let discriminant = self.parse_expression()?;
+        self.expect_punct(")")?;
+        self.expect_punct("{")?;
+        let mut cases = vec![];
+        while !self.eat_punct("}") {
+            let test = if self.eat_keyword("case") {
+                Some(self.parse_expression()?)
+            } else if self.eat_keyword("default") {
+                None
+            } else {
+                return self.unexpected();
+            };
+            self.expect_punct(":")?;
+            let mut body = vec![];
+            while !self.is_keyword("case") && !self.is_keyword("default") && !self.is_punct("}") {
+                if self.peek() == &Token::Eof {
+                    return self.error("expected }");
+                }
+                body.push(self.parse_statement()?);
+            }
+            cases.push((test, body));
+        }
+        Ok(Stmt::Switch(discriminant, cases))
+    }
+
+    /// After ``function``: the optional name, the parameters and the body.
+    fn parse_function(&mut self, is_arrow: bool) -> Result<FunctionDef, SyntaxError> {
+        let name = if self.is_punct("(") {
+            None
+        } else {
+            Some(self.parse_identifier()?)
+        };
+        let params = self.parse_params()?;
+        Ok(FunctionDef {
+            name: name,
+            params: params,
+            body: self.parse_block()?,
+            is_arrow: is_arrow,
+        })
+    }
+
+    fn parse_params(&mut self) -> Result<Vec<(String, Option<Expr>)>, SyntaxError> {
+        self.expect_punct("(")?;
+        let mut params = vec![];
+        while !self.eat_punct(")") {
+            let name = self.parse_identifier()?;
+            let default = if self.eat_punct("=") {
+                Some(self.parse_assignment()?)
+            } else {
+                None
+            };
+            params.push((name, default));
+            if !self.eat_punct(",") {
+                self.expect_punct(")")?;
+                break;
+            }
+        }
+        Ok(params)
+    }
+
+    fn parse_expression(&mut self) -> Result<Expr, SyntaxError> {
+        let expr = self.parse_assignment()?;
+        if !self.is_punct(",") {
+            return Ok(expr);
+        }
+        let mut exprs = vec![expr];
+        while self.eat_punct(",") {
+            exprs.push(self.parse_assignment()?);
+        }
+        Ok(Expr::Sequence(exprs))
+    }
+
+    /// Whether an arrow function starts here.
+    fn is_arrow_function(&self) -> bool {
+        match *self.peek() {
+            Token::Ident(_) => self.peek_at(1).token == Token::Punct("=>"),
+            Token::Punct("(") => {
+                let mut depth = 0;
+                let mut n = 0;
+                loop {
+                    match self.peek_at(n).token {
+                        Token::Punct("(") => depth += 1,
+                        Token::Punct(")") if depth == 1 => {
+                            return self.peek_at(n + 1).token == Token::Punct("=>");
+                        }
+                        Token::Punct(")") => depth -= 1,
+                        Token::Eof => return false,
+                        _ => {}
+                    }
+                    n += 1;
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_arrow_function(&mut self) -> Result<Expr, SyntaxError> {
+        let params = if self.is_punct("(") {
+            self.parse_params()?
+        } else {
+            vec![(self.parse_identifier()?, None)]
+        };
+        self.expect_punct("=>")?;
+        let body = if self.is_punct("{") {
+            self.parse_block()?
+        } else {
+            vec![Stmt::Return(Some(self.parse_assignment()?))]
+        };
+        Ok(Expr::Function(Rc::new(FunctionDef {
+            name: None,
+            params: params,
+            body: body,
+            is_arrow: true,
+        })))
+    }
+
+    fn parse_assignment(&mut self) -> Result<Expr, SyntaxError> {
+        self.nested(Parser::parse_assignment_inner)
+    }
+
+    fn parse_assignment_inner(&mut self) -> Result<Expr, SyntaxError> {
+        if self.is_arrow_function() {
+            return self.parse_arrow_function();
+        }
+        let target = self.parse_conditional()?;
+        let op = match *self.peek() {
+            Token::Punct("=") => None,
+            Token::Punct(p) if p.len() >= 2 && p.ends_with('=') && !p.starts_with("==")
+                && !p.starts_with("!=") && p != "<=" && p != ">=" =>
+            {
+                Some(binary_op(&p[..p.len() - 1]).unwrap().0)
+            }
+            _ => return Ok(target),
+        };
+        match target {
+            Expr::Ident(_) | Expr::Member(..) => {}
+            _ => return self.error("invalid assignment target"),
+        }
+        self.next();
+        let value = self.parse_assignment()?;
+        Ok(Expr::Assign(op, Box::new(target), Box::new(value)))
+    }
+
+    fn parse_conditional(&mut self) -> Result<Expr, SyntaxError> {
+        let test = self.parse_binary(0)?;
+        if !self.eat_punct("?") {
+            return Ok(test);
+        }
+        let consequent = self.parse_assignment()?;
+        self.expect_punct(":")?;
+        let alternate = self.parse_assignment()?;
+        Ok(Expr::Conditional(
+            Box::new(test),
+            Box::new(consequent),
+            Box::new(alternate),
+        ))
+    }
+
+    /// The binary operators of precedence ``min_precedence`` and higher.
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, SyntaxError> {
+        self.nested(|parser| parser.parse_binary_inner(min_precedence))
+    }
+
+    fn parse_binary_inner(&mut self, min_precedence: u8) -> Result<Expr, SyntaxError> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let operator = match *self.peek() {
+                Token::Punct(p) => p,
+                Token::Ident(ref name) if name == "instanceof" => "instanceof",
+                Token::Ident(ref name) if name == "in" => "in",
+                _ => return Ok(left),
+            };
+            let (op, precedence) = match binary_op(operator) {
+                Some((op, precedence)) if precedence >= min_precedence => (op, precedence),
+                _ => match logical_op(operator) {
+                    Some((op, precedence)) if precedence >= min_precedence => {
+                        self.next();
+                        let right = self.parse_binary(precedence + 1)?;
+                        left = Expr::Logical(op, Box::new(left), Box::new(right));
+                        continue;
+                    }
+                    _ => return Ok(left),
+                },
+            };
+            self.next();
+            // ** is right-associative.
+            let right = if op == BinaryOp::Exp {
+                self.parse_binary(precedence)?
+            } else {
+                self.parse_binary(precedence + 1)?
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, SyntaxError> {
+        self.nested(Parser::parse_unary_inner)
+    }
+
+    fn parse_unary_inner(&mut self) -> Result<Expr, SyntaxError> {
+        let op = match *self.peek() {
+            Token::Punct("!") => UnaryOp::Not,
+            Token::Punct("-") => UnaryOp::Neg,
+            Token::Punct("+") => UnaryOp::Plus,
+            Token::Punct("~") => UnaryOp::BitNot,
+            Token::Punct("++") | Token::Punct("--") => {
+                let increment = self.next() == Token::Punct("++");
+                let target = self.parse_unary()?;
+                return Ok(Expr::Update(increment, true, Box::new(target)));
+            }
+            Token::Ident(ref name) if name == "typeof" => UnaryOp::TypeOf,
+            Token::Ident(ref name) if name == "void" => UnaryOp::Void,
+            Token::Ident(ref name) if name == "delete" => UnaryOp::Delete,
+            _ => return self.parse_postfix(),
+        };
+        self.next();
+        Ok(Expr::Unary(op, Box::new(self.parse_unary()?)))
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, SyntaxError> {
+        let expr = self.parse_call_member()?;
+        if (self.is_punct("++") || self.is_punct("--")) && !self.peek_at(0).newline_before {
+            let increment = self.next() == Token::Punct("++");
+            return Ok(Expr::Update(increment, false, Box::new(expr)));
+        }
+        Ok(expr)
+    }
+
+    fn parse_arguments(&mut self) -> Result<Vec<Expr>, SyntaxError> {
+        self.expect_punct("(")?;
+        let mut args = vec![];
+        while !self.eat_punct(")") {
+            args.push(self.parse_assignment()?);
+            if !self.eat_punct(",") {
+                self.expect_punct(")")?;
+                break;
+            }
+        }
+        Ok(args)
+    }
+
+    /// After ``.``: any name, even a reserved word.
+    fn parse_property_name(&mut self) -> Result<Expr, SyntaxError> {
+        match self.next() {
+            Token::Ident(name) => Ok(Expr::Str(name)),
+            _ => self.error("expected a property name"),
+        }
+    }
+
+    fn parse_call_member(&mut self) -> Result<Expr, SyntaxError> {
+        let mut expr = if self.eat_keyword("new") {
+            let mut callee = self.parse_primary()?;
+            loop {
+                if self.eat_punct(".") {
+                    callee = Expr::Member(Box::new(callee), Box::new(self.parse_property_name()?));
+                } else if self.eat_punct("[") {
+                    let key = self.parse_expression()?;
+                    self.expect_punct("]")?;
+                    callee = Expr::Member(Box::new(callee), Box::new(key));
+                } else {
+                    break;
+                }
+            }
+            let args = if self.is_punct("(") {
+                self.parse_arguments()?
+            } else {
+                vec![]
+            };
+            Expr::New(Box::new(callee), args)
+        } else {
+            self.parse_primary()?
+        };
+        loop {
+            if self.eat_punct(".") {
+                expr = Expr::Member(Box::new(expr), Box::new(self.parse_property_name()?));
+            } else if self.eat_punct("[") {
+                let key = self.parse_expression()?;
+                self.expect_punct("]")?;
+                expr = Expr::Member(Box::new(expr), Box::new(key));
+            } else if self.is_punct("(") {
+                expr = Expr::Call(Box::new(expr), self.parse_arguments()?);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, SyntaxError> {
+        if self.peek() == &Token::Eof {
+            return self.unexpected();
+        }
+        match self.next() {
+            Token::Num(n) => Ok(Expr::Number(n)),
+            Token::Str(s) => Ok(Expr::Str(s)),
+            Token::Template(strings, sources) => {
+                let mut exprs = vec![];
+                for source in sources {
+                    let tokens = Lexer::new(source.as_str()).tokens()?;
+                    // As deep as the template
+                    let mut parser = Parser {
+                        tokens: tokens,
+                        pos: 0,
+                        depth: self.depth,
+                    };
+                    exprs.push(parser.parse_expression()?);
+                    if parser.peek() != &Token::Eof {
+                        return parser.unexpected();
+                    }
+                }
+                Ok(Expr::Template(strings, exprs))
+            }
+            Token::Ident(name) => match name.as_str() {
+                "this" => Ok(Expr::This),
+                "null" => Ok(Expr::Null),
+                "true" => Ok(Expr::Bool(true)),
+                "false" => Ok(Expr::Bool(false)),
+                "function" => Ok(Expr::Function(Rc::new(self.parse_function(false)?))),
+                name if RESERVED_WORDS.contains(&name) => {
+                    self.pos -= 1;
+                    self.unexpected()
+                }
+                _ => Ok(Expr::Ident(name)),
+            },
+            Token::Punct("(") => {
+                let expr = self.parse_expression()?;
+                self.expect_punct(")")?;
+                Ok(expr)
+            }
+            Token::Punct("[") => {
+                let mut elements = vec![];
+                while !self.eat_punct("]") {
+                    elements.push(self.parse_assignment()?);
+                    if !self.eat_punct(",") {
+                        self.expect_punct("]")?;
+                        break;
+                    }
+                }
+                Ok(Expr::Array(elements))
+            }
+            Token::Punct("{") => self.parse_object(),
+            _ => {
+                self.pos -= 1;
+                self.unexpected()
+            }
+        }
+    }
+
+    /// After ``{``.
+    fn parse_object(&mut self) -> Result<Expr, SyntaxError> {
+        let mut properties = vec![];
+        while !self.eat_punct("}") {
+            let (key, shorthand) = match self.next() {
+                Token::Ident(name) => (Expr::Str(name.clone()), Some(name)),
+                Token::Str(s) => (Expr::Str(s), None),
+                Token::Num(n) => (Expr::Number(n), None),
+                Token::Punct("[") => {
+                    let key = self.parse_assignment()?;
+                    self.expect_punct("]")?;
+                    (key, None)
+                }
+                _ => {
+                    self.pos -= 1;
+                    return self.unexpected();
+                }
+            };
+            let value = if self.eat_punct(":") {
+                self.parse_assignment()?
+            } else if self.is_punct("(") {
+                let params = self.parse_params()?;
+                Expr::Function(Rc::new(FunctionDef {
+                    name: shorthand,
+                    params: params,
+                    body: self.parse_block()?,
+                    is_arrow: false,
+                }))
+            } else {
+                match shorthand {
+                    Some(name) => Expr::Ident(name),
+                    None => return self.error("expected :"),
+                }
+            };
+            properties.push((key, value));
+            if !self.eat_punct(",") {
+                self.expect_punct("}")?;
+                break;
+            }
+        }
+        Ok(Expr::Object(properties))
+    }
+}
+
+/// The binary operator ``p``, and its precedence.
+fn binary_op(p: &str) -> Option<(BinaryOp, u8)> {
+    Some(match p {
+        "|" => (BinaryOp::BitOr, 4),
+        "^" => (BinaryOp::BitXor, 5),
+        "&" => (BinaryOp::BitAnd, 6),
+        "==" => (BinaryOp::Eq, 7),
+        "!=" => (BinaryOp::NotEq, 7),
+        "===" => (BinaryOp::StrictEq, 7),
+        "!==" => (BinaryOp::StrictNotEq, 7),
+        "<" => (BinaryOp::Lt, 8),
+        ">" => (BinaryOp::Gt, 8),
+        "<=" => (BinaryOp::LtEq, 8),
+        ">=" => (BinaryOp::GtEq, 8),
+        "instanceof" => (BinaryOp::InstanceOf, 8),
+        "in" => (BinaryOp::In, 8),
+        "<<" => (BinaryOp::Shl, 9),
+        ">>" => (BinaryOp::Shr, 9),
+        ">>>" => (BinaryOp::UShr, 9),
+        "+" => (BinaryOp::Add, 10),
+        "-" => (BinaryOp::Sub, 10),
+        "*" => (BinaryOp::Mul, 11),
+        "/" => (BinaryOp::Div, 11),
+        "%" => (BinaryOp::Rem, 11),
+        "**" => (BinaryOp::Exp, 12),
+        _ => return None,
+    })
+}
+
+fn logical_op(p: &str) -> Option<(LogicalOp, u8)> {
+    Some(match p {
+        "??" => (LogicalOp::Nullish, 1),
+        "||" => (LogicalOp::Or, 2),
+        "&&" => (LogicalOp::And, 3),
+        _ => return None,
+    })
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(
+        parse("let a = 1 + 2 * 3\na++").unwrap(),
+        vec![
+            Stmt::Var(
+                VarKind::Let,
+                vec![
+                    (
+                        "a".to_string(),
+                        Some(Expr::Binary(
+                            BinaryOp::Add,
+                            Box::new(Expr::Number(1.0)),
+                            Box::new(Expr::Binary(
+                                BinaryOp::Mul,
+                                Box::new(Expr::Number(2.0)),
+                                Box::new(Expr::Number(3.0)),
+                            )),
+                        )),
+                    ),
+                ],
+            ),
+            Stmt::Expr(Expr::Update(
+                true,
+                false,
+                Box::new(Expr::Ident("a".to_string())),
+            )),
+        ]
+    );
+
+    assert_eq!(
+        parse("x => x.y['z'](`a${b}c`)").unwrap(),
+        vec![
+            Stmt::Expr(Expr::Function(Rc::new(FunctionDef {
+                name: None,
+                params: vec![("x".to_string(), None)],
+                body: vec![
+                    Stmt::Return(Some(Expr::Call(
+                        Box::new(Expr::Member(
+                            Box::new(Expr::Member(
+                                Box::new(Expr::Ident("x".to_string())),
+                                Box::new(Expr::Str("y".to_string())),
+                            )),
+                            Box::new(Expr::Str("z".to_string())),
+                        )),
+                        vec![
+                            Expr::Template(
+                                vec!["a".to_string(), "c".to_string()],
+                                vec![Expr::Ident("b".to_string())],
+                            ),
+                        ],
+                    ))),
+                ],
+                is_arrow: true,
+            }))),
+        ]
+    );
+
+    // A return at the end of a line returns nothing.
+    match parse("function f() { return\n1 }").unwrap()[0] {
+        Stmt::Function(ref f) => assert_eq!(f.body[0], Stmt::Return(None)),
+        ref stmt => panic!("{:?}", stmt),
+    }
+
+    for source in &[
+        "for (let i = 0; i < 3; i++) {}",
+        "for (const x of [1, 2]) ;",
+        "var o = { a: 1, 'b': [2], c() { return this.a } }",
+        "try { throw new Error('e') } catch (e) {} finally {}",
+        "switch (a) { case 1: break; default: a = -a ** 2 }",
+        "a = b ? c : d ?? e || f && g",
+        "/* comment */ a.b.c = (1, 2) // comment",
+    ] {
+        assert!(parse(source).is_ok(), "{}", source);
+    }
+
+    assert_eq!(
+        parse("let = 1").unwrap_err().to_string(),
+        "SyntaxError: expected an identifier (line 1)"
+    );
+    assert_eq!(parse("a b").unwrap_err().line, 1);
+    assert_eq!(parse("\n'abc").unwrap_err().line, 2);
+    assert!(parse("1 = 2").is_err());
+    assert!(parse("class A {}").is_err());
+
+    // Nested too deeply to be parsed without overflowing the stack
+    let too_deep = |prefix: &str, suffix: &str| {
+        let source = format!("{}1{}", prefix.repeat(10_000), suffix.repeat(10_000));
+        parse(source.as_str()).unwrap_err().message
+    };
+    assert_eq!(too_deep("(", ")"), "too deeply nested");
+    assert_eq!(too_deep("[", "]"), "too deeply nested");
+    assert_eq!(too_deep("!", ""), "too deeply nested");
+    assert_eq!(too_deep("a = ", ""), "too deeply nested");
+    assert_eq!(too_deep("1 ** ", ""), "too deeply nested");
+    assert_eq!(too_deep("{", "}"), "too deeply nested");
+    assert_eq!(too_deep("`${", "}`"), "too deeply nested");
+    assert!(parse(format!("{}1{}", "(".repeat(32), ")".repeat(32)).as_str()).is_ok());
+}
@@ -1,25 +1,50 @@
-use style::{Display, Style};
+use style::{Display, Style, TextTransform, LANG_PROPERTY};
 use dom::{ElementData, LayoutType, Node, NodeType};
+use containment;
 use float::Floats;
-use font::{Font, FontSlant, FontWeight};
-use inline::LineMaker;
+use generated::{self, Counters};
+use font::Font;
+#[cfg(feature = "raster")]
+use font::{FontSlant, FontWeight};
+use inline::{LineMaker, LineMetrics};
+use lazy;
+use media;
+use quirks;
 use style;
+use mathml::MathLayout;
+use srcset;
+use svg;
+use canvas2d;
+use stats::{self, Phase};
+use trace;
 use default_style;
 use css::{parse_attr_style, Declaration, Rule, Selector, SimpleSelector, Specificity, Stylesheet,
-          Value};
+          Unit, Value};
 
-use std::collections::HashMap;
+use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet};
 use std::default::Default;
 use std::fmt;
 use std::ops::Range;
 
+#[cfg(feature = "raster")]
 use cairo;
+#[cfg(feature = "raster")]
 use pango;
-use gdk_pixbuf;
-use gtk;
 
 use app_units::Au;
 
+const DEFAULT_TEXT_INPUT_CHARS: f64 = 20.0;
+const DEFAULT_TEXTAREA_ROWS: f64 = 2.0;
+/// The most characters or lines ``size``, ``cols`` and ``rows`` make a text input as wide or high as
+const MAX_TEXT_INPUT_COUNT: f64 = 10000.0;
+/// The width (px) of the arrow at the end of a ``<select>``
+pub const SELECT_ARROW_WIDTH: f64 = 16.0;
+
+/// The content height of a containing block whose height depends on its content, so that the
+/// percentage heights in it are ``auto`` (see ``LayoutBox::definite_height``)
+pub const INDEFINITE_HEIGHT: Au = Au(-1);
+
 // CSS box model. All sizes are in px.
 
 #[derive(Clone, Copy, Default, Debug, Hash, PartialEq, Eq)]
@@ -53,14 +78,31 @@ pub enum LayoutInfo {
     Generic,
     Text,
     Image(ImageData),
+    /// An inline ``<svg>``, sized like an image (see ``svg::intrinsic_size``)
+    Svg(ImageData),
+    /// A ``<canvas>``, sized like an image (see ``canvas2d::intrinsic_size``)
+    Canvas(ImageData),
+    /// A ``<math>``, as large as its formula, whose baseline is that of the line
+    Math(MathLayout),
     Anker,
-    Button(Option<gtk::Button>, usize),
+    /// The node id (the widget is ``controls::BUTTONS``)
+    Button(usize),
+    TextInput(usize), // node id
+    /// The node id (where it is painted is ``controls::SELECTS``)
+    Select(usize),
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ImageData {
-    pub pixbuf: Option<gdk_pixbuf::Pixbuf>,
+    /// The size of the image (px) over its density, None until it is loaded. The decoded image
+    /// is painted from ``inline::pixbuf``.
+    pub intrinsic_size: Option<(f64, f64)>,
     pub metadata: ImageMetaData,
+    /// The URL of the image selected when it was laid out, and its density (see
+    /// ``srcset::select_source``)
+    pub source: Option<(String, f64)>,
+    /// The ``<source>``s before the ``<img>`` in its ``<picture>``
+    pub picture_sources: Vec<Node>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -91,19 +133,41 @@ pub struct LayoutBox {
     pub info: LayoutInfo,
     pub floats: Floats,
     pub children: Vec<LayoutBox>,
+    /// The id of the node (see ``dom::Node::find_node_by_id_mut``). None for anonymous boxes.
+    pub node_id: Option<usize>,
+    /// The properties of ``::first-line``, for the anonymous block with the first line of a block
+    pub first_line: Option<Style>,
+    /// What the block was laid out in, to be laid out again alone (see ``relayout_changed``).
+    /// None if it was not laid out, or was laid out next to floats.
+    laid_out_in: Option<LaidOutIn>,
+}
+
+/// What a block in normal flow was laid out in: the floats (none), the margin it collapsed with,
+/// its containing block (as high as the boxes before it) and the block its percentage heights are
+/// of
+#[derive(Clone, Debug)]
+struct LaidOutIn {
+    floats: Floats,
+    last_margin_bottom: Au,
+    containing_block: Dimensions,
+    saved_block: Dimensions,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Text {
     pub font: Font,
     pub range: Range<usize>,
+    /// The line is broken in a word after this text. A hyphen is painted.
+    pub hyphenated: bool,
 }
 
 impl ImageData {
-    pub fn new(pixbuf: Option<gdk_pixbuf::Pixbuf>, metadata: ImageMetaData) -> ImageData {
+    pub fn new(intrinsic_size: Option<(f64, f64)>, metadata: ImageMetaData) -> ImageData {
         ImageData {
-            pixbuf: pixbuf,
+            intrinsic_size: intrinsic_size,
             metadata: metadata,
+            source: None,
+            picture_sources: vec![],
         }
     }
 
@@ -132,6 +196,9 @@ impl LayoutBox {
             floats: Floats::new(),
             dimensions: Default::default(),
             children: Vec::with_capacity(16),
+            node_id: None,
+            first_line: None,
+            laid_out_in: None,
         }
     }
 
@@ -139,10 +206,11 @@ impl LayoutBox {
         &self.property
     }
 
-    pub fn set_text_info(&mut self, font: Font, range: Range<usize>) {
+    pub fn set_text_info(&mut self, font: Font, range: Range<usize>, hyphenated: bool) {
         if let BoxType::TextNode(ref mut r) = self.box_type {
             r.font = font;
             r.range = range;
+            r.hyphenated = hyphenated;
         }
     }
 
@@ -152,66 +220,109 @@ impl LayoutBox {
 }
 
 /// Build the tree of LayoutBoxes, but don't perform any layout calculations yet.
+/// ``counters`` are updated by the elements (and their pseudo-elements) in the order their boxes
+/// are generated, for their ``content``.
 fn build_layout_tree(
     node: &Node,
     stylesheet: &Stylesheet,
     default_style: &Stylesheet,
-    inherited_property: &Style,
     parent_specified_values: &Style,
     appeared_elements: &Vec<SimpleSelector>,
-    // id: &mut usize,
+    counters: &mut Counters,
+    id: &mut usize,
 ) -> LayoutBox {
+    let inherited_property = inherit_peoperties(parent_specified_values, &INHERITED_PROPERTIES);
     let mut appeared_elements = appeared_elements.clone();
+    // The names and styles of ``::before`` and ``::after``
+    let mut pseudo_element_values = vec![];
+    let (mut first_line, mut first_letter) = (None, None);
     let specified_values = match node.data {
         NodeType::Element(ref elem) => {
-            let values = specified_values(
+            let mut values = specified_values(
                 elem,
+                None,
                 default_style,
                 stylesheet,
-                inherited_property,
+                &inherited_property,
                 &appeared_elements,
             );
-            appeared_elements.push(SimpleSelector {
-                tag_name: Some(elem.tag_name.clone()),
-                id: elem.id().and_then(|id| Some(id.clone())),
-                class: elem.classes().iter().map(|x| x.to_string()).collect(),
-            });
+            if parent_specified_values.display().is_flex_or_grid() {
+                blockify(&mut values);
+            }
+            // Replaced elements have no content to put them around.
+            if elem.layout_type == LayoutType::Generic || elem.layout_type == LayoutType::Anker {
+                let inherited_property = inherit_peoperties(&values, &INHERITED_PROPERTIES);
+                for &pseudo_element in ["before", "after"].iter() {
+                    let mut pseudo_values = specified_values(
+                        elem,
+                        Some(pseudo_element),
+                        default_style,
+                        stylesheet,
+                        &inherited_property,
+                        &appeared_elements,
+                    );
+                    if values.display().is_flex_or_grid() {
+                        blockify(&mut pseudo_values);
+                    }
+                    pseudo_element_values.push((pseudo_element, pseudo_values));
+                }
+                let pseudo_element_style = |pseudo_element| {
+                    pseudo_element_style(
+                        elem,
+                        pseudo_element,
+                        default_style,
+                        stylesheet,
+                        &inherited_property,
+                        &appeared_elements,
+                    )
+                };
+                first_line = pseudo_element_style("first-line");
+                // In the first line, so inheriting from it
+                first_letter = pseudo_element_style("first-letter").map(|style| {
+                    let mut property = inherited_property.property.clone();
+                    if let Some(ref first_line) = first_line {
+                        property.extend(first_line.property.clone());
+                    }
+                    property.extend(style.property);
+                    Style::new_with(property)
+                });
+            }
+            appeared_elements.push(ancestor_selector(elem));
             values
         }
-        NodeType::Text(_) => {
-            Style::new_with(
-                if let Some(display) = parent_specified_values.property.get("display") {
-                    match display[0] {
-                        // If the parent element is an inline element, inherites the parent's properties.
-                        Value::Keyword(ref k) if k == "inline" => parent_specified_values.clone(),
-                        _ => inherited_property.clone(),
-                    }
-                } else {
-                    inherited_property.clone()
-                }.property
-                    .into_iter()
-                    .filter(|&(ref name, _)| name != "float")
-                    .collect(),
-            )
+        NodeType::Text(_) => text_style(parent_specified_values),
+    };
+
+    // Texts are laid out and painted transformed, so the ranges of the text boxes are in the
+    // transformed text.
+    let transformed;
+    let node = match (&node.data, specified_values.text_transform()) {
+        (_, TextTransform::None) | (&NodeType::Element(_), _) => node,
+        (&NodeType::Text(ref s), _) => {
+            transformed = Node {
+                data: NodeType::Text(specified_values.transform_text(s)),
+                children: vec![],
+            };
+            &transformed
         }
     };
 
+    // The root element is a block, which contains the other boxes.
+    let is_root = *id == 0;
+
     // Create the root box.
     let mut root = LayoutBox::new(
-        match specified_values.display() {
-            Display::Block => BoxType::BlockNode,
-            Display::Inline => match node.data {
-                NodeType::Element(_) => BoxType::InlineNode,
-                NodeType::Text(ref s) => BoxType::TextNode(Text {
-                    font: Font::new_empty(),
-                    range: 0..s.len(),
-                }),
-            },
-            Display::InlineBlock => match node.data {
-                NodeType::Element(_) => BoxType::InlineBlockNode,
-                NodeType::Text(_) => panic!(),
-            },
-            Display::None => BoxType::None, // TODO
+        match (specified_values.display(), &node.data) {
+            (Display::None, _) => BoxType::None, // TODO
+            (_, &NodeType::Text(ref s)) => BoxType::TextNode(Text {
+                font: Font::new_empty(),
+                range: 0..s.len(),
+                hyphenated: false,
+            }),
+            (Display::Block, _) | (Display::Flex, _) | (Display::Grid, _) => BoxType::BlockNode,
+            (_, _) if is_root => BoxType::BlockNode,
+            (Display::Inline, _) => BoxType::InlineNode,
+            (Display::InlineBlock, _) => BoxType::InlineBlockNode,
         },
         node.clone(),
         specified_values.clone(),
@@ -219,76 +330,392 @@ fn build_layout_tree(
             LayoutType::Generic => LayoutInfo::Generic,
             LayoutType::Text => LayoutInfo::Text,
             LayoutType::Image => LayoutInfo::Image(ImageData::new_empty()),
+            LayoutType::Svg => LayoutInfo::Svg(ImageData::new_empty()),
+            LayoutType::Canvas => LayoutInfo::Canvas(ImageData::new_empty()),
+            LayoutType::Math => LayoutInfo::Math(MathLayout::default()),
             LayoutType::Anker => LayoutInfo::Anker,
-            LayoutType::Button => LayoutInfo::Button(None, 0 /* *id */),
+            LayoutType::Button => LayoutInfo::Button(*id),
+            LayoutType::TextInput => LayoutInfo::TextInput(*id),
+            LayoutType::Select => LayoutInfo::Select(*id),
         },
     );
+    root.node_id = Some(*id);
 
     if root.box_type == BoxType::None {
+        // Skip the ids of the descendants that are not laid out.
+        *id += node.count_nodes() - 1;
         return root;
     }
 
     match specified_values.float() {
         style::FloatType::None => {}
+        _ if is_root => {}
         style::FloatType::Left | style::FloatType::Right => root.box_type = BoxType::Float,
     }
 
-    let inherited_property = inherit_peoperties(
-        &specified_values,
-        vec![
-            "font-size",
-            "line-height",
-            "font-weight",
-            "font-style",
-            "text-align",
-            "color",
-        ],
-    );
+    // With style containment, the counters and the quotes are as they were before the element
+    // once its subtree is generated.
+    let outer_counters = if specified_values.contain().style {
+        Some(counters.clone())
+    } else {
+        None
+    };
+    if let NodeType::Element(_) = node.data {
+        counters.update(&specified_values);
+    }
+
+    // Text inputs, SVGs and formulas are replaced elements. Their contents are painted from their
+    // values, and from the SVG DOM and the MathML of their subtrees.
+    if root.is_replaced() {
+        *id += node.count_nodes() - 1;
+        if let Some(outer_counters) = outer_counters {
+            *counters = outer_counters;
+        }
+        return root;
+    }
 
-    // Create the descendant boxes.
-    for child in node.children.iter() {
-        // *id += 1;
-        let child = build_layout_tree(
+    // Create the descendant boxes, between those of ``::before`` and ``::after``.
+    counters.enter();
+    let generated = |i: usize, counters: &mut Counters| match node.data {
+        NodeType::Element(ref elem) => pseudo_element_values
+            .get(i)
+            .and_then(|&(name, ref values)| generated_box(elem, name, values, counters)),
+        NodeType::Text(_) => None,
+    };
+    if let Some(before) = generated(0, counters) {
+        push_child(&mut root, before);
+    }
+    for (i, child) in node.children.iter().enumerate() {
+        *id += 1;
+        let mut child = build_layout_tree(
             child,
             stylesheet,
             default_style,
-            &inherited_property,
             &specified_values,
             &appeared_elements,
-            // id,
+            counters,
+            id,
         );
+        // The ``<img>`` of a ``<picture>`` may show one of the ``<source>``s before it.
+        match (&mut child.info, &node.data) {
+            (&mut LayoutInfo::Image(ref mut imgdata), &NodeType::Element(ref e))
+                if e.tag_name == "picture" =>
+            {
+                imgdata.picture_sources = node.children[..i]
+                    .iter()
+                    .filter(|sibling| match sibling.data {
+                        NodeType::Element(ref e) => e.tag_name == "source",
+                        NodeType::Text(_) => false,
+                    })
+                    .cloned()
+                    .collect();
+            }
+            _ => {}
+        }
+        push_child(&mut root, child);
+    }
+    if let Some(after) = generated(1, counters) {
+        push_child(&mut root, after);
+    }
+    counters.leave();
+    if let Some(outer_counters) = outer_counters {
+        *counters = outer_counters;
+    }
+
+    // The first line is the one of the anonymous block first in a block container.
+    if let BoxType::BlockNode | BoxType::InlineBlockNode | BoxType::Float = root.box_type {
+        if let Some(first_letter) = first_letter {
+            split_first_letter(&mut root, first_letter);
+        }
+        match root.children.first_mut() {
+            Some(anonymous_block) if anonymous_block.box_type == BoxType::AnonymousBlock => {
+                anonymous_block.first_line = first_line
+            }
+            _ => {}
+        }
+    }
+
+    root
+}
+
+/// The properties inherited by the children of an element
+const INHERITED_PROPERTIES: [&str; 25] = [
+    "font-size",
+    "line-height",
+    "font-weight",
+    "font-style",
+    "font-family",
+    "font-variant",
+    "font-variant-caps",
+    "font-variant-ligatures",
+    "font-kerning",
+    "font-feature-settings",
+    "letter-spacing",
+    "word-spacing",
+    "tab-size",
+    "hyphens",
+    LANG_PROPERTY,
+    "text-align",
+    "direction",
+    "text-transform",
+    "text-shadow",
+    "quotes",
+    "color",
+    "cursor",
+    "pointer-events",
+    "scrollbar-color",
+    "color-scheme",
+];
+
+/// The properties the anonymous blocks for the inline boxes inherit from their parents
+const ANONYMOUS_PROPERTIES: [&str; 6] = [
+    "font-size",
+    "line-height",
+    "font-weight",
+    "font-style",
+    "font-family",
+    "pointer-events",
+];
+
+/// Makes ``style`` that of a flex or grid item, which is a block, and is not floated.
+/// ref. https://www.w3.org/TR/css-display-3/#blockify
+fn blockify(style: &mut Style) {
+    match style.display() {
+        Display::Inline | Display::InlineBlock => {
+            style
+                .property
+                .insert("display".to_string(), vec![Value::Keyword("block".to_string())]);
+        }
+        Display::Block | Display::Flex | Display::Grid | Display::None => {}
+    }
+    style.property.remove("float");
+}
+
+/// Adds the box ``child`` to ``root``, or to the anonymous block for the inline boxes in it.
+fn push_child(root: &mut LayoutBox, child: LayoutBox) {
+    // The white space between the items of a flex or grid container is not laid out.
+    if root.property.display().is_flex_or_grid() {
+        match child.node.data {
+            NodeType::Text(ref s) if s.trim().is_empty() => return,
+            _ => {}
+        }
+    }
+    match (child.property.display(), child.property.float()) {
+        (Display::Block, style::FloatType::None)
+        | (Display::Flex, style::FloatType::None)
+        | (Display::Grid, style::FloatType::None) => {
+            root.children.push(child);
+        }
+        (Display::Inline, style::FloatType::None)
+        | (Display::InlineBlock, style::FloatType::None) => {
+            root.get_inline_container().children.push(child);
+        }
+        (_, style::FloatType::Left) | (_, style::FloatType::Right) => {
+            root.children.push(child);
+        }
+        (Display::None, _) => {} // Don't lay out nodes with `display: none;`
+    }
+}
 
-        match (child.property.display(), child.property.float()) {
-            (Display::Block, style::FloatType::None) => {
-                root.children.push(child);
+/// The style of a text in an element with ``parent_specified_values``
+fn text_style(parent_specified_values: &Style) -> Style {
+    let inherited_property = inherit_peoperties(parent_specified_values, &INHERITED_PROPERTIES);
+    Style::new_with(
+        if let Some(display) = parent_specified_values.property.get("display") {
+            match display[0] {
+                // If the parent element is an inline element, inherites the parent's properties.
+                Value::Keyword(ref k) if k == "inline" => parent_specified_values.clone(),
+                _ => inherited_property,
             }
-            (Display::Inline, style::FloatType::None)
-            | (Display::InlineBlock, style::FloatType::None) => {
-                root.get_inline_container().children.push(child);
+        } else {
+            inherited_property
+        }.property
+            .into_iter()
+            .filter(|&(ref name, _)| name != "float")
+            .collect(),
+    )
+}
+
+/// The box of a pseudo-element of ``elem`` with ``specified_values`` (``::before`` or
+/// ``::after``), containing the text of its ``content``. None if it has no content (or is not
+/// displayed). It is not in the DOM, so it has no node id.
+fn generated_box(
+    elem: &ElementData,
+    pseudo_element: &str,
+    specified_values: &Style,
+    counters: &mut Counters,
+) -> Option<LayoutBox> {
+    let display = specified_values.display();
+    if display == Display::None {
+        return None;
+    }
+    counters.update(specified_values);
+    let content = generated::content(specified_values, elem, counters)?;
+
+    let text_style = text_style(specified_values);
+    let content = text_style.transform_text(&content);
+    let text = LayoutBox::new(
+        BoxType::TextNode(Text {
+            font: Font::new_empty(),
+            range: 0..content.len(),
+            hyphenated: false,
+        }),
+        Node::text(content),
+        text_style,
+        LayoutInfo::Text,
+    );
+    let mut generated = LayoutBox::new(
+        match (display, specified_values.float()) {
+            (_, style::FloatType::Left) | (_, style::FloatType::Right) => BoxType::Float,
+            (Display::Block, _) | (Display::Flex, _) | (Display::Grid, _) => BoxType::BlockNode,
+            (Display::InlineBlock, _) => BoxType::InlineBlockNode,
+            _ => BoxType::InlineNode,
+        },
+        Node::elem(format!("::{}", pseudo_element), HashMap::new(), vec![]),
+        specified_values.clone(),
+        LayoutInfo::Generic,
+    );
+    generated.get_inline_container().children.push(text);
+    Some(generated)
+}
+
+/// Puts the first letter of the first line in ``root`` (and the punctuation around it) into a
+/// box of ``::first-letter`` with ``specified_values``. A floated one is taken out of the line.
+fn split_first_letter(root: &mut LayoutBox, specified_values: Style) {
+    // The inline boxes including the first text, and where it is
+    fn first_text(layout_box: &mut LayoutBox) -> Option<(&mut Vec<LayoutBox>, usize)> {
+        let position = layout_box.children.iter().position(|child| match child.box_type {
+            BoxType::TextNode(ref text) => !text.range.is_empty(),
+            BoxType::InlineNode => match child.info {
+                LayoutInfo::Generic | LayoutInfo::Anker => !child.children.is_empty(),
+                _ => true,
+            },
+            _ => true,
+        })?;
+        match (&layout_box.children[position].box_type, &layout_box.children[position].info) {
+            (&BoxType::TextNode(_), _) => Some((&mut layout_box.children, position)),
+            (&BoxType::InlineNode, &LayoutInfo::Generic)
+            | (&BoxType::InlineNode, &LayoutInfo::Anker) => {
+                first_text(&mut layout_box.children[position])
             }
-            (_, style::FloatType::Left) | (_, style::FloatType::Right) => {
-                root.children.push(child);
+            // The first line starts with an atomic box.
+            _ => None,
+        }
+    }
+
+    let float = specified_values.float();
+    let mut first_letter = LayoutBox::new(
+        match float {
+            style::FloatType::None => BoxType::InlineNode,
+            _ => BoxType::Float,
+        },
+        Node::elem("::first-letter".to_string(), HashMap::new(), vec![]),
+        specified_values,
+        LayoutInfo::Generic,
+    );
+    {
+        let (boxes, position) = match root.children.first_mut() {
+            Some(child) if child.box_type == BoxType::AnonymousBlock => match first_text(child) {
+                Some(text) => text,
+                None => return,
+            },
+            _ => return,
+        };
+        let text = boxes[position].clone();
+        let (range, letter, letter_text) = match (&text.box_type, &text.node.data) {
+            (&BoxType::TextNode(ref text), &NodeType::Text(ref s)) => {
+                let letter = generated::first_letter(&s[text.range.clone()]);
+                let letter = text.range.start + letter.start..text.range.start + letter.end;
+                let letter_text = s[letter.clone()].to_string();
+                (text.range.clone(), letter, letter_text)
             }
-            (Display::None, _) => {} // Don't lay out nodes with `display: none;`
+            _ => return,
+        };
+        let with_range = |range: Range<usize>, property: Style| {
+            let mut text = text.clone();
+            text.property = property;
+            text.set_text_info(Font::new_empty(), range, false);
+            text
+        };
+
+        // Transformed like the first line
+        let text_style = text_style(&first_letter.property);
+        let letter_text = text_style.transform_text(&letter_text);
+        let mut letter_box = with_range(0..letter_text.len(), text_style);
+        letter_box.node = Node::text(letter_text);
+        letter_box.node_id = None;
+        first_letter.get_inline_container().children.push(letter_box);
+        let mut split = vec![];
+        if range.start < letter.start {
+            split.push(with_range(range.start..letter.start, text.property.clone()));
+        }
+        if float == style::FloatType::None {
+            split.push(first_letter.clone());
+        }
+        if letter.end < range.end {
+            split.push(with_range(letter.end..range.end, text.property.clone()));
         }
+        boxes.splice(position..position + 1, split);
     }
+    if float != style::FloatType::None {
+        root.children.insert(0, first_letter);
+    }
+}
 
-    root
+/// The properties of ``pseudo_element`` (``first-line`` or ``first-letter``) of ``elem``,
+/// declared by the rules for it (those which apply to it). None if there are not any.
+fn pseudo_element_style(
+    elem: &ElementData,
+    pseudo_element: &str,
+    default_style: &Stylesheet,
+    stylesheet: &Stylesheet,
+    inherited_property: &Style,
+    appeared_elements: &Vec<SimpleSelector>,
+) -> Option<Style> {
+    let rules = cascaded_rules(
+        elem,
+        Some(pseudo_element),
+        default_style,
+        stylesheet,
+        appeared_elements,
+    );
+    let declared: HashSet<&str> = rules
+        .iter()
+        .flat_map(|&(_, (_, rule))| rule.declarations.iter())
+        .map(|declaration| declaration.name.as_str())
+        .filter(|name| generated::applies_to(pseudo_element, name))
+        .collect();
+    if declared.is_empty() {
+        return None;
+    }
+    let mut style = specified_values(
+        elem,
+        Some(pseudo_element),
+        default_style,
+        stylesheet,
+        inherited_property,
+        appeared_elements,
+    );
+    style.property.retain(|name, _| declared.contains(name.as_str()));
+    Some(style)
 }
 
-fn inherit_peoperties(specified_values: &Style, property_list: Vec<&str>) -> Style {
+fn inherit_peoperties(specified_values: &Style, property_list: &[&str]) -> Style {
     let mut inherited_property = HashMap::new();
     let specified_values = &specified_values.property;
     for property in property_list {
-        if let Some(value) = specified_values.get(property) {
+        if let Some(value) = specified_values.get(*property) {
             inherited_property.insert(property.to_string(), value.clone());
         }
     }
     Style::new_with(inherited_property)
 }
 
+/// The style of ``elem``, or of its pseudo-element ``pseudo_element`` (e.g. ``before``, which
+/// inherits ``inherited_property`` from the element).
 fn specified_values(
     elem: &ElementData,
+    pseudo_element: Option<&str>,
     default_style: &Stylesheet,
     stylesheet: &Stylesheet,
     inherited_property: &Style,
@@ -296,9 +723,6 @@ fn specified_values(
 ) -> Style {
     let mut values = HashMap::with_capacity(16);
 
-    let mut rules = matching_rules(elem, &default_style, appeared_elements);
-    rules.append(&mut matching_rules(elem, stylesheet, appeared_elements));
-
     // Insert inherited properties
     inherited_property
         .property
@@ -307,28 +731,134 @@ fn specified_values(
             values.insert(name.clone(), value.clone());
         });
 
-    // Go through the rules from lowest to highest specificity.
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
-    rules.iter().for_each(|&(_, rule)| {
-        rule.declarations.iter().for_each(|declaration| {
-            values.insert(declaration.name.clone(), declaration.values.clone());
-        })
+    // The attributes mapped to properties win over the default style, but not over the rules
+    // of the page.
+    let hints = if pseudo_element.is_none() {
+        presentational_hints(elem)
+    } else {
+        vec![]
+    };
+    for Declaration { name, values: vals } in hints.iter().cloned() {
+        values.insert(name, vals);
+    }
+    let hinted = |name: &str| hints.iter().any(|declaration| declaration.name == name);
+
+    let rules = cascaded_rules(elem, pseudo_element, default_style, stylesheet, appeared_elements);
+    rules.iter().for_each(|&(origin, (_, rule))| {
+        rule.declarations
+            .iter()
+            .filter(|declaration| origin == Origin::Author || !hinted(&declaration.name))
+            .for_each(|declaration| {
+                values.insert(declaration.name.clone(), declaration.values.clone());
+            })
     });
 
-    if let Some(attr_style) = elem.attrs.get("style") {
+    if let Some(attr_style) = elem.attrs.get("style").filter(|_| pseudo_element.is_none()) {
         let decls = parse_attr_style(attr_style.clone());
         for Declaration { name, values: vals } in decls {
             values.insert(name, vals);
         }
     }
 
-    Style::new_with(values)
+    // Inherited as px, so that the children resolve theirs against it.
+    if let Some(font_size) = values.get("font-size").map(|x| x[0].clone()) {
+        let parent_font_size = inherited_property.clone().font_size().to_f64_px();
+        values.insert(
+            "font-size".to_string(),
+            vec![
+                Value::Length(
+                    style::computed_font_size(&font_size, parent_font_size),
+                    Unit::Px,
+                ),
+            ],
+        );
+    }
+
+    // System colors (e.g. ``canvastext``) are those of the color scheme of the element, and the
+    // children inherit them as they are.
+    let mut style = Style::new_with(values);
+    let color_scheme = style.color_scheme();
+    for values in style.property.values_mut() {
+        for value in values.iter_mut() {
+            let color = match *value {
+                Value::Keyword(ref k) => color_scheme.system_color(k),
+                _ => None,
+            };
+            if let Some(color) = color {
+                *value = Value::Color(color);
+            }
+        }
+    }
+    style
 }
 
-type MatchedRule<'a> = (Specificity, &'a Rule);
+pub type MatchedRule<'a> = (Specificity, &'a Rule);
+
+/// The properties the attributes of ``elem`` are mapped to: ``hidden`` to ``display: none``,
+/// ``dir`` to ``direction``, and ``lang`` to the language of the text (``LANG_PROPERTY``).
+/// ref. https://html.spec.whatwg.org/multipage/rendering.html#presentational-hints
+pub fn presentational_hints(elem: &ElementData) -> Vec<Declaration> {
+    let declaration = |name: &str, value: &str| Declaration {
+        name: name.to_string(),
+        values: vec![Value::Keyword(value.to_string())],
+    };
+    let mut hints = vec![];
+    if elem.attrs.contains_key("hidden") {
+        hints.push(declaration("display", "none"));
+    }
+    match elem.attrs.get("dir").map(|dir| dir.trim().to_lowercase()) {
+        Some(ref dir) if dir == "ltr" || dir == "rtl" => hints.push(declaration("direction", dir)),
+        _ => {}
+    }
+    if let Some(lang) = elem.attrs.get("lang") {
+        hints.push(declaration(LANG_PROPERTY, lang.trim()));
+    }
+    hints
+}
+
+/// Where a rule comes from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Origin {
+    UserAgent,
+    Author,
+}
+
+/// The rules matching ``elem`` (or its pseudo-element ``pseudo_element``) from lowest to highest
+/// specificity (the user agent's first if equal), in the order the cascade applies them.
+/// ``appeared_elements`` are the ancestors.
+pub fn cascaded_rules<'a>(
+    elem: &ElementData,
+    pseudo_element: Option<&str>,
+    default_style: &'a Stylesheet,
+    stylesheet: &'a Stylesheet,
+    appeared_elements: &Vec<SimpleSelector>,
+) -> Vec<(Origin, MatchedRule<'a>)> {
+    let mut rules: Vec<_> = matching_rules(elem, pseudo_element, default_style, appeared_elements)
+        .into_iter()
+        .map(|rule| (Origin::UserAgent, rule))
+        .collect();
+    rules.extend(
+        matching_rules(elem, pseudo_element, stylesheet, appeared_elements)
+            .into_iter()
+            .map(|rule| (Origin::Author, rule)),
+    );
+    rules.sort_by(|&(_, (a, _)), &(_, (b, _))| a.cmp(&b));
+    rules
+}
+
+/// What the descendants of ``elem`` match the ancestor in a combinator against.
+pub fn ancestor_selector(elem: &ElementData) -> SimpleSelector {
+    SimpleSelector {
+        tag_name: Some(elem.tag_name.clone()),
+        id: elem.id().and_then(|id| Some(id.clone())),
+        class: elem.classes().iter().map(|x| x.to_string()).collect(),
+        pseudo_element: None,
+    }
+}
 
 fn matching_rules<'a>(
     elem: &ElementData,
+    pseudo_element: Option<&str>,
     stylesheet: &'a Stylesheet,
     appeared_elements: &Vec<SimpleSelector>,
 ) -> Vec<MatchedRule<'a>> {
@@ -338,23 +868,39 @@ fn matching_rules<'a>(
     stylesheet
         .rules
         .iter()
-        .filter_map(|rule| match_rule(elem, rule, appeared_elements))
+        .filter(|rule| rule.applies())
+        .filter_map(|rule| match_rule(elem, pseudo_element, rule, appeared_elements))
         .collect()
 }
 
 fn match_rule<'a>(
     elem: &ElementData,
+    pseudo_element: Option<&str>,
     rule: &'a Rule,
     appeared_elements: &Vec<SimpleSelector>,
 ) -> Option<MatchedRule<'a>> {
     // Find the first (most specific) matching selector.
     rule.selectors
         .iter()
-        .find(|selector| matches(elem, *selector, appeared_elements))
+        .find(|selector| {
+            selector.pseudo_element() == pseudo_element
+                && matches_element(elem, *selector, appeared_elements)
+        })
         .map(|selector| (selector.specificity(), rule))
 }
 
-fn matches(
+/// Whether ``elem``, under the ancestors ``appeared_elements`` (``ancestor_selector``s, the
+/// parent last), matches ``selector``.
+pub fn matches(
+    elem: &ElementData,
+    selector: &Selector,
+    appeared_elements: &Vec<SimpleSelector>,
+) -> bool {
+    selector.pseudo_element().is_none() && matches_element(elem, selector, appeared_elements)
+}
+
+/// Like ``matches``, ignoring the pseudo-element of ``selector``
+fn matches_element(
     elem: &ElementData,
     selector: &Selector,
     appeared_elements: &Vec<SimpleSelector>,
@@ -380,7 +926,7 @@ fn matches_descendant_combinator(
         !((simple.tag_name.is_some() && e.tag_name != simple.tag_name)
             || (simple.id.is_some() && e.id != simple.id)
             || (!simple.class.iter().all(|class| e.class.contains(class))))
-    }) && matches(elem, selector_b, appeared_elements)
+    }) && matches_element(elem, selector_b, appeared_elements)
 }
 
 fn matches_child_combinator(
@@ -396,7 +942,7 @@ fn matches_child_combinator(
                 .class
                 .iter()
                 .all(|class| last_elem.class.contains(class))))
-            && matches(elem, selector_b, appeared_elements)
+            && matches_element(elem, selector_b, appeared_elements)
     } else {
         false
     }
@@ -432,75 +978,724 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
     true
 }
 
-use std::cell::RefCell;
-thread_local!(pub static LAYOUTBOX: RefCell<Option<LayoutBox>> = { RefCell::new(None) };);
+use std::cell::{Cell, RefCell};
+thread_local!(
+    pub static LAYOUTBOX: RefCell<Option<LayoutBox>> = { RefCell::new(None) };
+    /// The tree last laid out from ``LAYOUTBOX``, which is painted and hit-tested (see
+    /// ``laid_out``). It is painted again as it is until the boxes or the viewport change.
+    static LAID_OUT: RefCell<Option<LayoutBox>> = { RefCell::new(None) };
+    /// The viewport ``LAID_OUT`` was laid out in, None once it is to be laid out again (see
+    /// ``invalidate_layout``)
+    static LAID_OUT_VIEWPORT: Cell<Option<Rect>> = { Cell::new(None) };
+    /// The viewport ``LAID_OUT`` was laid out in, and the paths (child indices from the root) of
+    /// the boxes of ``LAYOUTBOX`` laid out otherwise since, which are laid out again alone in it
+    /// (see ``relayout_changed``). None if the whole tree is laid out again.
+    static CHANGED: RefCell<Option<(Rect, Vec<Vec<usize>>)>> = { RefCell::new(None) };
+);
+
+/// The properties that change how a box is painted, but not how the boxes are laid out
+const PAINT_PROPERTIES: [&str; 30] = [
+    "color",
+    "background",
+    "background-color",
+    "background-image",
+    "background-position",
+    "background-repeat",
+    "background-attachment",
+    "border-color",
+    "border-top-color",
+    "border-right-color",
+    "border-bottom-color",
+    "border-left-color",
+    "border-image",
+    "outline",
+    "outline-color",
+    "outline-style",
+    "outline-width",
+    "outline-offset",
+    "box-shadow",
+    "text-shadow",
+    "text-decoration",
+    "text-decoration-color",
+    "opacity",
+    "visibility",
+    "filter",
+    "clip-path",
+    "cursor",
+    "pointer-events",
+    "caret-color",
+    "scrollbar-color",
+];
+
+/// How much of the boxes built before ``update_tree`` keeps. The later ones keep less.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TreeUpdate {
+    /// The same boxes with the same styles
+    Unchanged,
+    /// The same boxes with styles that only paint them otherwise (see ``PAINT_PROPERTIES``), so
+    /// the tree laid out before is painted with them
+    Repaint,
+    /// The same boxes with styles (or nodes) that lay them out otherwise
+    Relayout,
+    /// Some boxes were built again
+    Rebuilt,
+}
+
+/// Makes the next ``layout_tree`` lay the boxes out again, e.g. when what they are laid out
+/// with (but the viewport) changes. The tree laid out before is kept until then.
+pub fn invalidate_layout() {
+    LAID_OUT_VIEWPORT.with(|v| v.set(None));
+    CHANGED.with(|c| *c.borrow_mut() = None);
+}
+
+/// Makes the next ``layout_tree`` lay the boxes at ``paths`` (in ``LAYOUTBOX``) out again, and
+/// only them if it can (see ``relayout_changed``).
+fn invalidate_boxes(paths: Vec<Vec<usize>>) {
+    let viewport = LAID_OUT_VIEWPORT.with(|v| v.take());
+    CHANGED.with(|c| {
+        let mut changed = c.borrow_mut();
+        match viewport {
+            Some(viewport) => *changed = Some((viewport, paths)),
+            // Laid out again already
+            None => if let Some((_, ref mut changed)) = *changed {
+                changed.extend(paths);
+            },
+        }
+    });
+}
 
-/// Transform a style tree into a layout tree.
+/// Transform a style tree into a layout tree. Returns a copy of the tree retained by ``lay_out``.
 pub fn layout_tree(
     root: &Node,
     stylesheet: &Stylesheet,
-    mut containing_block: Dimensions,
+    containing_block: Dimensions,
 ) -> LayoutBox {
+    lay_out(root, stylesheet, containing_block);
+    laid_out(|root_box| root_box.clone()).unwrap()
+}
+
+/// Calls ``f`` with the tree laid out last (see ``lay_out``), None if there is none.
+pub fn laid_out<T, F>(f: F) -> Option<T>
+where
+    F: FnOnce(&mut LayoutBox) -> T,
+{
+    LAID_OUT.with(|l| l.borrow_mut().as_mut().map(f))
+}
+
+/// Keeps ``root_box``, laid out on another thread (see ``layout_worker``), as the tree laid out
+/// last. The boxes are built and laid out again here the next time.
+pub fn keep_laid_out(root_box: LayoutBox) {
+    LAID_OUT.with(|l| *l.borrow_mut() = Some(root_box));
+    invalidate_layout();
+}
+
+/// Takes the tree laid out last, e.g. to hand it to another thread.
+pub fn take_laid_out() -> Option<LayoutBox> {
+    invalidate_layout();
+    LAID_OUT.with(|l| l.borrow_mut().take())
+}
+
+/// Drops the tree laid out last, and the boxes it was laid out from (e.g. when another page is
+/// loaded).
+pub fn discard_layout() {
+    LAYOUTBOX.with(|lb| *lb.borrow_mut() = None);
+    take_laid_out();
+}
+
+/// Lays out the DOM ``root`` styled with ``stylesheet`` in ``containing_block`` (the viewport),
+/// unless the tree laid out last is laid out so. The tree is retained, and borrowed with
+/// ``laid_out``.
+pub fn lay_out(root: &Node, stylesheet: &Stylesheet, mut containing_block: Dimensions) {
+    // The rules for other widths apply.
+    if media::set_viewport_width(containing_block.content.width.to_f64_px())
+        && stylesheet.rules.iter().any(|rule| !rule.media.is_empty())
+    {
+        LAYOUTBOX.with(|lb| *lb.borrow_mut() = None);
+    }
+    if LAID_OUT_VIEWPORT.with(|v| v.get()) == Some(containing_block.content)
+        && LAYOUTBOX.with(|lb| lb.borrow().is_some())
+    {
+        return;
+    }
+    // Only the boxes changed, and their ancestors whose sizes change
+    let changed = CHANGED.with(|c| c.borrow_mut().take());
+    if let Some((viewport, paths)) = changed {
+        if viewport == containing_block.content
+            && stats::time(Phase::Layout, || relayout_changed(paths, containing_block))
+        {
+            LAID_OUT_VIEWPORT.with(|v| v.set(Some(viewport)));
+            return;
+        }
+    }
+
+    // Laid out from a copy of the boxes built: the lines replace the children of the anonymous
+    // blocks.
     let mut first_construction_of_layout_tree = false;
     let mut root_box = LAYOUTBOX.with(|layoutbox| {
         layoutbox
             .borrow_mut()
             .get_or_insert_with(|| {
                 first_construction_of_layout_tree = true;
-                // let mut id = 0;
-                let default_style = default_style::default_style();
-                build_layout_tree(
-                    root,
-                    &stylesheet,
-                    &default_style,
-                    &style::Style::new(),
-                    &style::Style::new(),
-                    &vec![],
-                    // &mut id,
-                )
+                stats::time(Phase::Style, || {
+                    let default_style = default_style::default_style();
+                    build_layout_tree(
+                        root,
+                        &stylesheet,
+                        &default_style,
+                        &style::Style::new(),
+                        &vec![],
+                        &mut Counters::new(),
+                        &mut 0,
+                    )
+                })
             })
             .clone()
     });
 
-    // Save the initial containing block height for calculating percent heights.
+    // Save the initial containing block (the viewport) height for calculating percent heights.
     let saved_block = containing_block;
+    let viewport_rect = containing_block.content;
     let viewport = containing_block;
     // The layout algorithm expects the container height to start at 0.
     containing_block.content.height = Au::from_f64_px(0.0);
 
-    root_box.layout(
-        &mut Floats::new(),
-        Au(0),
-        containing_block,
-        saved_block,
-        viewport,
-    );
+    stats::time(Phase::Layout, || {
+        root_box.layout(
+            &mut Floats::new(),
+            Au(0),
+            containing_block,
+            saved_block,
+            viewport,
+        );
+        // Laid out again from the boxes built, until the ``content-visibility: auto`` boxes
+        // whose contents are laid out, and the lazy images loaded, are those near the part of
+        // the document shown
+        let mut passes = 1;
+        while passes < containment::MAX_LAYOUT_PASSES
+            && (containment::update(&root_box) | lazy::update(&root_box))
+        {
+            root_box = LAYOUTBOX.with(|layoutbox| layoutbox.borrow().clone().unwrap());
+            root_box.layout(
+                &mut Floats::new(),
+                Au(0),
+                containing_block,
+                saved_block,
+                viewport,
+            );
+            passes += 1;
+        }
+    });
 
     if first_construction_of_layout_tree {
+        // The boxes built get the styles computed while laying them out, by their nodes.
+        let mut styles = HashMap::new();
+        collect_styles(&root_box, &mut styles);
         LAYOUTBOX.with(|layoutbox| {
             if let Some(ref mut layoutbox) = *layoutbox.borrow_mut() {
-                assign_style_properties(&root_box, layoutbox);
-                fn assign_style_properties(root_box: &LayoutBox, layoutbox: &mut LayoutBox) {
-                    if root_box.box_type != BoxType::AnonymousBlock {
-                        layoutbox.property = root_box.property.clone();
-                        for (child, layoutbox_child) in
-                            root_box.children.iter().zip(&mut layoutbox.children)
-                        {
-                            assign_style_properties(child, layoutbox_child);
-                        }
-                    }
-                }
+                assign_styles(layoutbox, &styles);
             }
         });
     }
 
-    root_box
+    LAID_OUT.with(|l| *l.borrow_mut() = Some(root_box));
+    LAID_OUT_VIEWPORT.with(|v| v.set(Some(viewport_rect)));
+}
+
+/// Lays out again the boxes of ``LAYOUTBOX`` at ``paths`` in the tree laid out last, each in
+/// what it was laid out in, and then the ancestors whose margin boxes change. Returns whether it
+/// could: the boxes are blocks in normal flow (or in them), not next to floats, and the root does
+/// not change size. Otherwise, the whole tree is to be laid out again.
+fn relayout_changed(mut paths: Vec<Vec<usize>>, viewport: Dimensions) -> bool {
+    // Those in the boxes laid out again with others are laid out with them.
+    paths.sort();
+    paths.dedup_by(|path, ancestor| path.starts_with(ancestor));
+
+    let relaid = LAYOUTBOX.with(|layoutbox| {
+        let layoutbox = layoutbox.borrow();
+        let built = match *layoutbox {
+            Some(ref built) => built,
+            None => return false,
+        };
+        LAID_OUT.with(|l| {
+            let mut laid_out = l.borrow_mut();
+            let laid_out = match *laid_out {
+                Some(ref mut laid_out) => laid_out,
+                None => return false,
+            };
+            paths
+                .into_iter()
+                .all(|path| relayout_box(built, laid_out, path, viewport))
+        })
+    });
+    // Where the ``content-visibility: auto`` boxes and the lazy images are may change with them.
+    relaid && laid_out(|root| !(containment::update(root) | lazy::update(root))).unwrap_or(false)
+}
+
+/// Lays out again the box of ``built`` at ``path`` in ``laid_out`` (see ``relayout_changed``), or
+/// the nearest ancestor that can be laid out alone and keeps its margin box.
+fn relayout_box(
+    built: &LayoutBox,
+    laid_out: &mut LayoutBox,
+    mut path: Vec<usize>,
+    viewport: Dimensions,
+) -> bool {
+    loop {
+        // The root is as large as the whole tree, or in the viewport.
+        if path.is_empty() {
+            return false;
+        }
+        let built_box = match box_at(built, &path) {
+            Some(built_box) => built_box,
+            None => return false,
+        };
+        // Laid out by the lines of its anonymous block, or by its ancestors (e.g. the flex or grid
+        // container it is an item of)
+        let item = box_at(built, &path[..path.len() - 1])
+            .is_some_and(|parent| parent.property.display().is_flex_or_grid());
+        if built_box.box_type != BoxType::BlockNode || built_box.node_id.is_none() || item {
+            path.pop();
+            continue;
+        }
+        let laid_out_box = match laid_out_box_at(laid_out, built, &path) {
+            Some(laid_out_box) => laid_out_box,
+            // In a line
+            None => return false,
+        };
+        let mut input = match laid_out_box.laid_out_in {
+            Some(ref input) => input.clone(),
+            None => return false,
+        };
+
+        let mut layout_box = built_box.clone();
+        layout_box.layout(
+            &mut input.floats,
+            input.last_margin_bottom,
+            input.containing_block,
+            input.saved_block,
+            viewport,
+        );
+        let (old, new) = (laid_out_box.dimensions, layout_box.dimensions);
+        let same_size =
+            old.margin_box() == new.margin_box() && old.margin.bottom == new.margin.bottom;
+        if same_size {
+            *laid_out_box = layout_box;
+            return true;
+        }
+        path.pop();
+    }
+}
+
+/// The box laid out in ``laid_out`` from the box at ``path`` in ``built``, whose ancestors are
+/// laid out from those of ``built`` where they are (not in lines) and get their nodes. None if it
+/// is in a line.
+fn laid_out_box_at<'a>(
+    laid_out: &'a mut LayoutBox,
+    built: &LayoutBox,
+    path: &[usize],
+) -> Option<&'a mut LayoutBox> {
+    if laid_out.box_type != built.box_type || laid_out.node_id != built.node_id {
+        return None;
+    }
+    let (&i, rest) = match path.split_first() {
+        Some(first) => first,
+        None => return Some(laid_out),
+    };
+    // The nodes of the ancestors of a subtree changed are changed too.
+    laid_out.node = built.node.clone();
+    let (laid_out_child, built_child) = (laid_out.children.get_mut(i)?, built.children.get(i)?);
+    laid_out_box_at(laid_out_child, built_child, rest)
+}
+
+/// Restyles the boxes cached by ``layout_tree`` with ``stylesheet`` (see ``update_tree``), so
+/// that they are not built again if the styles don't change them. Returns whether they were
+/// kept. Nothing happens if no boxes are cached.
+pub fn restyle(stylesheet: &Stylesheet) -> bool {
+    let update = update_boxes(|layoutbox, changed| {
+        let root = layoutbox.node.clone();
+        update_tree(layoutbox, &root, stylesheet, changed)
+    });
+    update.is_some_and(|update| update != TreeUpdate::Rebuilt)
+}
+
+/// Updates the boxes cached by ``layout_tree`` for the DOM ``root`` (e.g. after scripts changed
+/// it) styled with ``stylesheet`` (see ``update_subtrees``). The tree laid out from them is kept,
+/// or restyled, if they are laid out as they were. Nothing happens if no boxes are cached.
+pub fn update(root: &Node, stylesheet: &Stylesheet) -> TreeUpdate {
+    update_boxes(|layoutbox, changed| update_subtrees(layoutbox, root, stylesheet, changed))
+        .unwrap_or(TreeUpdate::Unchanged)
+}
+
+/// Updates the boxes cached by ``layout_tree`` with ``f``, and the tree laid out from them as
+/// ``f`` returns: the boxes it adds the paths of to its second argument are laid out again. None
+/// if no boxes are cached.
+fn update_boxes<F>(f: F) -> Option<TreeUpdate>
+where
+    F: FnOnce(&mut LayoutBox, &mut Vec<Vec<usize>>) -> TreeUpdate,
+{
+    let mut changed = vec![];
+    let update = LAYOUTBOX.with(|layoutbox| {
+        let mut layoutbox = layoutbox.borrow_mut();
+        let layoutbox = layoutbox.as_mut()?;
+        let update = f(layoutbox, &mut changed);
+        // The boxes not laid out again are painted with their new styles too.
+        if update >= TreeUpdate::Repaint {
+            let mut styles = HashMap::new();
+            collect_styles(layoutbox, &mut styles);
+            laid_out(|laid_out| assign_styles(laid_out, &styles));
+        }
+        Some(update)
+    });
+    if update >= Some(TreeUpdate::Relayout) {
+        invalidate_boxes(changed);
+    }
+    update
+}
+
+/// The styles of the boxes of the tree, by the ids of their nodes
+fn collect_styles(layout_box: &LayoutBox, styles: &mut HashMap<usize, Style>) {
+    if let Some(id) = layout_box.node_id {
+        styles.insert(id, layout_box.property.clone());
+    }
+    for child in &layout_box.children {
+        collect_styles(child, styles);
+    }
+}
+
+/// Gives the boxes of the laid out tree the ``styles`` of their nodes.
+fn assign_styles(layout_box: &mut LayoutBox, styles: &HashMap<usize, Style>) {
+    if let Some(style) = layout_box.node_id.and_then(|id| styles.get(&id)) {
+        layout_box.property = style.clone();
+    }
+    for child in &mut layout_box.children {
+        assign_styles(child, styles);
+    }
+}
+
+/// Builds the boxes of the DOM ``root`` styled with ``stylesheet`` again, and updates the tree
+/// ``boxes`` built before (not laid out) with them: the boxes of the same types and nodes, with
+/// the same children, get the new styles, and the others are replaced with their descendants.
+/// Returns how much it kept, and adds to ``changed`` the paths (child indices from the root) of
+/// the boxes laid out otherwise.
+pub fn update_tree(
+    boxes: &mut LayoutBox,
+    root: &Node,
+    stylesheet: &Stylesheet,
+    changed: &mut Vec<Vec<usize>>,
+) -> TreeUpdate {
+    let built = stats::time(Phase::Style, || {
+        build_layout_tree(
+            root,
+            stylesheet,
+            &default_style::default_style(),
+            &style::Style::new(),
+            &vec![],
+            &mut Counters::new(),
+            &mut 0,
+        )
+    });
+    update_box(boxes, built, &mut vec![], changed)
+}
+
+/// Updates ``layout_box`` (at ``path``) with the boxes ``built`` again for its node (see
+/// ``update_tree``).
+fn update_box(
+    layout_box: &mut LayoutBox,
+    built: LayoutBox,
+    path: &mut Vec<usize>,
+    changed: &mut Vec<Vec<usize>>,
+) -> TreeUpdate {
+    // Texts are compared as they are (e.g. transformed by ``text-transform``) by their nodes.
+    let same_box = layout_box.box_type == built.box_type
+        && layout_box.node_id == built.node_id
+        && layout_box.children.len() == built.children.len();
+    if !same_box {
+        *layout_box = built;
+        changed.push(path.clone());
+        return TreeUpdate::Rebuilt;
+    }
+    let same_first_line = match (&layout_box.first_line, &built.first_line) {
+        (&Some(ref a), &Some(ref b)) => a.property == b.property,
+        (&None, &None) => true,
+        _ => false,
+    };
+    let changed_properties: Vec<&String> = built
+        .property
+        .property
+        .iter()
+        .filter(|&(name, values)| layout_box.property.property.get(name) != Some(values))
+        .map(|(name, _)| name)
+        .chain(
+            layout_box
+                .property
+                .property
+                .keys()
+                .filter(|name| !built.property.property.contains_key(*name)),
+        )
+        .collect();
+    // Replaced elements are painted from their subtrees (e.g. SVG).
+    let mut update = if layout_box.node.data != built.node.data
+        || (layout_box.is_replaced() && layout_box.node != built.node)
+        || !same_first_line
+        || (!changed_properties.is_empty() && layout_box.node_id.is_none())
+        || changed_properties.iter().any(|name| !PAINT_PROPERTIES.contains(&name.as_str()))
+    {
+        TreeUpdate::Relayout
+    } else if !changed_properties.is_empty() {
+        TreeUpdate::Repaint
+    } else {
+        TreeUpdate::Unchanged
+    };
+    if update == TreeUpdate::Relayout {
+        changed.push(path.clone());
+    }
+    layout_box.node = built.node;
+    layout_box.property = built.property;
+    layout_box.first_line = built.first_line;
+    layout_box.info = built.info;
+    let children = layout_box.children.iter_mut().zip(built.children);
+    for (i, (child, built_child)) in children.enumerate() {
+        path.push(i);
+        update = max(update, update_box(child, built_child, path, changed));
+        path.pop();
+    }
+    update
+}
+
+/// Updates the tree ``boxes`` built before (not laid out) for the DOM ``root`` styled with
+/// ``stylesheet``, building again only the boxes of the subtrees changed from the DOM they were
+/// built for (see ``changed_subtrees``), in the styles of their parents. The styles are assumed
+/// to be the same otherwise: the whole tree is built again (see ``update_tree``) if the DOM is the
+/// same, or if its boxes depend on those before them (see ``depends_on_preceding``). Returns how
+/// much it kept, and adds to ``changed`` the paths of the boxes laid out otherwise (see
+/// ``update_tree``).
+pub fn update_subtrees(
+    boxes: &mut LayoutBox,
+    root: &Node,
+    stylesheet: &Stylesheet,
+    changed_boxes: &mut Vec<Vec<usize>>,
+) -> TreeUpdate {
+    if boxes.node == *root {
+        // The same DOM styled otherwise
+        return update_tree(boxes, root, stylesheet, changed_boxes);
+    }
+    let mut changed = vec![];
+    if boxes.node.data != root.data || depends_on_preceding(boxes) {
+        changed.push(vec![]);
+    } else {
+        changed_subtrees(&boxes.node, root, &mut vec![], &mut changed);
+    }
+
+    // All built before any replaces the boxes before, whose nodes are found by their old ids
+    let default_style = default_style::default_style();
+    let mut built = vec![];
+    while built.len() < changed.len() {
+        let i = built.len();
+        match rebuild_subtree(boxes, root, &changed[i], stylesheet, &default_style) {
+            Some(subtree) => built.push(subtree),
+            // Built with its parent, and the siblings
+            None => {
+                let parent = match changed[i].split_last() {
+                    Some((_, parent)) => parent.to_vec(),
+                    None => return update_tree(boxes, root, stylesheet, changed_boxes),
+                };
+                let first = changed.iter().position(|path| path.starts_with(&parent)).unwrap();
+                changed.retain(|path| !path.starts_with(&parent));
+                changed.insert(first, parent);
+                built.truncate(first);
+            }
+        }
+    }
+
+    let mut update = TreeUpdate::Unchanged;
+    for (path, subtree) in changed.iter().zip(built) {
+        // The ids of the nodes before are the new ones already.
+        let id = root.find_id_by_path(path).unwrap();
+        let old_count = node_at(&boxes.node, path).count_nodes();
+        let new_count = node_at(root, path).count_nodes();
+        shift_ids(boxes, id + old_count, new_count as isize - old_count as isize);
+        // The boxes laid out after it are of other nodes now.
+        if new_count != old_count {
+            changed_boxes.push(vec![]);
+        }
+        let mut box_path = find_path(boxes, id).unwrap();
+        let layout_box = box_at_mut(boxes, &box_path).unwrap();
+        update = max(update, update_box(layout_box, subtree, &mut box_path, changed_boxes));
+    }
+
+    // The ancestors of the boxes built again have the new nodes too.
+    let mut ancestors = HashMap::new();
+    for path in &changed {
+        for depth in 0..path.len() {
+            let ancestor = &path[..depth];
+            ancestors.insert(root.find_id_by_path(ancestor).unwrap(), node_at(root, ancestor));
+        }
+    }
+    assign_nodes(boxes, &ancestors);
+    update
+}
+
+/// Adds to ``changed`` the paths (child indices from the root) of the nodes under ``old`` (at
+/// ``path``) whose subtrees differ in ``new``, in document order: those with other children, and
+/// the parents of the nodes changed otherwise, whose siblings may be styled otherwise too (e.g.
+/// by ``+``).
+fn changed_subtrees(old: &Node, new: &Node, path: &mut Vec<usize>, changed: &mut Vec<Vec<usize>>) {
+    if old.children.len() != new.children.len()
+        || old.children
+            .iter()
+            .zip(&new.children)
+            .any(|(old, new)| old.data != new.data)
+    {
+        changed.push(path.clone());
+        return;
+    }
+    for (i, (old, new)) in old.children.iter().zip(&new.children).enumerate() {
+        if old != new {
+            path.push(i);
+            changed_subtrees(old, new, path, changed);
+            path.pop();
+        }
+    }
+}
+
+/// Whether boxes of the tree (built, not laid out) depend on the boxes before them: the generated
+/// contents, which show counters and quotes, and ``::first-letter``, split from the text of
+/// another node
+fn depends_on_preceding(layout_box: &LayoutBox) -> bool {
+    (layout_box.node_id.is_none() && layout_box.box_type != BoxType::AnonymousBlock)
+        || ["counter-reset", "counter-increment", "counter-set"]
+            .iter()
+            .any(|name| layout_box.property.property.contains_key(*name))
+        || layout_box.children.iter().any(depends_on_preceding)
+}
+
+/// Builds the boxes of the node at ``path`` in ``root`` again, as ``build_layout_tree`` builds
+/// them in the box of its parent in ``boxes`` (built for the DOM before). None if they are not
+/// put where the boxes of the node were.
+fn rebuild_subtree(
+    boxes: &LayoutBox,
+    root: &Node,
+    path: &[usize],
+    stylesheet: &Stylesheet,
+    default_style: &Stylesheet,
+) -> Option<LayoutBox> {
+    let (_, parent_path) = path.split_last()?;
+    let old_box = find_box(boxes, boxes.node.find_id_by_path(path)?)?;
+    let parent_box = find_box(boxes, boxes.node.find_id_by_path(parent_path)?)?;
+    match node_at(root, parent_path).data {
+        // The ``<img>`` of a ``<picture>`` may show the ``<source>``s before it.
+        NodeType::Element(ref e) if e.tag_name == "picture" => return None,
+        _ => {}
+    }
+    let appeared_elements: Vec<SimpleSelector> = (0..path.len())
+        .filter_map(|depth| match node_at(root, &path[..depth]).data {
+            NodeType::Element(ref elem) => Some(ancestor_selector(elem)),
+            NodeType::Text(_) => None,
+        })
+        .collect();
+    let mut id = root.find_id_by_path(path)?;
+    let subtree = stats::time(Phase::Style, || {
+        build_layout_tree(
+            node_at(root, path),
+            stylesheet,
+            default_style,
+            &parent_box.property,
+            &appeared_elements,
+            &mut Counters::new(),
+            &mut id,
+        )
+    });
+    if subtree.box_type == old_box.box_type {
+        Some(subtree)
+    } else {
+        None
+    }
+}
+
+/// The node at the child indices ``path`` from ``root``
+fn node_at<'a>(root: &'a Node, path: &[usize]) -> &'a Node {
+    path.iter().fold(root, |node, &i| &node.children[i])
+}
+
+/// The box of the node ``id`` in the tree of ``layout_box``
+fn find_box(layout_box: &LayoutBox, id: usize) -> Option<&LayoutBox> {
+    if layout_box.node_id == Some(id) {
+        return Some(layout_box);
+    }
+    layout_box.children.iter().filter_map(|child| find_box(child, id)).next()
+}
+
+/// The path (child indices from ``layout_box``) of the box of the node ``id``
+fn find_path(layout_box: &LayoutBox, id: usize) -> Option<Vec<usize>> {
+    if layout_box.node_id == Some(id) {
+        return Some(vec![]);
+    }
+    layout_box
+        .children
+        .iter()
+        .enumerate()
+        .filter_map(|(i, child)| {
+            let mut path = find_path(child, id)?;
+            path.insert(0, i);
+            Some(path)
+        })
+        .next()
+}
+
+/// The box at ``path`` in ``root``
+fn box_at<'a>(root: &'a LayoutBox, path: &[usize]) -> Option<&'a LayoutBox> {
+    path.iter().try_fold(root, |layout_box, &i| layout_box.children.get(i))
+}
+
+fn box_at_mut<'a>(root: &'a mut LayoutBox, path: &[usize]) -> Option<&'a mut LayoutBox> {
+    path.iter().try_fold(root, |layout_box, &i| layout_box.children.get_mut(i))
+}
+
+/// Adds ``delta`` to the node ids from ``from`` on in the tree of ``layout_box``.
+fn shift_ids(layout_box: &mut LayoutBox, from: usize, delta: isize) {
+    if delta == 0 {
+        return;
+    }
+    let shift = |id: &mut usize| {
+        if *id >= from {
+            *id = (*id as isize + delta) as usize;
+        }
+    };
+    if let Some(ref mut id) = layout_box.node_id {
+        shift(id);
+    }
+    match layout_box.info {
+        LayoutInfo::Button(ref mut id)
+        | LayoutInfo::TextInput(ref mut id)
+        | LayoutInfo::Select(ref mut id) => shift(id),
+        _ => {}
+    }
+    for child in &mut layout_box.children {
+        shift_ids(child, from, delta);
+    }
+}
+
+/// Gives the boxes of the tree the ``nodes`` of their ids.
+fn assign_nodes(layout_box: &mut LayoutBox, nodes: &HashMap<usize, &Node>) {
+    if let Some(node) = layout_box.node_id.and_then(|id| nodes.get(&id)) {
+        layout_box.node = (*node).clone();
+    }
+    for child in &mut layout_box.children {
+        assign_nodes(child, nodes);
+    }
+}
+
+/// Computes the styles of the boxes of the tree ``root`` (not laid out) with ``stylesheet``
+/// again (see ``update_tree``). Returns whether no box was built again.
+pub fn restyle_tree(root: &mut LayoutBox, stylesheet: &Stylesheet) -> bool {
+    let node = root.node.clone();
+    update_tree(root, &node, stylesheet, &mut vec![]) != TreeUpdate::Rebuilt
 }
 
 impl LayoutBox {
     /// Lay out a box and its descendants.
     /// `saved_block` is used to know the maximum width/height of the box, calculate the percent
-    /// width/height and so on.
+    /// width/height and so on. Its content height is ``INDEFINITE_HEIGHT`` if it depends on the
+    /// content.
     pub fn layout(
         &mut self,
         floats: &mut Floats,
@@ -509,6 +1704,21 @@ impl LayoutBox {
         saved_block: Dimensions,
         viewport: Dimensions,
     ) {
+        let _span = trace::layout_span(|| self.describe());
+        layout_trace!(
+            "containing block: {}x{}px",
+            containing_block.content.width.to_f64_px(),
+            containing_block.content.height.to_f64_px()
+        );
+        // Next to floats, where it is depends on the boxes before it.
+        if self.box_type == BoxType::BlockNode && !floats.is_present() {
+            self.laid_out_in = Some(LaidOutIn {
+                floats: floats.clone(),
+                last_margin_bottom: last_margin_bottom,
+                containing_block: containing_block,
+                saved_block: saved_block,
+            });
+        }
         match self.box_type {
             BoxType::BlockNode => self.layout_block(
                 floats,
@@ -536,41 +1746,65 @@ impl LayoutBox {
                 self.dimensions.content.y = containing_block.content.height;
 
                 let mut linemaker = LineMaker::new(self.children.clone(), floats.clone());
-                linemaker.run(containing_block.content.width, containing_block);
+                if quirks::mode() == quirks::Mode::NoQuirks {
+                    linemaker.strut = Some(LineMetrics::of_text(&mut self.property));
+                }
+                match self.first_line {
+                    Some(ref first_line) => linemaker.run_with_first_line(
+                        first_line,
+                        containing_block.content.width,
+                        containing_block,
+                    ),
+                    None => linemaker.run(containing_block.content.width, containing_block),
+                }
                 linemaker.end_of_lines();
                 linemaker.assign_position();
 
                 self.dimensions.content.width = linemaker.intrinsic_width();
                 self.dimensions.content.height = linemaker.cur_height;
                 self.children = linemaker.new_boxes;
+                layout_trace!("{} boxes in the lines", self.children.len());
             }
-            // InlineNode and TextNode is contained in AnonymousBlock.
-            BoxType::InlineNode | BoxType::TextNode(_) => unreachable!(),
-            BoxType::None => {}
+            // InlineNode and TextNode is contained in AnonymousBlock, which lays them out.
+            BoxType::InlineNode | BoxType::TextNode(_) | BoxType::None => {}
         }
+        layout_trace!(
+            "content: {}x{}px at ({}, {}) in the containing block",
+            self.dimensions.content.width.to_f64_px(),
+            self.dimensions.content.height.to_f64_px(),
+            self.dimensions.content.x.to_f64_px(),
+            self.dimensions.content.y.to_f64_px()
+        );
     }
 
     /// Where a new inline child should go.
     fn get_inline_container(&mut self) -> &mut LayoutBox {
         match self.box_type {
-            BoxType::InlineNode | BoxType::AnonymousBlock => self,
+            // Texts and boxes not laid out have no children, whichever they are given.
+            BoxType::InlineNode
+            | BoxType::AnonymousBlock
+            | BoxType::TextNode(_)
+            | BoxType::None => self,
             BoxType::Float | BoxType::BlockNode | BoxType::InlineBlockNode => {
                 match self.children.last() {
                     Some(&LayoutBox {
                         box_type: BoxType::AnonymousBlock,
                         ..
                     }) => {}
-                    _ => self.children.push(LayoutBox::new(
-                        BoxType::AnonymousBlock,
-                        Node::text("".to_string()),
-                        Style::new(),
-                        LayoutInfo::Generic,
-                    )),
+                    _ => {
+                        // Inherited from this box, to be hit by the pointer like it, and for
+                        // the strut of the lines
+                        let property = inherit_peoperties(&self.property, &ANONYMOUS_PROPERTIES);
+                        self.children.push(LayoutBox::new(
+                            BoxType::AnonymousBlock,
+                            Node::text("".to_string()),
+                            property,
+                            LayoutInfo::Generic,
+                        ))
+                    }
                 }
                 self.children.last_mut().unwrap()
             }
-            BoxType::TextNode(_) => panic!(),
-            BoxType::None => unreachable!(),
         }
     }
 
@@ -578,39 +1812,59 @@ impl LayoutBox {
         let (padding_top, padding_right, padding_bottom, padding_left) = self.property.padding();
 
         let d = &mut self.dimensions;
-        d.padding.left = Au::from_f64_px(padding_left.to_px().unwrap());
-        d.padding.top = Au::from_f64_px(padding_top.to_px().unwrap());
-        d.padding.bottom = Au::from_f64_px(padding_bottom.to_px().unwrap());
-        d.padding.right = Au::from_f64_px(padding_right.to_px().unwrap());
+        d.padding.left = Au::from_f64_px(padding_left.to_px().unwrap_or(0.0));
+        d.padding.top = Au::from_f64_px(padding_top.to_px().unwrap_or(0.0));
+        d.padding.bottom = Au::from_f64_px(padding_bottom.to_px().unwrap_or(0.0));
+        d.padding.right = Au::from_f64_px(padding_right.to_px().unwrap_or(0.0));
     }
 
     pub fn assign_margin(&mut self) {
         let (margin_top, margin_right, margin_bottom, margin_left) = self.property.margin();
 
         let d = &mut self.dimensions;
-        d.margin.left = Au::from_f64_px(margin_left.to_px().unwrap());
-        d.margin.top = Au::from_f64_px(margin_top.to_px().unwrap());
-        d.margin.bottom = Au::from_f64_px(margin_bottom.to_px().unwrap());
-        d.margin.right = Au::from_f64_px(margin_right.to_px().unwrap());
+        d.margin.left = Au::from_f64_px(margin_left.to_px().unwrap_or(0.0));
+        d.margin.top = Au::from_f64_px(margin_top.to_px().unwrap_or(0.0));
+        d.margin.bottom = Au::from_f64_px(margin_bottom.to_px().unwrap_or(0.0));
+        d.margin.right = Au::from_f64_px(margin_right.to_px().unwrap_or(0.0));
     }
 
     pub fn assign_border_width(&mut self) {
         let (border_top, border_right, border_bottom, border_left) = self.property.border_width();
 
         let d = &mut self.dimensions;
-        d.border.left = Au::from_f64_px(border_left.to_px().unwrap());
-        d.border.top = Au::from_f64_px(border_top.to_px().unwrap());
-        d.border.bottom = Au::from_f64_px(border_bottom.to_px().unwrap());
-        d.border.right = Au::from_f64_px(border_right.to_px().unwrap());
+        d.border.left = Au::from_f64_px(border_left.to_px().unwrap_or(0.0));
+        d.border.top = Au::from_f64_px(border_top.to_px().unwrap_or(0.0));
+        d.border.bottom = Au::from_f64_px(border_bottom.to_px().unwrap_or(0.0));
+        d.border.right = Au::from_f64_px(border_right.to_px().unwrap_or(0.0));
     }
 }
 
 impl LayoutBox {
     pub fn load_image(&mut self, containing_block: Dimensions) {
         use inline;
+        let aspect_ratio = self.property.aspect_ratio();
+        let (node, node_id) = (&self.node, self.node_id);
         match &mut self.info {
             &mut LayoutInfo::Image(ref mut imgdata) => {
-                inline::get_image(&self.node, imgdata, containing_block);
+                // Another image for another viewport or display
+                let source = srcset::select_source(&self.node, &imgdata.picture_sources)
+                    .filter(|&(ref url, _)| !lazy::defers(node, node_id, url));
+                if source != imgdata.source {
+                    imgdata.source = source;
+                    imgdata.intrinsic_size = None;
+                }
+                inline::get_image(&self.node, imgdata, aspect_ratio, containing_block);
+            }
+            &mut LayoutInfo::Svg(ref mut imgdata) => {
+                imgdata.intrinsic_size = Some(svg::intrinsic_size(&self.node));
+                inline::get_image(&self.node, imgdata, aspect_ratio, containing_block);
+            }
+            &mut LayoutInfo::Canvas(ref mut imgdata) => {
+                imgdata.intrinsic_size = Some(canvas2d::intrinsic_size(&self.node));
+                inline::get_image(&self.node, imgdata, aspect_ratio, containing_block);
+            }
+            &mut LayoutInfo::Math(ref mut math) => {
+                *math = MathLayout::of(&self.node, self.property.font());
             }
             _ => {}
         }
@@ -618,7 +1872,19 @@ impl LayoutBox {
 
     pub fn is_replaced(&self) -> bool {
         match self.info {
-            LayoutInfo::Image(_) => true,
+            LayoutInfo::Image(_)
+            | LayoutInfo::Svg(_)
+            | LayoutInfo::Canvas(_)
+            | LayoutInfo::Math(_)
+            | LayoutInfo::TextInput(_)
+            | LayoutInfo::Select(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_text_input(&self) -> bool {
+        match self.info {
+            LayoutInfo::TextInput(_) => true,
             _ => false,
         }
     }
@@ -626,11 +1892,22 @@ impl LayoutBox {
     pub fn assign_replaced_width_if_necessary(&mut self) {
         if self.is_replaced() {
             match self.info {
-                LayoutInfo::Image(ref imgdata) => {
+                LayoutInfo::Image(ref imgdata)
+                | LayoutInfo::Svg(ref imgdata)
+                | LayoutInfo::Canvas(ref imgdata) => {
                     if imgdata.metadata.width > Au(0) {
                         self.dimensions.content.width = imgdata.metadata.width;
                     }
                 }
+                LayoutInfo::TextInput(_) => {
+                    self.dimensions.content.width = self.text_input_width();
+                }
+                LayoutInfo::Select(_) => {
+                    self.dimensions.content.width = self.select_width();
+                }
+                LayoutInfo::Math(ref math) => {
+                    self.dimensions.content.width = Au::from_f64_px(math.width);
+                }
                 _ => {}
             }
         }
@@ -639,17 +1916,89 @@ impl LayoutBox {
     pub fn assign_replaced_height_if_necessary(&mut self) {
         if self.is_replaced() {
             match self.info {
-                LayoutInfo::Image(ref imgdata) => {
+                LayoutInfo::Image(ref imgdata)
+                | LayoutInfo::Svg(ref imgdata)
+                | LayoutInfo::Canvas(ref imgdata) => {
                     if imgdata.metadata.height > Au(0) {
                         self.dimensions.content.height = imgdata.metadata.height;
                     }
                 }
+                LayoutInfo::TextInput(_) => {
+                    self.dimensions.content.height = self.text_input_height();
+                }
+                LayoutInfo::Select(_) => {
+                    self.dimensions.content.height = self.property
+                        .value("height")
+                        .and_then(|h| h[0].to_px())
+                        .map_or_else(|| self.property.line_height(), Au::from_f64_px);
+                }
+                LayoutInfo::Math(ref math) => {
+                    self.dimensions.content.height = Au::from_f64_px(math.ascent + math.descent);
+                }
                 _ => {}
             }
         }
     }
+
+    /// The width of <input type=text> (`size` characters wide) or <textarea> (`cols` characters
+    /// wide) unless `width` is specified.
+    fn text_input_width(&mut self) -> Au {
+        if let Some(width) = self.property.value("width").and_then(|w| w[0].to_px()) {
+            return Au::from_f64_px(width);
+        }
+        let chars = self.text_input_count(
+            if self.node.is_multiline_text_input() {
+                "cols"
+            } else {
+                "size"
+            },
+            DEFAULT_TEXT_INPUT_CHARS,
+        );
+        // TODO: Use the average character width of the font.
+        Au::from_f64_px(self.property.font_size().to_f64_px() * chars / 2.0)
+    }
+
+    /// The height of <input type=text> (one line) or <textarea> (`rows` lines) unless `height` is
+    /// specified.
+    fn text_input_height(&mut self) -> Au {
+        if let Some(height) = self.property.value("height").and_then(|h| h[0].to_px()) {
+            return Au::from_f64_px(height);
+        }
+        let rows = if self.node.is_multiline_text_input() {
+            self.text_input_count("rows", DEFAULT_TEXTAREA_ROWS)
+        } else {
+            1.0
+        };
+        Au::from_f64_px(self.property.line_height().to_f64_px() * rows)
+    }
+
+    /// The whole number of characters or lines in the ``name`` attribute, ``default`` unless it
+    /// is positive, and at most ``MAX_TEXT_INPUT_COUNT``
+    fn text_input_count(&self, name: &str, default: f64) -> f64 {
+        let count = self.node
+            .attr(name)
+            .map(|n| n.to_num().trunc())
+            .filter(|&n| n >= 1.0)
+            .unwrap_or(default);
+        count.min(MAX_TEXT_INPUT_COUNT)
+    }
+
+    /// The width of <select> (its widest option and the arrow) unless `width` is specified.
+    fn select_width(&mut self) -> Au {
+        if let Some(width) = self.property.value("width").and_then(|w| w[0].to_px()) {
+            return Au::from_f64_px(width);
+        }
+        let font = self.property.font().select();
+        let widest = self.node
+            .select_options()
+            .iter()
+            .map(|option| font.text_width(option.option_label().as_str()))
+            .fold(0.0, f64::max);
+        Au::from_f64_px(widest + SELECT_ARROW_WIDTH)
+    }
 }
 
+#[cfg(feature = "raster")]
 impl FontWeight {
     pub fn to_cairo_font_weight(&self) -> cairo::FontWeight {
         match self {
@@ -665,6 +2014,7 @@ impl FontWeight {
     }
 }
 
+#[cfg(feature = "raster")]
 impl FontSlant {
     pub fn to_cairo_font_slant(&self) -> cairo::FontSlant {
         match self {
@@ -689,6 +2039,10 @@ impl Rect {
             height: self.height + edge.top + edge.bottom,
         }
     }
+    pub fn contains_point(self, x: f64, y: f64) -> bool {
+        self.x.to_f64_px() <= x && x <= self.x.to_f64_px() + self.width.to_f64_px()
+            && self.y.to_f64_px() <= y && y <= self.y.to_f64_px() + self.height.to_f64_px()
+    }
     pub fn add_parent_coordinate(self, x: Au, y: Au) -> Rect {
         Rect {
             x: self.x + x,
@@ -697,6 +2051,33 @@ impl Rect {
             height: self.height,
         }
     }
+    /// The area in both rects. None if they do not overlap.
+    pub fn intersection(self, other: Rect) -> Option<Rect> {
+        let x = max(self.x, other.x);
+        let y = max(self.y, other.y);
+        let right = min(self.x + self.width, other.x + other.width);
+        let bottom = min(self.y + self.height, other.y + other.height);
+        if right <= x || bottom <= y {
+            return None;
+        }
+        Some(Rect {
+            x: x,
+            y: y,
+            width: right - x,
+            height: bottom - y,
+        })
+    }
+    /// The smallest rect containing both.
+    pub fn union(self, other: Rect) -> Rect {
+        let x = min(self.x, other.x);
+        let y = min(self.y, other.y);
+        Rect {
+            x: x,
+            y: y,
+            width: max(self.x + self.width, other.x + other.width) - x,
+            height: max(self.y + self.height, other.y + other.height) - y,
+        }
+    }
 }
 
 impl Dimensions {
@@ -745,14 +2126,609 @@ impl Dimensions {
 
 // Functions for displaying
 
-// TODO: Implement all features.
-impl fmt::Display for LayoutBox {
-    // TODO: Implement all features
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "{:?}", self.dimensions)?;
+impl LayoutBox {
+    /// A deterministic dump of the tree for layout tests. A line for each box (indented by the
+    /// depth) has the box type, the node, and the content, padding, border and margin boxes in
+    /// the document coordinates (px).
+    pub fn dump(&self) -> String {
+        let mut dump = String::new();
+        self.write_tree(&mut dump, 0, Au(0), Au(0), &LayoutBox::describe)
+            .unwrap();
+        dump
+    }
+
+    /// The box type and the node, e.g. ``BlockNode <div id=main>``.
+    pub fn describe(&self) -> String {
+        let box_type = match self.box_type {
+            BoxType::TextNode(_) => "TextNode".to_string(),
+            ref box_type => format!("{:?}", box_type),
+        };
+        format!("{} {}", box_type, self.describe_node(None))
+    }
+
+    /// The node of the box, e.g. ``<div id=main>``, or the text of a text box quoted (cut after
+    /// ``max_chars`` if any).
+    fn describe_node(&self, max_chars: Option<usize>) -> String {
+        match (&self.box_type, &self.node.data) {
+            (&BoxType::TextNode(ref text), &NodeType::Text(ref s)) => {
+                let text = &s[text.range.clone()];
+                match max_chars {
+                    Some(max_chars) if text.chars().count() > max_chars => {
+                        let cut: String = text.chars().take(max_chars).collect();
+                        format!("{:?}...", cut)
+                    }
+                    _ => format!("{:?}", text),
+                }
+            }
+            (_, &NodeType::Text(_)) => "#text".to_string(),
+            (_, &NodeType::Element(ref elem)) => {
+                let mut classes: Vec<&str> = elem.classes().into_iter().collect();
+                classes.sort();
+                let mut element = format!("<{}", elem.tag_name);
+                if let Some(id) = elem.id() {
+                    element += format!(" id={}", id).as_str();
+                }
+                if !classes.is_empty() {
+                    element += format!(" class={}", classes.join(" ")).as_str();
+                }
+                element + ">"
+            }
+        }
+    }
+
+    /// Writes a line for each box of the tree (indented by the depth): ``describe`` of it, and
+    /// its content, padding, border and margin boxes in the document coordinates (px).
+    fn write_tree<W: fmt::Write>(
+        &self,
+        out: &mut W,
+        depth: usize,
+        x: Au,
+        y: Au,
+        describe: &dyn Fn(&LayoutBox) -> String,
+    ) -> fmt::Result {
+        let mut dimensions = self.dimensions;
+        dimensions.content = dimensions.content.add_parent_coordinate(x, y);
+        let rect = |rect: Rect| {
+            format!(
+                "({}, {}, {}x{})",
+                rect.x.to_f64_px(),
+                rect.y.to_f64_px(),
+                rect.width.to_f64_px(),
+                rect.height.to_f64_px()
+            )
+        };
+        writeln!(
+            out,
+            "{}{} content={} padding={} border={} margin={}",
+            "  ".repeat(depth),
+            describe(self),
+            rect(dimensions.content),
+            rect(dimensions.padding_box()),
+            rect(dimensions.border_box()),
+            rect(dimensions.margin_box())
+        )?;
+
         for child in &self.children {
-            write!(f, "{}", child)?;
+            let (x, y) = (dimensions.content.x, dimensions.content.y);
+            child.write_tree(out, depth + 1, x, y, describe)?;
         }
         Ok(())
     }
 }
+
+/// The laid out tree for debugging, like ``LayoutBox::dump`` with shorter box types (e.g.
+/// ``block``) and texts.
+impl fmt::Display for LayoutBox {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let describe = |layout_box: &LayoutBox| {
+            let box_type = match layout_box.box_type {
+                BoxType::BlockNode => "block",
+                BoxType::InlineNode => "inline",
+                BoxType::InlineBlockNode => "inline-block",
+                BoxType::Float => "float",
+                BoxType::TextNode(_) => "text",
+                BoxType::AnonymousBlock => "anonymous",
+                BoxType::None => "none",
+            };
+            match layout_box.box_type {
+                BoxType::AnonymousBlock => box_type.to_string(),
+                _ => format!("{} {}", box_type, layout_box.describe_node(Some(20))),
+            }
+        };
+        self.write_tree(f, 0, Au(0), Au(0), &describe)
+    }
+}
+
+/// The tree of ``html_src`` laid out with ``css_src`` in a viewport of ``width`` x ``height`` px
+#[cfg(test)]
+pub fn lay_out_html(html_src: &str, css_src: &str, width: f64, height: f64) -> LayoutBox {
+    use css;
+    use html;
+    use network::Url;
+
+    let document = html::parse(html_src.to_string(), Url::parse("file:///a.html").unwrap());
+    let mut viewport: Dimensions = Default::default();
+    viewport.content.width = Au::from_f64_px(width);
+    viewport.content.height = Au::from_f64_px(height);
+    LAYOUTBOX.with(|lb| *lb.borrow_mut() = None);
+    layout_tree(&document, &css::parse(css_src.to_string()), viewport)
+}
+
+/// The texts of the text nodes of ``layout_box``, in order
+#[cfg(test)]
+fn text(layout_box: &LayoutBox) -> String {
+    match (&layout_box.box_type, &layout_box.node.data) {
+        (&BoxType::TextNode(_), &NodeType::Text(ref s)) => s.clone(),
+        _ => layout_box.children.iter().map(text).collect(),
+    }
+}
+
+#[test]
+fn test_display() {
+    let root = lay_out_html(
+        "<div id=\"a\"><p>abcdefghijklmnopqrstuvwxyz</p><span>b</span></div>",
+        "p { margin: 0; height: 20px; padding: 1px; }",
+        400.0,
+        0.0,
+    );
+    let display = root.to_string();
+    let lines: Vec<&str> = display.lines().collect();
+    assert!(lines[0].starts_with("block <div id=a> content=(0, 0, 400x"));
+    assert_eq!(
+        lines[1],
+        "  block <p> content=(1, 1, 398x20) padding=(0, 0, 400x22) border=(0, 0, 400x22) \
+         margin=(0, 0, 400x22)"
+    );
+    assert!(lines[2].starts_with("    anonymous content="));
+    assert!(lines[3].starts_with("      text \"abcdefghijklmnopqrst\"... content="));
+    assert!(display.contains("\n    inline <span> content="));
+}
+
+#[test]
+fn test_restyle_tree() {
+    use css;
+
+    lay_out_html("<div><p>a</p><span>b</span></div>", "", 100.0, 0.0);
+    let mut root = LAYOUTBOX.with(|lb| lb.borrow().clone().unwrap());
+
+    // The same boxes, with the new styles
+    assert!(restyle_tree(&mut root, &css::parse("p { color: red; }".to_string())));
+    assert_eq!(
+        root.children[0].property.property.get("color"),
+        Some(&vec![Value::Keyword("red".to_string())])
+    );
+
+    // Other boxes
+    let stylesheet = "p { display: none; } span { text-transform: uppercase; }";
+    let stylesheet = css::parse(stylesheet.to_string());
+    assert!(!restyle_tree(&mut root, &stylesheet));
+    assert_eq!(root.children.len(), 1);
+    // The anonymous block, the span and its text
+    let text_box = &root.children[0].children[0].children[0];
+    assert_eq!(text_box.node.data, NodeType::Text("B".to_string()));
+    assert!(restyle_tree(&mut root, &stylesheet));
+}
+
+#[test]
+fn test_retained_layout_tree() {
+    use css;
+    use html;
+    use network::Url;
+
+    let parse = |src: &str| html::parse(src.to_string(), Url::parse("file:///a.html").unwrap());
+    let document = parse("<div><p>a</p><p>b</p></div>");
+    let mut viewport: Dimensions = Default::default();
+    viewport.content.width = Au::from_f64_px(100.0);
+    let stylesheet = |src: &str| css::parse(src.to_string());
+    LAYOUTBOX.with(|lb| *lb.borrow_mut() = None);
+    layout_tree(&document, &stylesheet("p { color: black; }"), viewport);
+    let retained = || LAID_OUT_VIEWPORT.with(|v| v.get().is_some());
+    assert_eq!(update(&document, &stylesheet("p { color: black; }")), TreeUpdate::Unchanged);
+    assert!(retained());
+
+    // Painted as it was laid out, with the new color
+    let red = stylesheet("p { color: red; }");
+    assert_eq!(update(&document, &red), TreeUpdate::Repaint);
+    assert!(retained());
+    let root = layout_tree(&document, &red, viewport);
+    assert_eq!(
+        root.children[1].property.property.get("color"),
+        Some(&vec![Value::Keyword("red".to_string())])
+    );
+
+    // Laid out again
+    let taller = stylesheet("p { color: red; height: 30px; }");
+    assert_eq!(update(&document, &taller), TreeUpdate::Relayout);
+    assert!(!retained());
+    let root = layout_tree(&document, &taller, viewport);
+    assert_eq!(root.dimensions.content.height, Au::from_f64_px(60.0));
+    // In another viewport too
+    viewport.content.width = Au::from_f64_px(50.0);
+    let root = layout_tree(&document, &taller, viewport);
+    assert_eq!(root.dimensions.content.width, viewport.content.width);
+
+    // The boxes of a node more are built.
+    let document = parse("<div><p>a</p><p>b</p><p>c</p></div>");
+    assert_eq!(update(&document, &taller), TreeUpdate::Rebuilt);
+    let root = layout_tree(&document, &taller, viewport);
+    assert_eq!(root.dimensions.content.height, Au::from_f64_px(90.0));
+}
+
+#[test]
+fn test_relayout_changed() {
+    use css;
+    use html;
+    use network::Url;
+
+    let parse = |src: &str| html::parse(src.to_string(), Url::parse("file:///a.html").unwrap());
+    let stylesheet = css::parse(
+        "p { margin: 0; } section { display: block; height: 40px; }".to_string(),
+    );
+    let mut viewport: Dimensions = Default::default();
+    viewport.content.width = Au::from_f64_px(100.0);
+    let long_text = "a a a a a a a a a a a a a a a a a a a a a a a a a a a a a a";
+    let relaid_out = |document: &Node| {
+        // The box laid out from the last ``<p>`` keeps the mark unless it is laid out again.
+        laid_out(|root| root.children[1].z_index = 1);
+        assert_eq!(update(document, &stylesheet), TreeUpdate::Relayout);
+        let root = layout_tree(document, &stylesheet, viewport);
+        LAYOUTBOX.with(|lb| *lb.borrow_mut() = None);
+        assert_eq!(root.dump(), layout_tree(document, &stylesheet, viewport).dump());
+        root.children[1].z_index != 1
+    };
+    LAYOUTBOX.with(|lb| *lb.borrow_mut() = None);
+    layout_tree(&parse("<div><section><p>a</p></section><p>b</p></div>"), &stylesheet, viewport);
+
+    // Only the section, as high as it was
+    let document = format!("<div><section><p>{}</p></section><p>b</p></div>", long_text);
+    assert!(!relaid_out(&parse(&document)));
+    // The whole tree, higher
+    let document = format!(
+        "<div><section><p>{}</p></section><p>{}</p></div>",
+        long_text, long_text
+    );
+    assert!(relaid_out(&parse(&document)));
+}
+
+#[test]
+fn test_update_subtrees() {
+    use css;
+    use html;
+    use network::Url;
+
+    // What the boxes are built from and with
+    fn boxes(
+        layout_box: &LayoutBox,
+        list: &mut Vec<(BoxType, Option<usize>, LayoutInfo, Node, HashMap<String, Vec<Value>>)>,
+    ) {
+        list.push((
+            layout_box.box_type.clone(),
+            layout_box.node_id,
+            layout_box.info.clone(),
+            layout_box.node.clone(),
+            layout_box.property.property.clone(),
+        ));
+        for child in &layout_box.children {
+            boxes(child, list);
+        }
+    }
+    let same = |a: &LayoutBox, b: &LayoutBox| {
+        let (mut a_boxes, mut b_boxes) = (vec![], vec![]);
+        boxes(a, &mut a_boxes);
+        boxes(b, &mut b_boxes);
+        a_boxes == b_boxes
+    };
+
+    let parse = |src: &str| html::parse(src.to_string(), Url::parse("file:///a.html").unwrap());
+    let stylesheet = css::parse("p + p { color: red; } span { display: block; }".to_string());
+    let build = |document: &Node, stylesheet: &Stylesheet| {
+        build_layout_tree(
+            document,
+            stylesheet,
+            &default_style::default_style(),
+            &Style::new(),
+            &vec![],
+            &mut Counters::new(),
+            &mut 0,
+        )
+    };
+    let document = parse("<div><p>a</p><p><span>b</span></p><input value=\"c\"></div>");
+    let mut root = build(&document, &stylesheet);
+
+    // A text changed
+    let document = parse("<div><p>a</p><p><span>d</span></p><input value=\"c\"></div>");
+    let mut changed = vec![];
+    assert_eq!(
+        update_subtrees(&mut root, &document, &stylesheet, &mut changed),
+        TreeUpdate::Relayout
+    );
+    assert!(same(&root, &build(&document, &stylesheet)));
+    assert_eq!(changed, vec![vec![1, 0, 0, 0]]);
+
+    // A node more: the ids of the nodes after it are shifted, so the whole tree is laid out again.
+    let document = parse("<div><p>a</p><p><span>d</span>e</p><input value=\"c\"></div>");
+    let mut changed = vec![];
+    assert_eq!(
+        update_subtrees(&mut root, &document, &stylesheet, &mut changed),
+        TreeUpdate::Rebuilt
+    );
+    assert!(same(&root, &build(&document, &stylesheet)));
+    assert_eq!(root.children[2].children[0].info, LayoutInfo::TextInput(7));
+    assert!(changed.contains(&vec![]));
+
+    // A node less, and the sibling after it styled otherwise
+    let document = parse("<div><p><span>d</span>e</p><input value=\"c\"></div>");
+    assert_eq!(
+        update_subtrees(&mut root, &document, &stylesheet, &mut vec![]),
+        TreeUpdate::Rebuilt
+    );
+    assert!(same(&root, &build(&document, &stylesheet)));
+
+    // Only the subtree changed is styled again.
+    let blue = css::parse("* { color: blue; }".to_string());
+    let document = parse("<div><p><span>f</span>e</p><input value=\"c\"></div>");
+    update_subtrees(&mut root, &document, &blue, &mut vec![]);
+    let color = |layout_box: &LayoutBox| layout_box.property.property.get("color").cloned();
+    let blue = Some(vec![Value::Keyword("blue".to_string())]);
+    assert_eq!(color(&root.children[0].children[0]), blue);
+    assert_ne!(color(&root.children[0]), blue);
+}
+
+#[test]
+fn test_generated_content() {
+    lay_out_html(
+        "<ol><li>a</li><li>b<ol><li>c</li></ol></li></ol>",
+        "ol { counter-reset: item; } li { counter-increment: item; }
+         li::before { content: 'n' counters(item, '.') ' '; text-transform: uppercase; }
+         li:after { content: '!'; display: block; }",
+        100.0,
+        0.0,
+    );
+    let root = LAYOUTBOX.with(|lb| lb.borrow().clone().unwrap());
+
+    assert_eq!(text(&root.children[0]), "N1 a!");
+    // The anonymous block of ``::before`` and the text, the nested list, then ``::after``
+    let second = &root.children[1];
+    let before = &second.children[0].children[0];
+    assert_eq!(before.describe(), "InlineNode <::before>");
+    assert_eq!(before.node_id, None);
+    assert_eq!(text(&second.children[0]), "N2 b");
+    assert_eq!(text(&second.children[1]), "N2.1 c!");
+    assert_eq!(second.children[2].describe(), "BlockNode <::after>");
+    assert_eq!(text(&second.children[2]), "!");
+
+    // The quotes of ``<q>`` by default, nested
+    lay_out_html("<p><q>a <q>b</q></q></p>", "", 100.0, 0.0);
+    let root = LAYOUTBOX.with(|lb| lb.borrow().clone().unwrap());
+    assert_eq!(text(&root), "\u{201c}a \u{2018}b\u{2019}\u{201d}");
+}
+
+#[test]
+fn test_first_line_and_first_letter() {
+    // The texts laid out in the lines, with their colors
+    fn texts(layout_box: &LayoutBox, found: &mut Vec<(String, Value)>) {
+        match (&layout_box.box_type, &layout_box.node.data) {
+            (&BoxType::TextNode(ref text), &NodeType::Text(ref s)) => {
+                let color = layout_box.property.value("color").unwrap()[0].clone();
+                found.push((s[text.range.clone()].to_string(), color))
+            }
+            _ => layout_box.children.iter().for_each(|child| texts(child, found)),
+        }
+    }
+
+    let words = "aaa bbb ccc ddd eee fff ggg hhh iii jjj kkk lll mmm nnn ooo ppp";
+    let root = lay_out_html(
+        format!("<div><p>\u{201c}{}</p><h1>x<span>y</span></h1></div>", words).as_str(),
+        "div { color: black; } p::first-line { color: red; text-transform: uppercase; }
+         p::first-letter { color: blue; display: block; } h1:first-letter { float: left; }",
+        100.0,
+        0.0,
+    );
+    let keyword = |k: &str| Value::Keyword(k.to_string());
+    let (red, blue, black) = (keyword("red"), keyword("blue"), keyword("black"));
+
+    let p = &root.children[0];
+    let mut p_texts = vec![];
+    texts(p, &mut p_texts);
+    // The first letter with its punctuation, in the first line
+    assert_eq!(p_texts[0], ("\u{201c}A".to_string(), blue));
+    let first_line: Vec<_> = p_texts[1..].iter().take_while(|&&(_, ref c)| *c == red).collect();
+    assert!(!first_line.is_empty() && first_line.len() < p_texts.len() - 1);
+    assert!(p_texts[1 + first_line.len()..].iter().all(|&(_, ref color)| *color == black));
+    // All the text, once
+    let text: String = p_texts.iter().map(|&(ref text, _)| text.as_str()).collect();
+    assert_eq!(text.to_lowercase(), format!("\u{201c}{}", words));
+
+    // Floated out of the line
+    let h1 = &root.children[1];
+    assert_eq!(h1.children[0].describe(), "Float <::first-letter>");
+    let mut h1_texts = vec![];
+    texts(h1, &mut h1_texts);
+    assert_eq!(h1_texts, vec![("x".to_string(), black.clone()), ("y".to_string(), black)]);
+    assert_eq!(h1.children[1].box_type, BoxType::AnonymousBlock);
+}
+
+#[test]
+fn test_containment() {
+    use overflow;
+    use style::Overflow;
+
+    let root = lay_out_html(
+        "<div><div class=\"float\"></div><div id=\"size\"><p></p></div>\
+         <div id=\"layout\"><p></p></div><div id=\"paint\"><p></p></div><div><p></p></div></div>",
+        ".float { float: left; width: 10px; height: 10px; } p { height: 20px; }
+         #size { contain: size; } #layout { contain: layout; } #paint { contain: content; }",
+        100.0,
+        0.0,
+    );
+    let (size, layout, paint, uncontained) = (
+        &root.children[1],
+        &root.children[2],
+        &root.children[3],
+        &root.children[4],
+    );
+
+    // Sized as if it were empty, with its content overflowing it
+    assert_eq!(size.dimensions.content.height, Au(0));
+    assert_eq!(size.children[0].dimensions.content.height, Au::from_f64_px(20.0));
+    assert_eq!(uncontained.dimensions.content.height, Au::from_f64_px(20.0));
+    // The float outside does not reach in.
+    assert!(size.floats.is_present());
+    assert!(!layout.floats.is_present());
+    assert!(!paint.floats.is_present());
+    assert_eq!(overflow::overflow(paint), (Overflow::Clip, Overflow::Clip));
+    assert_eq!(overflow::overflow(layout), (Overflow::Visible, Overflow::Visible));
+
+    // The counters changed in the subtree are restored after it.
+    let root = lay_out_html(
+        "<ol><li>a</li><li class=\"contained\">b<ol><li>c</li></ol></li><li>d</li></ol>",
+        "li { counter-increment: item; } .contained { contain: style; counter-increment: item 5; }
+         li::before { content: counter(item) ' '; }",
+        100.0,
+        0.0,
+    );
+    assert_eq!(text(&root), "1 a6 b7 c2 d");
+}
+
+#[test]
+fn test_percentage_heights() {
+    let html_src = "<!DOCTYPE html><html><body><div id=\"wrapper\"><p></p></div>\
+                    <span><i></i></span></body></html>";
+    let heights = |css_src: &str| -> (f64, f64, f64) {
+        let css_src = format!("p {{ height: 10px; }} {}", css_src);
+        let root = lay_out_html(html_src, css_src.as_str(), 100.0, 200.0);
+        let body = &root.children[0];
+        (
+            root.dimensions.content.height.to_f64_px(),
+            body.dimensions.content.height.to_f64_px(),
+            body.children[0].dimensions.content.height.to_f64_px(),
+        )
+    };
+
+    // Of the viewport, then of the parents
+    assert_eq!(heights("html, body, #wrapper { height: 100%; }"), (200.0, 200.0, 200.0));
+    let css_src = "html { height: 50%; } body { height: 40px; } #wrapper { height: 50%; }";
+    assert_eq!(heights(css_src), (100.0, 40.0, 20.0));
+    // ``auto`` where the height of the parent depends on the content, and in lines
+    assert_eq!(heights("body, #wrapper { height: 100%; }"), (10.0, 10.0, 10.0));
+    let css_src = "html, body { height: 100%; } i { display: inline-block; height: 100%; }";
+    assert_eq!(heights(css_src).1, 200.0);
+    let line = &lay_out_html(html_src, css_src, 100.0, 200.0).children[0].children[1];
+    assert!(line.dimensions.content.height < Au::from_f64_px(200.0));
+}
+
+#[test]
+fn test_quirks_mode() {
+    // Set back even if an assertion fails, so that the next tests on the thread are not in
+    // quirks mode
+    struct NoQuirks;
+    impl Drop for NoQuirks {
+        fn drop(&mut self) {
+            quirks::set_mode(quirks::Mode::NoQuirks);
+        }
+    }
+    let _no_quirks = NoQuirks;
+
+    // Without a doctype
+    let root = lay_out_html(
+        "<html><body><div id=\"wrapper\"><p></p></div><span><i></i></span></body></html>",
+        "body { margin: 8px; } p { height: 50%; }
+         i { display: inline-block; width: 10px; height: 10px; }",
+        100.0,
+        200.0,
+    );
+    assert_eq!(quirks::mode(), quirks::Mode::Quirks);
+    let body = &root.children[0];
+
+    // html fills the viewport and body fills html, less its margins.
+    assert_eq!(root.dimensions.content.height.to_f64_px(), 200.0);
+    assert_eq!(body.dimensions.content.height.to_f64_px(), 184.0);
+    // Percentages of the height of the nearest ancestor whose height does not depend on its content
+    let wrapper = &body.children[0];
+    assert_eq!(wrapper.children[0].dimensions.content.height.to_f64_px(), 100.0);
+    // The line of an image alone is as high as the image.
+    assert_eq!(body.children[1].dimensions.content.height.to_f64_px(), 10.0);
+}
+
+#[test]
+fn test_math_baseline() {
+    let root = lay_out_html(
+        "<!DOCTYPE html><html><body><p>a<math><mfrac><mn>1</mn><mn>2</mn></mfrac></math>\
+         </p></body></html>",
+        "body { margin: 0; } p { margin: 0; }",
+        100.0,
+        0.0,
+    );
+    let line = &root.children[0].children[0].children[0];
+    let (text, math) = (&line.children[0], &line.children[1]);
+    let (ascent, descent) = match math.info {
+        LayoutInfo::Math(ref math) => (math.ascent, math.descent),
+        ref info => panic!("{:?}", info),
+    };
+    // The formula is a replaced box, as high as the fraction, on the baseline of the text.
+    assert!(math.children.is_empty());
+    assert_eq!(math.dimensions.content.height, Au::from_f64_px(ascent + descent));
+    let text_ascent = match text.box_type {
+        BoxType::TextNode(ref text) => text.font.get_ascent_descent().0,
+        ref box_type => panic!("{:?}", box_type),
+    };
+    assert_eq!(
+        text.dimensions.content.y + text_ascent,
+        math.dimensions.content.y + Au::from_f64_px(ascent)
+    );
+    assert_eq!(line.dimensions.content.height, math.dimensions.content.height);
+}
+
+#[test]
+fn test_text_input_size() {
+    fn find_input(layout_box: &LayoutBox) -> Option<&LayoutBox> {
+        if layout_box.is_text_input() {
+            return Some(layout_box);
+        }
+        layout_box.children.iter().find_map(find_input)
+    }
+    let sizes = |attrs: &str| {
+        let html_src = format!("<p><textarea {}></textarea></p>", attrs);
+        let root = lay_out_html(html_src.as_str(), "", 100.0, 0.0);
+        let content = find_input(&root).unwrap().dimensions.content;
+        (content.width, content.height)
+    };
+    let (width, height) = sizes("");
+    assert_eq!(sizes("cols=\"20\" rows=\"2\""), (width, height));
+    // Not positive, or not a number: the default
+    assert_eq!(sizes("cols=\"-5\" rows=\"0\""), (width, height));
+    assert_eq!(sizes("cols=\"a\" rows=\"0.5\""), (width, height));
+    // Too large
+    let (huge_width, huge_height) = sizes("cols=\"1000000000000\" rows=\"1000000000000\"");
+    assert_eq!((huge_width.to_f64_px() / width.to_f64_px()).round(), 500.0);
+    assert_eq!((huge_height.to_f64_px() / height.to_f64_px()).round(), 5000.0);
+}
+
+#[test]
+fn test_presentational_hints() {
+    use font::FontLanguage;
+
+    let root = lay_out_html(
+        "<!DOCTYPE html><html><body><p hidden>a</p><div hidden class=\"shown\">b</div>\
+         <p dir=\"RTL\" lang=\"tr\">i</p></body></html>",
+        "body { margin: 0; } p { margin: 0; text-transform: uppercase; } \
+         .shown { display: block; }",
+        100.0,
+        0.0,
+    );
+    let display = root.to_string();
+    // Not shown unless the page shows it
+    assert!(!display.contains("text \"a\""));
+    assert!(display.contains("text \"b\""));
+
+    let text = &root.children[0].children[1].children[0].children[0];
+    assert_eq!(text.node.data, NodeType::Text("İ".to_string()));
+    assert_eq!(text.property.direction(), Some(style::Direction::Rtl));
+    // At the start of the line, on the right
+    let content = text.dimensions.content;
+    assert_eq!(content.x + content.width, Au::from_f64_px(100.0));
+    match text.box_type {
+        BoxType::TextNode(ref text) => assert_eq!(text.font.lang, FontLanguage::new(Some("tr"))),
+        ref box_type => panic!("{:?}", box_type),
+    }
+}
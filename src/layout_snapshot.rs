@@ -0,0 +1,98 @@
+//! Layout snapshot tests: each ``<name>.html`` in a directory is laid out headlessly, and the
+//! dump of the layout tree (see ``LayoutBox::dump``) is compared with ``<name>.layout``.
+//!
+//! Run with ``NAGLFAR_UPDATE_SNAPSHOTS=1`` to write the dumps as the expectations instead.
+
+use interface;
+use reftest::{VIEWPORT_HEIGHT, VIEWPORT_WIDTH};
+
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+pub const UPDATE_SNAPSHOTS: &'static str = "NAGLFAR_UPDATE_SNAPSHOTS";
+
+/// The line number (from 1), and the expected and actual lines at the first difference.
+pub fn first_difference(expected: &str, actual: &str) -> Option<(usize, String, String)> {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => return None,
+            (e, a) if e != a => {
+                return Some((
+                    line,
+                    e.unwrap_or("(end)").to_string(),
+                    a.unwrap_or("(end)").to_string(),
+                ))
+            }
+            _ => line += 1,
+        }
+    }
+}
+
+/// Runs the tests in ``dir``. Returns the failures.
+pub fn run(dir: &str) -> Vec<String> {
+    let dir = match Path::new(dir).canonicalize() {
+        Ok(dir) => dir,
+        Err(e) => return vec![format!("{}: {}", dir, e)],
+    };
+    let mut pages: Vec<_> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("html"))
+            .collect(),
+        Err(e) => return vec![format!("{}: {}", dir.display(), e)],
+    };
+    pages.sort();
+    let update = env::var(UPDATE_SNAPSHOTS).is_ok();
+
+    let mut failures = vec![];
+    for page in pages {
+        let url = format!("file://{}", page.to_str().unwrap());
+        let dump = interface::layout_headless(url.as_str(), VIEWPORT_WIDTH, VIEWPORT_HEIGHT).dump();
+        let expectation_path = page.with_extension("layout");
+        let name = page.file_name().unwrap().to_string_lossy().into_owned();
+
+        if update {
+            if let Err(e) = fs::File::create(&expectation_path)
+                .and_then(|mut f| f.write_all(dump.as_bytes()))
+            {
+                failures.push(format!("{}: {}", expectation_path.display(), e));
+            }
+            continue;
+        }
+
+        let mut expected = String::new();
+        if fs::File::open(&expectation_path)
+            .and_then(|mut f| f.read_to_string(&mut expected))
+            .is_err()
+        {
+            failures.push(format!("{}: no expectation (run with {}=1)", name, UPDATE_SNAPSHOTS));
+            continue;
+        }
+        if let Some((line, expected, actual)) = first_difference(expected.as_str(), dump.as_str()) {
+            failures.push(format!(
+                "{}:{}:\n  expected: {}\n  actual:   {}",
+                name, line, expected, actual
+            ));
+        }
+    }
+    failures
+}
+
+#[test]
+fn test_first_difference() {
+    assert_eq!(first_difference("a\nb\n", "a\nb\n"), None);
+    assert_eq!(
+        first_difference("a\nb\n", "a\nc\n"),
+        Some((2, "b".to_string(), "c".to_string()))
+    );
+    assert_eq!(
+        first_difference("a\n", "a\nb\n"),
+        Some((2, "(end)".to_string(), "b".to_string()))
+    );
+}
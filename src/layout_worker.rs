@@ -0,0 +1,189 @@
+//! Styling and layout on a worker thread, so that the thread of the window can go on handling
+//! events meanwhile. The DOM, the stylesheets and the layout tree are ``Send`` (images are laid
+//! out with their sizes, and painted from the decoded images of the painting thread), so the
+//! laid out tree is handed back to be painted. The window lays out each page loaded this way
+//! (see ``interface::display_list``).
+//!
+//! What layout reads besides the DOM and the stylesheets is kept by the thread that lays out: it
+//! is handed to the worker (see ``LayoutState``), and what the worker keeps of the layout (the
+//! ``content-visibility: auto`` boxes and the lazy images) is handed back with the tree. The
+//! canvases stay on the thread of the window, which draws on them and paints them: layout only
+//! reads their sizes from the DOM.
+//!
+//! Images are loaded by the thread that receives the layout (see ``LaidOut::load_images``): the
+//! loads of a thread are received on it.
+
+use containment;
+use dom::Node;
+use css::Stylesheet;
+use inline;
+use lazy;
+use media::{self, ColorScheme, MediaType};
+use quirks;
+use layout::{self, Dimensions, LayoutBox, Rect};
+
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+/// What the thread that lays out keeps for layout: what styles and lays out the page besides the
+/// DOM and the stylesheets, and where the boxes laid out before were.
+#[derive(Clone, Debug)]
+pub struct LayoutState {
+    quirks_mode: quirks::Mode,
+    preferred_color_scheme: ColorScheme,
+    media_type: MediaType,
+    device_pixel_ratio: f64,
+    visible_area: Option<Rect>,
+    boxes: containment::Boxes,
+    lazy_images: lazy::Images,
+}
+
+impl LayoutState {
+    /// The state of this thread
+    pub fn capture() -> LayoutState {
+        LayoutState {
+            quirks_mode: quirks::mode(),
+            preferred_color_scheme: media::preferred_color_scheme(),
+            media_type: media::media_type(),
+            device_pixel_ratio: media::device_pixel_ratio(),
+            visible_area: containment::visible_area(),
+            boxes: containment::boxes(),
+            lazy_images: lazy::images(),
+        }
+    }
+
+    /// Makes it the state of this thread.
+    pub fn install(self) {
+        quirks::set_mode(self.quirks_mode);
+        media::set_preferred_color_scheme(self.preferred_color_scheme);
+        media::set_media_type(self.media_type);
+        // The images are selected for the display of the window.
+        media::set_device_pixel_ratio(self.device_pixel_ratio);
+        containment::set_visible_area(self.visible_area);
+        containment::set_boxes(self.boxes);
+        lazy::set_images(self.lazy_images);
+    }
+}
+
+/// The result of ``spawn``.
+pub struct LaidOut {
+    pub layout_root: LayoutBox,
+    /// The images laid out before they were loaded (as placeholders)
+    pub images_to_load: Vec<String>,
+    boxes: containment::Boxes,
+    lazy_images: lazy::Images,
+}
+
+impl LaidOut {
+    /// Loads the images laid out before they were loaded in the background. The page should be
+    /// laid out again when they arrive (see ``interface::apply_loaded_resources``).
+    pub fn load_images(&self) {
+        inline::load_images(&self.images_to_load);
+    }
+
+    /// Keeps where the ``content-visibility: auto`` boxes and the lazy images were laid out, for
+    /// the layouts after it on this thread. Returns whether the page has to be laid out again:
+    /// the part of the document shown has changed meanwhile, so that boxes or images have come
+    /// near it, or gone away from it.
+    pub fn keep_state(&mut self) -> bool {
+        let boxes = ::std::mem::take(&mut self.boxes);
+        let lazy_images = ::std::mem::take(&mut self.lazy_images);
+        containment::set_boxes(boxes) | lazy::set_images(lazy_images)
+    }
+}
+
+/// Styles and lays out ``root`` with ``stylesheet`` in ``viewport`` on a new thread, in the
+/// ``LayoutState`` of this thread. The result is received once, or never if the layout panics.
+pub fn spawn(root: Node, stylesheet: Stylesheet, viewport: Dimensions) -> Receiver<LaidOut> {
+    let (sender, receiver) = channel();
+    let state = LayoutState::capture();
+    thread::spawn(move || {
+        inline::defer_image_loads();
+        state.install();
+        layout::discard_layout();
+        layout::lay_out(&root, &stylesheet, viewport);
+        sender
+            .send(LaidOut {
+                layout_root: layout::take_laid_out().unwrap(),
+                images_to_load: inline::deferred_image_loads(),
+                boxes: containment::boxes(),
+                lazy_images: lazy::images(),
+            })
+            .ok();
+    });
+    receiver
+}
+
+#[test]
+fn test_spawn() {
+    use css;
+    use html;
+    use network::Url;
+
+    use app_units::Au;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Node>();
+    assert_send_sync::<Stylesheet>();
+    assert_send_sync::<LayoutBox>();
+
+    let document = html::parse(
+        "<div style=\"padding: 10px\">text<img src=\"a.png\"></div>".to_string(),
+        Url::parse("file:///not/found/a.html").unwrap(),
+    );
+    let stylesheet = css::parse("div { margin: 0; }".to_string());
+    let mut viewport: Dimensions = Default::default();
+    viewport.content.width = Au::from_f64_px(200.0);
+
+    let laid_out = spawn(document.clone(), stylesheet.clone(), viewport).recv().unwrap();
+    layout::LAYOUTBOX.with(|lb| *lb.borrow_mut() = None);
+    let layout_root = layout::layout_tree(&document, &stylesheet, viewport);
+    assert_eq!(laid_out.layout_root.dump(), layout_root.dump());
+    assert_eq!(laid_out.images_to_load, vec!["file:///not/found/a.png".to_string()]);
+}
+
+#[test]
+fn test_spawn_state() {
+    use css;
+    use html;
+    use network::Url;
+
+    use app_units::Au;
+
+    // Without a doctype, in quirks mode, dark, and scrolled to the top
+    let document = html::parse(
+        "<html><body><p></p><img loading=\"lazy\" src=\"a.png\" width=\"10\" height=\"10\"></body>\
+         </html>"
+            .to_string(),
+        Url::parse("file:///not/found/a.html").unwrap(),
+    );
+    let stylesheet = css::parse(
+        "body { margin: 0; } p { margin: 0; height: 1000px; }
+         @media (prefers-color-scheme: dark) { p { height: 2000px; } }"
+            .to_string(),
+    );
+    let mut viewport: Dimensions = Default::default();
+    viewport.content.width = Au::from_f64_px(100.0);
+    viewport.content.height = Au::from_f64_px(100.0);
+    media::set_preferred_color_scheme(ColorScheme::Dark);
+    containment::set_visible_area(Some(viewport.content));
+
+    let mut laid_out = spawn(document, stylesheet, viewport).recv().unwrap();
+    let body = &laid_out.layout_root.children[0];
+    assert_eq!(body.children[0].dimensions.content.height, Au::from_f64_px(2000.0));
+    // The line of an image alone is as high as the image.
+    assert_eq!(body.children[1].dimensions.content.height, Au::from_f64_px(10.0));
+    // Far below the part shown
+    assert!(laid_out.images_to_load.is_empty());
+
+    // Scrolled near it meanwhile
+    let mut area = viewport.content;
+    area.y = Au::from_f64_px(1900.0);
+    assert!(!containment::set_visible_area(Some(area)) && !lazy::came_near());
+    assert!(laid_out.keep_state());
+
+    quirks::set_mode(quirks::Mode::NoQuirks);
+    media::set_preferred_color_scheme(ColorScheme::Light);
+    containment::set_visible_area(None);
+    lazy::set_images(lazy::Images::default());
+}
@@ -0,0 +1,173 @@
+//! Lazy images: an ``<img loading=lazy>`` is not loaded until it comes near the part of the
+//! document shown (see ``containment::set_visible_area``). Until then, it is laid out as a
+//! placeholder as large as its ``width`` and ``height``, one of them and its ``aspect-ratio``, so
+//! that the page does not move when it arrives.
+//! ref. https://html.spec.whatwg.org/multipage/urls-and-fetching.html#lazy-loading-attributes
+//!
+//! Where an image is comes from the layout before, kept by the id of its node like the
+//! ``content-visibility: auto`` boxes, so the page is laid out again (see ``update``) when an image
+//! not loaded is laid out near the part shown, or is scrolled near it (see ``came_near``).
+
+use containment;
+use dom::{Node, NodeType};
+use layout::{self, LayoutBox, LayoutInfo, Rect};
+use overflow;
+
+use app_units::Au;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// A lazy image laid out
+#[derive(Clone, Debug)]
+struct LazyImage {
+    url: String,
+    /// In the document, None until it is known
+    border_box: Option<Rect>,
+    /// Once it came near
+    loaded: bool,
+}
+
+thread_local!(
+    /// The lazy images laid out, by the id of their node
+    static LAZY_IMAGES: RefCell<HashMap<usize, LazyImage>> = { RefCell::new(HashMap::new()) };
+);
+
+/// Whether ``node`` is an image loaded lazily (``loading=lazy``).
+pub fn is_lazy(node: &Node) -> bool {
+    match node.data {
+        NodeType::Element(ref e) => e.attrs
+            .get("loading")
+            .is_some_and(|loading| loading.eq_ignore_ascii_case("lazy")),
+        NodeType::Text(_) => false,
+    }
+}
+
+/// Whether the image at ``url`` of ``node`` (with ``node_id``) is not loaded yet in the layout
+/// going on: it is lazy and has not come near the part of the document shown.
+pub fn defers(node: &Node, node_id: Option<usize>, url: &str) -> bool {
+    let id = match node_id {
+        Some(id) if is_lazy(node) => id,
+        _ => return false,
+    };
+    LAZY_IMAGES.with(|i| {
+        let mut images = i.borrow_mut();
+        let new_image = || LazyImage {
+            url: url.to_string(),
+            border_box: None,
+            loaded: false,
+        };
+        let image = images.entry(id).or_insert_with(new_image);
+        // Another image of the node (e.g. of another page)
+        if image.url != url {
+            *image = new_image();
+        }
+        image.loaded |= containment::is_near(image.border_box);
+        !image.loaded
+    })
+}
+
+/// Keeps where the lazy images of the laid out tree ``root`` are. Returns whether it has to be
+/// laid out again: an image not loaded is near the part of the document shown.
+pub fn update(root: &LayoutBox) -> bool {
+    fn update_box(layout_box: &LayoutBox, x: Au, y: Au, changed: &mut bool) {
+        if let (&LayoutInfo::Image(_), Some(id)) = (&layout_box.info, layout_box.node_id) {
+            let border_box = layout_box.dimensions.border_box().add_parent_coordinate(x, y);
+            LAZY_IMAGES.with(|i| {
+                if let Some(image) = i.borrow_mut().get_mut(&id) {
+                    image.border_box = Some(border_box);
+                    *changed |= !image.loaded && containment::is_near(image.border_box);
+                }
+            });
+        }
+        let (child_x, child_y) = overflow::child_origin(layout_box, x, y);
+        for child in &layout_box.children {
+            update_box(child, child_x, child_y, changed);
+        }
+    }
+
+    if LAZY_IMAGES.with(|i| i.borrow().values().all(|image| image.loaded)) {
+        return false;
+    }
+    let mut changed = false;
+    update_box(root, Au(0), Au(0), &mut changed);
+    changed
+}
+
+/// Whether an image not loaded is near the part of the document shown now, e.g. after it is
+/// scrolled. Then, the page has to be laid out again to load it.
+pub fn came_near() -> bool {
+    let near = LAZY_IMAGES.with(|i| {
+        i.borrow()
+            .values()
+            .any(|image| !image.loaded && containment::is_near(image.border_box))
+    });
+    if near {
+        layout::invalidate_layout();
+    }
+    near
+}
+
+/// The lazy images laid out on a thread, to lay out on another one (see ``layout_worker``)
+#[derive(Clone, Debug, Default)]
+pub struct Images(HashMap<usize, LazyImage>);
+
+/// The lazy images laid out on this thread
+pub fn images() -> Images {
+    Images(LAZY_IMAGES.with(|i| i.borrow().clone()))
+}
+
+/// Keeps ``images`` (laid out on another thread) instead of those laid out on this thread. Returns
+/// whether the page has to be laid out again, like ``came_near``, if an image not loaded has been
+/// scrolled near meanwhile.
+pub fn set_images(images: Images) -> bool {
+    LAZY_IMAGES.with(|i| *i.borrow_mut() = images.0);
+    came_near()
+}
+
+#[test]
+fn test_lazy_images() {
+    use inline;
+    use layout::Dimensions;
+
+    let mut viewport: Dimensions = Default::default();
+    viewport.content.width = Au::from_f64_px(100.0);
+    viewport.content.height = Au::from_f64_px(100.0);
+    let layout = || {
+        layout::lay_out_html(
+            "<!DOCTYPE html><html><body><div></div><img loading=\"lazy\" src=\"a.png\" \
+             width=\"40\" style=\"aspect-ratio: 2 / 1\"><img loading=\"LAZY\" src=\"b.png\" \
+             width=\"10\" height=\"10\"></body></html>",
+            "div { height: 500px; }",
+            100.0,
+            100.0,
+        )
+    };
+    LAZY_IMAGES.with(|i| i.borrow_mut().clear());
+    inline::defer_image_loads();
+
+    // Far below the part shown, as large as their placeholders
+    assert!(!containment::set_visible_area(Some(viewport.content)));
+    let root = layout();
+    let line = &root.children[0].children[1];
+    assert_eq!(line.children[0].dimensions.content.height, Au::from_f64_px(20.0));
+    assert_eq!(line.children[1].dimensions.content.width, Au::from_f64_px(10.0));
+    assert!(inline::deferred_image_loads().is_empty());
+    assert!(!came_near());
+
+    // Scrolled near
+    inline::defer_image_loads();
+    let mut area = viewport.content;
+    area.y = Au::from_f64_px(350.0);
+    containment::set_visible_area(Some(area));
+    assert!(came_near());
+    layout();
+    assert_eq!(
+        inline::deferred_image_loads(),
+        vec!["file:///a.png".to_string(), "file:///b.png".to_string()]
+    );
+    // Not deferred again when scrolled away
+    containment::set_visible_area(Some(viewport.content));
+    assert!(!came_near());
+    containment::set_visible_area(None);
+}
@@ -1,22 +1,101 @@
+#[macro_use]
+pub mod trace;
 pub mod css;
+pub mod media;
 pub mod style;
 pub mod default_style;
+pub mod generated;
 pub mod html;
+pub mod quirks;
 pub mod dom;
 pub mod font;
+pub mod bidi;
+pub mod hyphenation;
+pub mod linebreak;
 pub mod inline;
 pub mod block;
 pub mod float;
+pub mod flex;
+pub mod grid;
+pub mod shape;
+pub mod background;
+pub mod overflow;
+pub mod containment;
+pub mod scroll;
+pub mod filter;
+pub mod svg;
+pub mod mathml;
+pub mod srcset;
+pub mod lazy;
+#[cfg(feature = "raster")]
+pub mod animated_image;
+pub mod paged;
 pub mod layout;
+pub mod layout_worker;
+pub mod controls;
 pub mod painter;
+#[cfg(feature = "raster")]
+pub mod raster;
+#[cfg(feature = "raster")]
+pub mod gpu;
+#[cfg(feature = "raster")]
+pub mod tile;
+pub mod frame;
+pub mod canvas;
+pub mod canvas2d;
+pub mod image;
+pub mod selection;
+pub mod inspector;
+pub mod js_parser;
+pub mod js_interpreter;
+pub mod script;
+pub mod event;
+pub mod task;
+#[cfg(feature = "gtk")]
 pub mod window;
+pub mod network;
+pub mod cache;
+pub mod cookie;
+pub mod loader;
+pub mod history;
+pub mod stats;
 pub mod interface;
+pub mod document;
+#[cfg(feature = "raster")]
+pub mod reftest;
+#[cfg(feature = "raster")]
+pub mod layout_snapshot;
+#[cfg(feature = "raster")]
+pub mod wpt;
+pub mod web;
 
-extern crate app_units;
+#[cfg(feature = "trace")]
+#[macro_use]
+extern crate log;
+#[cfg(feature = "gpu")]
+extern crate pollster;
+#[cfg(feature = "gpu")]
+extern crate wgpu;
+#[cfg(feature = "gtk")]
+extern crate gtk;
+#[cfg(feature = "raster")]
 extern crate cairo;
+#[cfg(feature = "raster")]
 extern crate gdk;
+#[cfg(feature = "raster")]
 extern crate gdk_pixbuf;
+#[cfg(feature = "raster")]
 extern crate glib;
-extern crate gtk;
+#[cfg(feature = "raster")]
 extern crate pango;
+#[cfg(feature = "raster")]
+extern crate pango_sys;
+#[cfg(feature = "raster")]
 extern crate pangocairo;
+
+extern crate app_units;
+extern crate url;
+
+// The window is painted with cairo.
+#[cfg(all(feature = "gtk", not(feature = "raster")))]
+compile_error!("the \"gtk\" feature needs the \"raster\" feature");
@@ -0,0 +1,204 @@
+//! Line breaking opportunities after a simplified Unicode Line Breaking Algorithm.
+//! ref. https://www.unicode.org/reports/tr14/
+//!
+//! The classes are merged into the ones that make a difference in the texts of boxes. Mandatory
+//! breaks don't occur in them (the HTML parser collapses newlines), and the rules for Hangul jamo,
+//! regional indicators and some of the rules for quotes and numbers are left out.
+
+use font::is_combining_mark;
+
+/// The line breaking classes of characters, merged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BreakClass {
+    /// Letters and most symbols (AL, HL, PR, PO, etc.)
+    Alphabetic,
+    /// Ideographs, kana, Hangul syllables and emoji (ID, H2, H3, EB), apart from one another
+    Ideographic,
+    /// NU
+    Numeric,
+    /// SP
+    Space,
+    /// ZW
+    ZeroWidthSpace,
+    /// No break on either side (GL, WJ)
+    Glue,
+    /// OP: no break after
+    Open,
+    /// CL (e.g. "}" and "。"): no break before
+    Close,
+    /// CP (")" and "]"): no break before, nor between it and letters after it
+    CloseParenthesis,
+    /// QU: no break on either side unless there are spaces
+    Quote,
+    /// EX ("!" and "?"): no break before
+    Exclamation,
+    /// IS ("," and "."): no break before, nor between it and letters after it
+    Infix,
+    /// SY ("/"): no break before, nor between it and digits after it
+    Symbol,
+    /// NS and IN (e.g. small kana, the prolonged sound mark and "…"): no break before
+    NonStarter,
+    /// HY ("-"): no break before, nor between it and digits after it
+    Hyphen,
+    /// BA (e.g. the tab, soft hyphens and dashes): no break before
+    BreakAfter,
+    /// CM and ZWJ: drawn with the character before it
+    CombiningMark,
+}
+
+pub fn break_class(c: char) -> BreakClass {
+    use self::BreakClass::*;
+
+    if is_combining_mark(c) {
+        return CombiningMark;
+    }
+    match c as u32 {
+        0x20 => Space,
+        0x200B => ZeroWidthSpace,
+        0xA0 | 0x2007 | 0x202F | 0x2060 | 0xFEFF => Glue,
+        0x09 | 0xAD | 0x2000...0x2006 | 0x2008...0x200A | 0x2010 | 0x2012...0x2014 | 0x3000
+        | 0x7C => BreakAfter,
+        0x2D => Hyphen,
+        0x28 | 0x5B | 0x7B | 0xA1 | 0xBF | 0x3008 | 0x300A | 0x300C | 0x300E | 0x3010 | 0x3014
+        | 0x3016 | 0x3018 | 0x301A | 0xFF08 | 0xFF3B | 0xFF5B | 0xFF5F | 0xFF62 => Open,
+        0x29 | 0x5D => CloseParenthesis,
+        0x7D | 0x3001 | 0x3002 | 0x3009 | 0x300B | 0x300D | 0x300F | 0x3011 | 0x3015 | 0x3017
+        | 0x3019 | 0x301B | 0xFF09 | 0xFF0C | 0xFF0E | 0xFF3D | 0xFF5D | 0xFF60 | 0xFF61
+        | 0xFF63 | 0xFF64 => Close,
+        0x22 | 0x27 | 0xAB | 0xBB | 0x2018 | 0x2019 | 0x201C | 0x201D | 0x2039 | 0x203A => Quote,
+        0x21 | 0x3F | 0xFF01 | 0xFF1F => Exclamation,
+        0x2C | 0x2E | 0x3A | 0x3B | 0x37E | 0x589 | 0x60C | 0x60D | 0x2044 | 0xFE10 | 0xFE13
+        | 0xFE14 => Infix,
+        0x2F => Symbol,
+        0x2025 | 0x2026 | 0x203C | 0x203D | 0x2047...0x2049 | 0x3005 | 0x303B | 0x309B...0x309E
+        | 0x30A0 | 0x30FB...0x30FE | 0x31F0...0x31FF | 0xFF65 | 0xFF67...0xFF70 | 0xFF9E
+        | 0xFF9F => NonStarter,
+        // Small kana
+        0x3041 | 0x3043 | 0x3045 | 0x3047 | 0x3049 | 0x3063 | 0x3083 | 0x3085 | 0x3087 | 0x308E
+        | 0x3095 | 0x3096 | 0x30A1 | 0x30A3 | 0x30A5 | 0x30A7 | 0x30A9 | 0x30C3 | 0x30E3
+        | 0x30E5 | 0x30E7 | 0x30EE | 0x30F5 | 0x30F6 => NonStarter,
+        0x30...0x39 | 0x660...0x669 | 0x6F0...0x6F9 | 0x966...0x96F => Numeric,
+        0x2E80...0x2FFF // CJK radicals, etc.
+        | 0x3040...0x31EF // Kana, Bopomofo, etc.
+        | 0x3200...0x4DBF | 0x4E00...0x9FFF | 0xF900...0xFAFF // CJK ideographs, etc.
+        | 0xA000...0xA4CF // Yi
+        | 0xAC00...0xD7A3 // Hangul syllables
+        | 0xFE30...0xFE4F | 0xFF00...0xFFEF // Fullwidth forms
+        | 0x1F000...0x1FAFF // Emoji
+        | 0x20000...0x3FFFD => Ideographic,
+        _ => Alphabetic,
+    }
+}
+
+/// Whether a line may break between a character of the class ``before`` and one of ``after``.
+/// ``spaces`` is whether there are spaces between them.
+fn can_break(before: BreakClass, after: BreakClass, spaces: bool) -> bool {
+    use self::BreakClass::*;
+
+    match (before, after) {
+        // LB7, LB8
+        (_, ZeroWidthSpace) => false,
+        (ZeroWidthSpace, _) => true,
+        // LB11, LB12
+        (Glue, _) | (_, Glue) if !spaces => false,
+        // LB13
+        (_, Close) | (_, CloseParenthesis) | (_, Exclamation) | (_, Infix) | (_, Symbol) => false,
+        // LB14
+        (Open, _) => false,
+        // LB19
+        (Quote, _) | (_, Quote) if !spaces => false,
+        // LB18
+        _ if spaces => true,
+        // LB21
+        (_, NonStarter) | (_, Hyphen) | (_, BreakAfter) => false,
+        // LB23, LB25, LB28, LB29
+        (Alphabetic, Alphabetic)
+        | (Alphabetic, Numeric)
+        | (Numeric, Alphabetic)
+        | (Numeric, Numeric)
+        | (Infix, Alphabetic)
+        | (Infix, Numeric)
+        | (Symbol, Numeric)
+        | (Hyphen, Numeric) => false,
+        // LB30
+        (Alphabetic, Open)
+        | (Numeric, Open)
+        | (CloseParenthesis, Alphabetic)
+        | (CloseParenthesis, Numeric) => false,
+        // LB31
+        _ => true,
+    }
+}
+
+/// The byte offsets in ``s`` where a line may break (before the character at the offset). The
+/// end of ``s`` is included if ``s`` ends with spaces, since a line may break after spaces
+/// whatever follows them.
+pub fn break_opportunities(s: &str) -> Vec<usize> {
+    let mut opportunities = vec![];
+    // The class of the last character other than spaces
+    let mut last: Option<BreakClass> = None;
+    let mut spaces = false;
+    let mut after_zwj = false;
+
+    for (i, c) in s.char_indices() {
+        let class = break_class(c);
+        let zwj = after_zwj;
+        after_zwj = c == '\u{200D}';
+        match class {
+            // LB7
+            BreakClass::Space => {
+                spaces = last.is_some();
+                continue;
+            }
+            // LB8a, LB9
+            _ if zwj => continue,
+            BreakClass::CombiningMark if last.is_some() && !spaces => continue,
+            _ => {}
+        }
+
+        // LB10: Marks without a base are letters.
+        let class = if class == BreakClass::CombiningMark {
+            BreakClass::Alphabetic
+        } else {
+            class
+        };
+        if let Some(before) = last {
+            if can_break(before, class, spaces) {
+                opportunities.push(i);
+            }
+        }
+        last = Some(class);
+        spaces = false;
+    }
+
+    if spaces {
+        opportunities.push(s.len());
+    }
+    opportunities
+}
+
+#[test]
+fn test_break_opportunities() {
+    let split = |s: &str| {
+        let mut parts = vec![];
+        let mut start = 0;
+        for end in break_opportunities(s) {
+            parts.push(s[start..end].to_string());
+            start = end;
+        }
+        parts.push(s[start..].to_string());
+        parts
+    };
+
+    assert_eq!(split("hello world "), vec!["hello ", "world ", ""]);
+    assert_eq!(split("a-b (c) d.e, 1-2"), vec!["a-", "b ", "(c) ", "d.e, ", "1-2"]);
+    assert_eq!(split("see example.com/index"), vec!["see ", "example.com/", "index"]);
+    // CJK text has no spaces. Not before a closing mark or a small kana.
+    assert_eq!(split("日本語。ちょっと"), vec!["日", "本", "語。", "ちょっ", "と"]);
+    assert_eq!(split("「漢字」です"), vec!["「漢", "字」", "で", "す"]);
+    assert_eq!(split("abc日本"), vec!["abc", "日", "本"]);
+    assert_eq!(split("10\u{a0}km"), vec!["10\u{a0}km"]);
+    assert_eq!(split("e\u{301} \u{301}"), vec!["e\u{301} ", "\u{301}"]);
+    assert_eq!(split("\"quoted\"!"), vec!["\"quoted\"!"]);
+    assert_eq!(split(""), vec![""]);
+}
@@ -0,0 +1,120 @@
+use css;
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+/// A subresource loaded on a worker thread.
+#[derive(Debug)]
+pub enum Loaded {
+    /// The author stylesheet, with its ``@import``s resolved.
+    Stylesheet(css::Stylesheet),
+    /// (image URL, downloaded file name)
+    Image(String, String),
+    /// The icon of the page: (icon URL, downloaded file name)
+    Icon(String, String),
+    Failed,
+}
+
+type Message = (usize, Loaded); // (generation, loaded resource)
+
+thread_local!(
+    static CHANNEL: (Sender<Message>, Receiver<Message>) = { channel() };
+    static GENERATION: Cell<usize> = { Cell::new(0) };
+    static REQUESTED: RefCell<HashSet<String>> = { RefCell::new(HashSet::new()) };
+    // The number of the requested resources received by ``finished()``
+    static RECEIVED: Cell<usize> = { Cell::new(0) };
+);
+
+/// Discards the resources still being loaded for the previous page.
+pub fn start_new_page() {
+    GENERATION.with(|g| g.set(g.get() + 1));
+    REQUESTED.with(|r| r.borrow_mut().clear());
+    RECEIVED.with(|r| r.set(0));
+}
+
+/// Runs ``job`` on a worker thread unless ``key`` (usually URL) is already requested on this
+/// page. The result is received by ``finished()``.
+pub fn spawn<F>(key: &str, job: F)
+where
+    F: FnOnce() -> Loaded + Send + 'static,
+{
+    if !REQUESTED.with(|r| r.borrow_mut().insert(key.to_string())) {
+        return;
+    }
+
+    let generation = GENERATION.with(|g| g.get());
+    let sender = CHANNEL.with(|c| c.0.clone());
+    thread::spawn(move || {
+        // Failing to load a subresource must not bring down the whole browser.
+        let loaded = catch_unwind(AssertUnwindSafe(job)).unwrap_or(Loaded::Failed);
+        sender.send((generation, loaded)).ok();
+    });
+}
+
+/// Returns the resources loaded for the current page since the last call.
+pub fn finished() -> Vec<Loaded> {
+    let generation = GENERATION.with(|g| g.get());
+    let loaded: Vec<Loaded> = CHANNEL.with(|c| {
+        c.1
+            .try_iter()
+            .filter(|&(g, _)| g == generation)
+            .map(|(_, loaded)| loaded)
+            .collect()
+    });
+    RECEIVED.with(|r| r.set(r.get() + loaded.len()));
+    loaded
+}
+
+/// (received, requested): how many of the resources requested on this page are received by
+/// ``finished()`` (loaded or failed).
+pub fn progress() -> (usize, usize) {
+    (
+        RECEIVED.with(|r| r.get()),
+        REQUESTED.with(|r| r.borrow().len()),
+    )
+}
+
+#[test]
+fn test_spawn() {
+    use std::time::Duration;
+
+    fn wait() -> Vec<Loaded> {
+        for _ in 0..100 {
+            let loaded = finished();
+            if !loaded.is_empty() {
+                return loaded;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        vec![]
+    }
+
+    start_new_page();
+    spawn("a.png", || Loaded::Image("a.png".to_string(), "cache/a.png".to_string()));
+    // Requested twice, loaded once.
+    spawn("a.png", || Loaded::Failed);
+    match wait().as_slice() {
+        &[Loaded::Image(ref url, _)] => assert_eq!(url, "a.png"),
+        loaded => panic!("{:?}", loaded),
+    }
+    assert_eq!(progress(), (1, 1));
+
+    spawn("b.css", || panic!("network error"));
+    match wait().as_slice() {
+        &[Loaded::Failed] => {}
+        loaded => panic!("{:?}", loaded),
+    }
+
+    // Results for the previous page are dropped.
+    spawn("c.css", || {
+        thread::sleep(Duration::from_millis(100));
+        Loaded::Failed
+    });
+    start_new_page();
+    assert_eq!(progress(), (0, 0));
+    thread::sleep(Duration::from_millis(200));
+    assert!(finished().is_empty());
+}
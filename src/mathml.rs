@@ -0,0 +1,308 @@
+//! MathML: a ``<math>`` is laid out like an inline replaced element, as large as its formula
+//! (see ``MathLayout::of``), with the baseline of the formula on that of the line. Its subtree is
+//! parsed into ``MathNode``s: ``mrow``, ``mfrac``, ``msup``, ``msub``, ``msubsup`` and the tokens
+//! ``mi``, ``mn``, ``mo`` and ``mtext``. Other elements are rows.
+//! ref. https://w3c.github.io/mathml-core/
+//!
+//! The scripts and the parts of the fractions are smaller than their base (by
+//! ``SCRIPT_SIZE_MULTIPLIER``, down to ``SCRIPT_MIN_SIZE``), as in inline formulas.
+
+use dom::{Node, NodeType};
+use font::{Font, FontSlant};
+
+use app_units::Au;
+
+/// How much smaller the scripts are than their base
+pub const SCRIPT_SIZE_MULTIPLIER: f64 = 0.71;
+
+/// The size (px) the scripts are not made smaller than
+pub const SCRIPT_MIN_SIZE: f64 = 8.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TokenKind {
+    /// ``mi``, in italic if it is a single character
+    Identifier,
+    /// ``mn``
+    Number,
+    /// ``mo``, spaced by its kind
+    Operator,
+    /// ``mtext``
+    Text,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MathNode {
+    Row(Vec<MathNode>),
+    /// The numerator and the denominator
+    Fraction(Box<MathNode>, Box<MathNode>),
+    /// The base, the subscript and the superscript
+    Scripts(Box<MathNode>, Option<Box<MathNode>>, Option<Box<MathNode>>),
+    Token(TokenKind, String),
+}
+
+impl MathNode {
+    /// Parses the subtree of ``node`` (e.g. a ``<math>``). A fraction or a script without all of
+    /// its children is a row.
+    pub fn from_node(node: &Node) -> MathNode {
+        let tag_name = match node.data {
+            NodeType::Element(ref e) => e.tag_name.as_str(),
+            NodeType::Text(ref text) => return MathNode::Token(TokenKind::Text, text.clone()),
+        };
+        let token = |kind| MathNode::Token(kind, node.text_content().trim().to_string());
+        let children: Vec<MathNode> = node.children
+            .iter()
+            .filter(|child| match child.data {
+                NodeType::Element(_) => true,
+                NodeType::Text(_) => false,
+            })
+            .map(MathNode::from_node)
+            .collect();
+        let part = |i: usize| Box::new(children[i].clone());
+        match (tag_name, children.len()) {
+            ("mi", _) => token(TokenKind::Identifier),
+            ("mn", _) => token(TokenKind::Number),
+            ("mo", _) => token(TokenKind::Operator),
+            ("mtext", _) => token(TokenKind::Text),
+            ("mfrac", 2) => MathNode::Fraction(part(0), part(1)),
+            ("msub", 2) => MathNode::Scripts(part(0), Some(part(1)), None),
+            ("msup", 2) => MathNode::Scripts(part(0), None, Some(part(1))),
+            ("msubsup", 3) => MathNode::Scripts(part(0), Some(part(1)), Some(part(2))),
+            _ => MathNode::Row(children),
+        }
+    }
+}
+
+/// What a formula paints, placed from the start of its baseline (px, downward)
+#[derive(Clone, Debug, PartialEq)]
+pub enum MathItem {
+    /// The text, and the start of its baseline
+    Text(String, (f64, f64), Font),
+    /// The x, the y, the width and the height of a fraction bar
+    Bar(f64, f64, f64, f64),
+}
+
+/// A formula laid out (px)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MathLayout {
+    pub width: f64,
+    /// Above the baseline
+    pub ascent: f64,
+    /// Under the baseline
+    pub descent: f64,
+    pub items: Vec<MathItem>,
+}
+
+impl MathLayout {
+    /// Lays out the formula of the ``<math>`` ``node`` in ``font``.
+    pub fn of(node: &Node, font: Font) -> MathLayout {
+        layout(&MathNode::from_node(node), font)
+    }
+
+    /// Appends ``other`` with the start of its baseline at (``x``, ``y``).
+    fn place(&mut self, other: MathLayout, x: f64, y: f64) {
+        self.width = self.width.max(x + other.width);
+        self.ascent = self.ascent.max(other.ascent - y);
+        self.descent = self.descent.max(other.descent + y);
+        self.items.extend(other.items.into_iter().map(|item| match item {
+            MathItem::Text(text, (left, baseline), font) => {
+                MathItem::Text(text, (left + x, baseline + y), font)
+            }
+            MathItem::Bar(left, top, width, height) => {
+                MathItem::Bar(left + x, top + y, width, height)
+            }
+        }));
+    }
+}
+
+/// The font of the scripts of a base in ``font``
+fn script_font(font: Font) -> Font {
+    let size = font.size.to_f64_px();
+    Font {
+        size: Au::from_f64_px((size * SCRIPT_SIZE_MULTIPLIER).max(SCRIPT_MIN_SIZE.min(size))),
+        ..font
+    }
+}
+
+/// The space (in em) before and after an operator: wide around relations, narrower around
+/// binary operators, and after separators.
+/// ref. https://w3c.github.io/mathml-core/#operator-dictionary
+fn operator_spacing(operator: &str) -> (f64, f64) {
+    match operator {
+        "=" | "<" | ">" | "≤" | "≥" | "≠" | "≈" | "≡" | "→" | "←" | "⇒" => (0.2778, 0.2778),
+        "+" | "-" | "−" | "±" | "×" | "÷" | "·" | "∗" => (0.2222, 0.2222),
+        "," | ";" => (0.0, 0.1667),
+        _ => (0.0, 0.0),
+    }
+}
+
+fn layout(node: &MathNode, font: Font) -> MathLayout {
+    let em = font.size.to_f64_px();
+    match *node {
+        MathNode::Token(kind, ref text) => {
+            // The minus sign, not the hyphen
+            let text = if kind == TokenKind::Operator && text == "-" { "−" } else { text };
+            let font = match kind {
+                TokenKind::Identifier if text.chars().count() == 1 => Font {
+                    slant: FontSlant::Italic,
+                    ..font
+                },
+                _ => font,
+            };
+            let (before, after) = match kind {
+                TokenKind::Operator => operator_spacing(text),
+                _ => (0.0, 0.0),
+            };
+            let selected = font.select();
+            let (ascent, descent) = selected.get_ascent_descent();
+            let width = selected.text_width(text);
+            MathLayout {
+                width: (before + after) * em + width,
+                ascent: ascent.to_f64_px(),
+                descent: descent.to_f64_px(),
+                items: if text.is_empty() {
+                    vec![]
+                } else {
+                    vec![MathItem::Text(text.to_string(), (before * em, 0.0), font)]
+                },
+            }
+        }
+        MathNode::Row(ref children) => {
+            let mut row = MathLayout::default();
+            for child in children {
+                let x = row.width;
+                row.place(layout(child, font), x, 0.0);
+            }
+            row
+        }
+        MathNode::Fraction(ref numerator, ref denominator) => {
+            let part_font = script_font(font);
+            let numerator = layout(numerator, part_font);
+            let denominator = layout(denominator, part_font);
+            // The bar is on the math axis, at the middle of the minus sign.
+            let thickness = (em * 0.06).max(1.0);
+            let axis = em * 0.25;
+            let gap = thickness.max(em * 0.1);
+            let padding = (em * 0.1).max(1.0);
+            let width = numerator.width.max(denominator.width) + padding * 2.0;
+
+            let mut fraction = MathLayout {
+                width: width,
+                ascent: axis + thickness / 2.0,
+                descent: 0.0,
+                items: vec![MathItem::Bar(0.0, -axis - thickness / 2.0, width, thickness)],
+            };
+            let numerator_y = -axis - thickness / 2.0 - gap - numerator.descent;
+            let denominator_y = -axis + thickness / 2.0 + gap + denominator.ascent;
+            let (numerator_x, denominator_x) = (
+                (width - numerator.width) / 2.0,
+                (width - denominator.width) / 2.0,
+            );
+            fraction.place(numerator, numerator_x, numerator_y);
+            fraction.place(denominator, denominator_x, denominator_y);
+            fraction
+        }
+        MathNode::Scripts(ref base, ref subscript, ref superscript) => {
+            let base = layout(base, font);
+            let script_font = script_font(font);
+            let subscript = subscript.as_ref().map(|script| layout(script, script_font));
+            let superscript = superscript.as_ref().map(|script| layout(script, script_font));
+
+            // Raised to the top of the base, and lowered to its bottom
+            let mut superscript_shift = superscript
+                .as_ref()
+                .map_or(0.0, |script| (em * 0.4).max(base.ascent - script.ascent));
+            let mut subscript_shift = subscript
+                .as_ref()
+                .map_or(0.0, |script| (em * 0.2).max(base.descent - script.descent));
+            if let (Some(subscript), Some(superscript)) = (subscript.as_ref(), superscript.as_ref())
+            {
+                // Apart from each other
+                let gap = (superscript_shift - superscript.descent)
+                    - (subscript.ascent - subscript_shift);
+                if gap < em * 0.2 {
+                    subscript_shift += em * 0.2 - gap;
+                }
+            } else if superscript.is_some() {
+                superscript_shift = superscript_shift.max(em * 0.3);
+            }
+
+            let x = base.width;
+            let mut scripts = MathLayout::default();
+            scripts.place(base, 0.0, 0.0);
+            let space = em * 0.05;
+            if let Some(subscript) = subscript {
+                scripts.place(subscript, x, subscript_shift);
+            }
+            if let Some(superscript) = superscript {
+                scripts.place(superscript, x, -superscript_shift);
+            }
+            scripts.width += space;
+            scripts
+        }
+    }
+}
+
+#[test]
+fn test_math_layout() {
+    use html;
+    use network::Url;
+
+    let document = html::parse(
+        "<!DOCTYPE html><math><msup><mi>x</mi><mn>2</mn></msup><mo>-</mo>\
+         <mfrac><mn>1</mn><mi>y</mi></mfrac><mspace/></math>"
+            .to_string(),
+        Url::parse("file:///a.html").unwrap(),
+    );
+    let math = &document;
+    let token = |kind, text: &str| Box::new(MathNode::Token(kind, text.to_string()));
+    assert_eq!(
+        MathNode::from_node(math),
+        MathNode::Row(vec![
+            MathNode::Scripts(
+                token(TokenKind::Identifier, "x"),
+                None,
+                Some(token(TokenKind::Number, "2"))
+            ),
+            *token(TokenKind::Operator, "-"),
+            MathNode::Fraction(token(TokenKind::Number, "1"), token(TokenKind::Identifier, "y")),
+            MathNode::Row(vec![]),
+        ])
+    );
+
+    let font = Font {
+        size: Au::from_f64_px(20.0),
+        ..Font::new_empty()
+    };
+    let base = layout(&MathNode::Token(TokenKind::Number, "1".to_string()), font);
+    let formula = MathLayout::of(math, font);
+    let (superscript, fraction_bar) = (&formula.items[1], &formula.items[3]);
+    // Raised and smaller
+    match *superscript {
+        MathItem::Text(ref text, (_, y), font) => {
+            assert_eq!(text, "2");
+            assert!(y <= -8.0);
+            assert_eq!(font.size, Au::from_f64_px(14.2));
+        }
+        ref item => panic!("{:?}", item),
+    }
+    match formula.items[0] {
+        MathItem::Text(_, _, font) => assert_eq!(font.slant, FontSlant::Italic),
+        ref item => panic!("{:?}", item),
+    }
+    match formula.items[2] {
+        MathItem::Text(ref text, (x, 0.0), _) => assert!(text == "−" && x > 0.0),
+        ref item => panic!("{:?}", item),
+    }
+    // The bar on the math axis, between the numerator and the denominator
+    match *fraction_bar {
+        MathItem::Bar(_, y, _, height) => assert_eq!((y + height / 2.0, height), (-5.0, 1.2)),
+        ref item => panic!("{:?}", item),
+    }
+    match (&formula.items[4], &formula.items[5]) {
+        (&MathItem::Text(_, (_, numerator), _), &MathItem::Text(_, (_, denominator), _)) => {
+            assert!(numerator < -5.0 && denominator > 0.0)
+        }
+        items => panic!("{:?}", items),
+    }
+    assert!(formula.ascent > base.ascent && formula.descent > base.descent);
+}
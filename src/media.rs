@@ -0,0 +1,341 @@
+//! Media queries: the rules in ``@media`` apply while one of its queries matches the screen and
+//! the preferences of the user. Of the media features, ``width`` and ``resolution`` (with their
+//! ``min-`` and ``max-`` prefixes) and ``prefers-color-scheme`` are supported; the queries testing
+//! the others never match. ref. https://www.w3.org/TR/mediaqueries-5/
+//!
+//! The width is that of the viewport last laid out (see ``layout::layout_tree``), and the
+//! resolution the device pixel ratio (see ``set_device_pixel_ratio``). They also select the
+//! sources of the responsive images (see ``srcset``).
+//!
+//! The color scheme preferred (see ``set_preferred_color_scheme``) is that of the system for the
+//! window, and also gives the system colors of the boxes in the schemes they support (see
+//! ``style::Style::color_scheme``).
+//!
+//! The media type is ``screen``, or ``print`` while a document is printed (see ``paged``).
+
+use css::{Color, BLACK, GRAY, WHITE};
+
+use std::cell::Cell;
+use std::fmt;
+
+thread_local!(
+    static PREFERRED_COLOR_SCHEME: Cell<ColorScheme> = { Cell::new(ColorScheme::Light) };
+    static VIEWPORT_WIDTH: Cell<f64> = { Cell::new(0.0) };
+    static DEVICE_PIXEL_RATIO: Cell<f64> = { Cell::new(1.0) };
+    static MEDIA_TYPE: Cell<MediaType> = { Cell::new(MediaType::Screen) };
+);
+
+/// What the document is shown on, which the queries of ``screen`` or ``print`` test
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MediaType {
+    Screen,
+    Print,
+}
+
+impl MediaType {
+    pub fn name(self) -> &'static str {
+        match self {
+            MediaType::Screen => "screen",
+            MediaType::Print => "print",
+        }
+    }
+}
+
+/// ``light`` or ``dark``, of ``prefers-color-scheme`` and ``color-scheme``
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+impl ColorScheme {
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorScheme::Light => "light",
+            ColorScheme::Dark => "dark",
+        }
+    }
+
+    /// The system color ``name`` (in lowercase, e.g. ``canvastext``) in the scheme. None if it is
+    /// not a system color.
+    pub fn system_color(self, name: &str) -> Option<Color> {
+        let rgb = |r, g, b| Color { r: r, g: g, b: b, a: 0xff };
+        let (light, dark) = match name {
+            "canvas" => (WHITE, rgb(0x12, 0x12, 0x12)),
+            "canvastext" | "fieldtext" | "buttontext" => (BLACK, WHITE),
+            "linktext" => (rgb(0x00, 0x00, 0xee), rgb(0x9e, 0x9e, 0xff)),
+            "field" => (WHITE, rgb(0x3b, 0x3b, 0x3b)),
+            "buttonface" => (rgb(0xef, 0xef, 0xef), rgb(0x6b, 0x6b, 0x6b)),
+            "buttonborder" => (GRAY, rgb(0x85, 0x85, 0x85)),
+            _ => return None,
+        };
+        Some(match self {
+            ColorScheme::Light => light,
+            ColorScheme::Dark => dark,
+        })
+    }
+}
+
+/// Sets the color scheme preferred by the user, e.g. when the system setting changes. Returns
+/// whether it changed (and the page has to be styled again).
+pub fn set_preferred_color_scheme(scheme: ColorScheme) -> bool {
+    PREFERRED_COLOR_SCHEME.with(|p| p.replace(scheme)) != scheme
+}
+
+pub fn preferred_color_scheme() -> ColorScheme {
+    PREFERRED_COLOR_SCHEME.with(|p| p.get())
+}
+
+/// Sets the width of the viewport (CSS px) the queries test. Returns whether it changed.
+pub fn set_viewport_width(width: f64) -> bool {
+    VIEWPORT_WIDTH.with(|w| w.replace(width)) != width
+}
+
+pub fn viewport_width() -> f64 {
+    VIEWPORT_WIDTH.with(|w| w.get())
+}
+
+/// Sets the device px per CSS px (e.g. 2 on HiDPI displays, times the zoom). Returns whether it
+/// changed (and the page has to be laid out again).
+pub fn set_device_pixel_ratio(ratio: f64) -> bool {
+    DEVICE_PIXEL_RATIO.with(|r| r.replace(ratio)) != ratio
+}
+
+pub fn device_pixel_ratio() -> f64 {
+    DEVICE_PIXEL_RATIO.with(|r| r.get())
+}
+
+/// Sets the media type, e.g. ``print`` while printing. Returns whether it changed (and the page
+/// has to be styled again).
+pub fn set_media_type(media_type: MediaType) -> bool {
+    MEDIA_TYPE.with(|t| t.replace(media_type)) != media_type
+}
+
+pub fn media_type() -> MediaType {
+    MEDIA_TYPE.with(|t| t.get())
+}
+
+/// The length ``value`` in CSS px: px, em and rem (of the initial font size) and vw. None if it
+/// is not one.
+pub fn parse_length(value: &str) -> Option<f64> {
+    let value = value.trim().to_lowercase();
+    let number_end = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'))
+        .unwrap_or(value.len());
+    let number: f64 = value[..number_end].parse().ok()?;
+    match &value[number_end..] {
+        "px" => Some(number),
+        "em" | "rem" => Some(number * 16.0),
+        "vw" => Some(number * viewport_width() / 100.0),
+        "" if number == 0.0 => Some(0.0),
+        _ => None,
+    }
+}
+
+/// The resolution ``value`` in dppx (device px per CSS px), e.g. ``2x`` or ``192dpi``
+fn parse_resolution(value: &str) -> Option<f64> {
+    let value = value.trim().to_lowercase();
+    let number_end = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let number: f64 = value[..number_end].parse().ok()?;
+    match &value[number_end..] {
+        "dppx" | "x" => Some(number),
+        "dpi" => Some(number / 96.0),
+        "dpcm" => Some(number * 2.54 / 96.0),
+        _ => None,
+    }
+}
+
+/// A media query, e.g. ``not print and (prefers-color-scheme: dark)``
+#[derive(Clone, Debug, PartialEq)]
+pub struct MediaQuery {
+    /// ``not``: it matches where the rest does not.
+    pub negated: bool,
+    /// e.g. ``screen``, ``all`` if it has none
+    pub media_type: String,
+    /// The features tested, with their values, e.g. ``prefers-color-scheme`` and ``dark``
+    pub features: Vec<(String, Option<String>)>,
+}
+
+impl MediaQuery {
+    /// ``not all``, which invalid queries are
+    fn not_all() -> MediaQuery {
+        MediaQuery {
+            negated: true,
+            media_type: "all".to_string(),
+            features: vec![],
+        }
+    }
+
+    fn parse(source: &str) -> Option<MediaQuery> {
+        // The words and the features in parentheses
+        let source = source.to_lowercase();
+        let mut words = vec![];
+        let mut rest = source.trim();
+        while !rest.is_empty() {
+            let end = if rest.starts_with('(') {
+                rest.find(')')? + 1
+            } else {
+                rest.find(|c: char| c.is_whitespace() || c == '(')
+                    .unwrap_or(rest.len())
+            };
+            words.push(&rest[..end]);
+            rest = rest[end..].trim_start();
+        }
+
+        let mut query = MediaQuery {
+            negated: false,
+            media_type: "all".to_string(),
+            features: vec![],
+        };
+        let mut words = words.into_iter().peekable();
+        if let Some(&word) = words.peek().filter(|&&word| word == "not" || word == "only") {
+            query.negated = word == "not";
+            words.next();
+        }
+        let mut needs_and = false;
+        if let Some(&word) = words.peek().filter(|word| !word.starts_with('(')) {
+            if word == "and" || word == "not" || word == "only" {
+                return None;
+            }
+            query.media_type = word.to_string();
+            needs_and = true;
+            words.next();
+        }
+        for word in words {
+            if needs_and {
+                if word != "and" {
+                    return None;
+                }
+            } else {
+                let feature = word.strip_prefix('(')?.strip_suffix(')')?;
+                let mut parts = feature.splitn(2, ':');
+                let name = parts.next()?.trim().to_string();
+                let value = parts.next().map(|value| value.trim().to_string());
+                query.features.push((name, value));
+            }
+            needs_and = !needs_and;
+        }
+        // Nothing after ``and``, or ``not`` alone
+        if !needs_and {
+            return None;
+        }
+        Some(query)
+    }
+
+    /// Whether it matches now. A query testing a feature not supported never matches.
+    pub fn matches(&self) -> bool {
+        let mut matches = self.media_type == "all" || self.media_type == media_type().name();
+        for &(ref name, ref value) in &self.features {
+            match feature_matches(name, value.as_ref().map(|value| value.as_str())) {
+                Some(feature_matches) => matches &= feature_matches,
+                None => return false,
+            }
+        }
+        matches != self.negated
+    }
+}
+
+/// Whether the media feature ``name`` has ``value`` (or is not ``none`` without one). None if it
+/// is not supported.
+fn feature_matches(name: &str, value: Option<&str>) -> Option<bool> {
+    // ``min-`` and ``max-`` features test whether it is at least and at most the value.
+    let (feature, at_least) = match (name.strip_prefix("min-"), name.strip_prefix("max-")) {
+        (Some(feature), _) => (feature, Some(true)),
+        (_, Some(feature)) => (feature, Some(false)),
+        _ => (name, None),
+    };
+    let (actual, expected) = match (feature, value, at_least) {
+        ("width", Some(value), _) => (viewport_width(), parse_length(value)?),
+        ("resolution", Some(value), _) => (device_pixel_ratio(), parse_resolution(value)?),
+        ("width", None, None) | ("resolution", None, None) => return Some(true),
+        ("prefers-color-scheme", Some(value), None) => {
+            return Some(value == preferred_color_scheme().name())
+        }
+        ("prefers-color-scheme", None, None) => return Some(true),
+        _ => return None,
+    };
+    Some(match at_least {
+        Some(true) => actual >= expected,
+        Some(false) => actual <= expected,
+        None => actual == expected,
+    })
+}
+
+/// Parses the comma-separated media queries of ``@media`` (e.g. ``screen, print``). An invalid
+/// query is ``not all``.
+pub fn parse_query_list(source: &str) -> Vec<MediaQuery> {
+    if source.trim().is_empty() {
+        return vec![];
+    }
+    source
+        .split(',')
+        .map(|query| MediaQuery::parse(query).unwrap_or_else(MediaQuery::not_all))
+        .collect()
+}
+
+/// Whether one of ``queries`` matches now. No query matches all.
+pub fn matches(queries: &[MediaQuery]) -> bool {
+    queries.is_empty() || queries.iter().any(|query| query.matches())
+}
+
+impl fmt::Display for MediaQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.negated {
+            write!(f, "not ")?;
+        }
+        write!(f, "{}", self.media_type)?;
+        for &(ref name, ref value) in &self.features {
+            match *value {
+                Some(ref value) => write!(f, " and ({}: {})", name, value)?,
+                None => write!(f, " and ({})", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_media_queries() {
+    let query_matches = |source: &str| matches(&parse_query_list(source));
+
+    set_preferred_color_scheme(ColorScheme::Light);
+    assert!(query_matches(""));
+    assert!(query_matches("screen"));
+    assert!(query_matches("only screen and (prefers-color-scheme: light)"));
+    assert!(!query_matches("(prefers-color-scheme: dark)"));
+    assert!(query_matches("not all and (prefers-color-scheme: dark)"));
+    assert!(!query_matches("print"));
+    assert!(query_matches("print, (PREFERS-COLOR-SCHEME: LIGHT)"));
+    // Features not supported, and invalid queries
+    assert!(!query_matches("(orientation: portrait)"));
+    assert!(!query_matches("not all and (orientation: portrait)"));
+    assert!(!query_matches("(max-width: 600)"));
+    assert!(!query_matches("screen and"));
+    assert!(!query_matches("screen (prefers-color-scheme: light)"));
+
+    assert!(set_preferred_color_scheme(ColorScheme::Dark));
+    assert!(!set_preferred_color_scheme(ColorScheme::Dark));
+    assert!(query_matches("(prefers-color-scheme: dark)"));
+    assert_eq!(
+        parse_query_list("not screen and (prefers-color-scheme: dark)")[0].to_string(),
+        "not screen and (prefers-color-scheme: dark)"
+    );
+    set_preferred_color_scheme(ColorScheme::Light);
+
+    // The viewport and the display
+    set_viewport_width(800.0);
+    assert!(query_matches("(min-width: 600px)"));
+    assert!(!query_matches("screen and (max-width: 37.5em)"));
+    assert!(query_matches("(width: 800px) and (max-resolution: 1dppx)"));
+    assert!(set_device_pixel_ratio(2.0));
+    assert!(query_matches("(min-resolution: 192dpi) and (resolution: 2x)"));
+    assert!(query_matches("(width)"));
+    set_device_pixel_ratio(1.0);
+
+    // While printing
+    assert!(set_media_type(MediaType::Print));
+    assert!(query_matches("print") && !query_matches("screen") && query_matches("not screen"));
+    set_media_type(MediaType::Screen);
+}
@@ -0,0 +1,604 @@
+extern crate brotli_decompressor;
+extern crate libflate;
+#[cfg(feature = "network")]
+extern crate reqwest;
+#[cfg(feature = "network")]
+use self::reqwest::header::{CacheControl, CacheDirective, ContentEncoding, ContentType, ETag,
+                            Expires, Headers, LastModified, SetCookie, UserAgent};
+#[cfg(feature = "network")]
+pub use self::reqwest::header::HttpDate;
+#[cfg(feature = "network")]
+use self::reqwest::{Client, RedirectPolicy, StatusCode};
+pub use url::Url;
+
+#[cfg(feature = "network")]
+use cache;
+#[cfg(feature = "network")]
+use cache::CacheEntry;
+#[cfg(feature = "network")]
+use cookie;
+
+use std::fmt;
+use std::io;
+use std::io::Read;
+#[cfg(feature = "network")]
+use std::time::{Duration, SystemTime};
+
+pub const USER_AGENT: &'static str = concat!(
+    "Mozilla/5.0 (X11; Linux x86_64) Naglfar/",
+    env!("CARGO_PKG_VERSION")
+);
+
+/// Content codings decoded by ``decode_content``.
+pub const ACCEPT_ENCODING: &'static str = "gzip, deflate, br";
+
+/// Following more redirects than this fails.
+pub const MAX_REDIRECTS: usize = 10;
+
+#[cfg(feature = "network")]
+thread_local!(
+    static CLIENT: Client = {
+        let mut headers = Headers::new();
+        headers.set(UserAgent::new(USER_AGENT));
+        Client::builder()
+            .default_headers(headers)
+            .redirect(RedirectPolicy::limited(MAX_REDIRECTS))
+            // Decompressed by ourselves, since reqwest only supports gzip.
+            .gzip(false)
+            .build()
+            .expect("cannot create HTTP client")
+    };
+);
+
+/// What the fetched resource is used for. Decides the ``Accept`` header.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Destination {
+    Document,
+    Style,
+    Image,
+}
+
+impl Destination {
+    pub fn accept(&self) -> &'static str {
+        match *self {
+            Destination::Document => {
+                "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"
+            }
+            Destination::Style => "text/css,*/*;q=0.1",
+            Destination::Image => "image/png,image/svg+xml,image/*;q=0.8,*/*;q=0.5",
+        }
+    }
+}
+
+/// How ``fetch`` uses the HTTP cache.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CacheMode {
+    /// Fresh entries are used as they are, and stale ones are revalidated.
+    Default,
+    /// The cache is not read (hard reload). The response is still stored.
+    Reload,
+}
+
+#[derive(Clone, Debug)]
+pub struct Response {
+    /// The URL after redirects.
+    pub url: Url,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum FetchError {
+    #[cfg(feature = "network")]
+    Request(reqwest::Error),
+    #[cfg(feature = "network")]
+    Status(StatusCode),
+    Io(io::Error),
+    /// Built without the ``network`` feature
+    #[cfg(not(feature = "network"))]
+    Offline,
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "network")]
+            FetchError::Request(ref e) => write!(f, "the request failed: {}", e),
+            #[cfg(feature = "network")]
+            FetchError::Status(status) => write!(f, "the server answered {}", status),
+            FetchError::Io(ref e) => write!(f, "the response cannot be saved: {}", e),
+            #[cfg(not(feature = "network"))]
+            FetchError::Offline => write!(f, "naglfar is built without the network"),
+        }
+    }
+}
+
+/// Resolves ``reference`` (e.g. ``href`` or ``src`` value) against ``base``.
+/// Returns None if ``reference`` is not a valid URL.
+pub fn resolve_url(base: &Url, reference: &str) -> Option<Url> {
+    base.join(reference.trim()).ok()
+}
+
+/// Guesses what the user means by ``input`` typed in the address bar: an absolute URL, or a
+/// bare host name like ``example.com/a`` or ``localhost:8000`` (``http://`` is added).
+/// Returns None if ``input`` looks like a path.
+pub fn guess_url(input: &str) -> Option<Url> {
+    let input = input.trim();
+    if let Ok(url) = Url::parse(input) {
+        // ``localhost:8000`` is parsed as the scheme ``localhost``.
+        match url.scheme() {
+            "http" | "https" | "file" | "data" => return Some(url),
+            _ => {}
+        }
+    }
+
+    if input.is_empty() || input.starts_with(&['.', '/', '~'][..])
+        || input.contains(char::is_whitespace)
+    {
+        return None;
+    }
+
+    let host_port = input.split(&['/', '?', '#'][..]).next().unwrap();
+    let (host, port) = match host_port.rfind(':') {
+        Some(i) => (&host_port[..i], &host_port[i + 1..]),
+        None => (host_port, ""),
+    };
+    let labels: Vec<&str> = host.split('.').collect();
+    let tld = labels[labels.len() - 1];
+    let is_ipv4 = labels.len() == 4 && labels.iter().all(|l| l.parse::<u8>().is_ok());
+    let is_domain_name = labels.len() >= 2 && labels.iter().all(|l| !l.is_empty())
+        && tld.chars().all(|c| c.is_ascii_alphabetic())
+        // e.g. ``index.html``
+        && guess_mime_type(host).is_none();
+    let has_port = !port.is_empty() && port.chars().all(|c| c.is_ascii_digit());
+
+    if host == "localhost" || is_ipv4 || is_domain_name || has_port {
+        Url::parse(format!("http://{}", input).as_str()).ok()
+    } else {
+        None
+    }
+}
+
+/// ``text/html; charset=utf-8`` -> ``text/html``
+pub fn mime_essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Guesses the MIME type from the extension of ``path``.
+pub fn guess_mime_type(path: &str) -> Option<&'static str> {
+    let ext = ::std::path::Path::new(path)
+        .extension()?
+        .to_str()?
+        .to_ascii_lowercase();
+    Some(match ext.as_str() {
+        "html" | "htm" => "text/html",
+        "xhtml" => "application/xhtml+xml",
+        "css" => "text/css",
+        "txt" => "text/plain",
+        "js" => "application/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "bmp" => "image/bmp",
+        "ico" => "image/x-icon",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        _ => return None,
+    })
+}
+
+/// Returns true if a resource of ``mime_type`` can be shown as plain text.
+pub fn is_text_mime_type(mime_type: &str) -> bool {
+    mime_type.starts_with("text/") || mime_type.ends_with("+xml") || mime_type.ends_with("+json")
+        || mime_type == "application/javascript" || mime_type == "application/json"
+        || mime_type == "application/xml"
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataUrl {
+    pub mime_type: String,
+    pub body: Vec<u8>,
+}
+
+/// Decodes ``data:[<mediatype>][;base64],<data>``.
+/// ref. https://fetch.spec.whatwg.org/#data-urls
+pub fn decode_data_url(url: &str) -> Option<DataUrl> {
+    let (scheme, rest) = url.split_at(url.find(':')?);
+    if !scheme.eq_ignore_ascii_case("data") {
+        return None;
+    }
+    let rest = &rest[1..];
+
+    let comma = rest.find(',')?;
+    let (mediatype, data) = (rest[..comma].trim(), &rest[comma + 1..]);
+
+    let (mediatype, is_base64) = match mediatype.rfind(';') {
+        Some(i) if mediatype[i + 1..].trim().eq_ignore_ascii_case("base64") => {
+            (mediatype[..i].trim(), true)
+        }
+        _ => (mediatype, false),
+    };
+
+    let data = percent_decode(data);
+    let body = if is_base64 {
+        base64_decode(data.as_slice())?
+    } else {
+        data
+    };
+
+    Some(DataUrl {
+        mime_type: if mediatype.is_empty() {
+            "text/plain;charset=US-ASCII".to_string()
+        } else if mediatype.starts_with(';') {
+            format!("text/plain{}", mediatype)
+        } else {
+            mediatype.to_string()
+        },
+        body: body,
+    })
+}
+
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() && bytes[i + 1].is_ascii_hexdigit()
+            && bytes[i + 2].is_ascii_hexdigit()
+        {
+            decoded.push(u8::from_str_radix(&s[i + 1..i + 3], 16).unwrap());
+            i += 3;
+            continue;
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    decoded
+}
+
+/// Returns None if ``data`` is not valid base64. ASCII whitespaces are ignored.
+fn base64_decode(data: &[u8]) -> Option<Vec<u8>> {
+    fn sextet(c: u8) -> Option<u32> {
+        match c {
+            b'A'...b'Z' => Some((c - b'A') as u32),
+            b'a'...b'z' => Some((c - b'a') as u32 + 26),
+            b'0'...b'9' => Some((c - b'0') as u32 + 52),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        }
+    }
+
+    let data: Vec<u8> = data.iter()
+        .cloned()
+        .filter(|c| !c.is_ascii_whitespace())
+        .collect();
+    let data = match data.iter().rposition(|&c| c != b'=') {
+        Some(last) if data.len() - last - 1 <= 2 => &data[..last + 1],
+        Some(_) => return None,
+        None => &data[..0],
+    };
+    if data.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut decoded = Vec::with_capacity(data.len() * 3 / 4);
+    for chunk in data.chunks(4) {
+        let mut bits = 0u32;
+        for &c in chunk {
+            bits = (bits << 6) | sextet(c)?;
+        }
+        bits <<= 6 * (4 - chunk.len()) as u32;
+        for i in 0..chunk.len() - 1 {
+            decoded.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+/// Fetches ``url`` over HTTP(S). Fresh cached responses are returned without any request,
+/// and stale ones are revalidated with ``If-None-Match``/``If-Modified-Since``.
+#[cfg(feature = "network")]
+pub fn fetch(
+    url: &Url,
+    destination: Destination,
+    cache_mode: CacheMode,
+) -> Result<Response, FetchError> {
+    let now = SystemTime::now();
+    let cached = match cache_mode {
+        CacheMode::Default => cache::lookup(url),
+        CacheMode::Reload => None,
+    };
+
+    let mut headers = Headers::new();
+    headers.set_raw("Accept", destination.accept());
+    headers.set_raw("Accept-Encoding", ACCEPT_ENCODING);
+    if let Some(cookie_header) = cookie::cookie_header(url) {
+        headers.set_raw("Cookie", cookie_header);
+    }
+    if cache_mode == CacheMode::Reload {
+        // Also tells the caches on the way not to answer from their stored responses.
+        headers.set_raw("Cache-Control", "no-cache");
+        headers.set_raw("Pragma", "no-cache");
+    }
+
+    if let Some(ref entry) = cached {
+        if entry.is_fresh(now) {
+            return Ok(entry.to_response());
+        }
+        if let Some(ref etag) = entry.etag {
+            headers.set_raw("If-None-Match", etag.clone());
+        }
+        if let Some(ref last_modified) = entry.last_modified {
+            headers.set_raw("If-Modified-Since", last_modified.clone());
+        }
+    }
+
+    let mut response = CLIENT
+        .with(|client| client.get(url.clone()).headers(headers).send())
+        .map_err(FetchError::Request)?;
+
+    // NOTE: Set-Cookie in redirect responses is not seen here since reqwest follows redirects
+    // by itself.
+    if let Some(&SetCookie(ref set_cookies)) = response.headers().get::<SetCookie>() {
+        cookie::store_set_cookies(response.url(), set_cookies.as_slice());
+    }
+
+    if response.status() == StatusCode::NotModified {
+        if let Some(mut entry) = cached {
+            entry.fresh_until = fresh_until(response.headers(), now);
+            cache::store(url, entry.clone());
+            return Ok(entry.to_response());
+        }
+    }
+
+    if !response.status().is_success() {
+        return Err(FetchError::Status(response.status()));
+    }
+
+    let mut body = vec![];
+    response.read_to_end(&mut body).map_err(FetchError::Io)?;
+
+    let headers = response.headers();
+    if let Some(content_encoding) = headers.get::<ContentEncoding>() {
+        body = decode_content(body, content_encoding.to_string().as_str())
+            .map_err(FetchError::Io)?;
+    }
+
+    let entry = CacheEntry {
+        url: response.url().clone(),
+        content_type: headers
+            .get::<ContentType>()
+            .map(|content_type| content_type.to_string()),
+        body: body,
+        etag: headers.get::<ETag>().map(|etag| etag.to_string()),
+        last_modified: headers
+            .get::<LastModified>()
+            .map(|last_modified| last_modified.to_string()),
+        fresh_until: fresh_until(headers, now),
+    };
+
+    if is_storable(headers) {
+        cache::store(url, entry.clone());
+    }
+
+    Ok(entry.to_response())
+}
+
+/// Without the ``network`` feature, nothing is fetched over HTTP(S).
+#[cfg(not(feature = "network"))]
+pub fn fetch(
+    _url: &Url,
+    _destination: Destination,
+    _cache_mode: CacheMode,
+) -> Result<Response, FetchError> {
+    Err(FetchError::Offline)
+}
+
+/// Decompresses ``body`` encoded with ``content_encoding`` (e.g. ``gzip``, or ``deflate, br``
+/// meaning deflate and then brotli was applied).
+pub fn decode_content(body: Vec<u8>, content_encoding: &str) -> io::Result<Vec<u8>> {
+    fn read_all<R: Read>(mut decoder: R) -> io::Result<Vec<u8>> {
+        let mut decoded = vec![];
+        decoder.read_to_end(&mut decoded)?;
+        Ok(decoded)
+    }
+
+    let mut body = body;
+    for coding in content_encoding.split(',').rev() {
+        body = match coding.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => read_all(libflate::gzip::Decoder::new(body.as_slice())?)?,
+            // Should be zlib format, but some servers send raw deflate data.
+            "deflate" => match libflate::zlib::Decoder::new(body.as_slice()).and_then(read_all) {
+                Ok(decoded) => decoded,
+                Err(_) => read_all(libflate::deflate::Decoder::new(body.as_slice()))?,
+            },
+            "br" => read_all(brotli_decompressor::Decompressor::new(body.as_slice(), 4096))?,
+            "identity" | "" => body,
+            coding => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unsupported content coding: {}", coding),
+                ))
+            }
+        };
+    }
+    Ok(body)
+}
+
+#[cfg(feature = "network")]
+fn is_storable(headers: &Headers) -> bool {
+    match headers.get::<CacheControl>() {
+        Some(&CacheControl(ref directives)) => !directives.contains(&CacheDirective::NoStore),
+        None => true,
+    }
+}
+
+/// Returns when a response with ``headers`` received at ``now`` becomes stale.
+/// None means it must be revalidated every time.
+#[cfg(feature = "network")]
+fn fresh_until(headers: &Headers, now: SystemTime) -> Option<SystemTime> {
+    if let Some(&CacheControl(ref directives)) = headers.get::<CacheControl>() {
+        if directives.contains(&CacheDirective::NoCache) {
+            return None;
+        }
+        for directive in directives {
+            if let &CacheDirective::MaxAge(secs) = directive {
+                return Some(now + Duration::from_secs(secs as u64));
+            }
+        }
+    }
+
+    headers
+        .get::<Expires>()
+        .map(|&Expires(date)| SystemTime::from(date))
+}
+
+#[cfg(feature = "network")]
+impl CacheEntry {
+    fn to_response(&self) -> Response {
+        Response {
+            url: self.url.clone(),
+            content_type: self.content_type.clone(),
+            body: self.body.clone(),
+        }
+    }
+}
+
+#[test]
+fn test_accept() {
+    assert!(Destination::Document.accept().starts_with("text/html"));
+    assert!(Destination::Style.accept().starts_with("text/css"));
+    assert!(USER_AGENT.contains("Naglfar/"));
+}
+
+#[test]
+fn test_resolve_url() {
+    let base = Url::parse("http://example.com/a/b/index.html").unwrap();
+    let resolve = |reference| resolve_url(&base, reference).unwrap().into_string();
+    assert_eq!(resolve("c.png"), "http://example.com/a/b/c.png");
+    assert_eq!(resolve("../c.css"), "http://example.com/a/c.css");
+    assert_eq!(resolve("/c.css"), "http://example.com/c.css");
+    assert_eq!(resolve("//cdn.example.org/c.js"), "http://cdn.example.org/c.js");
+    assert_eq!(resolve("https://example.org/"), "https://example.org/");
+    assert_eq!(resolve("?q=1"), "http://example.com/a/b/index.html?q=1");
+
+    let base = Url::parse("file:///home/user/top.html").unwrap();
+    assert_eq!(
+        resolve_url(&base, "img/a.png").unwrap().as_str(),
+        "file:///home/user/img/a.png"
+    );
+}
+
+#[test]
+fn test_guess_url() {
+    let guess = |input| guess_url(input).map(|url| url.into_string());
+    assert_eq!(guess("https://example.com/"), Some("https://example.com/".to_string()));
+    assert_eq!(guess(" example.com "), Some("http://example.com/".to_string()));
+    assert_eq!(guess("www.example.com/a?q=1"), Some("http://www.example.com/a?q=1".to_string()));
+    assert_eq!(guess("localhost:8000"), Some("http://localhost:8000/".to_string()));
+    assert_eq!(guess("localhost"), Some("http://localhost/".to_string()));
+    assert_eq!(guess("127.0.0.1"), Some("http://127.0.0.1/".to_string()));
+    assert_eq!(guess("index.html"), None);
+    assert_eq!(guess("./example.com"), None);
+    assert_eq!(guess("/home/user/a.html"), None);
+    assert_eq!(guess("a/b.html"), None);
+    assert_eq!(guess("example"), None);
+}
+
+#[cfg(feature = "network")]
+#[test]
+fn test_fresh_until() {
+    use std::time::UNIX_EPOCH;
+
+    let now = UNIX_EPOCH + Duration::from_secs(1000000000);
+    let mut headers = Headers::new();
+    assert_eq!(fresh_until(&headers, now), None);
+    assert!(is_storable(&headers));
+
+    headers.set(CacheControl(vec![CacheDirective::Public, CacheDirective::MaxAge(60)]));
+    assert_eq!(fresh_until(&headers, now), Some(now + Duration::from_secs(60)));
+
+    headers.set(CacheControl(vec![CacheDirective::NoCache, CacheDirective::MaxAge(60)]));
+    assert_eq!(fresh_until(&headers, now), None);
+
+    headers.set(CacheControl(vec![CacheDirective::NoStore]));
+    assert!(!is_storable(&headers));
+}
+
+#[test]
+fn test_decode_data_url() {
+    assert_eq!(
+        decode_data_url("data:text/css,div%20%7B%20color:%20red;%20%7D"),
+        Some(DataUrl {
+            mime_type: "text/css".to_string(),
+            body: b"div { color: red; }".to_vec(),
+        })
+    );
+    assert_eq!(
+        decode_data_url("data:image/png;base64,iVBORw0K\nGgo="),
+        Some(DataUrl {
+            mime_type: "image/png".to_string(),
+            body: b"\x89PNG\r\n\x1a\n".to_vec(),
+        })
+    );
+    assert_eq!(
+        decode_data_url("data:,Hello").unwrap().mime_type,
+        "text/plain;charset=US-ASCII"
+    );
+    assert_eq!(decode_data_url("data:;base64,YQ").unwrap().body, b"a".to_vec());
+    assert_eq!(decode_data_url("data:;base64,Y"), None);
+    assert_eq!(decode_data_url("data:;base64,Y!=="), None);
+    assert_eq!(decode_data_url("data:text/plain"), None);
+    assert_eq!(decode_data_url("http://example.com/,a"), None);
+}
+
+#[test]
+fn test_mime_type() {
+    assert_eq!(mime_essence("Text/HTML; charset=utf-8"), "text/html");
+    assert_eq!(guess_mime_type("/a/b.PNG"), Some("image/png"));
+    assert_eq!(guess_mime_type("/a/b"), None);
+    assert!(is_text_mime_type("text/css"));
+    assert!(is_text_mime_type("application/rss+xml"));
+    assert!(!is_text_mime_type("application/octet-stream"));
+}
+
+#[test]
+fn test_decode_content() {
+    use std::io::Write;
+
+    let text = b"<html><body>naglfar naglfar naglfar</body></html>".to_vec();
+
+    let mut gzip = libflate::gzip::Encoder::new(vec![]).unwrap();
+    gzip.write_all(text.as_slice()).unwrap();
+    let gzip = gzip.finish().into_result().unwrap();
+    assert_eq!(decode_content(gzip.clone(), "gzip").unwrap(), text);
+
+    let mut zlib = libflate::zlib::Encoder::new(vec![]).unwrap();
+    zlib.write_all(text.as_slice()).unwrap();
+    let zlib = zlib.finish().into_result().unwrap();
+    assert_eq!(decode_content(zlib, "deflate").unwrap(), text);
+
+    let mut deflate = libflate::deflate::Encoder::new(vec![]);
+    deflate.write_all(text.as_slice()).unwrap();
+    let deflate = deflate.finish().into_result().unwrap();
+    assert_eq!(decode_content(deflate, "deflate").unwrap(), text);
+
+    // An uncompressed meta-block of "hello naglfar", followed by an empty last meta-block.
+    let brotli = vec![
+        0xc0, 0x00, 0x10, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x20, 0x6e, 0x61, 0x67, 0x6c, 0x66, 0x61,
+        0x72, 0x03,
+    ];
+    assert_eq!(decode_content(brotli, "br").unwrap(), b"hello naglfar".to_vec());
+    assert!(decode_content(vec![0x06], "br").unwrap().is_empty());
+
+    assert_eq!(decode_content(text.clone(), "identity").unwrap(), text);
+    assert!(decode_content(gzip, "compress").is_err());
+}
\ No newline at end of file
@@ -0,0 +1,438 @@
+//! Overflow: the content of a box clipped to its padding box, in one direction or both, and
+//! scrolled in it. ref. https://www.w3.org/TR/css-overflow-3/
+//!
+//! How far each scroll container is scrolled is kept by the id of its node, so that it stays
+//! where it is when the page is laid out again. The painter and the hit tests place the children
+//! of a box at ``child_origin``.
+
+use css::Color;
+use layout::{BoxType, LayoutBox, Rect};
+use painter::{SCROLLBAR_THUMB_COLOR, SCROLLBAR_THUMB_HOVER_COLOR, SCROLLBAR_TRACK_COLOR};
+use style::{Overflow, ScrollbarWidth, Style};
+
+use app_units::Au;
+
+use std::cell::{Cell, RefCell};
+use std::cmp::max;
+use std::collections::HashMap;
+
+/// How far a clip rect reaches (CSS px) in a direction that the content is not clipped in
+const UNCLIPPED: f64 = 1_000_000.0;
+
+/// The width of the scrollbars of scroll containers (CSS px), painted over their padding box
+pub const SCROLLBAR_WIDTH: f64 = 8.0;
+const SCROLLBAR_MIN_THUMB_LENGTH: f64 = 16.0;
+
+thread_local!(
+    /// How far the scroll containers are scrolled right and down (CSS px), by the id of their
+    /// node
+    static SCROLL_OFFSETS: RefCell<HashMap<usize, (f64, f64)>> = { RefCell::new(HashMap::new()) };
+    /// Whether the scrollbars are overlay scrollbars: their tracks are not painted, and their
+    /// thumbs only while the pointer is over them
+    static OVERLAY_SCROLLBARS: Cell<bool> = { Cell::new(false) };
+    /// The id of the node of the scroll container whose scrollbars the pointer is over
+    static HOVERED_SCROLLBAR: Cell<Option<usize>> = { Cell::new(None) };
+);
+
+/// ``overflow-x`` and ``overflow-y`` of ``layout_box``. They are ``visible`` but for block
+/// containers, and for the root (whose overflow is that of the viewport, which the window
+/// scrolls). Paint containment clips like ``clip`` the directions that are ``visible``.
+pub fn overflow(layout_box: &LayoutBox) -> (Overflow, Overflow) {
+    match layout_box.box_type {
+        BoxType::BlockNode | BoxType::InlineBlockNode | BoxType::Float
+            if layout_box.node_id != Some(0) =>
+        {
+            let (x, y) = layout_box.property.overflow();
+            if !layout_box.property.contain().paint {
+                return (x, y);
+            }
+            let clip = |overflow| match overflow {
+                Overflow::Visible => Overflow::Clip,
+                overflow => overflow,
+            };
+            (clip(x), clip(y))
+        }
+        _ => (Overflow::Visible, Overflow::Visible),
+    }
+}
+
+/// The rect the descendants of ``layout_box`` are clipped to, in the coordinates of the document
+/// where the content area of its parent is at (``x``, ``y``): its padding box in the directions
+/// it clips, and unbounded in the others. None if it does not clip.
+pub fn clip_rect(layout_box: &LayoutBox, x: Au, y: Au) -> Option<Rect> {
+    let (overflow_x, overflow_y) = overflow(layout_box);
+    if !overflow_x.clips() && !overflow_y.clips() {
+        return None;
+    }
+    let padding_box = layout_box.dimensions.padding_box().add_parent_coordinate(x, y);
+    let unclipped = Au::from_f64_px(UNCLIPPED);
+    let axis = |clips: bool, start: Au, size: Au| {
+        if clips {
+            (start, size)
+        } else {
+            (start - unclipped, size + unclipped * 2)
+        }
+    };
+    let (x, width) = axis(overflow_x.clips(), padding_box.x, padding_box.width);
+    let (y, height) = axis(overflow_y.clips(), padding_box.y, padding_box.height);
+    Some(Rect {
+        x: x,
+        y: y,
+        width: width,
+        height: height,
+    })
+}
+
+/// Whether the point (``px``, ``py``) in the document may hit the descendants of ``layout_box``
+/// (placed like in ``clip_rect``): they are not clipped there.
+pub fn hits_descendants(layout_box: &LayoutBox, x: Au, y: Au, px: f64, py: f64) -> bool {
+    clip_rect(layout_box, x, y).is_none_or(|clip| clip.contains_point(px, py))
+}
+
+/// Where the children of ``layout_box`` are placed from, in the coordinates of the document where
+/// the content area of its parent is at (``x``, ``y``): its content area, moved by how far it is
+/// scrolled.
+pub fn child_origin(layout_box: &LayoutBox, x: Au, y: Au) -> (Au, Au) {
+    let (scroll_x, scroll_y) = scroll_offset(layout_box);
+    (
+        x + layout_box.dimensions.content.x - Au::from_f64_px(scroll_x),
+        y + layout_box.dimensions.content.y - Au::from_f64_px(scroll_y),
+    )
+}
+
+/// How far ``layout_box`` is scrolled right and down (CSS px). Always within its content, which
+/// may have shrunk since it was scrolled.
+pub fn scroll_offset(layout_box: &LayoutBox) -> (f64, f64) {
+    let offset = layout_box
+        .node_id
+        .and_then(|id| SCROLL_OFFSETS.with(|s| s.borrow().get(&id).cloned()));
+    match offset {
+        Some((x, y)) => {
+            let (overflow_x, overflow_y) = overflow(layout_box);
+            let (max_x, max_y) = max_scroll_offset(layout_box);
+            let clamp = |offset: f64, max: f64, scrolls: bool| {
+                if scrolls {
+                    offset.min(max).max(0.0)
+                } else {
+                    0.0
+                }
+            };
+            (clamp(x, max_x, overflow_x.scrolls()), clamp(y, max_y, overflow_y.scrolls()))
+        }
+        None => (0.0, 0.0),
+    }
+}
+
+/// The size (CSS px) of what may be scrolled to in ``layout_box``: its padding box, and the
+/// border boxes of its descendants that are not clipped by a box in between, with the padding
+/// after them. ref. https://www.w3.org/TR/css-overflow-3/#scrollable
+pub fn scrollable_size(layout_box: &LayoutBox) -> (f64, f64) {
+    fn extend(end: &mut (Au, Au), padding: (Au, Au), layout_box: &LayoutBox, x: Au, y: Au) {
+        let border_box = layout_box.dimensions.border_box().add_parent_coordinate(x, y);
+        end.0 = max(end.0, border_box.x + border_box.width + padding.0);
+        end.1 = max(end.1, border_box.y + border_box.height + padding.1);
+        if clip_rect(layout_box, x, y).is_some() {
+            return;
+        }
+        for child in &layout_box.children {
+            extend(
+                end,
+                padding,
+                child,
+                x + layout_box.dimensions.content.x,
+                y + layout_box.dimensions.content.y,
+            );
+        }
+    }
+
+    let dimensions = layout_box.dimensions;
+    let padding_box = dimensions.padding_box();
+    let mut end = (padding_box.width, padding_box.height);
+    // From the top left of the padding box
+    let (x, y) = (dimensions.padding.left, dimensions.padding.top);
+    let padding = (dimensions.padding.right, dimensions.padding.bottom);
+    for child in &layout_box.children {
+        extend(&mut end, padding, child, x, y);
+    }
+    (end.0.to_f64_px(), end.1.to_f64_px())
+}
+
+/// How far ``layout_box`` can be scrolled right and down at most (CSS px).
+pub fn max_scroll_offset(layout_box: &LayoutBox) -> (f64, f64) {
+    let (width, height) = scrollable_size(layout_box);
+    let padding_box = layout_box.dimensions.padding_box();
+    (
+        (width - padding_box.width.to_f64_px()).max(0.0),
+        (height - padding_box.height.to_f64_px()).max(0.0),
+    )
+}
+
+/// Scrolls ``layout_box`` by (``dx``, ``dy``) px in the directions the user scrolls it, as far as
+/// its content goes. Returns whether it moved.
+pub fn scroll_by(layout_box: &LayoutBox, dx: f64, dy: f64) -> bool {
+    let id = match layout_box.node_id {
+        Some(id) => id,
+        None => return false,
+    };
+    let (overflow_x, overflow_y) = overflow(layout_box);
+    let (max_x, max_y) = max_scroll_offset(layout_box);
+    let (x, y) = scroll_offset(layout_box);
+    let scrolled = |offset: f64, delta: f64, max: f64, scrolls: bool| {
+        if scrolls {
+            (offset + delta).min(max).max(0.0)
+        } else {
+            offset
+        }
+    };
+    let new_offset = (
+        scrolled(x, dx, max_x, overflow_x.user_scrolls()),
+        scrolled(y, dy, max_y, overflow_y.user_scrolls()),
+    );
+    if new_offset == (x, y) {
+        return false;
+    }
+    SCROLL_OFFSETS.with(|s| s.borrow_mut().insert(id, new_offset));
+    true
+}
+
+/// Forgets how far the boxes are scrolled, e.g. when another page is loaded.
+pub fn reset_scroll_offsets() {
+    SCROLL_OFFSETS.with(|s| s.borrow_mut().clear());
+}
+
+/// Makes the scrollbars (of the viewport too) overlay scrollbars, or not.
+pub fn set_overlay_scrollbars(overlay: bool) {
+    OVERLAY_SCROLLBARS.with(|o| o.set(overlay));
+}
+
+pub fn overlay_scrollbars() -> bool {
+    OVERLAY_SCROLLBARS.with(|o| o.get())
+}
+
+/// Sets the scroll container (by the id of its node) whose scrollbars the pointer is over.
+/// Returns whether it changed, and so they have to be painted again.
+pub fn set_hovered_scrollbar(id: Option<usize>) -> bool {
+    HOVERED_SCROLLBAR.with(|h| h.replace(id)) != id
+}
+
+/// The width of the scrollbars of ``scrollbar-width``, where ``auto`` is ``auto_width``
+pub fn scrollbar_width(width: ScrollbarWidth, auto_width: f64) -> f64 {
+    match width {
+        ScrollbarWidth::Auto => auto_width,
+        ScrollbarWidth::Thin => auto_width / 2.0,
+        ScrollbarWidth::None => 0.0,
+    }
+}
+
+/// The colors of the track and of the thumb of the scrollbars of a box with ``style`` (the root
+/// one for the viewport): ``scrollbar-color``, or darker thumbs while ``hovered``. Transparent
+/// ones are not painted.
+pub fn scrollbar_colors(style: &Style, hovered: bool) -> (Color, Color) {
+    let (track, thumb) = match style.scrollbar_color() {
+        Some((thumb, track)) => (track, thumb),
+        None if hovered => (SCROLLBAR_TRACK_COLOR, SCROLLBAR_THUMB_HOVER_COLOR),
+        None => (SCROLLBAR_TRACK_COLOR, SCROLLBAR_THUMB_COLOR),
+    };
+    if !overlay_scrollbars() {
+        return (track, thumb);
+    }
+    let transparent = Color { a: 0, ..track };
+    (transparent, if hovered { thumb } else { transparent })
+}
+
+/// Whether the pointer is over the scrollbars of ``layout_box``
+pub fn scrollbar_hovered(layout_box: &LayoutBox) -> bool {
+    layout_box.node_id.is_some() && HOVERED_SCROLLBAR.with(|h| h.get()) == layout_box.node_id
+}
+
+/// The id of the node of the innermost scroll container in ``root`` that has a scrollbar at
+/// (``x``, ``y``) in the document (CSS px)
+pub fn scrollbar_at(root: &LayoutBox, x: f64, y: f64) -> Option<usize> {
+    fn find(layout_box: &LayoutBox, origin_x: Au, origin_y: Au, x: f64, y: f64) -> Option<usize> {
+        let (child_x, child_y) = child_origin(layout_box, origin_x, origin_y);
+        let inner = layout_box
+            .children
+            .iter()
+            .rev()
+            .find_map(|child| find(child, child_x, child_y, x, y));
+        if inner.is_some() {
+            return inner;
+        }
+        let bars = scrollbars(layout_box, origin_x, origin_y);
+        if bars.iter().any(|&(track, _)| track.contains_point(x, y)) {
+            layout_box.node_id
+        } else {
+            None
+        }
+    }
+    find(root, Au(0), Au(0), x, y)
+}
+
+/// The tracks and the thumbs of the scrollbars of ``layout_box`` (placed like in ``clip_rect``):
+/// in the directions it is ``scroll``, or ``auto`` and its content overflows, unless it is
+/// ``scrollbar-width: none``. They are painted over its padding box, at its right and its bottom.
+pub fn scrollbars(layout_box: &LayoutBox, x: Au, y: Au) -> Vec<(Rect, Rect)> {
+    let (overflow_x, overflow_y) = overflow(layout_box);
+    if !overflow_x.user_scrolls() && !overflow_y.user_scrolls() {
+        return vec![];
+    }
+    let bar = scrollbar_width(layout_box.property.scrollbar_width(), SCROLLBAR_WIDTH);
+    if bar == 0.0 {
+        return vec![];
+    }
+    let bar = Au::from_f64_px(bar);
+    let padding_box = layout_box.dimensions.padding_box().add_parent_coordinate(x, y);
+    let (width, height) = scrollable_size(layout_box);
+    let (max_x, max_y) = max_scroll_offset(layout_box);
+    let (scroll_x, scroll_y) = scroll_offset(layout_box);
+    let shown = |overflow: Overflow, max: f64| {
+        overflow == Overflow::Scroll || (overflow == Overflow::Auto && max > 0.0)
+    };
+    let (horizontal, vertical) = (shown(overflow_x, max_x), shown(overflow_y, max_y));
+    // Where the thumb starts in the track and how long it is
+    let thumb = |track: f64, visible: f64, scrollable: f64, offset: f64, max: f64| {
+        let length = (track * visible / scrollable)
+            .max(SCROLLBAR_MIN_THUMB_LENGTH)
+            .min(track);
+        let start = if max > 0.0 {
+            (track - length) * offset / max
+        } else {
+            0.0
+        };
+        (Au::from_f64_px(start), Au::from_f64_px(length))
+    };
+
+    let mut bars = vec![];
+    if vertical {
+        let track = Rect {
+            x: padding_box.x + padding_box.width - bar,
+            y: padding_box.y,
+            width: bar,
+            height: padding_box.height - if horizontal { bar } else { Au(0) },
+        };
+        let (start, length) = thumb(
+            track.height.to_f64_px(),
+            padding_box.height.to_f64_px(),
+            height,
+            scroll_y,
+            max_y,
+        );
+        let thumb = Rect {
+            y: track.y + start,
+            height: length,
+            ..track
+        };
+        bars.push((track, thumb));
+    }
+    if horizontal {
+        let track = Rect {
+            x: padding_box.x,
+            y: padding_box.y + padding_box.height - bar,
+            width: padding_box.width - if vertical { bar } else { Au(0) },
+            height: bar,
+        };
+        let (start, length) = thumb(
+            track.width.to_f64_px(),
+            padding_box.width.to_f64_px(),
+            width,
+            scroll_x,
+            max_x,
+        );
+        let thumb = Rect {
+            x: track.x + start,
+            width: length,
+            ..track
+        };
+        bars.push((track, thumb));
+    }
+    bars
+}
+
+#[test]
+fn test_overflow() {
+    use dom::NodeType;
+    use layout;
+
+    let layout_root = |src: &str| {
+        layout::lay_out_html(
+            format!("<html><body>{}</body></html>", src).as_str(),
+            "body { margin: 0; }
+             div { display: block; width: 100px; height: 50px; padding: 5px; }
+             p { display: block; margin: 0; width: 300px; height: 200px; }",
+            800.0,
+            600.0,
+        )
+    };
+    fn find<'a>(layout_box: &'a LayoutBox, tag_name: &str) -> Option<&'a LayoutBox> {
+        match layout_box.node.data {
+            NodeType::Element(ref e) if e.tag_name == tag_name => return Some(layout_box),
+            _ => {}
+        }
+        layout_box.children.iter().find_map(|child| find(child, tag_name))
+    }
+    let px = Au::from_f64_px;
+
+    // Clipped horizontally to the padding box, and not vertically
+    let root = layout_root("<div style=\"overflow-x: clip\"><p></p></div>");
+    let div = find(&root, "div").unwrap();
+    let clip = clip_rect(div, Au(0), Au(0)).unwrap();
+    assert_eq!((clip.x, clip.width), (px(0.0), px(110.0)));
+    assert!(clip.y < px(-1000.0) && clip.height > px(2000.0));
+    assert!(!hits_descendants(div, Au(0), Au(0), 150.0, 10.0));
+    assert!(hits_descendants(div, Au(0), Au(0), 10.0, 150.0));
+    assert!(scrollbars(div, Au(0), Au(0)).is_empty());
+    assert!(clip_rect(&root, Au(0), Au(0)).is_none());
+
+    // Scrolled vertically as far as the bottom of the paragraph and the padding under it
+    let root = layout_root("<div style=\"overflow-x: clip; overflow-y: scroll\"><p></p></div>");
+    let div = find(&root, "div").unwrap();
+    assert_eq!(scrollable_size(div), (310.0, 210.0));
+    assert_eq!(max_scroll_offset(div), (200.0, 150.0));
+    assert!(!scroll_by(div, 0.0, -10.0));
+    assert!(scroll_by(div, 50.0, 500.0));
+    // ``clip`` computes to ``hidden``, which only scripts scroll.
+    assert_eq!(scroll_offset(div), (0.0, 150.0));
+    assert_eq!(child_origin(div, Au(0), Au(0)), (px(5.0), px(-145.0)));
+    let bars = scrollbars(div, Au(0), Au(0));
+    assert_eq!(bars.len(), 1);
+    let (track, thumb) = bars[0];
+    assert_eq!((track.x, track.height), (px(102.0), px(60.0)));
+    // 60px of the 210px are shown, and the thumb is at the end.
+    assert_eq!(thumb.height, px(60.0 * 60.0 / 210.0));
+    assert!((thumb.y + thumb.height - px(60.0)).0.abs() <= 1);
+
+    // The pointer is over the scrollbar, not over the content.
+    assert_eq!(scrollbar_at(&root, 105.0, 30.0), div.node_id);
+    assert_eq!(scrollbar_at(&root, 50.0, 30.0), None);
+    assert!(set_hovered_scrollbar(div.node_id));
+    assert!(!set_hovered_scrollbar(div.node_id));
+    assert!(scrollbar_hovered(div));
+    let style = &div.property;
+    let default_colors = (SCROLLBAR_TRACK_COLOR, SCROLLBAR_THUMB_COLOR);
+    assert_eq!(scrollbar_colors(style, false), default_colors);
+    assert_eq!(scrollbar_colors(style, true).1, SCROLLBAR_THUMB_HOVER_COLOR);
+    // Only the thumb of overlay scrollbars, and only while hovered
+    set_overlay_scrollbars(true);
+    assert_eq!(scrollbar_colors(style, false).0.a, 0);
+    assert_eq!(scrollbar_colors(style, false).1.a, 0);
+    assert_eq!(scrollbar_colors(style, true).1, SCROLLBAR_THUMB_HOVER_COLOR);
+    set_overlay_scrollbars(false);
+    set_hovered_scrollbar(None);
+
+    reset_scroll_offsets();
+    assert_eq!(scroll_offset(div), (0.0, 0.0));
+
+    // Thin, with the colors of the author, or none at all
+    let root = layout_root(
+        "<div style=\"overflow: scroll; scrollbar-width: thin; scrollbar-color: red blue\"></div>",
+    );
+    let div = find(&root, "div").unwrap();
+    let (track, _) = scrollbars(div, Au(0), Au(0))[0];
+    assert_eq!(track.width, px(SCROLLBAR_WIDTH / 2.0));
+    let (track_color, thumb_color) = scrollbar_colors(&div.property, false);
+    assert_eq!((track_color.b, thumb_color.r), (0xff, 0xff));
+    let root = layout_root("<div style=\"overflow: scroll; scrollbar-width: none\"><p></p></div>");
+    let div = find(&root, "div").unwrap();
+    assert!(scrollbars(div, Au(0), Au(0)).is_empty());
+    assert!(scroll_by(div, 0.0, 10.0));
+}
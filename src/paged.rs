@@ -0,0 +1,229 @@
+//! Paged media: a document printed (see ``interface::print_to_pdf``) is laid out as wide as the
+//! page area of its first page, and split into pages of the page areas. Their size and margins
+//! are those of the ``@page`` rules that apply (see ``page_box``), the queries of ``print``
+//! matching meanwhile.
+//! ref. https://www.w3.org/TR/css-page-3/
+//!
+//! All the pages are as large as the first one, and their margin boxes are not painted.
+
+use css::{Declaration, Stylesheet, Unit, Value};
+
+/// The size (CSS px) of ``size: auto``, A4
+pub const DEFAULT_PAGE_SIZE: (f64, f64) = (210.0 * MM, 297.0 * MM);
+
+/// The margins (CSS px) of ``margin: auto``, half an inch
+pub const DEFAULT_PAGE_MARGIN: f64 = 48.0;
+
+/// One millimeter in CSS px
+const MM: f64 = 96.0 / 25.4;
+
+/// A page, in CSS px
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PageBox {
+    pub width: f64,
+    pub height: f64,
+    /// The top, the right, the bottom and the left margins
+    pub margin: (f64, f64, f64, f64),
+}
+
+impl PageBox {
+    /// The x, the y, the width and the height of the page area, in which the document is
+    /// painted. At least 1px large.
+    pub fn area(&self) -> (f64, f64, f64, f64) {
+        let (top, right, bottom, left) = self.margin;
+        (
+            left,
+            top,
+            (self.width - left - right).max(1.0),
+            (self.height - top - bottom).max(1.0),
+        )
+    }
+}
+
+/// The page box of the page ``index`` (from 0) of a document with ``stylesheet``. The rules of
+/// ``:first`` override those of ``:left`` and ``:right``, which override those of all pages.
+/// The first page is a right page.
+pub fn page_box(stylesheet: &Stylesheet, index: usize) -> PageBox {
+    let (width, height) = page_size(&cascade(stylesheet, 0));
+    let default = DEFAULT_PAGE_MARGIN;
+    let mut page = PageBox {
+        width: width,
+        height: height,
+        margin: (default, default, default, default),
+    };
+    for declaration in cascade(stylesheet, index) {
+        let margin = |value: &Value, len: f64| match *value {
+            Value::Keyword(ref k) if k == "auto" => Some(DEFAULT_PAGE_MARGIN),
+            ref value => value.maybe_percent_to_px(len),
+        };
+        let values = &declaration.values;
+        let (top, right, bottom, left) = &mut page.margin;
+        match declaration.name.as_str() {
+            "margin" => {
+                // 1 to 4 values, as of the ``margin`` of a box
+                let i = |n: usize| match values.len() {
+                    1 => 0,
+                    2 => n % 2,
+                    3 => [0, 1, 2, 1][n],
+                    _ => n,
+                };
+                if values.is_empty() || values.len() > 4 {
+                    continue;
+                }
+                for (n, (margin_side, len)) in
+                    vec![(top, height), (right, width), (bottom, height), (left, width)]
+                        .into_iter()
+                        .enumerate()
+                {
+                    if let Some(px) = margin(&values[i(n)], len) {
+                        *margin_side = px;
+                    }
+                }
+            }
+            "margin-top" | "margin-right" | "margin-bottom" | "margin-left" => {
+                let (margin_side, len) = match declaration.name.as_str() {
+                    "margin-top" => (top, height),
+                    "margin-right" => (right, width),
+                    "margin-bottom" => (bottom, height),
+                    _ => (left, width),
+                };
+                if let Some(px) = values.first().and_then(|value| margin(value, len)) {
+                    *margin_side = px;
+                }
+            }
+            _ => {}
+        }
+    }
+    page
+}
+
+/// The pages a document ``height`` CSS px high (laid out in the page area of the first page) is
+/// split into, and where each of them starts in the document. At least one.
+pub fn paginate(stylesheet: &Stylesheet, height: f64) -> Vec<(PageBox, f64)> {
+    let mut pages = vec![];
+    let mut offset = 0.0;
+    loop {
+        let page = page_box(stylesheet, pages.len());
+        pages.push((page, offset));
+        offset += page.area().3;
+        if offset >= height {
+            return pages;
+        }
+    }
+}
+
+/// The declarations of the ``@page`` rules that apply to the page ``index``, in the order they
+/// override each other
+fn cascade(stylesheet: &Stylesheet, index: usize) -> Vec<&Declaration> {
+    let mut rules: Vec<(usize, &[Declaration])> = stylesheet
+        .pages
+        .iter()
+        .filter(|page| page.applies())
+        .filter_map(|page| {
+            let specificity = match page.selector.as_str() {
+                "" => 0,
+                ":right" if index.is_multiple_of(2) => 1,
+                ":left" if !index.is_multiple_of(2) => 1,
+                ":first" if index == 0 => 2,
+                // Named pages, or of another side
+                _ => return None,
+            };
+            Some((specificity, page.declarations.as_slice()))
+        })
+        .collect();
+    // Stable, so that the later rules of a selector override the former ones
+    rules.sort_by_key(|&(specificity, _)| specificity);
+    rules.into_iter().flat_map(|(_, declarations)| declarations).collect()
+}
+
+/// The width and the height of the page of the last ``size`` in ``declarations``
+fn page_size(declarations: &[&Declaration]) -> (f64, f64) {
+    let size = declarations
+        .iter()
+        .rev()
+        .find(|declaration| declaration.name == "size")
+        .map_or(&[][..], |declaration| declaration.values.as_slice());
+
+    let lengths: Vec<f64> = size.iter()
+        .filter_map(|value| match *value {
+            Value::Length(_, Unit::Percent) => None,
+            Value::Length(_, _) => value.to_px(),
+            _ => None,
+        })
+        .collect();
+    match lengths.as_slice() {
+        [side] => return (*side, *side),
+        [width, height] => return (*width, *height),
+        _ => {}
+    }
+
+    let mut page_size = DEFAULT_PAGE_SIZE;
+    let mut landscape = false;
+    for value in size {
+        if let Value::Keyword(ref keyword) = *value {
+            match keyword.to_lowercase().as_str() {
+                "landscape" => landscape = true,
+                "portrait" => landscape = false,
+                name => page_size = named_page_size(name).unwrap_or(page_size),
+            }
+        }
+    }
+    let (short, long) = (page_size.0.min(page_size.1), page_size.0.max(page_size.1));
+    if landscape {
+        (long, short)
+    } else {
+        (short, long)
+    }
+}
+
+/// The portrait size (CSS px) of a page size of ``size``, e.g. ``a4`` or ``letter``
+fn named_page_size(name: &str) -> Option<(f64, f64)> {
+    match name {
+        "a5" => Some((148.0 * MM, 210.0 * MM)),
+        "a4" => Some((210.0 * MM, 297.0 * MM)),
+        "a3" => Some((297.0 * MM, 420.0 * MM)),
+        "b5" => Some((176.0 * MM, 250.0 * MM)),
+        "b4" => Some((250.0 * MM, 353.0 * MM)),
+        "letter" => Some((8.5 * 96.0, 11.0 * 96.0)),
+        "legal" => Some((8.5 * 96.0, 14.0 * 96.0)),
+        "ledger" => Some((11.0 * 96.0, 17.0 * 96.0)),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_page_box() {
+    use css;
+    use media::{self, MediaType};
+
+    let stylesheet = css::parse(
+        "@page { size: letter landscape; margin: 1in 25%; } \
+         @page :first { margin-top: 2in; } \
+         @page :left { margin-left: 0; } \
+         @media print { @page :right { margin-right: 1cm } } \
+         @page wide { size: a3 }"
+            .to_string(),
+    );
+    assert_eq!(stylesheet.pages.len(), 5);
+
+    let first = page_box(&stylesheet, 0);
+    assert_eq!((first.width, first.height), (1056.0, 816.0));
+    assert_eq!(first.margin, (192.0, 264.0, 96.0, 264.0));
+    assert_eq!(first.area(), (264.0, 192.0, 528.0, 528.0));
+    assert_eq!(page_box(&stylesheet, 1).margin, (96.0, 264.0, 96.0, 0.0));
+
+    media::set_media_type(MediaType::Print);
+    assert_eq!(page_box(&stylesheet, 2).margin.1, 96.0 / 2.54);
+    media::set_media_type(MediaType::Screen);
+
+    // Split into the page areas of each page
+    let pages = paginate(&stylesheet, 1200.0);
+    let offsets: Vec<f64> = pages.iter().map(|&(_, offset)| offset).collect();
+    assert_eq!(offsets, vec![0.0, 528.0, 1152.0]);
+
+    let default = page_box(&css::parse("@page { size: 100px 50px }".to_string()), 0);
+    assert_eq!((default.width, default.height), (100.0, 50.0));
+    let default = page_box(&css::parse("".to_string()), 0);
+    assert_eq!((default.width, default.height), DEFAULT_PAGE_SIZE);
+    assert_eq!(default.area().2, DEFAULT_PAGE_SIZE.0 - DEFAULT_PAGE_MARGIN * 2.0);
+}
@@ -1,57 +1,342 @@
-use layout::{BoxType, ImageMetaData, LayoutBox, LayoutInfo, Rect};
+use layout::{BoxType, Dimensions, EdgeSizes, ImageMetaData, LayoutBox, LayoutInfo, Rect,
+             SELECT_ARROW_WIDTH};
+use background::{self, BackgroundImage, BorderImage};
+use overflow;
+use shape::{self, FillRule, Shape};
+use svg::{self, Graphic, PathSegment, Svg};
+use canvas2d;
+use mathml::MathItem;
+use filter::Filter;
+use html;
+use network;
 use font::Font;
+use hyphenation::HYPHEN;
+use image::{Image, PixbufExt};
+use inline;
 use dom::{ElementData, LayoutType, NodeType};
-use css::{Color, TextDecoration, BLACK};
+use css::{Color, TextDecoration, BLACK, WHITE};
 use app_units::Au;
 
-use gdk_pixbuf;
+#[cfg(feature = "gtk")]
 use gtk;
 
-use window::{AnkerKind, ANKERS, URL_FRAGMENTS};
+use std::cell::Cell;
+use std::cmp::max;
+use std::slice;
+
+use style::{ObjectFit, Outline};
+use stats::{self, Phase};
+use controls::{AnkerKind, SelectArea, TextInputArea, ANKERS, FOCUSED_SELECT, FOCUSED_TEXT_INPUT,
+               SELECTS, TEXT_INPUTS, URL_FRAGMENTS};
+#[cfg(feature = "gtk")]
+use controls::BUTTONS;
+
+pub const FOCUS_RING_COLOR: Color = Color {
+    r: 0x4d,
+    g: 0x90,
+    b: 0xfe,
+    a: 0xff,
+};
+
+pub const SCROLLBAR_TRACK_COLOR: Color = Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 0x0d,
+};
+
+pub const SCROLLBAR_THUMB_COLOR: Color = Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 0x66,
+};
+
+pub const SCROLLBAR_THUMB_HOVER_COLOR: Color = Color {
+    r: 0,
+    g: 0,
+    b: 0,
+    a: 0x99,
+};
+
+pub const IMAGE_PLACEHOLDER_COLOR: Color = Color {
+    r: 0xee,
+    g: 0xee,
+    b: 0xee,
+    a: 0xff,
+};
+
+thread_local!(
+    /// The part of the document shown (CSS px), which fixed backgrounds are placed in. Set by
+    /// ``set_viewport``. None is the whole document.
+    static VIEWPORT: Cell<Option<Rect>> = { Cell::new(None) };
+    /// The viewport the display list built last is for, and whether it has fixed backgrounds
+    static PAINTED_VIEWPORT: Cell<(Rect, bool)> = { Cell::new((Rect::default(), false)) };
+    /// The id of the node whose background is painted on the canvas instead of its box (see
+    /// ``canvas_background_box``)
+    static CANVAS_BACKGROUND: Cell<Option<usize>> = { Cell::new(None) };
+);
+
+/// Sets the part of the document shown (CSS px), e.g. when it is scrolled.
+pub fn set_viewport(viewport: Rect) {
+    VIEWPORT.with(|v| v.set(Some(viewport)));
+}
+
+/// Whether the display list built last has to be built again, because it has fixed backgrounds
+/// and the viewport has moved since.
+pub fn fixed_backgrounds_moved() -> bool {
+    let (painted, fixed) = PAINTED_VIEWPORT.with(|v| v.get());
+    fixed && VIEWPORT.with(|v| v.get()).is_some_and(|viewport| viewport != painted)
+}
 
 #[derive(Debug, Clone)]
 pub enum DisplayCommand {
     SolidColor(Color, Rect),
-    Image(gdk_pixbuf::Pixbuf, ImageMetaData, Rect),
+    Image(Image, ImageMetaData, Rect),
+    /// A path filled and stroked, e.g. a shape of an ``<svg>``
+    Path(svg::Path),
+    /// A background image scaled to the size of the tiles, painted at each of them and clipped
+    /// to the last rect (the border box). The image of an ``<img>`` placed by ``object-fit`` is
+    /// one tile clipped to the content box.
+    ImageTiles(Image, ImageMetaData, Vec<Rect>, Rect),
     Text(String, Rect, Color, Vec<TextDecoration>, Font),
+    /// Painted like a ``Text``, which it is the shadow of (``text-shadow``), but not selected
+    TextShadow(String, Rect, Color, Vec<TextDecoration>, Font),
+    /// A widget of the window (see ``controls::BUTTONS``)
+    #[cfg(feature = "gtk")]
     Button(gtk::Button, Rect),
+    /// The items until the matching ``PopClip`` are clipped to the rect (and to the clips pushed
+    /// before), e.g. the content of a box with ``overflow: hidden``.
+    PushClip(Rect),
+    /// Like ``PushClip``, to a shape (``clip-path``)
+    PushClipPath(Shape),
+    PopClip,
+    /// The items until the matching ``PopLayer`` are painted into a layer of its own, which
+    /// covers the rect (all they paint).
+    PushLayer(Rect),
+    /// The filters are applied to the layer, which is then painted over the earlier items.
+    PopLayer(Vec<Filter>),
 }
 
 #[derive(Debug, Clone)]
 pub struct DisplayCommandInfo {
     pub command: DisplayCommand,
+    /// True for the first text in a block. Copied text has a line break there.
+    pub starts_block: bool,
 }
 
 impl DisplayCommandInfo {
     pub fn new(command: DisplayCommand) -> DisplayCommandInfo {
-        DisplayCommandInfo { command: command }
+        DisplayCommandInfo {
+            command: command,
+            starts_block: false,
+        }
     }
 }
 
 pub type DisplayList = Vec<DisplayCommandInfo>;
 
-pub fn build_display_list(layout_root: &mut LayoutBox) -> DisplayList {
-    let mut list = Vec::new();
-    render_layout_box(
-        &mut list,
-        Au::from_f64_px(0.0),
-        Au::from_f64_px(0.0),
-        layout_root,
+/// What a display list is painted with (see ``paint``), e.g. cairo (``raster::CairoBackend``)
+/// or a canvas of a web page (``canvas::CanvasBackend``). The rects are in CSS px in the
+/// coordinates of the document, and each item is painted over the earlier ones.
+pub trait PaintBackend {
+    fn fill_rect(&mut self, color: &Color, rect: Rect);
+    /// ``metadata`` is the size to scale ``image`` to.
+    fn draw_image(&mut self, image: &Image, metadata: &ImageMetaData, rect: Rect);
+    /// Draws ``image`` scaled to ``metadata`` at each of ``tiles``, clipped to ``clip``.
+    fn draw_image_tiles(
+        &mut self,
+        image: &Image,
+        metadata: &ImageMetaData,
+        tiles: &[Rect],
+        clip: Rect,
+    );
+    /// The top of ``rect`` is the top of the line.
+    fn draw_text(
+        &mut self,
+        text: &str,
+        rect: Rect,
+        color: &Color,
+        decorations: &[TextDecoration],
+        font: &Font,
     );
-    list
+    /// Fills ``path`` with its fill, then strokes it with its stroke.
+    fn draw_path(&mut self, path: &svg::Path);
+    /// Clips what is painted until the matching ``pop_clip`` to ``rect``, within the current
+    /// clip.
+    fn push_clip(&mut self, rect: Rect);
+    /// Like ``push_clip``, to ``shape``.
+    fn push_clip_path(&mut self, shape: &Shape);
+    fn pop_clip(&mut self);
+    /// Paints what is painted until the matching ``pop_layer`` into a new layer, out of which
+    /// nothing is painted in ``rect``.
+    fn push_layer(&mut self, rect: Rect);
+    /// Applies ``filters`` to the layer pushed last, and paints it.
+    fn pop_layer(&mut self, filters: &[Filter]);
+}
+
+/// Paints the display list with ``backend``, in order. Buttons are widgets placed by the window,
+/// and are not painted.
+pub fn paint(list: &DisplayList, backend: &mut dyn PaintBackend) {
+    for item in list {
+        match item.command {
+            DisplayCommand::SolidColor(ref color, rect) => backend.fill_rect(color, rect),
+            DisplayCommand::Image(ref image, ref metadata, rect) => {
+                backend.draw_image(image, metadata, rect)
+            }
+            DisplayCommand::ImageTiles(ref image, ref metadata, ref tiles, clip) => {
+                backend.draw_image_tiles(image, metadata, tiles, clip)
+            }
+            DisplayCommand::Path(ref path) => backend.draw_path(path),
+            DisplayCommand::Text(ref text, rect, ref color, ref decorations, ref font)
+            | DisplayCommand::TextShadow(ref text, rect, ref color, ref decorations, ref font) => {
+                backend.draw_text(text, rect, color, decorations, font)
+            }
+            #[cfg(feature = "gtk")]
+            DisplayCommand::Button(_, _) => {}
+            DisplayCommand::PushClip(rect) => backend.push_clip(rect),
+            DisplayCommand::PushClipPath(ref shape) => backend.push_clip_path(shape),
+            DisplayCommand::PopClip => backend.pop_clip(),
+            DisplayCommand::PushLayer(rect) => backend.push_layer(rect),
+            DisplayCommand::PopLayer(ref filters) => backend.pop_layer(filters),
+        }
+    }
+}
+
+/// The first item is always the canvas: a ``SolidColor`` as large as the document (the margin
+/// box of the root) and at least as large as the viewport. It is ``Canvas`` of the color scheme
+/// of the root (white if it is light), under the background of the root, or of the ``<body>`` if
+/// the root has none (which is then not painted on their box).
+pub fn build_display_list(layout_root: &mut LayoutBox) -> DisplayList {
+    stats::time(Phase::Paint, || {
+        let viewport = VIEWPORT
+            .with(|v| v.get())
+            .unwrap_or_else(|| layout_root.dimensions.border_box());
+        PAINTED_VIEWPORT.with(|v| v.set((viewport, false)));
+
+        // The canvas is the document, and the viewport where the document is shorter.
+        let root_box = layout_root.dimensions.margin_box();
+        let canvas = Rect {
+            x: Au(0),
+            y: Au(0),
+            width: max(root_box.x + root_box.width, viewport.width),
+            height: max(root_box.y + root_box.height, viewport.height),
+        };
+        let mut list = vec![];
+        let canvas_color = layout_root
+            .property
+            .color_scheme()
+            .system_color("canvas")
+            .unwrap_or(WHITE);
+        let root_padding_box = layout_root.dimensions.padding_box();
+        let mut background_box = canvas_background_box(layout_root);
+        let color = background_box.as_mut().and_then(|background_box| {
+            lookup_color(background_box, "background-color", "background")
+        });
+        if color.is_none_or(|color| color.a < 0xff) {
+            let canvas_color = DisplayCommand::SolidColor(canvas_color, canvas);
+            list.push(DisplayCommandInfo::new(canvas_color));
+        }
+        if let Some(color) = color {
+            list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(color, canvas)));
+        }
+        // The image is placed in the root as if its box had it, and repeated over the canvas.
+        let id = background_box.and_then(|background_box| {
+            render_background_image_in(&mut list, background_box, root_padding_box, canvas);
+            background_box.node_id
+        });
+        CANVAS_BACKGROUND.with(|c| c.set(id));
+
+        render_layout_box(
+            &mut list,
+            Au::from_f64_px(0.0),
+            Au::from_f64_px(0.0),
+            layout_root,
+        );
+        list
+    })
+}
+
+/// The box whose background is painted on the whole canvas instead of on it: the root, or the
+/// ``<body>`` in the ``<html>`` root if the root has no background. None if neither has one.
+fn canvas_background_box(layout_root: &mut LayoutBox) -> Option<&mut LayoutBox> {
+    fn has_background(layout_box: &mut LayoutBox) -> bool {
+        lookup_color(layout_box, "background-color", "background").is_some_and(|c| c.a > 0)
+            || layout_box.property.background_image().is_some()
+    }
+    let is_element = |layout_box: &LayoutBox, tag_name: &str| match layout_box.node.data {
+        NodeType::Element(ref e) => e.tag_name == tag_name,
+        NodeType::Text(_) => false,
+    };
+
+    if layout_root.box_type == BoxType::None {
+        return None;
+    }
+    if has_background(layout_root) {
+        return Some(layout_root);
+    }
+    if !is_element(layout_root, "html") {
+        return None;
+    }
+    let body = layout_root
+        .children
+        .iter_mut()
+        .find(|child| is_element(child, "body") && child.box_type != BoxType::None)?;
+    if has_background(body) {
+        Some(body)
+    } else {
+        None
+    }
+}
+
+/// The dimensions of every box in the layout tree, with the content area in the coordinates of
+/// the document (like the display list). Parents come before their children.
+pub fn box_model(layout_root: &LayoutBox) -> Vec<Dimensions> {
+    let mut boxes = vec![];
+    collect_box_model(&mut boxes, Au(0), Au(0), layout_root);
+    boxes
+}
+
+fn collect_box_model(boxes: &mut Vec<Dimensions>, x: Au, y: Au, layout_box: &LayoutBox) {
+    let mut dimensions = layout_box.dimensions;
+    dimensions.content = dimensions.content.add_parent_coordinate(x, y);
+    boxes.push(dimensions);
+    let (child_x, child_y) = overflow::child_origin(layout_box, x, y);
+    for child in &layout_box.children {
+        collect_box_model(boxes, child_x, child_y, child);
+    }
 }
 
 fn render_layout_box(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox) {
     let is_input_elem = match layout_box.info {
-        LayoutInfo::Button(_, _) => true,
+        LayoutInfo::Button(_) => true,
         _ => false,
     };
 
     let mut buf = DisplayList::new();
 
+    // Nothing of the box (nor of its descendants) is painted out of its clip-path.
+    let clip_path = shape::clip_path(layout_box, x, y);
+    if let Some(ref clip_path) = clip_path {
+        buf.push(DisplayCommandInfo::new(DisplayCommand::PushClipPath(
+            clip_path.clone(),
+        )));
+    }
+
+    // The box and its descendants are filtered together, before they are clipped.
+    let filters = layout_box.property.filter();
+    let layer_start = buf.len();
+
     render_background(&mut buf, x, y, layout_box);
     render_borders(&mut buf, x, y, layout_box);
 
+    // The content overflowing the padding box is clipped, and scrolled.
+    let clip = overflow::clip_rect(layout_box, x, y);
+    if let Some(clip) = clip {
+        buf.push(DisplayCommandInfo::new(DisplayCommand::PushClip(clip)));
+    }
+    let (child_x, child_y) = overflow::child_origin(layout_box, x, y);
+
     let mut children = layout_box.children.clone();
     children.sort_by(|&LayoutBox { z_index: a, .. }, &LayoutBox { z_index: b, .. }| a.cmp(&b));
 
@@ -59,31 +344,47 @@ fn render_layout_box(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut Layo
         .iter_mut()
         .filter(|child| child.box_type != BoxType::Float)
     {
-        render_layout_box(
-            &mut buf,
-            x + layout_box.dimensions.content.x,
-            y + layout_box.dimensions.content.y,
-            &mut child,
-        );
+        render_layout_box(&mut buf, child_x, child_y, &mut child);
     }
     for mut child in children
         .iter_mut()
         .filter(|child| child.box_type == BoxType::Float)
     {
-        render_layout_box(
-            &mut buf,
-            x + layout_box.dimensions.content.x,
-            y + layout_box.dimensions.content.y,
-            &mut child,
-        );
+        render_layout_box(&mut buf, child_x, child_y, &mut child);
+    }
+    if clip.is_some() {
+        buf.push(DisplayCommandInfo::new(DisplayCommand::PopClip));
+        render_scrollbars(&mut buf, x, y, layout_box);
     }
 
     render_text(&mut buf, x, y, layout_box);
     render_image(&mut buf, x, y, layout_box);
+    render_svg(&mut buf, x, y, layout_box);
+    render_canvas(&mut buf, x, y, layout_box);
+    render_math(&mut buf, x, y, layout_box);
+    render_text_input(&mut buf, x, y, layout_box);
+    render_select(&mut buf, x, y, layout_box);
+    render_outline(&mut buf, x, y, layout_box);
+
+    if !filters.is_empty() {
+        if let Some(rect) = layer_rect(&buf[layer_start..], &filters) {
+            buf.insert(layer_start, DisplayCommandInfo::new(DisplayCommand::PushLayer(rect)));
+            buf.push(DisplayCommandInfo::new(DisplayCommand::PopLayer(filters)));
+        }
+    }
+
+    if clip_path.is_some() {
+        buf.push(DisplayCommandInfo::new(DisplayCommand::PopClip));
+    }
 
     register_anker(x, y, layout_box);
     register_url_fragment(x, y, layout_box);
 
+    match layout_box.box_type {
+        BoxType::BlockNode | BoxType::AnonymousBlock | BoxType::Float => mark_block_start(&mut buf),
+        _ => {}
+    }
+
     if is_input_elem {
         render_button(list, &mut buf, x, y, layout_box);
     } else {
@@ -91,6 +392,54 @@ fn render_layout_box(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut Layo
     }
 }
 
+/// The rect covering all that ``items`` paint once ``filters`` are applied to them. None if they
+/// paint nothing.
+fn layer_rect(items: &[DisplayCommandInfo], filters: &[Filter]) -> Option<Rect> {
+    let painted = items
+        .iter()
+        .filter_map(|item| match item.command {
+            DisplayCommand::SolidColor(_, rect)
+            | DisplayCommand::Image(_, _, rect)
+            | DisplayCommand::ImageTiles(_, _, _, rect)
+            | DisplayCommand::Text(_, rect, _, _, _)
+            | DisplayCommand::TextShadow(_, rect, _, _, _) => Some(rect),
+            DisplayCommand::Path(ref path) => path.bounds(),
+            _ => None,
+        })
+        .fold(None, |union: Option<Rect>, rect| {
+            Some(union.map_or(rect, |union| union.union(rect)))
+        })?;
+    let outset = Au::from_f64_px(filters.iter().map(Filter::outset).sum::<f64>().ceil());
+    Some(painted.expanded_by(EdgeSizes {
+        left: outset,
+        right: outset,
+        top: outset,
+        bottom: outset,
+    }))
+}
+
+fn render_scrollbars(list: &mut DisplayList, x: Au, y: Au, layout_box: &LayoutBox) {
+    let hovered = overflow::scrollbar_hovered(layout_box);
+    let (track_color, thumb_color) = overflow::scrollbar_colors(&layout_box.property, hovered);
+    for (track, thumb) in overflow::scrollbars(layout_box, x, y) {
+        for &(color, rect) in &[(track_color, track), (thumb_color, thumb)] {
+            if color.a > 0 {
+                list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(color, rect)));
+            }
+        }
+    }
+}
+
+fn mark_block_start(list: &mut DisplayList) {
+    if let Some(info) = list.iter_mut().find(|info| match info.command {
+        DisplayCommand::Text(_, _, _, _, _) => true,
+        _ => false,
+    }) {
+        info.starts_block = true;
+    }
+}
+
+#[cfg(feature = "gtk")]
 fn render_button(
     list: &mut DisplayList,
     _children: &mut DisplayList,
@@ -98,14 +447,23 @@ fn render_button(
     y: Au,
     layout_box: &mut LayoutBox,
 ) {
-    if let &LayoutInfo::Button(ref btn, _) = &layout_box.info {
+    // No button is painted before the window creates it.
+    let btn = match layout_box.info {
+        LayoutInfo::Button(id) => BUTTONS.with(|b| b.borrow().get(&id).cloned()),
+        _ => None,
+    };
+    if let Some(btn) = btn {
         list.push(DisplayCommandInfo::new(DisplayCommand::Button(
-            btn.clone().unwrap(),
+            btn,
             layout_box.dimensions.content.add_parent_coordinate(x, y),
         )));
     }
 }
 
+/// No button is painted without the window (the ``gtk`` feature).
+#[cfg(not(feature = "gtk"))]
+fn render_button(_: &mut DisplayList, _: &mut DisplayList, _: Au, _: Au, _: &mut LayoutBox) {}
+
 fn render_text(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox) {
     if let &mut BoxType::TextNode(ref text_info) = &mut layout_box.box_type {
         let text = if let NodeType::Text(ref text) = layout_box.node.data {
@@ -113,14 +471,50 @@ fn render_text(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox)
         } else {
             unreachable!()
         };
+        let text = if text_info.hyphenated {
+            format!("{}{}", text, HYPHEN)
+        } else {
+            text.to_string()
+        };
+        let rect = layout_box.dimensions.content.add_parent_coordinate(x, y);
+        let decorations = layout_box.property.text_decoration();
+
+        // Under the text, the last shadow at the bottom
+        for shadow in layout_box.property.text_shadow().into_iter().rev() {
+            if let Filter::DropShadow {
+                offset: (offset_x, offset_y),
+                blur,
+                color,
+            } = shadow
+            {
+                let mut shadow_rect = rect;
+                shadow_rect.x += Au::from_f64_px(offset_x);
+                shadow_rect.y += Au::from_f64_px(offset_y);
+                let shadow = DisplayCommandInfo::new(DisplayCommand::TextShadow(
+                    text.clone(),
+                    shadow_rect,
+                    color,
+                    decorations.clone(),
+                    text_info.font,
+                ));
+                if blur > 0.0 {
+                    let filters = vec![Filter::Blur(blur / 2.0)];
+                    let layer =
+                        layer_rect(slice::from_ref(&shadow), &filters).unwrap_or(shadow_rect);
+                    list.push(DisplayCommandInfo::new(DisplayCommand::PushLayer(layer)));
+                    list.push(shadow);
+                    list.push(DisplayCommandInfo::new(DisplayCommand::PopLayer(filters)));
+                } else {
+                    list.push(shadow);
+                }
+            }
+        }
+
         list.push(DisplayCommandInfo::new(DisplayCommand::Text(
-            text.to_string(),
-            layout_box.dimensions.content.add_parent_coordinate(x, y),
-            match layout_box.property.value("color") {
-                Some(maybe_color) => maybe_color[0].to_color(),
-                _ => None,
-            }.unwrap_or(BLACK),
-            layout_box.property.text_decoration(),
+            text,
+            rect,
+            layout_box.property.color(),
+            decorations,
             text_info.font,
         )));
     }
@@ -133,25 +527,260 @@ fn render_image(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox
     {
         if layout_type == &LayoutType::Image {
             if let &LayoutInfo::Image(ref imgdata) = &layout_box.info {
-                list.push(DisplayCommandInfo::new(DisplayCommand::Image(
-                    imgdata.pixbuf.clone().unwrap(),
-                    imgdata.metadata.clone(),
-                    layout_box.dimensions.content.add_parent_coordinate(x, y),
-                )))
+                let rect = layout_box.dimensions.content.add_parent_coordinate(x, y);
+                let fit = layout_box.property.object_fit();
+                let pixbuf = imgdata.source.as_ref().and_then(|&(ref url, _)| inline::pixbuf(url));
+                list.push(DisplayCommandInfo::new(match pixbuf {
+                    Some(pixbuf) if fit != ObjectFit::Fill => {
+                        let natural_size = imgdata.intrinsic_size.unwrap_or((
+                            pixbuf.get_width() as f64,
+                            pixbuf.get_height() as f64,
+                        ));
+                        let position = layout_box.property.object_position();
+                        let object = background::object_rect(fit, &position, natural_size, rect);
+                        let size = ImageMetaData::new(object.width, object.height);
+                        DisplayCommand::ImageTiles(pixbuf, size, vec![object], rect)
+                    }
+                    Some(pixbuf) => {
+                        DisplayCommand::Image(pixbuf, imgdata.metadata.clone(), rect)
+                    }
+                    // Still loading
+                    None => DisplayCommand::SolidColor(IMAGE_PLACEHOLDER_COLOR, rect),
+                }))
+            }
+        }
+    }
+}
+
+/// Paints the bitmap of a ``<canvas>`` into its content box. Nothing until it is drawn on.
+fn render_canvas(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox) {
+    let (imgdata, node_id) = match (&layout_box.info, layout_box.node_id) {
+        (&LayoutInfo::Canvas(ref imgdata), Some(node_id)) => (imgdata, node_id),
+        _ => return,
+    };
+    let size = canvas2d::bitmap_size(&layout_box.node);
+    if let Some(pixbuf) = canvas2d::pixbuf(node_id, size) {
+        let rect = layout_box.dimensions.content.add_parent_coordinate(x, y);
+        list.push(DisplayCommandInfo::new(DisplayCommand::Image(
+            pixbuf,
+            imgdata.metadata.clone(),
+            rect,
+        )));
+    }
+}
+
+/// Paints the SVG DOM of an ``<svg>`` into its content box, which clips it.
+fn render_svg(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox) {
+    match layout_box.info {
+        LayoutInfo::Svg(_) => {}
+        _ => return,
+    }
+    let rect = layout_box.dimensions.content.add_parent_coordinate(x, y);
+    let svg = Svg::from_node(&layout_box.node);
+    let transform = svg.transform(rect);
+    let color = layout_box.property.color();
+    let font = layout_box.property.font();
+
+    list.push(DisplayCommandInfo::new(DisplayCommand::PushClip(rect)));
+    for graphic in &svg.graphics {
+        match *graphic {
+            Graphic::Path(ref segments, ref style) => {
+                let path = svg::Path {
+                    segments: segments
+                        .iter()
+                        .map(|segment| segment.map(|point| transform.apply(point)))
+                        .collect(),
+                    fill: style.fill.resolve(color).map(|fill| (fill, style.fill_rule)),
+                    stroke: style
+                        .stroke
+                        .resolve(color)
+                        .filter(|_| style.stroke_width > 0.0)
+                        .map(|stroke| (stroke, style.stroke_width * transform.scale)),
+                };
+                list.push(DisplayCommandInfo::new(DisplayCommand::Path(path)));
+            }
+            Graphic::Text(ref text, position, ref style) => {
+                let fill = match style.fill.resolve(color) {
+                    Some(fill) => fill,
+                    None => continue,
+                };
+                let size = style.font_size.unwrap_or_else(|| font.size.to_f64_px());
+                let font = Font {
+                    size: Au::from_f64_px(size * transform.scale),
+                    ..font
+                };
+                // The text is placed by its baseline.
+                let selected = font.select();
+                let (ascent, descent) = selected.get_ascent_descent();
+                let (left, baseline) = transform.apply(position);
+                let rect = Rect {
+                    x: Au::from_f64_px(left),
+                    y: Au::from_f64_px(baseline) - ascent,
+                    width: Au::from_f64_px(selected.text_width(text)),
+                    height: ascent + descent,
+                };
+                list.push(DisplayCommandInfo::new(DisplayCommand::Text(
+                    text.clone(),
+                    rect,
+                    fill,
+                    vec![],
+                    font,
+                )));
             }
         }
     }
+    list.push(DisplayCommandInfo::new(DisplayCommand::PopClip));
+}
+
+fn render_math(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox) {
+    let math = match layout_box.info {
+        LayoutInfo::Math(ref math) => math.clone(),
+        _ => return,
+    };
+    let content = layout_box.dimensions.content.add_parent_coordinate(x, y);
+    let color = layout_box.property.color();
+    // The items are placed from the start of the baseline.
+    let (left, baseline) = (content.x.to_f64_px(), content.y.to_f64_px() + math.ascent);
+    for item in math.items {
+        let command = match item {
+            MathItem::Text(text, (x, y), font) => {
+                let selected = font.select();
+                let (ascent, descent) = selected.get_ascent_descent();
+                let rect = Rect {
+                    x: Au::from_f64_px(left + x),
+                    y: Au::from_f64_px(baseline + y) - ascent,
+                    width: Au::from_f64_px(selected.text_width(text.as_str())),
+                    height: ascent + descent,
+                };
+                DisplayCommand::Text(text, rect, color, vec![], font)
+            }
+            MathItem::Bar(x, y, width, height) => {
+                let rect = Rect {
+                    x: Au::from_f64_px(left + x),
+                    y: Au::from_f64_px(baseline + y),
+                    width: Au::from_f64_px(width),
+                    height: Au::from_f64_px(height),
+                };
+                DisplayCommand::SolidColor(color, rect)
+            }
+        };
+        list.push(DisplayCommandInfo::new(command));
+    }
+}
+
+fn render_text_input(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox) {
+    let id = match layout_box.info {
+        LayoutInfo::TextInput(id) => id,
+        _ => return,
+    };
+
+    let value = layout_box.node.text_input_value();
+    let rect = layout_box.dimensions.content.add_parent_coordinate(x, y);
+    let font = layout_box.property.font();
+    let line_height = layout_box.property.line_height();
+    let color = layout_box.property.color();
+
+    for (i, line) in value.split('\n').enumerate() {
+        list.push(DisplayCommandInfo::new(DisplayCommand::Text(
+            line.to_string(),
+            Rect {
+                y: rect.y + line_height * i as i32,
+                height: line_height,
+                ..rect
+            },
+            color,
+            vec![],
+            font,
+        )));
+    }
+
+    TEXT_INPUTS.with(|text_inputs| {
+        text_inputs.borrow_mut().insert(
+            id,
+            TextInputArea {
+                rect: rect,
+                font: font,
+                line_height: line_height,
+                value: value,
+                multiline: layout_box.node.is_multiline_text_input(),
+                pointer_events: layout_box.property.pointer_events(),
+            },
+        );
+    });
+}
+
+/// Paints the selected option of a <select> and an arrow pointing down at its end.
+fn render_select(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox) {
+    let id = match layout_box.info {
+        LayoutInfo::Select(id) => id,
+        _ => return,
+    };
+
+    let options: Vec<String> = layout_box
+        .node
+        .select_options()
+        .iter()
+        .map(|option| option.option_label())
+        .collect();
+    let selected = layout_box.node.selected_option();
+    let rect = layout_box.dimensions.content.add_parent_coordinate(x, y);
+    let color = layout_box.property.color();
+    let arrow_width = Au::from_f64_px(SELECT_ARROW_WIDTH);
+
+    if let Some(label) = selected.map(|i| options[i].clone()) {
+        list.push(DisplayCommandInfo::new(DisplayCommand::Text(
+            label,
+            Rect {
+                width: max(rect.width - arrow_width, Au(0)),
+                ..rect
+            },
+            color,
+            vec![],
+            layout_box.property.font(),
+        )));
+    }
+
+    let center_x = (rect.x + rect.width).to_f64_px() - SELECT_ARROW_WIDTH / 2.0;
+    let center_y = (rect.y + rect.height / 2).to_f64_px();
+    let half = SELECT_ARROW_WIDTH / 4.0;
+    list.push(DisplayCommandInfo::new(DisplayCommand::Path(svg::Path {
+        segments: vec![
+            PathSegment::MoveTo(center_x - half, center_y - half / 2.0),
+            PathSegment::LineTo(center_x + half, center_y - half / 2.0),
+            PathSegment::LineTo(center_x, center_y + half / 2.0),
+            PathSegment::Close,
+        ],
+        fill: Some((color, FillRule::NonZero)),
+        stroke: None,
+    })));
+
+    let border_box = layout_box.dimensions.border_box().add_parent_coordinate(x, y);
+    SELECTS.with(|selects| {
+        selects.borrow_mut().insert(
+            id,
+            SelectArea {
+                rect: border_box,
+                options: options,
+                selected: selected,
+                pointer_events: layout_box.property.pointer_events(),
+            },
+        );
+    });
 }
 
 fn register_anker(x: Au, y: Au, layout_box: &mut LayoutBox) {
+    // Not clicked
+    if !layout_box.property.pointer_events() {
+        return;
+    }
     match layout_box.info {
         LayoutInfo::Anker => {
             if let Some(url) = layout_box.node.anker_url() {
                 let rect = layout_box.dimensions.content.add_parent_coordinate(x, y);
                 ANKERS.with(|ankers| {
                     ankers.borrow_mut().entry(rect).or_insert_with(|| {
-                        if url.chars().next().unwrap() == '#' {
-                            AnkerKind::URLFragment(url[1..].to_string())
+                        if let Some(fragment) = url.strip_prefix('#') {
+                            AnkerKind::URLFragment(fragment.to_string())
                         } else {
                             AnkerKind::URL(url.to_string())
                         }
@@ -182,6 +811,10 @@ fn register_url_fragment(x: Au, y: Au, layout_box: &mut LayoutBox) {
 }
 
 fn render_background(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox) {
+    // Painted on the canvas
+    if layout_box.node_id.is_some() && CANVAS_BACKGROUND.with(|c| c.get()) == layout_box.node_id {
+        return;
+    }
     lookup_color(layout_box, "background-color", "background").map(|color| {
         list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(
             color,
@@ -191,9 +824,104 @@ fn render_background(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut Layo
                 .add_parent_coordinate(x, y),
         )))
     });
+    render_background_image(list, x, y, layout_box);
+}
+
+/// Paints the background image over the background color. Nothing is painted until it is
+/// loaded.
+fn render_background_image(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox) {
+    let padding_box = layout_box.dimensions.padding_box().add_parent_coordinate(x, y);
+    let border_box = layout_box.dimensions.border_box().add_parent_coordinate(x, y);
+    render_background_image_in(list, layout_box, padding_box, border_box);
+}
+
+/// Paints the background image of ``layout_box`` as if its padding box and border box were
+/// ``padding_box`` and ``border_box``.
+fn render_background_image_in(
+    list: &mut DisplayList,
+    layout_box: &mut LayoutBox,
+    padding_box: Rect,
+    border_box: Rect,
+) {
+    let pixbuf = match layout_box.property.background_image().and_then(style_pixbuf) {
+        Some(pixbuf) => pixbuf,
+        None => return,
+    };
+
+    let background = BackgroundImage::from_style(&layout_box.property);
+    let (viewport, _) = PAINTED_VIEWPORT.with(|v| v.get());
+    if background.fixed {
+        PAINTED_VIEWPORT.with(|v| v.set((viewport, true)));
+    }
+    let (positioning_area, painting_area) = match background.areas(
+        padding_box,
+        border_box,
+        viewport,
+    ) {
+        Some(areas) => areas,
+        None => return,
+    };
+    let image_size = (pixbuf.get_width() as f64, pixbuf.get_height() as f64);
+    let tiles = background.tiles(image_size, positioning_area, painting_area);
+    if let Some(&Rect { width, height, .. }) = tiles.first() {
+        list.push(DisplayCommandInfo::new(DisplayCommand::ImageTiles(
+            pixbuf,
+            ImageMetaData::new(width, height),
+            tiles,
+            painting_area,
+        )));
+    }
+}
+
+/// The image at ``url`` of a style (e.g. of ``background-image``), if it is loaded.
+fn style_pixbuf(url: String) -> Option<Image> {
+    // URLs in the stylesheets loaded are resolved already (see ``interface::resolve_urls``).
+    let url = html::BASE_URL
+        .with(|base| base.borrow().as_ref().and_then(|base| network::resolve_url(base, &url)))
+        .map_or(url, |url| url.to_string());
+    inline::background_pixbuf(&url)
+}
+
+/// Paints the border image instead of the borders. Returns false if there is none, or it is not
+/// loaded yet.
+fn render_border_image(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox) -> bool {
+    let pixbuf = match layout_box.property.border_image_source().and_then(style_pixbuf) {
+        Some(pixbuf) => pixbuf,
+        None => return false,
+    };
+    let image_size = (pixbuf.get_width() as f64, pixbuf.get_height() as f64);
+    let regions = BorderImage::from_style(&layout_box.property).regions(
+        image_size,
+        layout_box.dimensions.border_box().add_parent_coordinate(x, y),
+        &layout_box.dimensions.border,
+    );
+    for region in regions {
+        let source = region.source;
+        let image = match pixbuf.new_subpixbuf(
+            source.x.to_f64_px().round() as i32,
+            source.y.to_f64_px().round() as i32,
+            source.width.to_f64_px().round() as i32,
+            source.height.to_f64_px().round() as i32,
+        ) {
+            Some(image) => image,
+            None => continue,
+        };
+        let (width, height) = (region.tiles[0].width, region.tiles[0].height);
+        list.push(DisplayCommandInfo::new(DisplayCommand::ImageTiles(
+            image,
+            ImageMetaData::new(width, height),
+            region.tiles,
+            region.clip,
+        )));
+    }
+    true
 }
 
 fn render_borders(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox) {
+    if render_border_image(list, x, y, layout_box) {
+        return;
+    }
+
     let d = &layout_box.dimensions;
     let border_box = d.border_box().add_parent_coordinate(x, y);
 
@@ -252,6 +980,64 @@ fn render_borders(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutB
     }
 }
 
+fn render_outline(list: &mut DisplayList, x: Au, y: Au, layout_box: &mut LayoutBox) {
+    let focused = match layout_box.info {
+        LayoutInfo::TextInput(id) => {
+            FOCUSED_TEXT_INPUT.with(|f| f.borrow().as_ref().map(|caret| caret.id) == Some(id))
+        }
+        LayoutInfo::Select(id) => FOCUSED_SELECT.with(|f| f.get()) == Some(id),
+        _ => false,
+    };
+
+    let outline = if focused && !layout_box.property.outline_specified() {
+        // The default focus ring.
+        Some(Outline {
+            width: Au::from_f64_px(2.0),
+            color: FOCUS_RING_COLOR,
+            offset: Au(0),
+        })
+    } else {
+        layout_box.property.outline()
+    };
+
+    if let Some(Outline {
+        width,
+        color,
+        offset,
+    }) = outline
+    {
+        // Outlines are drawn outside the border box and do not take up space.
+        let border_box = layout_box
+            .dimensions
+            .border_box()
+            .add_parent_coordinate(x, y);
+        let outer = Rect {
+            x: border_box.x - offset - width,
+            y: border_box.y - offset - width,
+            width: border_box.width + (offset + width) * 2,
+            height: border_box.height + (offset + width) * 2,
+        };
+
+        for rect in &[
+            // Left, right, top, bottom
+            Rect { width: width, ..outer },
+            Rect {
+                x: outer.x + outer.width - width,
+                width: width,
+                ..outer
+            },
+            Rect { height: width, ..outer },
+            Rect {
+                y: outer.y + outer.height - width,
+                height: width,
+                ..outer
+            },
+        ] {
+            list.push(DisplayCommandInfo::new(DisplayCommand::SolidColor(color, *rect)));
+        }
+    }
+}
+
 /// Return the specified color for CSS property `name` or `fallback_name`, or None if no color was specified.
 fn lookup_color(layout_box: &mut LayoutBox, name: &str, fallback_name: &str) -> Option<Color> {
     match layout_box
@@ -262,3 +1048,208 @@ fn lookup_color(layout_box: &mut LayoutBox, name: &str, fallback_name: &str) ->
         _ => None,
     }
 }
+
+#[test]
+fn test_box_model() {
+    use layout;
+
+    let layout_root = layout::lay_out_html(
+        "<div><div style=\"margin: 10px; padding: 5px; border-width: 1px\">a</div></div>",
+        "",
+        100.0,
+        0.0,
+    );
+
+    let boxes = box_model(&layout_root);
+    assert_eq!(boxes.len(), 4); // The divs, the anonymous block and the text
+    let inner = boxes[1];
+    assert_eq!(inner.margin.left, Au::from_f64_px(10.0));
+    assert_eq!(inner.content.x, Au::from_f64_px(16.0));
+    assert_eq!(inner.content.y, Au::from_f64_px(16.0));
+    assert_eq!(inner.margin_box().width, Au::from_f64_px(100.0));
+    // The text is positioned in the content area of the inner div
+    assert!(boxes[3].content.x >= inner.content.x && boxes[3].content.y >= inner.content.y);
+}
+
+#[test]
+fn test_canvas() {
+    use layout;
+
+    for css_src in &["", "html { background: none; }", "html { display: none; }"] {
+        let mut layout_root = layout::lay_out_html("<p>a</p>", css_src, 100.0, 0.0);
+        let canvas = layout_root.dimensions.border_box();
+        match build_display_list(&mut layout_root)[0].command {
+            DisplayCommand::SolidColor(ref color, rect) => {
+                assert_eq!((color, rect), (&WHITE, canvas));
+            }
+            ref command => panic!("{:?} for {:?}", command, css_src),
+        }
+    }
+}
+
+#[test]
+fn test_canvas_background() {
+    use css::{BLUE, RED};
+    use layout;
+
+    let px = Au::from_f64_px;
+    let canvas = Rect {
+        x: Au(0),
+        y: Au(0),
+        width: px(100.0),
+        height: px(300.0),
+    };
+    VIEWPORT.with(|v| v.set(Some(canvas)));
+    let paint = |css_src: &str| -> Vec<(Color, Rect)> {
+        let html_src = "<!DOCTYPE html><html><body><p>a</p></body></html>";
+        let mut layout_root = layout::lay_out_html(html_src, css_src, 100.0, 300.0);
+        build_display_list(&mut layout_root)
+            .iter()
+            .filter_map(|item| match item.command {
+                DisplayCommand::SolidColor(color, rect) => Some((color, rect)),
+                _ => None,
+            })
+            .collect()
+    };
+
+    // The background of the body fills the canvas below the document, and not its box.
+    let colors = paint("body { display: block; background: red; }");
+    assert_eq!(colors, vec![(RED, canvas)]);
+    // The one of the root is used before it, and the body keeps its own.
+    let colors = paint("html { background: blue; } body { display: block; background: red; }");
+    assert_eq!(colors[0], (BLUE, canvas));
+    assert_eq!(colors[1].0, RED);
+    assert!(colors[1].1.height < px(300.0));
+    // Translucent backgrounds are over white.
+    let translucent = Color { a: 0x7f, ..RED };
+    let colors = paint("body { display: block; background: rgba(255, 0, 0, 0.5); }");
+    assert_eq!(colors, vec![(WHITE, canvas), (translucent, canvas)]);
+    VIEWPORT.with(|v| v.set(None));
+}
+
+#[test]
+fn test_color_scheme() {
+    use css::RED;
+    use layout;
+    use media::{self, ColorScheme};
+
+    let paint = |css_src: &str| -> (Color, Vec<Color>) {
+        let html_src = "<html><p>a</p><input value=\"b\"></html>";
+        let mut layout_root = layout::lay_out_html(html_src, css_src, 100.0, 0.0);
+        let list = build_display_list(&mut layout_root);
+        let canvas = match list[0].command {
+            DisplayCommand::SolidColor(color, _) => color,
+            ref command => panic!("{:?}", command),
+        };
+        let texts = list.iter().filter_map(|item| match item.command {
+            DisplayCommand::Text(_, _, color, _, _) => Some(color),
+            _ => None,
+        });
+        (canvas, texts.collect())
+    };
+    let dark = Color { r: 0x12, g: 0x12, b: 0x12, a: 0xff };
+
+    media::set_preferred_color_scheme(ColorScheme::Dark);
+    // Light unless the page supports dark
+    assert_eq!(paint(""), (WHITE, vec![BLACK, BLACK]));
+    assert_eq!(paint("html { color-scheme: light dark; }"), (dark, vec![WHITE, WHITE]));
+    assert_eq!(paint("p { color-scheme: dark; }"), (WHITE, vec![WHITE, BLACK]));
+    let css_src = "@media (prefers-color-scheme: dark) { p { color: red; } }";
+    assert_eq!(paint(css_src), (WHITE, vec![RED, BLACK]));
+    media::set_preferred_color_scheme(ColorScheme::Light);
+    assert_eq!(paint("html { color-scheme: light dark; }"), (WHITE, vec![BLACK, BLACK]));
+    assert_eq!(paint(css_src), (WHITE, vec![BLACK, BLACK]));
+}
+
+#[test]
+fn test_text_shadow() {
+    use css::{BLUE, RED};
+    use layout;
+
+    let mut layout_root = layout::lay_out_html(
+        "<p style=\"text-shadow: 1px 2px red, blue 0 0 4px\">a</p>",
+        "",
+        100.0,
+        0.0,
+    );
+    let list = build_display_list(&mut layout_root);
+
+    let texts: Vec<_> = list.iter().skip_while(|item| match item.command {
+        DisplayCommand::PushLayer(_) => false,
+        _ => true,
+    }).collect();
+    let text_rect = match texts[4].command {
+        DisplayCommand::Text(_, rect, color, _, _) => {
+            assert_eq!(color, BLACK);
+            rect
+        }
+        ref command => panic!("{:?}", command),
+    };
+    // The last shadow (blurred) at the bottom, then the first one
+    match (&texts[1].command, &texts[2].command) {
+        (&DisplayCommand::TextShadow(_, rect, color, _, _), &DisplayCommand::PopLayer(ref f)) => {
+            assert_eq!((rect, color), (text_rect, BLUE));
+            assert_eq!(f, &vec![Filter::Blur(2.0)]);
+        }
+        commands => panic!("{:?}", commands),
+    }
+    match texts[3].command {
+        DisplayCommand::TextShadow(ref text, rect, color, _, _) => {
+            assert_eq!((text.as_str(), color), ("a", RED));
+            assert_eq!(rect.x - text_rect.x, Au::from_f64_px(1.0));
+            assert_eq!(rect.y - text_rect.y, Au::from_f64_px(2.0));
+        }
+        ref command => panic!("{:?}", command),
+    }
+}
+
+#[test]
+fn test_svg() {
+    use css::{BLUE, RED};
+    use layout;
+    use svg::PathSegment;
+
+    let mut layout_root = layout::lay_out_html(
+        "<!DOCTYPE html><html><body><svg width=\"40\" viewBox=\"0 0 20 10\">\
+         <rect width=\"20\" height=\"10\" fill=\"red\" stroke=\"blue\"/><text y=\"8\">a</text>\
+         </svg></body></html>",
+        "body { display: block; margin: 0; }",
+        100.0,
+        0.0,
+    );
+    // A replaced element, as large as its width and the aspect ratio of its viewBox
+    let svg_box = &layout_root.children[0].children[0].children[0];
+    assert!(svg_box.children.is_empty());
+    assert_eq!(svg_box.dimensions.content.width, Au::from_f64_px(40.0));
+    assert_eq!(svg_box.dimensions.content.height, Au::from_f64_px(20.0));
+    let content = svg_box.dimensions.content;
+    let font_size = svg_box.property.clone().font_size();
+
+    let list = build_display_list(&mut layout_root);
+    let start = list.iter()
+        .position(|item| match item.command {
+            DisplayCommand::PushClip(rect) => rect == content,
+            _ => false,
+        })
+        .unwrap();
+    // Scaled twice, from the user space
+    match list[start + 1].command {
+        DisplayCommand::Path(ref path) => {
+            assert_eq!(path.segments[2], PathSegment::LineTo(40.0, 20.0));
+            assert_eq!(path.fill, Some((RED, shape::FillRule::NonZero)));
+            assert_eq!(path.stroke, Some((BLUE, 2.0)));
+        }
+        ref command => panic!("{:?}", command),
+    }
+    match list[start + 2].command {
+        DisplayCommand::Text(ref text, _, color, _, font) => {
+            assert_eq!((text.as_str(), color), ("a", BLACK));
+            assert_eq!(font.size, font_size * 2);
+        }
+        ref command => panic!("{:?}", command),
+    }
+    match list[start + 3].command {
+        DisplayCommand::PopClip => {}
+        ref command => panic!("{:?}", command),
+    }
+}
@@ -0,0 +1,204 @@
+//! Quirks mode: the pages without a doctype, or with one of an old HTML, are laid out with some of
+//! the quirks of the browsers they were written for. ref. https://quirks.spec.whatwg.org/
+//!
+//! ``html::parse`` selects the mode of the page from its doctype (see ``mode_for_doctype``). The
+//! quirks are:
+//! - The line height calculation quirk (also in limited quirks mode): the lines have no strut,
+//!   so that those of images alone (e.g. in table cells, when tables are laid out) are as high as
+//!   the images (see ``inline::LineMaker::strut``).
+//! - The percentage height calculation quirk: percentage heights are of the nearest ancestor
+//!   whose height does not depend on its content, or of the viewport.
+//! - The html element fills the viewport quirk and the body element fills the html element
+//!   quirk: their heights are at least that of the viewport, less the margins of ``<body>``.
+
+use std::cell::Cell;
+
+thread_local!(
+    static MODE: Cell<Mode> = { Cell::new(Mode::NoQuirks) };
+);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    NoQuirks,
+    /// Almost standards mode: only the line height calculation quirk
+    LimitedQuirks,
+    Quirks,
+}
+
+/// The beginnings of the public identifiers of the doctypes of quirks mode
+const QUIRKY_PUBLIC_ID_PREFIXES: [&str; 55] = [
+    "+//silmaril//dtd html pro v0r11 19970101//",
+    "-//as//dtd html 3.0 aswedit + extensions//",
+    "-//advasoft ltd//dtd html 3.0 aswedit + extensions//",
+    "-//ietf//dtd html 2.0 level 1//",
+    "-//ietf//dtd html 2.0 level 2//",
+    "-//ietf//dtd html 2.0 strict level 1//",
+    "-//ietf//dtd html 2.0 strict level 2//",
+    "-//ietf//dtd html 2.0 strict//",
+    "-//ietf//dtd html 2.0//",
+    "-//ietf//dtd html 2.1e//",
+    "-//ietf//dtd html 3.0//",
+    "-//ietf//dtd html 3.2 final//",
+    "-//ietf//dtd html 3.2//",
+    "-//ietf//dtd html 3//",
+    "-//ietf//dtd html level 0//",
+    "-//ietf//dtd html level 1//",
+    "-//ietf//dtd html level 2//",
+    "-//ietf//dtd html level 3//",
+    "-//ietf//dtd html strict level 0//",
+    "-//ietf//dtd html strict level 1//",
+    "-//ietf//dtd html strict level 2//",
+    "-//ietf//dtd html strict level 3//",
+    "-//ietf//dtd html strict//",
+    "-//ietf//dtd html//",
+    "-//metrius//dtd metrius presentational//",
+    "-//microsoft//dtd internet explorer 2.0 html strict//",
+    "-//microsoft//dtd internet explorer 2.0 html//",
+    "-//microsoft//dtd internet explorer 2.0 tables//",
+    "-//microsoft//dtd internet explorer 3.0 html strict//",
+    "-//microsoft//dtd internet explorer 3.0 html//",
+    "-//microsoft//dtd internet explorer 3.0 tables//",
+    "-//netscape comm. corp.//dtd html//",
+    "-//netscape comm. corp.//dtd strict html//",
+    "-//o'reilly and associates//dtd html 2.0//",
+    "-//o'reilly and associates//dtd html extended 1.0//",
+    "-//o'reilly and associates//dtd html extended relaxed 1.0//",
+    "-//sq//dtd html 2.0 hotmetal + extensions//",
+    "-//softquad software//dtd hotmetal pro 6.0::19990601::extensions to html 4.0//",
+    "-//softquad//dtd hotmetal pro 4.0::19971010::extensions to html 4.0//",
+    "-//spyglass//dtd html 2.0 extended//",
+    "-//sun microsystems corp.//dtd hotjava html//",
+    "-//sun microsystems corp.//dtd hotjava strict html//",
+    "-//w3c//dtd html 3 1995-03-24//",
+    "-//w3c//dtd html 3.2 draft//",
+    "-//w3c//dtd html 3.2 final//",
+    "-//w3c//dtd html 3.2//",
+    "-//w3c//dtd html 3.2s draft//",
+    "-//w3c//dtd html 4.0 frameset//",
+    "-//w3c//dtd html 4.0 transitional//",
+    "-//w3c//dtd html experimental 19960712//",
+    "-//w3c//dtd html experimental 970421//",
+    "-//w3c//dtd w3 html//",
+    "-//w3o//dtd w3 html 3.0//",
+    "-//webtechs//dtd mozilla html 2.0//",
+    "-//webtechs//dtd mozilla html//",
+];
+
+/// The beginnings of the public identifiers of HTML 4.01 Transitional and Frameset, of quirks
+/// mode without a system identifier, and of limited quirks mode with one
+const HTML4_TRANSITIONAL_PREFIXES: [&str; 2] = [
+    "-//w3c//dtd html 4.01 frameset//",
+    "-//w3c//dtd html 4.01 transitional//",
+];
+
+/// The beginnings of the public identifiers of the doctypes of limited quirks mode
+const LIMITED_QUIRKY_PUBLIC_ID_PREFIXES: [&str; 2] = [
+    "-//w3c//dtd xhtml 1.0 frameset//",
+    "-//w3c//dtd xhtml 1.0 transitional//",
+];
+
+/// Sets the mode the page is styled and laid out in.
+pub fn set_mode(mode: Mode) {
+    MODE.with(|m| m.set(mode));
+}
+
+pub fn mode() -> Mode {
+    MODE.with(|m| m.get())
+}
+
+/// The mode of a page with ``doctype``, what is between ``<!`` and ``>`` (e.g. ``DOCTYPE html``).
+/// None is a page without one, in quirks mode.
+/// ref. https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode
+pub fn mode_for_doctype(doctype: Option<&str>) -> Mode {
+    let doctype = match doctype {
+        Some(doctype) => doctype.to_lowercase(),
+        None => return Mode::Quirks,
+    };
+    let rest = match doctype.trim().strip_prefix("doctype") {
+        Some(rest) => rest.trim_start(),
+        None => return Mode::Quirks,
+    };
+    let name_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let (name, mut rest) = (&rest[..name_end], rest[name_end..].trim_start());
+
+    // ``PUBLIC "public id" "system id"`` or ``SYSTEM "system id"``, quoted by " or '
+    fn quoted(rest: &mut &str) -> Option<String> {
+        let quote = rest.chars().next().filter(|&c| c == '"' || c == '\'')?;
+        let end = rest[1..].find(quote)? + 1;
+        let string = rest[1..end].to_string();
+        *rest = rest[end + 1..].trim_start();
+        Some(string)
+    }
+    let (public_id, system_id) = if let Some(after) = rest.strip_prefix("public") {
+        rest = after.trim_start();
+        let public_id = quoted(&mut rest);
+        (public_id, quoted(&mut rest))
+    } else if let Some(after) = rest.strip_prefix("system") {
+        rest = after.trim_start();
+        (None, quoted(&mut rest))
+    } else {
+        (None, None)
+    };
+
+    let public_id = public_id.unwrap_or_default();
+    let starts_with_any = |prefixes: &[&str]| {
+        prefixes.iter().any(|prefix| public_id.starts_with(prefix))
+    };
+    if name != "html"
+        || public_id == "-//w3o//dtd w3 html strict 3.0//en//"
+        || public_id == "-/w3c/dtd html 4.0 transitional/en"
+        || public_id == "html"
+        || system_id.as_ref().is_some_and(|system_id| {
+            system_id == "http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd"
+        })
+        || starts_with_any(&QUIRKY_PUBLIC_ID_PREFIXES)
+        || (system_id.is_none() && starts_with_any(&HTML4_TRANSITIONAL_PREFIXES))
+    {
+        Mode::Quirks
+    } else if starts_with_any(&LIMITED_QUIRKY_PUBLIC_ID_PREFIXES)
+        || (system_id.is_some() && starts_with_any(&HTML4_TRANSITIONAL_PREFIXES))
+    {
+        Mode::LimitedQuirks
+    } else {
+        Mode::NoQuirks
+    }
+}
+
+#[test]
+fn test_mode_for_doctype() {
+    assert_eq!(mode_for_doctype(None), Mode::Quirks);
+    assert_eq!(mode_for_doctype(Some("DOCTYPE html")), Mode::NoQuirks);
+    assert_eq!(mode_for_doctype(Some("doctype HTML ")), Mode::NoQuirks);
+    assert_eq!(mode_for_doctype(Some("DOCTYPE")), Mode::Quirks);
+    assert_eq!(mode_for_doctype(Some("DOCTYPE svg")), Mode::Quirks);
+    assert_eq!(
+        mode_for_doctype(Some(
+            "DOCTYPE HTML PUBLIC \"-//W3C//DTD HTML 4.01//EN\" \
+             \"http://www.w3.org/TR/html4/strict.dtd\""
+        )),
+        Mode::NoQuirks
+    );
+    assert_eq!(
+        mode_for_doctype(Some("DOCTYPE HTML PUBLIC \"-//W3C//DTD HTML 3.2 Final//EN\"")),
+        Mode::Quirks
+    );
+    let transitional = "DOCTYPE HTML PUBLIC '-//W3C//DTD HTML 4.01 Transitional//EN'";
+    assert_eq!(mode_for_doctype(Some(transitional)), Mode::Quirks);
+    assert_eq!(
+        mode_for_doctype(Some(
+            format!("{} 'http://www.w3.org/TR/html4/loose.dtd'", transitional).as_str()
+        )),
+        Mode::LimitedQuirks
+    );
+    assert_eq!(
+        mode_for_doctype(Some(
+            "DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\" \
+             \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd\""
+        )),
+        Mode::LimitedQuirks
+    );
+    assert_eq!(
+        mode_for_doctype(Some("DOCTYPE html SYSTEM \"about:legacy-compat\"")),
+        Mode::NoQuirks
+    );
+}
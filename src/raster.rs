@@ -0,0 +1,486 @@
+//! Rasterizing the display list with cairo (see ``CairoBackend``): into a tile of the page
+//! (``paint_tile``), a whole page (``paint``, for screenshots, or ``paint_png`` in strips) or any
+//! cairo context (``paint_to``). Texts are laid out and drawn with Pango.
+
+extern crate libflate;
+
+use animated_image::{png_chunk, PNG_SIGNATURE};
+use css::{Color, TextDecoration};
+use font::{Font, FONT_DESC};
+use gpu::{self, Renderer};
+use layout::{ImageMetaData, Rect};
+use painter::{self, DisplayCommand, DisplayList, PaintBackend};
+use shape::{FillRule, Shape};
+use stats::{self, Phase};
+use svg::{self, PathSegment};
+use filter::{self, Filter};
+use tile;
+
+use std::{f64::consts::PI, mem};
+use std::io::{self, BufWriter, Write};
+
+use cairo::{self, Context};
+use cairo::prelude::SurfaceExt;
+use gdk::ContextExt;
+use gdk_pixbuf::{self, InterpType, PixbufExt};
+use pango::{self, LayoutExt};
+use pangocairo;
+
+use app_units::Au;
+
+/// The height (device px) of the strips ``paint_png`` paints one after another
+const STRIP_HEIGHT: i32 = 1024;
+
+/// Paints the part ``rect`` (device px) of the document of the display list at ``scale`` on a
+/// new tile, with the renderer selected (see ``gpu``). Fails if the tile can't be allocated.
+pub fn paint_tile(
+    items: &DisplayList,
+    scale: f64,
+    rect: tile::DeviceRect,
+    pango_layout: &mut pango::Layout,
+) -> Result<cairo::ImageSurface, cairo::Status> {
+    let (x, y, width, height) = rect;
+    // Only the items over the tile (in CSS px, rounded out)
+    let (left, top) = ((x as f64 / scale).floor(), (y as f64 / scale).floor());
+    let right = ((x + width) as f64 / scale).ceil();
+    let bottom = ((y + height) as f64 / scale).ceil();
+    let items = tile::cull(
+        items,
+        Rect {
+            x: Au::from_f64_px(left),
+            y: Au::from_f64_px(top),
+            width: Au::from_f64_px(right - left),
+            height: Au::from_f64_px(bottom - top),
+        },
+    );
+
+    if gpu::renderer() == Renderer::Gpu {
+        let tile = stats::time(Phase::Raster, || gpu::paint(&items, scale, rect, pango_layout));
+        if let Some(tile) = tile {
+            return Ok(tile);
+        }
+    }
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height)?;
+    let ctx = cairo::Context::new(&surface);
+    ctx.translate(-x as f64, -y as f64);
+    ctx.scale(scale, scale);
+    paint_to(&ctx, &items, pango_layout);
+    Ok(surface)
+}
+
+/// Paints the display list on a new surface of the size of the document (the first item)
+/// multiplied by ``scale``. Fails if it is larger than cairo surfaces can be.
+pub fn paint(
+    items: &DisplayList,
+    scale: f64,
+    pango_layout: &mut pango::Layout,
+) -> Result<cairo::ImageSurface, cairo::Status> {
+    let content_rect = if let DisplayCommand::SolidColor(_, content_rect) = items[0].command {
+        content_rect
+    } else {
+        unreachable!()
+    };
+
+    let surface = cairo::ImageSurface::create(
+        cairo::Format::ARgb32,
+        (content_rect.width.to_f64_px() * scale).ceil() as i32,
+        (content_rect.height.to_f64_px() * scale).ceil() as i32,
+    )?;
+    let ctx = cairo::Context::new(&surface);
+    ctx.scale(scale, scale);
+    paint_to(&ctx, items, pango_layout);
+    Ok(surface)
+}
+
+/// Writes the part (0, 0, ``width``, ``height``) (CSS px, 1 device px each) of the document of
+/// the display list, painted on white, as a PNG to ``out``. It is painted in strips of
+/// ``STRIP_HEIGHT`` px, each compressed before the next, so that it may be taller than cairo
+/// surfaces can be.
+pub fn paint_png<W: Write>(
+    items: &DisplayList,
+    (width, height): (i32, i32),
+    pango_layout: &mut pango::Layout,
+    out: &mut W,
+) -> Result<(), cairo::IoError> {
+    // 8 bits per channel, RGB
+    let mut ihdr = (width as u32).to_be_bytes().to_vec();
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+    out.write_all(&PNG_SIGNATURE).map_err(cairo::IoError::Io)?;
+    out.write_all(&png_chunk(b"IHDR", &ihdr)).map_err(cairo::IoError::Io)?;
+
+    let idat = BufWriter::with_capacity(1 << 16, Idat(&mut *out));
+    let mut zlib = libflate::zlib::Encoder::new(idat).map_err(cairo::IoError::Io)?;
+    let mut y = 0;
+    while y < height {
+        let strip_height = STRIP_HEIGHT.min(height - y);
+        let mut strip = cairo::ImageSurface::create(cairo::Format::Rgb24, width, strip_height)
+            .map_err(cairo::IoError::Cairo)?;
+        {
+            let ctx = cairo::Context::new(&strip);
+            ctx.set_source_rgb(1.0, 1.0, 1.0);
+            ctx.paint();
+            ctx.translate(0.0, -y as f64);
+            let rect = Rect {
+                x: Au(0),
+                y: Au::from_px(y),
+                width: Au::from_px(width),
+                height: Au::from_px(strip_height),
+            };
+            paint_to(&ctx, &tile::cull(items, rect), pango_layout);
+        }
+        strip.flush();
+        let stride = strip.get_stride() as usize;
+        let data = strip.get_data().map_err(|_| cairo::IoError::Cairo(cairo::Status::NoMemory))?;
+        for row in data.chunks(stride).take(strip_height as usize) {
+            // Each row starts with its filter type, none.
+            let mut scanline = vec![0];
+            for pixel in row[..width as usize * 4].chunks(4) {
+                // xRGB, in the native byte order
+                let xrgb = u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                scanline.extend_from_slice(&[(xrgb >> 16) as u8, (xrgb >> 8) as u8, xrgb as u8]);
+            }
+            zlib.write_all(&scanline).map_err(cairo::IoError::Io)?;
+        }
+        y += strip_height;
+    }
+    let mut idat = zlib.finish().into_result().map_err(cairo::IoError::Io)?;
+    idat.flush().map_err(cairo::IoError::Io)?;
+    out.write_all(&png_chunk(b"IEND", &[])).map_err(cairo::IoError::Io)
+}
+
+/// Writes what is written to it in IDAT chunks of a PNG
+struct Idat<W: Write>(W);
+
+impl<W: Write> Write for Idat<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if !data.is_empty() {
+            self.0.write_all(&png_chunk(b"IDAT", data))?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Paints the display list on ``ctx``, in its user space (1 unit per CSS px).
+pub fn paint_to(ctx: &Context, items: &DisplayList, pango_layout: &mut pango::Layout) {
+    stats::time(Phase::Raster, || {
+        painter::paint(items, &mut CairoBackend::new(ctx.clone(), pango_layout))
+    });
+}
+
+/// Paints with cairo, and texts with Pango.
+pub struct CairoBackend<'a> {
+    /// Of the target, or of the layer pushed last
+    pub ctx: Context,
+    pub pango_layout: &'a mut pango::Layout,
+    /// The contexts under the layers pushed, and each layer. None if it could not be created
+    /// (and the context under it is painted on directly).
+    layers: Vec<(Context, Option<Layer>)>,
+}
+
+/// A layer, and where its top left is in the device space of the target
+type Layer = (cairo::ImageSurface, f64, f64);
+
+impl<'a> CairoBackend<'a> {
+    pub fn new(ctx: Context, pango_layout: &'a mut pango::Layout) -> CairoBackend<'a> {
+        CairoBackend {
+            ctx: ctx,
+            pango_layout: pango_layout,
+            layers: vec![],
+        }
+    }
+
+    fn set_source_color(&self, color: &Color) {
+        self.ctx.set_source_rgba(
+            color.r as f64 / 255.0,
+            color.g as f64 / 255.0,
+            color.b as f64 / 255.0,
+            color.a as f64 / 255.0,
+        );
+    }
+}
+
+impl<'a> PaintBackend for CairoBackend<'a> {
+    fn fill_rect(&mut self, color: &Color, rect: Rect) {
+        self.ctx.rectangle(
+            rect.x.to_f64_px(),
+            rect.y.to_f64_px(),
+            rect.width.to_f64_px(),
+            rect.height.to_f64_px(),
+        );
+        self.set_source_color(color);
+        self.ctx.fill();
+    }
+
+    fn draw_image(&mut self, image: &gdk_pixbuf::Pixbuf, metadata: &ImageMetaData, rect: Rect) {
+        let scaled = image.scale_simple(
+            metadata.width.to_f64_px() as i32,
+            metadata.height.to_f64_px() as i32,
+            InterpType::Hyper,
+        );
+        // None if scaled to nothing
+        if let Some(scaled) = scaled {
+            self.ctx
+                .set_source_pixbuf(&scaled, rect.x.to_f64_px(), rect.y.to_f64_px());
+            self.ctx.paint();
+        }
+    }
+
+    fn draw_image_tiles(
+        &mut self,
+        image: &gdk_pixbuf::Pixbuf,
+        metadata: &ImageMetaData,
+        tiles: &[Rect],
+        clip: Rect,
+    ) {
+        // Scaled once for all the tiles
+        let scaled = match image.scale_simple(
+            metadata.width.to_f64_px().round() as i32,
+            metadata.height.to_f64_px().round() as i32,
+            InterpType::Hyper,
+        ) {
+            Some(scaled) => scaled,
+            None => return,
+        };
+        self.ctx.save();
+        self.ctx.rectangle(
+            clip.x.to_f64_px(),
+            clip.y.to_f64_px(),
+            clip.width.to_f64_px(),
+            clip.height.to_f64_px(),
+        );
+        self.ctx.clip();
+        for tile in tiles {
+            self.ctx
+                .set_source_pixbuf(&scaled, tile.x.to_f64_px(), tile.y.to_f64_px());
+            self.ctx.paint();
+        }
+        self.ctx.restore();
+    }
+
+    fn draw_text(
+        &mut self,
+        text: &str,
+        rect: Rect,
+        color: &Color,
+        decorations: &[TextDecoration],
+        font: &Font,
+    ) {
+        self.set_source_color(color);
+        self.ctx.move_to(rect.x.to_f64_px(), rect.y.to_f64_px());
+
+        let pango_layout = &mut *self.pango_layout;
+        // Measures a space with the font.
+        let tabs = font.select().to_pango_tab_array(text);
+        FONT_DESC.with(|font_desc| {
+            let mut font_desc = font_desc.borrow_mut();
+            font.set_to_pango_font_desc(&mut font_desc);
+
+            let attr_list = font.to_pango_attr_list(text);
+            for decoration in decorations {
+                match decoration {
+                    &TextDecoration::Underline => {
+                        attr_list.insert(
+                            pango::Attribute::new_underline(pango::Underline::Single).unwrap(),
+                        );
+                    }
+                    // Not supported by this Pango.
+                    &TextDecoration::Overline => {}
+                    &TextDecoration::LineThrough => {
+                        attr_list.insert(pango::Attribute::new_strikethrough(true).unwrap());
+                    }
+                    &TextDecoration::None => {}
+                }
+            }
+
+            pango_layout.set_tabs(tabs.as_ref());
+            pango_layout.set_text(text);
+            pango_layout.set_attributes(Some(&attr_list));
+            pango_layout.set_font_description(Some(&*font_desc));
+        });
+
+        pangocairo::functions::show_layout(&self.ctx, pango_layout);
+    }
+
+    fn draw_path(&mut self, path: &svg::Path) {
+        self.ctx.new_path();
+        for segment in &path.segments {
+            match *segment {
+                PathSegment::MoveTo(x, y) => self.ctx.move_to(x, y),
+                PathSegment::LineTo(x, y) => self.ctx.line_to(x, y),
+                PathSegment::CurveTo(x1, y1, x2, y2, x, y) => {
+                    self.ctx.curve_to(x1, y1, x2, y2, x, y)
+                }
+                PathSegment::Close => self.ctx.close_path(),
+            }
+        }
+        if let Some((ref color, fill_rule)) = path.fill {
+            self.set_source_color(color);
+            self.ctx.set_fill_rule(match fill_rule {
+                FillRule::NonZero => cairo::FillRule::Winding,
+                FillRule::EvenOdd => cairo::FillRule::EvenOdd,
+            });
+            self.ctx.fill_preserve();
+        }
+        if let Some((ref color, width)) = path.stroke {
+            self.set_source_color(color);
+            self.ctx.set_line_width(width);
+            self.ctx.stroke_preserve();
+        }
+        self.ctx.new_path();
+    }
+
+    fn push_clip(&mut self, rect: Rect) {
+        self.ctx.save();
+        self.ctx.rectangle(
+            rect.x.to_f64_px(),
+            rect.y.to_f64_px(),
+            rect.width.to_f64_px(),
+            rect.height.to_f64_px(),
+        );
+        self.ctx.clip();
+    }
+
+    fn push_clip_path(&mut self, shape: &Shape) {
+        self.ctx.save();
+        self.ctx.new_path();
+        match *shape {
+            Shape::Ellipse {
+                center: (cx, cy),
+                radius: (rx, ry),
+            } => {
+                if rx > 0.0 && ry > 0.0 {
+                    // The path is not in the saved state.
+                    self.ctx.save();
+                    self.ctx.translate(cx, cy);
+                    self.ctx.scale(rx, ry);
+                    self.ctx.arc(0.0, 0.0, 1.0, 0.0, 2.0 * PI);
+                    self.ctx.restore();
+                }
+            }
+            Shape::Inset {
+                rect: (x, y, width, height),
+                radius: r,
+            } => {
+                self.ctx.new_sub_path();
+                self.ctx.arc(x + width - r, y + r, r, -PI / 2.0, 0.0);
+                self.ctx.arc(x + width - r, y + height - r, r, 0.0, PI / 2.0);
+                self.ctx.arc(x + r, y + height - r, r, PI / 2.0, PI);
+                self.ctx.arc(x + r, y + r, r, PI, 1.5 * PI);
+                self.ctx.close_path();
+            }
+            Shape::Polygon(fill_rule, ref points) => {
+                for &(x, y) in points {
+                    self.ctx.line_to(x, y);
+                }
+                self.ctx.close_path();
+                self.ctx.set_fill_rule(match fill_rule {
+                    FillRule::NonZero => cairo::FillRule::Winding,
+                    FillRule::EvenOdd => cairo::FillRule::EvenOdd,
+                });
+            }
+        }
+        self.ctx.clip();
+    }
+
+    fn pop_clip(&mut self) {
+        self.ctx.restore();
+    }
+
+    fn push_layer(&mut self, rect: Rect) {
+        // In the device space, so that the layer is as sharp as the target
+        let (x0, y0) = self.ctx
+            .user_to_device(rect.x.to_f64_px(), rect.y.to_f64_px());
+        let (x1, y1) = self.ctx.user_to_device(
+            (rect.x + rect.width).to_f64_px(),
+            (rect.y + rect.height).to_f64_px(),
+        );
+        let (left, top) = (x0.min(x1).floor(), y0.min(y1).floor());
+        let width = (x0.max(x1).ceil() - left).max(1.0) as i32;
+        let height = (y0.max(y1).ceil() - top).max(1.0) as i32;
+        let surface = match cairo::ImageSurface::create(cairo::Format::ARgb32, width, height) {
+            Ok(surface) => surface,
+            Err(_) => {
+                self.layers.push((self.ctx.clone(), None));
+                return;
+            }
+        };
+        let layer_ctx = Context::new(&surface);
+        let mut matrix = self.ctx.get_matrix();
+        matrix.x0 -= left;
+        matrix.y0 -= top;
+        layer_ctx.set_matrix(matrix);
+        let parent = mem::replace(&mut self.ctx, layer_ctx);
+        self.layers.push((parent, Some((surface, left, top))));
+    }
+
+    fn pop_layer(&mut self, filters: &[Filter]) {
+        let (parent, layer) = match self.layers.pop() {
+            Some(layer) => layer,
+            None => return,
+        };
+        // The context of the layer is dropped, so that its pixels can be borrowed.
+        self.ctx = parent;
+        let (mut surface, left, top) = match layer {
+            Some(layer) => layer,
+            None => return,
+        };
+        let scale = self.ctx.get_matrix().xx;
+        let (width, height, stride) = (
+            surface.get_width() as usize,
+            surface.get_height() as usize,
+            surface.get_stride() as usize,
+        );
+        if let Ok(mut data) = surface.get_data() {
+            filter::apply(filters, &mut data, width, height, stride, scale);
+        }
+        self.ctx.save();
+        self.ctx.identity_matrix();
+        self.ctx.set_source_surface(&surface, left, top);
+        self.ctx.paint();
+        self.ctx.restore();
+    }
+}
+
+#[test]
+fn test_paint_png() {
+    use css::RED;
+    use painter::DisplayCommandInfo;
+
+    use gdk_pixbuf::{PixbufLoader, PixbufLoaderExt};
+
+    // Taller than a strip, and red over the second one
+    let rect = |y: i32, height: i32| Rect {
+        x: Au(0),
+        y: Au::from_px(y),
+        width: Au::from_px(4),
+        height: Au::from_px(height),
+    };
+    let items: DisplayList = vec![DisplayCommand::SolidColor(RED, rect(STRIP_HEIGHT + 10, 5))]
+        .into_iter()
+        .map(DisplayCommandInfo::new)
+        .collect();
+    let surface = cairo::ImageSurface::create(cairo::Format::Rgb24, 0, 0).unwrap();
+    let pango_ctx = pangocairo::functions::create_context(&Context::new(&surface)).unwrap();
+    let mut png = vec![];
+    let size = (4, STRIP_HEIGHT + 20);
+    paint_png(&items, size, &mut pango::Layout::new(&pango_ctx), &mut png).unwrap();
+
+    let loader = PixbufLoader::new_with_type("png").unwrap();
+    loader.write(&png).unwrap();
+    loader.close().unwrap();
+    let pixbuf = loader.get_pixbuf().unwrap();
+    assert_eq!((pixbuf.get_width(), pixbuf.get_height()), size);
+    let pixel = |y: i32| {
+        let i = (y * pixbuf.get_rowstride()) as usize;
+        let pixels = unsafe { pixbuf.get_pixels() };
+        (pixels[i], pixels[i + 1], pixels[i + 2])
+    };
+    assert_eq!(pixel(0), (255, 255, 255));
+    assert_eq!(pixel(STRIP_HEIGHT + 12), (255, 0, 0));
+    assert_eq!(pixel(STRIP_HEIGHT + 19), (255, 255, 255));
+}
@@ -0,0 +1,286 @@
+//! Reftests: a test page and a reference page (written differently, e.g. without the feature
+//! tested) are rendered headlessly and their pixels compared.
+//!
+//! A manifest lists the tests, one a line, like the ``reftest.list`` of Gecko:
+//!
+//! ```text
+//! # A comment
+//! == test.html reference.html
+//! != test.html different.html
+//! fuzz(2,100) == test.html reference.html
+//! ```
+//!
+//! ``fuzz(d,n)`` allows up to ``n`` pixels to differ by up to ``d`` in each channel (e.g. for
+//! antialiasing).
+
+use interface;
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use cairo::ImageSurface;
+
+/// The viewport the pages are rendered in (CSS px).
+pub const VIEWPORT_WIDTH: f64 = 800.0;
+pub const VIEWPORT_HEIGHT: f64 = 600.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    /// ``==``
+    Equal,
+    /// ``!=``
+    NotEqual,
+}
+
+/// How different the pixels may be for the pages to be equal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fuzz {
+    /// The largest difference in a channel
+    pub max_difference: u8,
+    pub max_pixels: usize,
+}
+
+pub const NO_FUZZ: Fuzz = Fuzz {
+    max_difference: 0,
+    max_pixels: 0,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reftest {
+    pub comparison: Comparison,
+    pub fuzz: Fuzz,
+    /// The paths relative to the manifest
+    pub test: String,
+    pub reference: String,
+}
+
+/// How two renderings differ.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Difference {
+    /// The largest difference in a channel of a pixel
+    pub max_difference: u8,
+    /// The number of the pixels that differ
+    pub pixels: usize,
+}
+
+impl Difference {
+    pub fn within(&self, fuzz: Fuzz) -> bool {
+        self.pixels == 0
+            || (self.max_difference <= fuzz.max_difference && self.pixels <= fuzz.max_pixels)
+    }
+}
+
+/// Parses a manifest. Returns the line number (from 1) and the line if one is invalid.
+pub fn parse_manifest(src: &str) -> Result<Vec<Reftest>, (usize, String)> {
+    let mut tests = vec![];
+    for (i, line) in src.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        match parse_manifest_line(line) {
+            Some(test) => tests.push(test),
+            None => return Err((i + 1, line.to_string())),
+        }
+    }
+    Ok(tests)
+}
+
+fn parse_manifest_line(line: &str) -> Option<Reftest> {
+    let mut words = line.split_whitespace().peekable();
+
+    let fuzz = match words.peek() {
+        Some(word) if word.starts_with("fuzz(") && word.ends_with(')') => {
+            let args: Vec<&str> = word["fuzz(".len()..word.len() - 1].split(',').collect();
+            if args.len() != 2 {
+                return None;
+            }
+            Some(Fuzz {
+                max_difference: args[0].trim().parse().ok()?,
+                max_pixels: args[1].trim().parse().ok()?,
+            })
+        }
+        _ => None,
+    };
+    if fuzz.is_some() {
+        words.next();
+    }
+
+    let comparison = match words.next() {
+        Some("==") => Comparison::Equal,
+        Some("!=") => Comparison::NotEqual,
+        _ => return None,
+    };
+    let test = Reftest {
+        comparison: comparison,
+        fuzz: fuzz.unwrap_or(NO_FUZZ),
+        test: words.next()?.to_string(),
+        reference: words.next()?.to_string(),
+    };
+    match words.next() {
+        Some(_) => None,
+        None => Some(test),
+    }
+}
+
+/// Compares the pixels. The pixels outside of either surface differ by 255.
+pub fn compare(a: &mut ImageSurface, b: &mut ImageSurface) -> Difference {
+    let (width, height) = (a.get_width().max(b.get_width()), a.get_height().max(b.get_height()));
+    let (a_width, a_height, a_stride) = (a.get_width(), a.get_height(), a.get_stride());
+    let (b_width, b_height, b_stride) = (b.get_width(), b.get_height(), b.get_stride());
+    let a = a.get_data().expect("the surface is used elsewhere");
+    let b = b.get_data().expect("the surface is used elsewhere");
+
+    let mut difference = Difference {
+        max_difference: 0,
+        pixels: 0,
+    };
+    for y in 0..height {
+        for x in 0..width {
+            let pixel_difference = if x < a_width && y < a_height && x < b_width && y < b_height {
+                let a_pixel = (y * a_stride + x * 4) as usize;
+                let b_pixel = (y * b_stride + x * 4) as usize;
+                (0..4)
+                    .map(|i| a[a_pixel + i].abs_diff(b[b_pixel + i]))
+                    .max()
+                    .unwrap()
+            } else {
+                255
+            };
+            if pixel_difference > 0 {
+                difference.pixels += 1;
+                difference.max_difference = difference.max_difference.max(pixel_difference);
+            }
+        }
+    }
+    difference
+}
+
+/// Runs the tests in the manifest at ``manifest_path``. Returns the failures.
+pub fn run(manifest_path: &str) -> Vec<String> {
+    let mut src = String::new();
+    if let Err(e) = fs::File::open(manifest_path).and_then(|mut f| f.read_to_string(&mut src)) {
+        return vec![format!("{}: {}", manifest_path, e)];
+    }
+    let tests = match parse_manifest(src.as_str()) {
+        Ok(tests) => tests,
+        Err((line, s)) => return vec![format!("{}:{}: invalid line: {}", manifest_path, line, s)],
+    };
+
+    let dir = Path::new(manifest_path)
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .canonicalize()
+        .expect("cannot find the directory of the manifest");
+    let render = |path: &str| {
+        let url = format!("file://{}", dir.join(path).to_str().unwrap());
+        interface::render_headless(url.as_str(), VIEWPORT_WIDTH, VIEWPORT_HEIGHT)
+            .map_err(|status| format!("{}: cannot render: {:?}", path, status))
+    };
+
+    let mut failures = vec![];
+    for test in tests {
+        let (mut test_surface, mut reference) =
+            match (render(test.test.as_str()), render(test.reference.as_str())) {
+                (Ok(test_surface), Ok(reference)) => (test_surface, reference),
+                (Err(e), _) | (_, Err(e)) => {
+                    failures.push(e);
+                    continue;
+                }
+            };
+        let difference = compare(&mut test_surface, &mut reference);
+        let passed = match test.comparison {
+            Comparison::Equal => difference.within(test.fuzz),
+            Comparison::NotEqual => !difference.within(test.fuzz),
+        };
+        if !passed {
+            failures.push(format!(
+                "{} {} {}: {} pixels differ (by up to {})",
+                test.test,
+                if test.comparison == Comparison::Equal { "==" } else { "!=" },
+                test.reference,
+                difference.pixels,
+                difference.max_difference
+            ));
+        }
+    }
+    failures
+}
+
+#[test]
+fn test_parse_manifest() {
+    let tests = parse_manifest(
+        "# Box model
+== a.html a-ref.html
+fuzz(2,10) != b.html b-ref.html # comment",
+    );
+    assert_eq!(parse_manifest("== a.html"), Err((1, "== a.html".to_string())));
+    assert_eq!(
+        tests,
+        Ok(vec![
+            Reftest {
+                comparison: Comparison::Equal,
+                fuzz: NO_FUZZ,
+                test: "a.html".to_string(),
+                reference: "a-ref.html".to_string(),
+            },
+            Reftest {
+                comparison: Comparison::NotEqual,
+                fuzz: Fuzz {
+                    max_difference: 2,
+                    max_pixels: 10,
+                },
+                test: "b.html".to_string(),
+                reference: "b-ref.html".to_string(),
+            },
+        ])
+    );
+}
+
+#[test]
+fn test_compare() {
+    use cairo::{Context, Format};
+
+    let surface = |width, height, fill: &[(f64, f64, f64, f64)]| {
+        let surface = ImageSurface::create(Format::ARgb32, width, height).unwrap();
+        {
+            let ctx = Context::new(&surface);
+            ctx.set_source_rgb(1.0, 1.0, 1.0);
+            ctx.paint();
+            ctx.set_source_rgb(0.0, 0.0, 0.0);
+            for &(x, y, w, h) in fill {
+                ctx.rectangle(x, y, w, h);
+                ctx.fill();
+            }
+        }
+        surface
+    };
+
+    let difference = compare(
+        &mut surface(10, 10, &[(0.0, 0.0, 2.0, 2.0)]),
+        &mut surface(10, 10, &[(0.0, 0.0, 2.0, 2.0)]),
+    );
+    assert_eq!(difference.pixels, 0);
+
+    let difference = compare(
+        &mut surface(10, 10, &[(0.0, 0.0, 2.0, 2.0)]),
+        &mut surface(10, 10, &[(0.0, 0.0, 2.0, 1.0)]),
+    );
+    assert_eq!(
+        difference,
+        Difference {
+            max_difference: 255,
+            pixels: 2,
+        }
+    );
+    assert!(!difference.within(NO_FUZZ));
+    assert!(difference.within(Fuzz {
+        max_difference: 255,
+        max_pixels: 2,
+    }));
+
+    // Different sizes
+    let difference = compare(&mut surface(10, 10, &[]), &mut surface(10, 12, &[]));
+    assert_eq!(difference.pixels, 20);
+}
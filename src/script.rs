@@ -0,0 +1,1287 @@
+//! The scripts of a page: its inline ``<script>``s are run by ``js_interpreter`` after it is
+//! parsed, in document order, with ``document``, ``window`` and elements bound to the DOM.
+//!
+//! An element is the path of child indices from the root to it, so an element a script keeps
+//! refers to another node (or none) after the DOM is restructured above it. Scripts with a
+//! ``src`` are not loaded.
+//!
+//! Scripts listen to the events of ``window``, ``document`` and elements with
+//! ``addEventListener`` (or ``onclick`` and the like), dispatched by ``Runtime::dispatch_event``.
+//! The functions given to ``setTimeout`` and ``setInterval`` are run by ``Runtime::run_timers``.
+//!
+//! Setting ``innerHTML`` parses the HTML in the context of the element (``html::parse_fragment``)
+//! and replaces its children, whose listeners and properties are forgotten. Its scripts do not
+//! run.
+
+use canvas2d;
+#[cfg(feature = "raster")]
+use canvas2d::{DrawingState, TextAlign, TextBaseline};
+use css;
+use dom::{ElementData, Node, NodeType};
+use event::{Event, Phase};
+use html;
+use inline;
+use js_interpreter::{self, array, error, host_method, host_object, object, HostObject, Interpreter,
+                     Value};
+use layout;
+use network::Url;
+use srcset;
+use task::{TaskId, TaskQueue};
+
+#[cfg(feature = "raster")]
+use image::{Image, PixbufExt};
+
+use std::collections::HashMap;
+use std::mem;
+use std::time::{Duration, Instant};
+
+/// The types of ``<script>``s that are run
+const SCRIPT_TYPES: [&str; 4] = ["", "text/javascript", "application/javascript", "module"];
+
+const DOCUMENT_METHODS: [&str; 7] = [
+    "addEventListener",
+    "removeEventListener",
+    "getElementById",
+    "getElementsByTagName",
+    "getElementsByClassName",
+    "querySelector",
+    "querySelectorAll",
+];
+const ELEMENT_METHODS: [&str; 11] = [
+    "addEventListener",
+    "removeEventListener",
+    "click",
+    "getAttribute",
+    "setAttribute",
+    "removeAttribute",
+    "hasAttribute",
+    "getElementsByTagName",
+    "getElementsByClassName",
+    "querySelector",
+    "querySelectorAll",
+];
+const STYLE_METHODS: [&str; 3] = ["getPropertyValue", "setProperty", "removeProperty"];
+const WINDOW_METHODS: [&str; 2] = ["addEventListener", "removeEventListener"];
+/// The functions of ``window`` that are global variables
+const TIMER_FUNCTIONS: [&str; 4] = ["setTimeout", "setInterval", "clearTimeout", "clearInterval"];
+/// The shortest interval of ``setInterval``, so that a page can not keep the browser busy
+const MIN_INTERVAL: u64 = 4; // ms
+const EVENT_METHODS: [&str; 3] = ["preventDefault", "stopPropagation", "stopImmediatePropagation"];
+/// The methods of the 2D context of a ``<canvas>`` (see ``canvas2d::Context2d``)
+#[cfg(feature = "raster")]
+const CONTEXT_METHODS: [&str; 22] = [
+    "fillRect",
+    "strokeRect",
+    "clearRect",
+    "beginPath",
+    "closePath",
+    "moveTo",
+    "lineTo",
+    "bezierCurveTo",
+    "quadraticCurveTo",
+    "arc",
+    "rect",
+    "fill",
+    "stroke",
+    "fillText",
+    "strokeText",
+    "measureText",
+    "drawImage",
+    "save",
+    "restore",
+    "translate",
+    "scale",
+    "rotate",
+];
+
+/// The method ``name`` of ``object`` if it is one of ``methods``
+fn method(object: &HostObject, methods: &[&'static str], name: &str) -> Option<Value> {
+    methods
+        .iter()
+        .find(|&&method| method == name)
+        .map(|&method| host_method(object, method))
+}
+
+/// A function added with ``addEventListener``
+struct Listener {
+    /// The class and the key of the ``HostObject`` it listens to
+    target: (&'static str, Vec<usize>),
+    kind: String,
+    callback: Value,
+    capture: bool,
+}
+
+/// What ``setTimeout`` and ``setInterval`` are given
+struct Timer {
+    /// A function, or the source of a script
+    callback: Value,
+    args: Vec<Value>,
+    /// The interval of ``setInterval``
+    interval: Option<Duration>,
+}
+
+/// An event being dispatched, which its ``event`` object reads and changes
+struct Dispatching {
+    event: Event,
+    phase: Phase,
+    current_target: Value,
+    stopped: bool,
+    stopped_immediately: bool,
+    canceled: bool,
+}
+
+/// The ``Host`` of the scripts of a page
+pub struct Dom {
+    document: Node,
+    /// What the URLs in the HTML given to ``innerHTML`` are relative to
+    base_url: Url,
+    /// Set when a script changes the DOM, which then has to be styled and laid out again
+    mutated: bool,
+    /// The properties scripts add to elements, e.g. ``el.data = 1``
+    properties: HashMap<Vec<usize>, Vec<(String, Value)>>,
+    /// In the order they are added
+    listeners: Vec<Listener>,
+    /// The events being dispatched, the innermost (dispatched by a listener) last. The key of
+    /// an ``event`` object is its index.
+    events: Vec<Dispatching>,
+    timers: TaskQueue<Timer>,
+    /// The timer whose callback is running, and whether it cleared itself
+    running_timer: Option<(TaskId, bool)>,
+}
+
+/// The scripts of a page, whose global variables last as long as it.
+pub struct Runtime {
+    js: Interpreter<Dom>,
+}
+
+impl Runtime {
+    /// The runtime of the page at ``base_url``.
+    pub fn new(base_url: Url) -> Runtime {
+        let mut js = Interpreter::new(Dom {
+            document: Node::text(String::new()),
+            base_url: base_url,
+            mutated: false,
+            properties: HashMap::new(),
+            listeners: vec![],
+            events: vec![],
+            timers: TaskQueue::new(),
+            running_timer: None,
+        });
+        js.define_global("document", host_object("document", vec![]));
+        js.define_global("window", host_object("window", vec![]));
+        let window = HostObject {
+            class: "window",
+            key: vec![],
+        };
+        for &name in TIMER_FUNCTIONS.iter() {
+            js.define_global(name, host_method(&window, name));
+        }
+        Runtime { js: js }
+    }
+
+    /// Runs the ``<script>``s of ``document`` in document order. An error (printed) stops the
+    /// script it is in, and the next ones run.
+    pub fn run_scripts(&mut self, document: &mut Node) {
+        let mut sources = vec![];
+        find_scripts(document, &mut sources);
+        for source in sources {
+            let _ = self.eval(document, source.as_str());
+        }
+    }
+
+    /// Runs ``source`` on ``document``. Returns the value of its last expression statement as a
+    /// string, or the error it throws.
+    pub fn eval(&mut self, document: &mut Node, source: &str) -> Result<String, String> {
+        mem::swap(&mut self.js.host.document, document);
+        let result = self.js.run(source).map(|value| value.to_string());
+        mem::swap(&mut self.js.host.document, document);
+        self.print_console();
+        if let Err(ref e) = result {
+            eprintln!("Uncaught {}", e);
+        }
+        result
+    }
+
+    /// Dispatches ``event`` to the listeners on ``document``. Clicking a checkbox toggles it
+    /// before they are called, and back if the click is canceled. Returns false if a listener
+    /// canceled the event: the embedder then does not do its default action (e.g. following a
+    /// link).
+    pub fn dispatch_event(&mut self, document: &mut Node, event: Event) -> bool {
+        mem::swap(&mut self.js.host.document, document);
+        let not_canceled = dispatch_with_activation(&mut self.js, event);
+        mem::swap(&mut self.js.host.document, document);
+        self.print_console();
+        not_canceled
+    }
+
+    /// Runs the callbacks of the timers due at ``now`` on ``document``, each once at most: the
+    /// timers they set and the next runs of intervals are left to the next call. Returns true if
+    /// one ran.
+    pub fn run_timers(&mut self, document: &mut Node, now: Instant) -> bool {
+        let count = self.js.host.timers.count_due(now);
+        if count == 0 {
+            return false;
+        }
+
+        mem::swap(&mut self.js.host.document, document);
+        for _ in 0..count {
+            let (id, due, timer) = match self.js.host.timers.pop_due(now) {
+                Some(timer) => timer,
+                None => break,
+            };
+            self.js.host.running_timer = Some((id, false));
+            let result = match timer.callback {
+                Value::Str(ref source) => self.js.run(source).map(|_| ()),
+                ref callback => {
+                    let window = host_object("window", vec![]);
+                    let args = timer.args.clone();
+                    self.js.call_function(callback, window, args).map(|_| ())
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("Uncaught {}", e);
+            }
+            let cleared = self.js.host.running_timer.take().is_some_and(|t| t.1);
+            if let (Some(interval), false) = (timer.interval, cleared) {
+                self.js.host.timers.schedule_again(id, due + interval, timer);
+            }
+        }
+        mem::swap(&mut self.js.host.document, document);
+        self.print_console();
+        true
+    }
+
+    /// When the next timer is due.
+    pub fn next_timer(&self) -> Option<Instant> {
+        self.js.host.timers.next_due()
+    }
+
+    /// Prints what was logged with ``console`` to stderr, like the errors uncaught.
+    fn print_console(&mut self) {
+        for line in self.js.console.drain(..) {
+            eprintln!("console: {}", line);
+        }
+    }
+
+    /// Whether the scripts changed the DOM since the last call: the layout boxes cached for it
+    /// are then out of date.
+    pub fn take_mutated(&mut self) -> bool {
+        mem::replace(&mut self.js.host.mutated, false)
+    }
+}
+
+fn find_scripts(node: &Node, sources: &mut Vec<String>) {
+    if let NodeType::Element(ref e) = node.data {
+        let script_type = e.attrs.get("type").map_or(String::new(), |t| t.to_lowercase());
+        if e.tag_name == "script" {
+            if !e.attrs.contains_key("src") && SCRIPT_TYPES.contains(&script_type.as_str()) {
+                sources.push(node.text_content());
+            }
+            return;
+        }
+    }
+    for child in &node.children {
+        find_scripts(child, sources);
+    }
+}
+
+/// ``dispatch``, with a click on a checkbox toggling it (back if the click is canceled).
+fn dispatch_with_activation(js: &mut Interpreter<Dom>, event: Event) -> bool {
+    let checkbox = event.kind == "click" && element_at(&js.host.document, &event.target)
+        .is_some_and(|e| e.tag_name == "input" && e.attrs.get("type").is_some_and(|t| {
+            t.eq_ignore_ascii_case("checkbox")
+        }));
+    let target = event.target.clone();
+    let toggle = |dom: &mut Dom| {
+        let checked = element_at(&dom.document, &target).is_some_and(|e| {
+            e.attrs.contains_key("checked")
+        });
+        dom.set_attribute(&target, "checked", if checked { None } else { Some(String::new()) });
+    };
+
+    if checkbox {
+        toggle(&mut js.host);
+    }
+    let not_canceled = dispatch(js, event);
+    if checkbox && !not_canceled {
+        toggle(&mut js.host);
+    }
+    not_canceled
+}
+
+/// Calls the listeners of ``window``, ``document`` and the ancestors of the target of
+/// ``event`` that capture it, down to the target, and then those that do not, back up. The
+/// ``on<type>`` property of each is called after its listeners that do not capture, and
+/// canceles the event by returning false. Returns false if the event is canceled. Errors thrown
+/// by listeners are printed.
+fn dispatch(js: &mut Interpreter<Dom>, event: Event) -> bool {
+    let mut targets = vec![("window", vec![]), ("document", vec![])];
+    targets.extend(event.propagation_path().into_iter().map(|path| ("element", path)));
+    let at_target = targets.len() - 1;
+    let kind = event.kind.clone();
+
+    let index = js.host.events.len();
+    js.host.events.push(Dispatching {
+        event: event,
+        phase: Phase::None,
+        current_target: Value::Null,
+        stopped: false,
+        stopped_immediately: false,
+        canceled: false,
+    });
+    let event_object = host_object("event", vec![index]);
+
+    let capturing = (0..at_target + 1).map(|i| (i, true));
+    let bubbling = (0..at_target + 1).rev().map(|i| (i, false));
+    for (i, capture) in capturing.chain(bubbling) {
+        let phase = match (i == at_target, capture) {
+            (true, _) => Phase::AtTarget,
+            (false, true) => Phase::Capturing,
+            (false, false) => Phase::Bubbling,
+        };
+        let (class, ref key) = targets[i];
+        let current_target = host_object(class, key.clone());
+        {
+            let dispatching = &mut js.host.events[index];
+            if dispatching.stopped {
+                break;
+            }
+            dispatching.phase = phase;
+            dispatching.current_target = current_target.clone();
+        }
+
+        let mut callbacks: Vec<Value> = js.host
+            .listeners
+            .iter()
+            .filter(|l| l.target.0 == class && l.target.1 == *key)
+            .filter(|l| l.kind == kind && l.capture == capture)
+            .map(|l| l.callback.clone())
+            .collect();
+        let handler = if capture {
+            Value::Undefined
+        } else {
+            js.get_property(&current_target, &format!("on{}", kind))
+                .unwrap_or(Value::Undefined)
+        };
+        if handler.is_function() {
+            callbacks.push(handler.clone());
+        }
+
+        for callback in callbacks {
+            if js.host.events[index].stopped_immediately {
+                break;
+            }
+            let args = vec![event_object.clone()];
+            match js.call_function(&callback, current_target.clone(), args) {
+                Ok(Value::Bool(false)) if callback == handler => {
+                    js.host.events[index].canceled = true
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Uncaught {}", e),
+            }
+        }
+    }
+
+    !js.host.events.pop().unwrap().canceled
+}
+
+fn node_at<'a>(root: &'a Node, path: &[usize]) -> Option<&'a Node> {
+    path.iter().try_fold(root, |node, &i| node.children.get(i))
+}
+
+fn node_at_mut<'a>(root: &'a mut Node, path: &[usize]) -> Option<&'a mut Node> {
+    path.iter()
+        .try_fold(root, |node, &i| node.children.get_mut(i))
+}
+
+fn element_at<'a>(root: &'a Node, path: &[usize]) -> Option<&'a ElementData> {
+    match node_at(root, path) {
+        Some(&Node {
+            data: NodeType::Element(ref e),
+            ..
+        }) => Some(e),
+        _ => None,
+    }
+}
+
+/// The paths of the elements under ``node`` (at ``path``) in document order that ``f`` is true
+/// for, up to ``limit``. ``ancestors`` are those of ``node`` for ``layout::matches``.
+fn find_elements<F>(
+    node: &Node,
+    path: &mut Vec<usize>,
+    ancestors: &mut Vec<css::SimpleSelector>,
+    f: &F,
+    limit: usize,
+    found: &mut Vec<Vec<usize>>,
+) where
+    F: Fn(&ElementData, &Vec<css::SimpleSelector>) -> bool,
+{
+    let e = match node.data {
+        NodeType::Element(ref e) => e,
+        NodeType::Text(_) => return,
+    };
+    if f(e, ancestors) {
+        found.push(path.clone());
+    }
+    ancestors.push(layout::ancestor_selector(e));
+    for (i, child) in node.children.iter().enumerate() {
+        if found.len() >= limit {
+            break;
+        }
+        path.push(i);
+        find_elements(child, path, ancestors, f, limit, found);
+        path.pop();
+    }
+    ancestors.pop();
+}
+
+/// The elements of ``root`` matching the selector list ``selectors``, up to ``limit``.
+fn query_selector(
+    root: &Node,
+    selectors: &str,
+    limit: usize,
+) -> Result<Vec<Vec<usize>>, Value> {
+    let stylesheet = css::parse(format!("{} {{}}", selectors));
+    let selectors = match stylesheet.rules.first() {
+        Some(rule) if !rule.selectors.is_empty() => rule.selectors.clone(),
+        _ => {
+            return Err(error(
+                "SyntaxError",
+                format!("'{}' is not a valid selector", selectors),
+            ));
+        }
+    };
+    let mut found = vec![];
+    find_elements(
+        root,
+        &mut vec![],
+        &mut vec![],
+        &|e: &ElementData, ancestors: &Vec<css::SimpleSelector>| {
+            selectors.iter().any(|s| layout::matches(e, s, ancestors))
+        },
+        limit,
+        &mut found,
+    );
+    Ok(found)
+}
+
+fn element(path: Vec<usize>) -> Value {
+    host_object("element", path)
+}
+
+/// The first of ``paths`` as an element, or null
+fn first_element(paths: Vec<Vec<usize>>) -> Value {
+    paths.into_iter().next().map_or(Value::Null, element)
+}
+
+fn elements(paths: Vec<Vec<usize>>) -> Value {
+    array(paths.into_iter().map(element).collect())
+}
+
+/// ``background-color`` for ``backgroundColor``
+fn css_property_name(name: &str) -> String {
+    let mut property = String::new();
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            property.push('-');
+            property.push(c.to_ascii_lowercase());
+        } else {
+            property.push(c);
+        }
+    }
+    property
+}
+
+/// The declarations of a ``style`` attribute as (name, value)
+fn style_declarations(style: &str) -> Vec<(String, String)> {
+    style
+        .split(';')
+        .filter_map(|declaration| {
+            let mut parts = declaration.splitn(2, ':');
+            let name = parts.next()?.trim().to_lowercase();
+            let value = parts.next()?.trim().to_string();
+            if name.is_empty() {
+                None
+            } else {
+                Some((name, value))
+            }
+        })
+        .collect()
+}
+
+impl Dom {
+    fn element_mut(&mut self, path: &[usize]) -> Option<&mut ElementData> {
+        match node_at_mut(&mut self.document, path) {
+            Some(&mut Node {
+                data: NodeType::Element(ref mut e),
+                ..
+            }) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn set_attribute(&mut self, path: &[usize], name: &str, value: Option<String>) {
+        if let Some(e) = self.element_mut(path) {
+            match value {
+                Some(value) => e.attrs.insert(name.to_lowercase(), value),
+                None => e.attrs.remove(&name.to_lowercase()),
+            };
+            self.mutated = true;
+        }
+    }
+
+    fn style_property(&self, path: &[usize], property: &str) -> String {
+        let style = element_at(&self.document, path).and_then(|e| e.attrs.get("style"));
+        style
+            .map(|style| style_declarations(style))
+            .and_then(|declarations| {
+                declarations
+                    .into_iter()
+                    .rev()
+                    .find(|d| d.0 == property)
+                    .map(|d| d.1)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Sets (or removes, if ``value`` is empty) the declaration of ``property`` in the ``style``
+    /// attribute of the element at ``path``.
+    fn set_style_property(&mut self, path: &[usize], property: &str, value: &str) {
+        let style = element_at(&self.document, path)
+            .and_then(|e| e.attrs.get("style"))
+            .cloned()
+            .unwrap_or_default();
+        let mut declarations: Vec<(String, String)> = style_declarations(&style)
+            .into_iter()
+            .filter(|d| d.0 != property)
+            .collect();
+        if !value.trim().is_empty() {
+            declarations.push((property.to_string(), value.trim().to_string()));
+        }
+        let style: Vec<String> = declarations
+            .iter()
+            .map(|&(ref name, ref value)| format!("{}: {}", name, value))
+            .collect();
+        self.set_attribute(path, "style", Some(style.join("; ")));
+    }
+
+    /// Replaces the children of the node at ``path``, and forgets the listeners and properties
+    /// of the nodes removed.
+    fn replace_children(&mut self, path: &[usize], children: Vec<Node>) {
+        let node = match node_at_mut(&mut self.document, path) {
+            Some(node) => node,
+            None => return,
+        };
+        node.children = children;
+        let is_descendant = |key: &[usize]| key.len() > path.len() && key.starts_with(path);
+        self.properties.retain(|key, _| !is_descendant(key));
+        self.listeners
+            .retain(|l| l.target.0 != "element" || !is_descendant(&l.target.1));
+        self.mutated = true;
+    }
+
+    /// The node id of the ``<canvas>`` at ``path`` and the size of its bitmap, None if there is
+    /// none
+    fn canvas(&self, path: &[usize]) -> Option<(usize, (u32, u32))> {
+        let node = node_at(&self.document, path)?;
+        match node.data {
+            NodeType::Element(ref e) if e.tag_name == "canvas" => {}
+            _ => return None,
+        }
+        Some((self.document.find_id_by_path(path)?, canvas2d::bitmap_size(node)))
+    }
+
+    /// The bitmap of ``image``, an ``<img>`` or a ``<canvas>``. None until it is loaded or drawn
+    /// on.
+    #[cfg(feature = "raster")]
+    fn image_bitmap(&self, image: &Value) -> Option<Image> {
+        let path = image.as_host_object().filter(|o| o.class == "element")?.key;
+        if let Some((id, size)) = self.canvas(&path) {
+            return canvas2d::pixbuf(id, size);
+        }
+        let (url, _) = srcset::select_source(node_at(&self.document, &path)?, &[])?;
+        inline::pixbuf(&url)
+    }
+
+    fn expando(&self, path: &[usize], name: &str) -> Value {
+        self.properties
+            .get(path)
+            .and_then(|properties| properties.iter().find(|p| p.0 == name))
+            .map_or(Value::Undefined, |p| p.1.clone())
+    }
+
+    fn set_expando(&mut self, path: &[usize], name: &str, value: Value) {
+        let properties = self.properties.entry(path.to_vec()).or_default();
+        match properties.iter().position(|p| p.0 == name) {
+            Some(i) => properties[i].1 = value,
+            None => properties.push((name.to_string(), value)),
+        }
+    }
+}
+
+/// Sets the property ``name`` of a 2D context to ``value``. Invalid values are ignored.
+#[cfg(feature = "raster")]
+fn set_context_property(state: &mut DrawingState, name: &str, value: &Value) {
+    let (string, number) = (value.to_string(), value.to_number());
+    match name {
+        "fillStyle" | "strokeStyle" => {
+            if let Some(color) = canvas2d::parse_color(&string) {
+                if name == "fillStyle" {
+                    state.fill_style = color;
+                } else {
+                    state.stroke_style = color;
+                }
+            }
+        }
+        "lineWidth" if number > 0.0 && number.is_finite() => state.line_width = number,
+        "globalAlpha" if (0.0..=1.0).contains(&number) => state.global_alpha = number,
+        "font" if canvas2d::is_valid_font(&string) => state.font = string,
+        "textAlign" => {
+            if let Some(text_align) = TextAlign::from_keyword(&string) {
+                state.text_align = text_align;
+            }
+        }
+        "textBaseline" => {
+            if let Some(text_baseline) = TextBaseline::from_keyword(&string) {
+                state.text_baseline = text_baseline;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Calls the method ``name`` of the 2D context of the ``<canvas>`` at ``path``. Those given
+/// arguments that are not finite numbers do nothing.
+#[cfg(feature = "raster")]
+fn call_context(dom: &Dom, path: &[usize], name: &str, args: &[Value]) -> Value {
+    let (id, size) = match dom.canvas(path) {
+        Some(canvas) => canvas,
+        None => return Value::Undefined,
+    };
+    let numbers: Vec<f64> = args.iter().map(Value::to_number).collect();
+    let n = |i: usize| numbers[i];
+    // The arguments from ``start`` on are ``count`` finite numbers.
+    let finite = |start: usize, count: usize| {
+        numbers.len() >= start + count
+            && numbers[start..start + count].iter().all(|n| n.is_finite())
+    };
+    let text = args.first().map(Value::to_string).unwrap_or_default();
+    // Before the canvas drawn on is borrowed, as the image may be another one
+    let image = match name {
+        "drawImage" => args.first().and_then(|image| dom.image_bitmap(image)),
+        _ => None,
+    };
+
+    canvas2d::with_context(id, size, |context| {
+        match name {
+            "fillRect" if finite(0, 4) => context.fill_rect(n(0), n(1), n(2), n(3)),
+            "strokeRect" if finite(0, 4) => context.stroke_rect(n(0), n(1), n(2), n(3)),
+            "clearRect" if finite(0, 4) => context.clear_rect(n(0), n(1), n(2), n(3)),
+            "rect" if finite(0, 4) => context.rect(n(0), n(1), n(2), n(3)),
+            "beginPath" => context.begin_path(),
+            "closePath" => context.close_path(),
+            "moveTo" if finite(0, 2) => context.move_to(n(0), n(1)),
+            "lineTo" if finite(0, 2) => context.line_to(n(0), n(1)),
+            "bezierCurveTo" if finite(0, 6) => {
+                context.bezier_curve_to(n(0), n(1), n(2), n(3), n(4), n(5))
+            }
+            "quadraticCurveTo" if finite(0, 4) => {
+                context.quadratic_curve_to(n(0), n(1), n(2), n(3))
+            }
+            "arc" if finite(0, 5) => {
+                let ccw = args.get(5).is_some_and(Value::truthy);
+                context.arc(n(0), n(1), n(2), n(3), n(4), ccw)
+            }
+            "fill" => context.fill(args.last().is_some_and(|rule| rule.to_string() == "evenodd")),
+            "stroke" => context.stroke(),
+            "fillText" if finite(1, 2) => context.fill_text(&text, n(1), n(2)),
+            "strokeText" if finite(1, 2) => context.stroke_text(&text, n(1), n(2)),
+            "measureText" => {
+                let width = Value::Number(context.measure_text(&text));
+                return object(vec![("width".to_string(), width)]);
+            }
+            "drawImage" => {
+                let image = match image {
+                    Some(ref image) => image,
+                    None => return Value::Undefined,
+                };
+                let natural = (0.0, 0.0, image.get_width() as f64, image.get_height() as f64);
+                let (source, dest) = match numbers.len() {
+                    3 if finite(1, 2) => (natural, (n(1), n(2), natural.2, natural.3)),
+                    5 if finite(1, 4) => (natural, (n(1), n(2), n(3), n(4))),
+                    9 if finite(1, 8) => ((n(1), n(2), n(3), n(4)), (n(5), n(6), n(7), n(8))),
+                    _ => return Value::Undefined,
+                };
+                context.draw_image(image, source, dest)
+            }
+            "save" => context.save(),
+            "restore" => context.restore(),
+            "translate" if finite(0, 2) => context.translate(n(0), n(1)),
+            "scale" if finite(0, 2) => context.scale(n(0), n(1)),
+            "rotate" if finite(0, 1) => context.rotate(n(0)),
+            _ => {}
+        }
+        Value::Undefined
+    })
+}
+
+impl js_interpreter::Host for Dom {
+    fn get(js: &mut Interpreter<Dom>, object: &HostObject, name: &str) -> Result<Value, Value> {
+        let dom = &js.host;
+        let path = &object.key;
+        Ok(match object.class {
+            "window" => match js.global(name) {
+                Some(value) => value,
+                None => method(object, &WINDOW_METHODS, name).unwrap_or(Value::Undefined),
+            },
+            "event" => {
+                let dispatching = match dom.events.get(path[0]) {
+                    Some(dispatching) => dispatching,
+                    None => return Ok(Value::Undefined),
+                };
+                let event = &dispatching.event;
+                match name {
+                    "type" => Value::Str(event.kind.clone()),
+                    "target" => element(event.target.clone()),
+                    "currentTarget" => dispatching.current_target.clone(),
+                    "eventPhase" => Value::Number(dispatching.phase as i32 as f64),
+                    "bubbles" | "cancelable" => Value::Bool(true),
+                    "defaultPrevented" => Value::Bool(dispatching.canceled),
+                    "key" => Value::Str(event.key.clone()),
+                    "clientX" => Value::Number(event.client_x),
+                    "clientY" => Value::Number(event.client_y),
+                    "button" => Value::Number(0.0),
+                    _ => method(object, &EVENT_METHODS, name).unwrap_or(Value::Undefined),
+                }
+            }
+            "document" => match name {
+                "documentElement" => element(vec![]),
+                "body" => first_element(query_selector(&dom.document, "body", 1)?),
+                "head" => first_element(query_selector(&dom.document, "head", 1)?),
+                "title" => Value::Str(dom.document.find_title().unwrap_or_default()),
+                _ => method(object, &DOCUMENT_METHODS, name)
+                    .unwrap_or_else(|| dom.expando(path, name)),
+            },
+            #[cfg(feature = "raster")]
+            "context2d" => {
+                let state = dom.canvas(path)
+                    .map_or_else(DrawingState::default, |(id, _)| canvas2d::state(id));
+                match name {
+                    "canvas" => element(path.clone()),
+                    "fillStyle" => Value::Str(canvas2d::serialize_color(state.fill_style)),
+                    "strokeStyle" => Value::Str(canvas2d::serialize_color(state.stroke_style)),
+                    "lineWidth" => Value::Number(state.line_width),
+                    "globalAlpha" => Value::Number(state.global_alpha),
+                    "font" => Value::Str(state.font),
+                    "textAlign" => Value::Str(state.text_align.keyword().to_string()),
+                    "textBaseline" => Value::Str(state.text_baseline.keyword().to_string()),
+                    _ => method(object, &CONTEXT_METHODS, name).unwrap_or(Value::Undefined),
+                }
+            }
+            "style" => match name {
+                "cssText" => Value::Str(
+                    element_at(&dom.document, path)
+                        .and_then(|e| e.attrs.get("style"))
+                        .cloned()
+                        .unwrap_or_default(),
+                ),
+                _ => method(object, &STYLE_METHODS, name).unwrap_or_else(|| {
+                    Value::Str(dom.style_property(path, &css_property_name(name)))
+                }),
+            },
+            _ => {
+                let node = match node_at(&dom.document, path) {
+                    Some(node) => node,
+                    None => return Ok(Value::Undefined),
+                };
+                let e = match node.data {
+                    NodeType::Element(ref e) => e,
+                    NodeType::Text(_) => return Ok(Value::Undefined),
+                };
+                let attr = |name: &str| Value::Str(e.attrs.get(name).cloned().unwrap_or_default());
+                match name {
+                    "tagName" | "nodeName" => Value::Str(e.tag_name.to_uppercase()),
+                    "id" => attr("id"),
+                    "className" => attr("class"),
+                    "textContent" | "innerText" => Value::Str(node.text_content()),
+                    "innerHTML" => Value::Str(html::serialize_children(node)),
+                    "outerHTML" => Value::Str(html::serialize(node)),
+                    "value" if e.tag_name == "select" => Value::Str(
+                        node.selected_option()
+                            .map(|i| node.select_options()[i].option_value())
+                            .unwrap_or_default(),
+                    ),
+                    "value" => Value::Str(node.text_input_value()),
+                    "width" | "height" if e.tag_name == "canvas" => {
+                        let (width, height) = canvas2d::bitmap_size(node);
+                        Value::Number(if name == "width" { width } else { height } as f64)
+                    }
+                    "getContext" if e.tag_name == "canvas" => host_method(object, "getContext"),
+                    "checked" => Value::Bool(e.attrs.contains_key("checked")),
+                    "style" => host_object("style", path.clone()),
+                    "children" => elements(
+                        node.children
+                            .iter()
+                            .enumerate()
+                            .filter(|&(_, child)| match child.data {
+                                NodeType::Element(_) => true,
+                                NodeType::Text(_) => false,
+                            })
+                            .map(|(i, _)| {
+                                let mut child_path = path.clone();
+                                child_path.push(i);
+                                child_path
+                            })
+                            .collect(),
+                    ),
+                    "parentElement" | "parentNode" if path.is_empty() => Value::Null,
+                    "parentElement" | "parentNode" => element(path[..path.len() - 1].to_vec()),
+                    _ => method(object, &ELEMENT_METHODS, name)
+                        .unwrap_or_else(|| dom.expando(path, name)),
+                }
+            }
+        })
+    }
+
+    fn set(
+        js: &mut Interpreter<Dom>,
+        object: &HostObject,
+        name: &str,
+        value: Value,
+    ) -> Result<(), Value> {
+        let dom = &mut js.host;
+        let path = &object.key;
+        match (object.class, name) {
+            ("window", _) => js.define_global(name, value),
+            ("document", "title") => {
+                let title = query_selector(&dom.document, "title", 1)?;
+                if let Some(title) = title.first() {
+                    dom.replace_children(title, vec![Node::text(value.to_string())]);
+                }
+            }
+            ("document", _) => dom.set_expando(path, name, value),
+            ("event", _) => {}
+            #[cfg(feature = "raster")]
+            ("context2d", _) => {
+                if let Some((id, size)) = dom.canvas(path) {
+                    canvas2d::with_context(id, size, |context| {
+                        set_context_property(context.state_mut(), name, &value)
+                    });
+                }
+            }
+            ("style", "cssText") => dom.set_attribute(path, "style", Some(value.to_string())),
+            ("style", _) => {
+                dom.set_style_property(path, &css_property_name(name), &value.to_string())
+            }
+            (_, "id") => dom.set_attribute(path, "id", Some(value.to_string())),
+            (_, "className") => dom.set_attribute(path, "class", Some(value.to_string())),
+            (_, "textContent") | (_, "innerText") => {
+                dom.replace_children(path, vec![Node::text(value.to_string())])
+            }
+            (_, "innerHTML") => {
+                let tag_name = match element_at(&dom.document, path) {
+                    Some(e) => e.tag_name.clone(),
+                    None => return Ok(()),
+                };
+                let children =
+                    html::parse_fragment(value.to_string(), &tag_name, dom.base_url.clone());
+                dom.replace_children(path, children)
+            }
+            (_, "value") => {
+                if let Some(node) = node_at_mut(&mut dom.document, path) {
+                    node.set_text_input_value(value.to_string());
+                    dom.mutated = true;
+                }
+            }
+            (_, "style") => dom.set_attribute(path, "style", Some(value.to_string())),
+            (_, "width") | (_, "height") if dom.canvas(path).is_some() => {
+                let size = value.to_number();
+                let size = if size >= 0.0 { size as u32 } else { 0 };
+                dom.set_attribute(path, name, Some(size.to_string()))
+            }
+            (_, "checked") => {
+                let checked = if value.truthy() { Some(String::new()) } else { None };
+                dom.set_attribute(path, "checked", checked)
+            }
+            _ => dom.set_expando(path, name, value),
+        }
+        Ok(())
+    }
+
+    fn call(
+        js: &mut Interpreter<Dom>,
+        object: &HostObject,
+        name: &'static str,
+        args: Vec<Value>,
+    ) -> Result<Value, Value> {
+        let arg = |i: usize| args.get(i).map_or(String::new(), |arg| arg.to_string());
+        let path = &object.key;
+        #[cfg(feature = "raster")]
+        {
+            if object.class == "context2d" {
+                return Ok(call_context(&js.host, path, name, &args));
+            }
+        }
+        match name {
+            "preventDefault" | "stopPropagation" | "stopImmediatePropagation" => {
+                if let Some(dispatching) = js.host.events.get_mut(path[0]) {
+                    match name {
+                        "preventDefault" => dispatching.canceled = true,
+                        "stopImmediatePropagation" => {
+                            dispatching.stopped = true;
+                            dispatching.stopped_immediately = true;
+                        }
+                        _ => dispatching.stopped = true,
+                    }
+                }
+                return Ok(Value::Undefined);
+            }
+            "addEventListener" | "removeEventListener" => {
+                let callback = args.get(1).cloned().unwrap_or(Value::Undefined);
+                // ``true``, or ``{ capture: true }``
+                let capture = match args.get(2) {
+                    Some(options @ &Value::Object(_)) => {
+                        js.get_property(options, "capture")?.truthy()
+                    }
+                    Some(capture) => capture.truthy(),
+                    None => false,
+                };
+                let listener = Listener {
+                    target: (object.class, path.clone()),
+                    kind: arg(0),
+                    callback: callback,
+                    capture: capture,
+                };
+                let listeners = &mut js.host.listeners;
+                let same = |l: &Listener| {
+                    l.target == listener.target && l.kind == listener.kind
+                        && l.callback == listener.callback
+                        && l.capture == listener.capture
+                };
+                let added = listeners.iter().position(same);
+                match (name, added) {
+                    ("addEventListener", None) if listener.callback.is_function() => {
+                        listeners.push(listener)
+                    }
+                    ("removeEventListener", Some(i)) => {
+                        listeners.remove(i);
+                    }
+                    _ => {}
+                }
+                return Ok(Value::Undefined);
+            }
+            "click" => {
+                dispatch_with_activation(js, Event::mouse("click", path.clone(), 0.0, 0.0));
+                return Ok(Value::Undefined);
+            }
+            "setTimeout" | "setInterval" => {
+                let delay = args.get(1).map_or(0.0, |delay| delay.to_number());
+                let mut delay = if delay > 0.0 { delay as u64 } else { 0 };
+                if name == "setInterval" {
+                    delay = delay.max(MIN_INTERVAL);
+                }
+                let delay = Duration::from_millis(delay);
+                let timer = Timer {
+                    callback: args.first().cloned().unwrap_or(Value::Undefined),
+                    args: args.iter().skip(2).cloned().collect(),
+                    interval: if name == "setInterval" { Some(delay) } else { None },
+                };
+                let id = js.host.timers.schedule(Instant::now() + delay, timer);
+                return Ok(Value::Number(id as f64));
+            }
+            "clearTimeout" | "clearInterval" => {
+                let id = args.first().map_or(0.0, |id| id.to_number());
+                if id >= 1.0 && js.host.timers.cancel(id as TaskId).is_none() {
+                    if let Some((running, ref mut cleared)) = js.host.running_timer {
+                        *cleared |= running == id as TaskId;
+                    }
+                }
+                return Ok(Value::Undefined);
+            }
+            _ => {}
+        }
+
+        let dom = &mut js.host;
+        let root = match node_at(&dom.document, path) {
+            Some(node) => node,
+            None => return Ok(Value::Undefined),
+        };
+        Ok(match name {
+            "getElementById" => {
+                let id = arg(0);
+                let mut found = vec![];
+                find_elements(
+                    root,
+                    &mut path.clone(),
+                    &mut vec![],
+                    &|e: &ElementData, _: &Vec<css::SimpleSelector>| e.id() == Some(&id),
+                    1,
+                    &mut found,
+                );
+                first_element(found)
+            }
+            "getElementsByTagName" | "getElementsByClassName" => {
+                let key = arg(0);
+                let mut found = vec![];
+                find_elements(
+                    root,
+                    &mut path.clone(),
+                    &mut vec![],
+                    &|e: &ElementData, _: &Vec<css::SimpleSelector>| match name {
+                        "getElementsByTagName" => key == "*" || e.tag_name == key.to_lowercase(),
+                        _ => key.split_whitespace().all(|c| e.classes().contains(c)),
+                    },
+                    usize::MAX,
+                    &mut found,
+                );
+                // Not the element itself
+                elements(found.into_iter().filter(|p| p != path).collect())
+            }
+            "querySelector" | "querySelectorAll" => {
+                // Only the descendants of an element
+                let scoped = object.class == "element";
+                let limit = if name == "querySelector" && !scoped {
+                    1
+                } else {
+                    usize::MAX
+                };
+                let found: Vec<Vec<usize>> = query_selector(&dom.document, &arg(0), limit)?
+                    .into_iter()
+                    .filter(|p| !scoped || (p.len() > path.len() && p.starts_with(path)))
+                    .collect();
+                if name == "querySelector" {
+                    first_element(found)
+                } else {
+                    elements(found)
+                }
+            }
+            "getContext" => match dom.canvas(path) {
+                // Drawn on with cairo (see ``canvas2d``)
+                Some(_) if arg(0) == "2d" && cfg!(feature = "raster") => {
+                    host_object("context2d", path.clone())
+                }
+                _ => Value::Null,
+            },
+            "getAttribute" => {
+                let name = arg(0).to_lowercase();
+                match element_at(&dom.document, path).and_then(|e| e.attrs.get(&name)) {
+                    Some(value) => Value::Str(value.clone()),
+                    None => Value::Null,
+                }
+            }
+            "hasAttribute" => {
+                let name = arg(0).to_lowercase();
+                let e = element_at(&dom.document, path);
+                Value::Bool(e.is_some_and(|e| e.attrs.contains_key(&name)))
+            }
+            "setAttribute" => {
+                dom.set_attribute(path, &arg(0), Some(arg(1)));
+                Value::Undefined
+            }
+            "removeAttribute" => {
+                dom.set_attribute(path, &arg(0), None);
+                Value::Undefined
+            }
+            "getPropertyValue" => Value::Str(dom.style_property(path, &arg(0).to_lowercase())),
+            "setProperty" => {
+                dom.set_style_property(path, &arg(0).to_lowercase(), &arg(1));
+                Value::Undefined
+            }
+            "removeProperty" => {
+                let old = dom.style_property(path, &arg(0).to_lowercase());
+                dom.set_style_property(path, &arg(0).to_lowercase(), "");
+                Value::Str(old)
+            }
+            _ => Value::Undefined,
+        })
+    }
+}
+
+#[test]
+fn test_dom_bindings() {
+    use html;
+    use network::Url;
+
+    let mut document = html::parse(
+        "<html><head><title>a</title></head><body>\
+         <p id=\"p\" class=\"x y\" style=\"color: red\">text <b>bold</b></p>\
+         <script>var p = document.getElementById('p'); p.style.backgroundColor = 'blue';</script>\
+         <script type=\"text/template\">document.title = 'no';</script>\
+         <script>document.title = 'b'; throw new Error('stops this script');</script>\
+         <script>p.data = 1;</script>\
+         </body></html>"
+            .to_string(),
+        Url::parse("file:///a.html").unwrap(),
+    );
+    let mut runtime = Runtime::new(Url::parse("file:///a.html").unwrap());
+    runtime.run_scripts(&mut document);
+    assert!(runtime.take_mutated());
+    assert!(!runtime.take_mutated());
+    assert_eq!(document.find_title(), Some("b".to_string()));
+
+    let mut eval = |source: &str| match runtime.eval(&mut document, source) {
+        Ok(value) => value,
+        Err(e) => e,
+    };
+    let cases = [
+        ("p.getAttribute('style')", "color: red; background-color: blue"),
+        ("p.style.color + p.style.getPropertyValue('background-color')", "redblue"),
+        ("p.textContent + '|' + p.tagName + '|' + p.className + '|' + p.data", "text bold|P|x y|1"),
+        ("document.getElementById('p') === p && p !== document.body", "true"),
+        ("document.getElementById('nope')", "null"),
+        ("document.querySelector('body .x').id + document.querySelectorAll('script').length", "p4"),
+        ("document.getElementsByClassName('y')[0] === p", "true"),
+        ("p.querySelector('b').textContent + p.getElementsByTagName('*').length", "bold1"),
+        ("document.body.children.length + p.parentElement.tagName", "5BODY"),
+        ("p.style.color = ''; p.setAttribute('title', 't'); p.getAttribute('style')", "background-color: blue"),
+        ("p.textContent = 'new'; p.children.length + p.textContent", "0new"),
+    ];
+    for &(source, expected) in cases.iter() {
+        assert_eq!(eval(source), expected, "{}", source);
+    }
+
+    let p = &document.children[1].children[0];
+    assert_eq!(p.children, vec![Node::text("new".to_string())]);
+    match p.data {
+        NodeType::Element(ref e) => assert_eq!(e.attrs.get("title"), Some(&"t".to_string())),
+        NodeType::Text(_) => panic!(),
+    }
+}
+
+#[test]
+fn test_events() {
+    use html;
+    use network::Url;
+
+    let mut document = html::parse(
+        "<html><body><div id=\"d\"><a id=\"a\" href=\"b.html\">a</a></div>\
+         <input id=\"c\" type=\"checkbox\"></body></html>"
+            .to_string(),
+        Url::parse("file:///a.html").unwrap(),
+    );
+    let mut runtime = Runtime::new(Url::parse("file:///a.html").unwrap());
+    let source = "var log = [];
+        function listen(target, name) {
+            target.addEventListener('click', e => log.push(name + e.eventPhase), true);
+            target.addEventListener('click', function(e) { log.push(name + e.eventPhase) });
+        }
+        listen(window, 'w'); listen(document, 'doc'); listen(document.body, 'b');
+        var a = document.getElementById('a');
+        listen(a, 'a');
+        var d = document.getElementById('d');
+        d.onclick = e => e.target === a && e.currentTarget === d && log.push('on');
+        var c = document.getElementById('c');
+        var cancel = false, checked;
+        c.addEventListener('click', e => { checked = c.checked; if (cancel) e.preventDefault() });";
+    runtime.eval(&mut document, source).unwrap();
+
+    // html > body > div#d > a#a
+    let a = vec![0, 0, 0];
+    assert!(runtime.dispatch_event(&mut document, Event::mouse("click", a.clone(), 1.0, 2.0)));
+    let log = runtime.eval(&mut document, "log.join(' ')").unwrap();
+    assert_eq!(log, "w1 doc1 b1 a2 a2 on b3 doc3 w3");
+
+    let source = "log = []; d.onclick = e => { e.stopPropagation(); return false };
+        a.addEventListener('click', e => log.push(e.clientY + e.type + e.defaultPrevented));";
+    runtime.eval(&mut document, source).unwrap();
+    assert!(!runtime.dispatch_event(&mut document, Event::mouse("click", a.clone(), 1.0, 2.0)));
+    let log = runtime.eval(&mut document, "log.join(' ')").unwrap();
+    assert_eq!(log, "w1 doc1 b1 a2 a2 2clickfalse");
+
+    // A checkbox is toggled before the listeners are called, and back if the click is canceled.
+    assert_eq!(runtime.eval(&mut document, "c.click(); c.checked"), Ok("true".to_string()));
+    assert!(runtime.take_mutated());
+    let source = "cancel = true; c.click(); checked + ' ' + c.checked";
+    assert_eq!(runtime.eval(&mut document, source), Ok("false true".to_string()));
+
+    let source = "log = []; var f = () => log.push('k');
+        document.addEventListener('keydown', f); document.addEventListener('keydown', f);";
+    runtime.eval(&mut document, source).unwrap();
+    assert!(runtime.dispatch_event(&mut document, Event::key("keydown", vec![0], "Enter")));
+    runtime.eval(&mut document, "document.removeEventListener('keydown', f)").unwrap();
+    assert!(runtime.dispatch_event(&mut document, Event::key("keydown", vec![0], "Enter")));
+    assert_eq!(runtime.eval(&mut document, "log.join(' ')"), Ok("k".to_string()));
+}
+
+#[test]
+fn test_inner_html() {
+    use html;
+    use network::Url;
+
+    let url = Url::parse("file:///a/a.html").unwrap();
+    let mut document = html::parse(
+        "<html><body><div id=\"d\"><p id=\"p\">a &amp; <b>b</b></p></div></body></html>"
+            .to_string(),
+        url.clone(),
+    );
+    let mut runtime = Runtime::new(url);
+    let source = "var d = document.getElementById('d'), p = document.getElementById('p');
+        var log = ''; p.data = 1;
+        p.addEventListener('click', () => log += 'p');
+        d.addEventListener('click', () => log += 'd');
+        p.innerHTML + '|' + p.outerHTML";
+    assert_eq!(
+        runtime.eval(&mut document, source),
+        Ok("a &amp; <b>b</b>|<p id=\"p\">a &amp; <b>b</b></p>".to_string())
+    );
+
+    let source = "d.innerHTML = '<a href=\"b.html\">x</a><script>log += \"s\"</script>';
+        var a = d.children[0];
+        a.getAttribute('href') + ' ' + d.children.length + ' ' + a.data + log";
+    assert_eq!(
+        runtime.eval(&mut document, source),
+        Ok("file:///a/b.html 2 undefined".to_string())
+    );
+    assert!(runtime.take_mutated());
+
+    // The listener of the <p> replaced is forgotten, not called for the <a> at its path.
+    // html > body > div#d > a
+    assert!(runtime.dispatch_event(&mut document, Event::mouse("click", vec![0, 0, 0], 0.0, 0.0)));
+    assert_eq!(runtime.eval(&mut document, "log"), Ok("d".to_string()));
+    assert_eq!(
+        html::serialize_children(&document.children[0].children[0]),
+        "<a href=\"file:///a/b.html\">x</a><script>log += \"s\"</script>"
+    );
+}
+
+#[cfg(feature = "raster")]
+#[test]
+fn test_canvas_context() {
+    use css::RED;
+    use html;
+    use network::Url;
+
+    let url = Url::parse("file:///a.html").unwrap();
+    let mut document = html::parse(
+        "<html><body><p>a</p><canvas id=\"c\" width=\"20\"></canvas></body></html>".to_string(),
+        url.clone(),
+    );
+    let id = (0..document.count_nodes())
+        .find(|&id| {
+            let path = document.find_path_by_id(id).unwrap();
+            assert_eq!(document.find_id_by_path(&path), Some(id));
+            element_at(&document, &path).is_some_and(|e| e.tag_name == "canvas")
+        })
+        .unwrap();
+    canvas2d::clear();
+    let mut runtime = Runtime::new(url);
+    let source = "var c = document.getElementById('c'), ctx = c.getContext('2d');
+        ctx.fillStyle = 'red'; ctx.lineWidth = -1; ctx.font = 'bold 12px serif'; ctx.font = 'x';
+        ctx.fillRect(0, 0, 10, 10); ctx.fillRect(NaN, 0, 20, 20);
+        c.width + ' ' + c.height + ' ' + ctx.fillStyle + ' ' + ctx.lineWidth + ' ' + ctx.font
+            + ' ' + ctx.canvas.id + ' ' + c.getContext('webgl') + ' '
+            + (ctx.measureText('ab').width > 0)";
+    assert_eq!(
+        runtime.eval(&mut document, source),
+        Ok("20 150 #ff0000 1 bold 12px serif c null true".to_string())
+    );
+    assert!(canvas2d::take_drawn() && !runtime.take_mutated());
+    let pixel = |size, x, y| canvas2d::with_context(id, size, |context| context.pixel(x, y));
+    assert_eq!(pixel((20, 150), 5, 5), Some(RED));
+    assert_eq!(pixel((20, 150), 15, 15).map(|color| color.a), Some(0));
+
+    // Cleared when it is resized
+    assert_eq!(runtime.eval(&mut document, "c.width = 30; c.width"), Ok("30".to_string()));
+    assert!(runtime.take_mutated());
+    assert_eq!(pixel((30, 150), 5, 5).map(|color| color.a), Some(0));
+}
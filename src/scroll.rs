@@ -0,0 +1,62 @@
+//! Smooth scrolling: with ``scroll-behavior: smooth`` on the root, the viewport is scrolled to
+//! where it goes (e.g. the element of a URL fragment) over ``SMOOTH_SCROLL_DURATION``, a frame at a
+//! time, instead of at once. ref. https://drafts.csswg.org/cssom-view/#smooth-scrolling
+//!
+//! The window moves a ``ScrollAnimation`` on each frame it draws, and ``Document`` on each call of
+//! ``Document::run_timers``.
+
+use std::time::{Duration, Instant};
+
+/// How long a smooth scroll takes
+pub const SMOOTH_SCROLL_DURATION: Duration = Duration::from_millis(250);
+
+/// How often the scroll position of a ``Document`` moves while it scrolls smoothly
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+/// A smooth scroll of the viewport from ``from`` to ``to`` (the scroll positions, in CSS px)
+/// started at ``start``
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScrollAnimation {
+    pub from: (f64, f64),
+    pub to: (f64, f64),
+    pub start: Instant,
+}
+
+impl ScrollAnimation {
+    pub fn new(from: (f64, f64), to: (f64, f64), start: Instant) -> ScrollAnimation {
+        ScrollAnimation {
+            from: from,
+            to: to,
+            start: start,
+        }
+    }
+
+    /// The scroll position at ``now``, and whether it has reached ``to``. It eases in and out.
+    pub fn position(&self, now: Instant) -> ((f64, f64), bool) {
+        let elapsed = now.saturating_duration_since(self.start);
+        if elapsed >= SMOOTH_SCROLL_DURATION {
+            return (self.to, true);
+        }
+        let t = elapsed.as_secs_f64() / SMOOTH_SCROLL_DURATION.as_secs_f64();
+        let eased = t * t * (3.0 - 2.0 * t);
+        let between = |from: f64, to: f64| from + (to - from) * eased;
+        (
+            (
+                between(self.from.0, self.to.0),
+                between(self.from.1, self.to.1),
+            ),
+            false,
+        )
+    }
+}
+
+#[test]
+fn test_scroll_animation() {
+    let start = Instant::now();
+    let animation = ScrollAnimation::new((0.0, 100.0), (0.0, 300.0), start);
+    assert_eq!(animation.position(start), ((0.0, 100.0), false));
+    assert_eq!(animation.position(start + SMOOTH_SCROLL_DURATION / 2), ((0.0, 200.0), false));
+    let ((_, early), _) = animation.position(start + SMOOTH_SCROLL_DURATION / 4);
+    assert!(100.0 < early && early < 150.0);
+    assert_eq!(animation.position(start + SMOOTH_SCROLL_DURATION), ((0.0, 300.0), true));
+}
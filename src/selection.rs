@@ -0,0 +1,346 @@
+use painter::{DisplayCommand, DisplayList};
+use layout::Rect;
+use font::Font;
+
+use std::cmp::{max, min};
+
+use app_units::Au;
+
+/// A position in the text painted on the page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TextPosition {
+    /// The index of the text in the display list
+    pub item: usize,
+    /// The byte offset in the text
+    pub offset: usize,
+}
+
+/// The text selected with the mouse, from where the drag started (``anchor``) to where the
+/// pointer is (``focus``). The range may span several texts (i.e. nodes and lines).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Selection {
+    pub anchor: TextPosition,
+    pub focus: TextPosition,
+}
+
+impl Selection {
+    pub fn new(pos: TextPosition) -> Selection {
+        Selection {
+            anchor: pos,
+            focus: pos,
+        }
+    }
+
+    /// (start, end) in document order.
+    pub fn range(&self) -> (TextPosition, TextPosition) {
+        (min(self.anchor, self.focus), max(self.anchor, self.focus))
+    }
+
+    pub fn is_collapsed(&self) -> bool {
+        self.anchor == self.focus
+    }
+
+    /// The selected part of each text: (item, text, start byte, end byte).
+    pub fn parts<'a>(&self, list: &'a DisplayList) -> Vec<(usize, &'a str, usize, usize)> {
+        let (start, end) = self.range();
+        let mut parts = vec![];
+        for (i, item) in list.iter().enumerate().take(end.item + 1).skip(start.item) {
+            if let DisplayCommand::Text(ref text, _, _, _, _) = item.command {
+                let from = if i == start.item { start.offset } else { 0 };
+                let to = if i == end.item { end.offset } else { text.len() };
+                let (from, to) = (min(from, text.len()), min(to, text.len()));
+                if from < to && text.is_char_boundary(from) && text.is_char_boundary(to) {
+                    parts.push((i, text.as_str(), from, to));
+                }
+            }
+        }
+        parts
+    }
+
+    /// The selected text. Texts in different blocks are separated by line breaks, and the ones in
+    /// different lines of a block by a space.
+    pub fn text(&self, list: &DisplayList) -> String {
+        let mut selected = String::new();
+        let mut last_y = None;
+        for (i, text, from, to) in self.parts(list) {
+            let y = match list[i].command {
+                DisplayCommand::Text(_, rect, _, _, _) => rect.y,
+                _ => unreachable!(),
+            };
+            if let Some(last_y) = last_y {
+                if list[i].starts_block {
+                    selected = selected.trim_end().to_string();
+                    selected.push('\n');
+                } else if last_y != y && !selected.ends_with(char::is_whitespace)
+                    && !text[from..to].starts_with(char::is_whitespace)
+                {
+                    selected.push(' ');
+                }
+            }
+            selected += &text[from..to];
+            last_y = Some(y);
+        }
+        selected
+    }
+
+    /// The rectangles to highlight. ``text_width`` measures a text drawn in the font.
+    pub fn rects<F>(&self, list: &DisplayList, text_width: F) -> Vec<Rect>
+    where
+        F: Fn(&Font, &str) -> f64,
+    {
+        self.parts(list)
+            .into_iter()
+            .filter_map(|(i, text, from, to)| match list[i].command {
+                DisplayCommand::Text(_, rect, _, _, ref font) => {
+                    // Measured as prefixes for the shaping to be the same as the whole text.
+                    let from_x = text_width(font, &text[..from]);
+                    let to_x = text_width(font, &text[..to]);
+                    Some(Rect {
+                        x: rect.x + Au::from_f64_px(from_x),
+                        width: Au::from_f64_px(to_x - from_x),
+                        ..rect
+                    })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The text position nearest to the point (``x``, ``y``) in the document.
+/// Returns None if no text is painted.
+pub fn position_at<F>(list: &DisplayList, x: f64, y: f64, text_width: F) -> Option<TextPosition>
+where
+    F: Fn(&Font, &str) -> f64,
+{
+    let texts: Vec<(usize, &str, Rect, &Font)> = list.iter()
+        .enumerate()
+        .filter_map(|(i, item)| match item.command {
+            DisplayCommand::Text(ref text, rect, _, _, ref font) => {
+                Some((i, text.as_str(), rect, font))
+            }
+            _ => None,
+        })
+        .collect();
+    let top = |rect: &Rect| rect.y.to_f64_px();
+    let bottom = |rect: &Rect| (rect.y + rect.height).to_f64_px();
+    let left = |rect: &Rect| rect.x.to_f64_px();
+    let right = |rect: &Rect| (rect.x + rect.width).to_f64_px();
+
+    // The texts on the line at y. The nearest one horizontally is chosen.
+    let on_line = texts
+        .iter()
+        .filter(|&&(_, _, rect, _)| top(&rect) <= y && y < bottom(&rect))
+        .min_by(|&&(_, _, a, _), &&(_, _, b, _)| {
+            let distance = |rect: &Rect| (left(rect) - x).max(x - right(rect)).max(0.0);
+            distance(&a).partial_cmp(&distance(&b)).unwrap()
+        });
+
+    if let Some(&(i, text, rect, font)) = on_line {
+        return Some(TextPosition {
+            item: i,
+            offset: offset_at(text, x - left(&rect), |s| text_width(font, s)),
+        });
+    }
+
+    // Between lines: the end of the last text above y, or the start of the first text.
+    match texts.iter().rev().find(|&&(_, _, rect, _)| bottom(&rect) <= y) {
+        Some(&(i, text, _, _)) => Some(TextPosition {
+            item: i,
+            offset: text.len(),
+        }),
+        None => texts.first().map(|&(i, _, _, _)| TextPosition { item: i, offset: 0 }),
+    }
+}
+
+/// Finds ``query`` (ASCII case-insensitively) in the painted text. A match may span several
+/// texts of a block, but not blocks.
+pub fn find_all(list: &DisplayList, query: &str) -> Vec<Selection> {
+    if query.is_empty() {
+        return vec![];
+    }
+
+    // All the texts joined, and where each text starts in it.
+    let mut page = String::new();
+    let mut starts: Vec<(usize, usize, usize)> = vec![]; // (item, start in page, text length)
+    for (i, item) in list.iter().enumerate() {
+        if let DisplayCommand::Text(ref text, _, _, _, _) = item.command {
+            if item.starts_block && !page.is_empty() {
+                page.push('\n');
+            }
+            starts.push((i, page.len(), text.len()));
+            page += text.as_str();
+        }
+    }
+    let page = page.to_ascii_lowercase();
+    let query = query.to_ascii_lowercase();
+
+    // The position of the byte at ``pos`` in the page (``pos`` is in a text or just after it).
+    let position = |pos: usize, is_end: bool| {
+        let &(item, start, len) = starts
+            .iter()
+            .rev()
+            .find(|&&(_, start, _)| start < pos || (!is_end && start == pos))
+            .unwrap_or(&starts[0]);
+        TextPosition {
+            item: item,
+            offset: min(pos - start, len),
+        }
+    };
+
+    page.match_indices(query.as_str())
+        .map(|(pos, matched)| Selection {
+            anchor: position(pos, false),
+            focus: position(pos + matched.len(), true),
+        })
+        .collect()
+}
+
+/// The byte offset in ``text`` nearest to ``x`` (from the left end of the text). ``text_width``
+/// measures prefixes of ``text`` so that shaping (e.g. ligatures) is taken into account, and
+/// the offset is never between a character and its combining marks.
+pub fn offset_at<F>(text: &str, x: f64, text_width: F) -> usize
+where
+    F: Fn(&str) -> f64,
+{
+    // The end of the last cluster and its x
+    let (mut boundary, mut boundary_x) = (0, 0.0);
+    for (i, c) in text.char_indices() {
+        let end = i + c.len_utf8();
+        let end_x = text_width(&text[..end]);
+        // Zero width: drawn over the character before it.
+        if end_x <= boundary_x && boundary > 0 {
+            boundary = end;
+            continue;
+        }
+        if x < (boundary_x + end_x) / 2.0 {
+            return boundary;
+        }
+        boundary = end;
+        boundary_x = end_x;
+    }
+    text.len()
+}
+
+#[cfg(test)]
+fn test_display_list() -> DisplayList {
+    use painter::DisplayCommandInfo;
+    use font::{FontSlant, FontWeight, DEFAULT_FONT_FAMILY, DEFAULT_FONT_FEATURES,
+               DEFAULT_FONT_LANGUAGE, DEFAULT_TAB_SIZE};
+    use css::BLACK;
+
+    let font = Font {
+        size: Au::from_f64_px(10.0),
+        weight: FontWeight::Normal,
+        slant: FontSlant::Normal,
+        family: DEFAULT_FONT_FAMILY,
+        features: DEFAULT_FONT_FEATURES,
+        letter_spacing: Au(0),
+        word_spacing: Au(0),
+        tab_size: DEFAULT_TAB_SIZE,
+        lang: DEFAULT_FONT_LANGUAGE,
+    };
+    let text = |s: &str, x: f64, y: f64| {
+        DisplayCommandInfo::new(DisplayCommand::Text(
+            s.to_string(),
+            Rect {
+                x: Au::from_f64_px(x),
+                y: Au::from_f64_px(y),
+                width: Au::from_f64_px(s.len() as f64 * 10.0),
+                height: Au::from_f64_px(20.0),
+            },
+            BLACK,
+            vec![],
+            font,
+        ))
+    };
+    let mut list = vec![
+        DisplayCommandInfo::new(DisplayCommand::SolidColor(BLACK, Rect::default())),
+        text("hello ", 0.0, 0.0),
+        text("world", 60.0, 0.0),
+        text("second", 0.0, 20.0),
+        text("block", 0.0, 40.0),
+    ];
+    list[1].starts_block = true;
+    list[4].starts_block = true;
+    list
+}
+
+#[test]
+fn test_position_at() {
+    let list = test_display_list();
+    let monospace = |_: &Font, s: &str| s.chars().count() as f64 * 10.0;
+    let pos = |x, y| position_at(&list, x, y, monospace).unwrap();
+
+    assert_eq!(pos(12.0, 5.0), TextPosition { item: 1, offset: 1 });
+    assert_eq!(pos(16.0, 5.0), TextPosition { item: 1, offset: 2 });
+    assert_eq!(pos(75.0, 5.0), TextPosition { item: 2, offset: 2 });
+    // Beyond the end of the line
+    assert_eq!(pos(300.0, 5.0), TextPosition { item: 2, offset: 5 });
+    assert_eq!(pos(300.0, 100.0), TextPosition { item: 4, offset: 5 });
+    assert_eq!(pos(300.0, 25.0), TextPosition { item: 3, offset: 6 });
+    assert_eq!(pos(10.0, -5.0), TextPosition { item: 1, offset: 0 });
+
+    assert_eq!(offset_at("abc", -3.0, |s| s.len() as f64), 0);
+    assert_eq!(offset_at("abc", 1.6, |s| s.len() as f64), 2);
+    // "e" + combining acute accent
+    let width = |s: &str| s.chars().filter(|&c| c != '\u{301}').count() as f64;
+    assert_eq!(offset_at("e\u{301}x", 1.2, width), 3);
+}
+
+#[test]
+fn test_find_all() {
+    let list = test_display_list();
+    let found = |query| {
+        find_all(&list, query)
+            .iter()
+            .map(|selection| selection.text(&list))
+            .collect::<Vec<String>>()
+    };
+
+    assert_eq!(found("O"), vec!["o", "o", "o", "o"]);
+    assert_eq!(found("lo wor"), vec!["lo wor"]);
+    assert_eq!(found("secondblock"), Vec::<String>::new());
+    assert!(found("").is_empty());
+
+    let matches = find_all(&list, "world");
+    assert_eq!(
+        matches[0].range(),
+        (
+            TextPosition { item: 2, offset: 0 },
+            TextPosition { item: 2, offset: 5 }
+        )
+    );
+}
+
+#[test]
+fn test_selection() {
+    let list = test_display_list();
+    let monospace = |_: &Font, s: &str| s.chars().count() as f64 * 10.0;
+
+    let mut selection = Selection::new(TextPosition { item: 3, offset: 3 });
+    assert!(selection.is_collapsed());
+    assert!(selection.parts(&list).is_empty());
+
+    // Dragged backward
+    selection.focus = TextPosition { item: 1, offset: 2 };
+    assert_eq!(
+        selection.parts(&list),
+        vec![
+            (1, "hello ", 2, 6),
+            (2, "world", 0, 5),
+            (3, "second", 0, 3),
+        ]
+    );
+
+    let rects = selection.rects(&list, monospace);
+    assert_eq!(rects.len(), 3);
+    assert_eq!(rects[0].x, Au::from_f64_px(20.0));
+    assert_eq!(rects[0].width, Au::from_f64_px(40.0));
+    assert_eq!(rects[2].width, Au::from_f64_px(30.0));
+
+    assert_eq!(selection.text(&list), "llo world sec");
+    selection.focus = TextPosition { item: 4, offset: 2 };
+    assert_eq!(selection.text(&list), "ond\nbl");
+
+}
@@ -0,0 +1,452 @@
+//! Basic shapes: ``circle()``, ``ellipse()``, ``inset()`` and ``polygon()``, e.g. the area of a
+//! float that the inline content next to it wraps around (``shape-outside``), or the part of a
+//! box that is painted and hit (``clip-path``).
+//! ref. https://www.w3.org/TR/css-shapes-1/#basic-shape-functions
+//!
+//! A shape is specified relative to a reference box (the margin box of a float, or the border box
+//! of a clipped box, by default), and placed in it once the box is laid out
+//! (``BasicShape::resolve``).
+
+use layout::{Dimensions, LayoutBox, Rect};
+use style::{parse_background_position, BackgroundPosition};
+use css::{Unit, Value};
+
+use app_units::Au;
+
+/// The radius of a circle, or of an ellipse in one direction
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShapeRadius {
+    /// A length, or a percentage of the reference box
+    Length(Value),
+    /// To the side of the reference box closest to the center (the default)
+    ClosestSide,
+    FarthestSide,
+}
+
+/// Which points are in a polygon whose edges cross.
+/// ref. https://www.w3.org/TR/SVG2/painting.html#FillRuleProperty
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FillRule {
+    /// Those the edges wind around
+    NonZero,
+    /// Those in an odd number of edges
+    EvenOdd,
+}
+
+/// The box a shape is placed in. ref. https://www.w3.org/TR/css-shapes-1/#typedef-shape-box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReferenceBox {
+    Margin,
+    Border,
+    Padding,
+    Content,
+}
+
+impl ReferenceBox {
+    pub fn from_keyword(keyword: &str) -> Option<ReferenceBox> {
+        match keyword {
+            "margin-box" => Some(ReferenceBox::Margin),
+            "border-box" => Some(ReferenceBox::Border),
+            "padding-box" => Some(ReferenceBox::Padding),
+            "content-box" => Some(ReferenceBox::Content),
+            _ => None,
+        }
+    }
+
+    pub fn rect(self, dimensions: Dimensions) -> Rect {
+        match self {
+            ReferenceBox::Margin => dimensions.margin_box(),
+            ReferenceBox::Border => dimensions.border_box(),
+            ReferenceBox::Padding => dimensions.padding_box(),
+            ReferenceBox::Content => dimensions.content,
+        }
+    }
+}
+
+/// A basic shape as specified
+#[derive(Clone, Debug, PartialEq)]
+pub enum BasicShape {
+    /// The radius and the center
+    Circle(ShapeRadius, (BackgroundPosition, BackgroundPosition)),
+    /// The horizontal and the vertical radius, and the center
+    Ellipse(
+        ShapeRadius,
+        ShapeRadius,
+        (BackgroundPosition, BackgroundPosition),
+    ),
+    /// The offsets from the top, the right, the bottom and the left of the reference box, and
+    /// the radius of the corners (only one is supported)
+    Inset([Value; 4], Value),
+    /// The vertices
+    Polygon(FillRule, Vec<(Value, Value)>),
+}
+
+/// A shape placed in the document (CSS px)
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Ellipse {
+        center: (f64, f64),
+        radius: (f64, f64),
+    },
+    /// A rect (x, y, width, height) with round corners
+    Inset {
+        rect: (f64, f64, f64, f64),
+        radius: f64,
+    },
+    Polygon(FillRule, Vec<(f64, f64)>),
+}
+
+impl BasicShape {
+    /// Parses a basic shape function, e.g. ``circle(50% at left)``. None if it is not one, or
+    /// is invalid.
+    pub fn parse(value: &Value) -> Option<BasicShape> {
+        let (name, args) = match value {
+            &Value::Function(ref name, ref args) => (name.as_str(), args),
+            _ => return None,
+        };
+        let at = Value::Keyword("at".to_string());
+        let (radii, center) = match args.iter().position(|arg| *arg == at) {
+            Some(i) => (&args[..i], parse_background_position(&args[i + 1..])?),
+            None => (&args[..], (center(), center())),
+        };
+        let radius = |value: &Value| match value {
+            &Value::Keyword(ref k) if k == "closest-side" => Some(ShapeRadius::ClosestSide),
+            &Value::Keyword(ref k) if k == "farthest-side" => Some(ShapeRadius::FarthestSide),
+            &Value::Length(_, _) | &Value::Num(_) => Some(ShapeRadius::Length(value.clone())),
+            _ => None,
+        };
+        match name {
+            "circle" => match radii {
+                [] => Some(BasicShape::Circle(ShapeRadius::ClosestSide, center)),
+                [r] => Some(BasicShape::Circle(radius(r)?, center)),
+                _ => None,
+            },
+            "ellipse" => match radii {
+                [] => Some(BasicShape::Ellipse(
+                    ShapeRadius::ClosestSide,
+                    ShapeRadius::ClosestSide,
+                    center,
+                )),
+                [rx, ry] => Some(BasicShape::Ellipse(radius(rx)?, radius(ry)?, center)),
+                _ => None,
+            },
+            "inset" => {
+                let round = Value::Keyword("round".to_string());
+                let (offsets, radius) = match args.iter().position(|arg| *arg == round) {
+                    Some(i) => (&args[..i], args.get(i + 1)?.clone()),
+                    None => (&args[..], Value::Length(0.0, Unit::Px)),
+                };
+                let offsets = match offsets {
+                    [a] => [a.clone(), a.clone(), a.clone(), a.clone()],
+                    [a, b] => [a.clone(), b.clone(), a.clone(), b.clone()],
+                    [a, b, c] => [a.clone(), b.clone(), c.clone(), b.clone()],
+                    [a, b, c, d] => [a.clone(), b.clone(), c.clone(), d.clone()],
+                    _ => return None,
+                };
+                Some(BasicShape::Inset(offsets, radius))
+            }
+            "polygon" => {
+                let (fill_rule, points) = match args.first() {
+                    Some(&Value::Keyword(ref k)) if k == "evenodd" => {
+                        (FillRule::EvenOdd, &args[1..])
+                    }
+                    Some(&Value::Keyword(ref k)) if k == "nonzero" => {
+                        (FillRule::NonZero, &args[1..])
+                    }
+                    _ => (FillRule::NonZero, &args[..]),
+                };
+                if points.len() < 6 || points.len() % 2 != 0 {
+                    return None;
+                }
+                Some(BasicShape::Polygon(
+                    fill_rule,
+                    points
+                        .chunks(2)
+                        .map(|point| (point[0].clone(), point[1].clone()))
+                        .collect(),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Places the shape in ``reference`` (in the coordinates of the document). Percentages are
+    /// of its size.
+    pub fn resolve(&self, reference: Rect) -> Shape {
+        let (x, y) = (reference.x.to_f64_px(), reference.y.to_f64_px());
+        let (width, height) = (reference.width.to_f64_px(), reference.height.to_f64_px());
+        let length = |value: &Value, size: f64| value.maybe_percent_to_px(size).unwrap_or(0.0);
+        let position = |position: &BackgroundPosition, size: f64| {
+            let offset = length(&position.offset, size);
+            if position.from_end {
+                size - offset
+            } else {
+                offset
+            }
+        };
+        let center = |center: &(BackgroundPosition, BackgroundPosition)| {
+            (x + position(&center.0, width), y + position(&center.1, height))
+        };
+        // The distances from the center to the closest and the farthest side in a direction
+        let sides = |center: f64, start: f64, size: f64| {
+            let (a, b) = ((center - start).abs(), (start + size - center).abs());
+            (a.min(b), a.max(b))
+        };
+        let radius = |radius: &ShapeRadius, sides: (f64, f64), size: f64| match radius {
+            &ShapeRadius::Length(ref value) => length(value, size).max(0.0),
+            &ShapeRadius::ClosestSide => sides.0,
+            &ShapeRadius::FarthestSide => sides.1,
+        };
+
+        match self {
+            &BasicShape::Circle(ref r, ref c) => {
+                let (cx, cy) = center(c);
+                let ((closest_x, farthest_x), (closest_y, farthest_y)) =
+                    (sides(cx, x, width), sides(cy, y, height));
+                let sides = (closest_x.min(closest_y), farthest_x.max(farthest_y));
+                // Percentages are of the diagonal divided by sqrt(2).
+                let r = radius(r, sides, width.hypot(height) / 2f64.sqrt());
+                Shape::Ellipse {
+                    center: (cx, cy),
+                    radius: (r, r),
+                }
+            }
+            &BasicShape::Ellipse(ref rx, ref ry, ref c) => {
+                let (cx, cy) = center(c);
+                Shape::Ellipse {
+                    center: (cx, cy),
+                    radius: (
+                        radius(rx, sides(cx, x, width), width),
+                        radius(ry, sides(cy, y, height), height),
+                    ),
+                }
+            }
+            &BasicShape::Inset(ref offsets, ref r) => {
+                let (top, right) = (length(&offsets[0], height), length(&offsets[1], width));
+                let (bottom, left) = (length(&offsets[2], height), length(&offsets[3], width));
+                let rect = (
+                    x + left,
+                    y + top,
+                    (width - left - right).max(0.0),
+                    (height - top - bottom).max(0.0),
+                );
+                Shape::Inset {
+                    rect: rect,
+                    radius: length(r, width).max(0.0).min(rect.2 / 2.0).min(rect.3 / 2.0),
+                }
+            }
+            &BasicShape::Polygon(fill_rule, ref points) => Shape::Polygon(
+                fill_rule,
+                points
+                    .iter()
+                    .map(|&(ref px, ref py)| (x + length(px, width), y + length(py, height)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl Shape {
+    /// The leftmost and the rightmost x of the shape between ``top`` and ``bottom``. None if it
+    /// is not there (or is empty).
+    pub fn horizontal_extent(&self, top: f64, bottom: f64) -> Option<(f64, f64)> {
+        match self {
+            &Shape::Ellipse {
+                center: (cx, cy),
+                radius: (rx, ry),
+            } => {
+                if rx <= 0.0 || ry <= 0.0 || bottom <= cy - ry || top >= cy + ry {
+                    return None;
+                }
+                // Widest where closest to the center
+                let dy = cy.max(top).min(bottom) - cy;
+                let half = rx * (1.0 - (dy / ry) * (dy / ry)).max(0.0).sqrt();
+                Some((cx - half, cx + half))
+            }
+            &Shape::Inset {
+                rect: (x, y, width, height),
+                radius,
+            } => {
+                if width <= 0.0 || height <= 0.0 || bottom <= y || top >= y + height {
+                    return None;
+                }
+                // How far the band is in a round corner
+                let dy = (y + radius - bottom).max(top - (y + height - radius)).max(0.0);
+                let indent = radius - (radius * radius - dy * dy).max(0.0).sqrt();
+                Some((x + indent, x + width - indent))
+            }
+            // The fill rule does not change the area a float wraps around.
+            &Shape::Polygon(_, ref points) => {
+                let mut extent: Option<(f64, f64)> = None;
+                let mut extend = |x: f64| {
+                    extent = Some(extent.map_or((x, x), |(min, max)| (min.min(x), max.max(x))));
+                };
+                for (i, &(x0, y0)) in points.iter().enumerate() {
+                    let (x1, y1) = points[(i + 1) % points.len()];
+                    // The part of the edge between ``top`` and ``bottom``
+                    let (low, high) = (y0.min(y1), y0.max(y1));
+                    if high < top || low > bottom {
+                        continue;
+                    }
+                    if y0 == y1 {
+                        extend(x0);
+                        extend(x1);
+                        continue;
+                    }
+                    let x_at = |y: f64| x0 + (x1 - x0) * (y - y0) / (y1 - y0);
+                    extend(x_at(low.max(top)));
+                    extend(x_at(high.min(bottom)));
+                }
+                extent
+            }
+        }
+    }
+}
+
+impl Shape {
+    /// Whether the point (``x``, ``y``) is in the shape.
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        match self {
+            &Shape::Ellipse {
+                center: (cx, cy),
+                radius: (rx, ry),
+            } => {
+                rx > 0.0 && ry > 0.0
+                    && ((x - cx) / rx).powi(2) + ((y - cy) / ry).powi(2) <= 1.0
+            }
+            &Shape::Inset { .. } => self
+                .horizontal_extent(y, y)
+                .is_some_and(|(left, right)| left <= x && x <= right),
+            &Shape::Polygon(fill_rule, ref points) => {
+                // How many times the edges wind around the point, counted along a ray to the
+                // right of it
+                let mut winding = 0;
+                let mut crossings = 0;
+                for (i, &(x0, y0)) in points.iter().enumerate() {
+                    let (x1, y1) = points[(i + 1) % points.len()];
+                    if (y0 <= y) == (y1 <= y) {
+                        continue;
+                    }
+                    if x0 + (x1 - x0) * (y - y0) / (y1 - y0) > x {
+                        crossings += 1;
+                        winding += if y1 > y0 { 1 } else { -1 };
+                    }
+                }
+                match fill_rule {
+                    FillRule::NonZero => winding != 0,
+                    FillRule::EvenOdd => crossings % 2 == 1,
+                }
+            }
+        }
+    }
+}
+
+/// The ``clip-path`` of ``layout_box`` placed in the document, where the content area of its
+/// parent is at (``x``, ``y``). None if it is not clipped.
+pub fn clip_path(layout_box: &LayoutBox, x: Au, y: Au) -> Option<Shape> {
+    let (shape, reference_box) = layout_box.property.clip_path()?;
+    Some(shape.resolve(reference_box.rect(layout_box.dimensions).add_parent_coordinate(x, y)))
+}
+
+/// Whether ``layout_box`` (placed like in ``clip_path``) and its descendants may be hit at
+/// (``px``, ``py``) in the document: it is not clipped out there.
+pub fn hits_clip_path(layout_box: &LayoutBox, x: Au, y: Au, px: f64, py: f64) -> bool {
+    clip_path(layout_box, x, y).is_none_or(|clip| clip.contains(px, py))
+}
+
+fn center() -> BackgroundPosition {
+    BackgroundPosition {
+        offset: Value::Length(50.0, Unit::Percent),
+        from_end: false,
+    }
+}
+
+#[test]
+fn test_shapes() {
+    use css;
+
+    let shape = |src: &str| {
+        let values = css::parse(format!("a {{ shape-outside: {} }}", src)).rules[0].declarations
+            [0]
+            .values
+            .clone();
+        BasicShape::parse(&values[0])
+    };
+    let px = Au::from_f64_px;
+    let reference = Rect {
+        x: px(10.0),
+        y: px(0.0),
+        width: px(100.0),
+        height: px(50.0),
+    };
+    let resolve = |src: &str| shape(src).unwrap().resolve(reference);
+
+    // Centered in the reference box, to its closest side by default
+    let circle = resolve("circle()");
+    assert_eq!(
+        circle,
+        Shape::Ellipse {
+            center: (60.0, 25.0),
+            radius: (25.0, 25.0),
+        }
+    );
+    assert_eq!(circle.horizontal_extent(20.0, 30.0), Some((35.0, 85.0)));
+    assert_eq!(circle.horizontal_extent(50.0, 60.0), None);
+    // 4px under the top of the circle: 2 * sqrt(25^2 - 21^2) wide
+    let (left, right) = circle.horizontal_extent(0.0, 4.0).unwrap();
+    assert!((right - left - 2.0 * (25.0f64 * 25.0 - 21.0 * 21.0).sqrt()).abs() < 1e-9);
+
+    assert_eq!(
+        resolve("circle(10px at left top)"),
+        Shape::Ellipse {
+            center: (10.0, 0.0),
+            radius: (10.0, 10.0),
+        }
+    );
+    assert_eq!(
+        resolve("ellipse(farthest-side 20% at 25% 10px)"),
+        Shape::Ellipse {
+            center: (35.0, 10.0),
+            radius: (75.0, 10.0),
+        }
+    );
+
+    let inset = resolve("inset(10px 20% round 5px)");
+    assert_eq!(
+        inset,
+        Shape::Inset {
+            rect: (30.0, 10.0, 60.0, 30.0),
+            radius: 5.0,
+        }
+    );
+    assert_eq!(inset.horizontal_extent(20.0, 21.0), Some((30.0, 90.0)));
+    // In the top corners
+    assert_eq!(inset.horizontal_extent(0.0, 10.0), None);
+    assert_eq!(inset.horizontal_extent(0.0, 11.0), Some((32.0, 88.0)));
+
+    // A triangle pointing right
+    let triangle = resolve("polygon(0 0, 100% 50%, 0 100%)");
+    assert_eq!(triangle.horizontal_extent(0.0, 10.0), Some((10.0, 50.0)));
+    assert_eq!(triangle.horizontal_extent(20.0, 30.0), Some((10.0, 110.0)));
+    assert_eq!(triangle.horizontal_extent(60.0, 70.0), None);
+    assert!(triangle.contains(20.0, 25.0));
+    assert!(!triangle.contains(100.0, 10.0));
+
+    assert!(circle.contains(60.0, 1.0) && !circle.contains(36.0, 1.0));
+    assert!(inset.contains(31.0, 20.0) && !inset.contains(30.5, 10.5));
+    // A star drawn with crossing edges, whose middle is only in it with ``nonzero``
+    let star = |fill_rule: &str| {
+        resolve(&format!(
+            "polygon({} 50% 0, 80% 100%, 0 30%, 100% 30%, 20% 100%)",
+            fill_rule
+        ))
+    };
+    assert!(star("").contains(60.0, 25.0));
+    assert!(star("nonzero,").contains(60.0, 25.0));
+    assert!(!star("evenodd,").contains(60.0, 25.0));
+    assert!(star("evenodd,").contains(60.0, 10.0));
+
+    assert_eq!(shape("circle(1px 2px)"), None);
+    assert_eq!(shape("unknown(1px)"), None);
+    assert_eq!(shape("polygon(0 0, 1px 1px)"), None);
+}
@@ -0,0 +1,240 @@
+//! Responsive images: the image an ``<img>`` loads is selected from the candidates of its
+//! ``srcset`` (or of the first ``<source>`` of its ``<picture>`` whose ``media`` matches) for the
+//! viewport width and the device pixel ratio, when it is laid out (see ``select_source``). The
+//! candidates of width descriptors are of the widths in ``sizes``.
+//! ref. https://html.spec.whatwg.org/multipage/images.html#selecting-an-image-source
+//!
+//! The image is as large as its natural size over the density of its candidate, e.g. an image of
+//! ``2x`` is half as large in CSS px.
+
+use dom::{Node, NodeType};
+use media;
+
+/// How a candidate is described
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Descriptor {
+    /// ``2x``: the density of the image (device px per CSS px)
+    Density(f64),
+    /// ``800w``: the width of the image (px), at the density of it over the source size
+    Width(f64),
+}
+
+/// An image of ``srcset``
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageCandidate {
+    pub url: String,
+    pub descriptor: Descriptor,
+}
+
+/// The image types the sources of ``type`` can be of
+const SUPPORTED_TYPES: [&str; 6] = [
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/bmp",
+    "image/x-icon",
+    "image/svg+xml",
+];
+
+/// Parses ``srcset``, e.g. ``a.png, b.png 2x, c.png 800w``. The candidates without a
+/// descriptor are of ``1x``; those with invalid or several ones are dropped.
+pub fn parse_srcset(srcset: &str) -> Vec<ImageCandidate> {
+    let mut candidates = vec![];
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        if rest.is_empty() {
+            return candidates;
+        }
+        let url_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let mut url = &rest[..url_end];
+        rest = &rest[url_end..];
+        // The descriptors are up to the next comma, unless the URL ends with one.
+        let descriptors = if url.ends_with(',') {
+            url = url.trim_end_matches(',');
+            ""
+        } else {
+            let end = rest.find(',').unwrap_or(rest.len());
+            let descriptors = &rest[..end];
+            rest = &rest[end..];
+            descriptors
+        };
+
+        let descriptors: Vec<&str> = descriptors.split_whitespace().collect();
+        let descriptor = match descriptors.as_slice() {
+            [] => Some(Descriptor::Density(1.0)),
+            [descriptor] => parse_descriptor(descriptor),
+            _ => None,
+        };
+        if let Some(descriptor) = descriptor {
+            candidates.push(ImageCandidate {
+                url: url.to_string(),
+                descriptor: descriptor,
+            });
+        }
+    }
+}
+
+fn parse_descriptor(descriptor: &str) -> Option<Descriptor> {
+    let (number, unit) = descriptor.split_at(descriptor.len().checked_sub(1)?);
+    let number: f64 = number.parse().ok()?;
+    match unit {
+        "x" if number >= 0.0 => Some(Descriptor::Density(number)),
+        "w" if number > 0.0 && number.fract() == 0.0 => Some(Descriptor::Width(number)),
+        _ => None,
+    }
+}
+
+/// ``srcset`` of ``candidates``, which is parsed back to them
+pub fn serialize_srcset(candidates: &[ImageCandidate]) -> String {
+    candidates
+        .iter()
+        .map(|candidate| match candidate.descriptor {
+            Descriptor::Density(density) => format!("{} {}x", candidate.url, density),
+            Descriptor::Width(width) => format!("{} {}w", candidate.url, width),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The width (CSS px) the image is shown at, of the first size in ``sizes`` whose media condition
+/// matches (e.g. ``(max-width: 600px) 100vw, 50vw``). The viewport width by default.
+pub fn source_size(sizes: Option<&str>) -> f64 {
+    sizes
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|size| {
+            let size = size.trim();
+            let length_start = size.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+            let (condition, length) = size.split_at(length_start);
+            let length = media::parse_length(length).filter(|&length| length >= 0.0)?;
+            let condition = condition.trim();
+            let matches = condition.is_empty()
+                || media::matches(&media::parse_query_list(condition));
+            if matches {
+                Some(length)
+            } else {
+                None
+            }
+        })
+        .next()
+        .unwrap_or_else(media::viewport_width)
+}
+
+/// The URL of the image ``img`` loads now, and its density. ``sources`` are the ``<source>``s
+/// before it in its ``<picture>``. None if it has none.
+pub fn select_source(img: &Node, sources: &[Node]) -> Option<(String, f64)> {
+    let attrs = |node: &Node, name: &str| match node.data {
+        NodeType::Element(ref e) => e.attrs.get(name).cloned(),
+        NodeType::Text(_) => None,
+    };
+    let source = sources.iter().find(|source| {
+        attrs(source, "srcset").is_some()
+            && attrs(source, "media")
+                .is_none_or(|media| media::matches(&media::parse_query_list(&media)))
+            && attrs(source, "type").is_none_or(|image_type| {
+                SUPPORTED_TYPES.contains(&image_type.trim().to_lowercase().as_str())
+            })
+    });
+
+    let (candidates, sizes) = match source {
+        Some(source) => (
+            parse_srcset(&attrs(source, "srcset").unwrap_or_default()),
+            attrs(source, "sizes"),
+        ),
+        None => {
+            let mut candidates = parse_srcset(&attrs(img, "srcset").unwrap_or_default());
+            // ``src`` is of ``1x`` unless another one is.
+            let described = candidates.iter().any(|candidate| match candidate.descriptor {
+                Descriptor::Density(density) => density == 1.0,
+                Descriptor::Width(_) => true,
+            });
+            match attrs(img, "src") {
+                Some(src) if !src.is_empty() && !described => {
+                    candidates.push(ImageCandidate {
+                        url: src,
+                        descriptor: Descriptor::Density(1.0),
+                    })
+                }
+                _ => {}
+            }
+            (candidates, attrs(img, "sizes"))
+        }
+    };
+
+    let source_size = source_size(sizes.as_deref());
+    let mut densities: Vec<(String, f64)> = candidates
+        .into_iter()
+        .map(|candidate| match candidate.descriptor {
+            Descriptor::Density(density) => (candidate.url, density),
+            Descriptor::Width(width) => (candidate.url, width / source_size),
+        })
+        .filter(|&(_, density)| density > 0.0 && density.is_finite())
+        .collect();
+    densities.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+    // The least dense image that is dense enough for the display, or the densest one
+    let ratio = media::device_pixel_ratio();
+    match densities.iter().position(|&(_, density)| density >= ratio) {
+        Some(i) => Some(densities.swap_remove(i)),
+        None => densities.pop(),
+    }
+}
+
+#[test]
+fn test_parse_srcset() {
+    let candidate = |url: &str, descriptor| ImageCandidate {
+        url: url.to_string(),
+        descriptor: descriptor,
+    };
+    let candidates = parse_srcset(" a.png, b.png 2x,c.png 800w , data:, e.png 1x 2x, f.png 0w,,");
+    assert_eq!(
+        candidates,
+        vec![
+            candidate("a.png", Descriptor::Density(1.0)),
+            candidate("b.png", Descriptor::Density(2.0)),
+            candidate("c.png", Descriptor::Width(800.0)),
+            candidate("data:", Descriptor::Density(1.0)),
+        ]
+    );
+    assert_eq!(
+        serialize_srcset(&candidates),
+        "a.png 1x, b.png 2x, c.png 800w, data: 1x"
+    );
+    assert_eq!(parse_srcset(&serialize_srcset(&candidates)), candidates);
+}
+
+#[test]
+fn test_select_source() {
+    use html;
+    use network::Url;
+
+    let picture = html::parse(
+        "<picture><source srcset=\"wide.png\" media=\"(min-width: 1000px)\">\
+         <source srcset=\"a.webp\" type=\"image/webp\">\
+         <img src=\"a.png\" srcset=\"a-400.png 400w, a-800.png 800w\" sizes=\"(max-width: 600px) \
+         100vw, 50vw\"></picture>"
+            .to_string(),
+        Url::parse("file:///a/").unwrap(),
+    );
+    let (sources, img) = picture.children.split_at(2);
+    let select = |viewport_width, ratio| {
+        media::set_viewport_width(viewport_width);
+        media::set_device_pixel_ratio(ratio);
+        select_source(&img[0], sources).unwrap()
+    };
+
+    // Of 400 CSS px, half the viewport
+    assert_eq!(select(800.0, 1.0), ("file:///a/a-400.png".to_string(), 1.0));
+    assert_eq!(select(800.0, 2.0), ("file:///a/a-800.png".to_string(), 2.0));
+    assert_eq!(select(800.0, 3.0), ("file:///a/a-800.png".to_string(), 2.0));
+    // The whole viewport
+    assert_eq!(select(500.0, 1.0), ("file:///a/a-800.png".to_string(), 1.6));
+    assert_eq!(select(1200.0, 1.0), ("file:///a/wide.png".to_string(), 1.0));
+    media::set_viewport_width(0.0);
+    media::set_device_pixel_ratio(1.0);
+
+    // ``src`` alone is of ``1x``.
+    let img = html::parse("<img src=\"a.png\">".to_string(), Url::parse("file:///").unwrap());
+    assert_eq!(select_source(&img, &[]), Some(("file:///a.png".to_string(), 1.0)));
+}
@@ -0,0 +1,175 @@
+//! Timing counters for the phases of the pipeline. Each phase records how long it took the
+//! last time and in total (since ``reset``).
+
+use trace;
+
+use std::sync::Mutex;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    /// Parsing HTML and CSS (including the stylesheets loaded in the background)
+    Parse,
+    /// Matching the rules and building the layout tree
+    Style,
+    Layout,
+    /// Building the display list
+    Paint,
+    /// Drawing the display list into a surface
+    Raster,
+}
+
+pub const PHASES: [Phase; 5] = [
+    Phase::Parse,
+    Phase::Style,
+    Phase::Layout,
+    Phase::Paint,
+    Phase::Raster,
+];
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Parse => "parse",
+            Phase::Style => "style",
+            Phase::Layout => "layout",
+            Phase::Paint => "paint",
+            Phase::Raster => "raster",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PhaseStats {
+    /// The number of times the phase ran
+    pub runs: u32,
+    pub last: Duration,
+    pub total: Duration,
+}
+
+impl PhaseStats {
+    pub fn average(&self) -> Duration {
+        if self.runs == 0 {
+            Duration::default()
+        } else {
+            self.total / self.runs
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PipelineStats {
+    pub parse: PhaseStats,
+    pub style: PhaseStats,
+    pub layout: PhaseStats,
+    pub paint: PhaseStats,
+    pub raster: PhaseStats,
+}
+
+impl PipelineStats {
+    pub fn phase(&self, phase: Phase) -> &PhaseStats {
+        match phase {
+            Phase::Parse => &self.parse,
+            Phase::Style => &self.style,
+            Phase::Layout => &self.layout,
+            Phase::Paint => &self.paint,
+            Phase::Raster => &self.raster,
+        }
+    }
+
+    fn phase_mut(&mut self, phase: Phase) -> &mut PhaseStats {
+        match phase {
+            Phase::Parse => &mut self.parse,
+            Phase::Style => &mut self.style,
+            Phase::Layout => &mut self.layout,
+            Phase::Paint => &mut self.paint,
+            Phase::Raster => &mut self.raster,
+        }
+    }
+
+    pub fn record(&mut self, phase: Phase, duration: Duration) {
+        let stats = self.phase_mut(phase);
+        stats.runs += 1;
+        stats.last = duration;
+        stats.total += duration;
+    }
+
+    /// One line a phase, e.g. ``layout   1.25 ms (avg 1.10 ms, 3 runs)``.
+    pub fn summary(&self) -> Vec<String> {
+        PHASES
+            .iter()
+            .map(|&phase| {
+                let stats = self.phase(phase);
+                format!(
+                    "{:<7}{:>7.2} ms (avg {:.2} ms, {} runs)",
+                    phase.name(),
+                    millis(stats.last),
+                    millis(stats.average()),
+                    stats.runs
+                )
+            })
+            .collect()
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+// Shared with the threads loading stylesheets in the background
+static STATS: Mutex<Option<PipelineStats>> = Mutex::new(None);
+
+/// The counters so far.
+pub fn stats() -> PipelineStats {
+    STATS.lock().unwrap().clone().unwrap_or_default()
+}
+
+pub fn reset() {
+    *STATS.lock().unwrap() = None;
+}
+
+pub fn record(phase: Phase, duration: Duration) {
+    STATS
+        .lock()
+        .unwrap()
+        .get_or_insert_with(PipelineStats::default)
+        .record(phase, duration);
+}
+
+/// Runs ``f`` as ``phase`` (in a span of it, see ``trace``).
+#[cfg(not(target_arch = "wasm32"))]
+pub fn time<T, F: FnOnce() -> T>(phase: Phase, f: F) -> T {
+    let _span = trace::pipeline_span(phase.name());
+    let start = Instant::now();
+    let result = f();
+    record(phase, start.elapsed());
+    result
+}
+
+/// There is no clock on wasm32 (``Instant::now`` panics), so ``phase`` is not timed.
+#[cfg(target_arch = "wasm32")]
+pub fn time<T, F: FnOnce() -> T>(_phase: Phase, f: F) -> T {
+    f()
+}
+
+#[test]
+fn test_pipeline_stats() {
+    let mut stats = PipelineStats::default();
+    assert_eq!(stats.layout.average(), Duration::default());
+
+    stats.record(Phase::Layout, Duration::from_millis(3));
+    stats.record(Phase::Layout, Duration::from_millis(1));
+    assert_eq!(
+        *stats.phase(Phase::Layout),
+        PhaseStats {
+            runs: 2,
+            last: Duration::from_millis(1),
+            total: Duration::from_millis(4),
+        }
+    );
+    assert_eq!(stats.layout.average(), Duration::from_millis(2));
+    assert_eq!(stats.paint.runs, 0);
+    assert_eq!(stats.summary()[2], "layout    1.00 ms (avg 2.00 ms, 2 runs)");
+}
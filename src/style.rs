@@ -1,5 +1,11 @@
-use css::{Color, TextDecoration, Unit, Value, pt2px};
-use font::{FontSlant, FontWeight};
+use css::{Color, TextDecoration, Unit, Value, pt2px, BLACK};
+use font::{Font, FontFamily, FontFeatures, FontLanguage, FontSlant, FontWeight, TabSize,
+           DEFAULT_TAB_SIZE};
+use hyphenation::{Hyphenator, Hyphens};
+use shape::{BasicShape, ReferenceBox};
+use grid::GridLine;
+use filter::Filter;
+use media::{self, ColorScheme};
 
 use std::collections::HashMap;
 
@@ -41,6 +47,54 @@ pub struct CachedProperties {
     line_height: Option<Value>,
 }
 
+impl TextTransform {
+    /// The text in ``lang`` to lay out and paint. The length may change (e.g. "ß" becomes "SS").
+    /// In Turkish and Azerbaijani, "i" and "ı" are the lowercase of "İ" and "I".
+    pub fn apply(&self, text: &str, lang: Option<&str>) -> String {
+        let turkic = lang.is_some_and(|lang| {
+            let primary = lang.split(['-', '_']).next().unwrap_or_default();
+            primary.eq_ignore_ascii_case("tr") || primary.eq_ignore_ascii_case("az")
+        });
+        let uppercase = |c: char, s: &mut String| match c {
+            'i' if turkic => s.push('İ'),
+            c => s.extend(c.to_uppercase()),
+        };
+        match *self {
+            TextTransform::None => text.to_string(),
+            TextTransform::Uppercase if !turkic => text.to_uppercase(),
+            TextTransform::Uppercase => {
+                let mut uppercased = String::with_capacity(text.len());
+                text.chars().for_each(|c| uppercase(c, &mut uppercased));
+                uppercased
+            }
+            TextTransform::Lowercase if !turkic => text.to_lowercase(),
+            TextTransform::Lowercase => text.chars()
+                .map(|c| match c {
+                    'I' => 'ı',
+                    'İ' => 'i',
+                    c => c,
+                })
+                .collect::<String>()
+                .to_lowercase(),
+            TextTransform::Capitalize => {
+                let mut capitalized = String::with_capacity(text.len());
+                let mut word_start = true;
+                for c in text.chars() {
+                    if word_start && c.is_alphanumeric() {
+                        uppercase(c, &mut capitalized);
+                    } else {
+                        capitalized.push(c);
+                    }
+                    // Punctuation before a word (e.g. "(") is skipped, but not in a word
+                    // (e.g. "don't").
+                    word_start = c.is_whitespace() || (word_start && !c.is_alphanumeric());
+                }
+                capitalized
+            }
+        }
+    }
+}
+
 impl CachedProperties {
     pub fn new() -> CachedProperties {
         CachedProperties {
@@ -59,9 +113,20 @@ pub enum Display {
     Inline,
     Block,
     InlineBlock,
+    /// A block whose children are laid out as flex items (see ``flex``)
+    Flex,
+    /// A block whose children are laid out in a grid (see ``grid``)
+    Grid,
     None,
 }
 
+impl Display {
+    /// Whether the children of the box are flex or grid items, which are blocks.
+    pub fn is_flex_or_grid(&self) -> bool {
+        *self == Display::Flex || *self == Display::Grid
+    }
+}
+
 #[derive(Clone, PartialEq, Debug, Copy)]
 pub enum FloatType {
     Left,
@@ -76,8 +141,284 @@ pub enum ClearType {
     Both,
 }
 
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub struct Outline {
+    pub width: Au,
+    pub color: Color,
+    pub offset: Au,
+}
+
+/// The pointer shown over the element. ref. https://www.w3.org/TR/css-ui-3/#cursor
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum Cursor {
+    /// Chosen by the context (e.g. the I-beam over text).
+    Auto,
+    Default,
+    None,
+    Pointer,
+    Text,
+    Wait,
+    Progress,
+    Help,
+    Crosshair,
+    Move,
+    NotAllowed,
+    ColResize,
+    RowResize,
+}
+
+/// ``direction``: the base direction of the text, e.g. from ``dir``
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum Direction {
+    Ltr,
+    Rtl,
+}
+
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum TextTransform {
+    None,
+    Uppercase,
+    Lowercase,
+    /// The first letter of each word is uppercased.
+    Capitalize,
+}
+
+/// How a background image is repeated in one direction.
+/// ref. https://www.w3.org/TR/css-backgrounds-3/#background-repeat
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum BackgroundRepeat {
+    Repeat,
+    /// Repeated as often as it fits without being clipped, with the space left between them.
+    Space,
+    /// Repeated, and scaled so that it fits a whole number of times.
+    Round,
+    NoRepeat,
+}
+
+/// How the edges and the middle of a border image fill their part of the border area in one
+/// direction. ref. https://www.w3.org/TR/css-backgrounds-3/#border-image-repeat
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum BorderImageRepeat {
+    Stretch,
+    /// Repeated from the center, clipped at the ends
+    Repeat,
+    /// Repeated, and scaled so that it fits a whole number of times
+    Round,
+    /// Repeated as often as it fits without being clipped, with the space left around them
+    Space,
+}
+
+/// ``background-size``. ref. https://www.w3.org/TR/css-backgrounds-3/#background-size
+#[derive(Clone, PartialEq, Debug)]
+pub enum BackgroundSize {
+    /// The width and the height (a length or a percentage of the positioning area). None is
+    /// ``auto``.
+    Explicit(Option<Value>, Option<Value>),
+    Cover,
+    Contain,
+}
+
+/// Where a background image is in one direction: ``offset`` (a length, or a percentage of the
+/// space left by the image) from the start (left or top), or from the end if ``from_end``.
+#[derive(Clone, PartialEq, Debug)]
+pub struct BackgroundPosition {
+    pub offset: Value,
+    pub from_end: bool,
+}
+
+impl BackgroundPosition {
+    fn percent(percent: f64) -> BackgroundPosition {
+        BackgroundPosition {
+            offset: Value::Length(percent, Unit::Percent),
+            from_end: false,
+        }
+    }
+}
+
+/// ``object-fit``: how the content of a replaced element (e.g. the image of an ``<img>``) is
+/// scaled in its content box. ref. https://www.w3.org/TR/css-images-3/#the-object-fit
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum ObjectFit {
+    /// Stretched to the box (the initial value)
+    Fill,
+    /// As large as it fits in the box, keeping its ratio
+    Contain,
+    /// As small as it covers the box, keeping its ratio
+    Cover,
+    /// Not scaled
+    None,
+    /// Like ``None`` or ``Contain``, whichever is smaller
+    ScaleDown,
+}
+
+/// ``contain``: the parts of the rendering of a box and its descendants that are isolated from
+/// the rest of the document. ref. https://www.w3.org/TR/css-contain-2/#contain-property
+#[derive(Clone, PartialEq, Debug, Copy, Default)]
+pub struct Containment {
+    /// The box is sized as if it were empty.
+    pub size: bool,
+    /// The floats inside do not affect the outside, nor those outside the inside.
+    pub layout: bool,
+    /// The descendants are clipped to the padding box.
+    pub paint: bool,
+    /// The counters and the quotes of the subtree do not affect the rest of the document.
+    pub style: bool,
+}
+
+/// ``scrollbar-width``
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum ScrollbarWidth {
+    Auto,
+    Thin,
+    /// No scrollbar is shown, but the box still scrolls.
+    None,
+}
+
+/// ``content-visibility``: whether the contents of a box are laid out and painted.
+/// ref. https://www.w3.org/TR/css-contain-2/#content-visibility
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum ContentVisibility {
+    Visible,
+    /// Skipped while the box is far from the part of the document shown
+    Auto,
+    /// Always skipped
+    Hidden,
+}
+
+/// ``overflow`` in one direction: what is done with the content that overflows the padding box.
+/// ref. https://www.w3.org/TR/css-overflow-3/#overflow-properties
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum Overflow {
+    Visible,
+    /// Clipped, and scrolled only by scripts
+    Hidden,
+    /// Clipped, and never scrolled
+    Clip,
+    /// Clipped, and scrolled by the user with a scrollbar always shown
+    Scroll,
+    /// Like ``scroll``, with the scrollbar shown only if the content overflows
+    Auto,
+}
+
+impl Overflow {
+    /// Whether the content is not painted out of the padding box.
+    pub fn clips(self) -> bool {
+        self != Overflow::Visible
+    }
+
+    /// Whether the box is a scroll container in this direction.
+    pub fn scrolls(self) -> bool {
+        match self {
+            Overflow::Hidden | Overflow::Scroll | Overflow::Auto => true,
+            Overflow::Visible | Overflow::Clip => false,
+        }
+    }
+
+    /// Whether the user scrolls it (with the wheel).
+    pub fn user_scrolls(self) -> bool {
+        self == Overflow::Scroll || self == Overflow::Auto
+    }
+}
+
+/// ``flex-direction``: the main axis of a flex container, along which its items are placed
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum FlexDirection {
+    Row,
+    /// From the right to the left
+    RowReverse,
+    Column,
+    /// From the bottom up
+    ColumnReverse,
+}
+
+impl FlexDirection {
+    /// Whether the main axis is vertical.
+    pub fn is_column(self) -> bool {
+        self == FlexDirection::Column || self == FlexDirection::ColumnReverse
+    }
+
+    /// Whether the items are placed from the end of the main axis.
+    pub fn is_reverse(self) -> bool {
+        self == FlexDirection::RowReverse || self == FlexDirection::ColumnReverse
+    }
+}
+
+/// ``flex-wrap``: whether the items of a flex container are broken into lines
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum FlexWrap {
+    NoWrap,
+    Wrap,
+    /// The lines from the end of the cross axis (the bottom, or the right of columns)
+    WrapReverse,
+}
+
+/// How the lines of a flex container (``align-content``), or the items in a line
+/// (``justify-content``), are placed in the space they leave.
+/// ref. https://www.w3.org/TR/css-align-3/#content-distribution
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum ContentDistribution {
+    /// At the start (``flex-start`` or ``start``, or ``normal`` of ``justify-content``)
+    Start,
+    End,
+    Center,
+    /// The first at the start and the last at the end, with the same space between them
+    SpaceBetween,
+    /// With the same space on both sides of each
+    SpaceAround,
+    /// With the same space between them and at the ends
+    SpaceEvenly,
+    /// Each grown by the same part of the space (``normal`` of ``align-content``). The items in
+    /// a line are at the start.
+    Stretch,
+}
+
+/// How an item is placed in the height of its flex line (``align-items`` and ``align-self``).
+/// ref. https://www.w3.org/TR/css-align-3/#self-alignment
+#[derive(Clone, PartialEq, Debug, Copy)]
+pub enum ItemAlignment {
+    /// As high as the line if its height is ``auto``, else at the top (``normal``)
+    Stretch,
+    /// At the top (``flex-start``, ``start`` or ``self-start``)
+    Start,
+    End,
+    Center,
+    /// With the baseline of its first line on that of the others
+    Baseline,
+}
+
+/// Not CSS. The ``lang`` attribute of the element (or its ancestor), inherited as a property.
+pub const LANG_PROPERTY: &'static str = "-x-lang";
+
 pub const DEFAULT_FONT_SIZE: f64 = 16.0f64;
 pub const DEFAULT_LINE_HEIGHT_SCALE: f64 = 1.2f64;
+/// The ratio of ``larger`` and ``smaller`` in ``font-size``
+pub const FONT_SIZE_SCALE: f64 = 1.2f64;
+
+/// ``font-size`` in px. Keywords are scaled from the default (``medium``), and relative sizes
+/// from the parent's. Invalid values are ignored (the size is inherited).
+/// ref. https://www.w3.org/TR/css-fonts-4/#absolute-size-mapping
+pub fn computed_font_size(font_size: &Value, parent_font_size: f64) -> f64 {
+    match *font_size {
+        Value::Keyword(ref k) => match k.as_str() {
+            "xx-small" => DEFAULT_FONT_SIZE * 3.0 / 5.0,
+            "x-small" => DEFAULT_FONT_SIZE * 3.0 / 4.0,
+            "small" => DEFAULT_FONT_SIZE * 8.0 / 9.0,
+            "medium" => DEFAULT_FONT_SIZE,
+            "large" => DEFAULT_FONT_SIZE * 6.0 / 5.0,
+            "x-large" => DEFAULT_FONT_SIZE * 3.0 / 2.0,
+            "xx-large" => DEFAULT_FONT_SIZE * 2.0,
+            "xxx-large" => DEFAULT_FONT_SIZE * 3.0,
+            "larger" => parent_font_size * FONT_SIZE_SCALE,
+            "smaller" => parent_font_size / FONT_SIZE_SCALE,
+            _ => parent_font_size,
+        },
+        Value::Length(f, _) if f < 0.0 => parent_font_size,
+        Value::Length(f, Unit::Percent) => parent_font_size * f / 100.0,
+        Value::Length(f, Unit::Em) => parent_font_size * f,
+        Value::Length(_, _) => font_size.to_px().unwrap_or(parent_font_size),
+        _ => parent_font_size,
+    }
+}
 
 impl Style {
     pub fn value(&self, name: &str) -> Option<Vec<Value>> {
@@ -103,6 +444,8 @@ impl Style {
                 Value::Keyword(ref s) => match &**s {
                     "block" => Display::Block,
                     "inline-block" => Display::InlineBlock,
+                    "flex" => Display::Flex,
+                    "grid" => Display::Grid,
                     "none" => Display::None,
                     "inline" | _ => Display::Inline,
                 },
@@ -142,6 +485,224 @@ impl Style {
         }
     }
 
+    /// ``row-gap`` and ``column-gap`` (or the one or two values of ``gap``; ``grid-row-gap``,
+    /// ``grid-column-gap`` and ``grid-gap`` are their old names) between the rows and the
+    /// columns of a flex or grid container whose content box is ``width`` x ``height``. The
+    /// percentages of an indefinite (negative) size, and ``normal``, are 0.
+    pub fn gap(&self, width: Au, height: Au) -> (Au, Au) {
+        let shorthand = self.lookup_without_default("gap", "grid-gap").unwrap_or_default();
+        let gap = |name: &str, old_name: &str, i: usize, basis: Au| {
+            let value = self.lookup_without_default(name, old_name)
+                .and_then(|values| values.first().cloned())
+                .or_else(|| shorthand.get(i).or_else(|| shorthand.first()).cloned());
+            Au::from_f64_px(match value {
+                Some(Value::Length(_, Unit::Percent)) if basis < Au(0) => 0.0,
+                Some(value) => value.maybe_percent_to_px(basis.to_f64_px()).unwrap_or(0.0).max(0.0),
+                None => 0.0,
+            })
+        };
+        (
+            gap("row-gap", "grid-row-gap", 0, height),
+            gap("column-gap", "grid-column-gap", 1, width),
+        )
+    }
+
+    /// ``flex-grow``, ``flex-shrink`` and ``flex-basis`` (or those of ``flex``), 0, 1 and
+    /// ``auto`` by default. ``flex: none`` is ``0 0 auto``, ``flex: auto`` is ``1 1 auto``, and
+    /// the basis is 0 if only the factors are given.
+    /// ref. https://www.w3.org/TR/css-flexbox-1/#flex-property
+    pub fn flex(&self) -> (f64, f64, Value) {
+        let auto = Value::Keyword("auto".to_string());
+        let (mut grow, mut shrink, mut basis) = (0.0, 1.0, auto.clone());
+        let shorthand = self.value("flex").unwrap_or_default();
+        match shorthand.first() {
+            Some(&Value::Keyword(ref k)) if k == "none" => shrink = 0.0,
+            Some(&Value::Keyword(ref k)) if k == "auto" => grow = 1.0,
+            Some(&Value::Keyword(ref k)) if k == "initial" => {}
+            Some(_) => {
+                let mut factors = vec![];
+                let mut specified_basis = None;
+                for value in &shorthand {
+                    match *value {
+                        Value::Num(n) if factors.len() < 2 => factors.push(n.max(0.0)),
+                        Value::Num(n) => specified_basis = Some(Value::Length(n, Unit::Px)),
+                        ref value => specified_basis = Some(value.clone()),
+                    }
+                }
+                grow = factors.first().cloned().unwrap_or(1.0);
+                shrink = factors.get(1).cloned().unwrap_or(1.0);
+                basis = specified_basis.unwrap_or(Value::Length(0.0, Unit::Px));
+            }
+            None => {}
+        }
+
+        let factor = |name: &str| match self.value(name).as_ref().and_then(|v| v.first()) {
+            Some(&Value::Num(n)) => Some(n.max(0.0)),
+            _ => None,
+        };
+        (
+            factor("flex-grow").unwrap_or(grow),
+            factor("flex-shrink").unwrap_or(shrink),
+            self.value("flex-basis")
+                .and_then(|values| values.first().cloned())
+                .unwrap_or(basis),
+        )
+    }
+
+    /// ``flex-direction`` (or the keyword of it in ``flex-flow``), ``row`` by default
+    pub fn flex_direction(&self) -> FlexDirection {
+        let values = self.lookup_without_default("flex-direction", "flex-flow")
+            .unwrap_or_default();
+        values
+            .iter()
+            .find_map(|value| match *value {
+                Value::Keyword(ref k) if k == "row" => Some(FlexDirection::Row),
+                Value::Keyword(ref k) if k == "row-reverse" => Some(FlexDirection::RowReverse),
+                Value::Keyword(ref k) if k == "column" => Some(FlexDirection::Column),
+                Value::Keyword(ref k) if k == "column-reverse" => {
+                    Some(FlexDirection::ColumnReverse)
+                }
+                _ => None,
+            })
+            .unwrap_or(FlexDirection::Row)
+    }
+
+    /// ``order``, where the item is placed among the items of its flex container, 0 by default
+    pub fn order(&self) -> i32 {
+        match self.value("order").as_ref().and_then(|values| values.first()) {
+            Some(&Value::Num(n)) => n as i32,
+            _ => 0,
+        }
+    }
+
+    /// ``flex-wrap`` (or the keyword of it in ``flex-flow``), ``nowrap`` by default
+    pub fn flex_wrap(&self) -> FlexWrap {
+        let values = self.lookup_without_default("flex-wrap", "flex-flow").unwrap_or_default();
+        values
+            .iter()
+            .find_map(|value| match *value {
+                Value::Keyword(ref k) if k == "nowrap" => Some(FlexWrap::NoWrap),
+                Value::Keyword(ref k) if k == "wrap" => Some(FlexWrap::Wrap),
+                Value::Keyword(ref k) if k == "wrap-reverse" => Some(FlexWrap::WrapReverse),
+                _ => None,
+            })
+            .unwrap_or(FlexWrap::NoWrap)
+    }
+
+    /// ``align-content``, ``normal`` (``stretch``) by default. The baselines are the start.
+    pub fn align_content(&self) -> ContentDistribution {
+        self.content_distribution("align-content", ContentDistribution::Stretch)
+    }
+
+    /// ``justify-content``, ``normal`` (the start) by default
+    pub fn justify_content(&self) -> ContentDistribution {
+        self.content_distribution("justify-content", ContentDistribution::Start)
+    }
+
+    /// ``align-items``, ``normal`` (``stretch``) by default
+    pub fn align_items(&self) -> ItemAlignment {
+        self.item_alignment("align-items").unwrap_or(ItemAlignment::Stretch)
+    }
+
+    /// ``align-self``, None for ``auto`` (the default): the ``align-items`` of the container
+    pub fn align_self(&self) -> Option<ItemAlignment> {
+        self.item_alignment("align-self")
+    }
+
+    /// The keyword of an alignment property ``name``, without the fallback alignment (``safe``
+    /// or ``unsafe``, which is ignored) and the preference of ``baseline`` (``first`` or
+    /// ``last``)
+    fn alignment_keyword(&self, name: &str) -> Option<String> {
+        self.value(name)?
+            .into_iter()
+            .rev()
+            .find_map(|value| match value {
+                Value::Keyword(k) => Some(k),
+                _ => None,
+            })
+    }
+
+    /// The ``ContentDistribution`` of ``name``, ``normal`` by default
+    fn content_distribution(
+        &self,
+        name: &str,
+        normal: ContentDistribution,
+    ) -> ContentDistribution {
+        match self.alignment_keyword(name).as_ref().map(String::as_str) {
+            Some("flex-start") | Some("start") | Some("left") | Some("baseline") => {
+                ContentDistribution::Start
+            }
+            Some("flex-end") | Some("end") | Some("right") => ContentDistribution::End,
+            Some("center") => ContentDistribution::Center,
+            Some("space-between") => ContentDistribution::SpaceBetween,
+            Some("space-around") => ContentDistribution::SpaceAround,
+            Some("space-evenly") => ContentDistribution::SpaceEvenly,
+            Some("stretch") => ContentDistribution::Stretch,
+            _ => normal,
+        }
+    }
+
+    /// The ``ItemAlignment`` of ``name``, None for ``auto``
+    fn item_alignment(&self, name: &str) -> Option<ItemAlignment> {
+        match self.alignment_keyword(name).as_ref().map(String::as_str) {
+            Some("normal") | Some("stretch") => Some(ItemAlignment::Stretch),
+            Some("flex-start") | Some("start") | Some("self-start") => Some(ItemAlignment::Start),
+            Some("flex-end") | Some("end") | Some("self-end") => Some(ItemAlignment::End),
+            Some("center") => Some(ItemAlignment::Center),
+            Some("baseline") => Some(ItemAlignment::Baseline),
+            _ => None,
+        }
+    }
+
+    /// Where a grid item starts and ends in the columns (``axis`` is ``"column"``) or in the
+    /// rows (``"row"``): ``grid-column-start`` and ``grid-column-end`` (or the two parts of
+    /// ``grid-column``, split by a ``/``, or of ``grid-area``), ``auto`` by default.
+    pub fn grid_placement(&self, axis: &str) -> (GridLine, GridLine) {
+        // ``grid-area: row-start / column-start / row-end / column-end``, the lines omitted after
+        // a name of that name
+        let area = self.value("grid-area").unwrap_or_default();
+        let area: Vec<&[Value]> = area.split(|value| *value == Value::Slash).collect();
+        let area_part = |i: usize| -> Vec<Value> {
+            if let Some(part) = area.get(i) {
+                return part.to_vec();
+            }
+            // The ``column-end`` omitted is the ``column-start``, the others the ``row-start``.
+            let copied = if i == 3 && area.len() > 1 { 1 } else { 0 };
+            match GridLine::parse(area[copied]) {
+                GridLine::Named(name, _) => vec![Value::Keyword(name)],
+                _ => vec![],
+            }
+        };
+        let (area_start, area_end) = match axis {
+            "row" => (area_part(0), area_part(2)),
+            _ => (area_part(1), area_part(3)),
+        };
+
+        let shorthand = self.value(&format!("grid-{}", axis));
+        let (start, end) = match shorthand {
+            Some(ref shorthand) => {
+                let mut parts = shorthand.split(|value| *value == Value::Slash);
+                let start = parts.next().unwrap_or(&[]);
+                let end = match parts.next() {
+                    Some(end) => end.to_vec(),
+                    None => match GridLine::parse(start) {
+                        GridLine::Named(name, _) => vec![Value::Keyword(name)],
+                        _ => vec![],
+                    },
+                };
+                (start.to_vec(), end)
+            }
+            None => (area_start, area_end),
+        };
+        let line = |edge: &str, shorthand: &[Value]| {
+            match self.value(&format!("grid-{}-{}", axis, edge)) {
+                Some(values) => GridLine::parse(&values),
+                None => GridLine::parse(shorthand),
+            }
+        };
+        (line("start", &start), line("end", &end))
+    }
+
     pub fn padding(&mut self) -> (Value, Value, Value, Value) {
         match (
             self.cached.padding.0.clone(),
@@ -188,7 +749,8 @@ impl Style {
                     padding_bottom.get_or_insert_with(|| padding[2].clone());
                     padding_left.get_or_insert_with(|| padding[3].clone());
                 }
-                0 | _ => unreachable!(),
+                // More values than four are invalid.
+                _ => {}
             }
         }
 
@@ -255,7 +817,8 @@ impl Style {
                     margin_bottom.get_or_insert_with(|| margin[2].clone());
                     margin_left.get_or_insert_with(|| margin[3].clone());
                 }
-                0 | _ => unreachable!(),
+                // More values than four are invalid.
+                _ => {}
             }
         }
 
@@ -340,7 +903,8 @@ impl Style {
                     border_bottom.get_or_insert_with(|| border[2].clone());
                     border_left.get_or_insert_with(|| border[3].clone());
                 }
-                0 | _ => unreachable!(),
+                // More values than four are invalid.
+                _ => {}
             }
         }
 
@@ -432,30 +996,31 @@ impl Style {
         if let Some(border) = self.value("border-color") {
             match border.len() {
                 1 => {
-                    border_top.get_or_insert_with(|| border[0].to_color().unwrap());
-                    border_bottom.get_or_insert_with(|| border[0].to_color().unwrap());
-                    border_left.get_or_insert_with(|| border[0].to_color().unwrap());
-                    border_right.get_or_insert_with(|| border[0].to_color().unwrap());
+                    border_top.get_or_insert_with(|| border[0].to_color().unwrap_or(BLACK));
+                    border_bottom.get_or_insert_with(|| border[0].to_color().unwrap_or(BLACK));
+                    border_left.get_or_insert_with(|| border[0].to_color().unwrap_or(BLACK));
+                    border_right.get_or_insert_with(|| border[0].to_color().unwrap_or(BLACK));
                 }
                 2 => {
-                    border_top.get_or_insert_with(|| border[0].to_color().unwrap());
-                    border_bottom.get_or_insert_with(|| border[0].to_color().unwrap());
-                    border_left.get_or_insert_with(|| border[1].to_color().unwrap());
-                    border_right.get_or_insert_with(|| border[1].to_color().unwrap());
+                    border_top.get_or_insert_with(|| border[0].to_color().unwrap_or(BLACK));
+                    border_bottom.get_or_insert_with(|| border[0].to_color().unwrap_or(BLACK));
+                    border_left.get_or_insert_with(|| border[1].to_color().unwrap_or(BLACK));
+                    border_right.get_or_insert_with(|| border[1].to_color().unwrap_or(BLACK));
                 }
                 3 => {
-                    border_top.get_or_insert_with(|| border[0].to_color().unwrap());
-                    border_left.get_or_insert_with(|| border[1].to_color().unwrap());
-                    border_right.get_or_insert_with(|| border[1].to_color().unwrap());
-                    border_bottom.get_or_insert_with(|| border[2].to_color().unwrap());
+                    border_top.get_or_insert_with(|| border[0].to_color().unwrap_or(BLACK));
+                    border_left.get_or_insert_with(|| border[1].to_color().unwrap_or(BLACK));
+                    border_right.get_or_insert_with(|| border[1].to_color().unwrap_or(BLACK));
+                    border_bottom.get_or_insert_with(|| border[2].to_color().unwrap_or(BLACK));
                 }
                 4 => {
-                    border_top.get_or_insert_with(|| border[0].to_color().unwrap());
-                    border_right.get_or_insert_with(|| border[1].to_color().unwrap());
-                    border_bottom.get_or_insert_with(|| border[2].to_color().unwrap());
-                    border_left.get_or_insert_with(|| border[3].to_color().unwrap());
+                    border_top.get_or_insert_with(|| border[0].to_color().unwrap_or(BLACK));
+                    border_right.get_or_insert_with(|| border[1].to_color().unwrap_or(BLACK));
+                    border_bottom.get_or_insert_with(|| border[2].to_color().unwrap_or(BLACK));
+                    border_left.get_or_insert_with(|| border[3].to_color().unwrap_or(BLACK));
                 }
-                0 | _ => unreachable!(),
+                // More values than four are invalid.
+                _ => {}
             }
         }
 
@@ -508,6 +1073,286 @@ impl Style {
         (border_top, border_right, border_bottom, border_left)
     }
 
+    pub fn cursor(&self) -> Cursor {
+        match self.value("cursor") {
+            // The fallback images (``url(...)``) are not supported. Uses the keyword at the end.
+            Some(x) => match x.last() {
+                Some(&Value::Keyword(ref s)) => match &**s {
+                    "default" => Cursor::Default,
+                    "none" => Cursor::None,
+                    "pointer" => Cursor::Pointer,
+                    "text" | "vertical-text" => Cursor::Text,
+                    "wait" => Cursor::Wait,
+                    "progress" => Cursor::Progress,
+                    "help" => Cursor::Help,
+                    "crosshair" | "cell" => Cursor::Crosshair,
+                    "move" | "all-scroll" => Cursor::Move,
+                    "not-allowed" | "no-drop" => Cursor::NotAllowed,
+                    "col-resize" | "ew-resize" | "e-resize" | "w-resize" => Cursor::ColResize,
+                    "row-resize" | "ns-resize" | "n-resize" | "s-resize" => Cursor::RowResize,
+                    "auto" | _ => Cursor::Auto,
+                },
+                _ => Cursor::Auto,
+            },
+            None => Cursor::Auto,
+        }
+    }
+
+    /// False for ``pointer-events: none``: the box is not hit by the pointer, which clicks and
+    /// hovers what is under it instead (its descendants with ``auto`` are hit).
+    pub fn pointer_events(&self) -> bool {
+        match self.value("pointer-events") {
+            Some(x) => x[0] != Value::Keyword("none".to_string()),
+            None => true,
+        }
+    }
+
+    /// The computed ``overflow-x`` and ``overflow-y`` (or the one or two values of
+    /// ``overflow``). A box scrolls in both directions or in neither: if one is ``visible`` or
+    /// ``clip`` and the other scrolls, ``visible`` computes to ``auto`` and ``clip`` to
+    /// ``hidden``.
+    pub fn overflow(&self) -> (Overflow, Overflow) {
+        let keyword = |value: &Value| match value {
+            &Value::Keyword(ref k) => match k.as_str() {
+                "visible" => Some(Overflow::Visible),
+                "hidden" => Some(Overflow::Hidden),
+                "clip" => Some(Overflow::Clip),
+                "scroll" => Some(Overflow::Scroll),
+                "auto" => Some(Overflow::Auto),
+                _ => None,
+            },
+            _ => None,
+        };
+        let shorthand = self.value("overflow").unwrap_or_default();
+        let shorthand: Vec<Overflow> = shorthand.iter().filter_map(keyword).collect();
+        let axis = |name: &str, i: usize| {
+            self.value(name)
+                .and_then(|values| values.first().and_then(keyword))
+                .or_else(|| shorthand.get(i).or_else(|| shorthand.first()).cloned())
+                .unwrap_or(Overflow::Visible)
+        };
+        let (x, y) = (axis("overflow-x", 0), axis("overflow-y", 1));
+
+        let fixup = |overflow: Overflow, other: Overflow| match overflow {
+            Overflow::Visible if other.scrolls() => Overflow::Auto,
+            Overflow::Clip if other.scrolls() => Overflow::Hidden,
+            overflow => overflow,
+        };
+        (fixup(x, y), fixup(y, x))
+    }
+
+    /// ``contain``, with ``strict`` and ``content`` expanded. ``none`` by default. The contents
+    /// that ``content-visibility`` may skip have layout, paint and style containment too.
+    pub fn contain(&self) -> Containment {
+        let mut containment = Containment::default();
+        if self.content_visibility() != ContentVisibility::Visible {
+            containment.layout = true;
+            containment.paint = true;
+            containment.style = true;
+        }
+        for value in self.value("contain").unwrap_or_default() {
+            let keyword = match value {
+                Value::Keyword(keyword) => keyword,
+                _ => continue,
+            };
+            match keyword.as_str() {
+                "size" => containment.size = true,
+                "layout" => containment.layout = true,
+                "paint" => containment.paint = true,
+                "style" => containment.style = true,
+                "content" | "strict" => {
+                    containment.size |= keyword == "strict";
+                    containment.layout = true;
+                    containment.paint = true;
+                    containment.style = true;
+                }
+                _ => {}
+            }
+        }
+        containment
+    }
+
+    /// Whether ``scroll-behavior`` is ``smooth``: the box (or the viewport, for the root) is
+    /// scrolled smoothly by navigation and scripts.
+    pub fn smooth_scrolling(&self) -> bool {
+        self.value("scroll-behavior")
+            .is_some_and(|values| values.first() == Some(&Value::Keyword("smooth".to_string())))
+    }
+
+    pub fn scrollbar_width(&self) -> ScrollbarWidth {
+        match self.value("scrollbar-width").as_ref().and_then(|v| v.first()) {
+            Some(&Value::Keyword(ref k)) if k == "thin" => ScrollbarWidth::Thin,
+            Some(&Value::Keyword(ref k)) if k == "none" => ScrollbarWidth::None,
+            _ => ScrollbarWidth::Auto,
+        }
+    }
+
+    /// ``color-scheme``: the scheme of the system colors, the one preferred by the user (see
+    /// ``media::preferred_color_scheme``) if it is one of those given, else the first one.
+    /// ``normal`` is light.
+    pub fn color_scheme(&self) -> ColorScheme {
+        let schemes: Vec<ColorScheme> = self.value("color-scheme")
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|value| match *value {
+                Value::Keyword(ref k) if k == "light" => Some(ColorScheme::Light),
+                Value::Keyword(ref k) if k == "dark" => Some(ColorScheme::Dark),
+                _ => None,
+            })
+            .collect();
+        let preferred = media::preferred_color_scheme();
+        if schemes.contains(&preferred) {
+            preferred
+        } else {
+            schemes.first().cloned().unwrap_or(ColorScheme::Light)
+        }
+    }
+
+    /// ``color``, ``CanvasText`` of the color scheme if it is not given
+    pub fn color(&self) -> Color {
+        self.value("color")
+            .and_then(|x| x[0].to_color())
+            .or_else(|| self.color_scheme().system_color("canvastext"))
+            .unwrap_or(BLACK)
+    }
+
+    /// ``scrollbar-color``: the colors of the thumb and of the track. None is ``auto``.
+    pub fn scrollbar_color(&self) -> Option<(Color, Color)> {
+        match self.value("scrollbar-color") {
+            Some(ref values) if values.len() == 2 => {
+                Some((values[0].to_color()?, values[1].to_color()?))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn content_visibility(&self) -> ContentVisibility {
+        match self.value("content-visibility").as_ref().and_then(|v| v.first()) {
+            Some(&Value::Keyword(ref k)) if k == "auto" => ContentVisibility::Auto,
+            Some(&Value::Keyword(ref k)) if k == "hidden" => ContentVisibility::Hidden,
+            _ => ContentVisibility::Visible,
+        }
+    }
+
+    /// ``contain-intrinsic-width`` and ``-height`` (or the one or two sizes of
+    /// ``contain-intrinsic-size``): the content size (px) of a box with size containment, and
+    /// whether the size it had when its contents were last laid out is used instead (``auto``).
+    /// ``none`` is 0.
+    pub fn contain_intrinsic_size(&self) -> ((bool, f64), (bool, f64)) {
+        let sizes = |values: Vec<Value>| {
+            // Each size may follow its own ``auto``.
+            let mut auto = false;
+            let mut sizes = vec![];
+            for value in values {
+                let size = match value {
+                    Value::Keyword(ref k) if k == "auto" => {
+                        auto = true;
+                        continue;
+                    }
+                    Value::Keyword(_) => 0.0,
+                    value => value.to_px().unwrap_or(0.0),
+                };
+                sizes.push((auto, size));
+                auto = false;
+            }
+            sizes
+        };
+        let shorthand = sizes(self.value("contain-intrinsic-size").unwrap_or_default());
+        let axis = |name: &str, i: usize| {
+            self.value(name)
+                .and_then(|values| sizes(values).first().cloned())
+                .or_else(|| shorthand.get(i).or_else(|| shorthand.first()).cloned())
+                .unwrap_or((false, 0.0))
+        };
+        (
+            axis("contain-intrinsic-width", 0),
+            axis("contain-intrinsic-height", 1),
+        )
+    }
+
+    /// ``shape-outside``: the shape (or the reference box itself) the inline content next to a
+    /// float wraps around, and the box it is placed in. None for ``none``.
+    pub fn shape_outside(&self) -> Option<(BasicShape, ReferenceBox)> {
+        self.basic_shape("shape-outside", ReferenceBox::Margin)
+    }
+
+    /// ``clip-path``: the shape (or the reference box itself) out of which a box and its
+    /// descendants are not painted nor hit, and the box it is placed in. None for ``none`` (or
+    /// an unsupported ``url()``).
+    pub fn clip_path(&self) -> Option<(BasicShape, ReferenceBox)> {
+        self.basic_shape("clip-path", ReferenceBox::Border)
+    }
+
+    /// ``filter``: the filter functions applied (in order) to what a box and its descendants
+    /// paint. Empty for ``none``; the functions not supported are ignored.
+    pub fn filter(&self) -> Vec<Filter> {
+        let current_color = self.color();
+        self.value("filter")
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|value| Filter::parse(value, current_color))
+            .collect()
+    }
+
+    /// ``text-shadow``: the shadows, the first painted on top, as ``drop-shadow()``s (which take
+    /// the same values). Empty for ``none``.
+    pub fn text_shadow(&self) -> Vec<Filter> {
+        let current_color = self.color();
+        self.value("text-shadow")
+            .unwrap_or_default()
+            .split(|value| *value == Value::Comma)
+            .filter_map(|shadow| {
+                let drop_shadow = Value::Function("drop-shadow".to_string(), shadow.to_vec());
+                Filter::parse(&drop_shadow, current_color)
+            })
+            .collect()
+    }
+
+    /// A basic shape and/or a reference box (``default_box`` if only the shape is given).
+    fn basic_shape(
+        &self,
+        name: &str,
+        default_box: ReferenceBox,
+    ) -> Option<(BasicShape, ReferenceBox)> {
+        let values = self.value(name)?;
+        let reference_box = values.iter().find_map(|value| match value {
+            // The SVG boxes are the border box of a CSS box.
+            &Value::Keyword(ref k) if ["fill-box", "stroke-box", "view-box"].contains(&&**k) => {
+                Some(ReferenceBox::Border)
+            }
+            &Value::Keyword(ref k) => ReferenceBox::from_keyword(k),
+            _ => None,
+        });
+        match values.iter().find_map(BasicShape::parse) {
+            Some(shape) => Some((shape, reference_box.unwrap_or(default_box))),
+            None => {
+                let zero = Value::Length(0.0, Unit::Px);
+                let box_shape = BasicShape::Inset(
+                    [zero.clone(), zero.clone(), zero.clone(), zero.clone()],
+                    zero,
+                );
+                reference_box.map(|reference_box| (box_shape, reference_box))
+            }
+        }
+    }
+
+    /// ``text`` transformed by ``text-transform`` in the language of the element.
+    pub fn transform_text(&self, text: &str) -> String {
+        self.text_transform().apply(text, self.lang().as_deref())
+    }
+
+    pub fn text_transform(&self) -> TextTransform {
+        match self.value("text-transform") {
+            Some(x) => match x[0] {
+                Value::Keyword(ref k) if k == "uppercase" => TextTransform::Uppercase,
+                Value::Keyword(ref k) if k == "lowercase" => TextTransform::Lowercase,
+                Value::Keyword(ref k) if k == "capitalize" => TextTransform::Capitalize,
+                _ => TextTransform::None,
+            },
+            None => TextTransform::None,
+        }
+    }
+
     pub fn text_decoration(&self) -> Vec<TextDecoration> {
         if let Some(text_decorations) = self.value("text-decoration") {
             let mut decorations = vec![];
@@ -528,14 +1373,18 @@ impl Style {
                 font_size
                     .clone()
                     .maybe_percent_to_px(DEFAULT_LINE_HEIGHT_SCALE)
-                    .unwrap(),
+                    .unwrap_or(DEFAULT_FONT_SIZE),
             );
         }
 
         let default_font_size = Value::Length(DEFAULT_FONT_SIZE, Unit::Px);
         let font_size = &self.value_with_default("font-size", &vec![default_font_size])[0];
         self.cached.font_size = Some(font_size.clone());
-        Au::from_f64_px(font_size.maybe_percent_to_px(DEFAULT_FONT_SIZE).unwrap())
+        Au::from_f64_px(
+            font_size
+                .maybe_percent_to_px(DEFAULT_FONT_SIZE)
+                .unwrap_or(DEFAULT_FONT_SIZE),
+        )
     }
 
     pub fn font_weight(&self) -> FontWeight {
@@ -543,6 +1392,171 @@ impl Style {
         self.value_with_default("font-weight", &vec![default_font_weight])[0].to_font_weight()
     }
 
+    /// The names between the commas of ``font-family``, the words of an unquoted one joined by
+    /// spaces (e.g. ``Times New Roman, serif``).
+    pub fn font_family(&self) -> FontFamily {
+        let names: Vec<String> = self.value("font-family")
+            .unwrap_or_default()
+            .split(|value| *value == Value::Comma)
+            .map(|family| {
+                family
+                    .iter()
+                    .filter_map(|value| match *value {
+                        Value::Keyword(ref name) | Value::Str(ref name) => Some(name.as_str()),
+                        _ => None,
+                    })
+                    .collect::<Vec<&str>>()
+                    .join(" ")
+            })
+            .filter(|name| !name.is_empty())
+            .collect();
+        FontFamily::new(&names)
+    }
+
+    /// The OpenType features from ``font-variant``, ``font-kerning`` and
+    /// ``font-feature-settings``, applied in this order.
+    pub fn font_features(&self) -> FontFeatures {
+        let mut features: Vec<(String, u32)> = vec![];
+        let mut feature = |tag: &str, value: u32| features.push((tag.to_string(), value));
+
+        let variants = ["font-variant", "font-variant-caps", "font-variant-ligatures"]
+            .iter()
+            .filter_map(|name| self.value(name))
+            .flat_map(|values| values.into_iter());
+        for variant in variants {
+            if let Value::Keyword(ref k) = variant {
+                match k.as_str() {
+                    "small-caps" => feature("smcp", 1),
+                    "all-small-caps" => {
+                        feature("c2sc", 1);
+                        feature("smcp", 1);
+                    }
+                    "petite-caps" => feature("pcap", 1),
+                    "all-petite-caps" => {
+                        feature("c2pc", 1);
+                        feature("pcap", 1);
+                    }
+                    "unicase" => feature("unic", 1),
+                    "titling-caps" => feature("titl", 1),
+                    "none" | "no-common-ligatures" => {
+                        feature("liga", 0);
+                        feature("clig", 0);
+                    }
+                    "common-ligatures" => {
+                        feature("liga", 1);
+                        feature("clig", 1);
+                    }
+                    "discretionary-ligatures" => feature("dlig", 1),
+                    "no-discretionary-ligatures" => feature("dlig", 0),
+                    "contextual" => feature("calt", 1),
+                    "no-contextual" => feature("calt", 0),
+                    _ => {}
+                }
+            }
+        }
+
+        match self.value("font-kerning").as_ref().map(|x| &x[0]) {
+            Some(&Value::Keyword(ref k)) if k == "normal" => feature("kern", 1),
+            Some(&Value::Keyword(ref k)) if k == "none" => feature("kern", 0),
+            _ => {}
+        }
+
+        // e.g. ``"liga" 0, "smcp", "ss01" on``. The value is 1 if omitted.
+        let settings = self.value("font-feature-settings").unwrap_or_default();
+        let mut settings = settings.iter().peekable();
+        while let Some(setting) = settings.next() {
+            let tag = match *setting {
+                Value::Str(ref tag) if tag.len() == 4 => tag,
+                _ => continue,
+            };
+            let value = match settings.peek() {
+                Some(&&Value::Num(n)) => n as u32,
+                Some(&&Value::Keyword(ref k)) if k == "on" => 1,
+                Some(&&Value::Keyword(ref k)) if k == "off" => 0,
+                _ => {
+                    feature(tag, 1);
+                    continue;
+                }
+            };
+            settings.next();
+            feature(tag, value);
+        }
+
+        FontFeatures::new(&features)
+    }
+
+    pub fn lang(&self) -> Option<String> {
+        match self.value(LANG_PROPERTY) {
+            Some(ref x) => match x[0] {
+                Value::Keyword(ref lang) if !lang.is_empty() => Some(lang.clone()),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    pub fn hyphens(&self) -> Hyphens {
+        match self.value("hyphens") {
+            Some(ref x) => match x[0] {
+                Value::Keyword(ref k) if k == "none" => Hyphens::None,
+                Value::Keyword(ref k) if k == "auto" => Hyphens::Auto,
+                _ => Hyphens::Manual,
+            },
+            None => Hyphens::Manual,
+        }
+    }
+
+    /// Finds where the words in the text of the element are hyphenated.
+    pub fn hyphenator(&self) -> Hyphenator {
+        let lang = self.lang();
+        Hyphenator::new(self.hyphens(), lang.as_deref())
+    }
+
+    /// The font to draw the text of the element in.
+    pub fn font(&mut self) -> Font {
+        let tab_size = self.tab_size();
+        let font = Font::new(
+            self.font_size(),
+            self.font_weight(),
+            self.font_style(),
+            self.font_family(),
+            self.font_features(),
+            self.spacing("letter-spacing"),
+            self.spacing("word-spacing"),
+        );
+        Font {
+            tab_size: tab_size,
+            lang: FontLanguage::new(self.lang().as_deref()),
+            ..font
+        }
+    }
+
+    /// Negative values are invalid.
+    pub fn tab_size(&self) -> TabSize {
+        match self.value("tab-size") {
+            Some(x) => match x[0] {
+                Value::Num(spaces) if spaces >= 0.0 => TabSize::Spaces(spaces),
+                ref length => match length.to_px() {
+                    Some(px) if px >= 0.0 => TabSize::Length(Au::from_f64_px(px)),
+                    _ => DEFAULT_TAB_SIZE,
+                },
+            },
+            None => DEFAULT_TAB_SIZE,
+        }
+    }
+
+    /// ``letter-spacing`` or ``word-spacing``. ``normal`` is zero.
+    pub fn spacing(&mut self, name: &str) -> Au {
+        let spacing = match self.value(name) {
+            Some(x) => x[0].clone(),
+            None => return Au(0),
+        };
+        Au::from_f64_px(match spacing {
+            Value::Length(f, Unit::Em) => f * self.font_size().to_f64_px(),
+            spacing => spacing.to_px().unwrap_or(0.0),
+        })
+    }
+
     pub fn font_style(&self) -> FontSlant {
         let default_font_slant = Value::Keyword("normal".to_string());
         self.lookup("font-style", "font-style", &vec![default_font_slant])[0].to_font_slant()
@@ -557,9 +1571,9 @@ impl Style {
                 &Value::Keyword(ref k) if k == "normal" => font_size * DEFAULT_LINE_HEIGHT_SCALE,
                 &Value::Length(f, Unit::Px) => f,
                 &Value::Length(f, Unit::Pt) => pt2px(f),
-                &Value::Length(_, _) => unimplemented!(),
-                &Value::Num(f) => font_size * f,
-                _ => panic!(),
+                &Value::Length(f, Unit::Percent) => font_size * f / 100.0,
+                &Value::Length(f, Unit::Em) | &Value::Num(f) => font_size * f,
+                _ => font_size * DEFAULT_LINE_HEIGHT_SCALE,
             });
         }
 
@@ -569,18 +1583,425 @@ impl Style {
             &Value::Keyword(ref k) if k == "normal" => font_size * DEFAULT_LINE_HEIGHT_SCALE,
             &Value::Length(f, Unit::Px) => f,
             &Value::Length(f, Unit::Pt) => pt2px(f),
-            &Value::Length(_, _) => unimplemented!(),
-            &Value::Num(f) => font_size * f,
-            _ => panic!(),
+            &Value::Length(f, Unit::Percent) => font_size * f / 100.0,
+            &Value::Length(f, Unit::Em) | &Value::Num(f) => font_size * f,
+            _ => font_size * DEFAULT_LINE_HEIGHT_SCALE,
         })
     }
 
+    /// ``text-align``, with ``start`` (the default) and ``end`` resolved to ``left`` or ``right``
+    /// by ``direction``.
     pub fn text_align(&self) -> Value {
-        self.value_with_default("text-align", &vec![Value::Keyword("left".to_string())])[0].clone()
+        let rtl = self.direction() == Some(Direction::Rtl);
+        let (start, end) = if rtl { ("right", "left") } else { ("left", "right") };
+        match self.value("text-align").map(|x| x[0].clone()) {
+            None => Value::Keyword(start.to_string()),
+            Some(Value::Keyword(ref k)) if k == "start" => Value::Keyword(start.to_string()),
+            Some(Value::Keyword(ref k)) if k == "end" => Value::Keyword(end.to_string()),
+            Some(text_align) => text_align,
+        }
+    }
+
+    /// None unless specified (or inherited): the paragraphs are then in the direction of their
+    /// first strong character.
+    pub fn direction(&self) -> Option<Direction> {
+        match self.value("direction") {
+            Some(ref x) => match x[0] {
+                Value::Keyword(ref k) if k == "ltr" => Some(Direction::Ltr),
+                Value::Keyword(ref k) if k == "rtl" => Some(Direction::Rtl),
+                _ => None,
+            },
+            None => None,
+        }
+    }
+
+    /// The URL of ``background-image`` (or the image in ``background``), not resolved.
+    pub fn background_image(&self) -> Option<String> {
+        let image = self.value("background-image").or_else(|| self.value("background"))?;
+        image.into_iter().find_map(|value| match value {
+            Value::Url(url) => Some(url),
+            _ => None,
+        })
+    }
+
+    /// Whether ``background-attachment`` (or ``background``) is ``fixed``: the background image
+    /// stays in the viewport as the content scrolls. ``local`` is ``scroll``.
+    pub fn background_attachment_fixed(&self) -> bool {
+        let fixed = Value::Keyword("fixed".to_string());
+        match self.value("background-attachment") {
+            Some(values) => values.first() == Some(&fixed),
+            None => self.value("background").is_some_and(|values| values.contains(&fixed)),
+        }
+    }
+
+    /// ``background-repeat`` in the horizontal and vertical directions.
+    pub fn background_repeat(&self) -> (BackgroundRepeat, BackgroundRepeat) {
+        let values = self.value("background-repeat")
+            .or_else(|| self.value("background"))
+            .unwrap_or_default();
+        let keywords: Vec<&str> = values
+            .iter()
+            .filter_map(|value| match value {
+                &Value::Keyword(ref k) => Some(k.as_str()),
+                _ => None,
+            })
+            .collect();
+        let repeat = |k: &str| match k {
+            "repeat" => Some(BackgroundRepeat::Repeat),
+            "space" => Some(BackgroundRepeat::Space),
+            "round" => Some(BackgroundRepeat::Round),
+            "no-repeat" => Some(BackgroundRepeat::NoRepeat),
+            _ => None,
+        };
+        if keywords.contains(&"repeat-x") {
+            return (BackgroundRepeat::Repeat, BackgroundRepeat::NoRepeat);
+        }
+        if keywords.contains(&"repeat-y") {
+            return (BackgroundRepeat::NoRepeat, BackgroundRepeat::Repeat);
+        }
+        let mut repeats = keywords.into_iter().filter_map(repeat);
+        match (repeats.next(), repeats.next()) {
+            (Some(x), Some(y)) => (x, y),
+            (Some(x), None) => (x, x),
+            _ => (BackgroundRepeat::Repeat, BackgroundRepeat::Repeat),
+        }
+    }
+
+    /// The URL of ``border-image-source`` (or the image in ``border-image``), not resolved.
+    pub fn border_image_source(&self) -> Option<String> {
+        let image = self.value("border-image-source")
+            .or_else(|| self.value("border-image"))?;
+        image.into_iter().find_map(|value| match value {
+            Value::Url(url) => Some(url),
+            _ => None,
+        })
+    }
+
+    /// The values of ``name`` (``border-image-slice``, ``-width`` or ``-outset``), or else those
+    /// of the ``index``th part of ``border-image``, e.g. ``30 fill`` (0), ``10px`` (1) and ``0``
+    /// (2) of ``url(a.png) 30 fill / 10px / 0 round``.
+    pub fn border_image_part(&self, name: &str, index: usize) -> Vec<Value> {
+        if let Some(values) = self.value(name) {
+            return values;
+        }
+        let values = self.value("border-image").unwrap_or_default();
+        values
+            .split(|value| *value == Value::Slash)
+            .nth(index)
+            .map_or(vec![], |part| part.to_vec())
+    }
+
+    /// ``border-image-repeat`` in the horizontal and vertical directions.
+    pub fn border_image_repeat(&self) -> (BorderImageRepeat, BorderImageRepeat) {
+        let values = self.value("border-image-repeat")
+            .or_else(|| self.value("border-image"))
+            .unwrap_or_default();
+        let mut repeats = values.iter().filter_map(|value| match value {
+            &Value::Keyword(ref k) => match k.as_str() {
+                "stretch" => Some(BorderImageRepeat::Stretch),
+                "repeat" => Some(BorderImageRepeat::Repeat),
+                "round" => Some(BorderImageRepeat::Round),
+                "space" => Some(BorderImageRepeat::Space),
+                _ => None,
+            },
+            _ => None,
+        });
+        match (repeats.next(), repeats.next()) {
+            (Some(x), Some(y)) => (x, y),
+            (Some(x), None) => (x, x),
+            _ => (BorderImageRepeat::Stretch, BorderImageRepeat::Stretch),
+        }
+    }
+
+    /// ``background-size``. In ``background``, only ``cover`` and ``contain`` are read.
+    pub fn background_size(&self) -> BackgroundSize {
+        let auto = |value: &Value| match value {
+            &Value::Keyword(_) => None,
+            value => Some(value.clone()),
+        };
+        match self.value("background-size") {
+            Some(values) => match values.first() {
+                Some(&Value::Keyword(ref k)) if k == "cover" => BackgroundSize::Cover,
+                Some(&Value::Keyword(ref k)) if k == "contain" => BackgroundSize::Contain,
+                Some(width) => {
+                    // One value is the width, and the height is ``auto``.
+                    BackgroundSize::Explicit(auto(width), values.get(1).and_then(auto))
+                }
+                None => BackgroundSize::Explicit(None, None),
+            },
+            None => {
+                let values = self.value("background").unwrap_or_default();
+                let keyword = |name: &str| values.contains(&Value::Keyword(name.to_string()));
+                if keyword("cover") {
+                    BackgroundSize::Cover
+                } else if keyword("contain") {
+                    BackgroundSize::Contain
+                } else {
+                    BackgroundSize::Explicit(None, None)
+                }
+            }
+        }
+    }
+
+    /// ``background-position`` in the horizontal and vertical directions (``0% 0%`` by
+    /// default). In ``background``, the values before ``/`` are the position.
+    /// ref. https://www.w3.org/TR/css-backgrounds-3/#background-position
+    pub fn background_position(&self) -> (BackgroundPosition, BackgroundPosition) {
+        let values: Vec<Value> = match self.value("background-position") {
+            Some(values) => values,
+            None => self.value("background")
+                .unwrap_or_default()
+                .into_iter()
+                .take_while(|value| !is_background_size_keyword(value))
+                .filter(is_position_value)
+                .collect(),
+        };
+        parse_background_position(&values).unwrap_or_else(|| {
+            (BackgroundPosition::percent(0.0), BackgroundPosition::percent(0.0))
+        })
+    }
+
+    /// ``aspect-ratio``: the width over the height, e.g. 2 of ``2 / 1``. None for ``auto``.
+    pub fn aspect_ratio(&self) -> Option<f64> {
+        let values = self.value("aspect-ratio")?;
+        let ratio = match values.as_slice() {
+            [Value::Num(width)] => *width,
+            [Value::Num(width), Value::Slash, Value::Num(height)] => width / height,
+            _ => return None,
+        };
+        if ratio > 0.0 && ratio.is_finite() {
+            Some(ratio)
+        } else {
+            None
+        }
+    }
+
+    pub fn object_fit(&self) -> ObjectFit {
+        match self.value("object-fit") {
+            Some(x) => match x[0] {
+                Value::Keyword(ref k) if k == "contain" => ObjectFit::Contain,
+                Value::Keyword(ref k) if k == "cover" => ObjectFit::Cover,
+                Value::Keyword(ref k) if k == "none" => ObjectFit::None,
+                Value::Keyword(ref k) if k == "scale-down" => ObjectFit::ScaleDown,
+                _ => ObjectFit::Fill,
+            },
+            None => ObjectFit::Fill,
+        }
+    }
+
+    /// The counters ``property`` (``counter-reset``, ``counter-increment`` or ``counter-set``)
+    /// changes, each with its value (``default`` if not given, clamped to ``i32``). Empty for
+    /// ``none``.
+    pub fn counter_changes(&self, property: &str, default: i32) -> Vec<(String, i32)> {
+        let mut changes: Vec<(String, i32)> = vec![];
+        for value in self.value(property).unwrap_or_default() {
+            match value {
+                Value::Keyword(ref k) if k == "none" => return vec![],
+                Value::Keyword(name) => changes.push((name, default)),
+                Value::Num(n) => if let Some(change) = changes.last_mut() {
+                    change.1 = n as i32;
+                },
+                _ => {}
+            }
+        }
+        changes
+    }
+
+    /// ``quotes``: the open and close quotes of each nesting level, the outermost first. Empty for
+    /// ``none``. ``auto`` are the English quotes.
+    pub fn quotes(&self) -> Vec<(String, String)> {
+        let values = self.value("quotes").unwrap_or_default();
+        let strings: Vec<String> = values
+            .iter()
+            .filter_map(|value| match *value {
+                Value::Str(ref s) => Some(s.clone()),
+                _ => None,
+            })
+            .collect();
+        match values.first() {
+            Some(&Value::Keyword(ref k)) if k == "none" => vec![],
+            Some(&Value::Str(_)) => strings
+                .chunks(2)
+                .filter(|pair| pair.len() == 2)
+                .map(|pair| (pair[0].clone(), pair[1].clone()))
+                .collect(),
+            _ => vec![
+                ("\u{201c}".to_string(), "\u{201d}".to_string()),
+                ("\u{2018}".to_string(), "\u{2019}".to_string()),
+            ],
+        }
+    }
+
+    /// ``object-position``: where the content of a replaced element is in its content box, like
+    /// ``background-position`` (but centered by default).
+    pub fn object_position(&self) -> (BackgroundPosition, BackgroundPosition) {
+        self.value("object-position")
+            .and_then(|values| parse_background_position(&values))
+            .unwrap_or_else(|| {
+                (BackgroundPosition::percent(50.0), BackgroundPosition::percent(50.0))
+            })
+    }
+
+    pub fn outline_specified(&self) -> bool {
+        self.value("outline").is_some() || self.value("outline-style").is_some()
+    }
+
+    /// Returns None if no outline is drawn (`outline-style` is `none`, the initial value).
+    /// ref. https://www.w3.org/TR/css-ui-3/#outline-props
+    pub fn outline(&self) -> Option<Outline> {
+        let mut width = self.value("outline-width").and_then(|x| x[0].to_border_width_px());
+        let mut color = self.value("outline-color").and_then(|x| x[0].to_color());
+        let mut style = self.value("outline-style").and_then(|x| x[0].to_border_style());
+
+        if let Some(outline) = self.value("outline") {
+            for value in outline {
+                if let Some(s) = value.to_border_style() {
+                    style.get_or_insert(s);
+                } else if let Some(c) = value.to_color() {
+                    color.get_or_insert(c);
+                } else if let Some(w) = value.to_border_width_px() {
+                    width.get_or_insert(w);
+                }
+            }
+        }
+
+        match style {
+            None => return None,
+            Some(ref s) if s == "none" || s == "hidden" => return None,
+            _ => {}
+        }
+
+        let offset = self.value("outline-offset")
+            .and_then(|x| x[0].to_px())
+            .unwrap_or(0.0);
+
+        Some(Outline {
+            // `medium` is the initial value of outline-width.
+            width: Au::from_f64_px(width.unwrap_or(3.0)),
+            // `currentColor`
+            color: color.unwrap_or_else(|| self.color()),
+            offset: Au::from_f64_px(offset),
+        })
+    }
+}
+
+fn is_background_size_keyword(value: &Value) -> bool {
+    match value {
+        &Value::Keyword(ref k) => k == "cover" || k == "contain",
+        _ => false,
+    }
+}
+
+fn is_position_value(value: &Value) -> bool {
+    match value {
+        &Value::Keyword(ref k) => match k.as_str() {
+            "left" | "right" | "top" | "bottom" | "center" => true,
+            _ => false,
+        },
+        &Value::Length(_, _) | &Value::Num(_) => true,
+        _ => false,
+    }
+}
+
+/// Parses 1 to 4 values of ``background-position`` (or of another position, e.g. the center of
+/// a shape). None if they are invalid.
+pub fn parse_background_position(
+    values: &[Value],
+) -> Option<(BackgroundPosition, BackgroundPosition)> {
+    let keyword = |value: &Value| match value {
+        &Value::Keyword(ref k) => Some(k.clone()),
+        _ => None,
+    };
+    // The edge a keyword is at, and whether it is vertical
+    let edge = |k: &str| match k {
+        "left" => Some((0.0, false)),
+        "right" => Some((100.0, false)),
+        "top" => Some((0.0, true)),
+        "bottom" => Some((100.0, true)),
+        "center" => Some((50.0, false)),
+        _ => None,
+    };
+    let position = |value: &Value| match keyword(value) {
+        Some(k) => edge(&k).map(|(percent, _)| BackgroundPosition::percent(percent)),
+        None => Some(BackgroundPosition {
+            offset: value.clone(),
+            from_end: false,
+        }),
+    };
+    let is_vertical = |value: &Value| keyword(value).is_some_and(|k| k == "top" || k == "bottom");
+    let is_horizontal =
+        |value: &Value| keyword(value).is_some_and(|k| k == "left" || k == "right");
+
+    match values.len() {
+        1 if is_vertical(&values[0]) => {
+            Some((BackgroundPosition::percent(50.0), position(&values[0])?))
+        }
+        1 => Some((position(&values[0])?, BackgroundPosition::percent(50.0))),
+        2 if is_vertical(&values[0]) || is_horizontal(&values[1]) => {
+            Some((position(&values[1])?, position(&values[0])?))
+        }
+        2 => Some((position(&values[0])?, position(&values[1])?)),
+        3 | 4 => {
+            // Keywords, each followed by the offset from its edge, e.g. ``right 10px top``
+            let (mut x, mut y) = (None, None);
+            let mut centered = 0;
+            let mut i = 0;
+            while i < values.len() {
+                let k = keyword(&values[i])?;
+                let (percent, vertical) = edge(&k)?;
+                let offset = match values.get(i + 1) {
+                    Some(value) if keyword(value).is_none() && k != "center" => {
+                        i += 1;
+                        value.clone()
+                    }
+                    _ => Value::Length(0.0, Unit::Percent),
+                };
+                i += 1;
+                if k == "center" {
+                    centered += 1;
+                    continue;
+                }
+                let axis = if vertical { &mut y } else { &mut x };
+                if axis.is_some() {
+                    return None;
+                }
+                *axis = Some(BackgroundPosition {
+                    offset: offset,
+                    from_end: percent == 100.0,
+                });
+            }
+            if centered > 1 {
+                return None;
+            }
+            Some((
+                x.unwrap_or_else(|| BackgroundPosition::percent(50.0)),
+                y.unwrap_or_else(|| BackgroundPosition::percent(50.0)),
+            ))
+        }
+        _ => None,
     }
 }
 
 impl Value {
+    pub fn to_border_style(&self) -> Option<String> {
+        match self {
+            &Value::Keyword(ref k) => match k.as_str() {
+                "none" | "hidden" | "dotted" | "dashed" | "solid" | "double" | "groove"
+                | "ridge" | "inset" | "outset" | "auto" => Some(k.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+    pub fn to_border_width_px(&self) -> Option<f64> {
+        match self {
+            &Value::Keyword(ref k) if k.as_str() == "thin" => Some(1.0),
+            &Value::Keyword(ref k) if k.as_str() == "medium" => Some(3.0),
+            &Value::Keyword(ref k) if k.as_str() == "thick" => Some(5.0),
+            &Value::Length(_, _) => self.to_px(),
+            _ => None,
+        }
+    }
     pub fn to_font_weight(&self) -> FontWeight {
         match self {
             &Value::Keyword(ref k) if k.as_str() == "normal" => FontWeight::Normal,
@@ -597,11 +2018,24 @@ impl Value {
     }
 }
 
+/// The style of an element with the declarations of ``src`` (e.g. ``"color: red;"``) only
+#[cfg(test)]
+pub fn style_from(src: &str) -> Style {
+    use css::parse_attr_style;
+
+    Style::new_with(
+        parse_attr_style(src.to_string())
+            .into_iter()
+            .map(|decl| (decl.name, decl.values))
+            .collect(),
+    )
+}
+
 #[test]
 fn test1() {
     use html;
     use css;
-    use std::path::Path;
+    use network::Url;
     use default_style::*;
 
     let src = "<html>
@@ -615,10 +2049,146 @@ fn test1() {
                    space
                  </body>
                </html>";
-    let dom_node = html::parse(src.to_string(), Path::new("a.html").to_path_buf());
+    let dom_node = html::parse(src.to_string(), Url::parse("file:///a.html").unwrap());
 
     let src = "* { display: block; }
                div, body > div, body span { width: 100px; height: 50px; color: #ffffff; background-color: #003300; } 
                a { display: inline; text-decoration: underline; }";
     css::parse(src.to_string());
 }
+
+#[test]
+fn test_overflow() {
+    let overflow = |src: &str| style_from(src).overflow();
+    use self::Overflow::*;
+    assert_eq!(overflow("color: red"), (Visible, Visible));
+    assert_eq!(overflow("overflow: hidden"), (Hidden, Hidden));
+    assert_eq!(overflow("overflow: clip scroll"), (Hidden, Scroll));
+    assert_eq!(overflow("overflow: hidden; overflow-y: clip"), (Hidden, Hidden));
+    assert_eq!(overflow("overflow-x: clip"), (Clip, Visible));
+    assert_eq!(overflow("overflow-x: clip; overflow-y: clip"), (Clip, Clip));
+    assert_eq!(overflow("overflow-x: hidden"), (Hidden, Auto));
+    assert_eq!(overflow("overflow-x: visible; overflow-y: scroll"), (Auto, Scroll));
+    assert_eq!(overflow("overflow-x: auto; overflow-y: clip"), (Auto, Hidden));
+    assert_eq!(overflow("overflow: nonsense"), (Visible, Visible));
+}
+
+#[test]
+fn test_outline() {
+    use css::RED;
+
+    assert_eq!(style_from("color: red;").outline(), None);
+    assert_eq!(style_from("outline: 2px none red;").outline(), None);
+    assert_eq!(
+        style_from("outline: 2px solid red; outline-offset: 1px;").outline(),
+        Some(Outline {
+            width: Au::from_f64_px(2.0),
+            color: RED,
+            offset: Au::from_f64_px(1.0),
+        })
+    );
+    assert_eq!(
+        style_from("color: red; outline-style: dashed; outline-width: thin;").outline(),
+        Some(Outline {
+            width: Au::from_f64_px(1.0),
+            color: RED,
+            offset: Au(0),
+        })
+    );
+}
+
+#[test]
+fn test_cursor() {
+    assert_eq!(style_from("color: red;").cursor(), Cursor::Auto);
+    assert_eq!(style_from("cursor: pointer;").cursor(), Cursor::Pointer);
+    assert_eq!(style_from("cursor: ew-resize;").cursor(), Cursor::ColResize);
+    assert_eq!(style_from("cursor: grab;").cursor(), Cursor::Auto);
+}
+
+#[test]
+fn test_font_family() {
+    let names = |src: &str| style_from(src).font_family().names();
+
+    assert_eq!(
+        names("font-family: Times New Roman, serif;"),
+        "Times New Roman,serif"
+    );
+    assert_eq!(
+        names("font-family: 'Noto Sans', Noto  Color Emoji;"),
+        "Noto Sans,Noto Color Emoji,sans-serif"
+    );
+    assert_eq!(names("font-family: monospace;"), "monospace");
+}
+
+#[test]
+fn test_font_features() {
+    let features = |src: &str| style_from(src).font_features().to_pango_string();
+
+    assert_eq!(features("color: red;"), "");
+    assert_eq!(features("font-variant: small-caps;"), "smcp=1");
+    assert_eq!(features("font-kerning: none;"), "kern=0");
+    assert_eq!(
+        features("font-feature-settings: \"liga\" 0, \"ss01\", \"tnum\" on;"),
+        "liga=0,ss01=1,tnum=1"
+    );
+    assert_eq!(
+        features("font-variant: all-small-caps; font-feature-settings: \"smcp\" off;"),
+        "c2sc=1,smcp=1,smcp=0"
+    );
+}
+
+#[test]
+fn test_spacing() {
+    let spacing = |src: &str, name: &str| style_from(src).spacing(name).to_f64_px();
+
+    assert_eq!(spacing("color: red;", "letter-spacing"), 0.0);
+    assert_eq!(spacing("letter-spacing: normal;", "letter-spacing"), 0.0);
+    assert_eq!(spacing("letter-spacing: 2px;", "letter-spacing"), 2.0);
+    assert_eq!(spacing("word-spacing: -1px;", "word-spacing"), -1.0);
+    assert_eq!(
+        spacing("font-size: 20px; letter-spacing: 0.5em;", "letter-spacing"),
+        10.0
+    );
+}
+
+#[test]
+fn test_text_transform() {
+    assert_eq!(TextTransform::Uppercase.apply("straße", None), "STRASSE");
+    assert_eq!(TextTransform::Lowercase.apply("ΑΒΓ Hello", None), "αβγ hello");
+    assert_eq!(
+        TextTransform::Capitalize.apply("hello (world) don't élan", None),
+        "Hello (World) Don't Élan"
+    );
+    assert_eq!(TextTransform::None.apply("aBc", None), "aBc");
+    // In Turkish
+    assert_eq!(TextTransform::Uppercase.apply("istanbul", Some("tr")), "İSTANBUL");
+    assert_eq!(TextTransform::Uppercase.apply("istanbul", Some("en")), "ISTANBUL");
+    assert_eq!(TextTransform::Lowercase.apply("DİYARBAKIR", Some("tr-TR")), "diyarbakır");
+    assert_eq!(TextTransform::Capitalize.apply("ırmak ile", Some("az")), "Irmak İle");
+}
+
+#[test]
+fn test_tab_size() {
+    let tab_size = |src: &str| style_from(src).tab_size();
+
+    assert_eq!(tab_size("color: red;"), TabSize::Spaces(8.0));
+    assert_eq!(tab_size("tab-size: 4;"), TabSize::Spaces(4.0));
+    assert_eq!(tab_size("tab-size: 20px;"), TabSize::Length(Au::from_f64_px(20.0)));
+    assert_eq!(tab_size("tab-size: -2;"), DEFAULT_TAB_SIZE);
+}
+
+#[test]
+fn test_computed_font_size() {
+    let px = |f| Value::Length(f, Unit::Px);
+    let keyword = |k: &str| Value::Keyword(k.to_string());
+
+    assert_eq!(computed_font_size(&px(12.0), 20.0), 12.0);
+    assert_eq!(computed_font_size(&keyword("medium"), 20.0), 16.0);
+    assert_eq!(computed_font_size(&keyword("xx-large"), 20.0), 32.0);
+    assert_eq!(computed_font_size(&keyword("larger"), 20.0), 24.0);
+    assert_eq!(computed_font_size(&keyword("smaller"), 24.0), 20.0);
+    assert_eq!(computed_font_size(&Value::Length(150.0, Unit::Percent), 20.0), 30.0);
+    assert_eq!(computed_font_size(&Value::Length(0.5, Unit::Em), 20.0), 10.0);
+    assert_eq!(computed_font_size(&px(-1.0), 20.0), 20.0);
+    assert_eq!(computed_font_size(&keyword("inherit"), 20.0), 20.0);
+}
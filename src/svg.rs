@@ -0,0 +1,789 @@
+//! Inline SVG: an ``<svg>`` in HTML is a replaced element, as large as its ``width`` and
+//! ``height`` (see ``intrinsic_size``). Its subtree is parsed into a lightweight SVG DOM
+//! (``Svg::from_node``): the shapes (``rect``, ``circle``, ``ellipse``, ``line``, ``polyline``,
+//! ``polygon`` and ``path``) become paths, filled and stroked, and ``text`` becomes texts.
+//! ref. https://www.w3.org/TR/SVG2/
+//!
+//! The painting properties (``fill``, ``fill-rule``, ``stroke``, ``stroke-width`` and
+//! ``font-size``) are the presentation attributes and the ``style`` attributes of the elements,
+//! inherited through ``<g>``. The ``viewBox`` is fit into the content box and centered
+//! (``preserveAspectRatio="xMidYMid meet"``). Transforms, gradients, ``<use>`` and the style
+//! sheets of the page are not supported.
+
+use css::{self, Color, Unit, Value, BLACK};
+use dom::{ElementData, Node, NodeType};
+use layout::Rect;
+use shape::FillRule;
+
+use app_units::Au;
+
+use std::f64::consts::PI;
+
+/// The size of an ``<svg>`` without ``width`` nor ``height``, like that of other replaced
+/// elements
+pub const DEFAULT_SIZE: (f64, f64) = (300.0, 150.0);
+
+/// A point, e.g. in user units
+type Point = (f64, f64);
+
+/// A command of a path, with absolute coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PathSegment {
+    MoveTo(f64, f64),
+    LineTo(f64, f64),
+    /// A cubic Bézier curve: the two control points, and the end
+    CurveTo(f64, f64, f64, f64, f64, f64),
+    Close,
+}
+
+impl PathSegment {
+    /// The segment with its points transformed by ``f``
+    pub fn map<F: Fn((f64, f64)) -> (f64, f64)>(self, f: F) -> PathSegment {
+        match self {
+            PathSegment::MoveTo(x, y) => {
+                let (x, y) = f((x, y));
+                PathSegment::MoveTo(x, y)
+            }
+            PathSegment::LineTo(x, y) => {
+                let (x, y) = f((x, y));
+                PathSegment::LineTo(x, y)
+            }
+            PathSegment::CurveTo(x1, y1, x2, y2, x, y) => {
+                let ((x1, y1), (x2, y2), (x, y)) = (f((x1, y1)), f((x2, y2)), f((x, y)));
+                PathSegment::CurveTo(x1, y1, x2, y2, x, y)
+            }
+            PathSegment::Close => PathSegment::Close,
+        }
+    }
+}
+
+/// A path placed in the document (CSS px): filled, then stroked with a width
+#[derive(Clone, Debug, PartialEq)]
+pub struct Path {
+    pub segments: Vec<PathSegment>,
+    pub fill: Option<(Color, FillRule)>,
+    pub stroke: Option<(Color, f64)>,
+}
+
+impl Path {
+    /// The rect covering all that the path paints. None if it paints nothing.
+    pub fn bounds(&self) -> Option<Rect> {
+        if self.fill.is_none() && self.stroke.is_none() {
+            return None;
+        }
+        // The curves are within their control points.
+        let mut points = self.segments.iter().flat_map(|segment| match *segment {
+            PathSegment::MoveTo(x, y) | PathSegment::LineTo(x, y) => vec![(x, y)],
+            PathSegment::CurveTo(x1, y1, x2, y2, x, y) => vec![(x1, y1), (x2, y2), (x, y)],
+            PathSegment::Close => vec![],
+        });
+        let first = points.next()?;
+        let (left, top, right, bottom) = points.fold(
+            (first.0, first.1, first.0, first.1),
+            |(left, top, right, bottom), (x, y)| {
+                (left.min(x), top.min(y), right.max(x), bottom.max(y))
+            },
+        );
+        let outset = self.stroke.map_or(0.0, |(_, width)| width / 2.0);
+        Some(Rect {
+            x: Au::from_f64_px(left - outset),
+            y: Au::from_f64_px(top - outset),
+            width: Au::from_f64_px(right - left + outset * 2.0),
+            height: Au::from_f64_px(bottom - top + outset * 2.0),
+        })
+    }
+}
+
+/// What ``fill`` or ``stroke`` paints with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Paint {
+    None,
+    Color(Color),
+    /// The ``color`` of the ``<svg>``
+    CurrentColor,
+}
+
+impl Paint {
+    fn parse(value: &Value) -> Option<Paint> {
+        match *value {
+            Value::Keyword(ref k) if k.eq_ignore_ascii_case("none") => Some(Paint::None),
+            Value::Keyword(ref k) if k.eq_ignore_ascii_case("currentcolor") => {
+                Some(Paint::CurrentColor)
+            }
+            ref value => value.to_color().map(Paint::Color),
+        }
+    }
+
+    /// The color painted with, if any. ``color`` is the current color.
+    pub fn resolve(self, color: Color) -> Option<Color> {
+        match self {
+            Paint::None => None,
+            Paint::Color(color) => Some(color),
+            Paint::CurrentColor => Some(color),
+        }
+    }
+}
+
+/// The painting properties of an element, inherited by its children
+#[derive(Clone, Debug, PartialEq)]
+pub struct SvgStyle {
+    pub fill: Paint,
+    pub fill_rule: FillRule,
+    pub stroke: Paint,
+    /// In user units
+    pub stroke_width: f64,
+    /// In user units. None is the font size of the ``<svg>``.
+    pub font_size: Option<f64>,
+}
+
+impl Default for SvgStyle {
+    fn default() -> SvgStyle {
+        SvgStyle {
+            fill: Paint::Color(BLACK),
+            fill_rule: FillRule::NonZero,
+            stroke: Paint::None,
+            stroke_width: 1.0,
+            font_size: None,
+        }
+    }
+}
+
+impl SvgStyle {
+    /// The style of ``element``, a child of an element with this style
+    fn cascade(&self, element: &ElementData) -> SvgStyle {
+        let mut style = self.clone();
+        let mut declarations: Vec<(String, Value)> = vec![];
+        for name in &["fill", "fill-rule", "stroke", "stroke-width", "font-size"] {
+            if let Some(value) = element.attrs.get(*name) {
+                declarations.push((name.to_string(), css::parse_value(value.clone())));
+            }
+        }
+        // The ``style`` attribute wins over the presentation attributes.
+        if let Some(source) = element.attrs.get("style") {
+            for declaration in css::parse_attr_style(source.clone()) {
+                if let Some(value) = declaration.values.into_iter().next() {
+                    declarations.push((declaration.name, value));
+                }
+            }
+        }
+
+        for (name, value) in declarations {
+            match name.as_str() {
+                "fill" => style.fill = Paint::parse(&value).unwrap_or(style.fill),
+                "stroke" => style.stroke = Paint::parse(&value).unwrap_or(style.stroke),
+                "fill-rule" => match value {
+                    Value::Keyword(ref k) if k == "evenodd" => style.fill_rule = FillRule::EvenOdd,
+                    Value::Keyword(ref k) if k == "nonzero" => style.fill_rule = FillRule::NonZero,
+                    _ => {}
+                },
+                "stroke-width" => {
+                    if let Some(width) = user_length(&value).filter(|&width| width >= 0.0) {
+                        style.stroke_width = width;
+                    }
+                }
+                "font-size" => {
+                    if let Some(size) = user_length(&value).filter(|&size| size >= 0.0) {
+                        style.font_size = Some(size);
+                    }
+                }
+                _ => {}
+            }
+        }
+        style
+    }
+}
+
+/// What an SVG paints, in user units
+#[derive(Clone, Debug, PartialEq)]
+pub enum Graphic {
+    Path(Vec<PathSegment>, SvgStyle),
+    /// The text, and the start of its baseline
+    Text(String, (f64, f64), SvgStyle),
+}
+
+/// The SVG DOM of an ``<svg>``: what it paints, in order
+#[derive(Clone, Debug, PartialEq)]
+pub struct Svg {
+    /// The min-x, the min-y, the width and the height of the user space fit into the viewport
+    pub view_box: Option<(f64, f64, f64, f64)>,
+    pub graphics: Vec<Graphic>,
+}
+
+/// Maps the user space of an ``<svg>`` to the document: scaled by ``scale``, then moved by
+/// ``translate``
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub scale: f64,
+    pub translate: (f64, f64),
+}
+
+impl Transform {
+    pub fn apply(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (
+            x * self.scale + self.translate.0,
+            y * self.scale + self.translate.1,
+        )
+    }
+}
+
+impl Svg {
+    /// Parses the subtree of the ``<svg>`` ``node``. The elements not supported are skipped with
+    /// their children.
+    pub fn from_node(node: &Node) -> Svg {
+        let mut graphics = vec![];
+        let style = match node.data {
+            NodeType::Element(ref element) => SvgStyle::default().cascade(element),
+            NodeType::Text(_) => SvgStyle::default(),
+        };
+        collect_graphics(node, &style, &mut graphics);
+        Svg {
+            view_box: view_box(node),
+            graphics: graphics,
+        }
+    }
+
+    /// The transform from the user space to ``viewport``, the content box in the document
+    pub fn transform(&self, viewport: Rect) -> Transform {
+        let (x, y) = (viewport.x.to_f64_px(), viewport.y.to_f64_px());
+        let (width, height) = (viewport.width.to_f64_px(), viewport.height.to_f64_px());
+        match self.view_box {
+            Some((min_x, min_y, box_width, box_height)) => {
+                let scale = (width / box_width).min(height / box_height);
+                Transform {
+                    scale: scale,
+                    translate: (
+                        x + (width - box_width * scale) / 2.0 - min_x * scale,
+                        y + (height - box_height * scale) / 2.0 - min_y * scale,
+                    ),
+                }
+            }
+            None => Transform {
+                scale: 1.0,
+                translate: (x, y),
+            },
+        }
+    }
+}
+
+/// The size of the ``<svg>`` ``node`` (px): its ``width`` and ``height``, or ``DEFAULT_SIZE``.
+/// With only one of them, the other is given by the aspect ratio of the ``viewBox``. The
+/// percentages are resolved later, like those of ``<img>``.
+pub fn intrinsic_size(node: &Node) -> (f64, f64) {
+    let length = |name: &str| length_attr(node, name).filter(|&length| length >= 0.0);
+    let ratio = view_box(node).map(|(_, _, width, height)| width / height);
+    match (length("width"), length("height"), ratio) {
+        (Some(width), Some(height), _) => (width, height),
+        (Some(width), None, Some(ratio)) => (width, width / ratio),
+        (None, Some(height), Some(ratio)) => (height * ratio, height),
+        (None, None, Some(ratio)) => (DEFAULT_SIZE.0, DEFAULT_SIZE.0 / ratio),
+        (width, height, None) => (
+            width.unwrap_or(DEFAULT_SIZE.0),
+            height.unwrap_or(DEFAULT_SIZE.1),
+        ),
+    }
+}
+
+fn view_box(node: &Node) -> Option<(f64, f64, f64, f64)> {
+    let source = match node.data {
+        NodeType::Element(ref element) => element.attrs.get("viewBox")?,
+        NodeType::Text(_) => return None,
+    };
+    let numbers = parse_numbers(source);
+    match numbers[..] {
+        [x, y, width, height] if width > 0.0 && height > 0.0 => Some((x, y, width, height)),
+        _ => None,
+    }
+}
+
+/// A length in user units (or px), e.g. ``2`` or ``2px``
+fn user_length(value: &Value) -> Option<f64> {
+    match *value {
+        Value::Num(n) | Value::Length(n, Unit::Px) => Some(n),
+        _ => None,
+    }
+}
+
+/// The attribute ``name`` of ``node`` as a length in user units
+fn length_attr(node: &Node, name: &str) -> Option<f64> {
+    user_length(&node.attr(name)?)
+}
+
+fn collect_graphics(node: &Node, style: &SvgStyle, graphics: &mut Vec<Graphic>) {
+    for child in &node.children {
+        let element = match child.data {
+            NodeType::Element(ref element) => element,
+            NodeType::Text(_) => continue,
+        };
+        let style = style.cascade(element);
+        let number = |name: &str| length_attr(child, name).unwrap_or(0.0);
+        let segments = match element.tag_name.as_str() {
+            "g" | "a" | "svg" => {
+                collect_graphics(child, &style, graphics);
+                continue;
+            }
+            "rect" => {
+                let (x, y, width, height) =
+                    (number("x"), number("y"), number("width"), number("height"));
+                // A missing radius is the other one.
+                let (rx, ry) = match (length_attr(child, "rx"), length_attr(child, "ry")) {
+                    (Some(rx), Some(ry)) => (rx, ry),
+                    (Some(r), None) | (None, Some(r)) => (r, r),
+                    (None, None) => (0.0, 0.0),
+                };
+                if width <= 0.0 || height <= 0.0 {
+                    continue;
+                }
+                rect(x, y, width, height, rx.min(width / 2.0), ry.min(height / 2.0))
+            }
+            "circle" => {
+                let r = number("r");
+                ellipse(number("cx"), number("cy"), r, r)
+            }
+            "ellipse" => ellipse(number("cx"), number("cy"), number("rx"), number("ry")),
+            "line" => vec![
+                PathSegment::MoveTo(number("x1"), number("y1")),
+                PathSegment::LineTo(number("x2"), number("y2")),
+            ],
+            "polyline" | "polygon" => {
+                let numbers = parse_numbers(element.attrs.get("points").map_or("", |p| p));
+                let mut segments: Vec<PathSegment> = numbers
+                    .chunks(2)
+                    .filter(|point| point.len() == 2)
+                    .enumerate()
+                    .map(|(i, point)| match i {
+                        0 => PathSegment::MoveTo(point[0], point[1]),
+                        _ => PathSegment::LineTo(point[0], point[1]),
+                    })
+                    .collect();
+                if element.tag_name == "polygon" && !segments.is_empty() {
+                    segments.push(PathSegment::Close);
+                }
+                segments
+            }
+            "path" => parse_path_data(element.attrs.get("d").map_or("", |d| d)),
+            "text" => {
+                let text = child.text_content();
+                let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                if !text.is_empty() {
+                    graphics.push(Graphic::Text(text, (number("x"), number("y")), style));
+                }
+                continue;
+            }
+            // e.g. ``defs`` and ``title``, which are not painted
+            _ => continue,
+        };
+        if !segments.is_empty() {
+            graphics.push(Graphic::Path(segments, style));
+        }
+    }
+}
+
+/// A rect with corners rounded by ``rx`` and ``ry``
+fn rect(x: f64, y: f64, width: f64, height: f64, rx: f64, ry: f64) -> Vec<PathSegment> {
+    let (right, bottom) = (x + width, y + height);
+    if rx <= 0.0 || ry <= 0.0 {
+        return vec![
+            PathSegment::MoveTo(x, y),
+            PathSegment::LineTo(right, y),
+            PathSegment::LineTo(right, bottom),
+            PathSegment::LineTo(x, bottom),
+            PathSegment::Close,
+        ];
+    }
+    let mut segments = vec![PathSegment::MoveTo(x + rx, y)];
+    let corners = [
+        ((right - rx, y), (right, y + ry)),
+        ((right, bottom - ry), (right - rx, bottom)),
+        ((x + rx, bottom), (x, bottom - ry)),
+        ((x, y + ry), (x + rx, y)),
+    ];
+    for &(start, end) in &corners {
+        segments.push(PathSegment::LineTo(start.0, start.1));
+        segments.extend(arc(start, (rx, ry), 0.0, false, true, end));
+    }
+    segments.push(PathSegment::Close);
+    segments
+}
+
+fn ellipse(cx: f64, cy: f64, rx: f64, ry: f64) -> Vec<PathSegment> {
+    if rx <= 0.0 || ry <= 0.0 {
+        return vec![];
+    }
+    let mut segments = vec![PathSegment::MoveTo(cx + rx, cy)];
+    segments.extend(arc((cx + rx, cy), (rx, ry), 0.0, false, true, (cx - rx, cy)));
+    segments.extend(arc((cx - rx, cy), (rx, ry), 0.0, false, true, (cx + rx, cy)));
+    segments.push(PathSegment::Close);
+    segments
+}
+
+/// The curves of an elliptical arc from ``from`` to ``to``, with the radii and the rotation (in
+/// degrees) of the ellipse, at most a quarter of it a curve.
+/// ref. https://www.w3.org/TR/SVG2/implnote.html#ArcImplementationNotes
+fn arc(
+    from: (f64, f64),
+    radii: (f64, f64),
+    rotation: f64,
+    large_arc: bool,
+    sweep: bool,
+    to: (f64, f64),
+) -> Vec<PathSegment> {
+    if from == to {
+        return vec![];
+    }
+    let (mut rx, mut ry) = (radii.0.abs(), radii.1.abs());
+    if rx == 0.0 || ry == 0.0 {
+        return vec![PathSegment::LineTo(to.0, to.1)];
+    }
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    let (dx, dy) = ((from.0 - to.0) / 2.0, (from.1 - to.1) / 2.0);
+    let (x1, y1) = (cos * dx + sin * dy, -sin * dx + cos * dy);
+    // Too small radii are scaled up to reach ``to``.
+    let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+    if lambda > 1.0 {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+    let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+    let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let sign = if large_arc == sweep { -1.0 } else { 1.0 };
+    let coefficient = sign * (numerator / denominator).max(0.0).sqrt();
+    let (cx1, cy1) = (coefficient * rx * y1 / ry, -coefficient * ry * x1 / rx);
+    let center = (
+        cos * cx1 - sin * cy1 + (from.0 + to.0) / 2.0,
+        sin * cx1 + cos * cy1 + (from.1 + to.1) / 2.0,
+    );
+
+    let angle = |(ux, uy): (f64, f64), (vx, vy): (f64, f64)| {
+        (ux * vy - uy * vx).atan2(ux * vx + uy * vy)
+    };
+    let start = angle((1.0, 0.0), ((x1 - cx1) / rx, (y1 - cy1) / ry));
+    let mut sweep_angle = angle(
+        ((x1 - cx1) / rx, (y1 - cy1) / ry),
+        ((-x1 - cx1) / rx, (-y1 - cy1) / ry),
+    );
+    if !sweep && sweep_angle > 0.0 {
+        sweep_angle -= 2.0 * PI;
+    } else if sweep && sweep_angle < 0.0 {
+        sweep_angle += 2.0 * PI;
+    }
+
+    // The points of the ellipse, and their tangents
+    let point = |t: f64| {
+        let (x, y) = (rx * t.cos(), ry * t.sin());
+        (center.0 + cos * x - sin * y, center.1 + sin * x + cos * y)
+    };
+    let tangent = |t: f64| {
+        let (x, y) = (-rx * t.sin(), ry * t.cos());
+        (cos * x - sin * y, sin * x + cos * y)
+    };
+    let curves = (sweep_angle.abs() / (PI / 2.0) - 1e-9).ceil().max(1.0) as usize;
+    let delta = sweep_angle / curves as f64;
+    let k = 4.0 / 3.0 * (delta / 4.0).tan();
+    (0..curves)
+        .map(|i| {
+            let (t1, t2) = (start + delta * i as f64, start + delta * (i + 1) as f64);
+            let (p1, d1, d2) = (point(t1), tangent(t1), tangent(t2));
+            let p2 = if i + 1 == curves { to } else { point(t2) };
+            PathSegment::CurveTo(
+                p1.0 + k * d1.0,
+                p1.1 + k * d1.1,
+                p2.0 - k * d2.0,
+                p2.1 - k * d2.1,
+                p2.0,
+                p2.1,
+            )
+        })
+        .collect()
+}
+
+/// Skips the whitespace and a comma, then parses a number, e.g. ``-1.5e2``. None (and nothing is
+/// consumed) if there is none.
+fn parse_number(rest: &mut &str) -> Option<f64> {
+    let start = rest.trim_start();
+    let start = start.strip_prefix(',').unwrap_or(start).trim_start();
+    let bytes = start.as_bytes();
+    let mut end = 0;
+    if end < bytes.len() && (bytes[end] == b'+' || bytes[end] == b'-') {
+        end += 1;
+    }
+    let digits = |end: &mut usize| {
+        let from = *end;
+        while *end < bytes.len() && bytes[*end].is_ascii_digit() {
+            *end += 1;
+        }
+        *end > from
+    };
+    let mut has_digits = digits(&mut end);
+    if end < bytes.len() && bytes[end] == b'.' {
+        end += 1;
+        has_digits |= digits(&mut end);
+    }
+    if !has_digits {
+        return None;
+    }
+    // An exponent only if digits follow, e.g. not in ``1em``
+    if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+        let mut exponent_end = end + 1;
+        if bytes
+            .get(exponent_end)
+            .is_some_and(|&sign| sign == b'+' || sign == b'-')
+        {
+            exponent_end += 1;
+        }
+        if digits(&mut exponent_end) {
+            end = exponent_end;
+        }
+    }
+    let number = start[..end].parse().ok()?;
+    *rest = &start[end..];
+    Some(number)
+}
+
+/// The numbers separated by whitespace or commas in ``source``, until one that is not a number
+fn parse_numbers(source: &str) -> Vec<f64> {
+    let mut rest = source;
+    let mut numbers = vec![];
+    while let Some(number) = parse_number(&mut rest) {
+        numbers.push(number);
+    }
+    numbers
+}
+
+/// Parses the flag of an arc, ``0`` or ``1``, which needs no separator after it.
+fn parse_flag(rest: &mut &str) -> Option<bool> {
+    let start = rest.trim_start();
+    let start = start.strip_prefix(',').unwrap_or(start).trim_start();
+    let flag = match start.chars().next()? {
+        '0' => false,
+        '1' => true,
+        _ => return None,
+    };
+    *rest = &start[1..];
+    Some(flag)
+}
+
+/// Parses the ``d`` attribute of a ``<path>``. The arcs and the quadratic curves become cubic
+/// curves. An error ends the path (what is before it is kept), e.g. one not starting with ``M``.
+/// ref. https://www.w3.org/TR/SVG2/paths.html#PathData
+pub fn parse_path_data(source: &str) -> Vec<PathSegment> {
+    let mut segments = vec![];
+    let mut rest = source;
+    let mut last_command = None;
+    let (mut current, mut start) = ((0.0, 0.0), (0.0, 0.0));
+    // The second control point of the last cubic and of the last quadratic curve, reflected by
+    // ``S`` and ``T``
+    let (mut last_cubic, mut last_quadratic): (Option<Point>, Option<Point>) = (None, None);
+
+    loop {
+        rest = rest.trim_start();
+        let c = match rest.chars().next() {
+            Some(c) => c,
+            None => break,
+        };
+        if c.is_ascii_alphabetic() {
+            last_command = Some(c);
+            rest = &rest[1..];
+            if c == 'Z' || c == 'z' {
+                segments.push(PathSegment::Close);
+                current = start;
+                last_cubic = None;
+                last_quadratic = None;
+                continue;
+            }
+        }
+        let command = match last_command {
+            Some(command) if command != 'Z' && command != 'z' => command,
+            _ => break,
+        };
+        // A path starts with a move.
+        if segments.is_empty() && command != 'M' && command != 'm' {
+            break;
+        }
+        let origin = if command.is_ascii_lowercase() { current } else { (0.0, 0.0) };
+        let point = |rest: &mut &str| -> Option<(f64, f64)> {
+            let x = parse_number(rest)?;
+            let y = parse_number(rest)?;
+            Some((origin.0 + x, origin.1 + y))
+        };
+        let reflect = |control: Option<(f64, f64)>| match control {
+            Some((x, y)) => (2.0 * current.0 - x, 2.0 * current.1 - y),
+            None => current,
+        };
+
+        let absolute_command = command.to_ascii_uppercase();
+        let segment = match absolute_command {
+            'M' => point(&mut rest).map(|p| {
+                start = p;
+                PathSegment::MoveTo(p.0, p.1)
+            }),
+            'L' => point(&mut rest).map(|p| PathSegment::LineTo(p.0, p.1)),
+            'H' => parse_number(&mut rest).map(|x| PathSegment::LineTo(origin.0 + x, current.1)),
+            'V' => parse_number(&mut rest).map(|y| PathSegment::LineTo(current.0, origin.1 + y)),
+            'C' | 'S' => {
+                let first = if absolute_command == 'C' {
+                    point(&mut rest)
+                } else {
+                    Some(reflect(last_cubic))
+                };
+                first.and_then(|c1| {
+                    let c2 = point(&mut rest)?;
+                    let p = point(&mut rest)?;
+                    Some(PathSegment::CurveTo(c1.0, c1.1, c2.0, c2.1, p.0, p.1))
+                })
+            }
+            'Q' | 'T' => {
+                let control = if absolute_command == 'Q' {
+                    point(&mut rest)
+                } else {
+                    Some(reflect(last_quadratic))
+                };
+                control.and_then(|q| point(&mut rest).map(|p| (q, p))).map(|(q, p)| {
+                    last_quadratic = Some(q);
+                    // The control points of the same cubic curve
+                    let towards = |(x, y): (f64, f64)| {
+                        (x + (q.0 - x) * 2.0 / 3.0, y + (q.1 - y) * 2.0 / 3.0)
+                    };
+                    let (c1, c2) = (towards(current), towards(p));
+                    PathSegment::CurveTo(c1.0, c1.1, c2.0, c2.1, p.0, p.1)
+                })
+            }
+            'A' => {
+                let mut parameters = || -> Option<(Point, f64, bool, bool, Point)> {
+                    let rx = parse_number(&mut rest)?;
+                    let ry = parse_number(&mut rest)?;
+                    let rotation = parse_number(&mut rest)?;
+                    let large_arc = parse_flag(&mut rest)?;
+                    let sweep = parse_flag(&mut rest)?;
+                    Some(((rx, ry), rotation, large_arc, sweep, point(&mut rest)?))
+                };
+                match parameters() {
+                    Some((radii, rotation, large_arc, sweep, p)) => {
+                        segments.extend(arc(current, radii, rotation, large_arc, sweep, p));
+                        current = p;
+                        last_cubic = None;
+                        last_quadratic = None;
+                        continue;
+                    }
+                    None => None,
+                }
+            }
+            _ => None,
+        };
+
+        let segment = match segment {
+            Some(segment) => segment,
+            None => break,
+        };
+        match segment {
+            PathSegment::CurveTo(_, _, x2, y2, x, y) => {
+                if absolute_command == 'C' || absolute_command == 'S' {
+                    last_cubic = Some((x2, y2));
+                    last_quadratic = None;
+                } else {
+                    last_cubic = None;
+                }
+                current = (x, y);
+            }
+            PathSegment::MoveTo(x, y) | PathSegment::LineTo(x, y) => {
+                last_cubic = None;
+                last_quadratic = None;
+                current = (x, y);
+            }
+            PathSegment::Close => {}
+        }
+        // The points after those of a move are lines.
+        if let PathSegment::MoveTo(_, _) = segment {
+            last_command = Some(if command == 'm' { 'l' } else { 'L' });
+        }
+        segments.push(segment);
+    }
+    segments
+}
+
+#[test]
+fn test_parse_path_data() {
+    use self::PathSegment::*;
+
+    assert_eq!(
+        parse_path_data("M10,20 l5 0 5-5H0v-1.5e1z m1 1 2 2"),
+        vec![
+            MoveTo(10.0, 20.0),
+            LineTo(15.0, 20.0),
+            LineTo(20.0, 15.0),
+            LineTo(0.0, 15.0),
+            LineTo(0.0, 0.0),
+            Close,
+            MoveTo(11.0, 21.0),
+            LineTo(13.0, 23.0),
+        ]
+    );
+    // A quadratic curve becomes a cubic one, and ``S`` reflects the last control point.
+    assert_eq!(
+        parse_path_data("M0 0Q3 3 6 0C6 1 7 1 8 0S10-1 10 0"),
+        vec![
+            MoveTo(0.0, 0.0),
+            CurveTo(2.0, 2.0, 4.0, 2.0, 6.0, 0.0),
+            CurveTo(6.0, 1.0, 7.0, 1.0, 8.0, 0.0),
+            CurveTo(9.0, -1.0, 10.0, -1.0, 10.0, 0.0),
+        ]
+    );
+    // A half circle is two curves, and the flags need no separators.
+    let arc = parse_path_data("M0 0a5 5 0 01 10 0");
+    assert_eq!(arc.len(), 3);
+    match arc[2] {
+        CurveTo(_, _, _, _, x, y) => assert_eq!((x, y), (10.0, 0.0)),
+        ref segment => panic!("{:?}", segment),
+    }
+    // An error ends the path.
+    assert_eq!(parse_path_data("M1 1 L2 x 3 3"), vec![MoveTo(1.0, 1.0)]);
+    assert_eq!(parse_path_data("L1 1"), vec![]);
+}
+
+#[test]
+fn test_svg_dom() {
+    use css::RED;
+    use html;
+    use network::Url;
+
+    let document = html::parse(
+        "<svg width=\"20\" viewBox=\"0 0 10 5\" fill=\"red\"><g stroke=\"currentColor\">\
+         <rect width=\"4\" height=\"2\" rx=\"1\"/><line x2=\"3\" style=\"stroke-width: 2\"/></g>\
+         <circle r=\"0\"/><text x=\"1\" y=\"4\" fill=\"none\"> a <tspan>b</tspan></text>\
+         <defs><rect width=\"1\" height=\"1\"/></defs></svg>"
+            .to_string(),
+        Url::parse("file:///a.html").unwrap(),
+    );
+    assert_eq!(intrinsic_size(&document), (20.0, 10.0));
+    let svg = Svg::from_node(&document);
+    assert_eq!(svg.view_box, Some((0.0, 0.0, 10.0, 5.0)));
+    assert_eq!(svg.graphics.len(), 3);
+    match svg.graphics[0] {
+        Graphic::Path(ref segments, ref style) => {
+            assert_eq!(segments.len(), 10);
+            assert_eq!(style.fill, Paint::Color(RED));
+            assert_eq!(style.stroke, Paint::CurrentColor);
+        }
+        ref graphic => panic!("{:?}", graphic),
+    }
+    match svg.graphics[1] {
+        Graphic::Path(_, ref style) => assert_eq!(style.stroke_width, 2.0),
+        ref graphic => panic!("{:?}", graphic),
+    }
+    match svg.graphics[2] {
+        Graphic::Text(ref text, position, ref style) => {
+            assert_eq!((text.as_str(), position, style.fill), ("a b", (1.0, 4.0), Paint::None));
+        }
+        ref graphic => panic!("{:?}", graphic),
+    }
+
+    // The viewBox is scaled to fit, and centered.
+    let viewport = Rect {
+        x: Au::from_f64_px(10.0),
+        y: Au::from_f64_px(0.0),
+        width: Au::from_f64_px(20.0),
+        height: Au::from_f64_px(20.0),
+    };
+    let transform = svg.transform(viewport);
+    assert_eq!(transform.apply((0.0, 0.0)), (10.0, 5.0));
+    assert_eq!(transform.apply((10.0, 5.0)), (30.0, 15.0));
+}
@@ -0,0 +1,115 @@
+//! The task queue of the event loop: work scheduled to run at a later time, e.g. the timers of
+//! scripts (``setTimeout`` and ``setInterval``) and tasks of the engine (``interface::post_task``).
+//! The window runs the tasks due from the GTK main loop (see ``interface::run_due_tasks``).
+
+use std::time::Instant;
+
+/// Given by ``TaskQueue::schedule`` to cancel the task. It is never 0.
+pub type TaskId = usize;
+
+struct Task<T> {
+    id: TaskId,
+    due: Instant,
+    task: T,
+}
+
+/// Tasks run in the order they are due, and those due at the same time in the order they are
+/// scheduled.
+pub struct TaskQueue<T> {
+    tasks: Vec<Task<T>>,
+    last_id: TaskId,
+}
+
+impl<T> Default for TaskQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> TaskQueue<T> {
+    pub fn new() -> TaskQueue<T> {
+        TaskQueue {
+            tasks: vec![],
+            last_id: 0,
+        }
+    }
+
+    /// Schedules ``task`` to run at ``due``.
+    pub fn schedule(&mut self, due: Instant, task: T) -> TaskId {
+        self.last_id += 1;
+        let id = self.last_id;
+        self.schedule_again(id, due, task);
+        id
+    }
+
+    /// Schedules ``task`` popped as ``id`` again (e.g. the next run of an interval), so that it
+    /// can still be canceled with ``id``.
+    pub fn schedule_again(&mut self, id: TaskId, due: Instant, task: T) {
+        // After the tasks due at the same time
+        let i = self.tasks.iter().position(|t| t.due > due).unwrap_or(self.tasks.len());
+        self.tasks.insert(
+            i,
+            Task {
+                id: id,
+                due: due,
+                task: task,
+            },
+        );
+    }
+
+    /// Removes the task ``id``. Returns it, or None if it has run or is canceled already.
+    pub fn cancel(&mut self, id: TaskId) -> Option<T> {
+        let i = self.tasks.iter().position(|t| t.id == id)?;
+        Some(self.tasks.remove(i).task)
+    }
+
+    /// When the first task is due.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.tasks.first().map(|t| t.due)
+    }
+
+    /// The number of tasks due at ``now``.
+    pub fn count_due(&self, now: Instant) -> usize {
+        self.tasks.iter().take_while(|t| t.due <= now).count()
+    }
+
+    /// Removes the first task if it is due at ``now``, and returns it with its id and when it
+    /// was due.
+    pub fn pop_due(&mut self, now: Instant) -> Option<(TaskId, Instant, T)> {
+        if self.next_due()? > now {
+            return None;
+        }
+        let t = self.tasks.remove(0);
+        Some((t.id, t.due, t.task))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+}
+
+#[test]
+fn test_task_queue() {
+    use std::time::Duration;
+
+    let now = Instant::now();
+    let ms = |ms: u64| now + Duration::from_millis(ms);
+    let mut queue = TaskQueue::new();
+    let a = queue.schedule(ms(20), "a");
+    let b = queue.schedule(ms(10), "b");
+    queue.schedule(ms(10), "c");
+    assert!(a != 0 && a != b);
+    assert_eq!(queue.next_due(), Some(ms(10)));
+    assert_eq!(queue.count_due(ms(15)), 2);
+
+    assert_eq!(queue.cancel(b), Some("b"));
+    assert_eq!(queue.cancel(b), None);
+    assert_eq!(queue.pop_due(ms(5)), None);
+    assert_eq!(queue.pop_due(ms(30)).map(|t| t.2), Some("c"));
+    let (id, due, task) = queue.pop_due(ms(30)).unwrap();
+    assert_eq!((id, due, task), (a, ms(20), "a"));
+
+    queue.schedule_again(a, ms(40), task);
+    assert_eq!(queue.cancel(a), Some("a"));
+    assert!(queue.is_empty());
+}
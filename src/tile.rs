@@ -0,0 +1,220 @@
+//! Tiled rasterization: the page is rasterized into tiles of ``TILE_SIZE`` device px, only
+//! around the viewport, so that scrolling a long document shows the tiles kept from the last
+//! frames and rasterizes only those scrolled into view (see ``raster::paint_tile``). Each tile
+//! paints only the items of the display list over it (see ``cull``).
+
+use layout::Rect;
+use painter::{DisplayCommand, DisplayCommandInfo, DisplayList};
+
+use std::collections::HashMap;
+
+use app_units::Au;
+use cairo;
+
+/// The width and the height of a tile in device px
+pub const TILE_SIZE: i32 = 512;
+
+/// (column, row) of a tile
+pub type TileIndex = (i32, i32);
+
+/// (x, y, width, height) in device px
+pub type DeviceRect = (i32, i32, i32, i32);
+
+/// The tiles of the page rasterized from a display list, at a scale.
+pub struct TileCache {
+    /// Device px per CSS px
+    pub scale: f64,
+    /// The size of the document in device px
+    pub size: (i32, i32),
+    tiles: HashMap<TileIndex, cairo::ImageSurface>,
+}
+
+impl TileCache {
+    pub fn new(scale: f64, size: (i32, i32)) -> TileCache {
+        TileCache {
+            scale: scale,
+            size: size,
+            tiles: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, index: TileIndex) -> Option<&cairo::ImageSurface> {
+        self.tiles.get(&index)
+    }
+
+    pub fn insert(&mut self, index: TileIndex, tile: cairo::ImageSurface) {
+        self.tiles.insert(index, tile);
+    }
+
+    /// Drops the tiles but ``keep``.
+    pub fn retain(&mut self, keep: &[TileIndex]) {
+        self.tiles.retain(|index, _| keep.contains(index));
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// The first of ``indices`` not rasterized yet
+    pub fn missing(&self, indices: &[TileIndex]) -> Option<TileIndex> {
+        indices
+            .iter()
+            .find(|index| !self.tiles.contains_key(index))
+            .cloned()
+    }
+
+    /// The tiles over ``area`` (device px: x, y, width, height) in the document, from the top
+    pub fn tiles_in(&self, area: (f64, f64, f64, f64)) -> Vec<TileIndex> {
+        tiles_in(area, self.size)
+    }
+
+    /// The part of the document tile ``index`` is
+    pub fn tile_rect(&self, index: TileIndex) -> DeviceRect {
+        tile_rect(index, self.size)
+    }
+}
+
+/// The tiles over ``area`` (device px) of a document of ``size`` device px, row by row from the
+/// top.
+pub fn tiles_in(area: (f64, f64, f64, f64), size: (i32, i32)) -> Vec<TileIndex> {
+    let (x, y, width, height) = area;
+    let tile = TILE_SIZE as f64;
+    let first_column = (x / tile).floor().max(0.0) as i32;
+    let first_row = (y / tile).floor().max(0.0) as i32;
+    let last_column = (((x + width).min(size.0 as f64) / tile).ceil() as i32).min(columns(size));
+    let last_row = (((y + height).min(size.1 as f64) / tile).ceil() as i32).min(rows(size));
+    let mut indices = vec![];
+    for row in first_row..last_row {
+        for column in first_column..last_column {
+            indices.push((column, row));
+        }
+    }
+    indices
+}
+
+fn columns(size: (i32, i32)) -> i32 {
+    (size.0 + TILE_SIZE - 1) / TILE_SIZE
+}
+
+fn rows(size: (i32, i32)) -> i32 {
+    (size.1 + TILE_SIZE - 1) / TILE_SIZE
+}
+
+/// The part of a document of ``size`` device px tile ``index`` is (smaller at the right and the
+/// bottom edges)
+pub fn tile_rect(index: TileIndex, size: (i32, i32)) -> DeviceRect {
+    let (x, y) = (index.0 * TILE_SIZE, index.1 * TILE_SIZE);
+    (
+        x,
+        y,
+        TILE_SIZE.min(size.0 - x).max(1),
+        TILE_SIZE.min(size.1 - y).max(1),
+    )
+}
+
+/// The size in device px of the document of the display list (its first item) at ``scale``
+pub fn document_size(items: &DisplayList, scale: f64) -> (i32, i32) {
+    match items.first().map(|item| &item.command) {
+        Some(&DisplayCommand::SolidColor(_, rect)) => (
+            ((rect.width.to_f64_px() * scale).ceil() as i32).max(1),
+            ((rect.height.to_f64_px() * scale).ceil() as i32).max(1),
+        ),
+        _ => (1, 1),
+    }
+}
+
+/// The items of ``items`` that may paint over ``rect`` (CSS px). The clips and the layers are
+/// all kept, so that they still match.
+pub fn cull(items: &DisplayList, rect: Rect) -> DisplayList {
+    items
+        .iter()
+        .filter(|item| match bounds(item) {
+            Some(bounds) => bounds.intersection(rect).is_some(),
+            None => true,
+        })
+        .cloned()
+        .collect()
+}
+
+/// Where ``item`` paints (CSS px), None if it is not known or it is not painted
+fn bounds(item: &DisplayCommandInfo) -> Option<Rect> {
+    match item.command {
+        DisplayCommand::SolidColor(_, rect) => Some(rect),
+        #[cfg(feature = "gtk")]
+        DisplayCommand::Button(_, rect) => Some(rect),
+        DisplayCommand::Image(_, ref metadata, rect) => Some(Rect {
+            width: metadata.width,
+            height: metadata.height,
+            ..rect
+        }),
+        DisplayCommand::ImageTiles(_, _, _, clip) => Some(clip),
+        // Glyphs may overhang the line (e.g. italics), by a half of it at most.
+        DisplayCommand::Text(_, rect, _, _, _) | DisplayCommand::TextShadow(_, rect, _, _, _) => {
+            let overhang = rect.height / 2 + Au::from_px(2);
+            Some(Rect {
+                x: rect.x - overhang,
+                y: rect.y - overhang,
+                width: rect.width + overhang * 2,
+                height: rect.height + overhang * 2,
+            })
+        }
+        DisplayCommand::Path(ref path) => path.bounds(),
+        DisplayCommand::PushClip(_)
+        | DisplayCommand::PushClipPath(_)
+        | DisplayCommand::PopClip
+        | DisplayCommand::PushLayer(_)
+        | DisplayCommand::PopLayer(_) => None,
+    }
+}
+
+#[test]
+fn test_tiles_in() {
+    let size = (800, 50_000);
+    assert_eq!(tiles_in((0.0, 0.0, 800.0, 600.0), size), vec![(0, 0), (1, 0), (0, 1), (1, 1)]);
+    // Scrolled far down: only the tiles over the viewport
+    assert_eq!(
+        tiles_in((0.0, 30_000.0, 800.0, 600.0), size),
+        vec![(0, 58), (1, 58), (0, 59), (1, 59)]
+    );
+    // Clamped to the document
+    assert_eq!(tiles_in((0.0, 49_900.0, 800.0, 600.0), size), vec![(0, 97), (1, 97)]);
+    assert_eq!(tiles_in((0.0, -600.0, 800.0, 600.0), size), vec![]);
+
+    assert_eq!(tile_rect((1, 97), size), (512, 49_664, 288, 336));
+}
+
+#[test]
+fn test_cull() {
+    use css::Color;
+
+    let rect = |x: i32, y: i32, width: i32, height: i32| Rect {
+        x: Au::from_px(x),
+        y: Au::from_px(y),
+        width: Au::from_px(width),
+        height: Au::from_px(height),
+    };
+    let black = Color {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 255,
+    };
+    let items: DisplayList = vec![
+        DisplayCommand::SolidColor(black.clone(), rect(0, 0, 100, 10_000)),
+        DisplayCommand::PushClip(rect(0, 0, 100, 100)),
+        DisplayCommand::SolidColor(black.clone(), rect(0, 50, 10, 10)),
+        DisplayCommand::PopClip,
+        DisplayCommand::SolidColor(black, rect(0, 5000, 10, 10)),
+    ].into_iter()
+        .map(DisplayCommandInfo::new)
+        .collect();
+    assert_eq!(document_size(&items, 2.0), (200, 20_000));
+
+    let culled = cull(&items, rect(0, 4900, 100, 200));
+    assert_eq!(culled.len(), 4);
+    match culled[3].command {
+        DisplayCommand::SolidColor(_, rect) => assert_eq!(rect.y, Au::from_px(5000)),
+        _ => panic!(),
+    }
+    assert_eq!(cull(&items, rect(0, 0, 100, 100)).len(), 4);
+}
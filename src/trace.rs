@@ -0,0 +1,156 @@
+//! Tracing of the pipeline with the ``log`` crate, enabled by the ``trace`` feature: a span for
+//! each phase (see ``stats::time``) and for each box laid out, and events for the used values
+//! computed in them. Without the feature, all of this compiles to nothing.
+//!
+//! ``init`` logs to stderr at the level in ``NAGLFAR_TRACE`` (``debug`` for the phases, ``trace``
+//! for the boxes too), indented by the depth of the spans:
+//!
+//! ```text
+//! $ NAGLFAR_TRACE=trace cargo run --features trace -- --headless example/test.html
+//! ```
+
+/// The environment variable with the level to log at.
+pub const TRACE_ENV: &'static str = "NAGLFAR_TRACE";
+
+/// The target of the spans of the phases.
+pub const PIPELINE_TARGET: &'static str = "naglfar::pipeline";
+/// The target of the spans and the events of the boxes.
+pub const LAYOUT_TARGET: &'static str = "naglfar::layout";
+
+/// Logs an event about the box being laid out, at the trace level. Without the ``trace``
+/// feature, the arguments are type checked but not evaluated.
+macro_rules! layout_trace {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "trace")]
+        trace!(target: ::trace::LAYOUT_TARGET, $($arg)+);
+        #[cfg(not(feature = "trace"))]
+        {
+            if false {
+                let _ = format!($($arg)+);
+            }
+        }
+    }};
+}
+
+#[cfg(feature = "trace")]
+mod logger {
+    use log::{self, Level, LevelFilter, Log, Metadata, Record};
+
+    use std::cell::Cell;
+    use std::env;
+    use std::time::Instant;
+
+    thread_local!(
+        pub static DEPTH: Cell<usize> = { Cell::new(0) };
+    );
+
+    struct StderrLogger;
+
+    impl Log for StderrLogger {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= log::max_level() && metadata.target().starts_with("naglfar")
+        }
+
+        fn log(&self, record: &Record) {
+            if self.enabled(record.metadata()) {
+                let depth = DEPTH.with(|d| d.get());
+                eprintln!("{:5} {}{}", record.level(), "  ".repeat(depth), record.args());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: StderrLogger = StderrLogger;
+
+    pub fn init() {
+        let level = env::var(super::TRACE_ENV)
+            .ok()
+            .and_then(|level| level.parse().ok())
+            .unwrap_or(LevelFilter::Off);
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(level);
+        }
+    }
+
+    /// Logged when entered, and when dropped.
+    pub struct Span {
+        target: &'static str,
+        level: Level,
+        name: String,
+        start: Instant,
+    }
+
+    pub fn enter<N: FnOnce() -> String>(
+        target: &'static str,
+        level: Level,
+        name: N,
+    ) -> Option<Span> {
+        if !log_enabled!(target: target, level) {
+            return None;
+        }
+        let span = Span {
+            target: target,
+            level: level,
+            name: name(),
+            start: Instant::now(),
+        };
+        log!(target: target, level, "> {}", span.name);
+        DEPTH.with(|d| d.set(d.get() + 1));
+        Some(span)
+    }
+
+    impl Drop for Span {
+        fn drop(&mut self) {
+            let elapsed = self.start.elapsed();
+            DEPTH.with(|d| d.set(d.get() - 1));
+            log!(
+                target: self.target,
+                self.level,
+                "< {} ({:.3} ms)",
+                self.name,
+                elapsed.as_secs() as f64 * 1000.0 + elapsed.subsec_nanos() as f64 / 1_000_000.0
+            );
+        }
+    }
+}
+
+/// Logs to stderr at the level in ``NAGLFAR_TRACE`` (nothing if unset).
+pub fn init() {
+    #[cfg(feature = "trace")]
+    logger::init();
+}
+
+/// Events logged while it is alive are indented under it. See ``pipeline_span`` and
+/// ``layout_span``.
+pub struct Span {
+    #[cfg(feature = "trace")]
+    _span: Option<logger::Span>,
+}
+
+/// Enters a span for the phase ``phase`` (at the debug level) until the result is dropped.
+pub fn pipeline_span(phase: &str) -> Span {
+    #[cfg(not(feature = "trace"))]
+    let _ = phase;
+    Span {
+        #[cfg(feature = "trace")]
+        _span: logger::enter(PIPELINE_TARGET, ::log::Level::Debug, || phase.to_string()),
+    }
+}
+
+/// Enters a span for a box (at the trace level). ``name`` is only called if it is logged.
+pub fn layout_span<N: FnOnce() -> String>(name: N) -> Span {
+    #[cfg(not(feature = "trace"))]
+    let _ = name;
+    Span {
+        #[cfg(feature = "trace")]
+        _span: logger::enter(LAYOUT_TARGET, ::log::Level::Trace, name),
+    }
+}
+
+#[test]
+fn test_spans() {
+    let _pipeline_span = pipeline_span("layout");
+    let _layout_span = layout_span(|| "BlockNode <div>".to_string());
+    layout_trace!("width: {}px", 100);
+}
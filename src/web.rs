@@ -0,0 +1,115 @@
+//! The engine in a web page: built for wasm32 without the ``raster`` and ``network`` features, a
+//! page given as HTML and CSS is laid out and painted as a script for a canvas (see
+//! ``Document::canvas_script``), which the web page runs with ``ctx`` bound to the 2D context of
+//! its canvas (see ``web/index.html``). Built with
+//!
+//! ```sh
+//! cargo rustc --lib --release --target wasm32-unknown-unknown --no-default-features \
+//!     --crate-type cdylib
+//! ```
+//!
+//! The strings cross the boundary NUL-terminated, in UTF-8, in the memory of the module: the web
+//! page writes them into buffers of ``naglfar_web_alloc`` (freed with ``naglfar_web_free``), and
+//! frees the strings returned with ``naglfar_web_string_free``. The texts are measured with
+//! estimated metrics (see ``font::Font::text_width``), and drawn with the fonts of the browser.
+
+use document::Document;
+use inline;
+use network::Url;
+
+#[cfg(target_arch = "wasm32")]
+use std::ffi::{CStr, CString};
+#[cfg(target_arch = "wasm32")]
+use std::mem;
+#[cfg(target_arch = "wasm32")]
+use std::os::raw::c_char;
+
+/// The script painting ``html`` styled with ``css`` (after its ``<style>`` tags) in a viewport of
+/// ``width`` x ``height`` CSS px, with relative URLs resolved against ``url``. The images are not
+/// loaded, as no thread can be spawned to load them (see ``inline::defer_image_loads``).
+pub fn canvas_script(html: &str, css: &str, url: Url, width: f64, height: f64) -> String {
+    inline::defer_image_loads();
+    let mut document = Document::from_html(html, url);
+    document.append_stylesheet(css);
+    document.layout(width, height);
+    document.canvas_script()
+}
+
+/// A buffer of ``len`` bytes for the web page to write a string into, to be freed by
+/// ``naglfar_web_free``.
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub extern "C" fn naglfar_web_alloc(len: usize) -> *mut c_char {
+    let mut buffer = Vec::<u8>::with_capacity(len);
+    let ptr = buffer.as_mut_ptr();
+    mem::forget(buffer);
+    ptr as *mut c_char
+}
+
+/// Frees a buffer of ``naglfar_web_alloc``.
+///
+/// # Safety
+///
+/// ``ptr`` is of ``naglfar_web_alloc(len)``, and not used afterwards.
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub unsafe extern "C" fn naglfar_web_free(ptr: *mut c_char, len: usize) {
+    drop(Vec::from_raw_parts(ptr as *mut u8, 0, len));
+}
+
+/// The script painting ``html`` and ``css`` (see ``canvas_script``), to be freed by
+/// ``naglfar_web_string_free``. NULL if a string is not UTF-8, or ``url`` is not an absolute URL.
+///
+/// # Safety
+///
+/// The strings are NUL-terminated.
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub unsafe extern "C" fn naglfar_web_paint(
+    html: *const c_char,
+    css: *const c_char,
+    url: *const c_char,
+    width: f64,
+    height: f64,
+) -> *mut c_char {
+    let to_str = |s: *const c_char| CStr::from_ptr(s).to_str().ok();
+    let url = to_str(url).and_then(|url| Url::parse(url).ok());
+    match (to_str(html), to_str(css), url) {
+        (Some(html), Some(css), Some(url)) => {
+            // The control characters are escaped in the script, NUL as well.
+            let script = canvas_script(html, css, url, width, height);
+            CString::new(script).unwrap().into_raw()
+        }
+        _ => ::std::ptr::null_mut(),
+    }
+}
+
+/// Frees a string of ``naglfar_web_paint``.
+///
+/// # Safety
+///
+/// ``s`` is of ``naglfar_web_paint``, and not used afterwards.
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub unsafe extern "C" fn naglfar_web_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+#[test]
+fn test_canvas_script() {
+    let url = Url::parse("https://example.com/").unwrap();
+    let script = canvas_script(
+        "<p>a</p><img src=\"a.png\">",
+        "body { margin: 0; } p { margin: 0; height: 10px; background-color: red; }",
+        url,
+        40.0,
+        30.0,
+    );
+    assert!(script.contains("\"rgba(255, 0, 0, 1)\";\nctx.fillRect(0, 0, 40, 10);\n"));
+    assert!(script.contains("ctx.fillText(\"a\", 0, "));
+    // The image is collected, not loaded.
+    let images = inline::deferred_image_loads();
+    assert!(!images.is_empty() && images.iter().all(|url| url.ends_with("a.png")));
+}
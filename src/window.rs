@@ -5,66 +5,288 @@ extern crate pango;
 extern crate pangocairo;
 
 use gtk::{Inhibit, ObjectExt, WidgetExt, traits::*};
-use gtk::ContainerExt;
+use gtk::{ContainerExt, EditableSignals, MenuExtManual};
 
 use glib::prelude::*; // or `use gtk::prelude::*;`
-use glib;
 
-use gdk::{ContextExt, Cursor, CursorType, Event, EventButton, EventMask, EventMotion, RGBA};
-use gdk_pixbuf::{InterpType, PixbufExt};
+use gdk::{Cursor, CursorType, Event, EventButton, EventKey, EventMask, EventMotion, FrameClockExt,
+          FrameClockPhase, ModifierType, ScrollDirection, RGBA};
+use gdk::enums::key;
 
 use cairo::Context;
 use pango::LayoutExt;
 
-use std::{cell::RefCell, collections::HashMap};
+use std::{cell::{Cell, RefCell}, cmp::min, mem, path::Path, rc::Rc, time::{Duration, Instant}};
 
-use layout::Rect;
-use painter::{DisplayCommand, DisplayList};
-use font::FONT_DESC;
-use css::{TextDecoration, px2pt};
-use interface::update_html_source;
+use containment;
+use controls::{AnkerKind, Caret, ANKERS, FOCUSED_SELECT, FOCUSED_TEXT_INPUT, SELECTS, TEXT_INPUTS,
+               URL_FRAGMENTS};
+use frame::{FrameScheduler, Invalidation};
+use gpu::{self, Renderer};
+use tile::{self, TileCache};
+use raster::paint_tile;
+use lazy;
+use overflow;
+use scroll::ScrollAnimation;
+use layout::{self, Rect};
+use media::{self, ColorScheme};
+use painter::{self, DisplayCommand, DisplayList, FOCUS_RING_COLOR};
+use font::Font;
+use css::Color;
+use interface::{self, apply_loaded_resources, box_model, current_url, cursor_at, inspect_at,
+                invalidate_display_list, load_progress, node_at, page_title, reload_html_source,
+                set_text_input_value, update_html_source};
+use dom::{Node, NodeType};
+use event::Dispatched;
+use network::{guess_url, CacheMode};
+use history::{History, HistoryEntry};
+use selection::{Selection, TextPosition};
+use selection;
+use stats;
+use style;
 
-#[derive(Clone, Debug)]
-pub enum AnkerKind {
-    URL(String),
-    URLFragment(String),
+use app_units::Au;
+
+/// The vertical scroll position of the page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScrollState {
+    pub y: f64,
+    pub content_height: f64,
+    pub viewport_height: f64,
 }
 
+const SCROLLBAR_WIDTH: f64 = 12.0;
+const SCROLLBAR_MIN_THUMB_HEIGHT: f64 = 24.0;
+const SCROLL_STEP: f64 = 48.0; // px per wheel notch or arrow key
+/// What stays visible when scrolled by a page
+const PAGE_SCROLL_OVERLAP: f64 = 40.0;
+
+/// Zoom factors (device px per CSS px) that Ctrl +/- steps through.
+const ZOOM_LEVELS: [f64; 13] = [
+    0.3, 0.5, 0.67, 0.8, 0.9, 1.0, 1.1, 1.2, 1.33, 1.5, 1.7, 2.0, 2.4
+];
+
+const SELECTION_COLOR: (f64, f64, f64, f64) = (0.2, 0.45, 0.9, 0.35); // rgba
+const FIND_MATCH_COLOR: (f64, f64, f64, f64) = (1.0, 0.9, 0.2, 0.5);
+const FIND_CURRENT_MATCH_COLOR: (f64, f64, f64, f64) = (1.0, 0.55, 0.1, 0.6);
+
+const CARET_BLINK_INTERVAL: u32 = 500; // ms
+const LOADER_POLL_INTERVAL: u32 = 50; // ms
+/// The page is laid out again when the window size has not changed for this long.
+const RESIZE_DEBOUNCE: u64 = 100; // ms
+
 thread_local!(
-    pub static ANKERS: RefCell<HashMap<Rect, AnkerKind>> = { RefCell::new(HashMap::with_capacity(8)) };
-    // HashMap<URL Fragment(id), y coordinate of the content>
-    pub static URL_FRAGMENTS: RefCell<HashMap<String, f64>> = { RefCell::new(HashMap::with_capacity(8)) };
-    pub static BUTTONS: RefCell<HashMap<usize, gtk::Button>> = { RefCell::new(HashMap::with_capacity(8)) };
-    pub static SURFACE_CACHE: RefCell<Option<cairo::ImageSurface>> = { RefCell::new(None) };
+    // When the main loop wakes up next to run the tasks of the engine due (see
+    // ``wake_up_for_tasks``)
+    static TASK_WAKEUP: Cell<Option<Instant>> = { Cell::new(None) };
 );
 
-static mut RESIZED: bool = false;
+/// What a view shows and what it is doing: each ``NaglfarView`` has its own, which the handlers of
+/// its widgets share.
+struct ViewState {
+    /// What has to be done again before the view is painted (see ``frame``)
+    frames: FrameScheduler,
+    /// The tiles of the page rasterized, None if its display list has to be built again
+    tiles: Option<TileCache>,
+    /// Whether the tiles around the viewport are rasterized when the main loop is idle (see
+    /// ``prefetch_tiles``)
+    prefetching: bool,
+    /// The device pixels per CSS pixel (at 100% zoom) ``tiles`` are rasterized for
+    device_scale: f64,
+    /// The frame composed before it is shown, in device px (see ``back_buffer``)
+    back_buffer: Option<cairo::ImageSurface>,
+    scroll: ScrollState,
+    /// The smooth scroll going on (see ``scroll_smoothly_to``)
+    scroll_animation: Option<ScrollAnimation>,
+    zoom: f64,
+    history: History,
+    toolbar: Option<Toolbar>,
+    find_bar: Option<FindBar>,
+    /// The link focused with Tab
+    focused_link: Option<AnkerKind>,
+    /// The pointer shown now
+    cursor: Option<CursorType>,
+    /// The context menu or the options of a <select>, kept alive while it is shown
+    context_menu: Option<gtk::Menu>,
+    find: FindState,
+    /// What is painted now, in CSS px
+    display_list: DisplayList,
+    selection: Option<Selection>,
+    /// True while the mouse button is held down to select text
+    selecting: bool,
+    /// Some(the distance from the top of the scrollbar thumb to the pointer) while dragging it
+    scrollbar_drag: Option<f64>,
+    /// True while the pointer is over the scrollbar of the viewport
+    scrollbar_hovered: bool,
+    /// The size of the drawing area, and when it last changed (None if already laid out for it)
+    drawing_area_size: (i32, i32),
+    resized_at: Option<Instant>,
+    /// Whether the timings of the pipeline are shown over the page (Ctrl+Shift+P)
+    hud_visible: bool,
+    /// Whether the box model of every box is shown over the page (Ctrl+Shift+B)
+    box_model_visible: bool,
+    /// Whether the next click inspects the element instead (Ctrl+Shift+C)
+    inspecting: bool,
+    signals: ViewSignals,
+    /// What the handlers of the view were last told: None when a new page is loaded
+    load_progress: Option<f64>,
+    /// The icon of the page the handlers of the view were last told
+    notified_icon: Option<gdk_pixbuf::Pixbuf>,
+    caret_visible: bool,
+}
 
-struct RenderingWindow {
-    window: gtk::Window,
+/// The state of a view, shared by the handlers of its widgets. It is never borrowed while GTK or
+/// the handlers connected to the view are called, since they may call back into the view.
+type View = Rc<RefCell<ViewState>>;
+
+impl ViewState {
+    fn new() -> ViewState {
+        ViewState {
+            frames: FrameScheduler::new(),
+            tiles: None,
+            prefetching: false,
+            device_scale: 1.0,
+            back_buffer: None,
+            scroll: ScrollState {
+                y: 0.0,
+                content_height: 0.0,
+                viewport_height: 0.0,
+            },
+            scroll_animation: None,
+            zoom: 1.0,
+            history: History::new(),
+            toolbar: None,
+            find_bar: None,
+            focused_link: None,
+            cursor: None,
+            context_menu: None,
+            find: FindState {
+                query: "".to_string(),
+                matches: vec![],
+                current: 0,
+            },
+            display_list: vec![],
+            selection: None,
+            selecting: false,
+            scrollbar_drag: None,
+            scrollbar_hovered: false,
+            drawing_area_size: (0, 0),
+            resized_at: None,
+            hud_visible: false,
+            box_model_visible: false,
+            inspecting: false,
+            signals: Default::default(),
+            load_progress: None,
+            notified_icon: None,
+            caret_visible: true,
+        }
+    }
+}
+
+/// The widgets above the page that show where we are.
+#[derive(Clone)]
+struct Toolbar {
+    back_button: gtk::Button,
+    forward_button: gtk::Button,
+    address_bar: gtk::Entry,
+}
+
+/// Where Tab moves the keyboard focus to.
+#[derive(Clone, Debug, PartialEq)]
+enum FocusTarget {
+    Link(Rect, AnkerKind),
+    TextInput(usize, Rect), // (node id, content area)
+    Select(usize, Rect),    // (node id, border box)
+}
+
+/// The bar below the page to find text in it (Ctrl+F).
+#[derive(Clone)]
+struct FindBar {
+    bar: gtk::Box,
+    entry: gtk::Entry,
+    status: gtk::Label,
+}
+
+/// The matches of the text being found. Empty if the find bar is closed.
+struct FindState {
+    query: String,
+    matches: Vec<Selection>,
+    current: usize,
+}
+
+/// A page: a widget that loads, lays out and paints web pages, to be added to any GTK
+/// container.
+///
+/// Each view has its own history, scroll position, zoom, selection, tiles and handlers connected
+/// (e.g. ``connect_title_changed``). The page loaded, its layout and its scripts are kept per
+/// thread like the rest of the engine (see ``interface``), so the views of a thread show the same
+/// page, each scrolled and zoomed on its own.
+///
+/// ```no_run
+/// extern crate gtk;
+/// extern crate naglfar;
+///
+/// use gtk::{ContainerExt, GtkWindowExt, Inhibit, WidgetExt};
+/// use naglfar::window::NaglfarView;
+///
+/// fn main() {
+///     gtk::init().unwrap();
+///     let window = gtk::Window::new(gtk::WindowType::Toplevel);
+///     let view = NaglfarView::new();
+///     {
+///         let window = window.clone();
+///         view.connect_title_changed(move |title| window.set_title(title.unwrap_or("")));
+///     }
+///     view.connect_link_clicked(|url| {
+///         println!("{}", url);
+///         Inhibit(false) // Inhibit(true) not to follow it
+///     });
+///     view.load_uri("https://example.com/");
+///     window.add(&view.widget());
+///     window.show_all();
+///     gtk::main();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct NaglfarView {
+    overlay: gtk::Overlay,
     drawing_area: gtk::DrawingArea,
+    state: View,
 }
 
-impl RenderingWindow {
-    fn new<F: 'static>(width: i32, height: i32, f: F) -> RenderingWindow
+/// The handlers connected to the view (see ``NaglfarView::connect_title_changed`` and others).
+#[derive(Default)]
+struct ViewSignals {
+    title_changed: Vec<TitleHandler>,
+    icon_changed: Vec<IconHandler>,
+    load_progress: Vec<Rc<dyn Fn(f64)>>,
+    link_clicked: Vec<LinkHandler>,
+}
+
+type TitleHandler = Rc<dyn Fn(Option<&str>)>;
+type IconHandler = Rc<dyn Fn(Option<&gdk_pixbuf::Pixbuf>)>;
+type LinkHandler = Rc<dyn Fn(&str) -> Inhibit>;
+
+impl Default for NaglfarView {
+    fn default() -> NaglfarView {
+        NaglfarView::new()
+    }
+}
+
+impl NaglfarView {
+    /// A view of the current page (none until ``load_uri`` is called, or
+    /// ``interface::update_html_source`` before).
+    pub fn new() -> NaglfarView {
+        NaglfarView::with_display_list(interface::display_list)
+    }
+
+    /// A view painting what ``f`` lays out in the drawing area at a zoom.
+    fn with_display_list<F: 'static>(f: F) -> NaglfarView
     where
-        F: Fn(&gtk::DrawingArea) -> DisplayList,
+        F: Fn(&gtk::DrawingArea, f64) -> DisplayList,
     {
-        let window = gtk::Window::new(gtk::WindowType::Toplevel);
-        window.set_title("Naglfar");
-        window.set_default_size(width, height);
-        window.override_background_color(
-            gtk::StateFlags::from_bits(gtk::StateFlags::NORMAL.bits()).unwrap(),
-            Some(&RGBA {
-                red: 1.0,
-                green: 1.0,
-                blue: 1.0,
-                alpha: 1.0,
-            }),
-        );
-
         let drawing_area = gtk::DrawingArea::new();
-        drawing_area.set_size_request(width, height);
+        let view: View = Rc::new(RefCell::new(ViewState::new()));
 
         let layout = gtk::Layout::new(None, None);
 
@@ -77,174 +299,375 @@ impl RenderingWindow {
             overlay.set_child_index(&layout, 1);
         }
 
-        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
-
-        let entry = gtk::Entry::new();
-        vbox.pack_start(&entry, false, false, 0);
-
-        entry
-            .connect("activate", true, |args| {
-                let entry = args[0]
-                    .clone()
-                    .downcast::<glib::Object>()
-                    .unwrap()
-                    .get()
-                    .unwrap()
-                    .downcast::<gtk::Entry>()
-                    .unwrap();
-                let vbox = entry.get_parent().unwrap().downcast::<gtk::Box>().unwrap();
-                let scrolled_window = vbox.get_children()[1]
-                    .clone()
-                    .downcast::<gtk::ScrolledWindow>()
-                    .unwrap();
-                let viewport = scrolled_window
-                    .get_child()
-                    .unwrap()
-                    .downcast::<gtk::Viewport>()
-                    .unwrap();
-                let overlay = viewport
-                    .get_child()
-                    .unwrap()
-                    .downcast::<gtk::Overlay>()
-                    .unwrap();
-                let drawing_area = overlay.get_children()[0].clone();
-
-                let url = entry.get_text().unwrap();
-                println!("URL: {}", url);
-
-                update_html_source(url);
-                ANKERS.with(|ankers| ankers.borrow_mut().clear());
-                SURFACE_CACHE.with(|sc| *sc.borrow_mut() = None);
-
-                drawing_area.queue_draw();
 
-                None
-            })
-            .unwrap();
+        // The page follows the theme, dark or not.
+        if let Some(settings) = gtk::Settings::get_default() {
+            set_preferred_color_scheme(&view, &drawing_area, theme_color_scheme(&settings));
+            let (view_, area) = (view.clone(), drawing_area.clone());
+            settings.connect_property_gtk_application_prefer_dark_theme_notify(move |settings| {
+                set_preferred_color_scheme(&view_, &area, theme_color_scheme(settings));
+            });
+            let (view_, area) = (view.clone(), drawing_area.clone());
+            settings.connect_property_gtk_theme_name_notify(move |settings| {
+                set_preferred_color_scheme(&view_, &area, theme_color_scheme(settings));
+            });
+        }
 
-        let scrolled_window = gtk::ScrolledWindow::new(None, None);
-        scrolled_window.add(&overlay);
-        vbox.pack_start(&scrolled_window, true, true, 0);
+        // The page is painted at most once a frame of the frame clock, for all that changed since
+        // the last one (see ``schedule_frame``).
+        {
+            let view = view.clone();
+            drawing_area.connect_realize(move |drawing_area| {
+                if let Some(clock) = drawing_area.get_frame_clock() {
+                    let (view, drawing_area) = (view.clone(), drawing_area.clone());
+                    clock.connect_update(move |_| {
+                        if view.borrow().frames.frame_requested() {
+                            drawing_area.queue_draw();
+                        }
+                    });
+                }
+            });
+        }
 
-        window.add(&vbox);
         overlay.add_events(
             EventMask::POINTER_MOTION_MASK.bits() as i32
-                | EventMask::BUTTON_PRESS_MASK.bits() as i32,
+                | EventMask::BUTTON_PRESS_MASK.bits() as i32
+                | EventMask::BUTTON_RELEASE_MASK.bits() as i32
+                | EventMask::SCROLL_MASK.bits() as i32
+                | EventMask::SMOOTH_SCROLL_MASK.bits() as i32,
         );
 
-        overlay
-            .connect("motion-notify-event", false, |args| {
-                use gdk::WindowExt;
-                let overlay = args[0]
-                    .clone()
-                    .downcast::<gtk::Overlay>()
-                    .unwrap()
-                    .get()
-                    .unwrap();
-                let (x, y) = args[1]
-                    .clone()
-                    .downcast::<Event>()
-                    .unwrap()
-                    .get()
-                    .unwrap()
-                    .downcast::<EventMotion>()
-                    .unwrap()
-                    .get_position();
-
-                ANKERS.with(|ankers| {
-                    let window = overlay.get_window().unwrap();
-                    if (&*ankers.borrow()).iter().any(|(rect, _)| {
-                        rect.x.to_f64_px() <= x && x <= rect.x.to_f64_px() + rect.width.to_f64_px()
-                            && rect.y.to_f64_px() <= y
-                            && y <= rect.y.to_f64_px() + rect.height.to_f64_px()
-                    }) {
-                        window.set_cursor(Some(&Cursor::new(CursorType::Hand1)));
-                    } else {
-                        // TODO: This is executed many times. It's inefficient.
-                        window.set_cursor(Some(&Cursor::new(CursorType::LeftPtr)));
+        {
+            let view = view.clone();
+            overlay.connect_scroll_event(move |overlay, event| {
+                let (dx, dy) = match event.get_direction() {
+                    ScrollDirection::Up => (0.0, -SCROLL_STEP),
+                    ScrollDirection::Down => (0.0, SCROLL_STEP),
+                    ScrollDirection::Left => (-SCROLL_STEP, 0.0),
+                    ScrollDirection::Right => (SCROLL_STEP, 0.0),
+                    ScrollDirection::Smooth => {
+                        let (dx, dy) = event.get_delta();
+                        (dx * SCROLL_STEP, dy * SCROLL_STEP)
                     }
-                });
-                Some(true.to_value())
-            })
-            .unwrap();
-
-        overlay
-            .connect("button-press-event", false, |args| {
-                let overlay = args[0]
-                    .clone()
-                    .downcast::<gtk::Overlay>()
-                    .unwrap()
-                    .get()
-                    .unwrap();
-                let (clicked_x, clicked_y) = args[1]
-                    .clone()
-                    .downcast::<Event>()
-                    .unwrap()
-                    .get()
-                    .unwrap()
-                    .downcast::<EventButton>()
-                    .unwrap()
-                    .get_position();
-
-                ANKERS.with(|ankers| {
-                    let mut jump_to_another_page = false;
-
-                    if let Some((_, ankerkind)) = ankers.borrow().iter().find(|&(rect, _)| {
-                        rect.x.to_f64_px() <= clicked_x
-                            && clicked_x <= rect.x.to_f64_px() + rect.width.to_f64_px()
-                            && rect.y.to_f64_px() <= clicked_y
-                            && clicked_y <= rect.y.to_f64_px() + rect.height.to_f64_px()
-                    }) {
-                        match ankerkind {
-                            &AnkerKind::URL(ref url) => {
-                                jump_to_another_page = true;
-                                update_html_source(url.to_string());
-                                overlay.get_children()[0].queue_draw(); // [0] is DrawingArea
-                            }
-                            &AnkerKind::URLFragment(ref id) => {
-                                URL_FRAGMENTS.with(|ufs| {
-                                    if let Some(content_y) = ufs.borrow().get(id) {
-                                        // TODO: Makes no sense.
-                                        let mut adjustment = overlay
-                                            .get_parent()
-                                            .unwrap()
-                                            .get_parent()
-                                            .unwrap()
-                                            .downcast::<gtk::ScrolledWindow>()
-                                            .unwrap()
-                                            .get_vadjustment()
-                                            .unwrap();
-                                        adjustment.set_value(*content_y);
-                                    }
-                                });
+                    _ => return Inhibit(false),
+                };
+                // The box under the pointer is scrolled first, and the page once it cannot be.
+                let (x, y) = event.get_position();
+                let (x, y) = to_document_point(&view, x, y);
+                let zoom = view.borrow().zoom;
+                if interface::scroll_box_at(x, y, dx / zoom, dy / zoom) {
+                    schedule_frame(&view, &overlay.get_children()[0], Invalidation::Raster);
+                    return Inhibit(true);
+                }
+                if dy == 0.0 {
+                    return Inhibit(false);
+                }
+                let y = view.borrow().scroll.y;
+                scroll_to(&view, y + dy);
+                // [0] is DrawingArea
+                schedule_frame(&view, &overlay.get_children()[0], Invalidation::Paint);
+                Inhibit(true)
+            });
+        }
+
+        {
+            let view = view.clone();
+            overlay.connect_button_release_event(move |_, _| {
+                let mut state = view.borrow_mut();
+                state.scrollbar_drag = None;
+                state.selecting = false;
+                Inhibit(false)
+            });
+        }
+
+        {
+            let view = view.clone();
+            overlay
+                .connect("motion-notify-event", false, move |args| {
+                    use gdk::WindowExt;
+                    let overlay = args[0]
+                        .clone()
+                        .downcast::<gtk::Overlay>()
+                        .unwrap()
+                        .get()
+                        .unwrap();
+                    let (x, y) = args[1]
+                        .clone()
+                        .downcast::<Event>()
+                        .unwrap()
+                        .get()
+                        .unwrap()
+                        .downcast::<EventMotion>()
+                        .unwrap()
+                        .get_position();
+                    let drawing_area = &overlay.get_children()[0];
+
+                    let grab = view.borrow().scrollbar_drag;
+                    if let Some(grab) = grab {
+                        let y = view.borrow().scroll.y_for_thumb_top(y - grab);
+                        scroll_to(&view, y);
+                        schedule_frame(&view, drawing_area, Invalidation::Paint);
+                        return Some(true.to_value());
+                    }
+
+                    let viewport_width = overlay.get_allocated_width() as f64;
+                    let on_scrollbar = x >= viewport_width - viewport_scrollbar_width();
+                    let hovered = {
+                        let mut state = view.borrow_mut();
+                        mem::replace(&mut state.scrollbar_hovered, on_scrollbar)
+                    };
+                    if hovered != on_scrollbar {
+                        schedule_frame(&view, drawing_area, Invalidation::Paint);
+                    }
+
+                    let (x, y) = to_document_point(&view, x, y);
+
+                    repaint_if_dom_changed(&view, drawing_area, interface::move_pointer(x, y));
+                    if interface::hover_scrollbar(x, y) {
+                        schedule_frame(&view, drawing_area, Invalidation::Raster);
+                    }
+
+                    if view.borrow().selecting {
+                        if let Some(pos) = text_position_at(&view, x, y) {
+                            if let Some(ref mut selection) = view.borrow_mut().selection {
+                                selection.focus = pos;
                             }
-                        };
+                            schedule_frame(&view, drawing_area, Invalidation::Paint);
+                        }
+                    }
+
+                    set_cursor(&view, &overlay, cursor_at(x, y));
+                    Some(true.to_value())
+                })
+                .unwrap();
+        }
+
+        {
+            let view = view.clone();
+            overlay
+                .connect("button-press-event", false, move |args| {
+                    let overlay = args[0]
+                        .clone()
+                        .downcast::<gtk::Overlay>()
+                        .unwrap()
+                        .get()
+                        .unwrap();
+                    let (clicked_x, clicked_y) = args[1]
+                        .clone()
+                        .downcast::<Event>()
+                        .unwrap()
+                        .get()
+                        .unwrap()
+                        .downcast::<EventButton>()
+                        .unwrap()
+                        .get_position();
+                    let (button, time) = {
+                        let event = args[1]
+                            .clone()
+                            .downcast::<Event>()
+                            .unwrap()
+                            .get()
+                            .unwrap()
+                            .downcast::<EventButton>()
+                            .unwrap();
+                        (event.get_button(), event.get_time())
+                    };
+                    let drawing_area = &overlay.get_children()[0];
+
+                    // The back and forward buttons of a mouse
+                    if button == 8 || button == 9 {
+                        if if button == 8 { go_back(&view) } else { go_forward(&view) } {
+                            schedule_frame(&view, drawing_area, Invalidation::Paint);
+                        }
+                        return Some(true.to_value());
                     }
 
-                    if jump_to_another_page {
-                        ankers.borrow_mut().clear();
-                        SURFACE_CACHE.with(|sc| *sc.borrow_mut() = None);
+                    if press_scrollbar(
+                        &view,
+                        overlay.get_allocated_width() as f64,
+                        clicked_x,
+                        clicked_y,
+                    ) {
+                        schedule_frame(&view, drawing_area, Invalidation::Paint);
+                        return Some(true.to_value());
                     }
-                });
-                Some(true.to_value())
-            })
-            .unwrap();
 
-        window.connect_configure_event(|_, _| {
-            unsafe {
-                RESIZED = true;
-            }
-            false
-        });
+                    let (clicked_x, clicked_y) = to_document_point(&view, clicked_x, clicked_y);
 
-        let instance = RenderingWindow {
-            window: window,
-            drawing_area: drawing_area,
-        };
+                    if button == 1 && mem::replace(&mut view.borrow_mut().inspecting, false) {
+                        inspect_element_at(clicked_x, clicked_y);
+                        return Some(true.to_value());
+                    }
 
-        instance
-            .drawing_area
-            .connect_draw(move |widget, cairo_context| {
+                    if button == 3 {
+                        show_context_menu(&view, &overlay, clicked_x, clicked_y, time);
+                        return Some(true.to_value());
+                    }
+
+                    let clicked_anker = anker_at(clicked_x, clicked_y);
+                    let anker_clicked = clicked_anker.is_some();
+                    let mut dispatched = Dispatched::default();
+                    if button == 1 {
+                        dispatched = interface::dispatch_mouse_event("click", clicked_x, clicked_y);
+                        repaint_if_dom_changed(&view, drawing_area, dispatched);
+                    }
+                    if let Some(ankerkind) = clicked_anker {
+                        if !dispatched.canceled {
+                            follow_link(&view, ankerkind);
+                        }
+                    }
+                    // Keyboard input goes to the page, not the address bar.
+                    drawing_area.grab_focus();
+                    view.borrow_mut().focused_link = None;
+
+                    // Focus the clicked text input, or blur the focused one.
+                    let clicked_text_input = TEXT_INPUTS.with(|text_inputs| {
+                        text_inputs
+                            .borrow()
+                            .iter()
+                            .find(|&(_, area)| {
+                                area.pointer_events
+                                    && area.rect.contains_point(clicked_x, clicked_y)
+                            })
+                            .map(|(id, area)| Caret {
+                                id: *id,
+                                pos: area.position_at(clicked_x, clicked_y),
+                            })
+                    });
+                    let text_input_clicked = clicked_text_input.is_some();
+                    let mut focus_changed = FOCUSED_TEXT_INPUT.with(|focused| {
+                        let mut focused = focused.borrow_mut();
+                        let changed = *focused != clicked_text_input;
+                        *focused = clicked_text_input;
+                        changed
+                    });
+                    // Focus the clicked <select> and show its options.
+                    let clicked_select = select_at(clicked_x, clicked_y);
+                    let focused_select = FOCUSED_SELECT.with(|f| f.replace(clicked_select));
+                    focus_changed |= focused_select != clicked_select;
+                    if let (1, Some(id)) = (button, clicked_select) {
+                        if !dispatched.canceled {
+                            show_select_popup(&view, drawing_area, id, 1, time);
+                        }
+                    }
+                    if focus_changed {
+                        view.borrow_mut().caret_visible = true;
+                        // Repaint the focus ring.
+                        schedule_frame(&view, drawing_area, Invalidation::Layout);
+                    }
+
+                    // Start selecting text, or clear the selection.
+                    let selecting = button == 1 && !anker_clicked && !text_input_clicked
+                        && clicked_select.is_none();
+                    let selection = if selecting {
+                        text_position_at(&view, clicked_x, clicked_y).map(Selection::new)
+                    } else {
+                        None
+                    };
+                    {
+                        let mut state = view.borrow_mut();
+                        state.selecting = selecting;
+                        state.selection = selection;
+                    }
+                    schedule_frame(&view, drawing_area, Invalidation::Paint);
+
+                    Some(true.to_value())
+                })
+                .unwrap();
+        }
+
+        // The keys of the browser (e.g. zoom) are handled by the window it is in.
+        drawing_area.set_can_focus(true);
+        drawing_area.add_events(EventMask::KEY_PRESS_MASK.bits() as i32);
+        {
+            let view = view.clone();
+            drawing_area.connect_key_press_event(move |drawing_area, event| {
+                let focused = FOCUSED_TEXT_INPUT
+                    .with(|f| f.borrow().as_ref().map(|caret| caret.id))
+                    .or_else(|| FOCUSED_SELECT.with(|f| f.get()));
+                let dispatched =
+                    interface::dispatch_key_event("keydown", &key_name(event), focused);
+                repaint_if_dom_changed(&view, drawing_area, dispatched);
+                if dispatched.canceled {
+                    return Inhibit(true);
+                }
+                if copy_by_key(&view, event) || move_focus_by_key(&view, event)
+                    || edit_focused_text_input(&view, event)
+                    || change_focused_select(&view, drawing_area.upcast_ref(), event)
+                    || scroll_by_key(&view, event)
+                {
+                    schedule_frame(&view, drawing_area, Invalidation::Paint);
+                    Inhibit(true)
+                } else {
+                    Inhibit(false)
+                }
+            });
+        }
+
+        {
+            let (view, drawing_area) = (view.clone(), drawing_area.clone());
+            gtk::timeout_add(CARET_BLINK_INTERVAL, move || {
+                if FOCUSED_TEXT_INPUT.with(|f| f.borrow().is_some()) {
+                    {
+                        let mut state = view.borrow_mut();
+                        state.caret_visible = !state.caret_visible;
+                    }
+                    schedule_frame(&view, &drawing_area, Invalidation::Paint);
+                }
+                Continue(true)
+            });
+        }
+
+        // Lay out and paint again when images or stylesheets arrive, and paint the page laid out
+        // on the worker thread once it is received.
+        {
+            let (view, drawing_area) = (view.clone(), drawing_area.clone());
+            gtk::timeout_add(LOADER_POLL_INTERVAL, move || {
+                if apply_loaded_resources() | interface::receive_layout() {
+                    schedule_frame(&view, &drawing_area, Invalidation::Raster);
+                }
+                notify_page_state(&view);
+                Continue(true)
+            });
+        }
+
+        // Run the timers of the page and the tasks of the engine when they are due.
+        {
+            let (view, drawing_area) = (view.clone(), drawing_area.clone());
+            interface::set_task_waker(move |due| wake_up_for_tasks(&view, &drawing_area, due));
+        }
+
+        // While the window is being resized, the page painted for the old size is shown.
+        {
+            let view = view.clone();
+            drawing_area.connect_size_allocate(move |drawing_area, allocation| {
+                let size = (allocation.width, allocation.height);
+                {
+                    let mut state = view.borrow_mut();
+                    if mem::replace(&mut state.drawing_area_size, size) == size {
+                        return;
+                    }
+                    if state.resized_at.replace(Instant::now()).is_some() {
+                        return; // The timer is already running.
+                    }
+                }
+
+                let (view, drawing_area) = (view.clone(), drawing_area.clone());
+                gtk::timeout_add(RESIZE_DEBOUNCE as u32, move || {
+                    let resized_at = view.borrow().resized_at.unwrap();
+                    if resized_at.elapsed() < Duration::from_millis(RESIZE_DEBOUNCE) {
+                        return Continue(true);
+                    }
+                    view.borrow_mut().resized_at = None;
+                    schedule_frame(&view, &drawing_area, Invalidation::Layout);
+                    Continue(false)
+                });
+            });
+        }
+
+        {
+            let view = view.clone();
+            drawing_area.connect_draw(move |widget, cairo_context| {
                 // println!("here");
 
                 // let overlay = widget
@@ -257,173 +680,1857 @@ impl RenderingWindow {
                 //     .downcast::<gtk::Layout>()
                 //     .unwrap(); // [1] is Layout
 
-                let surface = SURFACE_CACHE.with(|sc| {
-                    if let Some(ref surface) = *sc.borrow_mut() {
-                        unsafe {
-                            if RESIZED {
-                                RESIZED = false;
-                            } else {
-                                return surface.clone();
-                            }
+                // What has to be done again since the last frame, once for all (see ``frame``)
+                let begun = view.borrow_mut().frames.begin_frame();
+                let animating = match begun {
+                    Some((invalidation, animating)) => {
+                        if invalidation >= Invalidation::Raster {
+                            ANKERS.with(|ankers| ankers.borrow_mut().clear());
+                            view.borrow_mut().tiles = None;
+                        }
+                        if invalidation == Invalidation::Layout {
+                            interface::invalidate_display_list();
                         }
+                        animating
                     }
+                    None => false,
+                };
 
-                    let pango_ctx = widget.create_pango_context().unwrap();
-                    let mut pango_layout = pango::Layout::new(&pango_ctx);
-
-                    let items = f(widget);
-                    let content_rect =
-                        if let DisplayCommand::SolidColor(_, content_rect) = items[0].command {
-                            content_rect
-                        } else {
-                            unreachable!()
-                        };
+                // Moved to a monitor with another scale factor
+                let device_scale = device_scale(widget);
+                let zoom = {
+                    let mut state = view.borrow_mut();
+                    if mem::replace(&mut state.device_scale, device_scale) != device_scale {
+                        state.tiles = None;
+                    }
+                    state.zoom
+                };
+                // The responsive images are selected for the device px (see ``srcset``).
+                if media::set_device_pixel_ratio(zoom * device_scale) {
+                    layout::invalidate_layout();
+                    interface::invalidate_display_list();
+                    view.borrow_mut().tiles = None;
+                }
 
-                    widget
-                        .get_parent()
-                        .unwrap()
-                        .downcast::<gtk::Overlay>()
-                        .unwrap()
-                        .set_size_request(-1, content_rect.height.ceil_to_px());
-                    widget.set_size_request(-1, content_rect.height.ceil_to_px());
-
-                    let surface = cairo::ImageSurface::create(
-                        cairo::Format::ARgb32,
-                        content_rect.width.to_px(),
-                        content_rect.height.to_px(),
-                    ).unwrap();
-                    let ctx = cairo::Context::new(&surface);
-                    for item in &items {
-                        render_item(&ctx, &mut pango_layout, /* layout, */ &item.command);
+                // A smooth scroll moves on each frame.
+                let animation = view.borrow().scroll_animation;
+                if let (Some(animation), true) = (animation, animating) {
+                    let ((_, y), done) = animation.position(Instant::now());
+                    let mut state = view.borrow_mut();
+                    state.scroll.y = y * zoom;
+                    if !done {
+                        state.frames.request_animation_frame();
+                        request_frame(widget);
+                    } else {
+                        state.scroll_animation = None;
                     }
+                }
 
-                    // let radial = cairo::LinearGradient::new(0.0, 0.0, 0.0, 200.0);
-                    // use cairo::Gradient;
-                    // radial.add_color_stop_rgba(0.0, 0.0, 0.0, 0.0, 0.5);
-                    // radial.add_color_stop_rgba(0.4, 0.0, 0.0, 0.0, 0.0);
-                    // ctx.mask(&radial);
+                // Fixed backgrounds stay in the viewport as the page scrolls.
+                let visible_rect = visible_rect(&view, widget);
+                painter::set_viewport(visible_rect);
+                if painter::fixed_backgrounds_moved() {
+                    view.borrow_mut().tiles = None;
+                }
+                // The ``content-visibility: auto`` boxes and the lazy images scrolled near are laid
+                // out.
+                if containment::set_visible_area(Some(visible_rect)) | lazy::came_near() {
+                    interface::invalidate_display_list();
+                    view.borrow_mut().tiles = None;
+                }
 
-                    *sc.borrow_mut() = Some(surface.clone());
-                    surface
-                });
+                // The display list is in CSS px, and the tiles in device px so that text is sharp
+                // on HiDPI displays. The layout doesn't change.
+                if view.borrow().tiles.is_none() {
+                    let items = f(widget, zoom);
+                    let scale = zoom * device_scale;
+                    {
+                        let mut state = view.borrow_mut();
+                        let size = tile::document_size(&items, scale);
+                        state.tiles = Some(TileCache::new(scale, size));
+                        state.display_list = items;
+                    }
+                    // The positions of the matches may have changed.
+                    update_find_matches(&view);
+                }
 
-                let (_, redraw_start_y, redraw_end_x, redraw_end_y) = cairo_context.clip_extents();
+                let scroll = {
+                    let mut state = view.borrow_mut();
+                    let document_height = state.tiles.as_ref().unwrap().size.1;
+                    let s = &mut state.scroll;
+                    s.content_height = document_height as f64 / device_scale;
+                    s.viewport_height = widget.get_allocated_height() as f64;
+                    let y = s.y;
+                    s.scroll_to(y);
+                    *s
+                };
 
-                cairo_context.set_source_surface(&surface, 0.0, 0.0);
-                cairo_context.rectangle(
-                    0.0,
-                    redraw_start_y,
-                    redraw_end_x,
-                    redraw_end_y - redraw_start_y,
+                // The whole frame is composed on the back buffer first, so that a state partially
+                // painted is never shown.
+                let width = widget.get_allocated_width();
+                let back_buffer = back_buffer(
+                    &mut view.borrow_mut().back_buffer,
+                    width,
+                    scroll.viewport_height as i32,
+                    device_scale,
                 );
-                cairo_context.fill();
+                {
+                    let ctx = Context::new(&back_buffer);
+                    // On whole device px, so that there are no seams between the tiles
+                    let top = (scroll.y * device_scale).round();
+                    for (tile, (x, y, _, _)) in visible_tiles(&view, widget) {
+                        ctx.set_source_surface(&tile, x as f64, y as f64 - top);
+                        ctx.paint();
+                    }
+                    ctx.scale(device_scale, device_scale);
+
+                    let state = view.borrow();
+                    ctx.save();
+                    ctx.translate(0.0, -scroll.y);
+                    ctx.scale(zoom, zoom);
+                    draw_find_matches(&ctx, &state);
+                    draw_focused_link(&ctx, &state);
+                    draw_selection(&ctx, &state);
+                    draw_box_model(&ctx, &state);
+                    draw_caret(&ctx, &state);
+                    ctx.restore();
+
+                    draw_scrollbar(&ctx, width as f64, &state);
+                    draw_hud(&ctx, &state);
+                }
+
+                prefetch_tiles(&view, widget);
+
+                // Blitted at once (in the area to redraw, the clip of ``cairo_context``)
+                cairo_context.save();
+                cairo_context.scale(1.0 / device_scale, 1.0 / device_scale);
+                cairo_context.set_source_surface(&back_buffer, 0.0, 0.0);
+                cairo_context.paint();
+                cairo_context.restore();
 
                 // layout.show_all();
 
                 Inhibit(true)
             });
+        }
 
-        instance.window.show_all();
-        instance
+        NaglfarView {
+            overlay: overlay,
+            drawing_area: drawing_area,
+            state: view,
+        }
     }
 
-    fn exit_on_close(&self) {
-        self.window.connect_delete_event(|_, _| {
-            gtk::main_quit();
-            Inhibit(true)
-        });
+    /// The widget to add to a container.
+    pub fn widget(&self) -> gtk::Widget {
+        self.overlay.clone().upcast()
     }
-}
 
-fn render_item(
-    ctx: &Context,
-    pango_layout: &mut pango::Layout,
-    // layout: &gtk::Layout,
-    item: &DisplayCommand,
-) {
-    match item {
-        &DisplayCommand::SolidColor(ref color, rect) => {
-            ctx.rectangle(
-                rect.x.to_f64_px(),
-                rect.y.to_f64_px(),
-                rect.width.to_f64_px(),
-                rect.height.to_f64_px(),
-            );
-            ctx.set_source_rgba(
-                color.r as f64 / 255.0,
-                color.g as f64 / 255.0,
-                color.b as f64 / 255.0,
-                color.a as f64 / 255.0,
-            );
-            ctx.fill();
-        }
-        &DisplayCommand::Image(ref pixbuf, ref img_metadata, rect) => {
-            ctx.set_source_pixbuf(
-                &pixbuf
-                    .scale_simple(
-                        img_metadata.width.to_f64_px() as i32,
-                        img_metadata.height.to_f64_px() as i32,
-                        InterpType::Hyper,
-                    )
-                    .unwrap(),
-                rect.x.to_f64_px(),
-                rect.y.to_f64_px(),
-            );
-            ctx.paint();
-        }
-        &DisplayCommand::Text(ref text, rect, ref color, ref decorations, ref font) => {
-            FONT_DESC.with(|font_desc| {
-                let mut font_desc = font_desc.borrow_mut();
-                font_desc.set_size(pango::units_from_double(px2pt(font.size.to_f64_px())));
-                font_desc.set_style(font.slant.to_pango_font_slant());
-                font_desc.set_weight(font.weight.to_pango_font_weight());
-
-                let attr_list = pango::AttrList::new();
-                for decoration in decorations {
-                    match decoration {
-                        &TextDecoration::Underline => {
-                            attr_list.insert(
-                                pango::Attribute::new_underline(pango::Underline::Single).unwrap(),
-                            );
-                        }
-                        &TextDecoration::Overline => unimplemented!(),
-                        &TextDecoration::LineThrough => {
-                            attr_list.insert(pango::Attribute::new_strikethrough(true).unwrap());
-                        }
-                        &TextDecoration::None => {}
-                    }
-                }
+    /// Loads ``url`` (or a local path) as a new page in the session history.
+    pub fn load_uri(&self, url: &str) {
+        navigate(&self.state, url.to_string());
+        schedule_frame(&self.state, &self.drawing_area, Invalidation::Paint);
+    }
 
-                pango_layout.set_text(text.as_str());
-                pango_layout.set_attributes(Some(&attr_list));
-                pango_layout.set_font_description(Some(&*font_desc));
-            });
+    /// The URL of the page (after redirects).
+    pub fn uri(&self) -> Option<String> {
+        current_url().map(|url| url.into_string())
+    }
 
-            ctx.set_source_rgba(
-                color.r as f64 / 255.0,
-                color.g as f64 / 255.0,
-                color.b as f64 / 255.0,
-                color.a as f64 / 255.0,
-            );
-            ctx.move_to(rect.x.to_f64_px(), rect.y.to_f64_px());
+    pub fn title(&self) -> Option<String> {
+        page_title()
+    }
 
-            pangocairo::functions::show_layout(ctx, &pango_layout);
-        }
-        &DisplayCommand::Button(ref _btn, _rect) => {
-            // use gtk::LayoutExt;
-            // layout.put(btn, rect.x.ceil_to_px(), rect.y.ceil_to_px());
-        }
+    /// Returns false if there is no previous page.
+    pub fn go_back(&self) -> bool {
+        let went_back = go_back(&self.state);
+        schedule_frame(&self.state, &self.drawing_area, Invalidation::Paint);
+        went_back
+    }
+
+    /// Returns false if there is no next page.
+    pub fn go_forward(&self) -> bool {
+        let went_forward = go_forward(&self.state);
+        schedule_frame(&self.state, &self.drawing_area, Invalidation::Paint);
+        went_forward
+    }
+
+    pub fn reload(&self, cache_mode: CacheMode) {
+        reload(&self.state, cache_mode);
+        schedule_frame(&self.state, &self.drawing_area, Invalidation::Paint);
+    }
+
+    /// Makes the scrollbars of the page and of its boxes overlay scrollbars (shown while the
+    /// pointer is over them, over the content), or not.
+    pub fn set_overlay_scrollbars(&self, overlay: bool) {
+        overflow::set_overlay_scrollbars(overlay);
+        schedule_frame(&self.state, &self.drawing_area, Invalidation::Layout);
+    }
+
+    /// Sets what the page is rasterized with (see ``gpu``).
+    pub fn set_renderer(&self, renderer: Renderer) {
+        gpu::set_renderer(renderer);
+        schedule_frame(&self.state, &self.drawing_area, Invalidation::Raster);
+    }
+
+    /// Sets the color scheme preferred by the user, for ``prefers-color-scheme`` and the system
+    /// colors (see ``style::Style::color_scheme``). It is that of the GTK theme when the view is
+    /// made, and again each time the theme changes.
+    pub fn set_preferred_color_scheme(&self, scheme: ColorScheme) {
+        set_preferred_color_scheme(&self.state, &self.drawing_area, scheme);
+    }
+
+    /// Calls ``f`` with the ``<title>`` of each page loaded, once it is loaded.
+    pub fn connect_title_changed<F: Fn(Option<&str>) + 'static>(&self, f: F) {
+        self.state.borrow_mut().signals.title_changed.push(Rc::new(f));
+    }
+
+    /// Calls ``f`` with the icon of the page (see ``interface::favicon``) once it is loaded, and
+    /// with None when another page is loaded.
+    pub fn connect_icon_changed<F: Fn(Option<&gdk_pixbuf::Pixbuf>) + 'static>(&self, f: F) {
+        self.state.borrow_mut().signals.icon_changed.push(Rc::new(f));
+    }
+
+    /// Calls ``f`` with ``interface::load_progress`` each time it changes.
+    pub fn connect_load_progress<F: Fn(f64) + 'static>(&self, f: F) {
+        self.state.borrow_mut().signals.load_progress.push(Rc::new(f));
+    }
+
+    /// Calls ``f`` with the absolute URL of each link followed (clicked, or activated with the
+    /// keyboard or the context menu) before following it, which ``Inhibit(true)`` prevents.
+    pub fn connect_link_clicked<F: Fn(&str) -> Inhibit + 'static>(&self, f: F) {
+        self.state.borrow_mut().signals.link_clicked.push(Rc::new(f));
     }
 }
 
-pub fn render<F: 'static>(f: F)
-where
-    F: Fn(&gtk::DrawingArea) -> DisplayList,
-{
-    gtk::init().unwrap_or_else(|_| panic!("Failed to initialize GTK."));
+struct RenderingWindow {
+    window: gtk::Window,
+}
 
-    let window = RenderingWindow::new(800, 520, f);
-    window.exit_on_close();
+impl RenderingWindow {
+    fn new<F: 'static>(width: i32, height: i32, f: F) -> RenderingWindow
+    where
+        F: Fn(&gtk::DrawingArea, f64) -> DisplayList,
+    {
+        let window = gtk::Window::new(gtk::WindowType::Toplevel);
+        window.set_title("Naglfar");
+        window.set_default_size(width, height);
+        window.override_background_color(
+            gtk::StateFlags::from_bits(gtk::StateFlags::NORMAL.bits()).unwrap(),
+            Some(&RGBA {
+                red: 1.0,
+                green: 1.0,
+                blue: 1.0,
+                alpha: 1.0,
+            }),
+        );
 
-    gtk::main();
+        let view = NaglfarView::with_display_list(f);
+        let drawing_area = view.drawing_area.clone();
+        let state = view.state.clone();
+        {
+            let window = window.clone();
+            view.connect_title_changed(move |title| {
+                window.set_title(match title {
+                    Some(title) => format!("{} - Naglfar", title),
+                    None => "Naglfar".to_string(),
+                }.as_str())
+            });
+        }
+        {
+            let window = window.clone();
+            view.connect_icon_changed(move |icon| window.set_icon(icon));
+        }
+
+        let vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
+
+        let toolbar = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        let back_button = gtk::Button::new_with_label("\u{2190}");
+        let forward_button = gtk::Button::new_with_label("\u{2192}");
+        let reload_button = gtk::Button::new_with_label("\u{21bb}");
+        reload_button.set_tooltip_text("Reload (Shift+click to bypass the cache)");
+        let entry = gtk::Entry::new();
+        entry.set_placeholder_text("Enter a URL or a local path");
+        toolbar.pack_start(&back_button, false, false, 0);
+        toolbar.pack_start(&forward_button, false, false, 0);
+        toolbar.pack_start(&reload_button, false, false, 0);
+        toolbar.pack_start(&entry, true, true, 0);
+        vbox.pack_start(&toolbar, false, false, 0);
+
+        // The page loaded before the window is opened
+        if let Some(url) = current_url() {
+            state.borrow_mut().history.push(url.as_str().to_string());
+        }
+        state.borrow_mut().toolbar = Some(Toolbar {
+            back_button: back_button.clone(),
+            forward_button: forward_button.clone(),
+            address_bar: entry.clone(),
+        });
+        update_toolbar(&state);
+
+        {
+            let (state, drawing_area) = (state.clone(), drawing_area.clone());
+            back_button.connect_clicked(move |_| {
+                if go_back(&state) {
+                    schedule_frame(&state, &drawing_area, Invalidation::Paint);
+                }
+            });
+        }
+        {
+            let (state, drawing_area) = (state.clone(), drawing_area.clone());
+            forward_button.connect_clicked(move |_| {
+                if go_forward(&state) {
+                    schedule_frame(&state, &drawing_area, Invalidation::Paint);
+                }
+            });
+        }
+
+        {
+            let (state, drawing_area) = (state.clone(), drawing_area.clone());
+            reload_button.connect_clicked(move |_| {
+                let shift = gtk::get_current_event_state()
+                    .map(|state| state.contains(ModifierType::SHIFT_MASK));
+                reload(
+                    &state,
+                    if shift == Some(true) {
+                        CacheMode::Reload
+                    } else {
+                        CacheMode::Default
+                    },
+                );
+                schedule_frame(&state, &drawing_area, Invalidation::Paint);
+            });
+        }
+
+        {
+            let (state, drawing_area) = (state.clone(), drawing_area.clone());
+            entry.connect_activate(move |entry| {
+                let url = typed_url(entry.get_text().unwrap());
+                println!("URL: {}", url);
+
+                navigate(&state, url);
+                schedule_frame(&state, &drawing_area, Invalidation::Paint);
+            });
+        }
+
+        // Typing into the URL bar must not edit the text input in the page.
+        {
+            let (state, drawing_area) = (state.clone(), drawing_area.clone());
+            entry.connect_focus_in_event(move |_, _| {
+                if FOCUSED_TEXT_INPUT.with(|f| f.borrow_mut().take().is_some()) {
+                    schedule_frame(&state, &drawing_area, Invalidation::Layout);
+                }
+                Inhibit(false)
+            });
+        }
+
+        // The page is scrolled by ourselves (see ``ScrollState``), not by gtk::ScrolledWindow.
+        vbox.pack_start(&view.widget(), true, true, 0);
+
+        let find_bar = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        let find_entry = gtk::Entry::new();
+        find_entry.set_placeholder_text("Find in page");
+        let find_status = gtk::Label::new(None);
+        let find_close_button = gtk::Button::new_with_label("\u{2715}");
+        find_bar.pack_start(&find_entry, false, false, 0);
+        find_bar.pack_start(&find_status, false, false, 0);
+        find_bar.pack_end(&find_close_button, false, false, 0);
+        vbox.pack_start(&find_bar, false, false, 0);
+        state.borrow_mut().find_bar = Some(FindBar {
+            bar: find_bar.clone(),
+            entry: find_entry.clone(),
+            status: find_status.clone(),
+        });
+
+        {
+            let (state, drawing_area) = (state.clone(), drawing_area.clone());
+            find_entry.connect_changed(move |entry| {
+                find_in_page(&state, entry.get_text().unwrap_or_else(|| "".to_string()));
+                schedule_frame(&state, &drawing_area, Invalidation::Paint);
+            });
+        }
+        {
+            let (state, drawing_area) = (state.clone(), drawing_area.clone());
+            find_entry.connect_activate(move |_| {
+                let shift = gtk::get_current_event_state()
+                    .map(|state| state.contains(ModifierType::SHIFT_MASK));
+                find_next(&state, if shift == Some(true) { -1 } else { 1 });
+                schedule_frame(&state, &drawing_area, Invalidation::Paint);
+            });
+        }
+        {
+            let (state, drawing_area) = (state.clone(), drawing_area.clone());
+            find_entry.connect_key_press_event(move |_, event| {
+                if event.get_keyval() == key::Escape {
+                    close_find_bar(&state);
+                    schedule_frame(&state, &drawing_area, Invalidation::Paint);
+                    Inhibit(true)
+                } else {
+                    Inhibit(false)
+                }
+            });
+        }
+        {
+            let (state, drawing_area) = (state.clone(), drawing_area.clone());
+            find_close_button.connect_clicked(move |_| {
+                close_find_bar(&state);
+                schedule_frame(&state, &drawing_area, Invalidation::Paint);
+            });
+        }
+
+        window.add(&vbox);
+
+        {
+            let (state, drawing_area) = (state.clone(), drawing_area.clone());
+            window.connect_key_press_event(move |_, event| {
+                if zoom_by_key(&state, event) || navigate_by_key(&state, event)
+                    || find_by_key(&state, event) || toggle_hud_by_key(&state, event)
+                    || toggle_box_model_by_key(&state, event)
+                    || toggle_inspecting_by_key(&state, event)
+                    || toggle_renderer_by_key(&state, event)
+                {
+                    schedule_frame(&state, &drawing_area, Invalidation::Paint);
+                    Inhibit(true)
+                } else {
+                    Inhibit(false)
+                }
+            });
+        }
+
+        let instance = RenderingWindow { window: window };
+        instance.window.show_all();
+        find_bar.hide();
+        instance
+    }
+
+    fn exit_on_close(&self) {
+        self.window.connect_delete_event(|_, _| {
+            gtk::main_quit();
+            Inhibit(true)
+        });
+    }
+}
+
+/// Tells the handlers of the view the title of a new page, and its icon and the load progress if
+/// they changed.
+fn notify_page_state(view: &View) {
+    let progress = load_progress();
+    let last_progress = view.borrow_mut().load_progress.replace(progress);
+    if last_progress.is_none() {
+        let title = page_title();
+        let handlers = view.borrow().signals.title_changed.clone();
+        for handler in handlers {
+            handler(title.as_deref());
+        }
+    }
+    let icon = interface::favicon();
+    let last_icon = mem::replace(&mut view.borrow_mut().notified_icon, icon.clone());
+    if last_icon != icon {
+        let handlers = view.borrow().signals.icon_changed.clone();
+        for handler in handlers {
+            handler(icon.as_ref());
+        }
+    }
+    if last_progress != Some(progress) {
+        let handlers = view.borrow().signals.load_progress.clone();
+        for handler in handlers {
+            handler(progress);
+        }
+    }
+}
+
+/// Loads ``url`` as a new page in the session history.
+fn navigate(view: &View, url: String) {
+    save_scroll_position(view);
+    load_page(view, url);
+    if let Some(url) = current_url() {
+        view.borrow_mut().history.push(url.as_str().to_string());
+    }
+    update_toolbar(view);
+}
+
+/// Returns false if there is no previous page.
+fn go_back(view: &View) -> bool {
+    save_scroll_position(view);
+    let entry = view.borrow_mut().history.back().cloned();
+    restore_history_entry(view, entry)
+}
+
+/// Returns false if there is no next page.
+fn go_forward(view: &View) -> bool {
+    save_scroll_position(view);
+    let entry = view.borrow_mut().history.forward().cloned();
+    restore_history_entry(view, entry)
+}
+
+fn restore_history_entry(view: &View, entry: Option<HistoryEntry>) -> bool {
+    let entry = match entry {
+        Some(entry) => entry,
+        None => return false,
+    };
+    let scroll_y = entry.scroll_y * view.borrow().zoom;
+    load_page(view, entry.url);
+    // Not clamped here since the page is not laid out yet. The draw handler clamps it.
+    view.borrow_mut().scroll.y = scroll_y;
+    update_toolbar(view);
+    true
+}
+
+/// Loads the current page again, keeping the scroll position (clamped to the new page length).
+fn reload(view: &View, cache_mode: CacheMode) {
+    let scroll_y = view.borrow().scroll.y;
+    reload_html_source(cache_mode);
+    reset_page(view);
+    view.borrow_mut().scroll.y = scroll_y;
+    update_toolbar(view);
+}
+
+fn load_page(view: &View, url: String) {
+    update_html_source(url);
+    reset_page(view);
+}
+
+/// Forgets what is painted for the previous page.
+fn reset_page(view: &View) {
+    {
+        let mut state = view.borrow_mut();
+        state.load_progress = None;
+        state.focused_link = None;
+    }
+    close_find_bar(view);
+    {
+        let mut state = view.borrow_mut();
+        state.selection = None;
+        state.frames.invalidate(Invalidation::Raster);
+    }
+    clear_text_inputs();
+    scroll_to(view, 0.0);
+}
+
+fn save_scroll_position(view: &View) {
+    let state = &mut *view.borrow_mut();
+    state.history.set_scroll_y(state.scroll.y / state.zoom);
+}
+
+/// Shows the URL of the current page, and whether we can go back or forward.
+fn update_toolbar(view: &View) {
+    let toolbar = match view.borrow().toolbar.clone() {
+        Some(toolbar) => toolbar,
+        None => return,
+    };
+    let (can_go_back, can_go_forward) = {
+        let history = &view.borrow().history;
+        (history.can_go_back(), history.can_go_forward())
+    };
+    toolbar.back_button.set_sensitive(can_go_back);
+    toolbar.forward_button.set_sensitive(can_go_forward);
+    if let Some(url) = current_url() {
+        toolbar.address_bar.set_text(url.as_str());
+    }
+}
+
+fn focus_address_bar(view: &View) {
+    let toolbar = view.borrow().toolbar.clone();
+    if let Some(toolbar) = toolbar {
+        toolbar.address_bar.grab_focus(); // also selects the whole text
+    }
+}
+
+/// What is typed in the address bar: a URL, a local path, or a bare host name.
+fn typed_url(input: String) -> String {
+    if Path::new(input.trim()).exists() {
+        return input.trim().to_string();
+    }
+    match guess_url(input.as_str()) {
+        Some(url) => url.into_string(),
+        None => input,
+    }
+}
+
+/// Handles Alt+Left and Alt+Right (back and forward), F5 and Ctrl+R (reload, with Shift to
+/// bypass the cache), and Ctrl+L (address bar). Returns true if the key press is consumed.
+fn navigate_by_key(view: &View, event: &EventKey) -> bool {
+    let state = event.get_state();
+    let cache_mode = if state.contains(ModifierType::SHIFT_MASK) {
+        CacheMode::Reload
+    } else {
+        CacheMode::Default
+    };
+    match event.get_keyval() {
+        key::Left if state.contains(ModifierType::MOD1_MASK) => go_back(view),
+        key::Right if state.contains(ModifierType::MOD1_MASK) => go_forward(view),
+        key::F5 => {
+            reload(view, cache_mode);
+            true
+        }
+        key::r | key::R if state.contains(ModifierType::CONTROL_MASK) => {
+            reload(view, cache_mode);
+            true
+        }
+        key::l if state.contains(ModifierType::CONTROL_MASK) => {
+            focus_address_bar(view);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The scale factor of the monitor the widget is on (e.g. 2 on HiDPI displays).
+fn device_scale(widget: &gtk::DrawingArea) -> f64 {
+    widget
+        .get_property("scale-factor")
+        .ok()
+        .and_then(|value| value.get::<i32>())
+        .map_or(1.0, |scale| scale.max(1) as f64)
+}
+
+/// The next zoom level after ``zoom``, or 1.0 (``step == 0``).
+fn next_zoom_level(zoom: f64, step: i32) -> f64 {
+    let levels = ZOOM_LEVELS.iter().cloned();
+    match step {
+        0 => Some(1.0),
+        _ if step > 0 => levels.clone().find(|&l| l > zoom + 0.001),
+        _ => levels.rev().find(|&l| l < zoom - 0.001),
+    }.unwrap_or(zoom)
+}
+
+/// Handles Ctrl +, Ctrl - and Ctrl 0. Returns true if the zoom is changed.
+fn zoom_by_key(view: &View, event: &EventKey) -> bool {
+    if !event.get_state().contains(ModifierType::CONTROL_MASK) {
+        return false;
+    }
+    let step = match event.get_keyval() {
+        key::plus | key::equal | key::KP_Add => 1,
+        key::minus | key::KP_Subtract => -1,
+        key::_0 | key::KP_0 => 0,
+        _ => return false,
+    };
+
+    let mut state = view.borrow_mut();
+    let old_zoom = state.zoom;
+    let new_zoom = next_zoom_level(old_zoom, step);
+    if new_zoom == old_zoom {
+        return false;
+    }
+    state.zoom = new_zoom;
+
+    // Lay out again in the new CSS px size, keeping the scroll position roughly in place.
+    state.frames.invalidate(Invalidation::Layout);
+    state.scroll.y = state.scroll.y * new_zoom / old_zoom;
+    true
+}
+
+/// The part of the document shown in ``widget`` (CSS px).
+fn visible_rect(view: &View, widget: &gtk::DrawingArea) -> Rect {
+    let (zoom, scroll_y) = {
+        let state = view.borrow();
+        (state.zoom, state.scroll.y)
+    };
+    Rect {
+        x: Au(0),
+        y: Au::from_f64_px(scroll_y / zoom),
+        width: Au::from_f64_px(widget.get_allocated_width() as f64 / zoom),
+        height: Au::from_f64_px(widget.get_allocated_height() as f64 / zoom),
+    }
+}
+
+/// Converts a point in the widget to the one in the document (in CSS px).
+fn to_document_point(view: &View, x: f64, y: f64) -> (f64, f64) {
+    let state = view.borrow();
+    (x / state.zoom, (y + state.scroll.y) / state.zoom)
+}
+
+fn scroll_to(view: &View, y: f64) {
+    let mut state = view.borrow_mut();
+    state.scroll_animation = None;
+    state.scroll.scroll_to(y);
+}
+
+/// Scrolls to ``y`` over a few frames if the page has ``scroll-behavior: smooth``, or at once.
+/// The draw handler moves it on.
+fn scroll_smoothly_to(view: &View, y: f64) {
+    if !interface::smooth_scrolling() {
+        return scroll_to(view, y);
+    }
+    let mut state = view.borrow_mut();
+    let zoom = state.zoom;
+    let (from, to) = {
+        let mut s = state.scroll;
+        let from = s.y;
+        s.scroll_to(y);
+        (from, s.y)
+    };
+    let animation = ScrollAnimation::new((0.0, from / zoom), (0.0, to / zoom), Instant::now());
+    state.scroll_animation = Some(animation);
+    state.frames.request_animation_frame();
+}
+
+/// Starts dragging the scrollbar thumb, or scrolls by a page if the track is clicked.
+/// Returns false if (`x`, `y`) is not on the scrollbar.
+fn press_scrollbar(view: &View, viewport_width: f64, x: f64, y: f64) -> bool {
+    let scroll = view.borrow().scroll;
+    let width = viewport_scrollbar_width();
+    if !scroll.is_scrollable() || width == 0.0 || x < viewport_width - width {
+        return false;
+    }
+
+    let (thumb_top, thumb_height) = scroll.thumb();
+    if y < thumb_top {
+        scroll_to(view, scroll.y - scroll.viewport_height);
+    } else if thumb_top + thumb_height < y {
+        scroll_to(view, scroll.y + scroll.viewport_height);
+    } else {
+        view.borrow_mut().scrollbar_drag = Some(y - thumb_top);
+    }
+    true
+}
+
+/// Dark for a dark GTK theme: with ``gtk-application-prefer-dark-theme``, or named like
+/// ``Adwaita-dark``
+fn theme_color_scheme(settings: &gtk::Settings) -> ColorScheme {
+    let dark_name = settings
+        .get_property_gtk_theme_name()
+        .is_some_and(|name| name.to_lowercase().ends_with("-dark"));
+    if settings.get_property_gtk_application_prefer_dark_theme() || dark_name {
+        ColorScheme::Dark
+    } else {
+        ColorScheme::Light
+    }
+}
+
+/// Styles the page for ``scheme`` (see ``interface::set_preferred_color_scheme``), and paints it
+/// again in ``drawing_area``.
+fn set_preferred_color_scheme(view: &View, drawing_area: &gtk::DrawingArea, scheme: ColorScheme) {
+    interface::set_preferred_color_scheme(scheme);
+    schedule_frame(view, drawing_area, Invalidation::Raster);
+}
+
+/// The width of the scrollbar of the viewport, by ``scrollbar-width`` on the root
+fn viewport_scrollbar_width() -> f64 {
+    interface::viewport_scrollbar(SCROLLBAR_WIDTH, false).0
+}
+
+fn draw_scrollbar(ctx: &Context, viewport_width: f64, state: &ViewState) {
+    let scroll = &state.scroll;
+    if !scroll.is_scrollable() {
+        return;
+    }
+    let hovered = state.scrollbar_hovered || state.scrollbar_drag.is_some();
+    let (width, track, thumb) = interface::viewport_scrollbar(SCROLLBAR_WIDTH, hovered);
+    let set_color = |color: Color| {
+        ctx.set_source_rgba(
+            color.r as f64 / 255.0,
+            color.g as f64 / 255.0,
+            color.b as f64 / 255.0,
+            color.a as f64 / 255.0,
+        )
+    };
+
+    // Track
+    set_color(track);
+    ctx.rectangle(viewport_width - width, 0.0, width, scroll.viewport_height);
+    ctx.fill();
+
+    // Thumb
+    let (thumb_top, thumb_height) = scroll.thumb();
+    let inset = width / 6.0;
+    set_color(thumb);
+    ctx.rectangle(
+        viewport_width - width + inset,
+        thumb_top + inset,
+        width - inset * 2.0,
+        thumb_height - inset * 2.0,
+    );
+    ctx.fill();
+}
+
+impl ScrollState {
+    pub fn max_y(&self) -> f64 {
+        (self.content_height - self.viewport_height).max(0.0)
+    }
+
+    pub fn is_scrollable(&self) -> bool {
+        self.max_y() > 0.0
+    }
+
+    pub fn scroll_to(&mut self, y: f64) {
+        self.y = y.min(self.max_y()).max(0.0);
+    }
+
+    /// (top, height) of the scrollbar thumb in the viewport.
+    pub fn thumb(&self) -> (f64, f64) {
+        if !self.is_scrollable() {
+            return (0.0, self.viewport_height);
+        }
+        let height = (self.viewport_height * self.viewport_height / self.content_height)
+            .max(SCROLLBAR_MIN_THUMB_HEIGHT)
+            .min(self.viewport_height);
+        let top = (self.viewport_height - height) * self.y / self.max_y();
+        (top, height)
+    }
+
+    /// The scroll position where the top of the thumb is at `thumb_top`.
+    pub fn y_for_thumb_top(&self, thumb_top: f64) -> f64 {
+        let (_, height) = self.thumb();
+        let track = self.viewport_height - height;
+        if track <= 0.0 {
+            0.0
+        } else {
+            thumb_top / track * self.max_y()
+        }
+    }
+}
+
+fn clear_text_inputs() {
+    TEXT_INPUTS.with(|text_inputs| text_inputs.borrow_mut().clear());
+    FOCUSED_TEXT_INPUT.with(|f| *f.borrow_mut() = None);
+    SELECTS.with(|selects| selects.borrow_mut().clear());
+    FOCUSED_SELECT.with(|f| f.set(None));
+}
+
+fn measure_text(font: &Font, text: &str) -> f64 {
+    font.select().text_width(text)
+}
+
+/// The text position nearest to the point in the document (in CSS px).
+fn text_position_at(view: &View, x: f64, y: f64) -> Option<TextPosition> {
+    selection::position_at(&view.borrow().display_list, x, y, measure_text)
+}
+
+/// Puts the selected text on the clipboard. Returns false if nothing is selected.
+fn copy_selection(view: &View) -> bool {
+    let text = {
+        let state = view.borrow();
+        match state.selection {
+            Some(ref selection) => selection.text(&state.display_list),
+            None => "".to_string(),
+        }
+    };
+    if text.is_empty() {
+        return false;
+    }
+    gtk::Clipboard::get(&::gdk::SELECTION_CLIPBOARD).set_text(text.as_str());
+    true
+}
+
+/// Handles Ctrl+C. Returns true if the selection in the page is copied.
+fn copy_by_key(view: &View, event: &EventKey) -> bool {
+    // Ctrl+Shift+C is the inspector.
+    let ctrl_c = event.get_state().contains(ModifierType::CONTROL_MASK)
+        && !event.get_state().contains(ModifierType::SHIFT_MASK)
+        && (event.get_keyval() == key::c || event.get_keyval() == key::C);
+    // The address bar copies its own text.
+    let address_bar_focused = match view.borrow().toolbar {
+        Some(ref toolbar) => toolbar.address_bar.has_focus(),
+        None => false,
+    };
+    ctrl_c && !address_bar_focused && copy_selection(view)
+}
+
+/// Handles Ctrl+Shift+P. Returns true if the HUD is shown or hidden.
+fn toggle_hud_by_key(view: &View, event: &EventKey) -> bool {
+    let state = event.get_state();
+    let ctrl_shift_p = state.contains(ModifierType::CONTROL_MASK)
+        && state.contains(ModifierType::SHIFT_MASK)
+        && (event.get_keyval() == key::p || event.get_keyval() == key::P);
+    if ctrl_shift_p {
+        let mut state = view.borrow_mut();
+        state.hud_visible = !state.hud_visible;
+    }
+    ctrl_shift_p
+}
+
+/// Handles Ctrl+Shift+G. Returns true if the page is rasterized with the other renderer (see
+/// ``gpu``).
+fn toggle_renderer_by_key(view: &View, event: &EventKey) -> bool {
+    let state = event.get_state();
+    let ctrl_shift_g = state.contains(ModifierType::CONTROL_MASK)
+        && state.contains(ModifierType::SHIFT_MASK)
+        && (event.get_keyval() == key::g || event.get_keyval() == key::G);
+    if ctrl_shift_g {
+        gpu::set_renderer(match gpu::renderer() {
+            Renderer::Cairo => Renderer::Gpu,
+            Renderer::Gpu => Renderer::Cairo,
+        });
+        view.borrow_mut().frames.invalidate(Invalidation::Raster);
+    }
+    ctrl_shift_g
+}
+
+/// Draws the timings of the pipeline at the top left of the page.
+fn draw_hud(ctx: &Context, state: &ViewState) {
+    if !state.hud_visible {
+        return;
+    }
+    let pango_layout = match pangocairo::functions::create_layout(ctx) {
+        Some(pango_layout) => pango_layout,
+        None => return,
+    };
+    pango_layout.set_font_description(&pango::FontDescription::from_string("monospace 9"));
+    let mut summary = stats::stats().summary();
+    summary.push(format!("frames: {}", state.frames.frame_count()));
+    summary.push(format!("renderer: {}", gpu::renderer().name()));
+    let tiles = state.tiles.as_ref().map_or(0, |tiles| tiles.len());
+    summary.push(format!("tiles: {}", tiles));
+    pango_layout.set_text(summary.join("\n").as_str());
+    let (width, height) = pango_layout.get_pixel_size();
+
+    ctx.set_source_rgba(0.0, 0.0, 0.0, 0.7);
+    ctx.rectangle(4.0, 4.0, width as f64 + 8.0, height as f64 + 8.0);
+    ctx.fill();
+    ctx.set_source_rgb(1.0, 1.0, 1.0);
+    ctx.move_to(8.0, 8.0);
+    pangocairo::functions::show_layout(ctx, &pango_layout);
+}
+
+/// Handles Ctrl+Shift+B. Returns true if the box model overlay is shown or hidden.
+fn toggle_box_model_by_key(view: &View, event: &EventKey) -> bool {
+    let state = event.get_state();
+    let ctrl_shift_b = state.contains(ModifierType::CONTROL_MASK)
+        && state.contains(ModifierType::SHIFT_MASK)
+        && (event.get_keyval() == key::b || event.get_keyval() == key::B);
+    if ctrl_shift_b {
+        let mut state = view.borrow_mut();
+        state.box_model_visible = !state.box_model_visible;
+    }
+    ctrl_shift_b
+}
+
+/// Handles Ctrl+Shift+C. Returns true if the inspector mode is turned on or off.
+fn toggle_inspecting_by_key(view: &View, event: &EventKey) -> bool {
+    let state = event.get_state();
+    let ctrl_shift_c = state.contains(ModifierType::CONTROL_MASK)
+        && state.contains(ModifierType::SHIFT_MASK)
+        && (event.get_keyval() == key::c || event.get_keyval() == key::C);
+    if ctrl_shift_c {
+        let mut state = view.borrow_mut();
+        state.inspecting = !state.inspecting;
+    }
+    ctrl_shift_c
+}
+
+/// Fills the margin, border, padding and content areas of every box in the colors of the
+/// devtools of browsers.
+fn draw_box_model(ctx: &Context, state: &ViewState) {
+    if !state.box_model_visible {
+        return;
+    }
+    let fill_between = |outer: Rect, inner: Option<Rect>, (r, g, b): (f64, f64, f64)| {
+        ctx.set_source_rgba(r / 255.0, g / 255.0, b / 255.0, 0.35);
+        for rect in Some(outer).iter().chain(inner.iter()) {
+            ctx.rectangle(
+                rect.x.to_f64_px(),
+                rect.y.to_f64_px(),
+                rect.width.to_f64_px(),
+                rect.height.to_f64_px(),
+            );
+        }
+        ctx.fill();
+    };
+    ctx.save();
+    // The inner rectangle makes a hole
+    ctx.set_fill_rule(cairo::FillRule::EvenOdd);
+    for d in box_model() {
+        fill_between(d.margin_box(), Some(d.border_box()), (246.0, 178.0, 107.0));
+        fill_between(d.border_box(), Some(d.padding_box()), (255.0, 229.0, 153.0));
+        fill_between(d.padding_box(), Some(d.content), (147.0, 196.0, 125.0));
+        fill_between(d.content, None, (111.0, 168.0, 220.0));
+    }
+    ctx.restore();
+}
+
+/// Shows the pointer for the CSS ``cursor`` over the page.
+fn set_cursor(view: &View, overlay: &gtk::Overlay, cursor: style::Cursor) {
+    use gdk::WindowExt;
+    let cursor_type = match cursor {
+        style::Cursor::Auto | style::Cursor::Default => CursorType::LeftPtr,
+        style::Cursor::None => CursorType::BlankCursor,
+        style::Cursor::Pointer => CursorType::Hand1,
+        style::Cursor::Text => CursorType::Xterm,
+        style::Cursor::Wait | style::Cursor::Progress => CursorType::Watch,
+        style::Cursor::Help => CursorType::QuestionArrow,
+        style::Cursor::Crosshair => CursorType::Crosshair,
+        style::Cursor::Move => CursorType::Fleur,
+        style::Cursor::NotAllowed => CursorType::XCursor,
+        style::Cursor::ColResize => CursorType::SbHDoubleArrow,
+        style::Cursor::RowResize => CursorType::SbVDoubleArrow,
+    };
+    // Creating a cursor on every motion event is wasteful.
+    if view.borrow_mut().cursor.replace(cursor_type) == Some(cursor_type) {
+        return;
+    }
+    if let Some(window) = overlay.get_window() {
+        window.set_cursor(Some(&Cursor::new(cursor_type)));
+    }
+}
+
+/// The link at the point in the document (in CSS px).
+fn anker_at(x: f64, y: f64) -> Option<AnkerKind> {
+    ANKERS.with(|ankers| {
+        ankers
+            .borrow()
+            .iter()
+            .find(|&(rect, _)| rect.contains_point(x, y))
+            .map(|(_, ankerkind)| ankerkind.clone())
+    })
+}
+
+/// The image at the point in the document (in CSS px).
+fn image_at(view: &View, x: f64, y: f64) -> Option<::gdk_pixbuf::Pixbuf> {
+    view.borrow().display_list.iter().rev().find_map(|item| match item.command {
+        DisplayCommand::Image(ref pixbuf, _, rect) if rect.contains_point(x, y) => {
+            Some(pixbuf.clone())
+        }
+        _ => None,
+    })
+}
+
+/// The absolute URL the link goes to.
+fn link_address(ankerkind: &AnkerKind) -> String {
+    match *ankerkind {
+        AnkerKind::URL(ref url) => url.clone(),
+        AnkerKind::URLFragment(ref id) => match current_url() {
+            Some(mut url) => {
+                url.set_fragment(Some(id.as_str()));
+                url.into_string()
+            }
+            None => format!("#{}", id),
+        },
+    }
+}
+
+/// Shows the menu for what is at (``x``, ``y``) in the document (in CSS px).
+fn show_context_menu(view: &View, overlay: &gtk::Overlay, x: f64, y: f64, time: u32) {
+    let drawing_area = overlay.get_children()[0].clone(); // [0] is DrawingArea
+    let menu = gtk::Menu::new();
+
+    if let Some(ankerkind) = anker_at(x, y) {
+        let address = link_address(&ankerkind);
+        append_menu_item(view, &menu, &drawing_area, "Open link", move |view| {
+            follow_link(view, ankerkind.clone())
+        });
+        append_menu_item(view, &menu, &drawing_area, "Copy link address", move |_| {
+            gtk::Clipboard::get(&::gdk::SELECTION_CLIPBOARD).set_text(address.as_str())
+        });
+    }
+    if let Some(pixbuf) = image_at(view, x, y) {
+        append_menu_item(view, &menu, &drawing_area, "Copy image", move |_| {
+            gtk::Clipboard::get(&::gdk::SELECTION_CLIPBOARD).set_image(&pixbuf)
+        });
+    }
+    let has_selection = match view.borrow().selection {
+        Some(ref selection) => !selection.is_collapsed(),
+        None => false,
+    };
+    if has_selection {
+        append_menu_item(view, &menu, &drawing_area, "Copy", |view| {
+            copy_selection(view);
+        });
+    }
+    if let Some(url) = current_url() {
+        if url.scheme() != "view-source" {
+            append_menu_item(view, &menu, &drawing_area, "View source", move |view| {
+                navigate(view, format!("view-source:{}", url.as_str()))
+            });
+        }
+    }
+    if let Some(node) = node_at(x, y) {
+        append_menu_item(view, &menu, &drawing_area, "Inspect element", move |_| {
+            inspect_node(&node)
+        });
+    }
+
+    menu.show_all();
+    menu.popup_easy(3, time);
+    view.borrow_mut().context_menu = Some(menu);
+}
+
+fn append_menu_item<F: Fn(&View) + 'static>(
+    view: &View,
+    menu: &gtk::Menu,
+    drawing_area: &gtk::Widget,
+    label: &str,
+    action: F,
+) {
+    let item = gtk::MenuItem::new_with_label(label);
+    let (view, drawing_area) = (view.clone(), drawing_area.clone());
+    item.connect_activate(move |_| {
+        action(&view);
+        schedule_frame(&view, &drawing_area, Invalidation::Paint);
+    });
+    menu.append(&item);
+}
+
+/// Shows the element and its descendants in another window.
+fn inspect_node(node: &Node) {
+    show_text_window(
+        match node.data {
+            NodeType::Element(ref e) => format!("Inspect: <{}>", e.tag_name),
+            NodeType::Text(_) => "Inspect: #text".to_string(),
+        }.as_str(),
+        node.to_string().as_str(),
+    );
+}
+
+/// Prints what the inspector reports on the element at (``x``, ``y``) in the document, and shows
+/// it in another window.
+fn inspect_element_at(x: f64, y: f64) {
+    if let Some(report) = inspect_at(x, y) {
+        println!("{}", report);
+        show_text_window(
+            format!("Inspect: {}", report.lines().next().unwrap_or("")).as_str(),
+            report.as_str(),
+        );
+    }
+}
+
+fn show_text_window(title: &str, text: &str) {
+    let window = gtk::Window::new(gtk::WindowType::Toplevel);
+    window.set_title(title);
+    window.set_default_size(480, 360);
+
+    let text_view = gtk::TextView::new();
+    text_view.set_editable(false);
+    text_view.set_property_monospace(true);
+    if let Some(buffer) = text_view.get_buffer() {
+        buffer.set_text(text);
+    }
+    let scrolled_window = gtk::ScrolledWindow::new(None, None);
+    scrolled_window.add(&text_view);
+    window.add(&scrolled_window);
+    window.show_all();
+}
+
+fn follow_link(view: &View, ankerkind: AnkerKind) {
+    let address = link_address(&ankerkind);
+    let handlers = view.borrow().signals.link_clicked.clone();
+    if handlers.iter().any(|handler| handler(address.as_str()).0) {
+        return;
+    }
+    match ankerkind {
+        AnkerKind::URL(url) => navigate(view, url),
+        AnkerKind::URLFragment(id) => {
+            let content_y = URL_FRAGMENTS.with(|ufs| ufs.borrow().get(&id).cloned());
+            if let Some(content_y) = content_y {
+                let zoom = view.borrow().zoom;
+                scroll_smoothly_to(view, content_y * zoom);
+            }
+        }
+    }
+}
+
+/// Handles the arrow keys, PageUp, PageDown, Space, Home and End.
+/// Returns true if the key press is consumed.
+fn scroll_by_key(view: &View, event: &EventKey) -> bool {
+    let scroll = view.borrow().scroll;
+    let page = (scroll.viewport_height - PAGE_SCROLL_OVERLAP).max(SCROLL_STEP);
+    let shift = event.get_state().contains(ModifierType::SHIFT_MASK);
+    let y = match event.get_keyval() {
+        key::Up => scroll.y - SCROLL_STEP,
+        key::Down => scroll.y + SCROLL_STEP,
+        key::Page_Up => scroll.y - page,
+        key::Page_Down => scroll.y + page,
+        key::space if shift => scroll.y - page,
+        key::space => scroll.y + page,
+        key::Home => 0.0,
+        key::End => scroll.max_y(),
+        _ => return false,
+    };
+    scroll_to(view, y);
+    true
+}
+
+/// Handles Tab and Shift+Tab (moves the focus among links and form controls), and Enter (follows
+/// the focused link). Returns true if the key press is consumed.
+fn move_focus_by_key(view: &View, event: &EventKey) -> bool {
+    match event.get_keyval() {
+        key::Tab => move_focus(view, !event.get_state().contains(ModifierType::SHIFT_MASK)),
+        key::ISO_Left_Tab => move_focus(view, false),
+        key::Return | key::KP_Enter => {
+            let focused_link = view.borrow_mut().focused_link.take();
+            match focused_link {
+                Some(ankerkind) => {
+                    follow_link(view, ankerkind);
+                    true
+                }
+                None => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Links and form controls in the order they are painted (top to bottom, left to right).
+fn focus_targets() -> Vec<FocusTarget> {
+    let mut targets: Vec<FocusTarget> = ANKERS.with(|ankers| {
+        ankers
+            .borrow()
+            .iter()
+            .map(|(rect, ankerkind)| FocusTarget::Link(*rect, ankerkind.clone()))
+            .collect()
+    });
+    TEXT_INPUTS.with(|text_inputs| {
+        for (id, area) in text_inputs.borrow().iter() {
+            targets.push(FocusTarget::TextInput(*id, area.rect));
+        }
+    });
+    SELECTS.with(|selects| {
+        for (id, area) in selects.borrow().iter().filter(|&(_, area)| area.pointer_events) {
+            targets.push(FocusTarget::Select(*id, area.rect));
+        }
+    });
+    targets.sort_by_key(|target| {
+        let rect = target.rect();
+        (rect.y, rect.x)
+    });
+    // A link broken into several lines is focused once.
+    targets.dedup_by(|b, a| match (a, b) {
+        (&mut FocusTarget::Link(_, ref a), &mut FocusTarget::Link(_, ref b)) => a == b,
+        _ => false,
+    });
+    targets
+}
+
+impl FocusTarget {
+    fn rect(&self) -> Rect {
+        match *self {
+            FocusTarget::Link(rect, _)
+            | FocusTarget::TextInput(_, rect)
+            | FocusTarget::Select(_, rect) => rect,
+        }
+    }
+}
+
+/// Moves the keyboard focus to the next (or previous) link or form control.
+/// Returns false if there is nothing to focus.
+fn move_focus(view: &View, forward: bool) -> bool {
+    let targets = focus_targets();
+    if targets.is_empty() {
+        return false;
+    }
+
+    let focused_link = view.borrow().focused_link.clone();
+    let focused_input = FOCUSED_TEXT_INPUT.with(|f| f.borrow().as_ref().map(|caret| caret.id));
+    let focused_select = FOCUSED_SELECT.with(|f| f.get());
+    let current = targets.iter().position(|target| match *target {
+        FocusTarget::Link(_, ref ankerkind) => focused_link.as_ref() == Some(ankerkind),
+        FocusTarget::TextInput(id, _) => focused_input == Some(id),
+        FocusTarget::Select(id, _) => focused_select == Some(id),
+    });
+    let len = targets.len();
+    let next = match current {
+        Some(i) if forward => (i + 1) % len,
+        Some(i) => (i + len - 1) % len,
+        None if forward => 0,
+        None => len - 1,
+    };
+
+    let target = targets[next].clone();
+    let (caret, select) = match target {
+        FocusTarget::Link(_, ref ankerkind) => {
+            view.borrow_mut().focused_link = Some(ankerkind.clone());
+            (None, None)
+        }
+        FocusTarget::TextInput(id, _) => {
+            view.borrow_mut().focused_link = None;
+            let pos = TEXT_INPUTS.with(|t| t.borrow().get(&id).map(|a| a.value.len()));
+            let caret = Caret {
+                id: id,
+                pos: pos.unwrap_or(0),
+            };
+            (Some(caret), None)
+        }
+        FocusTarget::Select(id, _) => {
+            view.borrow_mut().focused_link = None;
+            (None, Some(id))
+        }
+    };
+    if focused_input.is_some() || caret.is_some() || focused_select.is_some() || select.is_some() {
+        FOCUSED_TEXT_INPUT.with(|f| *f.borrow_mut() = caret);
+        FOCUSED_SELECT.with(|f| f.set(select));
+        // Repaint the focus ring.
+        view.borrow_mut().frames.invalidate(Invalidation::Layout);
+    }
+
+    scroll_into_view(view, target.rect());
+    true
+}
+
+/// Scrolls as little as possible to show ``rect`` (in CSS px).
+fn scroll_into_view(view: &View, rect: Rect) {
+    let (scroll, zoom) = {
+        let state = view.borrow();
+        (state.scroll, state.zoom)
+    };
+    let top = rect.y.to_f64_px() * zoom;
+    let bottom = (rect.y + rect.height).to_f64_px() * zoom;
+    if top < scroll.y {
+        scroll_to(view, top - SCROLL_STEP);
+    } else if scroll.y + scroll.viewport_height < bottom {
+        scroll_to(view, bottom - scroll.viewport_height + SCROLL_STEP);
+    }
+}
+
+fn draw_focused_link(ctx: &Context, state: &ViewState) {
+    let focused_link = match state.focused_link {
+        Some(ref focused_link) => focused_link,
+        None => return,
+    };
+    let color = FOCUS_RING_COLOR;
+    ctx.set_source_rgb(
+        color.r as f64 / 255.0,
+        color.g as f64 / 255.0,
+        color.b as f64 / 255.0,
+    );
+    ctx.set_line_width(2.0);
+    // Every line of the link
+    ANKERS.with(|ankers| {
+        for (rect, _) in ankers.borrow().iter().filter(|&(_, a)| a == focused_link) {
+            ctx.rectangle(
+                rect.x.to_f64_px() - 1.0,
+                rect.y.to_f64_px() - 1.0,
+                rect.width.to_f64_px() + 2.0,
+                rect.height.to_f64_px() + 2.0,
+            );
+        }
+    });
+    ctx.stroke();
+}
+
+/// Handles Ctrl+F. Returns true if the find bar is opened.
+fn find_by_key(view: &View, event: &EventKey) -> bool {
+    if !event.get_state().contains(ModifierType::CONTROL_MASK)
+        || (event.get_keyval() != key::f && event.get_keyval() != key::F)
+    {
+        return false;
+    }
+    let find_bar = view.borrow().find_bar.clone();
+    if let Some(find_bar) = find_bar {
+        find_bar.bar.show();
+        find_bar.entry.grab_focus();
+    }
+    true
+}
+
+fn close_find_bar(view: &View) {
+    let find_bar = view.borrow().find_bar.clone();
+    if let Some(find_bar) = find_bar {
+        find_bar.bar.hide();
+        // Calls the handler of the entry, which finds nothing.
+        find_bar.entry.set_text("");
+    }
+    find_in_page(view, "".to_string());
+}
+
+/// Highlights the matches of ``query`` and scrolls to the first one.
+fn find_in_page(view: &View, query: String) {
+    {
+        let find = &mut view.borrow_mut().find;
+        find.query = query;
+        find.current = 0;
+    }
+    update_find_matches(view);
+    scroll_to_current_match(view);
+}
+
+/// Moves to the next match (``step == 1``) or the previous one (``step == -1``).
+fn find_next(view: &View, step: isize) {
+    {
+        let find = &mut view.borrow_mut().find;
+        let len = find.matches.len() as isize;
+        if len > 0 {
+            find.current = ((find.current as isize + step + len) % len) as usize;
+        }
+    }
+    update_find_status(view);
+    scroll_to_current_match(view);
+}
+
+fn update_find_matches(view: &View) {
+    {
+        let state = &mut *view.borrow_mut();
+        let matches = selection::find_all(&state.display_list, &state.find.query);
+        state.find.current = min(state.find.current, matches.len().saturating_sub(1));
+        state.find.matches = matches;
+    }
+    update_find_status(view);
+}
+
+fn update_find_status(view: &View) {
+    let (status, find_bar) = {
+        let state = view.borrow();
+        let find = &state.find;
+        let status = if find.query.is_empty() {
+            "".to_string()
+        } else if find.matches.is_empty() {
+            "Not found".to_string()
+        } else {
+            format!("{} of {}", find.current + 1, find.matches.len())
+        };
+        (status, state.find_bar.clone())
+    };
+    if let Some(find_bar) = find_bar {
+        find_bar.status.set_text(status.as_str());
+    }
+}
+
+/// Scrolls so that the current match is in the upper part of the viewport.
+fn scroll_to_current_match(view: &View) {
+    let (rect_y, viewport_height, zoom) = {
+        let state = view.borrow();
+        let rect_y = state.find.matches.get(state.find.current).and_then(|current| {
+            current
+                .rects(&state.display_list, measure_text)
+                .first()
+                .map(|rect| rect.y.to_f64_px())
+        });
+        (rect_y, state.scroll.viewport_height, state.zoom)
+    };
+    if let Some(y) = rect_y {
+        scroll_to(view, y * zoom - viewport_height / 3.0);
+    }
+}
+
+fn draw_find_matches(ctx: &Context, state: &ViewState) {
+    let find = &state.find;
+    for (i, found) in find.matches.iter().enumerate() {
+        let (r, g, b, a) = if i == find.current {
+            FIND_CURRENT_MATCH_COLOR
+        } else {
+            FIND_MATCH_COLOR
+        };
+        ctx.set_source_rgba(r, g, b, a);
+        for rect in found.rects(&state.display_list, measure_text) {
+            ctx.rectangle(
+                rect.x.to_f64_px(),
+                rect.y.to_f64_px(),
+                rect.width.to_f64_px(),
+                rect.height.to_f64_px(),
+            );
+        }
+        ctx.fill();
+    }
+}
+
+fn draw_selection(ctx: &Context, state: &ViewState) {
+    if let Some(ref selection) = state.selection {
+        let (r, g, b, a) = SELECTION_COLOR;
+        ctx.set_source_rgba(r, g, b, a);
+        for rect in selection.rects(&state.display_list, measure_text) {
+            ctx.rectangle(
+                rect.x.to_f64_px(),
+                rect.y.to_f64_px(),
+                rect.width.to_f64_px(),
+                rect.height.to_f64_px(),
+            );
+        }
+        ctx.fill();
+    }
+}
+
+fn draw_caret(ctx: &Context, state: &ViewState) {
+    if !state.caret_visible {
+        return;
+    }
+    FOCUSED_TEXT_INPUT.with(|focused| {
+        if let Some(ref caret) = *focused.borrow() {
+            TEXT_INPUTS.with(|text_inputs| {
+                if let Some(area) = text_inputs.borrow().get(&caret.id) {
+                    let rect = area.caret_rect(caret.pos);
+                    ctx.set_source_rgb(0.0, 0.0, 0.0);
+                    ctx.rectangle(
+                        rect.x.to_f64_px(),
+                        rect.y.to_f64_px(),
+                        rect.width.to_f64_px(),
+                        rect.height.to_f64_px(),
+                    );
+                    ctx.fill();
+                }
+            })
+        }
+    });
+}
+
+/// The ``key`` of the DOM key event of ``event``, e.g. ``a`` or ``Enter``.
+fn key_name(event: &EventKey) -> String {
+    let keyval = event.get_keyval();
+    match keyval {
+        key::Return | key::KP_Enter => "Enter",
+        key::BackSpace => "Backspace",
+        key::Delete => "Delete",
+        key::Tab | key::ISO_Left_Tab => "Tab",
+        key::Escape => "Escape",
+        key::Left => "ArrowLeft",
+        key::Right => "ArrowRight",
+        key::Up => "ArrowUp",
+        key::Down => "ArrowDown",
+        key::Home => "Home",
+        key::End => "End",
+        key::Page_Up => "PageUp",
+        key::Page_Down => "PageDown",
+        _ => match ::gdk::keyval_to_unicode(keyval) {
+            Some(c) if !c.is_control() => return c.to_string(),
+            _ => "Unidentified",
+        },
+    }.to_string()
+}
+
+/// Runs the tasks due at ``due`` (see ``interface::run_due_tasks``) from the main loop then,
+/// unless it already wakes up for them earlier.
+fn wake_up_for_tasks(view: &View, drawing_area: &gtk::DrawingArea, due: Instant) {
+    if TASK_WAKEUP.with(|w| w.get()).is_some_and(|wakeup| wakeup <= due) {
+        return;
+    }
+    TASK_WAKEUP.with(|w| w.set(Some(due)));
+    // Rounded up, not to wake up before the task is due
+    let delay = (due.saturating_duration_since(Instant::now()).as_micros() as u64).div_ceil(1000);
+    let (view, drawing_area) = (view.clone(), drawing_area.clone());
+    gtk::timeout_add(delay as u32, move || {
+        TASK_WAKEUP.with(|w| w.set(None));
+        if interface::run_due_tasks() {
+            schedule_frame(&view, &drawing_area, Invalidation::Raster);
+        }
+        Continue(false)
+    });
+}
+
+/// Lays out and paints the page again if the listeners of an event changed the DOM.
+/// The node id of the <select> at (``x``, ``y``) in the document (in CSS px).
+fn select_at(x: f64, y: f64) -> Option<usize> {
+    SELECTS.with(|selects| {
+        selects
+            .borrow()
+            .iter()
+            .find(|&(_, area)| area.pointer_events && area.rect.contains_point(x, y))
+            .map(|(id, _)| *id)
+    })
+}
+
+/// Shows the options of the <select> whose node id is ``id`` in a popup list, which selects the
+/// option chosen. It is opened by ``button`` (0 for a key).
+fn show_select_popup(view: &View, drawing_area: &gtk::Widget, id: usize, button: u32, time: u32) {
+    let area = match SELECTS.with(|selects| selects.borrow().get(&id).cloned()) {
+        Some(area) => area,
+        None => return,
+    };
+    let menu = gtk::Menu::new();
+    for (i, label) in area.options.iter().enumerate() {
+        let item = gtk::CheckMenuItem::new_with_label(label);
+        item.set_draw_as_radio(true);
+        item.set_active(area.selected == Some(i));
+        let (view, drawing_area) = (view.clone(), drawing_area.clone());
+        item.connect_activate(move |_| select_option(&view, &drawing_area, id, i));
+        menu.append(&item);
+    }
+    menu.show_all();
+    menu.popup_easy(button, time);
+    view.borrow_mut().context_menu = Some(menu);
+}
+
+/// Selects the option at ``index`` of the <select> whose node id is ``id``.
+fn select_option(view: &View, drawing_area: &gtk::Widget, id: usize, index: usize) {
+    let dispatched = interface::set_selected_option(id, index);
+    repaint_if_dom_changed(view, drawing_area, dispatched);
+    schedule_frame(view, drawing_area, Invalidation::Raster);
+}
+
+/// Handles a key press on the focused <select>: Up and Down select the option before or after
+/// the selected one, and Enter or Space shows the options. Returns true if it is consumed.
+fn change_focused_select(view: &View, drawing_area: &gtk::Widget, event: &EventKey) -> bool {
+    let id = match FOCUSED_SELECT.with(|f| f.get()) {
+        Some(id) => id,
+        None => return false,
+    };
+    let area = match SELECTS.with(|selects| selects.borrow().get(&id).cloned()) {
+        Some(area) => area,
+        None => return false,
+    };
+    let last = match area.options.len().checked_sub(1) {
+        Some(last) => last,
+        None => return false,
+    };
+    let selected = area.selected.unwrap_or(0);
+    match event.get_keyval() {
+        key::Up | key::Left => select_option(view, drawing_area, id, selected.saturating_sub(1)),
+        key::Down | key::Right => select_option(view, drawing_area, id, min(selected + 1, last)),
+        key::Home => select_option(view, drawing_area, id, 0),
+        key::End => select_option(view, drawing_area, id, last),
+        key::Return | key::KP_Enter | key::space => {
+            show_select_popup(view, drawing_area, id, 0, event.get_time())
+        }
+        _ => return false,
+    }
+    true
+}
+
+/// Has ``widget`` paint the page of ``view`` again on the next frame, after doing again what
+/// ``invalidation`` requires (see ``frame``).
+fn schedule_frame<W: WidgetExt>(view: &View, widget: &W, invalidation: Invalidation) {
+    view.borrow_mut().frames.invalidate(invalidation);
+    request_frame(widget);
+}
+
+/// Requests the next frame of the frame clock of ``widget``, on which the page is painted if
+/// anything has to be. Until ``widget`` is realized, it is painted when it is shown.
+fn request_frame<W: WidgetExt>(widget: &W) {
+    match widget.get_frame_clock() {
+        Some(clock) => clock.request_phase(FrameClockPhase::UPDATE),
+        None => widget.queue_draw(),
+    }
+}
+
+fn repaint_if_dom_changed<W: WidgetExt>(view: &View, drawing_area: &W, dispatched: Dispatched) {
+    if dispatched.dom_changed {
+        schedule_frame(view, drawing_area, Invalidation::Raster);
+    }
+}
+
+/// Handles a key press on the focused text input. Returns true if the key press is consumed.
+fn edit_focused_text_input(view: &View, event: &EventKey) -> bool {
+    let mut caret = match FOCUSED_TEXT_INPUT.with(|f| f.borrow().clone()) {
+        Some(caret) => caret,
+        None => return false,
+    };
+    let area = match TEXT_INPUTS.with(|text_inputs| text_inputs.borrow().get(&caret.id).cloned()) {
+        Some(area) => area,
+        None => return false,
+    };
+    let mut value = area.value.clone();
+    caret.pos = min(caret.pos, value.len());
+
+    let prev_pos = value[..caret.pos]
+        .char_indices()
+        .next_back()
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let next_pos = value[caret.pos..]
+        .chars()
+        .next()
+        .map(|c| caret.pos + c.len_utf8())
+        .unwrap_or(caret.pos);
+
+    let keyval = event.get_keyval();
+    match keyval {
+        key::BackSpace => {
+            value.drain(prev_pos..caret.pos);
+            caret.pos = prev_pos;
+        }
+        key::Delete => {
+            value.drain(caret.pos..next_pos);
+        }
+        key::Left => caret.pos = prev_pos,
+        key::Right => caret.pos = next_pos,
+        key::Up | key::Down if area.multiline => {
+            let rect = area.caret_rect(caret.pos);
+            let y = rect.y.to_f64_px() + area.line_height.to_f64_px() * if keyval == key::Up {
+                -0.5
+            } else {
+                1.5
+            };
+            if area.rect.y.to_f64_px() <= y
+                && y <= area.rect.y.to_f64_px() + area.rect.height.to_f64_px()
+            {
+                caret.pos = area.position_at(rect.x.to_f64_px(), y);
+            }
+        }
+        key::Home => caret.pos = 0,
+        key::End => caret.pos = value.len(),
+        key::Return if area.multiline => {
+            value.insert(caret.pos, '\n');
+            caret.pos += 1;
+        }
+        _ => match ::gdk::keyval_to_unicode(keyval) {
+            Some(c)
+                if !c.is_control() && !event.get_state().contains(ModifierType::CONTROL_MASK) =>
+            {
+                value.insert(caret.pos, c);
+                caret.pos += c.len_utf8();
+            }
+            _ => return false,
+        },
+    }
+
+    if value != area.value {
+        set_text_input_value(caret.id, value);
+        view.borrow_mut().frames.invalidate(Invalidation::Raster);
+    }
+    FOCUSED_TEXT_INPUT.with(|f| *f.borrow_mut() = Some(caret));
+    view.borrow_mut().caret_visible = true;
+    true
+}
+
+/// The back buffer to compose a frame of ``width`` x ``height`` px on, in device px: the one of
+/// the last frame (``bb``) if it is of the same size, cleared.
+fn back_buffer(
+    bb: &mut Option<cairo::ImageSurface>,
+    width: i32,
+    height: i32,
+    device_scale: f64,
+) -> cairo::ImageSurface {
+    let (width, height) = back_buffer_size(width, height, device_scale);
+    match *bb {
+        Some(ref surface) if (surface.get_width(), surface.get_height()) == (width, height) => {
+            let ctx = Context::new(surface);
+            ctx.set_operator(cairo::Operator::Clear);
+            ctx.paint();
+        }
+        _ => {
+            *bb = Some(cairo::ImageSurface::create(cairo::Format::ARgb32, width, height).unwrap())
+        }
+    }
+    bb.as_ref().unwrap().clone()
+}
+
+/// The size in device px of a back buffer of ``width`` x ``height`` px, at least 1 x 1
+fn back_buffer_size(width: i32, height: i32, device_scale: f64) -> (i32, i32) {
+    (
+        ((width as f64 * device_scale).ceil() as i32).max(1),
+        ((height as f64 * device_scale).ceil() as i32).max(1),
+    )
+}
+
+/// The viewport in the document (device px: x, y, width, height)
+fn viewport_area(state: &ViewState, widget: &gtk::DrawingArea) -> (f64, f64, f64, f64) {
+    let (device_scale, scroll) = (state.device_scale, state.scroll);
+    (
+        0.0,
+        scroll.y * device_scale,
+        widget.get_allocated_width() as f64 * device_scale,
+        widget.get_allocated_height() as f64 * device_scale,
+    )
+}
+
+/// The area around the viewport whose tiles are kept: a viewport above and below it
+fn retained_area(viewport: (f64, f64, f64, f64)) -> (f64, f64, f64, f64) {
+    let (x, y, width, height) = viewport;
+    (x, y - height, width, height * 3.0)
+}
+
+/// The tiles over the viewport, and where they are in the document. Those not rasterized yet
+/// are rasterized, and those far from the viewport are dropped.
+fn visible_tiles(
+    view: &View,
+    widget: &gtk::DrawingArea,
+) -> Vec<(cairo::ImageSurface, tile::DeviceRect)> {
+    let state = &mut *view.borrow_mut();
+    let viewport = viewport_area(state, widget);
+    let tiles = state.tiles.as_mut().unwrap();
+    let retained = tiles.tiles_in(retained_area(viewport));
+    tiles.retain(&retained);
+
+    let mut pango_layout = None;
+    let mut visible = vec![];
+    for index in tiles.tiles_in(viewport) {
+        let rect = tiles.tile_rect(index);
+        if tiles.get(index).is_none() {
+            let pango_layout = pango_layout.get_or_insert_with(|| {
+                pango::Layout::new(&widget.create_pango_context().unwrap())
+            });
+            let tile = paint_tile(&state.display_list, tiles.scale, rect, pango_layout);
+            // Left blank if it can't be allocated, and rasterized again on the next frame
+            if let Ok(tile) = tile {
+                tiles.insert(index, tile);
+            }
+        }
+        if let Some(tile) = tiles.get(index) {
+            visible.push((tile.clone(), rect));
+        }
+    }
+    visible
+}
+
+/// Rasterizes the tiles kept around the viewport (see ``retained_area``), one each time the main
+/// loop is idle, so that they are ready when they are scrolled into view.
+fn prefetch_tiles(view: &View, widget: &gtk::DrawingArea) {
+    if mem::replace(&mut view.borrow_mut().prefetching, true) {
+        return;
+    }
+    let (view, widget) = (view.clone(), widget.clone());
+    gtk::idle_add(move || {
+        let state = &mut *view.borrow_mut();
+        let viewport = viewport_area(state, &widget);
+        let prefetched = match state.tiles.as_mut() {
+            // Rasterized again from a new display list on the next frame
+            None => false,
+            Some(tiles) => {
+                let retained = tiles.tiles_in(retained_area(viewport));
+                match tiles.missing(&retained) {
+                    None => false,
+                    Some(index) => {
+                        let rect = tiles.tile_rect(index);
+                        let mut pango_layout =
+                            pango::Layout::new(&widget.create_pango_context().unwrap());
+                        let tile =
+                            paint_tile(&state.display_list, tiles.scale, rect, &mut pango_layout);
+                        match tile {
+                            Ok(tile) => {
+                                tiles.insert(index, tile);
+                                true
+                            }
+                            // Out of memory: the others are not prefetched either.
+                            Err(_) => false,
+                        }
+                    }
+                }
+            }
+        };
+        if !prefetched {
+            state.prefetching = false;
+        }
+        Continue(prefetched)
+    });
+}
+
+pub fn render<F: 'static>(f: F)
+where
+    F: Fn(&gtk::DrawingArea, f64) -> DisplayList,
+{
+    gtk::init().unwrap_or_else(|_| panic!("Failed to initialize GTK."));
+
+    let window = RenderingWindow::new(800, 520, f);
+    window.exit_on_close();
+
+    gtk::main();
+}
+
+#[test]
+fn test_next_zoom_level() {
+    assert_eq!(next_zoom_level(1.0, 1), 1.1);
+    assert_eq!(next_zoom_level(1.0, -1), 0.9);
+    assert_eq!(next_zoom_level(1.5, 0), 1.0);
+    // Clamped at both ends
+    assert_eq!(next_zoom_level(2.4, 1), 2.4);
+    assert_eq!(next_zoom_level(0.3, -1), 0.3);
+}
+
+#[test]
+fn test_back_buffer_size() {
+    assert_eq!(back_buffer_size(800, 520, 1.0), (800, 520));
+    assert_eq!(back_buffer_size(800, 520, 2.0), (1600, 1040));
+    assert_eq!(back_buffer_size(333, 100, 1.5), (500, 150));
+    // Before the drawing area is allocated
+    assert_eq!(back_buffer_size(0, 0, 1.0), (1, 1));
+}
+
+#[test]
+fn test_scroll_state() {
+    let mut scroll = ScrollState {
+        y: 0.0,
+        content_height: 2000.0,
+        viewport_height: 500.0,
+    };
+    assert_eq!(scroll.max_y(), 1500.0);
+    assert_eq!(scroll.thumb(), (0.0, 125.0));
+
+    scroll.scroll_to(3000.0);
+    assert_eq!(scroll.y, 1500.0);
+    assert_eq!(scroll.thumb(), (375.0, 125.0));
+    scroll.scroll_to(-10.0);
+    assert_eq!(scroll.y, 0.0);
+
+    assert_eq!(scroll.y_for_thumb_top(187.5), 750.0);
+
+    scroll.content_height = 400.0;
+    assert!(!scroll.is_scrollable());
+    assert_eq!(scroll.thumb(), (0.0, 500.0));
 }
@@ -0,0 +1,354 @@
+//! A runner for a curated subset of the reftests of the Web Platform Tests
+//! (https://github.com/web-platform-tests/wpt). Unlike ours (see ``reftest``), a WPT reftest
+//! names its references and how fuzzy the comparison is itself:
+//!
+//! ```text
+//! <link rel="match" href="reference/margin-left-001-ref.html">
+//! <meta name="fuzzy" content="maxDifference=0-2;totalPixels=0-100">
+//! ```
+//!
+//! ``include.list`` in the directory of the tests (laid out like WPT) lists the ones to run, one
+//! path a line. ``fails`` before a path marks a test known to fail until the feature is
+//! implemented. A result other than the expected one is reported as unexpected. The tests which
+//! are not reftests (e.g. testharness.js tests) are skipped.
+
+use dom::{Node, NodeType};
+use html;
+use interface;
+use network::Url;
+use reftest::{compare, Comparison, Fuzz, NO_FUZZ, VIEWPORT_HEIGHT, VIEWPORT_WIDTH};
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The tests are parsed as if they were served from here (the host WPT serves them from), so
+/// that the URLs of the references resolve to paths relative to the directory of the tests.
+const ORIGIN: &'static str = "http://web-platform.test/";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WptReftest {
+    /// The references (relative to the directory of the tests) the test must match or mismatch
+    pub references: Vec<(Comparison, String)>,
+    pub fuzz: Fuzz,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    Pass,
+    /// Why
+    Fail(String),
+    Skip(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestResult {
+    /// Relative to the directory of the tests
+    pub path: String,
+    pub status: Status,
+    /// Marked ``fails`` in ``include.list``
+    pub expected_to_fail: bool,
+}
+
+impl TestResult {
+    pub fn is_unexpected(&self) -> bool {
+        match self.status {
+            Status::Pass => self.expected_to_fail,
+            Status::Fail(_) => !self.expected_to_fail,
+            Status::Skip(_) => false,
+        }
+    }
+
+    /// e.g. ``FAIL (expected) css/CSS2/positioning/relpos-001.html: 100 pixels differ ...``
+    pub fn report(&self) -> String {
+        let (status, detail) = match self.status {
+            Status::Pass => ("PASS", None),
+            Status::Fail(ref why) => ("FAIL", Some(why)),
+            Status::Skip(ref why) => ("SKIP", Some(why)),
+        };
+        let expectation = match (self.is_unexpected(), self.expected_to_fail) {
+            (true, _) => " (unexpected)",
+            (false, true) => " (expected)",
+            (false, false) => "",
+        };
+        match detail {
+            Some(detail) => format!("{}{} {}: {}", status, expectation, self.path, detail),
+            None => format!("{}{} {}", status, expectation, self.path),
+        }
+    }
+}
+
+/// e.g. ``12 passed, 2 failed (2 expected), 1 skipped (15 tests)``
+pub fn summary(results: &[TestResult]) -> String {
+    let count = |f: &dyn Fn(&TestResult) -> bool| results.iter().filter(|r| f(r)).count();
+    format!(
+        "{} passed, {} failed ({} expected), {} skipped ({} tests)",
+        count(&|r| r.status == Status::Pass),
+        count(&|r| match r.status {
+            Status::Fail(_) => true,
+            _ => false,
+        }),
+        count(&|r| match r.status {
+            Status::Fail(_) => !r.is_unexpected(),
+            _ => false,
+        }),
+        count(&|r| match r.status {
+            Status::Skip(_) => true,
+            _ => false,
+        }),
+        results.len()
+    )
+}
+
+/// Parses ``include.list``: the paths, and whether the test is expected to fail. Returns the line
+/// number (from 1) and the line if one is invalid.
+pub fn parse_include_list(src: &str) -> Result<Vec<(String, bool)>, (usize, String)> {
+    let mut tests = vec![];
+    for (i, line) in src.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            ["fails", path] => tests.push((path.to_string(), true)),
+            [path] if *path != "fails" => tests.push((path.to_string(), false)),
+            _ => return Err((i + 1, line.to_string())),
+        }
+    }
+    Ok(tests)
+}
+
+/// Parses the ``content`` of ``<meta name="fuzzy">``, e.g. ``maxDifference=0-2;totalPixels=0-100``
+/// or ``2;100``. The upper bounds of the ranges are allowed. A reference before ``:`` is ignored
+/// (the fuzz applies to every reference).
+pub fn parse_fuzzy(content: &str) -> Option<Fuzz> {
+    let content = content.splitn(2, ':').last()?;
+    fn upper_bound<T: FromStr>(range: &str) -> Option<T> {
+        range.trim().rsplit('-').next()?.trim().parse().ok()
+    }
+
+    let mut fuzz = NO_FUZZ;
+    let args: Vec<&str> = content.split(';').collect();
+    if args.len() != 2 {
+        return None;
+    }
+    for (i, arg) in args.iter().enumerate() {
+        let mut name_and_range = arg.splitn(2, '=');
+        let (name, range) = match (name_and_range.next(), name_and_range.next()) {
+            (Some(name), Some(range)) => (name.trim(), range),
+            (Some(range), None) => (if i == 0 { "maxDifference" } else { "totalPixels" }, range),
+            _ => return None,
+        };
+        match name {
+            "maxDifference" => fuzz.max_difference = upper_bound(range)?,
+            "totalPixels" => fuzz.max_pixels = upper_bound(range)?,
+            _ => return None,
+        }
+    }
+    Some(fuzz)
+}
+
+/// The references and the fuzz of the test at ``path`` (relative to the directory of the tests).
+/// None if the test is not a reftest.
+pub fn parse_reftest(src: &str, path: &str) -> Option<WptReftest> {
+    let url = Url::parse(ORIGIN).and_then(|origin| origin.join(path)).ok()?;
+    let document = html::parse(src.to_string(), url);
+    let attrs = |tag_name| {
+        let mut nodes: Vec<&Node> = vec![];
+        document.find_nodes_by_tag_name(tag_name, &mut nodes);
+        nodes
+            .into_iter()
+            .filter_map(|node| match node.data {
+                NodeType::Element(ref elem) => Some(elem.attrs.clone()),
+                NodeType::Text(_) => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut references = vec![];
+    for link in attrs("link") {
+        let rel = link.get("rel").map(|rel| rel.to_lowercase()).unwrap_or_default();
+        let comparison = if rel.split_whitespace().any(|rel| rel == "match") {
+            Comparison::Equal
+        } else if rel.split_whitespace().any(|rel| rel == "mismatch") {
+            Comparison::NotEqual
+        } else {
+            continue;
+        };
+        if let Some(href) = link.get("href") {
+            let reference = href.trim_start_matches(ORIGIN);
+            references.push((comparison, reference.to_string()));
+        }
+    }
+    if references.is_empty() {
+        return None;
+    }
+
+    let fuzz = attrs("meta")
+        .into_iter()
+        .find(|meta| meta.get("name").map(|name| name.as_str()) == Some("fuzzy"))
+        .and_then(|meta| meta.get("content").and_then(|content| parse_fuzzy(content)))
+        .unwrap_or(NO_FUZZ);
+    Some(WptReftest {
+        references: references,
+        fuzz: fuzz,
+    })
+}
+
+/// Runs the test at ``path`` in ``dir`` (the root of the tests, which ``/`` in URLs refers to).
+pub fn run_test(dir: &Path, path: &str) -> Status {
+    let test_path = dir.join(path);
+    let mut src = String::new();
+    if let Err(e) = fs::File::open(&test_path).and_then(|mut f| f.read_to_string(&mut src)) {
+        return Status::Fail(format!("cannot read the test: {}", e));
+    }
+    let test = match parse_reftest(src.as_str(), path) {
+        Some(test) => test,
+        None => return Status::Skip("not a reftest".to_string()),
+    };
+
+    let render = |path: &Path| {
+        let url = format!("file://{}", path.to_str().unwrap());
+        // Like WPT, the viewport is compared (the page may be shorter).
+        interface::render_viewport_headless(url.as_str(), VIEWPORT_WIDTH, VIEWPORT_HEIGHT)
+    };
+    for (comparison, href) in test.references {
+        let reference_path = dir.join(href.as_str());
+        if !reference_path.is_file() {
+            return Status::Fail(format!("cannot find the reference {}", href));
+        }
+        // Rendered again for each reference, as comparing takes the data of the surfaces.
+        let (mut test_surface, mut reference) = match (render(&test_path), render(&reference_path)) {
+            (Ok(test_surface), Ok(reference)) => (test_surface, reference),
+            (Err(status), _) | (_, Err(status)) => {
+                return Status::Fail(format!("cannot render: {:?}", status))
+            }
+        };
+        let difference = compare(&mut test_surface, &mut reference);
+        match comparison {
+            Comparison::Equal if !difference.within(test.fuzz) => {
+                return Status::Fail(format!(
+                    "{} pixels differ (by up to {}) from {}",
+                    difference.pixels, difference.max_difference, href
+                ))
+            }
+            Comparison::NotEqual if difference.within(test.fuzz) => {
+                return Status::Fail(format!("looks the same as {}", href))
+            }
+            _ => {}
+        }
+    }
+    Status::Pass
+}
+
+/// Runs the tests listed in ``include.list`` in ``dir``.
+pub fn run(dir: &str) -> Result<Vec<TestResult>, String> {
+    let dir = Path::new(dir)
+        .canonicalize()
+        .map_err(|e| format!("{}: {}", dir, e))?;
+    let list_path = dir.join("include.list");
+    let mut src = String::new();
+    fs::File::open(&list_path)
+        .and_then(|mut f| f.read_to_string(&mut src))
+        .map_err(|e| format!("{}: {}", list_path.display(), e))?;
+    let tests = parse_include_list(src.as_str()).map_err(|(line, s)| {
+        format!("{}:{}: invalid line: {}", list_path.display(), line, s)
+    })?;
+
+    Ok(tests
+        .into_iter()
+        .map(|(path, expected_to_fail)| TestResult {
+            status: run_test(&dir, path.as_str()),
+            path: path,
+            expected_to_fail: expected_to_fail,
+        })
+        .collect())
+}
+
+#[test]
+fn test_parse_include_list() {
+    assert_eq!(
+        parse_include_list("# WPT\ncss/a.html\nfails css/b.html # TODO\n\n"),
+        Ok(vec![
+            ("css/a.html".to_string(), false),
+            ("css/b.html".to_string(), true),
+        ])
+    );
+    assert_eq!(parse_include_list("fails"), Err((1, "fails".to_string())));
+    assert_eq!(
+        parse_include_list("a.html b.html"),
+        Err((1, "a.html b.html".to_string()))
+    );
+}
+
+#[test]
+fn test_parse_fuzzy() {
+    let fuzz = |max_difference, max_pixels| {
+        Some(Fuzz {
+            max_difference: max_difference,
+            max_pixels: max_pixels,
+        })
+    };
+    assert_eq!(parse_fuzzy("maxDifference=0-2;totalPixels=0-100"), fuzz(2, 100));
+    assert_eq!(parse_fuzzy("totalPixels=10;maxDifference=3"), fuzz(3, 10));
+    assert_eq!(parse_fuzzy("1-2;300"), fuzz(2, 300));
+    assert_eq!(parse_fuzzy("ref.html:0-1;0-5"), fuzz(1, 5));
+    assert_eq!(parse_fuzzy("2"), None);
+    assert_eq!(parse_fuzzy("size=2;3"), None);
+}
+
+#[test]
+fn test_parse_reftest() {
+    let test = parse_reftest(
+        "<!DOCTYPE html>
+<title>A test</title>
+<link rel=\"help\" href=\"https://drafts.csswg.org/css2/\">
+<link rel=\"match\" href=\"reference/a-ref.html\">
+<link rel=\"mismatch\" href=\"/b-notref.html\">
+<meta name=\"fuzzy\" content=\"0-1;0-10\">
+<div>a</div>",
+        "css/css-box/a.html",
+    );
+    assert_eq!(
+        test,
+        Some(WptReftest {
+            references: vec![
+                (Comparison::Equal, "css/css-box/reference/a-ref.html".to_string()),
+                (Comparison::NotEqual, "b-notref.html".to_string()),
+            ],
+            fuzz: Fuzz {
+                max_difference: 1,
+                max_pixels: 10,
+            },
+        })
+    );
+    assert_eq!(
+        parse_reftest(
+            "<script src=\"/resources/testharness.js\"></script>",
+            "dom/a.html"
+        ),
+        None
+    );
+}
+
+#[test]
+fn test_test_result() {
+    let result = |status, expected_to_fail| TestResult {
+        path: "a.html".to_string(),
+        status: status,
+        expected_to_fail: expected_to_fail,
+    };
+    let results = vec![
+        result(Status::Pass, false),
+        result(Status::Fail("1 pixels differ".to_string()), true),
+        result(Status::Pass, true),
+        result(Status::Skip("not a reftest".to_string()), false),
+    ];
+    assert_eq!(results[0].report(), "PASS a.html");
+    assert_eq!(results[1].report(), "FAIL (expected) a.html: 1 pixels differ");
+    assert_eq!(results[2].report(), "PASS (unexpected) a.html");
+    assert_eq!(
+        summary(&results),
+        "2 passed, 1 failed (1 expected), 1 skipped (4 tests)"
+    );
+}
@@ -0,0 +1,14 @@
+//! Runs the layout snapshot tests under ``tests/layout``.
+
+#![cfg(feature = "raster")]
+
+extern crate naglfar;
+
+use naglfar::layout_snapshot;
+
+#[test]
+fn layout_snapshots() {
+    let dir = format!("{}/tests/layout", env!("CARGO_MANIFEST_DIR"));
+    let failures = layout_snapshot::run(dir.as_str());
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
@@ -0,0 +1,179 @@
+//! Feeds random input to the parsers and the layout, which must not panic on it. The inputs
+//! are random bytes, and random sequences of fragments of HTML and CSS (which get further than
+//! the first tag or rule).
+
+extern crate app_units;
+extern crate naglfar;
+extern crate rand;
+
+use naglfar::{css, html, layout, painter};
+use naglfar::network::Url;
+
+use app_units::Au;
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+const CASES: usize = 500;
+
+const HTML_FRAGMENTS: &'static [&'static str] = &[
+    "<", ">", "</", "/>", "<!--", "-->", "<!DOCTYPE html>", "<!", "=", "\"", "'", " ", "\n",
+    "html", "body", "div", "span", "p", "a", "img", "br", "input", "textarea", "button",
+    "style", "script", "<style>", "</style>", "<div>", "</div>", "<span>", "</span>", " id=",
+    " class=", " style=", " href=", " src=", " lang=", "x", "é", "日本", "\u{200d}", "\u{ad}",
+    "&amp;",
+];
+
+const CSS_FRAGMENTS: &'static [&'static str] = &[
+    "{", "}", ":", ";", ",", " ", "\n", "*", "#", ".", ">", "+", "[", "]", "(", ")", "@", "/*",
+    "*/", "\"", "'", "!important", "@import", "@media", "@charset", "@font-face", "url(",
+    "rgb(", "rgba(", "div", "a:hover", "::before", "display", "none", "inline", "inline-block",
+    "block", "float", "left", "font-size", "margin", "padding", "width", "height", "color",
+    "line-height", "text-transform", "uppercase", "0", "-1", "1.5", "10px", "50%", "2em", "#f",
+    "#fff", "#abcdef", "é", "日",
+];
+
+const PROPERTIES: &'static [&'static str] = &[
+    "display", "float", "clear", "width", "height", "margin", "margin-top", "margin-left",
+    "padding", "padding-right", "border", "border-top", "border-width", "border-left-width",
+    "border-color", "border-bottom-color", "outline", "outline-width", "outline-offset",
+    "color", "background", "background-color", "font-size", "font-weight", "font-style",
+    "font-family", "font-variant", "font-feature-settings", "line-height", "letter-spacing",
+    "word-spacing", "tab-size", "text-align", "text-decoration", "text-transform", "hyphens",
+    "cursor", "z-index",
+];
+
+const VALUES: &'static [&'static str] = &[
+    "0", "-1", "1.5", "10px", "-5px", "12pt", "50%", "2em", "auto", "none", "inherit", "normal",
+    "bold", "block", "inline", "inline-block", "left", "right", "both", "solid", "red", "#fff",
+    "rgb(1,2,3)", "rgba(1,2,3,0.5)", "url(a.png)", "\"str\"", "larger", "xx-large", "calc(1px)",
+    "!important",
+];
+
+fn rng() -> XorShiftRng {
+    XorShiftRng::from_seed([7; 16])
+}
+
+fn random_bytes(rng: &mut XorShiftRng) -> String {
+    let len = rng.gen_range(0, 200);
+    let bytes: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+    String::from_utf8_lossy(bytes.as_slice()).into_owned()
+}
+
+fn random_fragments(rng: &mut XorShiftRng, fragments: &[&str]) -> String {
+    let len = rng.gen_range(0, 60);
+    (0..len)
+        .map(|_| fragments[rng.gen_range(0, fragments.len())])
+        .collect()
+}
+
+fn lay_out(html_src: String, css_src: String) {
+    let document = html::parse(html_src, Url::parse("file:///fuzz.html").unwrap());
+    let stylesheet = css::parse(css_src);
+
+    let mut viewport: layout::Dimensions = Default::default();
+    viewport.content.width = Au::from_f64_px(300.0);
+    viewport.content.height = Au::from_f64_px(200.0);
+    layout::LAYOUTBOX.with(|lb| *lb.borrow_mut() = None);
+    let mut layout_tree = layout::layout_tree(&document, &stylesheet, viewport);
+    painter::build_display_list(&mut layout_tree);
+}
+
+#[test]
+fn html_random_bytes() {
+    let mut rng = rng();
+    for _ in 0..CASES {
+        html::parse(random_bytes(&mut rng), Url::parse("file:///fuzz.html").unwrap());
+    }
+}
+
+#[test]
+fn css_random_bytes() {
+    let mut rng = rng();
+    for _ in 0..CASES {
+        css::parse(random_bytes(&mut rng));
+        css::parse_attr_style(random_bytes(&mut rng));
+        css::parse_value(random_bytes(&mut rng));
+    }
+}
+
+#[test]
+fn html_random_fragments() {
+    let mut rng = rng();
+    for _ in 0..CASES {
+        let src = random_fragments(&mut rng, HTML_FRAGMENTS);
+        html::parse(src, Url::parse("file:///fuzz.html").unwrap());
+    }
+}
+
+#[test]
+fn css_random_fragments() {
+    let mut rng = rng();
+    for _ in 0..CASES {
+        css::parse(random_fragments(&mut rng, CSS_FRAGMENTS));
+        css::parse_attr_style(random_fragments(&mut rng, CSS_FRAGMENTS));
+        css::parse_value(random_fragments(&mut rng, CSS_FRAGMENTS));
+    }
+}
+
+#[test]
+fn layout_random_documents() {
+    let mut rng = rng();
+    for _ in 0..CASES / 5 {
+        let html_src = random_fragments(&mut rng, HTML_FRAGMENTS);
+        let css_src = random_fragments(&mut rng, CSS_FRAGMENTS);
+        lay_out(html_src, css_src);
+    }
+}
+
+#[test]
+fn layout_random_declarations() {
+    let mut rng = rng();
+    let mut declarations = |rng: &mut XorShiftRng| {
+        (0..rng.gen_range(0, 6))
+            .map(|_| {
+                let values: Vec<&str> = (0..rng.gen_range(0, 6))
+                    .map(|_| VALUES[rng.gen_range(0, VALUES.len())])
+                    .collect();
+                format!(
+                    "{}: {};",
+                    PROPERTIES[rng.gen_range(0, PROPERTIES.len())],
+                    values.join(" ")
+                )
+            })
+            .collect::<String>()
+    };
+    for _ in 0..CASES {
+        let html_src = format!(
+            "<div style=\"{}\">a <span style=\"{}\">b <div style=\"{}\">c</div></span></div>",
+            declarations(&mut rng),
+            declarations(&mut rng),
+            declarations(&mut rng)
+        );
+        let css_src = format!(
+            "html {{ {} }} body {{ {} }}",
+            declarations(&mut rng),
+            declarations(&mut rng)
+        );
+        lay_out(html_src, css_src);
+    }
+}
+
+#[test]
+fn layout_display_none_and_inline_roots() {
+    for css_src in &[
+        "html { display: none; }",
+        "html { display: inline; }",
+        "html { display: inline-block; }",
+        "html { float: left; }",
+        "html { background: none; }",
+        "html { display: ; font-size: ; margin: ; }",
+        "* { display: inline-block; }",
+        "* { display: inline-block; width: 50%; margin: auto; }",
+        "* { float: right; width: auto; }",
+    ] {
+        lay_out("<div>a<span>b</span></div>c".to_string(), css_src.to_string());
+        lay_out("<a href=\"\">a</a><img src=\"\"><input>".to_string(), css_src.to_string());
+        lay_out("<button>a</button><select></select>".to_string(), css_src.to_string());
+        lay_out("text only".to_string(), css_src.to_string());
+        lay_out("".to_string(), css_src.to_string());
+    }
+}
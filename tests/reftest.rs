@@ -0,0 +1,38 @@
+//! Runs the reftests under ``tests/reftests``.
+
+#![cfg(feature = "raster")]
+
+extern crate naglfar;
+
+use naglfar::reftest;
+
+fn run(manifest: &str) {
+    let manifest = format!("{}/tests/reftests/{}", env!("CARGO_MANIFEST_DIR"), manifest);
+    let failures = reftest::run(manifest.as_str());
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn box_model() {
+    run("box-model/reftest.list");
+}
+
+#[test]
+fn scripts() {
+    run("scripts/reftest.list");
+}
+
+#[test]
+fn overflow() {
+    run("overflow/reftest.list");
+}
+
+#[test]
+fn clip_path() {
+    run("clip-path/reftest.list");
+}
+
+#[test]
+fn filter() {
+    run("filter/reftest.list");
+}
@@ -0,0 +1,28 @@
+//! Runs the subset of the Web Platform Tests under ``tests/wpt`` (or in ``NAGLFAR_WPT_DIR``, e.g.
+//! a checkout of WPT with an ``include.list``). Run with ``--nocapture`` to see every result.
+
+#![cfg(feature = "raster")]
+
+extern crate naglfar;
+
+use naglfar::wpt;
+
+use std::env;
+
+#[test]
+fn wpt() {
+    let dir = env::var("NAGLFAR_WPT_DIR")
+        .unwrap_or_else(|_| format!("{}/tests/wpt", env!("CARGO_MANIFEST_DIR")));
+    let results = wpt::run(dir.as_str()).unwrap();
+    for result in &results {
+        println!("{}", result.report());
+    }
+    println!("{}", wpt::summary(&results));
+
+    let unexpected: Vec<String> = results
+        .iter()
+        .filter(|result| result.is_unexpected())
+        .map(|result| result.report())
+        .collect();
+    assert!(unexpected.is_empty(), "\n{}", unexpected.join("\n"));
+}